@@ -0,0 +1,234 @@
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::config::{Config, HostPort};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::{
+    config::{self, ClusterConfig, PUBLISHER_NAME, PUBLISHER_SET_NAME},
+    error::Error,
+    supervisor::{ProcessSpec, ProcessState, ProcessStatus, RestartPolicy, SupervisedProcess},
+};
+
+// How long a process may take to start listening on its gRPC port.
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What to launch, and from where.
+#[derive(Clone, Debug)]
+pub struct ClusterSpec {
+    pub range_servers: usize,
+    pub region: String,
+    pub zone: String,
+    pub cassandra_addr: HostPort,
+    pub cassandra_datacenter: Option<String>,
+    /// Where the component binaries were built, e.g. target/debug.
+    pub bin_dir: PathBuf,
+    /// Where the generated configs and the process logs go.
+    pub work_dir: PathBuf,
+    pub restart_policy: RestartPolicy,
+}
+
+/// A cluster running as local processes: a universe, a warden, an epoch
+/// service with a single epoch publisher, and a number of range servers. The
+/// processes are restarted if they exit, and killed when the cluster is shut
+/// down or dropped.
+pub struct LocalCluster {
+    config: ClusterConfig,
+    // In start order.
+    processes: Vec<SupervisedProcess>,
+}
+
+impl LocalCluster {
+    /// Starts the processes one at a time, each one once the ones it depends
+    /// on are listening.
+    pub async fn launch(spec: &ClusterSpec) -> Result<LocalCluster, Error> {
+        let config = config::generate(spec)?;
+        std::fs::create_dir_all(&spec.work_dir).map_err(|source| Error::WriteConfig {
+            path: spec.work_dir.display().to_string(),
+            source,
+        })?;
+        let mut cluster = LocalCluster {
+            config,
+            processes: Vec::new(),
+        };
+        match cluster.start_processes(spec).await {
+            Ok(()) => Ok(cluster),
+            Err(e) => {
+                cluster.shutdown().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn start_processes(&mut self, spec: &ClusterSpec) -> Result<(), Error> {
+        let base_config = write_config(spec, "config.json", &self.config.base)?;
+        let region = self.config.region.name.clone();
+        let zone = self.config.zone.name.clone();
+        let region_config = &self.config.base.regions[&self.config.region];
+        let warden_addr = region_config.warden_address.clone();
+        let publisher_addr = region_config
+            .epoch_publishers
+            .iter()
+            .flat_map(|set| set.publishers.iter())
+            .map(|publisher| publisher.backend_addr.clone())
+            .next()
+            .unwrap();
+
+        self.start(
+            spec,
+            "universe",
+            vec!["--config".into(), base_config.clone()],
+            &self.config.base.universe.proto_server_addr.clone(),
+        )
+        .await?;
+        self.start(
+            spec,
+            "warden",
+            vec![
+                "--config".into(),
+                base_config.clone(),
+                "--region".into(),
+                region.clone(),
+                "--zone".into(),
+                zone.clone(),
+            ],
+            &warden_addr,
+        )
+        .await?;
+        self.start(
+            spec,
+            "epoch",
+            vec!["--config".into(), base_config.clone()],
+            &self.config.base.epoch.proto_server_addr.clone(),
+        )
+        .await?;
+        self.start(
+            spec,
+            "epoch_publisher",
+            vec![
+                "--config".into(),
+                base_config,
+                "--region".into(),
+                region.clone(),
+                "--zone".into(),
+                zone.clone(),
+                "--publisher-set-name".into(),
+                PUBLISHER_SET_NAME.into(),
+                "--publisher-name".into(),
+                PUBLISHER_NAME.into(),
+            ],
+            &publisher_addr,
+        )
+        .await?;
+        for (i, rs_config) in self.config.range_servers.clone().iter().enumerate() {
+            let name = format!("range_server_{}", i);
+            let config_path = write_config(spec, &format!("{}.json", name), rs_config)?;
+            let addr = rs_config.range_server.proto_server_addr.clone();
+            self.start_named(
+                spec,
+                &name,
+                "rangeserver",
+                vec![
+                    "--config".into(),
+                    config_path,
+                    "--region".into(),
+                    region.clone(),
+                    "--zone".into(),
+                    zone.clone(),
+                    "--identity".into(),
+                    name.clone(),
+                    "--address".into(),
+                    addr.to_string(),
+                ],
+                &addr,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn start(
+        &mut self,
+        spec: &ClusterSpec,
+        binary: &str,
+        args: Vec<String>,
+        ready_addr: &HostPort,
+    ) -> Result<(), Error> {
+        self.start_named(spec, binary, binary, args, ready_addr)
+            .await
+    }
+
+    async fn start_named(
+        &mut self,
+        spec: &ClusterSpec,
+        name: &str,
+        binary: &str,
+        args: Vec<String>,
+        ready_addr: &HostPort,
+    ) -> Result<(), Error> {
+        let process = SupervisedProcess::spawn(
+            ProcessSpec {
+                name: name.to_string(),
+                program: spec.bin_dir.join(binary),
+                args,
+                log_path: spec.work_dir.join(format!("{}.log", name)),
+            },
+            spec.restart_policy.clone(),
+        )?;
+        let ready = wait_until_listening(&process, ready_addr).await;
+        self.processes.push(process);
+        ready?;
+        info!(name, addr = %ready_addr, "Process is listening");
+        Ok(())
+    }
+
+    pub fn config(&self) -> &ClusterConfig {
+        &self.config
+    }
+
+    pub fn statuses(&self) -> Vec<ProcessStatus> {
+        self.processes.iter().map(|p| p.status()).collect()
+    }
+
+    /// Stops the processes in the reverse of the order they were started in.
+    pub async fn shutdown(mut self) {
+        while let Some(process) = self.processes.pop() {
+            process.stop().await;
+        }
+    }
+}
+
+fn write_config(spec: &ClusterSpec, file_name: &str, config: &Config) -> Result<String, Error> {
+    let path = spec.work_dir.join(file_name);
+    std::fs::write(&path, serde_json::to_string_pretty(config).unwrap()).map_err(|source| {
+        Error::WriteConfig {
+            path: path.display().to_string(),
+            source,
+        }
+    })?;
+    Ok(path.display().to_string())
+}
+
+async fn wait_until_listening(process: &SupervisedProcess, addr: &HostPort) -> Result<(), Error> {
+    let not_ready = || Error::NotReady {
+        name: process.status().name,
+        addr: addr.to_string(),
+    };
+    let socket_addr = addr
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(not_ready)?;
+    let deadline = Instant::now() + READY_TIMEOUT;
+    while TcpStream::connect(socket_addr).await.is_err() {
+        // No point in waiting for a process that was given up on.
+        if Instant::now() >= deadline || process.status().state == ProcessState::Failed {
+            return Err(not_ready());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use common::config::{
+    CassandraConfig, Config, EpochConfig, EpochPublisher, EpochPublisherSet, FrontendConfig,
+    HostPort, RangeServerConfig, RegionConfig, UniverseConfig,
+};
+use common::region::{Region, Zone};
+
+use crate::{cluster::ClusterSpec, error::Error};
+
+pub const PUBLISHER_SET_NAME: &str = "ps1";
+pub const PUBLISHER_NAME: &str = "ep1";
+
+/// Configs generated for a local cluster, all pointing at freshly allocated
+/// local ports.
+pub struct ClusterConfig {
+    pub region: Region,
+    pub zone: Zone,
+    /// Used by every process but the range servers.
+    pub base: Config,
+    /// One per range server. They only differ from `base` in the addresses
+    /// the range server listens on.
+    pub range_servers: Vec<Config>,
+}
+
+struct PortAllocator {
+    allocated: HashSet<u16>,
+}
+
+impl PortAllocator {
+    // The ports are only checked to be unused, not reserved, so keep track of
+    // the ones handed out to avoid giving the same one twice.
+    fn next(&mut self) -> Result<HostPort, Error> {
+        for _ in 0..100 {
+            let port = portpicker::pick_unused_port().ok_or(Error::NoFreePort)?;
+            if self.allocated.insert(port) {
+                return Ok(HostPort {
+                    host: "127.0.0.1".to_string(),
+                    port,
+                });
+            }
+        }
+        Err(Error::NoFreePort)
+    }
+}
+
+pub fn generate(spec: &ClusterSpec) -> Result<ClusterConfig, Error> {
+    let mut ports = PortAllocator {
+        allocated: HashSet::new(),
+    };
+    let region = Region {
+        cloud: None,
+        name: spec.region.clone(),
+    };
+    let zone = Zone {
+        region: region.clone(),
+        name: spec.zone.clone(),
+    };
+    let publisher_set = EpochPublisherSet {
+        name: PUBLISHER_SET_NAME.to_string(),
+        zone: zone.clone(),
+        publishers: HashSet::from([EpochPublisher {
+            name: PUBLISHER_NAME.to_string(),
+            backend_addr: ports.next()?,
+            fast_network_addr: ports.next()?,
+            failure_domain: None,
+        }]),
+    };
+    let region_config = RegionConfig {
+        warden_address: ports.next()?,
+        epoch_publishers: HashSet::from([publisher_set]),
+        cassandra_datacenter: spec.cassandra_datacenter.clone(),
+    };
+    let base = Config {
+        range_server: RangeServerConfig {
+            range_maintenance_duration: Duration::from_secs(1),
+            proto_server_addr: ports.next()?,
+            fast_network_addr: ports.next()?,
+            preflight_epoch_advance_timeout: Some(Duration::from_secs(5)),
+            max_pending_prepares_per_range: None,
+            max_epoch_lease_extension: None,
+            handoff_drain_timeout: None,
+            lock_table: Default::default(),
+            conflict_stats: Default::default(),
+            write_stall: Default::default(),
+            checksum_verification: Default::default(),
+            decision_log: Default::default(),
+            expiry: Default::default(),
+            fast_network_transport: Default::default(),
+            mirroring: Default::default(),
+            transaction_recovery: Default::default(),
+        },
+        epoch: EpochConfig {
+            proto_server_addr: ports.next()?,
+            epoch_duration: Duration::from_millis(10),
+        },
+        universe: UniverseConfig {
+            proto_server_addr: ports.next()?,
+        },
+        frontend: FrontendConfig {
+            proto_server_addr: ports.next()?,
+            fast_network_addr: ports.next()?,
+            transaction_overall_timeout: Duration::from_secs(10),
+            fast_network_transport: Default::default(),
+        },
+        cassandra: CassandraConfig {
+            cql_addr: spec.cassandra_addr.clone(),
+            consistency: Default::default(),
+        },
+        regions: HashMap::from([(region.clone(), region_config)]),
+        admin: Default::default(),
+        quic: Default::default(),
+    };
+    let mut range_servers = Vec::with_capacity(spec.range_servers);
+    for _ in 0..spec.range_servers {
+        let mut config = base.clone();
+        config.range_server.proto_server_addr = ports.next()?;
+        config.range_server.fast_network_addr = ports.next()?;
+        range_servers.push(config);
+    }
+    Ok(ClusterConfig {
+        region,
+        zone,
+        base,
+        range_servers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn spec(range_servers: usize) -> ClusterSpec {
+        ClusterSpec {
+            range_servers,
+            region: "test-region".to_string(),
+            zone: "a".to_string(),
+            cassandra_addr: "127.0.0.1:9042".parse().unwrap(),
+            cassandra_datacenter: None,
+            bin_dir: PathBuf::from("target/debug"),
+            work_dir: PathBuf::from("target/local-cluster"),
+            restart_policy: Default::default(),
+        }
+    }
+
+    #[test]
+    fn range_servers_get_their_own_ports() {
+        let config = generate(&spec(3)).unwrap();
+        assert_eq!(config.range_servers.len(), 3);
+        let mut addrs = HashSet::new();
+        for rs in &config.range_servers {
+            assert!(addrs.insert(rs.range_server.proto_server_addr.clone()));
+            assert!(addrs.insert(rs.range_server.fast_network_addr.clone()));
+            assert_eq!(
+                rs.universe.proto_server_addr,
+                config.base.universe.proto_server_addr
+            );
+        }
+    }
+
+    #[test]
+    fn generated_config_round_trips_through_json() {
+        let config = generate(&spec(1)).unwrap();
+        let json = serde_json::to_string(&config.base).unwrap();
+        let parsed: Config = serde_json::from_str(&json).unwrap();
+        let region_config = parsed.regions.get(&config.region).unwrap();
+        assert_eq!(
+            region_config.warden_address,
+            config.base.regions[&config.region].warden_address
+        );
+        assert_eq!(region_config.epoch_publishers.len(), 1);
+    }
+}
@@ -0,0 +1,15 @@
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No unused local port is left to allocate")]
+    NoFreePort,
+    #[error("Failed to write {path}: {source}")]
+    WriteConfig { path: String, source: io::Error },
+    #[error("Failed to spawn {name}: {source}")]
+    Spawn { name: String, source: io::Error },
+    #[error("{name} did not start listening on {addr}")]
+    NotReady { name: String, addr: String },
+}
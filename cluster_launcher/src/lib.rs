@@ -0,0 +1,4 @@
+pub mod cluster;
+pub mod config;
+pub mod error;
+pub mod supervisor;
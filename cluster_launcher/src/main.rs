@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use cluster_launcher::{
+    cluster::{ClusterSpec, LocalCluster},
+    supervisor::RestartPolicy,
+};
+use common::config::HostPort;
+use tracing::{error, info};
+
+#[derive(Parser, Debug)]
+#[command(name = "cluster-launcher")]
+#[command(about = "Runs a local Atomix cluster as separate processes", long_about = None)]
+struct Args {
+    #[arg(long, default_value_t = 3)]
+    range_servers: usize,
+
+    #[arg(long, default_value = "target/debug")]
+    bin_dir: PathBuf,
+
+    #[arg(long, default_value = "target/local-cluster")]
+    work_dir: PathBuf,
+
+    #[arg(long, default_value = "127.0.0.1:9042")]
+    cassandra: HostPort,
+
+    #[arg(long)]
+    cassandra_datacenter: Option<String>,
+
+    #[arg(long, default_value = "test-region")]
+    region: String,
+
+    #[arg(long, default_value = "a")]
+    zone: String,
+
+    /// Restarts after which a process that keeps exiting is given up on.
+    #[arg(long, default_value_t = 3)]
+    max_restarts: u32,
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let spec = ClusterSpec {
+        range_servers: args.range_servers,
+        region: args.region,
+        zone: args.zone,
+        cassandra_addr: args.cassandra,
+        cassandra_datacenter: args.cassandra_datacenter,
+        bin_dir: args.bin_dir,
+        work_dir: args.work_dir,
+        restart_policy: RestartPolicy {
+            max_restarts: args.max_restarts,
+            ..Default::default()
+        },
+    };
+    let cluster = match LocalCluster::launch(&spec).await {
+        Ok(cluster) => cluster,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    };
+    info!(
+        "Local cluster is up, configs and logs are in {}",
+        spec.work_dir.display()
+    );
+    tokio::signal::ctrl_c().await.unwrap();
+    info!("Shutting down");
+    for status in cluster.statuses() {
+        info!(name = status.name, state = ?status.state, restarts = status.restarts);
+    }
+    cluster.shutdown().await;
+}
@@ -0,0 +1,228 @@
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::error::Error;
+
+/// How to start one of the processes of the cluster.
+#[derive(Clone, Debug)]
+pub struct ProcessSpec {
+    pub name: String,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Where the process' stdout and stderr go.
+    pub log_path: PathBuf,
+}
+
+/// What to do when a process exits on its own.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    /// Restarts after which the process is given up on.
+    pub max_restarts: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProcessState {
+    Running,
+    Restarting,
+    /// Kept exiting, and ran out of restarts.
+    Failed,
+    Stopped,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessStatus {
+    pub name: String,
+    pub state: ProcessState,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+}
+
+/// A child process that gets restarted when it exits, until it is stopped.
+pub struct SupervisedProcess {
+    status: Arc<Mutex<ProcessStatus>>,
+    stop: CancellationToken,
+    monitor: JoinHandle<()>,
+}
+
+fn start(spec: &ProcessSpec) -> Result<Child, Error> {
+    let spawn_error = |source| Error::Spawn {
+        name: spec.name.clone(),
+        source,
+    };
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&spec.log_path)
+        .map_err(spawn_error)?;
+    let log_clone = log.try_clone().map_err(spawn_error)?;
+    Command::new(&spec.program)
+        .args(&spec.args)
+        .stdin(Stdio::null())
+        .stdout(log)
+        .stderr(log_clone)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_error)
+}
+
+impl SupervisedProcess {
+    /// Starts the process. Fails if it cannot be spawned at all, later
+    /// failures are handled according to `policy`.
+    pub fn spawn(spec: ProcessSpec, policy: RestartPolicy) -> Result<SupervisedProcess, Error> {
+        let child = start(&spec)?;
+        info!(name = spec.name, pid = child.id(), "Started process");
+        let status = Arc::new(Mutex::new(ProcessStatus {
+            name: spec.name.clone(),
+            state: ProcessState::Running,
+            pid: child.id(),
+            restarts: 0,
+        }));
+        let stop = CancellationToken::new();
+        let monitor = tokio::spawn(Self::monitor(
+            spec,
+            policy,
+            child,
+            status.clone(),
+            stop.clone(),
+        ));
+        Ok(SupervisedProcess {
+            status,
+            stop,
+            monitor,
+        })
+    }
+
+    pub fn status(&self) -> ProcessStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Kills the process and waits for it to exit.
+    pub async fn stop(mut self) {
+        self.stop.cancel();
+        let _ = (&mut self.monitor).await;
+    }
+
+    async fn monitor(
+        spec: ProcessSpec,
+        policy: RestartPolicy,
+        mut child: Child,
+        status: Arc<Mutex<ProcessStatus>>,
+        stop: CancellationToken,
+    ) {
+        let set_state = |state, pid| {
+            let mut status = status.lock().unwrap();
+            status.state = state;
+            status.pid = pid;
+        };
+        loop {
+            tokio::select! {
+                () = stop.cancelled() => {
+                    let _ = child.kill().await;
+                    set_state(ProcessState::Stopped, None);
+                    return;
+                }
+                exit_status = child.wait() => {
+                    warn!(name = spec.name, ?exit_status, "Process exited unexpectedly");
+                }
+            }
+            let restarts = status.lock().unwrap().restarts;
+            if restarts >= policy.max_restarts {
+                error!(
+                    name = spec.name,
+                    restarts, "Process keeps exiting, giving up on it"
+                );
+                set_state(ProcessState::Failed, None);
+                return;
+            }
+            set_state(ProcessState::Restarting, None);
+            tokio::select! {
+                () = stop.cancelled() => {
+                    set_state(ProcessState::Stopped, None);
+                    return;
+                }
+                () = tokio::time::sleep(policy.backoff) => {}
+            }
+            child = match start(&spec) {
+                Ok(child) => child,
+                Err(e) => {
+                    error!(name = spec.name, "Failed to restart process: {}", e);
+                    set_state(ProcessState::Failed, None);
+                    return;
+                }
+            };
+            info!(name = spec.name, pid = child.id(), "Restarted process");
+            let mut status = status.lock().unwrap();
+            status.state = ProcessState::Running;
+            status.pid = child.id();
+            status.restarts += 1;
+        }
+    }
+}
+
+impl Drop for SupervisedProcess {
+    fn drop(&mut self) {
+        self.stop.cancel()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str, script: &str) -> ProcessSpec {
+        ProcessSpec {
+            name: name.to_string(),
+            program: PathBuf::from("sh"),
+            args: vec!["-c".to_string(), script.to_string()],
+            log_path: std::env::temp_dir().join(format!("cluster-launcher-test-{}.log", name)),
+        }
+    }
+
+    fn fast_policy() -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: 2,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn restarts_until_giving_up() {
+        let process = SupervisedProcess::spawn(spec("crashing", "exit 1"), fast_policy()).unwrap();
+        while process.status().state != ProcessState::Failed {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(process.status().restarts, 2);
+    }
+
+    #[tokio::test]
+    async fn stop_kills_the_process() {
+        let process =
+            SupervisedProcess::spawn(spec("sleeping", "sleep 60"), fast_policy()).unwrap();
+        let status = process.status();
+        assert_eq!(status.state, ProcessState::Running);
+        assert!(status.pid.is_some());
+        let monitor_status = process.status.clone();
+        process.stop().await;
+        let status = monitor_status.lock().unwrap().clone();
+        assert_eq!(status.state, ProcessState::Stopped);
+        assert_eq!(status.restarts, 0);
+    }
+}
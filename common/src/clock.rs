@@ -0,0 +1,142 @@
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Source of wall-clock and monotonic time. Code with timeouts or leases
+/// should get the time from an injected Clock, so that tests can control it.
+#[async_trait]
+pub trait Clock: Send + Sync + 'static {
+    /// Current wall-clock time.
+    fn now(&self) -> DateTime<Utc>;
+    /// Current monotonic time.
+    fn instant(&self) -> Instant;
+    /// Waits until the monotonic time reaches `deadline`.
+    async fn sleep_until(&self, deadline: Instant);
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.instant() + duration).await
+    }
+}
+
+/// Runs `future` until the clock reaches `deadline`. Returns None if it did
+/// not complete in time.
+pub async fn timeout_at<F: Future>(
+    clock: &dyn Clock,
+    deadline: Instant,
+    future: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        res = future => Some(res),
+        () = clock.sleep_until(deadline) => None,
+    }
+}
+
+/// The actual time.
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        tokio::time::sleep_until(deadline).await
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+pub struct ManualClock {
+    wall_start: DateTime<Utc>,
+    monotonic_start: Instant,
+    elapsed: watch::Sender<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(wall_start: DateTime<Utc>) -> ManualClock {
+        ManualClock {
+            wall_start,
+            monotonic_start: Instant::now(),
+            elapsed: watch::Sender::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves the clock forward, waking up the sleepers whose deadline passed.
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.borrow()
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.wall_start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.monotonic_start + self.elapsed()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        let mut elapsed = self.elapsed.subscribe();
+        // The sender lives as long as self, so waiting cannot fail.
+        let _ = elapsed
+            .wait_for(|elapsed| self.monotonic_start + *elapsed >= deadline)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn manual_clock_only_moves_when_advanced() {
+        let start = Utc::now();
+        let clock = ManualClock::new(start);
+        let instant = clock.instant();
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(clock.now(), start + Duration::from_secs(5));
+        assert_eq!(clock.instant(), instant + Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn manual_clock_wakes_up_sleepers() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let sleeper = {
+            let clock = clock.clone();
+            tokio::spawn(async move { clock.sleep(Duration::from_secs(10)).await })
+        };
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(9));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+        clock.advance(Duration::from_secs(1));
+        sleeper.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn timeout_at_expires_with_the_clock() {
+        let clock = ManualClock::new(Utc::now());
+        let deadline = clock.instant() + Duration::from_secs(1);
+        clock.advance(Duration::from_secs(1));
+        let res = timeout_at(&clock, deadline, std::future::pending::<()>()).await;
+        assert!(res.is_none());
+        let deadline = clock.instant() + Duration::from_secs(1);
+        assert_eq!(timeout_at(&clock, deadline, async { 42 }).await, Some(42));
+    }
+}
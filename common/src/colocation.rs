@@ -0,0 +1,92 @@
+use bytes::Bytes;
+use proto::universe::{colocation_policy::GroupPrefix, ColocationPolicy};
+
+/// Which keys of a keyspace live in the same range: all the keys starting
+/// with the same group prefix. See `ColocationPolicy` in universe.proto.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colocation {
+    PrefixLength(usize),
+    Delimiter(u8),
+}
+
+impl Colocation {
+    /// None if the policy is unset or malformed.
+    pub fn from_proto(policy: &ColocationPolicy) -> Option<Colocation> {
+        match policy.group_prefix.as_ref()? {
+            GroupPrefix::PrefixLength(0) => None,
+            GroupPrefix::PrefixLength(len) => Some(Colocation::PrefixLength(*len as usize)),
+            GroupPrefix::Delimiter(delimiter) => match delimiter[..] {
+                [delimiter] => Some(Colocation::Delimiter(delimiter)),
+                _ => None,
+            },
+        }
+    }
+
+    /// The group prefix of `key`, which identifies its group.
+    pub fn group_prefix<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        let len = match self {
+            Colocation::PrefixLength(len) => std::cmp::min(*len, key.len()),
+            Colocation::Delimiter(delimiter) => key
+                .iter()
+                .position(|b| b == delimiter)
+                .map_or(key.len(), |pos| pos + 1),
+        };
+        &key[..len]
+    }
+
+    /// The closest key at or before `split_key` that a range can be split at
+    /// without separating a group. A group's keys all sort between its
+    /// prefix and the next group's, so this is the group prefix itself.
+    pub fn align_split_key(&self, split_key: &Bytes) -> Bytes {
+        split_key.slice(..self.group_prefix(split_key).len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_proto_rejects_malformed_policies() {
+        let policy = |group_prefix| ColocationPolicy {
+            group_prefix: Some(group_prefix),
+        };
+        assert_eq!(
+            Colocation::from_proto(&policy(GroupPrefix::PrefixLength(4))),
+            Some(Colocation::PrefixLength(4))
+        );
+        assert_eq!(
+            Colocation::from_proto(&policy(GroupPrefix::Delimiter(b"/".to_vec()))),
+            Some(Colocation::Delimiter(b'/'))
+        );
+        assert_eq!(
+            Colocation::from_proto(&policy(GroupPrefix::PrefixLength(0))),
+            None
+        );
+        assert_eq!(
+            Colocation::from_proto(&policy(GroupPrefix::Delimiter(b"::".to_vec()))),
+            None
+        );
+        assert_eq!(
+            Colocation::from_proto(&ColocationPolicy { group_prefix: None }),
+            None
+        );
+    }
+
+    #[test]
+    fn split_keys_move_to_the_start_of_their_group() {
+        let by_delimiter = Colocation::Delimiter(b'/');
+        let align = |c: Colocation, key: &'static [u8]| c.align_split_key(&Bytes::from(key));
+        assert_eq!(
+            align(by_delimiter, b"customer42/order7"),
+            &b"customer42/"[..]
+        );
+        assert_eq!(align(by_delimiter, b"customer42/"), &b"customer42/"[..]);
+        // Keys without the delimiter are a group of their own.
+        assert_eq!(align(by_delimiter, b"customer42"), &b"customer42"[..]);
+
+        let by_length = Colocation::PrefixLength(4);
+        assert_eq!(align(by_length, b"abcdef"), &b"abcd"[..]);
+        assert_eq!(align(by_length, b"ab"), &b"ab"[..]);
+    }
+}
@@ -86,10 +86,57 @@ pub struct EpochConfig {
     pub epoch_duration: std::time::Duration,
 }
 
+/// Cassandra consistency levels, spelled as in CQL (e.g. "LOCAL_QUORUM").
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConsistencyLevel {
+    Any,
+    One,
+    Two,
+    Three,
+    Quorum,
+    All,
+    LocalQuorum,
+    EachQuorum,
+    LocalOne,
+}
+
+/// Consistency levels used for each class of Cassandra operations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CassandraConsistencyConfig {
+    /// Range leases and other range metadata.
+    pub range_metadata: ConsistencyLevel,
+    pub record_reads: ConsistencyLevel,
+    pub record_writes: ConsistencyLevel,
+}
+
+impl ConsistencyLevel {
+    /// Whether the level only involves replicas in the local datacenter.
+    pub fn is_dc_local(&self) -> bool {
+        matches!(
+            self,
+            ConsistencyLevel::LocalQuorum | ConsistencyLevel::LocalOne
+        )
+    }
+}
+
+impl Default for CassandraConsistencyConfig {
+    fn default() -> Self {
+        CassandraConsistencyConfig {
+            range_metadata: ConsistencyLevel::Quorum,
+            record_reads: ConsistencyLevel::LocalQuorum,
+            record_writes: ConsistencyLevel::LocalQuorum,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CassandraConfig {
     // Can't be SocketAddr because we want to use a DNS name.
     pub cql_addr: HostPort,
+    #[serde(default)]
+    pub consistency: CassandraConsistencyConfig,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -97,6 +144,10 @@ pub struct EpochPublisher {
     pub name: String,
     pub backend_addr: HostPort,
     pub fast_network_addr: HostPort,
+    /// What the publisher fails together with, e.g. the rack or machine it
+    /// runs on. Unset if unknown.
+    #[serde(default)]
+    pub failure_domain: Option<String>,
 }
 
 #[derive(Derivative, Serialize, Deserialize)]
@@ -108,17 +159,326 @@ pub struct EpochPublisherSet {
     pub publishers: HashSet<EpochPublisher>,
 }
 
+impl EpochPublisherSet {
+    /// How many publishers must agree on the epoch for it to be read.
+    pub fn quorum(&self) -> usize {
+        self.publishers.len() / 2 + 1
+    }
+
+    /// Checks that the set keeps a quorum of publishers if any one of its
+    /// failure domains fails.
+    pub fn check_failure_domains(&self) -> Result<(), String> {
+        let mut unknown = Vec::new();
+        let mut domains = HashMap::<&str, usize>::new();
+        for publisher in &self.publishers {
+            match &publisher.failure_domain {
+                None => unknown.push(publisher.name.as_str()),
+                Some(domain) => *domains.entry(domain.as_str()).or_default() += 1,
+            }
+        }
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(format!(
+                "publisher set {}: no failure domain configured for {}",
+                self.name,
+                unknown.join(", ")
+            ));
+        }
+        let Some((domain, count)) = domains.into_iter().max_by_key(|(d, c)| (*c, *d)) else {
+            return Err(format!("publisher set {} has no publishers", self.name));
+        };
+        if self.publishers.len() - count < self.quorum() {
+            return Err(format!(
+                "publisher set {}: losing failure domain {} (holding {} of {} publishers) \
+                 would leave fewer than a quorum of {}",
+                self.name,
+                domain,
+                count,
+                self.publishers.len(),
+                self.quorum()
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RangeServerConfig {
     pub range_maintenance_duration: time::Duration,
     pub proto_server_addr: HostPort,
     pub fast_network_addr: HostPort,
+    /// How long startup waits to observe the epoch advancing before giving
+    /// up. If unset, startup only checks that the epoch can be read.
+    #[serde(default)]
+    pub preflight_epoch_advance_timeout: Option<time::Duration>,
+    /// How many transactions can be prepared but not yet committed or
+    /// aborted on a range before it starts rejecting prepares. Defaults to
+    /// 1024.
+    #[serde(default)]
+    pub max_pending_prepares_per_range: Option<usize>,
+    /// How many epochs past the current one a range extends its epoch lease
+    /// to at most, when a coordinator asks it to so that a long transaction
+    /// prepared on it can still commit. Defaults to twice the length of a
+    /// regular lease.
+    #[serde(default)]
+    pub max_epoch_lease_extension: Option<u64>,
+    /// How long a range the warden moves to another range server keeps
+    /// serving the transactions already running on it before letting go.
+    /// Those still prepared by then are recovered by the new range server.
+    /// Defaults to 5 seconds.
+    #[serde(default)]
+    pub handoff_drain_timeout: Option<time::Duration>,
+    #[serde(default)]
+    pub lock_table: LockTableConfig,
+    #[serde(default)]
+    pub conflict_stats: ConflictStatsConfig,
+    #[serde(default)]
+    pub write_stall: WriteStallConfig,
+    #[serde(default)]
+    pub checksum_verification: ChecksumVerificationConfig,
+    #[serde(default)]
+    pub decision_log: DecisionLogConfig,
+    #[serde(default)]
+    pub expiry: ExpiryConfig,
+    #[serde(default)]
+    pub fast_network_transport: FastNetworkTransport,
+    #[serde(default)]
+    pub mirroring: MirroringConfig,
+    #[serde(default)]
+    pub transaction_recovery: TransactionRecoveryConfig,
+}
+
+/// What the fast network of a process sends messages over.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FastNetworkTransport {
+    /// One datagram per message. Messages that do not fit in a datagram are
+    /// dropped, as are any the network loses.
+    #[default]
+    Udp,
+    /// Length-prefixed messages over a pooled TCP connection to each peer.
+    /// Epochs are still read from the epoch publishers over UDP.
+    Tcp,
+    /// One QUIC stream per message over an encrypted connection to each
+    /// peer, see `QuicConfig`. Epochs are still read from the epoch
+    /// publishers over UDP.
+    Quic,
+}
+
+/// What a range does with lock requests once its lock table is full.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockTableOverflowPolicy {
+    /// Fail the request with `Overloaded`.
+    #[default]
+    Reject,
+    /// Keep queueing, but hold the requests past the bound in a compact
+    /// form that does not keep their transaction metadata alive.
+    Spill,
+}
+
+/// Bounds on the memory used by the lock table of each range.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockTableConfig {
+    /// Transactions queued to acquire the lock.
+    pub max_waiters: usize,
+    /// All requests tracked by the lock table: the holder, the transactions
+    /// queued to acquire the lock and the readers waiting for its release.
+    pub max_entries: usize,
+    pub overflow_policy: LockTableOverflowPolicy,
+    /// How long a transaction waits in line for the lock before giving up
+    /// with `LockWaitTimeout`, leaving its place to the ones behind it.
+    /// Unset, it waits for as long as the transactions ahead of it hold on.
+    pub wait_timeout: Option<time::Duration>,
+}
+
+impl Default for LockTableConfig {
+    fn default() -> Self {
+        LockTableConfig {
+            max_waiters: 4096,
+            max_entries: 16384,
+            overflow_policy: LockTableOverflowPolicy::Reject,
+            wait_timeout: None,
+        }
+    }
+}
+
+/// When a range considers its writes to storage stalled. A stalled range
+/// turns away prepares that write, telling coordinators when to retry, until
+/// its writes speed up again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteStallConfig {
+    /// Applying the writes of a commit to storage taking longer than this is
+    /// slow.
+    pub apply_latency_threshold: time::Duration,
+    /// The range is stalled once its applies have been slow for this long,
+    /// and recovers once it has seen no slow apply for as long.
+    pub sustained_for: time::Duration,
+}
+
+impl Default for WriteStallConfig {
+    fn default() -> Self {
+        WriteStallConfig {
+            apply_latency_threshold: time::Duration::from_secs(1),
+            sustained_for: time::Duration::from_secs(10),
+        }
+    }
+}
+
+/// How ranges check the records their prefetch buffer holds against the
+/// ones in storage.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChecksumVerificationConfig {
+    /// Each loaded range is verified this often. Unset disables the
+    /// background job, ranges can still be verified through the admin API.
+    pub interval: Option<time::Duration>,
+    /// Divergent keys kept and logged per verification, on top of their
+    /// count.
+    pub max_divergent_keys: usize,
+}
+
+impl Default for ChecksumVerificationConfig {
+    fn default() -> Self {
+        ChecksumVerificationConfig {
+            interval: None,
+            max_divergent_keys: 16,
+        }
+    }
+}
+
+/// Where ranges log the prepares and decisions they apply, for replaying
+/// them when investigating a range's history. See
+/// `rangeserver::decision_log`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecisionLogConfig {
+    /// Each range logs to its own directory under this one. Unset disables
+    /// the log.
+    pub directory: Option<std::path::PathBuf>,
+    /// A range starts a new file once its current one would grow past this.
+    pub max_file_bytes: u64,
+    /// Files kept per range, the oldest are deleted first.
+    pub max_files: usize,
+}
+
+impl Default for DecisionLogConfig {
+    fn default() -> Self {
+        DecisionLogConfig {
+            directory: None,
+            max_file_bytes: 64 * 1024 * 1024,
+            max_files: 8,
+        }
+    }
+}
+
+/// Which gets a range server copies to a shadow range server, e.g. one
+/// running a new version or storage backend, to compare their answers. See
+/// `rangeserver::mirroring`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirroringConfig {
+    /// The proto server address of the shadow range server. Unset disables
+    /// mirroring.
+    pub shadow_proto_addr: Option<HostPort>,
+    /// The fraction of gets copied, from 0 to 1.
+    pub fraction: f64,
+    /// Copied gets the shadow may be answering at once. Gets sampled past
+    /// that are not copied, so that a slow shadow does not pile them up.
+    pub max_in_flight: usize,
+}
+
+impl Default for MirroringConfig {
+    fn default() -> Self {
+        MirroringConfig {
+            shadow_proto_addr: None,
+            fraction: 0.01,
+            max_in_flight: 64,
+        }
+    }
+}
+
+/// How range servers resolve the transactions left prepared on their ranges
+/// by coordinators that went away, as with `CleanupOrphanedPrepares` in
+/// rangeserver.proto: committed ones are committed, and undecided ones are
+/// aborted for good in the tx_state_store before being aborted on the range.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransactionRecoveryConfig {
+    /// Each loaded range is checked this often. Unset disables the
+    /// background job, prepares can still be resolved through the admin API.
+    pub interval: Option<time::Duration>,
+    /// Prepares younger than this are left to their coordinators. Should be
+    /// well over the longest transaction timeout, as a coordinator finding
+    /// its transaction aborted this way has to run it again.
+    pub prepared_for: time::Duration,
+}
+
+impl Default for TransactionRecoveryConfig {
+    fn default() -> Self {
+        TransactionRecoveryConfig {
+            interval: Some(time::Duration::from_secs(60)),
+            prepared_for: time::Duration::from_secs(600),
+        }
+    }
+}
+
+/// How ranges get rid of the records whose TTL ran out. Reads skip expired
+/// records either way, this only decides when their space is reclaimed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExpiryConfig {
+    /// Each loaded range purges its expired records this often. Unset
+    /// disables the background job.
+    pub interval: Option<time::Duration>,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        ExpiryConfig {
+            interval: Some(time::Duration::from_secs(60)),
+        }
+    }
+}
+
+/// How each range aggregates the conflicts it sees by key prefix.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConflictStatsConfig {
+    /// Keys are grouped by their first `prefix_len` bytes.
+    pub prefix_len: usize,
+    /// If set, a key's prefix also stops right after the first occurrence of
+    /// this byte, so that e.g. "user/1234/cart" is grouped under "user/".
+    pub delimiter: Option<u8>,
+    /// Prefixes tracked per range. Conflicts on other prefixes are only
+    /// counted in the range's totals.
+    pub max_prefixes: usize,
+    /// Transaction labels (key and value) tracked per range. Conflicts of
+    /// transactions with other labels are only counted by prefix.
+    pub max_labels: usize,
+}
+
+impl Default for ConflictStatsConfig {
+    fn default() -> Self {
+        ConflictStatsConfig {
+            prefix_len: 8,
+            delimiter: None,
+            max_prefixes: 1024,
+            max_labels: 256,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RegionConfig {
     pub warden_address: HostPort,
     pub epoch_publishers: HashSet<EpochPublisherSet>,
+    /// Name of the Cassandra datacenter local to the region, which queries
+    /// prefer. Queries go to any datacenter if unset.
+    #[serde(default)]
+    pub cassandra_datacenter: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -131,6 +491,29 @@ pub struct FrontendConfig {
     pub proto_server_addr: HostPort,
     pub fast_network_addr: HostPort,
     pub transaction_overall_timeout: std::time::Duration,
+    #[serde(default)]
+    pub fast_network_transport: FastNetworkTransport,
+}
+
+/// The certificate the fast networks using QUIC present, and require peers
+/// to present, as servers and as clients alike. It is shared by the whole
+/// cluster, typically self-signed, and must be valid for the name "atomix".
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuicConfig {
+    /// PEM file holding the certificate.
+    pub certificate_path: Option<String>,
+    /// PEM file holding its private key.
+    pub private_key_path: Option<String>,
+}
+
+/// Access to the admin endpoints of every process, such as profiling.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Bearer token admin calls must carry. Admin endpoints that can hurt
+    /// the process are refused if unset.
+    pub token: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -141,4 +524,83 @@ pub struct Config {
     pub frontend: FrontendConfig,
     pub cassandra: CassandraConfig,
     pub regions: HashMap<Region, RegionConfig>,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub quic: QuicConfig,
+}
+
+impl Config {
+    /// Returns the Cassandra datacenter that hosts in the given region should
+    /// prefer, if one is configured.
+    pub fn cassandra_datacenter(&self, region: &Region) -> Option<String> {
+        self.regions
+            .get(region)
+            .and_then(|r| r.cassandra_datacenter.clone())
+    }
+
+    /// Checks the failure domains of every epoch publisher set, see
+    /// `EpochPublisherSet::check_failure_domains`. Returns the problems
+    /// found, sorted.
+    pub fn check_epoch_publishers(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .regions
+            .values()
+            .flat_map(|region| &region.epoch_publishers)
+            .filter_map(|set| set.check_failure_domains().err())
+            .collect();
+        problems.sort();
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publisher_set(domains: &[Option<&str>]) -> EpochPublisherSet {
+        let publishers = domains
+            .iter()
+            .enumerate()
+            .map(|(i, domain)| EpochPublisher {
+                name: format!("ep{}", i),
+                backend_addr: HostPort::from_str(&format!("127.0.0.1:{}", 50000 + 2 * i)).unwrap(),
+                fast_network_addr: HostPort::from_str(&format!("127.0.0.1:{}", 50001 + 2 * i))
+                    .unwrap(),
+                failure_domain: domain.map(str::to_string),
+            })
+            .collect();
+        EpochPublisherSet {
+            name: "ps1".to_string(),
+            zone: Zone::from_str("test-region/a").unwrap(),
+            publishers,
+        }
+    }
+
+    #[test]
+    fn publisher_sets_must_survive_losing_a_failure_domain() {
+        let spread = publisher_set(&[Some("rack1"), Some("rack2"), Some("rack3")]);
+        assert_eq!(spread.quorum(), 2);
+        assert_eq!(spread.check_failure_domains(), Ok(()));
+
+        let doubled_up = publisher_set(&[Some("rack1"), Some("rack1"), Some("rack2")]);
+        let problem = doubled_up.check_failure_domains().unwrap_err();
+        assert!(problem.contains("failure domain rack1 (holding 2 of 3 publishers)"));
+
+        let five = publisher_set(&[
+            Some("rack1"),
+            Some("rack1"),
+            Some("rack2"),
+            Some("rack2"),
+            Some("rack3"),
+        ]);
+        assert_eq!(five.check_failure_domains(), Ok(()));
+
+        let single = publisher_set(&[Some("rack1")]);
+        assert!(single.check_failure_domains().is_err());
+
+        let unknown = publisher_set(&[Some("rack1"), None, Some("rack2")]);
+        let problem = unknown.check_failure_domains().unwrap_err();
+        assert!(problem.contains("no failure domain configured for ep1"));
+    }
 }
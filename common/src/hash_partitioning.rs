@@ -0,0 +1,169 @@
+use uuid::Uuid;
+
+/// Hashes a key into the 64-bit hash space that hash-partitioned keyspaces
+/// are split over. This must give the same result on every host and across
+/// releases, so it is FNV-1a followed by a 64-bit finalizer rather than the
+/// std hasher.
+pub fn key_hash(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct HashPartition {
+    hash_lower_bound_inclusive: u64,
+    range_id: Uuid,
+}
+
+/// How the hash space of a hash-partitioned keyspace is split into ranges.
+/// Each range owns a contiguous slice of the hash space, so a key's range is
+/// known from its hash alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HashPartitioning {
+    // Sorted by lower bound. The first one starts at 0 and each one ends
+    // where the next one starts.
+    partitions: Vec<HashPartition>,
+}
+
+impl HashPartitioning {
+    /// Splits the hash space evenly between the given ranges, in order.
+    pub fn uniform(range_ids: Vec<Uuid>) -> Result<HashPartitioning, String> {
+        if range_ids.is_empty() {
+            return Err("A hash partitioning needs at least one range".to_string());
+        }
+        let width = (u64::MAX as u128 + 1) / range_ids.len() as u128;
+        let partitions = range_ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, range_id)| HashPartition {
+                hash_lower_bound_inclusive: (i as u128 * width) as u64,
+                range_id,
+            })
+            .collect();
+        Ok(HashPartitioning { partitions })
+    }
+
+    pub fn range_of_hash(&self, hash: u64) -> Uuid {
+        let idx = self
+            .partitions
+            .partition_point(|p| p.hash_lower_bound_inclusive <= hash);
+        self.partitions[idx - 1].range_id
+    }
+
+    pub fn range_of_key(&self, key: &[u8]) -> Uuid {
+        self.range_of_hash(key_hash(key))
+    }
+
+    /// Returns the slice of the hash space owned by the range, as its
+    /// inclusive lower bound and exclusive upper bound (None at the top).
+    pub fn hash_range(&self, range_id: &Uuid) -> Option<(u64, Option<u64>)> {
+        let idx = self
+            .partitions
+            .iter()
+            .position(|p| p.range_id == *range_id)?;
+        Some(self.bounds(idx))
+    }
+
+    pub fn range_ids(&self) -> impl Iterator<Item = &Uuid> {
+        self.partitions.iter().map(|p| &p.range_id)
+    }
+
+    fn bounds(&self, idx: usize) -> (u64, Option<u64>) {
+        (
+            self.partitions[idx].hash_lower_bound_inclusive,
+            self.partitions
+                .get(idx + 1)
+                .map(|p| p.hash_lower_bound_inclusive),
+        )
+    }
+
+    /// Splits the hash space of `range_id` in half. The range keeps the lower
+    /// half and `new_range_id` gets the upper half. Keys never move between
+    /// ranges other than these two.
+    pub fn split(&mut self, range_id: &Uuid, new_range_id: Uuid) -> Result<(), String> {
+        if self.partitions.iter().any(|p| p.range_id == new_range_id) {
+            return Err(format!("Range {} already exists", new_range_id));
+        }
+        let idx = self
+            .partitions
+            .iter()
+            .position(|p| p.range_id == *range_id)
+            .ok_or_else(|| format!("Unknown range: {}", range_id))?;
+        let (lower, upper) = self.bounds(idx);
+        let upper = upper.map_or(u64::MAX as u128 + 1, |u| u as u128);
+        let width = upper - lower as u128;
+        if width < 2 {
+            return Err(format!("Range {} is too small to split", range_id));
+        }
+        self.partitions.insert(
+            idx + 1,
+            HashPartition {
+                hash_lower_bound_inclusive: (lower as u128 + width / 2) as u64,
+                range_id: new_range_id,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_covers_hash_space() {
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let partitioning = HashPartitioning::uniform(ids.clone()).unwrap();
+        assert_eq!(partitioning.range_of_hash(0), ids[0]);
+        assert_eq!(partitioning.range_of_hash(u64::MAX / 2), ids[1]);
+        assert_eq!(partitioning.range_of_hash(u64::MAX), ids[2]);
+        assert_eq!(partitioning.hash_range(&ids[2]).unwrap().1, None);
+        assert!(HashPartitioning::uniform(vec![]).is_err());
+    }
+
+    #[test]
+    fn split_moves_upper_half_only() {
+        let ids: Vec<Uuid> = (0..2).map(|_| Uuid::new_v4()).collect();
+        let mut partitioning = HashPartitioning::uniform(ids.clone()).unwrap();
+        let keys: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let before: Vec<Uuid> = keys.iter().map(|k| partitioning.range_of_key(k)).collect();
+
+        let new_id = Uuid::new_v4();
+        partitioning.split(&ids[0], new_id).unwrap();
+        assert_eq!(partitioning.hash_range(&ids[0]), Some((0, Some(1 << 62))));
+        assert_eq!(
+            partitioning.hash_range(&new_id),
+            Some((1 << 62, Some(1 << 63)))
+        );
+        let mut moved = 0;
+        for (key, old) in keys.iter().zip(before) {
+            let new = partitioning.range_of_key(key);
+            if new != old {
+                assert_eq!(old, ids[0]);
+                assert_eq!(new, new_id);
+                moved += 1;
+            }
+        }
+        assert!(moved > 0);
+
+        assert!(partitioning.split(&ids[1], new_id).is_err());
+        assert!(partitioning.split(&Uuid::new_v4(), Uuid::new_v4()).is_err());
+    }
+
+    #[test]
+    fn key_hash_is_stable() {
+        // Changing this value would move keys between ranges in existing
+        // hash-partitioned keyspaces.
+        assert_eq!(key_hash(b"atomix"), 0xd4c684335dc16cc7);
+        assert_ne!(key_hash(b"a"), key_hash(b"b"));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Hash)]
@@ -11,4 +12,7 @@ pub struct HostInfo {
     pub identity: HostIdentity,
     pub address: SocketAddr,
     pub warden_connection_epoch: u64,
+    /// Operator-defined attributes of the host, e.g. "ssd" => "true", that
+    /// keyspaces can require of the range servers their ranges go on.
+    pub labels: HashMap<String, String>,
 }
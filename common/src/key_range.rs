@@ -32,6 +32,49 @@ impl KeyRange {
             upper_bound_exclusive: Some(Bytes::new()),
         }
     }
+
+    /// The keys starting with `prefix`.
+    pub fn with_prefix(prefix: Bytes) -> KeyRange {
+        // The upper bound is the smallest key greater than every key with the
+        // prefix: the prefix with its trailing 0xff bytes dropped and the
+        // last remaining byte incremented. Prefixes of only 0xff bytes (or
+        // no bytes) have no such key.
+        let upper_bound_exclusive = prefix.iter().rposition(|b| *b != 0xff).map(|i| {
+            let mut upper = prefix[..=i].to_vec();
+            upper[i] += 1;
+            Bytes::from(upper)
+        });
+        KeyRange {
+            lower_bound_inclusive: Some(prefix),
+            upper_bound_exclusive,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match (&self.lower_bound_inclusive, &self.upper_bound_exclusive) {
+            (_, None) => false,
+            (None, Some(upper)) => upper.is_empty(),
+            (Some(lower), Some(upper)) => lower >= upper,
+        }
+    }
+
+    /// The keys that fall in both ranges.
+    pub fn intersection(&self, other: &KeyRange) -> KeyRange {
+        let lower_bound_inclusive =
+            match (&self.lower_bound_inclusive, &other.lower_bound_inclusive) {
+                (Some(a), Some(b)) => Some(a.max(b).clone()),
+                (a, b) => a.clone().or(b.clone()),
+            };
+        let upper_bound_exclusive =
+            match (&self.upper_bound_exclusive, &other.upper_bound_exclusive) {
+                (Some(a), Some(b)) => Some(a.min(b).clone()),
+                (a, b) => a.clone().or(b.clone()),
+            };
+        KeyRange {
+            lower_bound_inclusive,
+            upper_bound_exclusive,
+        }
+    }
 }
 
 impl From<&proto::universe::KeyRange> for KeyRange {
@@ -61,4 +104,55 @@ mod tests {
         assert!(!real_range.includes(Bytes::from_static(b"G")));
         assert!(!real_range.includes(Bytes::from_static(b"Z")));
     }
+
+    #[test]
+    fn intersection() {
+        let range = |lower: Option<&'static [u8]>, upper: Option<&'static [u8]>| KeyRange {
+            lower_bound_inclusive: lower.map(Bytes::from_static),
+            upper_bound_exclusive: upper.map(Bytes::from_static),
+        };
+        let c_to_g = range(Some(b"C"), Some(b"G"));
+        assert_eq!(c_to_g.intersection(&KeyRange::all()), c_to_g);
+        assert_eq!(
+            c_to_g.intersection(&range(Some(b"E"), None)),
+            range(Some(b"E"), Some(b"G"))
+        );
+        assert_eq!(
+            range(None, Some(b"D")).intersection(&c_to_g),
+            range(Some(b"C"), Some(b"D"))
+        );
+        assert!(c_to_g.intersection(&range(Some(b"G"), None)).is_empty());
+        assert!(KeyRange::empty().is_empty());
+        assert!(!KeyRange::all().is_empty());
+    }
+
+    #[test]
+    fn with_prefix() {
+        let users = KeyRange::with_prefix(Bytes::from_static(b"user/"));
+        assert_eq!(
+            users.upper_bound_exclusive,
+            Some(Bytes::from_static(b"user0"))
+        );
+        assert!(users.includes(Bytes::from_static(b"user/")));
+        assert!(users.includes(Bytes::from_static(b"user/\xff\xff")));
+        assert!(!users.includes(Bytes::from_static(b"user0")));
+        assert!(!users.includes(Bytes::from_static(b"user")));
+
+        let trailing = KeyRange::with_prefix(Bytes::from_static(b"a\xff\xff"));
+        assert_eq!(
+            trailing.upper_bound_exclusive,
+            Some(Bytes::from_static(b"b"))
+        );
+        assert!(trailing.includes(Bytes::from_static(b"a\xff\xff\x00")));
+
+        let unbounded = KeyRange::with_prefix(Bytes::from_static(b"\xff"));
+        assert_eq!(unbounded.upper_bound_exclusive, None);
+        assert_eq!(
+            KeyRange::with_prefix(Bytes::new()),
+            KeyRange {
+                lower_bound_inclusive: Some(Bytes::new()),
+                upper_bound_exclusive: None,
+            }
+        );
+    }
 }
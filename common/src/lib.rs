@@ -1,13 +1,17 @@
+pub mod clock;
+pub mod colocation;
 pub mod config;
 pub mod constants;
 pub mod epoch_lease;
 pub mod full_range_id;
+pub mod hash_partitioning;
 pub mod host_info;
 pub mod key_range;
 pub mod keyspace;
 pub mod keyspace_id;
 pub mod membership;
 pub mod network;
+pub mod profiling;
 pub mod record;
 pub mod region;
 pub mod transaction_info;
@@ -0,0 +1,174 @@
+use crate::{
+    full_range_id::FullRangeId, hash_partitioning::HashPartitioning, host_info::HostInfo,
+    key_range::KeyRange, keyspace_id::KeyspaceId,
+    membership::range_assignment_oracle::RangeAssignmentOracle,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use uuid::Uuid;
+
+/// A `RangeAssignmentOracle` for hash-partitioned keyspaces. The range of a
+/// key is computed from its hash, without asking anyone. Hosts, and the
+/// ranges of keyspaces that are not hash-partitioned, come from `inner`.
+pub struct HashRangeAssignmentOracle {
+    inner: Arc<dyn RangeAssignmentOracle>,
+    partitionings: RwLock<HashMap<KeyspaceId, HashPartitioning>>,
+}
+
+impl HashRangeAssignmentOracle {
+    pub fn new(inner: Arc<dyn RangeAssignmentOracle>) -> HashRangeAssignmentOracle {
+        HashRangeAssignmentOracle {
+            inner,
+            partitionings: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Declares the keyspace hash-partitioned, replacing any previous
+    /// partitioning of it.
+    pub fn set_partitioning(&self, keyspace_id: KeyspaceId, partitioning: HashPartitioning) {
+        self.partitionings
+            .write()
+            .unwrap()
+            .insert(keyspace_id, partitioning);
+    }
+
+    pub fn partitioning(&self, keyspace_id: &KeyspaceId) -> Option<HashPartitioning> {
+        self.partitionings.read().unwrap().get(keyspace_id).cloned()
+    }
+
+    /// Applies a split of a hash-partitioned range, see
+    /// `HashPartitioning::split`.
+    pub fn split_range(&self, range_id: &FullRangeId, new_range_id: Uuid) -> Result<(), String> {
+        let mut partitionings = self.partitionings.write().unwrap();
+        let partitioning = partitionings
+            .get_mut(&range_id.keyspace_id)
+            .ok_or_else(|| {
+                format!(
+                    "Keyspace {} is not hash-partitioned",
+                    range_id.keyspace_id.id
+                )
+            })?;
+        partitioning.split(&range_id.range_id, new_range_id)
+    }
+}
+
+#[async_trait]
+impl RangeAssignmentOracle for HashRangeAssignmentOracle {
+    async fn full_range_id_of_key(
+        &self,
+        keyspace_id: KeyspaceId,
+        key: Bytes,
+    ) -> Option<FullRangeId> {
+        let range_id = self
+            .partitionings
+            .read()
+            .unwrap()
+            .get(&keyspace_id)
+            .map(|p| p.range_of_key(&key));
+        match range_id {
+            Some(range_id) => Some(FullRangeId {
+                keyspace_id,
+                range_id,
+            }),
+            None => self.inner.full_range_id_of_key(keyspace_id, key).await,
+        }
+    }
+
+    async fn full_range_ids_of_key_range(
+        &self,
+        keyspace_id: KeyspaceId,
+        key_range: &KeyRange,
+    ) -> Option<Vec<FullRangeId>> {
+        // Hashing scatters any key range over all the ranges.
+        let range_ids: Option<Vec<Uuid>> = self
+            .partitionings
+            .read()
+            .unwrap()
+            .get(&keyspace_id)
+            .map(|p| p.range_ids().copied().collect());
+        match range_ids {
+            Some(range_ids) => Some(
+                range_ids
+                    .into_iter()
+                    .map(|range_id| FullRangeId {
+                        keyspace_id,
+                        range_id,
+                    })
+                    .collect(),
+            ),
+            None => {
+                self.inner
+                    .full_range_ids_of_key_range(keyspace_id, key_range)
+                    .await
+            }
+        }
+    }
+
+    async fn host_of_range(&self, range_id: &FullRangeId) -> Option<HostInfo> {
+        self.inner.host_of_range(range_id).await
+    }
+
+    fn maybe_refresh_host_of_range(&self, range_id: &FullRangeId) {
+        self.inner.maybe_refresh_host_of_range(range_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::membership::static_range_assignment_oracle::{
+        StaticAssignment, StaticRangeAssignmentOracle,
+    };
+
+    #[tokio::test]
+    async fn routes_hash_partitioned_keyspaces_by_hash() {
+        let inner =
+            Arc::new(StaticRangeAssignmentOracle::new(StaticAssignment::default()).unwrap());
+        let oracle = HashRangeAssignmentOracle::new(inner);
+        let keyspace_id = KeyspaceId::new(Uuid::new_v4());
+        let range_id = Uuid::new_v4();
+        let key = Bytes::from_static(b"key");
+
+        assert!(oracle
+            .full_range_id_of_key(keyspace_id, key.clone())
+            .await
+            .is_none());
+        oracle.set_partitioning(
+            keyspace_id,
+            HashPartitioning::uniform(vec![range_id]).unwrap(),
+        );
+        let full_range_id = oracle
+            .full_range_id_of_key(keyspace_id, key.clone())
+            .await
+            .unwrap();
+        assert_eq!(full_range_id.range_id, range_id);
+
+        let new_range_id = Uuid::new_v4();
+        oracle.split_range(&full_range_id, new_range_id).unwrap();
+        let expected = oracle
+            .partitioning(&keyspace_id)
+            .unwrap()
+            .range_of_key(&key);
+        assert_eq!(
+            oracle
+                .full_range_id_of_key(keyspace_id, key)
+                .await
+                .unwrap()
+                .range_id,
+            expected
+        );
+        assert!(oracle
+            .split_range(
+                &FullRangeId {
+                    keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+                    range_id,
+                },
+                Uuid::new_v4()
+            )
+            .is_err());
+    }
+}
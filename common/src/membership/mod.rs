@@ -1 +1,3 @@
+pub mod hash_range_assignment_oracle;
 pub mod range_assignment_oracle;
+pub mod static_range_assignment_oracle;
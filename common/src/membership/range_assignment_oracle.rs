@@ -1,4 +1,6 @@
-use crate::{full_range_id::FullRangeId, host_info::HostInfo, keyspace_id::KeyspaceId};
+use crate::{
+    full_range_id::FullRangeId, host_info::HostInfo, key_range::KeyRange, keyspace_id::KeyspaceId,
+};
 use async_trait::async_trait;
 use bytes::Bytes;
 
@@ -9,9 +11,15 @@ pub trait RangeAssignmentOracle: Send + Sync + 'static {
         keyspace_id: KeyspaceId,
         key: Bytes,
     ) -> Option<FullRangeId>;
+    /// Returns every range of the keyspace that may hold keys in
+    /// `key_range`, or None if the keyspace is unknown.
+    async fn full_range_ids_of_key_range(
+        &self,
+        keyspace_id: KeyspaceId,
+        key_range: &KeyRange,
+    ) -> Option<Vec<FullRangeId>>;
     async fn host_of_range(&self, range_id: &FullRangeId) -> Option<HostInfo>;
     /// Requests refreshing the assignment.
     /// Should be used whenever a host says it does not own the range.
     fn maybe_refresh_host_of_range(&self, range_id: &FullRangeId);
-    // TODO: provide APIs for key ranges / scans
 }
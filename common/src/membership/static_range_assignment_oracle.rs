@@ -0,0 +1,233 @@
+use crate::{
+    full_range_id::FullRangeId,
+    host_info::{HostIdentity, HostInfo},
+    key_range::KeyRange,
+    keyspace_id::KeyspaceId,
+    membership::range_assignment_oracle::RangeAssignmentOracle,
+    region::Zone,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::SocketAddr, path::Path, str::FromStr};
+use uuid::Uuid;
+
+/// A range server named by a `StaticAssignment`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StaticHost {
+    pub zone: Zone,
+    pub address: SocketAddr,
+}
+
+/// One range of a keyspace and the host serving it. Bounds are UTF-8 keys;
+/// a missing bound means the range is unbounded on that side.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StaticRange {
+    pub range_id: String,
+    #[serde(default)]
+    pub lower_bound_inclusive: Option<String>,
+    #[serde(default)]
+    pub upper_bound_exclusive: Option<String>,
+    pub host: String,
+}
+
+/// A fixed assignment of key ranges to hosts, as read from a config file.
+/// `hosts` is keyed by host name and `keyspaces` by keyspace id.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StaticAssignment {
+    pub hosts: HashMap<String, StaticHost>,
+    pub keyspaces: HashMap<String, Vec<StaticRange>>,
+}
+
+/// A `RangeAssignmentOracle` that serves a fixed assignment instead of
+/// following the warden. Useful for tests, benchmarks and small deployments
+/// where ranges never move.
+pub struct StaticRangeAssignmentOracle {
+    // Sorted by lower bound, with no overlaps.
+    ranges: HashMap<KeyspaceId, Vec<(KeyRange, FullRangeId)>>,
+    hosts: HashMap<FullRangeId, HostInfo>,
+}
+
+fn parse_uuid(s: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(s).map_err(|_| format!("Invalid UUID: {}", s))
+}
+
+fn key_bound(bound: &Option<String>) -> Option<Bytes> {
+    bound.as_ref().map(|b| Bytes::copy_from_slice(b.as_bytes()))
+}
+
+impl StaticRangeAssignmentOracle {
+    pub fn new(assignment: StaticAssignment) -> Result<Self, String> {
+        let mut ranges = HashMap::new();
+        let mut hosts = HashMap::new();
+        for (keyspace_id, keyspace_ranges) in &assignment.keyspaces {
+            let keyspace_id = KeyspaceId::from_str(keyspace_id)?;
+            let mut key_ranges: Vec<(KeyRange, FullRangeId)> = Vec::new();
+            for range in keyspace_ranges {
+                let range_id = FullRangeId {
+                    keyspace_id,
+                    range_id: parse_uuid(&range.range_id)?,
+                };
+                let host = assignment
+                    .hosts
+                    .get(&range.host)
+                    .ok_or_else(|| format!("Unknown host: {}", range.host))?;
+                let host_info = HostInfo {
+                    identity: HostIdentity {
+                        name: range.host.clone(),
+                        zone: host.zone.clone(),
+                    },
+                    address: host.address,
+                    warden_connection_epoch: 0,
+                    labels: Default::default(),
+                };
+                if hosts.insert(range_id, host_info).is_some() {
+                    return Err(format!("Duplicate range: {}", range.range_id));
+                }
+                key_ranges.push((
+                    KeyRange {
+                        lower_bound_inclusive: key_bound(&range.lower_bound_inclusive),
+                        upper_bound_exclusive: key_bound(&range.upper_bound_exclusive),
+                    },
+                    range_id,
+                ));
+            }
+            // None sorts first, which is what an unbounded lower bound means.
+            key_ranges.sort_by(|a, b| a.0.lower_bound_inclusive.cmp(&b.0.lower_bound_inclusive));
+            for pair in key_ranges.windows(2) {
+                let overlaps = match (
+                    &pair[0].0.upper_bound_exclusive,
+                    &pair[1].0.lower_bound_inclusive,
+                ) {
+                    (None, _) | (_, None) => true,
+                    (Some(upper), Some(lower)) => upper > lower,
+                };
+                if overlaps {
+                    return Err(format!(
+                        "Overlapping ranges {} and {} in keyspace {}",
+                        pair[0].1.range_id, pair[1].1.range_id, keyspace_id.id
+                    ));
+                }
+            }
+            ranges.insert(keyspace_id, key_ranges);
+        }
+        Ok(StaticRangeAssignmentOracle { ranges, hosts })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let assignment: StaticAssignment =
+            serde_json::from_str(json).map_err(|e| format!("Invalid assignment: {}", e))?;
+        Self::new(assignment)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+}
+
+#[async_trait]
+impl RangeAssignmentOracle for StaticRangeAssignmentOracle {
+    async fn full_range_id_of_key(
+        &self,
+        keyspace_id: KeyspaceId,
+        key: Bytes,
+    ) -> Option<FullRangeId> {
+        self.ranges
+            .get(&keyspace_id)?
+            .iter()
+            .find(|(key_range, _)| key_range.includes(key.clone()))
+            .map(|(_, range_id)| *range_id)
+    }
+
+    async fn full_range_ids_of_key_range(
+        &self,
+        keyspace_id: KeyspaceId,
+        key_range: &KeyRange,
+    ) -> Option<Vec<FullRangeId>> {
+        Some(
+            self.ranges
+                .get(&keyspace_id)?
+                .iter()
+                .filter(|(range, _)| !range.intersection(key_range).is_empty())
+                .map(|(_, range_id)| *range_id)
+                .collect(),
+        )
+    }
+
+    async fn host_of_range(&self, range_id: &FullRangeId) -> Option<HostInfo> {
+        self.hosts.get(range_id).cloned()
+    }
+
+    fn maybe_refresh_host_of_range(&self, _range_id: &FullRangeId) {
+        // The assignment never changes, so there is nothing to refresh.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYSPACE: &str = "7c1e9fc1-3a5b-4c8e-9a0f-2d6b2f3e4a51";
+    const RANGE_A: &str = "0b6c2d4e-1f3a-4b5c-8d7e-9f0a1b2c3d4e";
+    const RANGE_B: &str = "5e6f7a8b-9c0d-4e1f-a2b3-c4d5e6f7a8b9";
+
+    fn assignment_json(split_a: &str, split_b: &str) -> String {
+        format!(
+            r#"{{
+                "hosts": {{
+                    "rs1": {{"zone": "test-region/a", "address": "127.0.0.1:50054"}},
+                    "rs2": {{"zone": "test-region/a", "address": "127.0.0.1:50055"}}
+                }},
+                "keyspaces": {{
+                    "{KEYSPACE}": [
+                        {{"range_id": "{RANGE_B}", "lower_bound_inclusive": "{split_b}", "host": "rs2"}},
+                        {{"range_id": "{RANGE_A}", "upper_bound_exclusive": "{split_a}", "host": "rs1"}}
+                    ]
+                }}
+            }}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn routes_keys_to_their_range_and_host() {
+        let oracle = StaticRangeAssignmentOracle::from_json(&assignment_json("m", "m")).unwrap();
+        let keyspace_id = KeyspaceId::from_str(KEYSPACE).unwrap();
+
+        let range = oracle
+            .full_range_id_of_key(keyspace_id, Bytes::from_static(b"apple"))
+            .await
+            .unwrap();
+        assert_eq!(range.range_id, Uuid::parse_str(RANGE_A).unwrap());
+        let host = oracle.host_of_range(&range).await.unwrap();
+        assert_eq!(host.identity.name, "rs1");
+        assert_eq!(host.address, "127.0.0.1:50054".parse().unwrap());
+
+        let range = oracle
+            .full_range_id_of_key(keyspace_id, Bytes::from_static(b"m"))
+            .await
+            .unwrap();
+        assert_eq!(range.range_id, Uuid::parse_str(RANGE_B).unwrap());
+        let host = oracle.host_of_range(&range).await.unwrap();
+        assert_eq!(host.identity.name, "rs2");
+
+        let other_keyspace = KeyspaceId::new(Uuid::new_v4());
+        assert!(oracle
+            .full_range_id_of_key(other_keyspace, Bytes::from_static(b"apple"))
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_overlapping_ranges() {
+        assert!(StaticRangeAssignmentOracle::from_json(&assignment_json("n", "m")).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_host() {
+        let json = assignment_json("m", "m").replace("\"host\": \"rs2\"", "\"host\": \"rs3\"");
+        assert!(StaticRangeAssignmentOracle::from_json(&json).is_err());
+    }
+}
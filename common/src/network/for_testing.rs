@@ -1 +1,2 @@
+pub mod in_memory_fast_network;
 pub mod udp_fast_network;
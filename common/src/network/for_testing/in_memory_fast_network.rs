@@ -0,0 +1,155 @@
+use crate::network::fast_network::FastNetwork as Trait;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tracing::trace;
+
+type Inbox = Arc<Mutex<VecDeque<(SocketAddr, Bytes)>>>;
+
+/// A network that lives entirely in memory, connecting the endpoints created
+/// from it. Lets several components run in one process without binding any
+/// sockets.
+#[derive(Default)]
+pub struct InMemoryNetwork {
+    inboxes: RwLock<HashMap<SocketAddr, Inbox>>,
+}
+
+impl InMemoryNetwork {
+    pub fn new() -> Arc<InMemoryNetwork> {
+        Arc::new(InMemoryNetwork::default())
+    }
+
+    /// Creates the endpoint for `address`. Panics if the address is already
+    /// taken by a live endpoint.
+    pub fn endpoint(self: &Arc<Self>, address: SocketAddr) -> Arc<InMemoryFastNetwork> {
+        let inbox = Inbox::default();
+        let mut inboxes = self.inboxes.write().unwrap();
+        if inboxes.contains_key(&address) {
+            panic!("address {} is already in use", address);
+        }
+        inboxes.insert(address, inbox.clone());
+        Arc::new(InMemoryFastNetwork {
+            address,
+            network: self.clone(),
+            inbox,
+            listeners: RwLock::new(HashMap::new()),
+            default_handler: RwLock::new(DefaultHandler::NotRegistered),
+        })
+    }
+}
+
+enum DefaultHandler {
+    NotRegistered,
+    Registered(mpsc::UnboundedSender<(SocketAddr, Bytes)>),
+}
+
+pub struct InMemoryFastNetwork {
+    address: SocketAddr,
+    network: Arc<InMemoryNetwork>,
+    inbox: Inbox,
+    listeners: RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<Bytes>>>,
+    default_handler: RwLock<DefaultHandler>,
+}
+
+impl InMemoryFastNetwork {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for InMemoryFastNetwork {
+    fn drop(&mut self) {
+        self.network.inboxes.write().unwrap().remove(&self.address);
+    }
+}
+
+impl Trait for InMemoryFastNetwork {
+    fn send(&self, to: SocketAddr, payload: Bytes) -> Result<(), std::io::Error> {
+        trace!("Sending to: {:?}", to);
+        let inboxes = self.network.inboxes.read().unwrap();
+        // Like UDP, messages to an address nobody listens on are dropped.
+        if let Some(inbox) = inboxes.get(&to) {
+            inbox.lock().unwrap().push_back((self.address, payload));
+        }
+        Ok(())
+    }
+
+    fn listen_default(&self) -> mpsc::UnboundedReceiver<(SocketAddr, Bytes)> {
+        let (s, r) = mpsc::unbounded_channel();
+        let mut default_handler = self.default_handler.write().unwrap();
+        *default_handler = DefaultHandler::Registered(s);
+        r
+    }
+
+    fn register(&self, from: SocketAddr) -> mpsc::UnboundedReceiver<Bytes> {
+        let (s, r) = mpsc::unbounded_channel();
+        let mut listeners = self.listeners.write().unwrap();
+        listeners.insert(from, s);
+        r
+    }
+
+    fn poll(&self) -> bool {
+        let (sender_addr, bytes) = match self.inbox.lock().unwrap().pop_front() {
+            None => return false,
+            Some(message) => message,
+        };
+        let listeners = self.listeners.read().unwrap();
+        let default_listener = self.default_handler.read().unwrap();
+        match listeners.get(&sender_addr) {
+            None => match &*default_listener {
+                DefaultHandler::NotRegistered => (),
+                DefaultHandler::Registered(s) => {
+                    let _ = s.send((sender_addr, bytes));
+                }
+            },
+            Some(s) => {
+                let _ = s.send(bytes);
+            }
+        };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn delivers_to_registered_and_default_listeners() {
+        let network = InMemoryNetwork::new();
+        let a = network.endpoint("127.0.0.1:1".parse().unwrap());
+        let b = network.endpoint("127.0.0.1:2".parse().unwrap());
+        let c = network.endpoint("127.0.0.1:3".parse().unwrap());
+        let mut from_a = b.register(a.address());
+        let mut default = b.listen_default();
+
+        a.send(b.address(), Bytes::from_static(b"hello")).unwrap();
+        c.send(b.address(), Bytes::from_static(b"world")).unwrap();
+        assert!(b.poll());
+        assert!(b.poll());
+        assert!(!b.poll());
+        assert_eq!(from_a.recv().await.unwrap(), Bytes::from_static(b"hello"));
+        assert_eq!(
+            default.recv().await.unwrap(),
+            (c.address(), Bytes::from_static(b"world"))
+        );
+    }
+
+    #[test]
+    fn address_is_released_on_drop() {
+        let network = InMemoryNetwork::new();
+        let address = "127.0.0.1:1".parse().unwrap();
+        let endpoint = network.endpoint(address);
+        // Messages to a dropped endpoint go nowhere.
+        drop(endpoint);
+        let other = network.endpoint("127.0.0.1:2".parse().unwrap());
+        other.send(address, Bytes::new()).unwrap();
+        let endpoint = network.endpoint(address);
+        assert!(!endpoint.poll());
+    }
+}
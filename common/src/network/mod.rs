@@ -1,2 +1,4 @@
 pub mod fast_network;
 pub mod for_testing;
+pub mod quic_fast_network;
+pub mod tcp_fast_network;
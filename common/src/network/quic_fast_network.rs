@@ -0,0 +1,683 @@
+//! A `FastNetwork` over QUIC, for congestion control and encryption on the
+//! fast path without the head-of-line blocking of TCP.
+//!
+//! A single connection is kept to each peer and used in both directions, and
+//! every message is sent on a stream of its own, so that a lost packet only
+//! holds up the message it carried rather than, say, the prepares of other
+//! transactions queued behind it. Connections to a peer that was reached
+//! before are resumed with 0-RTT, so reconnecting after an idle timeout or a
+//! restart of this process costs no round trip.
+//!
+//! 0-RTT data can be replayed by anyone on the path, and prepares, commits
+//! and aborts are not safe to process twice. Only the messages
+//! `replay_safe` allows are sent before the handshake of a resumed
+//! connection completes, the others wait for it, and receivers drop any
+//! other message that arrived as 0-RTT.
+//!
+//! Peers authenticate each other with the certificate shared by the
+//! cluster: both ends of a connection must present it.
+
+use crate::{config::QuicConfig, network::fast_network::FastNetwork as Trait};
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use bytes::Bytes;
+use flatbuf::rangeserver_flatbuffers::range_server::{MessageType, RequestEnvelope};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    ClientConfig, Connection, Endpoint, EndpointConfig, ServerConfig, TokioRuntime, WriteError,
+    ZeroRttAccepted,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use tokio::sync::{mpsc, watch};
+use tracing::{trace, warn};
+
+/// Messages longer than this are refused by the sender and dropped by the
+/// receiver.
+pub const MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+/// The name the certificate of every peer must be valid for.
+pub const SERVER_NAME: &str = "atomix";
+
+/// The certificate and key a `QuicFastNetwork` presents to its peers. Peers
+/// are only trusted if they present the same certificate, whichever end of
+/// the connection they are.
+pub struct QuicTls {
+    certificate: CertificateDer<'static>,
+    private_key: PrivateKeyDer<'static>,
+}
+
+impl QuicTls {
+    /// Reads the certificate and key from the files named in `config`.
+    pub fn from_config(config: &QuicConfig) -> io::Result<QuicTls> {
+        let (Some(certificate_path), Some(private_key_path)) =
+            (&config.certificate_path, &config.private_key_path)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "quic.certificate_path and quic.private_key_path must be set",
+            ));
+        };
+        let certificate = rustls_pemfile::certs(&mut io::BufReader::new(std::fs::File::open(
+            certificate_path,
+        )?))
+        .next()
+        .ok_or_else(|| invalid_data(format!("no certificate in {}", certificate_path)))??;
+        let private_key = rustls_pemfile::private_key(&mut io::BufReader::new(
+            std::fs::File::open(private_key_path)?,
+        ))?
+        .ok_or_else(|| invalid_data(format!("no private key in {}", private_key_path)))?;
+        Ok(QuicTls {
+            certificate,
+            private_key,
+        })
+    }
+
+    /// Generates a self-signed certificate, to be shared by the networks of
+    /// a test.
+    pub fn self_signed() -> QuicTls {
+        let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).unwrap();
+        QuicTls {
+            certificate: certified.cert.der().clone(),
+            private_key: PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into()),
+        }
+    }
+
+    fn roots(&self) -> io::Result<Arc<rustls::RootCertStore>> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(self.certificate.clone())
+            .map_err(io::Error::other)?;
+        Ok(Arc::new(roots))
+    }
+
+    fn server_config(&self) -> io::Result<ServerConfig> {
+        let client_verifier =
+            WebPkiClientVerifier::builder_with_provider(self.roots()?, crypto_provider())
+                .build()
+                .map_err(io::Error::other)?;
+        let mut crypto = rustls::ServerConfig::builder_with_provider(crypto_provider())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(io::Error::other)?
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(vec![self.certificate.clone()], self.private_key.clone_key())
+            .map_err(io::Error::other)?;
+        // Required by QUIC to accept 0-RTT.
+        crypto.max_early_data_size = u32::MAX;
+        let crypto = QuicServerConfig::try_from(crypto).map_err(io::Error::other)?;
+        Ok(ServerConfig::with_crypto(Arc::new(crypto)))
+    }
+
+    fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut crypto = rustls::ClientConfig::builder_with_provider(crypto_provider())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(io::Error::other)?
+            .with_root_certificates(self.roots()?)
+            .with_client_auth_cert(vec![self.certificate.clone()], self.private_key.clone_key())
+            .map_err(io::Error::other)?;
+        crypto.enable_early_data = true;
+        let crypto = QuicClientConfig::try_from(crypto).map_err(io::Error::other)?;
+        Ok(ClientConfig::new(Arc::new(crypto)))
+    }
+}
+
+fn crypto_provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Whether processing the message twice is harmless, so that it may be sent
+/// as 0-RTT data. Gets and scans are not, as they lock on behalf of their
+/// transaction, and a replay after it finished would leave the lock behind.
+/// Messages that are not range server envelopes never are. Responses have
+/// the same layout as requests, and are classified by their type as well.
+pub fn replay_safe(payload: &[u8]) -> bool {
+    match flatbuffers::root::<RequestEnvelope>(payload) {
+        Ok(envelope) => matches!(
+            envelope.type_(),
+            MessageType::Validate | MessageType::GetConflictStats
+        ),
+        Err(_) => false,
+    }
+}
+
+// Returns whether the handshake of a connection completed, once it does.
+fn established(accepted: Option<ZeroRttAccepted>) -> watch::Receiver<bool> {
+    let Some(accepted) = accepted else {
+        return watch::channel(true).1;
+    };
+    let (sender, receiver) = watch::channel(false);
+    tokio::spawn(async move {
+        // Also resolves if the handshake failed, in which case writing to
+        // the connection fails too.
+        accepted.await;
+        let _ = sender.send(true);
+    });
+    receiver
+}
+
+enum DefaultHandler {
+    NotRegistered,
+    Registered(mpsc::UnboundedSender<(SocketAddr, Bytes)>),
+}
+
+#[derive(Clone)]
+struct Peer {
+    connection: Connection,
+    // Whether the handshake completed, after which the streams opened are
+    // no longer 0-RTT.
+    established: watch::Receiver<bool>,
+}
+
+// The connection to a peer, locked while it is being established so that
+// concurrent sends wait for it rather than each opening their own.
+type ConnectionSlot = Arc<tokio::sync::Mutex<Option<Peer>>>;
+
+// What the tasks sending and receiving messages share with the network.
+struct Shared {
+    endpoint: Endpoint,
+    client_config: ClientConfig,
+    connections: Mutex<HashMap<SocketAddr, ConnectionSlot>>,
+    received: mpsc::UnboundedSender<(SocketAddr, Bytes)>,
+    zero_rtt_connections: AtomicUsize,
+    // Messages dropped for not being safe to receive as 0-RTT.
+    dropped_early_messages: AtomicUsize,
+}
+
+impl Shared {
+    fn slot(&self, peer: SocketAddr) -> ConnectionSlot {
+        self.connections
+            .lock()
+            .unwrap()
+            .entry(peer)
+            .or_default()
+            .clone()
+    }
+
+    // Returns the connection to `to`, establishing it unless it's open.
+    async fn connection(self: &Arc<Self>, to: SocketAddr) -> io::Result<Peer> {
+        let slot = self.slot(to);
+        let mut slot = slot.lock().await;
+        if let Some(peer) = slot
+            .as_ref()
+            .filter(|p| p.connection.close_reason().is_none())
+        {
+            return Ok(peer.clone());
+        }
+        let connecting = self
+            .endpoint
+            .connect_with(self.client_config.clone(), to, SERVER_NAME)
+            .map_err(io::Error::other)?;
+        let (connection, accepted) = match connecting.into_0rtt() {
+            Ok((connection, accepted)) => {
+                self.zero_rtt_connections.fetch_add(1, Ordering::Relaxed);
+                (connection, Some(accepted))
+            }
+            Err(connecting) => (connecting.await?, None),
+        };
+        trace!("Connected to {:?}", to);
+        let peer = Peer {
+            connection,
+            established: established(accepted),
+        };
+        tokio::spawn(self.clone().serve(peer.clone()));
+        *slot = Some(peer.clone());
+        Ok(peer)
+    }
+
+    // Returns the connection to `to` to send `payload` over, once it may be
+    // sent on it.
+    async fn connection_for(
+        self: &Arc<Self>,
+        to: SocketAddr,
+        payload: &[u8],
+    ) -> io::Result<Connection> {
+        let mut peer = self.connection(to).await?;
+        if !replay_safe(payload) {
+            let _ = peer.established.wait_for(|established| *established).await;
+        }
+        Ok(peer.connection)
+    }
+
+    async fn send(self: Arc<Self>, to: SocketAddr, payload: Bytes) -> io::Result<()> {
+        let connection = self.connection_for(to, &payload).await?;
+        match write(&connection, &payload).await {
+            // The peer did not take the resumed session, but the handshake
+            // went on to complete, and the message can now be sent again.
+            Err(WriteError::ZeroRttRejected) => Ok(write(&connection, &payload).await?),
+            // Most likely an idle connection the peer has since closed.
+            Err(WriteError::ConnectionLost(_)) => {
+                let connection = self.connection_for(to, &payload).await?;
+                Ok(write(&connection, &payload).await?)
+            }
+            res => Ok(res?),
+        }
+    }
+
+    // Receives the messages the peer sends on the connection, and makes it
+    // the one replies go over unless there is already one to the peer.
+    async fn serve(self: Arc<Self>, peer: Peer) {
+        let connection = peer.connection.clone();
+        let from = connection.remote_address();
+        {
+            let slot = self.slot(from);
+            let mut slot = slot.lock().await;
+            if slot
+                .as_ref()
+                .is_none_or(|p| p.connection.close_reason().is_some())
+            {
+                *slot = Some(peer);
+            }
+        }
+        while let Ok(mut stream) = connection.accept_uni().await {
+            let shared = self.clone();
+            tokio::spawn(async move {
+                match stream.read_to_end(MAX_MESSAGE_SIZE).await {
+                    Ok(bytes) if stream.is_0rtt() && !replay_safe(&bytes) => {
+                        shared
+                            .dropped_early_messages
+                            .fetch_add(1, Ordering::Relaxed);
+                        warn!("Dropping message from {:?} sent as 0-RTT data", from);
+                    }
+                    Ok(bytes) => {
+                        let _ = shared.received.send((from, Bytes::from(bytes)));
+                    }
+                    Err(e) => trace!("Dropping message from {:?}: {}", from, e),
+                }
+            });
+        }
+    }
+}
+
+async fn write(connection: &Connection, payload: &[u8]) -> Result<(), WriteError> {
+    let mut stream = connection
+        .open_uni()
+        .await
+        .map_err(WriteError::ConnectionLost)?;
+    stream.write_all(payload).await?;
+    stream.finish().map_err(|_| WriteError::ClosedStream)
+}
+
+/// A `FastNetwork` sending each message on a QUIC stream of its own, with
+/// one pooled connection per peer that is resumed with 0-RTT when it breaks.
+///
+/// Sends are handed to `runtime` and return right away, so like with UDP a
+/// message that can't be delivered is only logged, and callers still have to
+/// retry. Messages are received on `runtime` as well, but delivered by `poll`.
+pub struct QuicFastNetwork {
+    runtime: tokio::runtime::Handle,
+    shared: Arc<Shared>,
+    received: Mutex<mpsc::UnboundedReceiver<(SocketAddr, Bytes)>>,
+    listeners: RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<Bytes>>>,
+    default_handler: RwLock<DefaultHandler>,
+}
+
+impl QuicFastNetwork {
+    pub fn new(
+        socket: UdpSocket,
+        tls: &QuicTls,
+        runtime: tokio::runtime::Handle,
+    ) -> io::Result<QuicFastNetwork> {
+        let endpoint = {
+            let _guard = runtime.enter();
+            Endpoint::new(
+                EndpointConfig::default(),
+                Some(tls.server_config()?),
+                socket,
+                Arc::new(TokioRuntime),
+            )?
+        };
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            endpoint,
+            client_config: tls.client_config()?,
+            connections: Mutex::new(HashMap::new()),
+            received: sender,
+            zero_rtt_connections: AtomicUsize::new(0),
+            dropped_early_messages: AtomicUsize::new(0),
+        });
+        let accepting = shared.clone();
+        runtime.spawn(async move {
+            while let Some(incoming) = accepting.endpoint.accept().await {
+                let shared = accepting.clone();
+                tokio::spawn(async move {
+                    // Streams are accepted before the handshake completes, so
+                    // that the ones sent as 0-RTT data can be told apart.
+                    let connecting = match incoming.accept() {
+                        Ok(connecting) => connecting,
+                        Err(e) => {
+                            trace!("Failed to accept a connection: {}", e);
+                            return;
+                        }
+                    };
+                    match connecting.into_0rtt() {
+                        Ok((connection, accepted)) => {
+                            let peer = Peer {
+                                connection,
+                                established: established(Some(accepted)),
+                            };
+                            shared.serve(peer).await
+                        }
+                        Err(connecting) => match connecting.await {
+                            Ok(connection) => {
+                                let peer = Peer {
+                                    connection,
+                                    established: established(None),
+                                };
+                                shared.serve(peer).await
+                            }
+                            Err(e) => trace!("Failed to accept a connection: {}", e),
+                        },
+                    }
+                });
+            }
+        });
+        Ok(QuicFastNetwork {
+            runtime,
+            shared,
+            received: Mutex::new(receiver),
+            listeners: RwLock::new(HashMap::new()),
+            default_handler: RwLock::new(DefaultHandler::NotRegistered),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.shared.endpoint.local_addr()
+    }
+}
+
+impl Drop for QuicFastNetwork {
+    fn drop(&mut self) {
+        self.shared.endpoint.close(0u32.into(), b"");
+    }
+}
+
+impl Trait for QuicFastNetwork {
+    fn send(&self, to: SocketAddr, payload: Bytes) -> Result<(), std::io::Error> {
+        trace!("Sending to: {:?}", to);
+        if payload.len() > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("message of {} bytes exceeds the limit", payload.len()),
+            ));
+        }
+        let shared = self.shared.clone();
+        self.runtime.spawn(async move {
+            if let Err(e) = shared.send(to, payload).await {
+                warn!("Failed to send to {:?}: {}", to, e);
+            }
+        });
+        Ok(())
+    }
+
+    fn listen_default(&self) -> mpsc::UnboundedReceiver<(SocketAddr, Bytes)> {
+        let (s, r) = mpsc::unbounded_channel();
+        let mut default_handler = self.default_handler.write().unwrap();
+        *default_handler = DefaultHandler::Registered(s);
+        r
+    }
+
+    fn register(&self, from: SocketAddr) -> mpsc::UnboundedReceiver<Bytes> {
+        let (s, r) = mpsc::unbounded_channel();
+        let mut listeners = self.listeners.write().unwrap();
+        listeners.insert(from, s);
+        r
+    }
+
+    fn poll(&self) -> bool {
+        let Ok((from, bytes)) = self.received.lock().unwrap().try_recv() else {
+            return false;
+        };
+        let listeners = self.listeners.read().unwrap();
+        match listeners.get(&from) {
+            Some(s) => {
+                let _ = s.send(bytes);
+            }
+            None => match &*self.default_handler.read().unwrap() {
+                DefaultHandler::NotRegistered => (),
+                DefaultHandler::Registered(s) => {
+                    let _ = s.send((from, bytes));
+                }
+            },
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn network(tls: &QuicTls) -> (QuicFastNetwork, SocketAddr) {
+        let network = QuicFastNetwork::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            tls,
+            tokio::runtime::Handle::current(),
+        )
+        .unwrap();
+        let addr = network.local_addr().unwrap();
+        (network, addr)
+    }
+
+    // Polls both networks until `receiver` has a message.
+    async fn receive<T>(
+        networks: [&QuicFastNetwork; 2],
+        receiver: &mut mpsc::UnboundedReceiver<T>,
+    ) -> T {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                for network in networks {
+                    network.poll();
+                }
+                if let Ok(message) = receiver.try_recv() {
+                    return message;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("no message received")
+    }
+
+    #[tokio::test]
+    async fn messages_are_exchanged() {
+        let tls = QuicTls::self_signed();
+        let (a, a_addr) = network(&tls);
+        let (b, b_addr) = network(&tls);
+        let mut b_default = b.listen_default();
+        let mut a_from_b = a.register(b_addr);
+
+        a.send(b_addr, Bytes::from_static(b"ping")).unwrap();
+        let (from, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(from, a_addr);
+        assert_eq!(bytes, Bytes::from_static(b"ping"));
+
+        // The reply goes back over the connection a opened.
+        b.send(from, Bytes::from_static(b"pong")).unwrap();
+        let bytes = receive([&a, &b], &mut a_from_b).await;
+        assert_eq!(bytes, Bytes::from_static(b"pong"));
+        assert_eq!(b.shared.endpoint.open_connections(), 1);
+
+        let payload = Bytes::from((0..1 << 20).map(|i| i as u8).collect::<Vec<u8>>());
+        a.send(b_addr, payload.clone()).unwrap();
+        let (_, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(bytes, payload);
+    }
+
+    #[tokio::test]
+    async fn untrusted_peers_are_refused() {
+        let (a, _) = network(&QuicTls::self_signed());
+        let (b, b_addr) = network(&QuicTls::self_signed());
+        assert!(a
+            .shared
+            .clone()
+            .send(b_addr, Bytes::from_static(b"ping"))
+            .await
+            .is_err());
+        drop(b);
+    }
+
+    #[tokio::test]
+    async fn closed_connection_is_resumed_with_0rtt() {
+        let tls = QuicTls::self_signed();
+        let (a, _) = network(&tls);
+        let (b, b_addr) = network(&tls);
+        let mut b_default = b.listen_default();
+        a.send(b_addr, Bytes::from_static(b"first")).unwrap();
+        receive([&a, &b], &mut b_default).await;
+
+        // Like an idle timeout on b's side.
+        let connection = b
+            .shared
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        connection
+            .lock()
+            .await
+            .take()
+            .unwrap()
+            .connection
+            .close(0u32.into(), b"");
+        while a.shared.endpoint.open_connections() > 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        a.send(b_addr, Bytes::from_static(b"second")).unwrap();
+        let (_, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(bytes, Bytes::from_static(b"second"));
+        assert_eq!(a.shared.zero_rtt_connections.load(Ordering::Relaxed), 1);
+    }
+
+    fn envelope(message_type: MessageType, bytes: &[u8]) -> Bytes {
+        let mut fbb = flatbuffers::FlatBufferBuilder::new();
+        let bytes = Some(fbb.create_vector(bytes));
+        let envelope = RequestEnvelope::create(
+            &mut fbb,
+            &flatbuf::rangeserver_flatbuffers::range_server::RequestEnvelopeArgs {
+                type_: message_type,
+                bytes,
+            },
+        );
+        fbb.finish(envelope, None);
+        Bytes::copy_from_slice(fbb.finished_data())
+    }
+
+    #[tokio::test]
+    async fn prepares_are_not_accepted_as_0rtt_data() {
+        let tls = QuicTls::self_signed();
+        let (a, _) = network(&tls);
+        let (b, b_addr) = network(&tls);
+        let mut b_default = b.listen_default();
+        a.send(b_addr, envelope(MessageType::Validate, b"first"))
+            .unwrap();
+        receive([&a, &b], &mut b_default).await;
+        let connection = b
+            .shared
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        connection
+            .lock()
+            .await
+            .take()
+            .unwrap()
+            .connection
+            .close(0u32.into(), b"");
+        while a.shared.endpoint.open_connections() > 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // Like a prepare replayed from the 0-RTT data of an earlier
+        // connection: written before the handshake, bypassing `send`.
+        let peer = a.shared.connection(b_addr).await.unwrap();
+        assert_eq!(a.shared.zero_rtt_connections.load(Ordering::Relaxed), 1);
+        write(
+            &peer.connection,
+            &envelope(MessageType::Prepare, b"replayed"),
+        )
+        .await
+        .unwrap();
+        a.send(b_addr, envelope(MessageType::Prepare, b"second"))
+            .unwrap();
+        let (_, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(bytes, envelope(MessageType::Prepare, b"second"));
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while b.shared.dropped_early_messages.load(Ordering::Relaxed) == 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("replayed prepare not dropped");
+        assert!(b_default.try_recv().is_err());
+    }
+
+    #[test]
+    fn only_idempotent_messages_are_replay_safe() {
+        assert!(replay_safe(&envelope(MessageType::Validate, b"")));
+        assert!(replay_safe(&envelope(MessageType::GetConflictStats, b"")));
+        for message_type in [
+            MessageType::Get,
+            MessageType::Prepare,
+            MessageType::Commit,
+            MessageType::Abort,
+            MessageType::Scan,
+        ] {
+            assert!(!replay_safe(&envelope(message_type, b"")));
+        }
+        assert!(!replay_safe(b"ping"));
+    }
+
+    #[tokio::test]
+    async fn clients_without_the_certificate_are_refused() {
+        let tls = QuicTls::self_signed();
+        let (b, b_addr) = network(&tls);
+        let mut b_default = b.listen_default();
+        // Trusts b, but presents no certificate of its own.
+        let crypto = rustls::ClientConfig::builder_with_provider(crypto_provider())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(tls.roots().unwrap())
+            .with_no_client_auth();
+        let client_config =
+            ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto).unwrap()));
+        let endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        let result = async {
+            let connection = endpoint
+                .connect_with(client_config, b_addr, SERVER_NAME)
+                .map_err(io::Error::other)?
+                .await?;
+            write(
+                &connection,
+                &envelope(MessageType::Prepare, b"unauthenticated"),
+            )
+            .await
+            .map_err(io::Error::other)?;
+            connection.closed().await;
+            io::Result::Ok(())
+        };
+        let _ = tokio::time::timeout(Duration::from_secs(1), result).await;
+        b.poll();
+        assert!(b_default.try_recv().is_err());
+    }
+}
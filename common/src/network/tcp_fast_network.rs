@@ -0,0 +1,405 @@
+//! A `FastNetwork` over TCP, for messages that don't fit in a datagram or
+//! must not be silently lost.
+//!
+//! Each message is sent as a frame: its length as a big-endian u32, followed
+//! by its bytes. A single connection is kept to each peer and used in both
+//! directions. Its first frame carries the port the connecting side listens
+//! on, so that messages are attributed to the address the peer is reachable
+//! at, as with UDP, rather than to the ephemeral port it connected from.
+
+use crate::network::fast_network::FastNetwork as Trait;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Mutex, RwLock},
+    time::Duration,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::sync::mpsc;
+use tracing::{trace, warn};
+
+/// Frames longer than this are taken as a corrupt stream, and the connection
+/// is dropped.
+pub const MAX_FRAME_SIZE: usize = 64 << 20;
+
+// Connecting blocks the sender, so give up quickly on unreachable peers.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+const HEADER_SIZE: usize = 4;
+
+enum DefaultHandler {
+    NotRegistered,
+    Registered(mpsc::UnboundedSender<(SocketAddr, Bytes)>),
+}
+
+struct Connection {
+    stream: TcpStream,
+    // None for an accepted connection until its handshake is read.
+    peer: Option<SocketAddr>,
+    read_buf: BytesMut,
+    // Frames not yet accepted by the socket.
+    write_buf: BytesMut,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, peer: Option<SocketAddr>) -> io::Result<Connection> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            peer,
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+        })
+    }
+
+    fn push_frame(&mut self, payload: &[u8]) {
+        self.write_buf.reserve(HEADER_SIZE + payload.len());
+        self.write_buf.put_u32(payload.len() as u32);
+        self.write_buf.put_slice(payload);
+    }
+
+    // Writes as much of the buffered frames as the socket takes.
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => self.write_buf.advance(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // Reads whatever is available. Returns whether anything was read, or an
+    // error once the peer closed the connection.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 64 * 1024];
+        let mut read = false;
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    self.read_buf.extend_from_slice(&buf[..n]);
+                    read = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(read),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<Bytes>> {
+        if self.read_buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes(self.read_buf[..HEADER_SIZE].try_into().unwrap()) as usize;
+        if len > MAX_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame of {} bytes exceeds the limit", len),
+            ));
+        }
+        if self.read_buf.len() < HEADER_SIZE + len {
+            return Ok(None);
+        }
+        self.read_buf.advance(HEADER_SIZE);
+        Ok(Some(self.read_buf.split_to(len).freeze()))
+    }
+}
+
+#[derive(Default)]
+struct Connections {
+    next_id: u64,
+    by_id: HashMap<u64, Connection>,
+    // The connection messages to each peer are sent on.
+    by_peer: HashMap<SocketAddr, u64>,
+}
+
+impl Connections {
+    fn insert(&mut self, connection: Connection) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(peer) = connection.peer {
+            self.by_peer.insert(peer, id);
+        }
+        self.by_id.insert(id, connection);
+        id
+    }
+
+    fn remove(&mut self, id: u64) {
+        if let Some(peer) = self.by_id.remove(&id).and_then(|c| c.peer) {
+            if self.by_peer.get(&peer) == Some(&id) {
+                self.by_peer.remove(&peer);
+            }
+        }
+    }
+}
+
+/// A `FastNetwork` sending length-prefixed messages over TCP, with one pooled
+/// connection per peer that is reestablished when it breaks.
+///
+/// Like with UDP, messages buffered on a connection that breaks are lost, so
+/// callers still have to retry, but a message is never truncated or dropped
+/// for its size.
+pub struct TcpFastNetwork {
+    listener: TcpListener,
+    local_port: u16,
+    connections: Mutex<Connections>,
+    listeners: RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<Bytes>>>,
+    default_handler: RwLock<DefaultHandler>,
+}
+
+impl TcpFastNetwork {
+    pub fn new(listener: TcpListener) -> TcpFastNetwork {
+        listener.set_nonblocking(true).unwrap();
+        let local_port = listener.local_addr().unwrap().port();
+        TcpFastNetwork {
+            listener,
+            local_port,
+            connections: Mutex::new(Connections::default()),
+            listeners: RwLock::new(HashMap::new()),
+            default_handler: RwLock::new(DefaultHandler::NotRegistered),
+        }
+    }
+
+    fn connect(&self, to: SocketAddr) -> io::Result<Connection> {
+        let stream = TcpStream::connect_timeout(&to, CONNECT_TIMEOUT)?;
+        let mut connection = Connection::new(stream, Some(to))?;
+        connection.push_frame(&self.local_port.to_be_bytes());
+        Ok(connection)
+    }
+
+    // Queues `payload` on the pooled connection to `to`, if there is one.
+    // Returns None if there is none, and the error if writing to it failed,
+    // in which case the connection was dropped.
+    fn send_pooled(&self, to: SocketAddr, payload: &[u8]) -> Option<io::Result<()>> {
+        let mut connections = self.connections.lock().unwrap();
+        let id = *connections.by_peer.get(&to)?;
+        let connection = connections.by_id.get_mut(&id).unwrap();
+        connection.push_frame(payload);
+        let res = connection.flush();
+        if res.is_err() {
+            connections.remove(id);
+        }
+        Some(res)
+    }
+
+    fn deliver(&self, from: SocketAddr, bytes: Bytes) {
+        let listeners = self.listeners.read().unwrap();
+        match listeners.get(&from) {
+            Some(s) => {
+                let _ = s.send(bytes);
+            }
+            None => match &*self.default_handler.read().unwrap() {
+                DefaultHandler::NotRegistered => (),
+                DefaultHandler::Registered(s) => {
+                    let _ = s.send((from, bytes));
+                }
+            },
+        }
+    }
+
+    fn accept(&self) -> bool {
+        let mut accepted = false;
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    accepted = true;
+                    match Connection::new(stream, None) {
+                        Ok(connection) => {
+                            self.connections.lock().unwrap().insert(connection);
+                        }
+                        Err(e) => warn!("Failed to set up an accepted connection: {}", e),
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return accepted,
+                Err(e) => {
+                    warn!("TcpFastNetwork failed to accept a connection: {}", e);
+                    return accepted;
+                }
+            }
+        }
+    }
+}
+
+impl Trait for TcpFastNetwork {
+    fn send(&self, to: SocketAddr, payload: Bytes) -> Result<(), std::io::Error> {
+        trace!("Sending to: {:?}", to);
+        if payload.len() > MAX_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("message of {} bytes exceeds the limit", payload.len()),
+            ));
+        }
+        match self.send_pooled(to, &payload) {
+            Some(Ok(())) => return Ok(()),
+            Some(Err(e)) => trace!("Reconnecting to {:?} after: {}", to, e),
+            None => (),
+        }
+        // Connect without holding the lock, so that polling goes on meanwhile.
+        let mut connection = self.connect(to)?;
+        connection.push_frame(&payload);
+        connection.flush()?;
+        self.connections.lock().unwrap().insert(connection);
+        trace!("Send finished");
+        Ok(())
+    }
+
+    fn listen_default(&self) -> mpsc::UnboundedReceiver<(SocketAddr, Bytes)> {
+        let (s, r) = mpsc::unbounded_channel();
+        let mut default_handler = self.default_handler.write().unwrap();
+        *default_handler = DefaultHandler::Registered(s);
+        r
+    }
+
+    fn register(&self, from: SocketAddr) -> mpsc::UnboundedReceiver<Bytes> {
+        let (s, r) = mpsc::unbounded_channel();
+        let mut listeners = self.listeners.write().unwrap();
+        listeners.insert(from, s);
+        r
+    }
+
+    fn poll(&self) -> bool {
+        let mut progressed = self.accept();
+        let mut received = Vec::new();
+        {
+            let mut connections = self.connections.lock().unwrap();
+            let mut broken = Vec::new();
+            let Connections { by_id, by_peer, .. } = &mut *connections;
+            for (&id, connection) in by_id.iter_mut() {
+                let res = connection.flush().and_then(|()| {
+                    progressed |= connection.fill()?;
+                    while let Some(frame) = connection.next_frame()? {
+                        match connection.peer {
+                            Some(peer) => received.push((peer, frame)),
+                            None => {
+                                let port = frame.as_ref().try_into().map_err(|_| {
+                                    io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        "malformed handshake",
+                                    )
+                                })?;
+                                let ip = connection.stream.peer_addr()?.ip();
+                                let peer = SocketAddr::new(ip, u16::from_be_bytes(port));
+                                connection.peer = Some(peer);
+                                // Replies to the peer go over its connection,
+                                // unless there is already one to it.
+                                by_peer.entry(peer).or_insert(id);
+                            }
+                        }
+                    }
+                    Ok(())
+                });
+                if let Err(e) = res {
+                    if e.kind() != io::ErrorKind::UnexpectedEof {
+                        warn!("Dropping connection to {:?}: {}", connection.peer, e);
+                    }
+                    broken.push(id);
+                }
+            }
+            for id in broken {
+                connections.remove(id);
+            }
+        }
+        for (from, bytes) in received {
+            self.deliver(from, bytes);
+        }
+        progressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network() -> (TcpFastNetwork, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        (TcpFastNetwork::new(listener), addr)
+    }
+
+    // Polls both networks until `receiver` has a message.
+    async fn receive<T>(
+        networks: [&TcpFastNetwork; 2],
+        receiver: &mut mpsc::UnboundedReceiver<T>,
+    ) -> T {
+        for _ in 0..10_000 {
+            for network in networks {
+                network.poll();
+            }
+            if let Ok(message) = receiver.try_recv() {
+                return message;
+            }
+            tokio::task::yield_now().await;
+        }
+        panic!("no message received");
+    }
+
+    #[tokio::test]
+    async fn messages_are_exchanged() {
+        let (a, a_addr) = network();
+        let (b, b_addr) = network();
+        let mut b_default = b.listen_default();
+        let mut a_from_b = a.register(b_addr);
+
+        a.send(b_addr, Bytes::from_static(b"ping")).unwrap();
+        let (from, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(from, a_addr);
+        assert_eq!(bytes, Bytes::from_static(b"ping"));
+
+        // The reply goes back over the connection a opened.
+        b.send(from, Bytes::from_static(b"pong")).unwrap();
+        let bytes = receive([&a, &b], &mut a_from_b).await;
+        assert_eq!(bytes, Bytes::from_static(b"pong"));
+        assert_eq!(b.connections.lock().unwrap().by_id.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn large_messages_are_not_truncated() {
+        let (a, _) = network();
+        let (b, b_addr) = network();
+        let mut b_default = b.listen_default();
+        let payload = Bytes::from((0..1 << 20).map(|i| i as u8).collect::<Vec<u8>>());
+        a.send(b_addr, payload.clone()).unwrap();
+        a.send(b_addr, Bytes::from_static(b"after")).unwrap();
+        let (_, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(bytes, payload);
+        let (_, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(bytes, Bytes::from_static(b"after"));
+    }
+
+    #[tokio::test]
+    async fn reconnects_to_restarted_peer() {
+        let (a, a_addr) = network();
+        let (b, b_addr) = network();
+        let mut b_default = b.listen_default();
+        a.send(b_addr, Bytes::from_static(b"first")).unwrap();
+        receive([&a, &b], &mut b_default).await;
+
+        // The peer comes back on the same address, having lost its
+        // connections.
+        drop(b_default);
+        drop(b);
+        let b = TcpFastNetwork::new(TcpListener::bind(b_addr).unwrap());
+        let mut b_default = b.listen_default();
+        // The first send may still be taken by the old connection before its
+        // closing is noticed, like a datagram lost in flight.
+        for _ in 0..100 {
+            a.poll();
+            if a.connections.lock().unwrap().by_id.is_empty() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        a.send(b_addr, Bytes::from_static(b"second")).unwrap();
+        let (from, bytes) = receive([&a, &b], &mut b_default).await;
+        assert_eq!(from, a_addr);
+        assert_eq!(bytes, Bytes::from_static(b"second"));
+    }
+}
@@ -0,0 +1,169 @@
+//! The `Profiler` admin service (see profiling.proto), served next to the
+//! main proto service of range servers and frontends, so that a process can
+//! be profiled where it runs without restarting it.
+
+use std::time::Duration;
+
+use pprof::protos::Message;
+use proto::profiling::{
+    profiler_server::{Profiler, ProfilerServer},
+    GetMemoryStatsRequest, GetMemoryStatsResponse, ProfileCpuRequest, ProfileCpuResponse,
+};
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+
+use crate::config::AdminConfig;
+
+const MAX_CPU_PROFILE_DURATION: Duration = Duration::from_secs(60);
+const DEFAULT_FREQUENCY_HZ: i32 = 99;
+const MAX_FREQUENCY_HZ: i32 = 1000;
+
+pub struct ProfilingService {
+    token: Option<String>,
+    // The profiler samples the whole process, so only one profile can be
+    // taken at a time.
+    cpu_profile: Mutex<()>,
+}
+
+impl ProfilingService {
+    pub fn new(config: &AdminConfig) -> ProfilingService {
+        ProfilingService {
+            token: config.token.clone(),
+            cpu_profile: Mutex::new(()),
+        }
+    }
+
+    pub fn into_server(self) -> ProfilerServer<ProfilingService> {
+        ProfilerServer::new(self)
+    }
+
+    // Returns why the request is refused, if it is.
+    fn refusal<T>(&self, request: &Request<T>) -> Option<Status> {
+        let Some(token) = &self.token else {
+            return Some(Status::permission_denied(
+                "profiling is disabled, set admin.token to enable it",
+            ));
+        };
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        (presented != Some(token.as_str()))
+            .then(|| Status::unauthenticated("missing or wrong admin token"))
+    }
+}
+
+#[tonic::async_trait]
+impl Profiler for ProfilingService {
+    async fn profile_cpu(
+        &self,
+        request: Request<ProfileCpuRequest>,
+    ) -> Result<Response<ProfileCpuResponse>, Status> {
+        if let Some(refusal) = self.refusal(&request) {
+            return Err(refusal);
+        }
+        let request = request.into_inner();
+        let duration =
+            Duration::from_millis(request.duration_ms as u64).min(MAX_CPU_PROFILE_DURATION);
+        let frequency = match request.frequency_hz {
+            0 => DEFAULT_FREQUENCY_HZ,
+            hz => (hz as i32).clamp(1, MAX_FREQUENCY_HZ),
+        };
+        let Ok(_running) = self.cpu_profile.try_lock() else {
+            return Err(Status::failed_precondition(
+                "a CPU profile is already being taken",
+            ));
+        };
+        // The profiler guard can't be held across an await, so sample from a
+        // blocking thread.
+        let profile = tokio::task::spawn_blocking(move || {
+            let guard = pprof::ProfilerGuardBuilder::default()
+                .frequency(frequency)
+                .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+                .build()
+                .map_err(|e| e.to_string())?;
+            std::thread::sleep(duration);
+            let report = guard.report().build().map_err(|e| e.to_string())?;
+            let profile = report.pprof().map_err(|e| e.to_string())?;
+            Ok::<Vec<u8>, String>(profile.encode_to_vec())
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(Status::internal)?;
+        Ok(Response::new(ProfileCpuResponse { profile }))
+    }
+
+    async fn get_memory_stats(
+        &self,
+        request: Request<GetMemoryStatsRequest>,
+    ) -> Result<Response<GetMemoryStatsResponse>, Status> {
+        if let Some(refusal) = self.refusal(&request) {
+            return Err(refusal);
+        }
+        let status = tokio::fs::read_to_string("/proc/self/status")
+            .await
+            .map_err(|e| Status::unimplemented(format!("memory stats unavailable: {}", e)))?;
+        Ok(Response::new(memory_stats(&status)))
+    }
+}
+
+// Reads the sizes, given in kB, out of /proc/self/status.
+fn memory_stats(status: &str) -> GetMemoryStatsResponse {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().strip_suffix(" kB"))
+            .and_then(|kb| kb.trim().parse::<u64>().ok())
+            .map_or(0, |kb| kb * 1024)
+    };
+    GetMemoryStatsResponse {
+        resident_bytes: field("VmRSS"),
+        peak_resident_bytes: field("VmHWM"),
+        virtual_bytes: field("VmSize"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proc_status() {
+        let status = "Name:\trangeserver\nVmPeak:\t  300 kB\nVmSize:\t  200 kB\nVmHWM:\t   150 kB\nVmRSS:\t   100 kB\n";
+        assert_eq!(
+            memory_stats(status),
+            GetMemoryStatsResponse {
+                resident_bytes: 100 * 1024,
+                peak_resident_bytes: 150 * 1024,
+                virtual_bytes: 200 * 1024,
+            }
+        );
+    }
+
+    #[test]
+    fn refuses_without_token() {
+        let disabled = ProfilingService::new(&AdminConfig::default());
+        let mut request = Request::new(GetMemoryStatsRequest {});
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        assert_eq!(
+            disabled.refusal(&request).unwrap().code(),
+            tonic::Code::PermissionDenied
+        );
+
+        let enabled = ProfilingService::new(&AdminConfig {
+            token: Some("secret".to_string()),
+        });
+        assert!(enabled.refusal(&request).is_none());
+        assert_eq!(
+            enabled
+                .refusal(&Request::new(GetMemoryStatsRequest {}))
+                .unwrap()
+                .code(),
+            tonic::Code::Unauthenticated
+        );
+    }
+}
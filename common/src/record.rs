@@ -1,7 +1,58 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Hash)]
 pub struct Record {
     pub key: Bytes,
     pub val: Bytes,
+    /// How long the record lives once committed. None for records that never
+    /// expire.
+    pub ttl: Option<Duration>,
+}
+
+/// Expects a key to currently hold `expected`, or to be absent if None.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Condition {
+    pub key: Bytes,
+    pub expected: Option<Bytes>,
+}
+
+/// Adds `delta` to the counter at `key`. Counters are stored as 8-byte
+/// big-endian signed integers, and an absent key counts as 0.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Increment {
+    pub key: Bytes,
+    pub delta: i64,
+}
+
+impl Increment {
+    /// The value of the counter once the increment is applied to `current`,
+    /// or None if `current` is not a counter or the addition overflows.
+    pub fn apply(&self, current: Option<&[u8]>) -> Option<Bytes> {
+        let current = match current {
+            None => 0,
+            Some(current) => i64::from_be_bytes(current.try_into().ok()?),
+        };
+        let value = current.checked_add(self.delta)?;
+        Some(Bytes::copy_from_slice(&value.to_be_bytes()))
+    }
+}
+
+impl Record {
+    pub fn new(key: impl Into<Bytes>, val: impl Into<Bytes>) -> Record {
+        Record {
+            key: key.into(),
+            val: val.into(),
+            ttl: None,
+        }
+    }
+
+    pub fn with_ttl(key: impl Into<Bytes>, val: impl Into<Bytes>, ttl: Duration) -> Record {
+        Record {
+            key: key.into(),
+            val: val.into(),
+            ttl: Some(ttl),
+        }
+    }
 }
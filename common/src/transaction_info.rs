@@ -1,11 +1,49 @@
 use chrono::DateTime;
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 type UtcDateTime = DateTime<chrono::Utc>;
 
+/// How a transaction's reads are isolated from the transactions committing
+/// concurrently with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Transactions appear to run one after the other. Reads either lock
+    /// their range or, in keyspaces with optimistic reads, are checked at
+    /// prepare against the keys written since, and the transaction aborts if
+    /// any of them changed.
+    #[default]
+    Serializable,
+    /// Reads see what was committed as of `TransactionInfo::snapshot_epoch`,
+    /// and neither lock nor are checked at prepare. Instead, a transaction
+    /// aborts at prepare if a key it writes was written by another one since
+    /// its snapshot, so only concurrent writes conflict with each other. This
+    /// allows write skew: two transactions can each read what the other one
+    /// writes and both commit, e.g. two withdrawals each checking that the
+    /// sum of two balances covers them. Range servers reject snapshot
+    /// transactions without a snapshot epoch, since nothing would check them
+    /// against concurrent commits.
+    Snapshot,
+}
+
 #[derive(Clone, Debug)]
 pub struct TransactionInfo {
     pub id: Uuid,
+    /// When this attempt at the transaction started. It and the requests made
+    /// on its behalf time out `overall_timeout` after it.
     pub started: UtcDateTime,
+    /// When the first attempt at the transaction started, kept by its
+    /// retries. Range servers let older transactions wait for locks and abort
+    /// younger ones, so a transaction retried after losing a lock eventually
+    /// wins it. The same as `started` for a first attempt.
+    pub first_started: UtcDateTime,
     pub overall_timeout: std::time::Duration,
+    /// Free-form labels attached by the application (e.g. service name,
+    /// endpoint, tenant) used to attribute load and aborts to call sites.
+    pub labels: BTreeMap<String, String>,
+    pub isolation: IsolationLevel,
+    /// The epoch the reads of a transaction with snapshot isolation see the
+    /// database as of. Set by the coordinator when starting the transaction,
+    /// which runs it as serializable instead if there is no epoch to take.
+    pub snapshot_epoch: Option<u64>,
 }
@@ -1,8 +1,17 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
 use flatbuf::rangeserver_flatbuffers::range_server::*;
 use flatbuffers::FlatBufferBuilder;
 use uuid::Uuid;
 
-use crate::{full_range_id::FullRangeId, keyspace_id::KeyspaceId};
+use crate::{
+    full_range_id::FullRangeId,
+    keyspace_id::KeyspaceId,
+    transaction_info::{
+        IsolationLevel as CommonIsolationLevel, TransactionInfo as CommonTransactionInfo,
+    },
+};
 
 pub fn deserialize_uuid(uuidf: Uuidu128<'_>) -> Uuid {
     let res: u128 = ((uuidf.upper() as u128) << 64) | (uuidf.lower() as u128);
@@ -41,3 +50,108 @@ pub fn deserialize_range_id(id: &RangeId<'_>) -> Option<FullRangeId> {
         range_id,
     })
 }
+
+pub fn serialize_transaction_info<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    tx: &CommonTransactionInfo,
+) -> flatbuffers::WIPOffset<TransactionInfo<'a>> {
+    let labels = if tx.labels.is_empty() {
+        None
+    } else {
+        let labels_vector: Vec<_> = tx
+            .labels
+            .iter()
+            .map(|(k, v)| {
+                let key = Some(fbb.create_string(k));
+                let value = Some(fbb.create_string(v));
+                Label::create(fbb, &LabelArgs { key, value })
+            })
+            .collect();
+        Some(fbb.create_vector(&labels_vector))
+    };
+    TransactionInfo::create(
+        fbb,
+        &TransactionInfoArgs {
+            overall_timeout_us: tx.overall_timeout.as_micros() as u32,
+            labels,
+            isolation: match tx.isolation {
+                CommonIsolationLevel::Serializable => IsolationLevel::Serializable,
+                CommonIsolationLevel::Snapshot => IsolationLevel::Snapshot,
+            },
+            snapshot_epoch: tx.snapshot_epoch.unwrap_or(0),
+            started_us: tx.first_started.timestamp_micros(),
+        },
+    )
+}
+
+/// Isolation levels this version does not know are taken as serializable,
+/// which is never weaker than what was asked for.
+pub fn deserialize_isolation(info: &TransactionInfo<'_>) -> CommonIsolationLevel {
+    match info.isolation() {
+        IsolationLevel::Snapshot => CommonIsolationLevel::Snapshot,
+        _ => CommonIsolationLevel::Serializable,
+    }
+}
+
+pub fn deserialize_snapshot_epoch(info: &TransactionInfo<'_>) -> Option<u64> {
+    Some(info.snapshot_epoch()).filter(|epoch| *epoch > 0)
+}
+
+pub fn deserialize_started(info: &TransactionInfo<'_>) -> Option<DateTime<Utc>> {
+    Some(info.started_us())
+        .filter(|started| *started > 0)
+        .and_then(DateTime::from_timestamp_micros)
+}
+
+pub fn deserialize_labels(info: &TransactionInfo<'_>) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    for label in info.labels().iter().flatten() {
+        if let (Some(k), Some(v)) = (label.key(), label.value()) {
+            labels.insert(k.to_string(), v.to_string());
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn round_trip(tx: &CommonTransactionInfo) -> BTreeMap<String, String> {
+        let mut fbb = FlatBufferBuilder::new();
+        let info = serialize_transaction_info(&mut fbb, tx);
+        fbb.finish(info, None);
+        let info = flatbuffers::root::<TransactionInfo>(fbb.finished_data()).unwrap();
+        deserialize_labels(&info)
+    }
+
+    fn transaction_info(labels: BTreeMap<String, String>) -> CommonTransactionInfo {
+        let started = Utc::now();
+        CommonTransactionInfo {
+            id: Uuid::new_v4(),
+            started,
+            first_started: started,
+            overall_timeout: Duration::from_secs(10),
+            labels,
+            isolation: Default::default(),
+            snapshot_epoch: None,
+        }
+    }
+
+    #[test]
+    fn labels_round_trip() {
+        let labels = BTreeMap::from([
+            ("service".to_string(), "cart".to_string()),
+            ("endpoint".to_string(), "checkout".to_string()),
+            ("tenant".to_string(), String::new()),
+        ]);
+        assert_eq!(round_trip(&transaction_info(labels.clone())), labels);
+    }
+
+    #[test]
+    fn no_labels_round_trip() {
+        assert!(round_trip(&transaction_info(BTreeMap::new())).is_empty());
+    }
+}
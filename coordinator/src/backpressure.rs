@@ -0,0 +1,242 @@
+//! Lets applications see the coordinator running into overload, so they can
+//! delay or shed work at their edge before transactions start timing out.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{clock::Clock, full_range_id::FullRangeId};
+use futures::future::select_all;
+use tokio::{sync::watch, time::Instant};
+
+use crate::pool::PoolSaturation;
+
+// How long a range counts as overloaded after its server turned a request
+// away without saying when to come back.
+const DEFAULT_OVERLOAD_HOLD: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BackpressureLevel {
+    Normal,
+    /// Some range servers are turning requests away, or a pool has all of its
+    /// transactions in flight. Transactions on the overloaded ranges are
+    /// likely to abort.
+    Elevated,
+    /// Callers are queueing to start transactions on a pool.
+    Saturated,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backpressure {
+    pub level: BackpressureLevel,
+    /// Ranges whose servers recently turned requests away because they were
+    /// overloaded, had too many prepares pending or had their writes stall.
+    pub overloaded_ranges: Vec<FullRangeId>,
+    /// Tasks spawned on behalf of transactions that are still running.
+    pub in_flight_tasks: usize,
+    /// How full the pool is, when read from a `CoordinatorPool`.
+    pub pool: Option<PoolSaturation>,
+}
+
+impl Backpressure {
+    pub(crate) fn new(
+        mut overloaded_ranges: Vec<FullRangeId>,
+        in_flight_tasks: usize,
+        pool: Option<PoolSaturation>,
+    ) -> Backpressure {
+        overloaded_ranges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        overloaded_ranges.dedup();
+        let level = match &pool {
+            Some(pool) if pool.waiting > 0 => BackpressureLevel::Saturated,
+            Some(pool) if pool.in_flight >= pool.max_in_flight => BackpressureLevel::Elevated,
+            _ if !overloaded_ranges.is_empty() => BackpressureLevel::Elevated,
+            _ => BackpressureLevel::Normal,
+        };
+        Backpressure {
+            level,
+            overloaded_ranges,
+            in_flight_tasks,
+            pool,
+        }
+    }
+}
+
+/// Remembers which ranges signalled overload, until they said to retry.
+pub(crate) struct OverloadTracker {
+    clock: Arc<dyn Clock>,
+    until: Mutex<HashMap<FullRangeId, Instant>>,
+    changes: watch::Sender<()>,
+}
+
+impl OverloadTracker {
+    pub fn new(clock: Arc<dyn Clock>) -> OverloadTracker {
+        OverloadTracker {
+            clock,
+            until: Mutex::new(HashMap::new()),
+            changes: watch::Sender::new(()),
+        }
+    }
+
+    /// Marks the range as overloaded for `retry_after`, or a default hold if
+    /// the range server did not give one.
+    pub fn record(&self, range_id: &FullRangeId, retry_after: Option<Duration>) {
+        let now = self.clock.instant();
+        let until = now + retry_after.unwrap_or(DEFAULT_OVERLOAD_HOLD);
+        let mut ranges = self.until.lock().unwrap();
+        let newly_overloaded = match ranges.insert(*range_id, until) {
+            None => true,
+            Some(previous) if previous > until => {
+                ranges.insert(*range_id, previous);
+                false
+            }
+            Some(previous) => previous <= now,
+        };
+        drop(ranges);
+        if newly_overloaded {
+            self.changes.send_replace(());
+        }
+    }
+
+    pub fn overloaded_ranges(&self) -> Vec<FullRangeId> {
+        let now = self.clock.instant();
+        let mut ranges = self.until.lock().unwrap();
+        ranges.retain(|_, until| *until > now);
+        ranges.keys().copied().collect()
+    }
+
+    fn next_expiry(&self) -> Option<Instant> {
+        self.until.lock().unwrap().values().min().copied()
+    }
+}
+
+/// Notified when the backpressure level or the set of overloaded ranges
+/// changes. Counts such as the tasks in flight change all the time and do not
+/// trigger notifications on their own.
+pub struct BackpressureSubscription {
+    read: Box<dyn Fn() -> Backpressure + Send + Sync>,
+    trackers: Vec<Arc<OverloadTracker>>,
+    // Also notified of changes to a pool's saturation.
+    changes: Vec<watch::Receiver<()>>,
+    clock: Arc<dyn Clock>,
+    last: (BackpressureLevel, Vec<FullRangeId>),
+}
+
+impl BackpressureSubscription {
+    pub(crate) fn new(
+        read: Box<dyn Fn() -> Backpressure + Send + Sync>,
+        trackers: Vec<Arc<OverloadTracker>>,
+        mut changes: Vec<watch::Receiver<()>>,
+        clock: Arc<dyn Clock>,
+    ) -> BackpressureSubscription {
+        changes.extend(trackers.iter().map(|t| t.changes.subscribe()));
+        let current = read();
+        BackpressureSubscription {
+            read,
+            trackers,
+            changes,
+            clock,
+            last: (current.level, current.overloaded_ranges),
+        }
+    }
+
+    /// Waits until the level or the overloaded ranges differ from what the
+    /// previous call returned (or from when subscribing, on the first call).
+    pub async fn changed(&mut self) -> Backpressure {
+        loop {
+            // Marks everything seen before reading, so that a change made
+            // while reading still wakes us up below.
+            for changes in &mut self.changes {
+                changes.borrow_and_update();
+            }
+            let current = (self.read)();
+            if current.level != self.last.0 || current.overloaded_ranges != self.last.1 {
+                self.last = (current.level, current.overloaded_ranges.clone());
+                return current;
+            }
+            let next_expiry = self.trackers.iter().filter_map(|t| t.next_expiry()).min();
+            // The senders live as long as the trackers and pools we hold on
+            // to, so waiting on them never fails.
+            let changed = select_all(self.changes.iter_mut().map(|c| Box::pin(c.changed())));
+            let clock = &self.clock;
+            let expiry = async move {
+                match next_expiry {
+                    Some(expiry) => clock.sleep_until(expiry).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = changed => (),
+                () = expiry => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use common::{clock::ManualClock, keyspace_id::KeyspaceId};
+    use uuid::Uuid;
+
+    fn range() -> FullRangeId {
+        FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        }
+    }
+
+    #[tokio::test]
+    async fn ranges_stay_overloaded_until_the_latest_retry_after() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let tracker = OverloadTracker::new(clock.clone());
+        let range = range();
+        tracker.record(&range, Some(Duration::from_secs(2)));
+        clock.advance(Duration::from_secs(1));
+        // A shorter hold does not cut the current one short.
+        tracker.record(&range, Some(Duration::from_millis(500)));
+        clock.advance(Duration::from_millis(900));
+        assert_eq!(tracker.overloaded_ranges(), vec![range]);
+        // A longer one extends it.
+        tracker.record(&range, Some(Duration::from_secs(1)));
+        clock.advance(Duration::from_millis(900));
+        assert_eq!(tracker.overloaded_ranges(), vec![range]);
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(tracker.overloaded_ranges(), vec![]);
+        // Without a retry after, the default hold applies.
+        tracker.record(&range, None);
+        clock.advance(DEFAULT_OVERLOAD_HOLD - Duration::from_millis(1));
+        assert_eq!(tracker.overloaded_ranges(), vec![range]);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(tracker.overloaded_ranges(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn subscriptions_wake_up_when_overload_expires() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let tracker = Arc::new(OverloadTracker::new(clock.clone()));
+        let read = {
+            let tracker = tracker.clone();
+            Box::new(move || Backpressure::new(tracker.overloaded_ranges(), 0, None))
+        };
+        let mut subscription =
+            BackpressureSubscription::new(read, vec![tracker.clone()], Vec::new(), clock.clone());
+        let range = range();
+        tracker.record(&range, Some(Duration::from_secs(1)));
+        let backpressure = subscription.changed().await;
+        assert_eq!(backpressure.level, BackpressureLevel::Elevated);
+        assert_eq!(backpressure.overloaded_ranges, vec![range]);
+
+        // Nothing is recorded when the hold expires, only the clock moves.
+        let waiter = tokio::spawn(async move { subscription.changed().await });
+        clock.advance(Duration::from_millis(999));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+        clock.advance(Duration::from_millis(1));
+        let backpressure = waiter.await.unwrap();
+        assert_eq!(backpressure.level, BackpressureLevel::Normal);
+        assert_eq!(backpressure.overloaded_ranges, vec![]);
+    }
+}
@@ -0,0 +1,82 @@
+//! Stops the coordinator from sending requests to range servers that look
+//! down. Once requests to a server keep timing out or losing their
+//! connection, its circuit opens and requests for its ranges fail right away
+//! instead of each one waiting out its own timeout.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{clock::Clock, host_info::HostIdentity};
+use tokio::time::Instant;
+
+// Consecutive timeouts or dropped connections after which a server's circuit
+// opens. Any answer from the server, even an error, resets the count.
+const FAILURE_THRESHOLD: u32 = 5;
+// How long an open circuit fails requests before letting them through again
+// to find out whether the server is back.
+const OPEN_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct Circuit {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// The circuits of the range servers the coordinator sends requests to.
+pub(crate) struct CircuitBreakers {
+    clock: Arc<dyn Clock>,
+    circuits: Mutex<HashMap<HostIdentity, Circuit>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(clock: Arc<dyn Clock>) -> CircuitBreakers {
+        CircuitBreakers {
+            clock,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether requests may be sent to the server. Once the circuit has been
+    /// open for a while requests go through again, and the first failure
+    /// reopens it.
+    pub fn allows(&self, server: &HostIdentity) -> bool {
+        let circuits = self.circuits.lock().unwrap();
+        match circuits.get(server).and_then(|c| c.open_until) {
+            Some(open_until) => self.clock.instant() >= open_until,
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self, server: &HostIdentity) {
+        self.circuits.lock().unwrap().remove(server);
+    }
+
+    /// Records a request to the server that timed out or lost its connection.
+    /// Returns whether that opened the circuit.
+    pub fn record_failure(&self, server: &HostIdentity) -> bool {
+        let now = self.clock.instant();
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(server.clone()).or_default();
+        circuit.consecutive_failures += 1;
+        if circuit.consecutive_failures < FAILURE_THRESHOLD {
+            return false;
+        }
+        let was_open = circuit.open_until.is_some_and(|until| until > now);
+        circuit.open_until = Some(now + OPEN_DURATION);
+        !was_open
+    }
+
+    /// The servers requests are currently not sent to.
+    pub fn open_circuits(&self) -> Vec<HostIdentity> {
+        let now = self.clock.instant();
+        let circuits = self.circuits.lock().unwrap();
+        circuits
+            .iter()
+            .filter(|(_, c)| c.open_until.is_some_and(|until| until > now))
+            .map(|(server, _)| server.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,347 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{keyspace::Keyspace, transaction_info::TransactionInfo};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::{coordinator::Coordinator, error::Error, transaction::Transaction};
+
+/// A config document along with the version it was written at. Versions start
+/// at 1 and are incremented by one on every successful write.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionedDocument {
+    pub version: u64,
+    pub document: Bytes,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetOutcome {
+    Written {
+        version: u64,
+    },
+    /// The document's current version did not match the expected one, nothing
+    /// was written. `current` is None if the document does not exist.
+    VersionMismatch {
+        current: Option<u64>,
+    },
+}
+
+/// Recipe for storing configuration documents on top of transactions.
+///
+/// Each document is stored under its own key, prefixed by an 8-byte big-endian
+/// version. Writes are compare-and-swap on that version, and watchers get
+/// notified whenever a newer version of the document is observed.
+pub struct ConfigStore {
+    coordinator: Arc<Coordinator>,
+    keyspace: Keyspace,
+    transaction_timeout: Duration,
+}
+
+impl ConfigStore {
+    pub fn new(
+        coordinator: Arc<Coordinator>,
+        keyspace: Keyspace,
+        transaction_timeout: Duration,
+    ) -> ConfigStore {
+        ConfigStore {
+            coordinator,
+            keyspace,
+            transaction_timeout,
+        }
+    }
+
+    fn encode(doc: &VersionedDocument) -> Bytes {
+        let mut buf = BytesMut::with_capacity(8 + doc.document.len());
+        buf.put_u64(doc.version);
+        buf.put_slice(&doc.document);
+        buf.freeze()
+    }
+
+    fn decode(val: Bytes) -> Result<VersionedDocument, Error> {
+        let version = <[u8; 8]>::try_from(&val[..val.len().min(8)])
+            .map_err(|e| Error::InternalError(Arc::new(e)))?;
+        Ok(VersionedDocument {
+            version: u64::from_be_bytes(version),
+            document: val.slice(8..),
+        })
+    }
+
+    async fn start_transaction(&self) -> Transaction {
+        let started = self.coordinator.clock().now();
+        let transaction_info = Arc::new(TransactionInfo {
+            id: Uuid::new_v4(),
+            started,
+            first_started: started,
+            labels: BTreeMap::from([("recipe".to_string(), "config_store".to_string())]),
+            overall_timeout: self.transaction_timeout,
+            isolation: Default::default(),
+            snapshot_epoch: None,
+        });
+        self.coordinator.start_transaction(transaction_info).await
+    }
+
+    async fn get_in(
+        &self,
+        tx: &mut Transaction,
+        name: &str,
+    ) -> Result<Option<VersionedDocument>, Error> {
+        let val = tx
+            .get(&self.keyspace, Bytes::copy_from_slice(name.as_bytes()))
+            .await?;
+        val.map(Self::decode).transpose()
+    }
+
+    /// Returns the latest committed version of the document, if it exists.
+    pub async fn get(&self, name: &str) -> Result<Option<VersionedDocument>, Error> {
+        let mut tx = self.start_transaction().await;
+        let res = self.get_in(&mut tx, name).await;
+        // Read-only, so nothing to commit.
+        let _ = tx.abort().await;
+        res
+    }
+
+    // Reads the document as of the last epoch that is over. The read takes no
+    // locks, so polling it does not get in the way of writers.
+    async fn get_snapshot(&self, name: &str) -> Result<Option<VersionedDocument>, Error> {
+        let epoch = self.coordinator.current_epoch().await?;
+        let mut tx = self.start_transaction().await;
+        let res = tx
+            .get_snapshot(
+                &self.keyspace,
+                Bytes::copy_from_slice(name.as_bytes()),
+                epoch.saturating_sub(1),
+            )
+            .await;
+        // Read-only, so nothing to commit.
+        let _ = tx.abort().await;
+        res?.map(Self::decode).transpose()
+    }
+
+    /// Writes `document` if the currently stored version equals
+    /// `expected_version`. Passing None for `expected_version` only succeeds if
+    /// the document does not exist yet.
+    pub async fn set(
+        &self,
+        name: &str,
+        expected_version: Option<u64>,
+        document: Bytes,
+    ) -> Result<SetOutcome, Error> {
+        let mut tx = self.start_transaction().await;
+        let current = match self.get_in(&mut tx, name).await {
+            Ok(current) => current.map(|c| c.version),
+            Err(e) => {
+                let _ = tx.abort().await;
+                return Err(e);
+            }
+        };
+        if current != expected_version {
+            let _ = tx.abort().await;
+            return Ok(SetOutcome::VersionMismatch { current });
+        }
+        let version = current.unwrap_or(0) + 1;
+        let doc = VersionedDocument { version, document };
+        if let Err(e) = tx
+            .put(
+                &self.keyspace,
+                Bytes::copy_from_slice(name.as_bytes()),
+                Self::encode(&doc),
+            )
+            .await
+        {
+            let _ = tx.abort().await;
+            return Err(e);
+        }
+        // A failed commit has already aborted the transaction.
+        tx.commit().await?;
+        Ok(SetOutcome::Written { version })
+    }
+
+    /// Watches a document for changes. The returned receiver always holds the
+    /// latest version observed and is notified whenever a newer one is seen.
+    ///
+    /// Range servers do not push change notifications yet, so the document is
+    /// polled every `poll_interval`, as of the last epoch that is over: a
+    /// write is seen once the epoch after the one it committed in is over. The
+    /// background task runs on the coordinator's runtime and stops once all
+    /// receivers are dropped or the coordinator shuts down.
+    pub fn watch(
+        self: &Arc<Self>,
+        name: &str,
+        poll_interval: Duration,
+    ) -> watch::Receiver<Option<VersionedDocument>> {
+        let (sender, receiver) = watch::channel::<Option<VersionedDocument>>(None);
+        let store = self.clone();
+        let name = name.to_string();
+        self.coordinator.spawn_background(async move {
+            loop {
+                // Errors are transient from the watcher's point of view, just
+                // try again on the next tick.
+                if let Ok(latest) = store.get_snapshot(&name).await {
+                    sender.send_if_modified(|current| {
+                        let newer = match (&*current, &latest) {
+                            (None, Some(_)) => true,
+                            (Some(c), Some(l)) => l.version > c.version,
+                            (_, None) => false,
+                        };
+                        if newer {
+                            *current = latest;
+                        }
+                        newer
+                    });
+                }
+                tokio::select! {
+                    () = sender.closed() => return,
+                    () = store.coordinator.clock().sleep(poll_interval) => (),
+                }
+            }
+        });
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        for_testing::{self, TestContext},
+        outcome::{Decision, OutcomeFilter},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn store(context: &TestContext, keyspace: &Keyspace) -> Arc<ConfigStore> {
+        Arc::new(ConfigStore::new(
+            context.coordinator.clone(),
+            keyspace.clone(),
+            TIMEOUT,
+        ))
+    }
+
+    #[tokio::test]
+    async fn set_only_writes_over_the_expected_version() {
+        let context = for_testing::setup().await;
+        let store = store(&context, &context.keyspace);
+        assert_eq!(store.get("doc").await.unwrap(), None);
+        assert_eq!(
+            store
+                .set("doc", None, Bytes::from_static(b"a"))
+                .await
+                .unwrap(),
+            SetOutcome::Written { version: 1 }
+        );
+        assert_eq!(
+            store
+                .set("doc", None, Bytes::from_static(b"b"))
+                .await
+                .unwrap(),
+            SetOutcome::VersionMismatch { current: Some(1) }
+        );
+        assert_eq!(
+            store
+                .set("doc", Some(1), Bytes::from_static(b"b"))
+                .await
+                .unwrap(),
+            SetOutcome::Written { version: 2 }
+        );
+        assert_eq!(
+            store
+                .set("doc", Some(1), Bytes::from_static(b"c"))
+                .await
+                .unwrap(),
+            SetOutcome::VersionMismatch { current: Some(2) }
+        );
+        assert_eq!(
+            store.get("doc").await.unwrap(),
+            Some(VersionedDocument {
+                version: 2,
+                document: Bytes::from_static(b"b"),
+            })
+        );
+        assert_eq!(
+            store
+                .set("other", Some(1), Bytes::from_static(b"a"))
+                .await
+                .unwrap(),
+            SetOutcome::VersionMismatch { current: None }
+        );
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn failed_sets_abort_their_transaction() {
+        let context = for_testing::setup().await;
+        let store = store(&context, &context.read_only_keyspace);
+        let mut outcomes = context.coordinator.subscribe_to_outcomes(OutcomeFilter {
+            labels: BTreeMap::from([("recipe".to_string(), "config_store".to_string())]),
+            ..Default::default()
+        });
+        assert!(matches!(
+            store.set("doc", None, Bytes::from_static(b"a")).await,
+            Err(Error::KeyspaceIsReadOnly)
+        ));
+        let outcome = tokio::time::timeout(Duration::from_secs(1), outcomes.next())
+            .await
+            .expect("the transaction was left running")
+            .unwrap();
+        assert_eq!(outcome.decision, Decision::Aborted);
+        context.tear_down().await
+    }
+
+    // On the test's clock, which the watcher polls by.
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    // Advances the epoch until the watcher sees `version`, since it reads as
+    // of the last epoch that is over, and the clock so that it polls again.
+    async fn wait_for_version(
+        context: &mut TestContext,
+        watcher: &mut watch::Receiver<Option<VersionedDocument>>,
+        version: u64,
+    ) -> VersionedDocument {
+        for _ in 0..10 {
+            context.advance_epoch().await;
+            context.clock.advance(POLL_INTERVAL);
+            let seen = tokio::time::timeout(
+                Duration::from_millis(100),
+                watcher.wait_for(|doc| doc.as_ref().is_some_and(|doc| doc.version == version)),
+            )
+            .await;
+            if let Ok(seen) = seen {
+                return seen.unwrap().clone().unwrap();
+            }
+        }
+        panic!("the watcher never saw version {}", version)
+    }
+
+    #[tokio::test]
+    async fn watchers_see_the_latest_version() {
+        let mut context = for_testing::setup().await;
+        let store = store(&context, &context.keyspace);
+        let mut watcher = store.watch("doc", POLL_INTERVAL);
+        // The watcher's polls take no locks, so they never abort the writes.
+        assert_eq!(
+            store
+                .set("doc", None, Bytes::from_static(b"a"))
+                .await
+                .unwrap(),
+            SetOutcome::Written { version: 1 }
+        );
+        assert_eq!(
+            wait_for_version(&mut context, &mut watcher, 1).await,
+            VersionedDocument {
+                version: 1,
+                document: Bytes::from_static(b"a"),
+            }
+        );
+        assert_eq!(
+            store
+                .set("doc", Some(1), Bytes::from_static(b"b"))
+                .await
+                .unwrap(),
+            SetOutcome::Written { version: 2 }
+        );
+        let seen = wait_for_version(&mut context, &mut watcher, 2).await;
+        assert_eq!(seen.document, Bytes::from_static(b"b"));
+        context.tear_down().await
+    }
+}
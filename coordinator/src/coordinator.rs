@@ -1,23 +1,277 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use bytes::Bytes;
 use common::{
-    config::Config, membership::range_assignment_oracle::RangeAssignmentOracle,
-    network::fast_network::FastNetwork, region::Zone, transaction_info::TransactionInfo,
+    clock::{Clock, SystemClock},
+    config::Config,
+    full_range_id::FullRangeId,
+    host_info::HostIdentity,
+    keyspace::Keyspace,
+    keyspace_id::KeyspaceId,
+    membership::range_assignment_oracle::RangeAssignmentOracle,
+    network::fast_network::FastNetwork,
+    region::Zone,
+    transaction_info::{IsolationLevel, TransactionInfo},
+};
+use epoch_publisher::error::Error as EpochError;
+use epoch_reader::{regional::RegionalEpochReader, source::EpochSource};
+use futures::future::{join_all, BoxFuture};
+use proto::universe::{
+    get_keyspace_info_request::KeyspaceInfoSearchField, universe_client::UniverseClient,
+    GetKeyspaceInfoRequest, Keyspace as ProtoKeyspace,
 };
-use epoch_reader::reader::EpochReader;
-use proto::universe::universe_client::UniverseClient;
+use rangeclient::client::ConflictStats;
+use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
-use tx_state_store::client::Client as TxStateStoreClient;
+use tx_state_store::client::{Client as TxStateStoreClient, OpResult};
+use uuid::Uuid;
+
+use crate::{
+    backpressure::{Backpressure, BackpressureSubscription, OverloadTracker},
+    epoch_coalescer::EpochReadCoalescer,
+    error::Error,
+    instrumentation::Instrumentation,
+    lifecycle_log::LifecycleLogger,
+    metrics::{FanOutMetrics, FanOutRecorder},
+    outcome::{Decision, OutcomeFilter, OutcomeNotifier, OutcomeSubscription, TransactionOutcome},
+    participants::ParticipantRegistry,
+    tasks::{TaskAccounting, TransactionTasks},
+    transaction::Transaction,
+};
+
+// How long `Coordinator::force_abort` and `Coordinator::resolve_transaction`
+// keep retrying a participant that failed to hear the decision, and how long
+// they wait before the first retry, doubling on every further one.
+const NOTIFY_PARTICIPANTS_TIMEOUT: Duration = Duration::from_secs(10);
+const NOTIFY_PARTICIPANTS_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const NOTIFY_PARTICIPANTS_MAX_BACKOFF: Duration = Duration::from_secs(1);
 
-use crate::transaction::Transaction;
+/// How many requests went to range servers in the zone of the coordinator,
+/// and how many crossed to another zone.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ZoneTraffic {
+    pub same_zone_requests: u64,
+    pub cross_zone_requests: u64,
+}
+
+/// How `Coordinator::run_transaction` runs a transaction, and retries it
+/// when it aborts for a retryable reason.
+#[derive(Clone, Debug)]
+pub struct RunOptions {
+    /// Overall timeout of the transaction of each attempt.
+    pub transaction_timeout: Duration,
+    pub labels: BTreeMap<String, String>,
+    pub isolation: IsolationLevel,
+    /// Attempts made in total, including the first one.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry, doubling on every further
+    /// retry up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            transaction_timeout: Duration::from_secs(5),
+            labels: BTreeMap::new(),
+            isolation: IsolationLevel::Serializable,
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
 
 pub struct Coordinator {
     universe_client: UniverseClient<tonic::transport::Channel>,
     range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
     runtime: tokio::runtime::Handle,
     range_client: Arc<crate::rangeclient::RangeClient>,
-    epoch_reader: Arc<EpochReader>,
+    epoch_reader: Arc<dyn EpochSource>,
+    // Set if the epoch reader was built from the config.
+    regional_epoch_reader: Option<Arc<RegionalEpochReader>>,
+    // Wraps the epoch reader, sharing its reads between concurrent commits.
+    epoch_read_coalescer: Arc<EpochReadCoalescer>,
     tx_state_store: Arc<TxStateStoreClient>,
+    outcome_notifier: Arc<OutcomeNotifier>,
+    lifecycle_logger: Option<Arc<LifecycleLogger>>,
+    instrumentation: Option<Arc<dyn Instrumentation>>,
+    participant_registry: Arc<ParticipantRegistry>,
+    fan_out: Arc<FanOutRecorder>,
+    task_accounting: Arc<TaskAccounting>,
+    overload_tracker: Arc<OverloadTracker>,
+    clock: Arc<dyn Clock>,
+    // How often wait_for_epoch re-reads the epoch.
+    epoch_poll_interval: Duration,
+}
+
+/// Builds a Coordinator, constructing any dependency that is not explicitly
+/// provided from the config.
+pub struct CoordinatorBuilder {
+    config: Config,
+    zone: Zone,
+    range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
+    fast_network: Arc<dyn FastNetwork>,
+    runtime: Option<tokio::runtime::Handle>,
+    bg_runtime: Option<tokio::runtime::Handle>,
+    cancellation_token: Option<CancellationToken>,
+    universe_client: Option<UniverseClient<tonic::transport::Channel>>,
+    epoch_reader: Option<Arc<dyn EpochSource>>,
+    epoch_network: Option<Arc<dyn FastNetwork>>,
+    tx_state_store: Option<Arc<TxStateStoreClient>>,
+    lifecycle_logger: Option<LifecycleLogger>,
+    instrumentation: Option<Arc<dyn Instrumentation>>,
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl CoordinatorBuilder {
+    /// Runtime the coordinator runs its work on. Defaults to the runtime
+    /// `build` is called from.
+    pub fn runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Runtime for background work. Defaults to the coordinator's runtime.
+    pub fn bg_runtime(mut self, bg_runtime: tokio::runtime::Handle) -> Self {
+        self.bg_runtime = Some(bg_runtime);
+        self
+    }
+
+    /// Token that stops the coordinator's background tasks when cancelled.
+    /// Defaults to a token that is never cancelled.
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// Defaults to connecting to the universe address from the config.
+    pub fn universe_client(
+        mut self,
+        universe_client: UniverseClient<tonic::transport::Channel>,
+    ) -> Self {
+        self.universe_client = Some(universe_client);
+        self
+    }
+
+    /// Defaults to reading from the epoch publishers of the coordinator's zone.
+    pub fn epoch_reader(mut self, epoch_reader: Arc<dyn EpochSource>) -> Self {
+        self.epoch_reader = Some(epoch_reader);
+        self
+    }
+
+    /// Network the default epoch reader reads from the epoch publishers on,
+    /// which only serve over UDP. Defaults to the fast network.
+    pub fn epoch_network(mut self, epoch_network: Arc<dyn FastNetwork>) -> Self {
+        self.epoch_network = Some(epoch_network);
+        self
+    }
+
+    /// Defaults to the tx_state_store of the coordinator's region.
+    pub fn tx_state_store(mut self, tx_state_store: Arc<TxStateStoreClient>) -> Self {
+        self.tx_state_store = Some(tx_state_store);
+        self
+    }
+
+    /// Enables structured logging of the lifecycle of a sample of the
+    /// coordinator's transactions, and auditing of all of its transactions
+    /// that span several namespaces. Disabled by default.
+    pub fn lifecycle_logger(mut self, logger: LifecycleLogger) -> Self {
+        self.lifecycle_logger = Some(logger);
+        self
+    }
+
+    /// Hooks called around every operation of the coordinator's
+    /// transactions. None by default.
+    pub fn instrumentation(mut self, instrumentation: Arc<dyn Instrumentation>) -> Self {
+        self.instrumentation = Some(instrumentation);
+        self
+    }
+
+    /// Source of time for transaction timeouts. Defaults to the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub async fn build(self) -> Result<Coordinator, Error> {
+        let runtime = self.runtime.unwrap_or_else(tokio::runtime::Handle::current);
+        let bg_runtime = self.bg_runtime.unwrap_or_else(|| runtime.clone());
+        let cancellation_token = self.cancellation_token.unwrap_or_default();
+        let clock = self.clock.unwrap_or_else(|| Arc::new(SystemClock));
+        let overload_tracker = Arc::new(OverloadTracker::new(clock.clone()));
+        let range_client = Arc::new(crate::rangeclient::RangeClient::new(
+            self.zone.clone(),
+            self.range_assignment_oracle.clone(),
+            self.fast_network.clone(),
+            runtime.clone(),
+            cancellation_token.clone(),
+            overload_tracker.clone(),
+            clock.clone(),
+        ));
+        let tx_state_store = match self.tx_state_store {
+            Some(tx_state_store) => tx_state_store,
+            None => Arc::new(
+                TxStateStoreClient::new(self.config.clone(), self.zone.region.clone()).await,
+            ),
+        };
+        let (epoch_reader, regional_epoch_reader) = match self.epoch_reader {
+            Some(epoch_reader) => (epoch_reader, None),
+            None => {
+                let reader = Arc::new(
+                    RegionalEpochReader::new(
+                        &self.config,
+                        &self.zone,
+                        self.epoch_network
+                            .unwrap_or_else(|| self.fast_network.clone()),
+                        runtime.clone(),
+                        bg_runtime,
+                        cancellation_token.clone(),
+                    )
+                    .map_err(|e| Error::InternalError(Arc::new(std::io::Error::other(e))))?,
+                );
+                (reader.clone() as Arc<dyn EpochSource>, Some(reader))
+            }
+        };
+        let epoch_read_coalescer = Arc::new(EpochReadCoalescer::new(epoch_reader, runtime.clone()));
+        let epoch_reader = epoch_read_coalescer.clone() as Arc<dyn EpochSource>;
+        let universe_client = match self.universe_client {
+            Some(universe_client) => universe_client,
+            None => {
+                let universe_addr = format!("http://{}", self.config.universe.proto_server_addr);
+                UniverseClient::connect(universe_addr)
+                    .await
+                    .map_err(|e| Error::InternalError(Arc::new(e)))?
+            }
+        };
+
+        Ok(Coordinator {
+            universe_client,
+            range_assignment_oracle: self.range_assignment_oracle,
+            runtime,
+            range_client,
+            tx_state_store,
+            epoch_reader,
+            regional_epoch_reader,
+            epoch_read_coalescer,
+            outcome_notifier: Arc::new(OutcomeNotifier::new()),
+            lifecycle_logger: self.lifecycle_logger.map(Arc::new),
+            instrumentation: self.instrumentation,
+            participant_registry: Arc::new(ParticipantRegistry::new()),
+            fan_out: Arc::new(FanOutRecorder::new()),
+            task_accounting: Arc::new(TaskAccounting::new(cancellation_token)),
+            overload_tracker,
+            clock,
+            epoch_poll_interval: self.config.epoch.epoch_duration,
+        })
+    }
 }
 
 impl Coordinator {
@@ -30,47 +284,54 @@ impl Coordinator {
         bg_runtime: tokio::runtime::Handle,
         cancellation_token: CancellationToken,
     ) -> Coordinator {
-        let range_client = Arc::new(crate::rangeclient::RangeClient::new(
-            range_assignment_oracle.clone(),
-            fast_network.clone(),
-            runtime.clone(),
-            cancellation_token.clone(),
-        ));
-        let tx_state_store =
-            Arc::new(TxStateStoreClient::new(config.clone(), zone.region.clone()).await);
-        let region_config = config.regions.get(&zone.region).unwrap();
-        let publisher_set = region_config
-            .epoch_publishers
-            .iter()
-            .find(|&s| s.zone == zone)
-            .unwrap();
-        let epoch_reader = Arc::new(EpochReader::new(
-            fast_network.clone(),
-            runtime.clone(),
-            bg_runtime,
-            publisher_set.clone(),
-            cancellation_token.clone(),
-        ));
-        let universe_addr = format!("http://{}", config.universe.proto_server_addr.to_string());
-        let universe_client = UniverseClient::connect(universe_addr).await.unwrap();
+        Self::builder(config.clone(), zone, range_assignment_oracle, fast_network)
+            .runtime(runtime)
+            .bg_runtime(bg_runtime)
+            .cancellation_token(cancellation_token)
+            .build()
+            .await
+            .unwrap()
+    }
 
-        Coordinator {
-            universe_client,
+    /// Returns a builder for a coordinator in `zone`. The range assignment
+    /// oracle and the fast network have no sensible defaults and must always
+    /// be provided.
+    pub fn builder(
+        config: Config,
+        zone: Zone,
+        range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
+        fast_network: Arc<dyn FastNetwork>,
+    ) -> CoordinatorBuilder {
+        CoordinatorBuilder {
+            config,
+            zone,
             range_assignment_oracle,
-            runtime,
-            range_client,
-            tx_state_store,
-            epoch_reader,
+            fast_network,
+            runtime: None,
+            bg_runtime: None,
+            cancellation_token: None,
+            universe_client: None,
+            epoch_reader: None,
+            epoch_network: None,
+            tx_state_store: None,
+            lifecycle_logger: None,
+            instrumentation: None,
+            clock: None,
         }
     }
 
     pub async fn start_transaction(&self, transaction_info: Arc<TransactionInfo>) -> Transaction {
+        let transaction_info = self.take_snapshot(transaction_info).await;
         //TODO(tamer): start transaction at the tx_state_store.
         self.tx_state_store
             .start_transaction(transaction_info.id)
             .await
             .unwrap();
 
+        let timeline = self
+            .lifecycle_logger
+            .as_ref()
+            .and_then(|l| l.begin(&transaction_info));
         Transaction::new(
             transaction_info,
             self.universe_client.clone(),
@@ -78,7 +339,600 @@ impl Coordinator {
             self.range_assignment_oracle.clone(),
             self.epoch_reader.clone(),
             self.tx_state_store.clone(),
-            self.runtime.clone(),
+            self.outcome_notifier.clone(),
+            self.participant_registry.clone(),
+            self.fan_out.clone(),
+            self.lifecycle_logger.clone(),
+            timeline,
+            self.instrumentation.clone(),
+            TransactionTasks::new(self.runtime.clone(), self.task_accounting.clone()),
+            self.clock.clone(),
+        )
+    }
+
+    // Snapshot transactions read as of the last epoch that is over, so that
+    // nothing can commit in it anymore. If the epoch can't be read, or none
+    // is over yet, the transaction runs as serializable instead: without a
+    // snapshot, nothing would keep two of them from overwriting each other.
+    async fn take_snapshot(&self, transaction_info: Arc<TransactionInfo>) -> Arc<TransactionInfo> {
+        if transaction_info.isolation != IsolationLevel::Snapshot
+            || transaction_info.snapshot_epoch.is_some()
+        {
+            return transaction_info;
+        }
+        let snapshot_epoch = match self.epoch_reader.read_epoch().await {
+            Ok(epoch) => epoch.checked_sub(1).filter(|epoch| *epoch > 0),
+            Err(_) => None,
+        };
+        let isolation = match snapshot_epoch {
+            Some(_) => IsolationLevel::Snapshot,
+            None => IsolationLevel::Serializable,
+        };
+        Arc::new(TransactionInfo {
+            isolation,
+            snapshot_epoch,
+            ..(*transaction_info).clone()
+        })
+    }
+
+    /// Starts a transaction pinned to the ranges holding `keys`, see
+    /// `Transaction::pin`. If pinning fails, the transaction is aborted and
+    /// the error returned.
+    pub async fn start_pinned_transaction(
+        &self,
+        transaction_info: Arc<TransactionInfo>,
+        keys: &[(Keyspace, Bytes)],
+    ) -> Result<Transaction, Error> {
+        let mut tx = self.start_transaction(transaction_info).await;
+        if let Err(e) = tx.pin(keys).await {
+            let _ = tx.abort().await;
+            return Err(e);
+        }
+        Ok(tx)
+    }
+
+    /// Runs `body` in a new transaction and commits it, starting over in a
+    /// fresh transaction whenever the transaction aborts for a retryable
+    /// reason (see `TransactionAbortReason::is_retryable`), up to
+    /// `options.max_attempts` times. Other errors abort the transaction and
+    /// are returned as is.
+    ///
+    /// `body` gets the transaction and returns a boxed future, as in
+    /// `|tx| Box::pin(async move { ... })`. It may run several times, so it
+    /// should only have effects through the transaction, and must not commit
+    /// or abort the transaction itself.
+    pub async fn run_transaction<T, F>(&self, options: &RunOptions, mut body: F) -> Result<T, Error>
+    where
+        F: for<'a> FnMut(&'a mut Transaction) -> BoxFuture<'a, Result<T, Error>>,
+    {
+        let mut backoff = options.initial_backoff;
+        let mut attempt = 1;
+        // Retries keep the start of the first attempt, so that they grow
+        // older and eventually win the locks they abort over, but each one
+        // gets the whole timeout.
+        let first_started = self.clock.now();
+        loop {
+            let transaction_info = Arc::new(TransactionInfo {
+                id: Uuid::new_v4(),
+                started: self.clock.now(),
+                first_started,
+                overall_timeout: options.transaction_timeout,
+                labels: options.labels.clone(),
+                isolation: options.isolation,
+                snapshot_epoch: None,
+            });
+            let mut tx = self.start_transaction(transaction_info).await;
+            let res = match body(&mut tx).await {
+                Ok(value) => tx.commit().await.map(|()| value),
+                Err(e) => {
+                    let _ = tx.abort().await;
+                    Err(e)
+                }
+            };
+            match res {
+                Err(Error::TransactionAborted(reason))
+                    if reason.is_retryable() && attempt < options.max_attempts => {}
+                res => return res,
+            }
+            self.clock.sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, options.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    /// Commits several independent transactions together. Results are in the
+    /// same order as `transactions`, and each one is what `commit` would
+    /// have returned for that transaction on its own: transactions that fail
+    /// to prepare or get aborted don't hold back the others.
+    ///
+    /// All the transactions prepare concurrently, then their commit decisions
+    /// are written to the tx_state_store concurrently and their participants
+    /// notified concurrently. The decisions are still one conditional write
+    /// per transaction, as each one lives in its own partition: a batch
+    /// saves round trips, not writes.
+    pub async fn commit_batch(&self, transactions: &mut [Transaction]) -> Vec<Result<(), Error>> {
+        let op_start = Instant::now();
+        for tx in transactions.iter() {
+            tx.begin_op("commit", None);
+        }
+        let prepared = join_all(transactions.iter_mut().map(|tx| tx.prepare_for_commit())).await;
+        let decisions: Vec<(Uuid, u64)> = transactions
+            .iter()
+            .zip(&prepared)
+            .filter_map(|(tx, res)| res.as_ref().ok().map(|epoch| (tx.id(), *epoch)))
+            .collect();
+        let mut outcomes = self
+            .tx_state_store
+            .try_commit_transactions(&decisions)
+            .await
+            .into_iter();
+
+        let results = join_all(transactions.iter_mut().zip(prepared).map(|(tx, res)| {
+            let outcome = res.as_ref().ok().map(|_| outcomes.next().unwrap());
+            async move {
+                let epoch = res?;
+                match outcome.unwrap() {
+                    Ok(outcome) => {
+                        tx.apply_commit_decision(epoch, outcome)?;
+                        tx.notify_participants_of_commit(epoch).await;
+                        Ok(())
+                    }
+                    Err(_) => tx.settle_unknown_commit(epoch).await,
+                }
+            }
+        }))
+        .await;
+        for (tx, res) in transactions.iter_mut().zip(&results) {
+            tx.record_op("commit", None, op_start, res);
+        }
+        results
+    }
+
+    /// The clock transactions started by this coordinator are timed with.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Reads the current epoch from the epoch publishers of the coordinator's
+    /// region, or of another region if none of those answer.
+    pub async fn current_epoch(&self) -> Result<u64, Error> {
+        self.epoch_reader
+            .read_epoch()
+            .await
+            .map_err(|e| Error::InternalError(Arc::new(e)))
+    }
+
+    /// Waits until the current epoch is at least `epoch`, and returns it.
+    ///
+    /// Applications can use this as an external consistency barrier, e.g. not
+    /// serving a cached value before the epoch reaches the commit epoch of the
+    /// transaction that wrote it. Failures to read the epoch are retried, so
+    /// callers that cannot wait forever should bound this with a timeout.
+    pub async fn wait_for_epoch(&self, epoch: u64) -> Result<u64, Error> {
+        loop {
+            match self.epoch_reader.read_epoch().await {
+                Ok(current) if current >= epoch => return Ok(current),
+                Ok(_)
+                | Err(EpochError::EpochUnknown)
+                | Err(EpochError::Timeout)
+                | Err(EpochError::QuorumUnreachable { .. }) => (),
+                Err(e) => return Err(Error::InternalError(Arc::new(e))),
+            }
+            self.clock.sleep(self.epoch_poll_interval).await;
+        }
+    }
+
+    /// How many epoch reads went to the epoch publishers of another region.
+    /// None if the coordinator was built with its own epoch source.
+    pub fn cross_region_epoch_reads(&self) -> Option<u64> {
+        self.regional_epoch_reader
+            .as_ref()
+            .map(|r| r.cross_region_reads())
+    }
+
+    /// How many epoch reads were served by a read made for a concurrent
+    /// commit or epoch wait rather than going to the epoch publishers.
+    pub fn coalesced_epoch_reads(&self) -> u64 {
+        self.epoch_read_coalescer.coalesced_reads()
+    }
+
+    /// How many requests to range servers stayed in the coordinator's zone,
+    /// and how many went to another zone, which costs inter-zone transfer.
+    pub fn zone_traffic(&self) -> ZoneTraffic {
+        self.range_client.zone_traffic()
+    }
+
+    /// How the commits of the coordinator fanned out to participant ranges:
+    /// how many they prepared on, how large the prepares were and how long
+    /// the slowest one took.
+    pub fn fan_out(&self) -> FanOutMetrics {
+        self.fan_out.metrics()
+    }
+
+    /// Range servers that recently kept timing out or dropping connections.
+    /// Requests to their ranges fail with `Error::RangeServerUnavailable`
+    /// without being sent, until the ranges move or the servers recover.
+    pub fn open_circuits(&self) -> Vec<HostIdentity> {
+        self.range_client.open_circuits()
+    }
+
+    /// Number of tasks spawned on behalf of transactions that are still
+    /// running.
+    pub fn in_flight_tasks(&self) -> usize {
+        self.task_accounting.in_flight()
+    }
+
+    /// Whether range servers are pushing back on this coordinator's
+    /// transactions, so the application can hold off on starting new ones.
+    ///
+    /// A coordinator on its own puts no limit on the transactions it runs,
+    /// so it has no saturation to report: its level is only raised by
+    /// overloaded ranges, and `pool` is always `None`. Applications that want
+    /// to be held back by the number of transactions in flight run them
+    /// through a `CoordinatorPool` and read its backpressure instead.
+    pub fn backpressure(&self) -> Backpressure {
+        Backpressure::new(
+            self.overload_tracker.overloaded_ranges(),
+            self.in_flight_tasks(),
+            None,
         )
     }
+
+    /// Subscribes to changes of the backpressure level and of the ranges
+    /// found overloaded.
+    pub fn subscribe_to_backpressure(&self) -> BackpressureSubscription {
+        let tracker = self.overload_tracker.clone();
+        let tasks = self.task_accounting.clone();
+        BackpressureSubscription::new(
+            Box::new(move || {
+                Backpressure::new(tracker.overloaded_ranges(), tasks.in_flight(), None)
+            }),
+            vec![self.overload_tracker.clone()],
+            Vec::new(),
+            self.clock.clone(),
+        )
+    }
+
+    /// Runs `task` on the coordinator's runtime until it returns or the
+    /// coordinator shuts down.
+    pub(crate) fn spawn_background<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.task_accounting.spawn_background(&self.runtime, task)
+    }
+
+    pub(crate) fn overload_tracker(&self) -> &Arc<OverloadTracker> {
+        &self.overload_tracker
+    }
+
+    /// Subscribes to the commit/abort decisions of transactions started by
+    /// this coordinator that match `filter`. Only decisions made after
+    /// subscribing are delivered.
+    pub fn subscribe_to_outcomes(&self, filter: OutcomeFilter) -> OutcomeSubscription {
+        self.outcome_notifier.subscribe(filter)
+    }
+
+    /// Reads the conflicts seen on the range holding `key`, grouped by key
+    /// prefix, so applications can find their contended entities. Prefixes
+    /// are cut as set by `range_server.conflict_stats` in the range server's
+    /// config, and only cover the range since it was last loaded.
+    ///
+    /// The counts are advisory: they are kept in memory on the range server,
+    /// are not synchronized with anything, and may be lost at any time.
+    pub async fn conflict_stats(
+        &self,
+        keyspace: &Keyspace,
+        key: Bytes,
+    ) -> Result<ConflictStats, Error> {
+        let keyspace_info = self
+            .universe_client
+            .clone()
+            .get_keyspace_info(GetKeyspaceInfoRequest {
+                keyspace_info_search_field: Some(KeyspaceInfoSearchField::Keyspace(
+                    ProtoKeyspace {
+                        namespace: keyspace.namespace.clone(),
+                        name: keyspace.name.clone(),
+                    },
+                )),
+            })
+            .await
+            .map_err(|e| match e.code() {
+                tonic::Code::NotFound => Error::KeyspaceDoesNotExist,
+                _ => Error::InternalError(Arc::new(e)),
+            })?
+            .into_inner()
+            .keyspace_info
+            .ok_or(Error::KeyspaceDoesNotExist)?;
+        let keyspace_id = KeyspaceId::from_str(&keyspace_info.keyspace_id)
+            .map_err(|e| Error::InternalError(Arc::new(std::io::Error::other(e))))?;
+        let range_id = self
+            .range_assignment_oracle
+            .full_range_id_of_key(keyspace_id, key)
+            .await
+            .ok_or(Error::KeyspaceDoesNotExist)?;
+        self.range_client
+            .get_conflict_stats(&range_id)
+            .await
+            .map_err(|e| {
+                Error::InternalError(Arc::new(std::io::Error::other(format!(
+                    "failed to get conflict stats of range {:?}: {:?}",
+                    range_id, e
+                ))))
+            })
+    }
+
+    /// Forcibly aborts a transaction, e.g. one that is stuck holding hot locks.
+    ///
+    /// Writes an abort decision to the tx_state_store if the transaction is
+    /// still undecided, then asks all its known participants to abort it,
+    /// retrying for a while those that fail. The participants known to this
+    /// coordinator are always notified; ranges discovered by other means
+    /// (e.g. listing lock holders on range servers) can be passed in
+    /// `extra_participants`.
+    ///
+    /// Returns the final decision for the transaction, which is Committed if it
+    /// had already committed before the abort could be recorded.
+    pub async fn force_abort(
+        &self,
+        transaction_id: Uuid,
+        extra_participants: &[FullRangeId],
+    ) -> Result<Decision, Error> {
+        let outcome = self
+            .tx_state_store
+            .try_abort_transaction(transaction_id)
+            .await
+            .map_err(|e| Error::InternalError(Arc::new(e)))?;
+        if let OpResult::TransactionIsCommitted(info) = outcome {
+            return Ok(Decision::Committed { epoch: info.epoch });
+        }
+        self.notify_participants(transaction_id, extra_participants, None)
+            .await;
+        Ok(Decision::Aborted)
+    }
+
+    /// Resolves a transaction left prepared by a coordinator that went away,
+    /// e.g. one `ListInFlightTransactions` shows as prepared for long on its
+    /// ranges, which keep holding its locks until they hear the decision.
+    ///
+    /// Aborts the transaction in the tx_state_store if it is still
+    /// undecided, then tells its participants the decision, committing them
+    /// if it had committed. As with `force_abort`, participants not known to
+    /// this coordinator are passed in `participants`.
+    ///
+    /// Range servers resolve the transactions prepared on their ranges on
+    /// their own, see `TransactionRecoveryConfig`, this resolves one sooner.
+    pub async fn resolve_transaction(
+        &self,
+        transaction_id: Uuid,
+        participants: &[FullRangeId],
+    ) -> Result<Decision, Error> {
+        let outcome = self
+            .tx_state_store
+            .try_abort_transaction(transaction_id)
+            .await
+            .map_err(|e| Error::InternalError(Arc::new(e)))?;
+        let decision = match outcome {
+            OpResult::TransactionIsCommitted(info) => Decision::Committed { epoch: info.epoch },
+            OpResult::TransactionIsAborted => Decision::Aborted,
+        };
+        let commit_epoch = match decision {
+            Decision::Committed { epoch } => Some(epoch),
+            Decision::Aborted => None,
+        };
+        self.notify_participants(transaction_id, participants, commit_epoch)
+            .await;
+        Ok(decision)
+    }
+
+    // Commits the transaction at `commit_epoch` on its known participants
+    // and `extra_participants`, or aborts it there if None, then publishes
+    // the outcome. A participant that fails is retried with backoff for up
+    // to NOTIFY_PARTICIPANTS_TIMEOUT, as it holds the transaction's locks
+    // until it hears the decision.
+    async fn notify_participants(
+        &self,
+        transaction_id: Uuid,
+        extra_participants: &[FullRangeId],
+        commit_epoch: Option<u64>,
+    ) {
+        let mut participants = self.participant_registry.get(transaction_id);
+        participants
+            .ranges
+            .extend(extra_participants.iter().copied());
+        // Range servers only need the id to commit or abort a transaction.
+        let started = self.clock.now();
+        let transaction_info = Arc::new(TransactionInfo {
+            id: transaction_id,
+            started,
+            first_started: started,
+            overall_timeout: Duration::ZERO,
+            labels: BTreeMap::new(),
+            isolation: Default::default(),
+            snapshot_epoch: None,
+        });
+        let deadline = self.clock.instant() + NOTIFY_PARTICIPANTS_TIMEOUT;
+        let tasks = TransactionTasks::new(self.runtime.clone(), self.task_accounting.clone());
+        let mut join_set = JoinSet::new();
+        for range_id in participants.ranges {
+            let range_client = self.range_client.clone();
+            let transaction_info = transaction_info.clone();
+            let clock = self.clock.clone();
+            tasks.spawn(&mut join_set, async move {
+                let mut backoff = NOTIFY_PARTICIPANTS_INITIAL_BACKOFF;
+                loop {
+                    let res = match commit_epoch {
+                        Some(epoch) => {
+                            range_client
+                                .commit_transaction(transaction_info.clone(), &range_id, epoch)
+                                .await
+                        }
+                        None => {
+                            range_client
+                                .abort_transaction(transaction_info.clone(), &range_id)
+                                .await
+                        }
+                    };
+                    if res.is_ok() || clock.instant() + backoff > deadline {
+                        return res;
+                    }
+                    clock.sleep(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, NOTIFY_PARTICIPANTS_MAX_BACKOFF);
+                }
+            });
+        }
+        while join_set.join_next().await.is_some() {}
+
+        self.participant_registry.remove(transaction_id);
+        self.outcome_notifier.notify(TransactionOutcome {
+            id: transaction_id,
+            namespaces: participants.namespaces,
+            labels: participants.labels,
+            decision: match commit_epoch {
+                Some(epoch) => Decision::Committed { epoch },
+                None => Decision::Aborted,
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::TransactionAbortReason, for_testing};
+    use std::collections::HashSet;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    // The test range has a single lock, which a transaction takes when it
+    // prepares, so only the first transaction of these batches writes to it.
+    async fn start_batch(context: &for_testing::TestContext) -> Vec<Transaction> {
+        let mut writer = context.start_transaction(TIMEOUT).await;
+        writer
+            .put(
+                &context.keyspace,
+                Bytes::from_static(b"k"),
+                Bytes::from_static(b"v"),
+            )
+            .await
+            .unwrap();
+        vec![writer, context.start_transaction(TIMEOUT).await]
+    }
+
+    fn tx_state_store(context: &for_testing::TestContext) -> TxStateStoreClient {
+        TxStateStoreClient::in_memory(context.tx_state_store.clone())
+    }
+
+    #[tokio::test]
+    async fn batches_commit_each_transaction() {
+        let context = for_testing::setup().await;
+        context.epoch_source.set_epoch(5);
+        let mut transactions = start_batch(&context).await;
+        let results = context.coordinator.commit_batch(&mut transactions).await;
+        assert!(results.iter().all(|res| res.is_ok()), "{:?}", results);
+        for tx in &transactions {
+            assert_eq!(tx.commit_epoch(), Some(5));
+            assert!(matches!(
+                tx_state_store(&context)
+                    .get_transaction_outcome(tx.id())
+                    .await
+                    .unwrap(),
+                Some(OpResult::TransactionIsCommitted(info)) if info.epoch == 5
+            ));
+        }
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let value = tx
+            .get(&context.keyspace, Bytes::from_static(b"k"))
+            .await
+            .unwrap();
+        assert_eq!(value, Some(Bytes::from_static(b"v")));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn aborted_transactions_do_not_hold_back_the_batch() {
+        let context = for_testing::setup().await;
+        let mut transactions = start_batch(&context).await;
+        tx_state_store(&context)
+            .try_abort_transaction(transactions[0].id())
+            .await
+            .unwrap();
+        let results = context.coordinator.commit_batch(&mut transactions).await;
+        assert!(matches!(
+            results[0],
+            Err(Error::TransactionAborted(TransactionAbortReason::Other))
+        ));
+        assert!(results[1].is_ok());
+        assert_eq!(transactions[0].commit_epoch(), None);
+        assert_eq!(transactions[1].commit_epoch(), Some(1));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn force_aborts_are_published_with_the_transactions_labels_and_namespaces() {
+        let context = for_testing::setup().await;
+        let labels = BTreeMap::from([("service".to_string(), "checkout".to_string())]);
+        let started = context.clock.now();
+        let mut tx = context
+            .coordinator
+            .start_transaction(Arc::new(TransactionInfo {
+                id: Uuid::new_v4(),
+                started,
+                first_started: started,
+                overall_timeout: TIMEOUT,
+                labels: labels.clone(),
+                isolation: Default::default(),
+                snapshot_epoch: None,
+            }))
+            .await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        let mut outcomes = context.coordinator.subscribe_to_outcomes(OutcomeFilter {
+            namespaces: Some(HashSet::from([context.keyspace.namespace.clone()])),
+            labels,
+            ..Default::default()
+        });
+        let decision = context.coordinator.force_abort(tx.id(), &[]).await.unwrap();
+        assert_eq!(decision, Decision::Aborted);
+        let outcome = tokio::time::timeout(Duration::from_secs(1), outcomes.next())
+            .await
+            .expect("the force-abort was not published")
+            .unwrap();
+        assert_eq!(outcome.id, tx.id());
+        assert_eq!(outcome.decision, Decision::Aborted);
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn batched_commits_the_tx_state_store_cannot_record_end_in_an_unknown_state() {
+        let context = for_testing::setup().await;
+        let mut transactions = start_batch(&context).await;
+        context.tx_state_store.set_available(false);
+        let results = context.coordinator.commit_batch(&mut transactions).await;
+        assert!(results
+            .iter()
+            .all(|res| matches!(res, Err(Error::TransactionDoneButStateUnknown))));
+        // The transactions are settled rather than left preparing.
+        for tx in &mut transactions {
+            assert!(matches!(
+                tx.abort().await,
+                Err(Error::TransactionDoneButStateUnknown)
+            ));
+        }
+        context.tx_state_store.set_available(true);
+        for tx in &transactions {
+            assert!(tx_state_store(&context)
+                .get_transaction_outcome(tx.id())
+                .await
+                .unwrap()
+                .is_none());
+        }
+        context.tear_down().await
+    }
 }
@@ -0,0 +1,261 @@
+//! Shares epoch reads between transactions committing at the same time. Every
+//! commit needs the current epoch, and at high commit rates reading it once
+//! per commit makes the epoch publishers the bottleneck.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use async_trait::async_trait;
+use epoch_publisher::error::Error;
+use epoch_reader::source::EpochSource;
+use tokio::sync::watch;
+
+type Batch = watch::Sender<Option<Result<u64, Error>>>;
+
+#[derive(Default)]
+struct State {
+    // Whether a read of the epoch is in flight.
+    reading: bool,
+    // The callers that arrived while it was, served by the next read.
+    waiting: Option<Batch>,
+}
+
+struct Inner {
+    source: Arc<dyn EpochSource>,
+    state: Mutex<State>,
+    calls: AtomicU64,
+    reads: AtomicU64,
+}
+
+/// Reads the epoch from `source` for many callers at once. At most one read
+/// is in flight: callers arriving while it is wait for the next one, which
+/// starts as soon as it completes and serves all of them. Batches therefore
+/// grow with the commit rate and the latency of the epoch publishers, while
+/// a lone caller reads right away.
+///
+/// A caller is never handed the result of a read that started before it
+/// called, so every epoch returned is at least as fresh as one read by the
+/// caller itself, and the guarantees of `EpochSource` carry over.
+pub(crate) struct EpochReadCoalescer {
+    inner: Arc<Inner>,
+    runtime: tokio::runtime::Handle,
+}
+
+impl EpochReadCoalescer {
+    pub fn new(source: Arc<dyn EpochSource>, runtime: tokio::runtime::Handle) -> Self {
+        EpochReadCoalescer {
+            inner: Arc::new(Inner {
+                source,
+                state: Mutex::new(State::default()),
+                calls: AtomicU64::new(0),
+                reads: AtomicU64::new(0),
+            }),
+            runtime,
+        }
+    }
+
+    /// How many reads of the epoch were served by a read made for another
+    /// caller.
+    pub fn coalesced_reads(&self) -> u64 {
+        let calls = self.inner.calls.load(Ordering::Relaxed);
+        calls.saturating_sub(self.inner.reads.load(Ordering::Relaxed))
+    }
+}
+
+impl Inner {
+    // Reads the epoch for `batch`, then for the callers that queued up
+    // meanwhile, until none are left.
+    async fn read_batches(self: Arc<Self>, mut batch: Batch) {
+        loop {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            let res = self.source.read_epoch().await;
+            batch.send_replace(Some(res));
+            let mut state = self.state.lock().unwrap();
+            match state.waiting.take() {
+                Some(next) => batch = next,
+                None => {
+                    state.reading = false;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EpochSource for EpochReadCoalescer {
+    async fn read_epoch(&self) -> Result<u64, Error> {
+        self.inner.calls.fetch_add(1, Ordering::Relaxed);
+        let mut receiver = {
+            let mut state = self.inner.state.lock().unwrap();
+            match &state.waiting {
+                Some(batch) => batch.subscribe(),
+                None => {
+                    let (batch, receiver) = watch::channel(None);
+                    if state.reading {
+                        state.waiting = Some(batch);
+                    } else {
+                        state.reading = true;
+                        // Read on a task of its own, so that the callers
+                        // sharing the read don't depend on the one that
+                        // started it sticking around.
+                        self.runtime.spawn(self.inner.clone().read_batches(batch));
+                    }
+                    receiver
+                }
+            }
+        };
+        let res = match receiver.wait_for(Option::is_some).await {
+            Ok(res) => (*res).clone().unwrap(),
+            // The runtime is shutting down.
+            Err(_) => Err(Error::EpochUnknown),
+        };
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use epoch_reader::for_testing::epoch_source;
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+
+    // Reads the epoch of the mock when a read starts, but only returns it
+    // once the test lets it.
+    struct GatedSource {
+        epochs: Arc<epoch_source::EpochSource>,
+        started: AtomicU64,
+        gate: Semaphore,
+    }
+
+    #[async_trait]
+    impl EpochSource for GatedSource {
+        async fn read_epoch(&self) -> Result<u64, Error> {
+            let res = self.epochs.read_epoch().await;
+            self.started.fetch_add(1, Ordering::SeqCst);
+            self.gate.acquire().await.unwrap().forget();
+            res
+        }
+    }
+
+    struct TestContext {
+        epochs: Arc<epoch_source::EpochSource>,
+        source: Arc<GatedSource>,
+        coalescer: Arc<EpochReadCoalescer>,
+    }
+
+    fn setup() -> TestContext {
+        let epochs = Arc::new(epoch_source::EpochSource::new());
+        let source = Arc::new(GatedSource {
+            epochs: epochs.clone(),
+            started: AtomicU64::new(0),
+            gate: Semaphore::new(0),
+        });
+        let coalescer = Arc::new(EpochReadCoalescer::new(
+            source.clone(),
+            tokio::runtime::Handle::current(),
+        ));
+        TestContext {
+            epochs,
+            source,
+            coalescer,
+        }
+    }
+
+    impl TestContext {
+        fn read_epoch(&self) -> tokio::task::JoinHandle<Result<u64, Error>> {
+            let coalescer = self.coalescer.clone();
+            tokio::spawn(async move { coalescer.read_epoch().await })
+        }
+
+        async fn wait_until(&self, condition: impl Fn(&Self) -> bool) {
+            while !condition(self) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+
+        async fn wait_for_calls(&self, calls: u64) {
+            self.wait_until(|c| c.coalescer.inner.calls.load(Ordering::SeqCst) == calls)
+                .await
+        }
+
+        async fn wait_for_started_reads(&self, reads: u64) {
+            self.wait_until(|c| c.source.started.load(Ordering::SeqCst) == reads)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn lone_callers_read_right_away() {
+        let context = setup();
+        context.epochs.set_epoch(7);
+        context.source.gate.add_permits(1);
+        assert_eq!(context.coalescer.read_epoch().await.unwrap(), 7);
+        assert_eq!(context.coalescer.coalesced_reads(), 0);
+    }
+
+    #[tokio::test]
+    async fn callers_arriving_during_a_read_share_the_next_one() {
+        let context = setup();
+        context.epochs.set_epoch(7);
+        let first = context.read_epoch();
+        context.wait_for_started_reads(1).await;
+
+        context.epochs.set_epoch(8);
+        let batch: Vec<_> = (0..3).map(|_| context.read_epoch()).collect();
+        context.wait_for_calls(4).await;
+        // The callers queued up behind the read in flight instead of reading
+        // on their own.
+        assert_eq!(context.source.started.load(Ordering::SeqCst), 1);
+
+        context.source.gate.add_permits(1);
+        assert_eq!(first.await.unwrap().unwrap(), 7);
+        context.wait_for_started_reads(2).await;
+        context.source.gate.add_permits(1);
+        for caller in batch {
+            assert_eq!(caller.await.unwrap().unwrap(), 8);
+        }
+        assert_eq!(context.coalescer.inner.reads.load(Ordering::SeqCst), 2);
+        assert_eq!(context.coalescer.coalesced_reads(), 2);
+    }
+
+    #[tokio::test]
+    async fn callers_never_get_a_read_started_before_they_called() {
+        let context = setup();
+        context.epochs.set_epoch(7);
+        let first = context.read_epoch();
+        context.wait_for_started_reads(1).await;
+        // Arrives after the read in flight started, so it must not be
+        // handed its result even though it completes after this call.
+        context.epochs.set_epoch(8);
+        let second = context.read_epoch();
+        context.wait_for_calls(2).await;
+        context.source.gate.add_permits(2);
+        assert_eq!(first.await.unwrap().unwrap(), 7);
+        assert_eq!(second.await.unwrap().unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn errors_reach_the_whole_batch_and_do_not_stick() {
+        let context = setup();
+        context.epochs.set_epoch(7);
+        let first = context.read_epoch();
+        context.wait_for_started_reads(1).await;
+
+        context.epochs.set_available(false);
+        let batch: Vec<_> = (0..3).map(|_| context.read_epoch()).collect();
+        context.wait_for_calls(4).await;
+        context.source.gate.add_permits(2);
+        assert_eq!(first.await.unwrap().unwrap(), 7);
+        for caller in batch {
+            assert!(matches!(caller.await.unwrap(), Err(Error::EpochUnknown)));
+        }
+
+        context.epochs.set_available(true);
+        context.source.gate.add_permits(1);
+        assert_eq!(context.coalescer.read_epoch().await.unwrap(), 7);
+    }
+}
@@ -9,16 +9,98 @@ pub enum TransactionAbortReason {
     RangeLeadershipChanged,
     RangeLeaseExpired,
     RangePartitioningChanged,
+    /// A keyspace the transaction touched was dropped before it prepared.
+    KeyspaceDropped,
+    /// A range the transaction touched had too many transactions queued on
+    /// its locks. Retrying later may succeed.
+    RangeOverloaded,
+    /// A range the transaction touched had it wait for a lock for longer
+    /// than its range server allows, as other transactions held on to the
+    /// lock. Retrying later may succeed.
+    LockWaitTimeout,
+    /// The epoch to commit in could not be read, e.g. because no majority of
+    /// the epoch publishers answered. Retrying later may succeed.
+    EpochUnavailable,
     TransactionTimeout,
     PrepareFailed,
+    /// An external participant enrolled with `Transaction::enroll` failed to
+    /// prepare.
+    ExternalParticipantFailed,
+    /// A range aborted the transaction over a conflict with another one,
+    /// e.g. it lost a lock to an older transaction, or something committed
+    /// after it read optimistically. Range servers don't say which yet.
+    Conflict,
     Other,
 }
 
+impl TransactionAbortReason {
+    /// Whether running the transaction again from the start may succeed,
+    /// i.e. the abort came from contention or from ranges moving around
+    /// rather than from the transaction itself.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::DeadlockPrevention
+            | Self::TransactionLockLost
+            | Self::RangeLeadershipChanged
+            | Self::RangeLeaseExpired
+            | Self::RangePartitioningChanged
+            | Self::RangeOverloaded
+            | Self::LockWaitTimeout
+            | Self::EpochUnavailable
+            | Self::PrepareFailed
+            | Self::Conflict => true,
+            Self::KeyspaceDropped
+            | Self::ExternalParticipantFailed
+            | Self::TransactionTimeout
+            | Self::Other => false,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Error {
     KeyspaceDoesNotExist,
+    /// The keyspace is in read-only (maintenance) mode and rejects writes.
+    KeyspaceIsReadOnly,
+    /// A write breaks the validation policy of its keyspace, so the
+    /// transaction was aborted. Retrying the same writes fails the same way.
+    WriteRejected,
+    /// The condition of a `put_if` did not hold at prepare, so the
+    /// transaction was aborted.
+    ConditionFailed,
+    /// An `increment` applies to a value that is not a counter, or overflows
+    /// it. If the range found this at prepare, the transaction was aborted.
+    InvalidIncrement,
+    /// The policy of `namespace` does not let it share a transaction with
+    /// `peer`, another namespace the transaction touches. Policies are set
+    /// on the universe.
+    CrossNamespaceAccessDenied {
+        namespace: String,
+        peer: String,
+    },
     TransactionNoLongerRunning,
+    /// The savepoint is not one of the transaction's, or was discarded by
+    /// rolling back to an earlier one.
+    UnknownSavepoint,
     Timeout,
+    /// An operator froze a range the transaction touched, see
+    /// `SetRangeFrozen` in rangeserver.proto. The transaction can be retried
+    /// once the range is unfrozen.
+    RangeFrozen,
+    /// `Transaction::get_snapshot` asked for an epoch the range can no longer
+    /// serve, as its versions may have been compacted away or the range was
+    /// loaded since. Reading at a later epoch may succeed.
+    SnapshotTooOld,
+    /// The server of a range the transaction touched could not be reached,
+    /// or recently kept timing out so the request was not sent. The range
+    /// may be moved to another server shortly.
+    RangeServerUnavailable,
+    /// The transaction was pinned to its ranges with `Transaction::pin` and
+    /// the key is in none of them.
+    RangeNotPinned,
+    /// Not enough of the transaction's overall timeout is left to both perform
+    /// the requested operation and still prepare and commit.
+    InsufficientTimeRemaining,
     TransactionDoneButStateUnknown,
     TransactionAborted(TransactionAbortReason),
     InternalError(Arc<dyn std::error::Error + Send + Sync>),
@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+pub type ExternalError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A system outside of the database taking part in the two-phase commit of
+/// a transaction, e.g. an outbox in another database whose messages must be
+/// sent if and only if the transaction commits. See `Transaction::enroll`.
+///
+/// The decision recorded in the tx_state_store is the authority on whether
+/// the transaction committed. A participant that prepared and never heard
+/// back, e.g. because the coordinator crashed, must not guess: it gets the
+/// decision from `Coordinator::force_abort`, which aborts the transaction if
+/// it is still undecided and otherwise returns how it was decided.
+#[async_trait]
+pub trait ExternalParticipant: Send + Sync {
+    /// Durably stage the participant's part of the transaction, such that it
+    /// can still be committed or discarded after a crash. Failing aborts the
+    /// transaction.
+    async fn prepare(&self, transaction_id: Uuid) -> Result<(), ExternalError>;
+
+    /// Apply the staged part of the committed transaction. Must be
+    /// idempotent, as a participant recovering from a crash may also get to
+    /// apply it.
+    async fn commit(&self, transaction_id: Uuid, epoch: u64);
+
+    /// Discard whatever was staged for the aborted transaction. Also called
+    /// on participants that never prepared, or failed to.
+    async fn abort(&self, transaction_id: Uuid);
+}
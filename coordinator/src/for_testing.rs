@@ -0,0 +1,459 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{SocketAddr, UdpSocket},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use common::{
+    clock::{Clock, ManualClock},
+    config::{
+        CassandraConfig, Config, EpochConfig, FrontendConfig, HostPort, RangeServerConfig,
+        RegionConfig, UniverseConfig,
+    },
+    full_range_id::FullRangeId,
+    host_info::{HostIdentity, HostInfo},
+    keyspace::Keyspace,
+    keyspace_id::KeyspaceId,
+    membership::static_range_assignment_oracle::{
+        StaticAssignment, StaticHost, StaticRange, StaticRangeAssignmentOracle,
+    },
+    network::{fast_network::FastNetwork, for_testing::udp_fast_network::UdpFastNetwork},
+    region::{Region, Zone},
+    transaction_info::TransactionInfo,
+};
+use epoch_reader::for_testing::epoch_source::EpochSource;
+use proto::{
+    rangeserver::{range_server_client::RangeServerClient, RangeId, SplitRangeRequest},
+    universe::{universe_client::UniverseClient, KeyspaceInfo},
+};
+use rangeserver::{
+    for_testing::{epoch_supplier::EpochSupplier, mock_warden::MockWarden},
+    server::Server,
+    storage::in_memory::for_testing::TestContext as StorageContext,
+};
+use tokio::{net::TcpListener, runtime::Builder};
+use tokio_util::sync::CancellationToken;
+use tx_state_store::{
+    client::Client as TxStateStoreClient, for_testing::in_memory_storage::InMemoryStorage,
+};
+use uuid::Uuid;
+
+use crate::{coordinator::Coordinator, transaction::Transaction};
+
+mod mock_universe;
+mod reassignable_oracle;
+
+use mock_universe::MockUniverse;
+use reassignable_oracle::ReassignableOracle;
+
+const SERVER_NAME: &str = "test_server";
+
+/// How many epochs a range may extend its lease past the current one for a
+/// committing transaction.
+pub(crate) const MAX_EPOCH_LEASE_EXTENSION: u64 = 1000;
+
+/// A coordinator in front of one range server serving `keyspace` as a single
+/// range from memory, with the epoch, the clock and the tx_state_store all
+/// under the control of the test.
+pub(crate) struct TestContext {
+    pub coordinator: Arc<Coordinator>,
+    pub epoch_source: Arc<EpochSource>,
+    pub clock: Arc<ManualClock>,
+    pub tx_state_store: Arc<InMemoryStorage>,
+    pub keyspace: Keyspace,
+    /// Another name for the same keyspace, whose writes are rejected.
+    pub read_only_keyspace: Keyspace,
+    keyspace_id: KeyspaceId,
+    server_address: SocketAddr,
+    proto_server_address: SocketAddr,
+    universe_address: SocketAddr,
+    config: Config,
+    // The epoch of the server, which the coordinator's follows.
+    epoch: u64,
+    epoch_supplier: Arc<EpochSupplier>,
+    range_assignment_oracle: Arc<ReassignableOracle>,
+    ranges: Vec<StaticRange>,
+    cancellation_token: CancellationToken,
+    server_runtime: tokio::runtime::Runtime,
+    client_runtime: tokio::runtime::Runtime,
+    _mock_warden: MockWarden,
+}
+
+fn zone() -> Zone {
+    Zone {
+        region: Region {
+            cloud: None,
+            name: "test-region".into(),
+        },
+        name: "a".into(),
+    }
+}
+
+fn get_config(warden_address: HostPort) -> Config {
+    let region_config = RegionConfig {
+        warden_address,
+        epoch_publishers: HashSet::new(),
+        cassandra_datacenter: None,
+    };
+    let mut config = Config {
+        range_server: RangeServerConfig {
+            range_maintenance_duration: Duration::from_secs(1),
+            proto_server_addr: HostPort::from_str("127.0.0.1:50054").unwrap(),
+            fast_network_addr: HostPort::from_str("127.0.0.1:50055").unwrap(),
+            preflight_epoch_advance_timeout: None,
+            max_pending_prepares_per_range: None,
+            max_epoch_lease_extension: Some(MAX_EPOCH_LEASE_EXTENSION),
+            handoff_drain_timeout: None,
+            lock_table: Default::default(),
+            conflict_stats: Default::default(),
+            write_stall: Default::default(),
+            checksum_verification: Default::default(),
+            decision_log: Default::default(),
+            expiry: Default::default(),
+            fast_network_transport: Default::default(),
+            mirroring: Default::default(),
+            transaction_recovery: Default::default(),
+        },
+        universe: UniverseConfig {
+            proto_server_addr: "127.0.0.1:123".parse().unwrap(),
+        },
+        frontend: FrontendConfig {
+            proto_server_addr: "127.0.0.1:124".parse().unwrap(),
+            fast_network_addr: HostPort::from_str("127.0.0.1:125").unwrap(),
+            transaction_overall_timeout: Duration::from_secs(10),
+            fast_network_transport: Default::default(),
+        },
+        cassandra: CassandraConfig {
+            cql_addr: "127.0.0.1:9042".parse().unwrap(),
+            consistency: Default::default(),
+        },
+        regions: HashMap::new(),
+        admin: Default::default(),
+        quic: Default::default(),
+        epoch: EpochConfig {
+            // Not used, the epoch comes from the test.
+            proto_server_addr: "127.0.0.1:1".parse().unwrap(),
+            epoch_duration: Duration::from_millis(10),
+        },
+    };
+    config.regions.insert(zone().region, region_config);
+    config
+}
+
+fn assignment(
+    server_address: SocketAddr,
+    keyspace_id: String,
+    ranges: Vec<StaticRange>,
+) -> StaticAssignment {
+    StaticAssignment {
+        hosts: HashMap::from([(
+            SERVER_NAME.to_string(),
+            StaticHost {
+                zone: zone(),
+                address: server_address,
+            },
+        )]),
+        keyspaces: HashMap::from([(keyspace_id, ranges)]),
+    }
+}
+
+fn polled_fast_network(
+    socket: UdpSocket,
+    runtime: &tokio::runtime::Runtime,
+) -> Arc<UdpFastNetwork> {
+    let fast_network = Arc::new(UdpFastNetwork::new(socket));
+    let fast_network_clone = fast_network.clone();
+    runtime.spawn(async move {
+        loop {
+            // Tests run in parallel, so back off while there is nothing to
+            // receive rather than spin and starve the other tests.
+            if fast_network_clone.poll() {
+                tokio::task::yield_now().await
+            } else {
+                tokio::time::sleep(Duration::from_millis(1)).await
+            }
+        }
+    });
+    fast_network
+}
+
+pub(crate) async fn setup() -> TestContext {
+    let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mock_warden = MockWarden::new();
+    let warden_address = mock_warden.start(None).await.unwrap();
+    let config = get_config(HostPort {
+        host: warden_address.ip().to_string(),
+        port: warden_address.port(),
+    });
+    let storage_context: StorageContext =
+        rangeserver::storage::in_memory::for_testing::init().await;
+    let range_id = FullRangeId {
+        keyspace_id: storage_context.keyspace_id,
+        range_id: storage_context.range_id,
+    };
+    let cancellation_token = CancellationToken::new();
+
+    let server_runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+    let server_fast_network = polled_fast_network(server_socket, &server_runtime);
+    let epoch_supplier = Arc::new(EpochSupplier::new());
+    let proto_server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proto_server_address = proto_server_listener.local_addr().unwrap();
+    {
+        let config = config.clone();
+        let host_info = HostInfo {
+            identity: HostIdentity {
+                name: SERVER_NAME.into(),
+                zone: zone(),
+            },
+            address: server_address,
+            warden_connection_epoch: 0,
+            labels: Default::default(),
+        };
+        let storage = storage_context.storage.clone();
+        let epoch_supplier = epoch_supplier.clone();
+        let cancellation_token = cancellation_token.clone();
+        server_runtime.spawn(async move {
+            let bg_runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+            let server = Server::<_>::new(
+                config,
+                host_info,
+                storage,
+                epoch_supplier,
+                bg_runtime.handle().clone(),
+            );
+            let res = Server::start(
+                server,
+                server_fast_network,
+                cancellation_token,
+                proto_server_listener,
+            )
+            .await
+            .unwrap();
+            res.await.unwrap()
+        });
+    }
+    while !mock_warden.is_connected(&SERVER_NAME.to_string()).await {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    mock_warden
+        .assign(&range_id, &SERVER_NAME.to_string())
+        .await;
+    // Give the range manager time to see the assignment and wait for the
+    // epoch to advance, which loads the range with a lease of a couple
+    // hundred epochs from epoch 1.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    epoch_supplier.set_epoch(1).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let client_runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+    let fast_network =
+        polled_fast_network(UdpSocket::bind("127.0.0.1:0").unwrap(), &client_runtime);
+    let ranges = vec![StaticRange {
+        range_id: range_id.range_id.to_string(),
+        lower_bound_inclusive: None,
+        upper_bound_exclusive: None,
+        host: SERVER_NAME.to_string(),
+    }];
+    let range_assignment_oracle = Arc::new(ReassignableOracle::new(
+        StaticRangeAssignmentOracle::new(assignment(
+            server_address,
+            range_id.keyspace_id.id.to_string(),
+            ranges.clone(),
+        ))
+        .unwrap(),
+    ));
+    let epoch_source = Arc::new(EpochSource::new());
+    epoch_source.set_epoch(1);
+    let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+    let tx_state_store = Arc::new(InMemoryStorage::new());
+    let keyspace = Keyspace {
+        namespace: "test".into(),
+        name: "test".into(),
+    };
+    let read_only_keyspace = Keyspace {
+        namespace: "test".into(),
+        name: "read_only".into(),
+    };
+    let universe_address = MockUniverse::start(vec![
+        KeyspaceInfo {
+            keyspace_id: range_id.keyspace_id.id.to_string(),
+            namespace: keyspace.namespace.clone(),
+            name: keyspace.name.clone(),
+            ..Default::default()
+        },
+        KeyspaceInfo {
+            keyspace_id: range_id.keyspace_id.id.to_string(),
+            namespace: read_only_keyspace.namespace.clone(),
+            name: read_only_keyspace.name.clone(),
+            read_only: true,
+            ..Default::default()
+        },
+    ])
+    .await;
+    let universe_client = UniverseClient::connect(format!("http://{}", universe_address))
+        .await
+        .unwrap();
+    let coordinator = Coordinator::builder(
+        config.clone(),
+        zone(),
+        range_assignment_oracle.clone(),
+        fast_network as Arc<dyn FastNetwork>,
+    )
+    .runtime(client_runtime.handle().clone())
+    .cancellation_token(cancellation_token.clone())
+    .universe_client(universe_client)
+    .epoch_reader(epoch_source.clone())
+    .tx_state_store(Arc::new(TxStateStoreClient::in_memory(
+        tx_state_store.clone(),
+    )))
+    .clock(clock.clone())
+    .build()
+    .await
+    .unwrap();
+    TestContext {
+        coordinator: Arc::new(coordinator),
+        epoch_source,
+        clock,
+        tx_state_store,
+        keyspace,
+        read_only_keyspace,
+        keyspace_id: range_id.keyspace_id,
+        server_address,
+        proto_server_address,
+        universe_address,
+        config,
+        epoch: 1,
+        epoch_supplier,
+        range_assignment_oracle,
+        ranges,
+        cancellation_token,
+        server_runtime,
+        client_runtime,
+        _mock_warden: mock_warden,
+    }
+}
+
+impl TestContext {
+    /// Starts a transaction that times out after `overall_timeout` on the
+    /// test's clock.
+    pub async fn start_transaction(&self, overall_timeout: Duration) -> Transaction {
+        let started = self.clock.now();
+        let transaction_info = Arc::new(TransactionInfo {
+            id: Uuid::new_v4(),
+            started,
+            first_started: started,
+            overall_timeout,
+            labels: Default::default(),
+            isolation: Default::default(),
+            snapshot_epoch: None,
+        });
+        self.coordinator.start_transaction(transaction_info).await
+    }
+
+    /// Builds another coordinator in front of the same server, with its own
+    /// fast network but the test's epoch, clock and tx_state_store.
+    pub async fn new_coordinator(&self) -> Coordinator {
+        let fast_network = polled_fast_network(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            &self.client_runtime,
+        );
+        let universe_client = UniverseClient::connect(format!("http://{}", self.universe_address))
+            .await
+            .unwrap();
+        Coordinator::builder(
+            self.config.clone(),
+            zone(),
+            self.range_assignment_oracle.clone(),
+            fast_network as Arc<dyn FastNetwork>,
+        )
+        .runtime(self.client_runtime.handle().clone())
+        .cancellation_token(self.cancellation_token.clone())
+        .universe_client(universe_client)
+        .epoch_reader(self.epoch_source.clone())
+        .tx_state_store(Arc::new(TxStateStoreClient::in_memory(
+            self.tx_state_store.clone(),
+        )))
+        .clock(self.clock.clone())
+        .build()
+        .await
+        .unwrap()
+    }
+
+    /// Advances the epoch, on both the server and the coordinator.
+    pub async fn advance_epoch(&mut self) {
+        self.epoch += 1;
+        self.epoch_supplier.set_epoch(self.epoch).await;
+        self.epoch_source.set_epoch(self.epoch);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    /// Splits the range holding `split_key` at it, and has the coordinator
+    /// route to the two new ranges from then on. The server loads the new
+    /// ranges once the epoch advances, so this also advances the epoch. Their
+    /// leases start at the new epoch, so transactions can commit on them
+    /// right away.
+    pub async fn split(&mut self, split_key: &str) {
+        let keyspace_id = self.keyspace_id.id.to_string();
+        let position = self
+            .ranges
+            .iter()
+            .position(|range| {
+                range
+                    .lower_bound_inclusive
+                    .as_deref()
+                    .is_none_or(|lower| lower <= split_key)
+                    && range
+                        .upper_bound_exclusive
+                        .as_deref()
+                        .is_none_or(|upper| split_key < upper)
+            })
+            .unwrap();
+        let mut client =
+            RangeServerClient::connect(format!("http://{}", self.proto_server_address))
+                .await
+                .unwrap();
+        let new_ranges = client
+            .split_range(SplitRangeRequest {
+                range: Some(RangeId {
+                    keyspace_id: keyspace_id.clone(),
+                    range_id: self.ranges[position].range_id.clone(),
+                }),
+                split_key: split_key.as_bytes().to_vec(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .new_ranges;
+        let bound = |bound: Option<Vec<u8>>| bound.map(|b| String::from_utf8(b).unwrap());
+        self.ranges.splice(
+            position..=position,
+            new_ranges.into_iter().map(|range| StaticRange {
+                range_id: range.range_id,
+                lower_bound_inclusive: bound(range.lower_bound_inclusive),
+                upper_bound_exclusive: bound(range.upper_bound_exclusive),
+                host: SERVER_NAME.to_string(),
+            }),
+        );
+        self.range_assignment_oracle.reassign(
+            StaticRangeAssignmentOracle::new(assignment(
+                self.server_address,
+                keyspace_id,
+                self.ranges.clone(),
+            ))
+            .unwrap(),
+        );
+        // Give the range manager time to see the new assignment, as in
+        // `setup`.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.advance_epoch().await;
+    }
+
+    pub async fn tear_down(self) {
+        self.cancellation_token.cancel();
+        self.server_runtime.shutdown_background();
+        self.client_runtime.shutdown_background();
+    }
+}
@@ -0,0 +1,176 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use common::keyspace::Keyspace;
+use proto::universe::{
+    get_keyspace_info_request::KeyspaceInfoSearchField,
+    universe_server::{Universe, UniverseServer},
+    CheckCrossNamespaceAccessRequest, CheckCrossNamespaceAccessResponse, CreateKeyspaceRequest,
+    CreateKeyspaceResponse, DeleteKeyspaceAliasRequest, DeleteKeyspaceAliasResponse,
+    GetKeyspaceInfoRequest, GetKeyspaceInfoResponse, GetNamespaceDefaultsRequest,
+    GetNamespaceDefaultsResponse, GetNamespacePolicyRequest, GetNamespacePolicyResponse,
+    KeyspaceInfo, ListKeyspaceAliasesRequest, ListKeyspaceAliasesResponse, ListKeyspacesRequest,
+    ListKeyspacesResponse, RenameKeyspaceRequest, RenameKeyspaceResponse,
+    SetKeyspaceColocationRequest, SetKeyspaceColocationResponse, SetKeyspacePlacementRequest,
+    SetKeyspacePlacementResponse, SetKeyspaceReadOnlyRequest, SetKeyspaceReadOnlyResponse,
+    SetKeyspaceValidationPolicyRequest, SetKeyspaceValidationPolicyResponse,
+    SetNamespaceDefaultsRequest, SetNamespaceDefaultsResponse, SetNamespacePolicyRequest,
+    SetNamespacePolicyResponse, SplitKeyRangeRequest, SplitKeyRangeResponse,
+};
+use tokio::net::TcpListener;
+use tonic::{transport::Server, Request, Response, Status};
+
+/// A universe that only knows a fixed set of keyspaces, and only answers
+/// lookups of them.
+pub(crate) struct MockUniverse {
+    keyspaces: Vec<KeyspaceInfo>,
+}
+
+impl MockUniverse {
+    /// Serves `keyspaces` on a local port and returns its address.
+    pub(crate) async fn start(keyspaces: Vec<KeyspaceInfo>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let universe = Arc::new(MockUniverse { keyspaces });
+        tokio::spawn(async move {
+            let _ = Server::builder()
+                .add_service(UniverseServer::from_arc(universe))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+                .await;
+        });
+        addr
+    }
+
+    fn find(&self, keyspace: &Keyspace) -> Option<KeyspaceInfo> {
+        self.keyspaces
+            .iter()
+            .find(|info| info.namespace == keyspace.namespace && info.name == keyspace.name)
+            .cloned()
+    }
+}
+
+#[tonic::async_trait]
+impl Universe for MockUniverse {
+    async fn create_keyspace(
+        &self,
+        _request: Request<CreateKeyspaceRequest>,
+    ) -> Result<Response<CreateKeyspaceResponse>, Status> {
+        Err(Status::unimplemented("create_keyspace"))
+    }
+
+    async fn list_keyspaces(
+        &self,
+        _request: Request<ListKeyspacesRequest>,
+    ) -> Result<Response<ListKeyspacesResponse>, Status> {
+        Err(Status::unimplemented("list_keyspaces"))
+    }
+
+    async fn get_keyspace_info(
+        &self,
+        request: Request<GetKeyspaceInfoRequest>,
+    ) -> Result<Response<GetKeyspaceInfoResponse>, Status> {
+        let keyspace_info = match request.into_inner().keyspace_info_search_field {
+            Some(KeyspaceInfoSearchField::Keyspace(keyspace)) => self.find(&Keyspace {
+                namespace: keyspace.namespace,
+                name: keyspace.name,
+            }),
+            _ => None,
+        };
+        match keyspace_info {
+            Some(keyspace_info) => Ok(Response::new(GetKeyspaceInfoResponse {
+                keyspace_info: Some(keyspace_info),
+            })),
+            None => Err(Status::not_found("keyspace not found")),
+        }
+    }
+
+    async fn set_keyspace_read_only(
+        &self,
+        _request: Request<SetKeyspaceReadOnlyRequest>,
+    ) -> Result<Response<SetKeyspaceReadOnlyResponse>, Status> {
+        Err(Status::unimplemented("set_keyspace_read_only"))
+    }
+
+    async fn set_keyspace_validation_policy(
+        &self,
+        _request: Request<SetKeyspaceValidationPolicyRequest>,
+    ) -> Result<Response<SetKeyspaceValidationPolicyResponse>, Status> {
+        Err(Status::unimplemented("set_keyspace_validation_policy"))
+    }
+
+    async fn set_keyspace_placement(
+        &self,
+        _request: Request<SetKeyspacePlacementRequest>,
+    ) -> Result<Response<SetKeyspacePlacementResponse>, Status> {
+        Err(Status::unimplemented("set_keyspace_placement"))
+    }
+
+    async fn set_keyspace_colocation(
+        &self,
+        _request: Request<SetKeyspaceColocationRequest>,
+    ) -> Result<Response<SetKeyspaceColocationResponse>, Status> {
+        Err(Status::unimplemented("set_keyspace_colocation"))
+    }
+
+    async fn split_key_range(
+        &self,
+        _request: Request<SplitKeyRangeRequest>,
+    ) -> Result<Response<SplitKeyRangeResponse>, Status> {
+        Err(Status::unimplemented("split_key_range"))
+    }
+
+    async fn set_namespace_policy(
+        &self,
+        _request: Request<SetNamespacePolicyRequest>,
+    ) -> Result<Response<SetNamespacePolicyResponse>, Status> {
+        Err(Status::unimplemented("set_namespace_policy"))
+    }
+
+    async fn get_namespace_policy(
+        &self,
+        _request: Request<GetNamespacePolicyRequest>,
+    ) -> Result<Response<GetNamespacePolicyResponse>, Status> {
+        Err(Status::unimplemented("get_namespace_policy"))
+    }
+
+    async fn set_namespace_defaults(
+        &self,
+        _request: Request<SetNamespaceDefaultsRequest>,
+    ) -> Result<Response<SetNamespaceDefaultsResponse>, Status> {
+        Err(Status::unimplemented("set_namespace_defaults"))
+    }
+
+    async fn get_namespace_defaults(
+        &self,
+        _request: Request<GetNamespaceDefaultsRequest>,
+    ) -> Result<Response<GetNamespaceDefaultsResponse>, Status> {
+        Err(Status::unimplemented("get_namespace_defaults"))
+    }
+
+    async fn check_cross_namespace_access(
+        &self,
+        _request: Request<CheckCrossNamespaceAccessRequest>,
+    ) -> Result<Response<CheckCrossNamespaceAccessResponse>, Status> {
+        Err(Status::unimplemented("check_cross_namespace_access"))
+    }
+
+    async fn rename_keyspace(
+        &self,
+        _request: Request<RenameKeyspaceRequest>,
+    ) -> Result<Response<RenameKeyspaceResponse>, Status> {
+        Err(Status::unimplemented("rename_keyspace"))
+    }
+
+    async fn list_keyspace_aliases(
+        &self,
+        _request: Request<ListKeyspaceAliasesRequest>,
+    ) -> Result<Response<ListKeyspaceAliasesResponse>, Status> {
+        Err(Status::unimplemented("list_keyspace_aliases"))
+    }
+
+    async fn delete_keyspace_alias(
+        &self,
+        _request: Request<DeleteKeyspaceAliasRequest>,
+    ) -> Result<Response<DeleteKeyspaceAliasResponse>, Status> {
+        Err(Status::unimplemented("delete_keyspace_alias"))
+    }
+}
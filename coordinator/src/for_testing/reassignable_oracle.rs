@@ -0,0 +1,64 @@
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use common::{
+    full_range_id::FullRangeId,
+    host_info::HostInfo,
+    key_range::KeyRange,
+    keyspace_id::KeyspaceId,
+    membership::{
+        range_assignment_oracle::RangeAssignmentOracle,
+        static_range_assignment_oracle::StaticRangeAssignmentOracle,
+    },
+};
+
+/// A static assignment the test can replace, e.g. once it split a range.
+pub(crate) struct ReassignableOracle {
+    current: RwLock<Arc<StaticRangeAssignmentOracle>>,
+}
+
+impl ReassignableOracle {
+    pub(crate) fn new(oracle: StaticRangeAssignmentOracle) -> ReassignableOracle {
+        ReassignableOracle {
+            current: RwLock::new(Arc::new(oracle)),
+        }
+    }
+
+    pub(crate) fn reassign(&self, oracle: StaticRangeAssignmentOracle) {
+        *self.current.write().unwrap() = Arc::new(oracle);
+    }
+
+    fn current(&self) -> Arc<StaticRangeAssignmentOracle> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl RangeAssignmentOracle for ReassignableOracle {
+    async fn full_range_id_of_key(
+        &self,
+        keyspace_id: KeyspaceId,
+        key: Bytes,
+    ) -> Option<FullRangeId> {
+        self.current().full_range_id_of_key(keyspace_id, key).await
+    }
+
+    async fn full_range_ids_of_key_range(
+        &self,
+        keyspace_id: KeyspaceId,
+        key_range: &KeyRange,
+    ) -> Option<Vec<FullRangeId>> {
+        self.current()
+            .full_range_ids_of_key_range(keyspace_id, key_range)
+            .await
+    }
+
+    async fn host_of_range(&self, range_id: &FullRangeId) -> Option<HostInfo> {
+        self.current().host_of_range(range_id).await
+    }
+
+    fn maybe_refresh_host_of_range(&self, range_id: &FullRangeId) {
+        self.current().maybe_refresh_host_of_range(range_id)
+    }
+}
@@ -0,0 +1,40 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use common::keyspace::Keyspace;
+use uuid::Uuid;
+
+use crate::error::Error;
+
+/// Hooks that application performance monitoring (APM) agents, or anything
+/// else timing requests, can implement to see the operations of every
+/// transaction started by a coordinator. See
+/// `CoordinatorBuilder::instrumentation`.
+///
+/// The hooks are called inline with the operations, so they should only
+/// record what they are given and return quickly.
+pub trait Instrumentation: Send + Sync {
+    /// Called when an operation of a transaction starts.
+    fn on_request_start(&self, _request: &RequestInfo) {}
+
+    /// Called when that operation finishes, successfully or not.
+    fn on_request_end(&self, _request: &RequestInfo, _outcome: &RequestOutcome) {}
+}
+
+/// An operation of a transaction.
+pub struct RequestInfo<'a> {
+    /// The `Transaction` method called, e.g. "get", "put" or "commit".
+    pub operation: &'static str,
+    pub transaction_id: Uuid,
+    /// The labels the transaction was started with.
+    pub labels: &'a BTreeMap<String, String>,
+    /// The keyspace the operation is about, None for operations about the
+    /// whole transaction.
+    pub keyspace: Option<&'a Keyspace>,
+}
+
+/// How an operation of a transaction went.
+pub struct RequestOutcome<'a> {
+    pub duration: Duration,
+    /// None if the operation succeeded.
+    pub error: Option<&'a Error>,
+}
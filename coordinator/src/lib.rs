@@ -1,4 +1,20 @@
+pub mod backpressure;
+mod circuit_breaker;
+pub mod config_store;
 pub mod coordinator;
+mod epoch_coalescer;
 pub mod error;
+pub mod external;
+#[cfg(test)]
+mod for_testing;
+pub mod instrumentation;
+pub mod lifecycle_log;
+pub mod metrics;
+pub mod outcome;
+mod participants;
+pub mod pool;
 mod rangeclient;
+pub mod saga;
+pub mod sequence;
+mod tasks;
 pub mod transaction;
@@ -0,0 +1,161 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use common::{keyspace::Keyspace, transaction_info::TransactionInfo};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Opt-in logger that records the lifecycle (begin, operations, commit/abort)
+/// of a sample of transactions as one structured JSON object per line. It is
+/// meant to be shipped to a log pipeline by deployments that do not run a
+/// tracing backend.
+///
+/// Transactions that span several namespaces are also audited here, each
+/// with a record of its own whether sampled or not.
+pub struct LifecycleLogger {
+    // Fraction of transactions that get logged, in [0.0, 1.0].
+    sample_rate: f64,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl LifecycleLogger {
+    pub fn new(sample_rate: f64, writer: Box<dyn Write + Send>) -> LifecycleLogger {
+        LifecycleLogger {
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Logs sampled transactions to stdout.
+    pub fn to_stdout(sample_rate: f64) -> LifecycleLogger {
+        Self::new(sample_rate, Box::new(std::io::stdout()))
+    }
+
+    fn is_sampled(&self, id: Uuid) -> bool {
+        // Transaction ids are random, so using them for sampling gives a
+        // uniform sample while making the decision reproducible from the id.
+        const BUCKETS: u128 = 1_000_000;
+        ((id.as_u128() % BUCKETS) as f64) < self.sample_rate * BUCKETS as f64
+    }
+
+    /// Returns a timeline to record the transaction's lifecycle into, or None
+    /// if the transaction was not sampled.
+    pub(crate) fn begin(
+        self: &Arc<Self>,
+        transaction_info: &TransactionInfo,
+    ) -> Option<TransactionTimeline> {
+        if !self.is_sampled(transaction_info.id) {
+            return None;
+        }
+        Some(TransactionTimeline {
+            logger: self.clone(),
+            start: Instant::now(),
+            record: TimelineRecord {
+                transaction_id: transaction_info.id.to_string(),
+                started: transaction_info.started.to_rfc3339(),
+                labels: transaction_info.labels.clone(),
+                outcome: None,
+                events: Vec::new(),
+            },
+        })
+    }
+
+    /// Records a transaction that touched several namespaces, or that was
+    /// denied from doing so.
+    pub(crate) fn audit_cross_namespace(
+        &self,
+        transaction_info: &TransactionInfo,
+        namespaces: &BTreeSet<String>,
+        outcome: &'static str,
+    ) {
+        self.write(&CrossNamespaceRecord {
+            audit: "cross_namespace",
+            transaction_id: transaction_info.id.to_string(),
+            started: transaction_info.started.to_rfc3339(),
+            labels: transaction_info.labels.clone(),
+            namespaces: namespaces.iter().cloned().collect(),
+            outcome,
+        });
+    }
+
+    fn write(&self, record: &impl Serialize) {
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let mut writer = self.writer.lock().unwrap();
+        // Logging is best-effort, never fail the transaction because of it.
+        let _ = writeln!(writer, "{}", line);
+    }
+}
+
+#[derive(Serialize)]
+struct TimelineEvent {
+    op: &'static str,
+    // Offset from the beginning of the transaction.
+    at_us: u128,
+    duration_us: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TimelineRecord {
+    transaction_id: String,
+    started: String,
+    labels: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<&'static str>,
+    events: Vec<TimelineEvent>,
+}
+
+#[derive(Serialize)]
+struct CrossNamespaceRecord {
+    // Tells audit records apart from timelines in the same log.
+    audit: &'static str,
+    transaction_id: String,
+    started: String,
+    labels: BTreeMap<String, String>,
+    namespaces: Vec<String>,
+    // "committed", "aborted" or "denied".
+    outcome: &'static str,
+}
+
+/// Collects the events of a single sampled transaction and writes them out
+/// once the transaction finishes.
+pub(crate) struct TransactionTimeline {
+    logger: Arc<LifecycleLogger>,
+    start: Instant,
+    record: TimelineRecord,
+}
+
+impl TransactionTimeline {
+    /// Records an operation that started at `op_start`.
+    pub(crate) fn record<T, E: std::fmt::Debug>(
+        &mut self,
+        op: &'static str,
+        keyspace: Option<&Keyspace>,
+        op_start: Instant,
+        result: &Result<T, E>,
+    ) {
+        self.record.events.push(TimelineEvent {
+            op,
+            at_us: op_start.duration_since(self.start).as_micros(),
+            duration_us: op_start.elapsed().as_micros(),
+            keyspace: keyspace.map(|k| format!("{}.{}", k.namespace, k.name)),
+            error: result.as_ref().err().map(|e| format!("{:?}", e)),
+        });
+    }
+
+    /// Writes out the timeline with the transaction's final outcome.
+    pub(crate) fn finish(mut self, outcome: &'static str) {
+        self.record.outcome = Some(outcome);
+        self.logger.write(&self.record);
+    }
+}
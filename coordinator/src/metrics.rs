@@ -0,0 +1,170 @@
+//! Histograms of how commits fan out to participant ranges: how many ranges
+//! they prepare on, how much each prepare carries, and how long the slowest
+//! of them takes. Single-range commits are the ones colocating keys or a
+//! one-phase commit would speed up, and a slowest participant far behind the
+//! others points at a straggling range rather than at the commit protocol.
+//! Histograms are kept in memory from when the coordinator was built.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Upper bounds of the buckets, inclusive. Larger values go to an overflow
+// bucket.
+const PARTICIPANT_BOUNDS: &[u64] = &[1, 2, 3, 4, 6, 8, 12, 16, 32, 64];
+const PAYLOAD_BYTES_BOUNDS: &[u64] = &[
+    0,
+    64,
+    256,
+    1 << 10,
+    4 << 10,
+    16 << 10,
+    64 << 10,
+    256 << 10,
+    1 << 20,
+    4 << 20,
+    16 << 20,
+];
+const LATENCY_US_BOUNDS: &[u64] = &[
+    250, 500, 1_000, 2_000, 4_000, 8_000, 16_000, 32_000, 64_000, 128_000, 256_000, 512_000,
+    1_024_000, 2_048_000, 4_096_000, 8_192_000,
+];
+
+/// A point-in-time copy of a histogram.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistogramSnapshot {
+    /// Inclusive upper bound of each bucket but the last, which counts the
+    /// values above all of them.
+    pub bounds: Vec<u64>,
+    /// Values counted in each bucket, one more than there are bounds.
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: u64,
+}
+
+impl HistogramSnapshot {
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile, for `q` in [0, 1].
+    /// None if nothing was recorded, or if it falls in the overflow bucket.
+    pub fn quantile_bound(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return self.bounds.get(i).copied();
+            }
+        }
+        None
+    }
+}
+
+struct Histogram {
+    bounds: &'static [u64],
+    counts: Vec<AtomicU64>,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, value: u64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        HistogramSnapshot {
+            bounds: self.bounds.to_vec(),
+            count: counts.iter().sum(),
+            counts,
+            sum: self.sum.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// How the commits of a coordinator fanned out, see `Coordinator::fan_out`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FanOutMetrics {
+    /// Participant ranges of each commit that got to prepare, read-only ones
+    /// included.
+    pub participants: HistogramSnapshot,
+    /// Bytes of the keys and values each participant's prepare carried,
+    /// without framing.
+    pub range_payload_bytes: HistogramSnapshot,
+    /// Microseconds the slowest participant took to prepare, for commits
+    /// that prepared on all of them. Retries of throttled prepares count.
+    pub slowest_prepare_us: HistogramSnapshot,
+    /// Microseconds between the slowest participant and the next slowest
+    /// preparing, for those commits with several participants. Most of the
+    /// slowest prepare being a gap means a single range held the commit up.
+    pub slowest_prepare_gap_us: HistogramSnapshot,
+}
+
+pub(crate) struct FanOutRecorder {
+    participants: Histogram,
+    range_payload_bytes: Histogram,
+    slowest_prepare_us: Histogram,
+    slowest_prepare_gap_us: Histogram,
+}
+
+impl FanOutRecorder {
+    pub fn new() -> FanOutRecorder {
+        FanOutRecorder {
+            participants: Histogram::new(PARTICIPANT_BOUNDS),
+            range_payload_bytes: Histogram::new(PAYLOAD_BYTES_BOUNDS),
+            slowest_prepare_us: Histogram::new(LATENCY_US_BOUNDS),
+            slowest_prepare_gap_us: Histogram::new(LATENCY_US_BOUNDS),
+        }
+    }
+
+    /// Counts a commit about to prepare on ranges carrying `payload_bytes`
+    /// each.
+    pub fn record_prepare(&self, payload_bytes: impl ExactSizeIterator<Item = usize>) {
+        self.participants.record(payload_bytes.len() as u64);
+        for bytes in payload_bytes {
+            self.range_payload_bytes.record(bytes as u64);
+        }
+    }
+
+    /// Counts how long the participants of a commit that prepared on all of
+    /// them took, in any order.
+    pub fn record_prepared(&self, mut latencies: Vec<Duration>) {
+        latencies.sort_unstable();
+        let mut slowest_first = latencies.iter().rev();
+        let Some(slowest) = slowest_first.next() else {
+            return;
+        };
+        self.slowest_prepare_us.record(slowest.as_micros() as u64);
+        if let Some(next) = slowest_first.next() {
+            self.slowest_prepare_gap_us
+                .record((*slowest - *next).as_micros() as u64);
+        }
+    }
+
+    pub fn metrics(&self) -> FanOutMetrics {
+        FanOutMetrics {
+            participants: self.participants.snapshot(),
+            range_payload_bytes: self.range_payload_bytes.snapshot(),
+            slowest_prepare_us: self.slowest_prepare_us.snapshot(),
+            slowest_prepare_gap_us: self.slowest_prepare_gap_us.snapshot(),
+        }
+    }
+}
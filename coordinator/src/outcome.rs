@@ -0,0 +1,105 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+// Number of outcomes buffered per subscriber before it starts lagging.
+const SUBSCRIPTION_BUFFER_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Committed { epoch: u64 },
+    Aborted,
+}
+
+/// The final decision for a transaction along with enough metadata about it
+/// for subscribers to decide whether they care.
+#[derive(Clone, Debug)]
+pub struct TransactionOutcome {
+    pub id: Uuid,
+    /// Namespaces of all keyspaces the transaction accessed.
+    pub namespaces: HashSet<String>,
+    pub labels: BTreeMap<String, String>,
+    pub decision: Decision,
+}
+
+/// Selects which outcomes a subscriber gets notified about. An empty filter
+/// matches every transaction.
+#[derive(Clone, Debug, Default)]
+pub struct OutcomeFilter {
+    /// If set, only transactions that accessed at least one of these
+    /// namespaces match.
+    pub namespaces: Option<HashSet<String>>,
+    /// Only transactions carrying all of these labels (with equal values)
+    /// match.
+    pub labels: BTreeMap<String, String>,
+    /// If true, aborted transactions never match.
+    pub committed_only: bool,
+}
+
+impl OutcomeFilter {
+    pub fn matches(&self, outcome: &TransactionOutcome) -> bool {
+        if self.committed_only && outcome.decision == Decision::Aborted {
+            return false;
+        }
+        if !self
+            .labels
+            .iter()
+            .all(|(k, v)| outcome.labels.get(k) == Some(v))
+        {
+            return false;
+        }
+        match &self.namespaces {
+            None => true,
+            Some(namespaces) => !namespaces.is_disjoint(&outcome.namespaces),
+        }
+    }
+}
+
+pub struct OutcomeSubscription {
+    filter: OutcomeFilter,
+    receiver: broadcast::Receiver<Arc<TransactionOutcome>>,
+}
+
+impl OutcomeSubscription {
+    /// Waits for the next outcome matching the subscription's filter.
+    ///
+    /// Returns `RecvError::Lagged` if the subscriber fell behind and some
+    /// outcomes were dropped, in which case the subscriber must reconcile using
+    /// other means (e.g. the tx_state_store) before continuing.
+    pub async fn next(&mut self) -> Result<Arc<TransactionOutcome>, broadcast::error::RecvError> {
+        loop {
+            let outcome = self.receiver.recv().await?;
+            if self.filter.matches(&outcome) {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+/// Fans out decisions made by a coordinator to its subscribers.
+pub(crate) struct OutcomeNotifier {
+    sender: broadcast::Sender<Arc<TransactionOutcome>>,
+}
+
+impl OutcomeNotifier {
+    pub(crate) fn new() -> OutcomeNotifier {
+        let (sender, _) = broadcast::channel(SUBSCRIPTION_BUFFER_SIZE);
+        OutcomeNotifier { sender }
+    }
+
+    pub(crate) fn subscribe(&self, filter: OutcomeFilter) -> OutcomeSubscription {
+        OutcomeSubscription {
+            filter,
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    pub(crate) fn notify(&self, outcome: TransactionOutcome) {
+        // Not having any subscribers is fine.
+        let _ = self.sender.send(Arc::new(outcome));
+    }
+}
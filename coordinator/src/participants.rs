@@ -0,0 +1,81 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Mutex,
+};
+
+use common::{full_range_id::FullRangeId, transaction_info::TransactionInfo};
+use uuid::Uuid;
+
+/// What an admin operation needs to know about an in-flight transaction to
+/// finish it on its behalf: the ranges to notify, and what to publish its
+/// outcome with.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Participants {
+    pub(crate) ranges: HashSet<FullRangeId>,
+    pub(crate) namespaces: HashSet<String>,
+    pub(crate) labels: BTreeMap<String, String>,
+}
+
+/// Tracks the participant ranges of the transactions currently in-flight on a
+/// coordinator, so that they can be notified by admin operations such as a
+/// forced abort.
+pub(crate) struct ParticipantRegistry {
+    participants: Mutex<HashMap<Uuid, Participants>>,
+}
+
+impl ParticipantRegistry {
+    pub(crate) fn new() -> ParticipantRegistry {
+        ParticipantRegistry {
+            participants: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn add(&self, transaction: &TransactionInfo, range_id: FullRangeId) {
+        let mut participants = self.participants.lock().unwrap();
+        Self::entry(&mut participants, transaction)
+            .ranges
+            .insert(range_id);
+    }
+
+    /// Forgets a range the transaction turned out to have nothing to prepare
+    /// on.
+    pub(crate) fn remove_range(&self, transaction_id: Uuid, range_id: &FullRangeId) {
+        let mut participants = self.participants.lock().unwrap();
+        if let Some(participants) = participants.get_mut(&transaction_id) {
+            participants.ranges.remove(range_id);
+        }
+    }
+
+    /// Records that the transaction reached a keyspace of `namespace`.
+    pub(crate) fn add_namespace(&self, transaction: &TransactionInfo, namespace: &str) {
+        let mut participants = self.participants.lock().unwrap();
+        Self::entry(&mut participants, transaction)
+            .namespaces
+            .insert(namespace.to_string());
+    }
+
+    fn entry<'a>(
+        participants: &'a mut HashMap<Uuid, Participants>,
+        transaction: &TransactionInfo,
+    ) -> &'a mut Participants {
+        participants
+            .entry(transaction.id)
+            .or_insert_with(|| Participants {
+                labels: transaction.labels.clone(),
+                ..Default::default()
+            })
+    }
+
+    pub(crate) fn remove(&self, transaction_id: Uuid) {
+        let mut participants = self.participants.lock().unwrap();
+        participants.remove(&transaction_id);
+    }
+
+    pub(crate) fn get(&self, transaction_id: Uuid) -> Participants {
+        let participants = self.participants.lock().unwrap();
+        participants
+            .get(&transaction_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use common::transaction_info::TransactionInfo;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    backpressure::{Backpressure, BackpressureSubscription},
+    coordinator::Coordinator,
+    transaction::Transaction,
+};
+
+struct Counters {
+    admission: Arc<Semaphore>,
+    max_in_flight: usize,
+    waiting: AtomicUsize,
+    // Transactions in flight on each member.
+    per_member: Vec<AtomicUsize>,
+    // Notified whenever one of the counts changes.
+    changes: watch::Sender<()>,
+}
+
+impl Counters {
+    fn saturation(&self) -> PoolSaturation {
+        PoolSaturation {
+            in_flight: self.max_in_flight - self.admission.available_permits(),
+            max_in_flight: self.max_in_flight,
+            waiting: self.waiting.load(Ordering::Relaxed),
+            per_coordinator: self
+                .per_member
+                .iter()
+                .map(|n| n.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+}
+
+/// Spreads the transactions of a process over several coordinators, so that
+/// a single process can drive many more concurrent transactions than one
+/// coordinator's runtime and range server connections can.
+///
+/// Each coordinator must be built with its own fast network, since a fast
+/// network only delivers the responses of a range server to one client, and
+/// should run its tasks on its own runtime. New transactions go to the member
+/// with the fewest in flight, so a member slowed down by e.g. a busy runtime
+/// gets fewer of them. A transaction stays on the member it started on until
+/// it finishes: work already started is not moved to idle members.
+///
+/// At most `max_in_flight` transactions run at once across the pool. Starting
+/// more waits for one to finish, in the order the callers started waiting.
+pub struct CoordinatorPool {
+    members: Arc<[Coordinator]>,
+    counters: Arc<Counters>,
+    // Where the search for the least loaded member starts, rotated so that
+    // ties are spread over the members.
+    next: AtomicUsize,
+}
+
+/// How busy a `CoordinatorPool` is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolSaturation {
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+    /// Callers waiting for a transaction to finish so they can start theirs.
+    pub waiting: usize,
+    /// Transactions in flight on each member, in the order the members were
+    /// given to the pool.
+    pub per_coordinator: Vec<usize>,
+}
+
+impl PoolSaturation {
+    /// Fraction of `max_in_flight` in use, 1.0 when full.
+    pub fn utilization(&self) -> f64 {
+        self.in_flight as f64 / self.max_in_flight as f64
+    }
+}
+
+/// A transaction started by a `CoordinatorPool`. It counts against the pool
+/// until dropped, whether or not it was committed or aborted.
+pub struct PooledTransaction {
+    transaction: Transaction,
+    _slot: Slot,
+}
+
+// Counts a caller as waiting for room in the pool until dropped, so that a
+// caller that gives up on waiting stops counting.
+struct Waiting<'a>(&'a Counters);
+
+impl<'a> Waiting<'a> {
+    fn new(counters: &'a Counters) -> Self {
+        counters.waiting.fetch_add(1, Ordering::Relaxed);
+        counters.changes.send_replace(());
+        Waiting(counters)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.waiting.fetch_sub(1, Ordering::Relaxed);
+        self.0.changes.send_replace(());
+    }
+}
+
+// A transaction's room in the pool and on its member, given back when
+// dropped, including when the caller gives up while the member is still
+// starting the transaction.
+struct Slot {
+    counters: Arc<Counters>,
+    member: usize,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Slot {
+    fn new(counters: Arc<Counters>, member: usize, permit: OwnedSemaphorePermit) -> Self {
+        counters.per_member[member].fetch_add(1, Ordering::Relaxed);
+        counters.changes.send_replace(());
+        Slot {
+            counters,
+            member,
+            permit: Some(permit),
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.counters.per_member[self.member].fetch_sub(1, Ordering::Relaxed);
+        // Released before notifying, so that subscribers see the room it
+        // leaves in the pool.
+        drop(self.permit.take());
+        self.counters.changes.send_replace(());
+    }
+}
+
+impl Deref for PooledTransaction {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.transaction
+    }
+}
+
+impl DerefMut for PooledTransaction {
+    fn deref_mut(&mut self) -> &mut Transaction {
+        &mut self.transaction
+    }
+}
+
+impl CoordinatorPool {
+    /// Fails if `coordinators` is empty or `max_in_flight` is zero.
+    pub fn new(
+        coordinators: Vec<Coordinator>,
+        max_in_flight: usize,
+    ) -> Result<CoordinatorPool, String> {
+        if coordinators.is_empty() {
+            return Err("a coordinator pool needs at least one coordinator".to_string());
+        }
+        if max_in_flight == 0 {
+            return Err("max_in_flight must be positive".to_string());
+        }
+        let counters = Counters {
+            admission: Arc::new(Semaphore::new(max_in_flight)),
+            max_in_flight,
+            waiting: AtomicUsize::new(0),
+            per_member: coordinators.iter().map(|_| AtomicUsize::new(0)).collect(),
+            changes: watch::Sender::new(()),
+        };
+        Ok(CoordinatorPool {
+            members: coordinators.into(),
+            counters: Arc::new(counters),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Starts a transaction on the least loaded coordinator, first waiting
+    /// for room in the pool if it is full.
+    pub async fn start_transaction(
+        &self,
+        transaction_info: Arc<TransactionInfo>,
+    ) -> PooledTransaction {
+        let waiting = Waiting::new(&self.counters);
+        // The semaphore is never closed.
+        let permit = self
+            .counters
+            .admission
+            .clone()
+            .acquire_owned()
+            .await
+            .unwrap();
+        drop(waiting);
+
+        let member = self.least_loaded();
+        let slot = Slot::new(self.counters.clone(), member, permit);
+        let transaction = self.members[member]
+            .start_transaction(transaction_info)
+            .await;
+        PooledTransaction {
+            transaction,
+            _slot: slot,
+        }
+    }
+
+    pub fn saturation(&self) -> PoolSaturation {
+        self.counters.saturation()
+    }
+
+    /// The backpressure of the whole pool: its saturation, and the ranges
+    /// any of its coordinators found overloaded.
+    pub fn backpressure(&self) -> Backpressure {
+        read_backpressure(&self.counters, &self.members)
+    }
+
+    /// Subscribes to changes of the pool's backpressure level and of the
+    /// ranges its coordinators found overloaded.
+    pub fn subscribe_to_backpressure(&self) -> BackpressureSubscription {
+        let counters = self.counters.clone();
+        let members = self.members.clone();
+        let trackers = self
+            .members
+            .iter()
+            .map(|c| c.overload_tracker().clone())
+            .collect();
+        BackpressureSubscription::new(
+            Box::new(move || read_backpressure(&counters, &members)),
+            trackers,
+            vec![self.counters.changes.subscribe()],
+            self.members[0].clock().clone(),
+        )
+    }
+
+    /// The coordinators of the pool, e.g. to subscribe to the outcomes of
+    /// the transactions each of them runs.
+    pub fn coordinators(&self) -> impl Iterator<Item = &Coordinator> {
+        self.members.iter()
+    }
+
+    // Returns the index of the member to start the next transaction on.
+    fn least_loaded(&self) -> usize {
+        let n = self.members.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % n;
+        (start..n)
+            .chain(0..start)
+            .min_by_key(|i| self.counters.per_member[*i].load(Ordering::Relaxed))
+            .unwrap()
+    }
+}
+
+fn read_backpressure(counters: &Counters, members: &[Coordinator]) -> Backpressure {
+    let mut overloaded_ranges = Vec::new();
+    let mut in_flight_tasks = 0;
+    for coordinator in members {
+        overloaded_ranges.extend(coordinator.overload_tracker().overloaded_ranges());
+        in_flight_tasks += coordinator.in_flight_tasks();
+    }
+    Backpressure::new(
+        overloaded_ranges,
+        in_flight_tasks,
+        Some(counters.saturation()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::for_testing;
+    use common::clock::Clock;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    async fn pool(context: &for_testing::TestContext, max_in_flight: usize) -> CoordinatorPool {
+        let members = vec![
+            context.new_coordinator().await,
+            context.new_coordinator().await,
+        ];
+        CoordinatorPool::new(members, max_in_flight).unwrap()
+    }
+
+    fn transaction_info(context: &for_testing::TestContext) -> Arc<TransactionInfo> {
+        let started = context.clock.now();
+        Arc::new(TransactionInfo {
+            id: Uuid::new_v4(),
+            started,
+            first_started: started,
+            overall_timeout: TIMEOUT,
+            labels: Default::default(),
+            isolation: Default::default(),
+            snapshot_epoch: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn transactions_start_on_the_least_loaded_member() {
+        let context = for_testing::setup().await;
+        let pool = pool(&context, 4).await;
+        let first = pool.start_transaction(transaction_info(&context)).await;
+        let second = pool.start_transaction(transaction_info(&context)).await;
+        let _third = pool.start_transaction(transaction_info(&context)).await;
+        assert_eq!(pool.saturation().per_coordinator, vec![2, 1]);
+        drop(second);
+        assert_eq!(pool.saturation().per_coordinator, vec![2, 0]);
+        let _fourth = pool.start_transaction(transaction_info(&context)).await;
+        // The search for the least loaded member starts at the first one
+        // this time, and goes past it.
+        let _fifth = pool.start_transaction(transaction_info(&context)).await;
+        assert_eq!(
+            pool.saturation(),
+            PoolSaturation {
+                in_flight: 4,
+                max_in_flight: 4,
+                waiting: 0,
+                per_coordinator: vec![2, 2],
+            }
+        );
+        drop(first);
+        assert_eq!(pool.saturation().per_coordinator, vec![1, 2]);
+        assert_eq!(pool.saturation().in_flight, 3);
+        context.tear_down().await;
+    }
+
+    #[tokio::test]
+    async fn full_pools_admit_waiters_once_a_transaction_finishes() {
+        let context = for_testing::setup().await;
+        let pool = Arc::new(pool(&context, 1).await);
+        let first = pool.start_transaction(transaction_info(&context)).await;
+        let waiter = {
+            let pool = pool.clone();
+            let transaction_info = transaction_info(&context);
+            tokio::spawn(async move {
+                let _tx = pool.start_transaction(transaction_info).await;
+            })
+        };
+        while pool.saturation().waiting == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(pool.saturation().in_flight, 1);
+        assert!(!waiter.is_finished());
+        drop(first);
+        waiter.await.unwrap();
+        assert_eq!(
+            pool.saturation(),
+            PoolSaturation {
+                in_flight: 0,
+                max_in_flight: 1,
+                waiting: 0,
+                per_coordinator: vec![0, 0],
+            }
+        );
+        context.tear_down().await;
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiters_stop_counting_as_waiting() {
+        let context = for_testing::setup().await;
+        let pool = pool(&context, 1).await;
+        let _first = pool.start_transaction(transaction_info(&context)).await;
+        let waited = tokio::time::timeout(
+            Duration::from_millis(10),
+            pool.start_transaction(transaction_info(&context)),
+        )
+        .await;
+        assert!(waited.is_err());
+        assert_eq!(
+            pool.saturation(),
+            PoolSaturation {
+                in_flight: 1,
+                max_in_flight: 1,
+                waiting: 0,
+                per_coordinator: vec![1, 0],
+            }
+        );
+        context.tear_down().await;
+    }
+}
@@ -1,42 +1,92 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use bytes::Bytes;
 use common::{
-    full_range_id::FullRangeId, host_info::HostIdentity,
-    membership::range_assignment_oracle::RangeAssignmentOracle, network::fast_network::FastNetwork,
-    record::Record, transaction_info::TransactionInfo,
+    clock::Clock,
+    full_range_id::FullRangeId,
+    host_info::HostIdentity,
+    key_range::KeyRange,
+    membership::range_assignment_oracle::RangeAssignmentOracle,
+    network::fast_network::FastNetwork,
+    record::{Condition, Increment, Record},
+    region::Zone,
+    transaction_info::TransactionInfo,
+};
+use rangeclient::client::{
+    ConflictStats, Error, GetResult, PrepareOk, RangeClient as Client, RetryPolicy, ScanResult,
 };
-use rangeclient::client::{Error, GetResult, PrepareOk, RangeClient as Client};
 use tokio::sync::RwLock;
 use tokio_util::sync::CancellationToken;
 
+use crate::{
+    backpressure::OverloadTracker, circuit_breaker::CircuitBreakers, coordinator::ZoneTraffic,
+};
+
 /// RangeClient abstracts away the individual rangeservers and allows users
 /// to reach any range just by using the range id.
 pub struct RangeClient {
+    zone: Zone,
     range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
     range_clients: RwLock<HashMap<HostIdentity, Arc<Client>>>,
     fast_network: Arc<dyn FastNetwork>,
     runtime: tokio::runtime::Handle,
     cancellation_token: CancellationToken,
+    overloads: Arc<OverloadTracker>,
+    circuit_breakers: CircuitBreakers,
+    same_zone_requests: AtomicU64,
+    cross_zone_requests: AtomicU64,
 }
 
 // public interface
 impl RangeClient {
     pub fn new(
+        zone: Zone,
         range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
         fast_network: Arc<dyn FastNetwork>,
         runtime: tokio::runtime::Handle,
         cancellation_token: CancellationToken,
+        overloads: Arc<OverloadTracker>,
+        clock: Arc<dyn Clock>,
     ) -> RangeClient {
         RangeClient {
+            zone,
             range_assignment_oracle,
             fast_network,
             range_clients: RwLock::new(HashMap::new()),
             runtime,
             cancellation_token,
+            overloads,
+            circuit_breakers: CircuitBreakers::new(clock),
+            same_zone_requests: AtomicU64::new(0),
+            cross_zone_requests: AtomicU64::new(0),
         }
     }
 
+    pub fn zone_traffic(&self) -> ZoneTraffic {
+        ZoneTraffic {
+            same_zone_requests: self.same_zone_requests.load(Ordering::Relaxed),
+            cross_zone_requests: self.cross_zone_requests.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn open_circuits(&self) -> Vec<HostIdentity> {
+        self.circuit_breakers.open_circuits()
+    }
+
+    /// Finds the server of the range and makes sure there is a connection to
+    /// it, without sending it a request. Fails like a request would if the
+    /// range has no known server or the server's circuit is open.
+    pub async fn leader_of(&self, range_id: &FullRangeId) -> Result<HostIdentity, Error> {
+        let client = self.connect_to_range(range_id).await?;
+        Ok(client.host_info().identity)
+    }
+
     pub async fn get(
         &self,
         tx: Arc<TransactionInfo>,
@@ -44,12 +94,46 @@ impl RangeClient {
         keys: Vec<Bytes>,
     ) -> Result<GetResult, Error> {
         let client = self.get_range_client(range_id).await?;
-        client
-            .get(tx, range_id, keys)
-            .await
-            .map_err(|e| self.handle_rangeserver_err(range_id, e))
+        let res = client.get(tx, range_id, keys).await;
+        self.handle_response(range_id, &client, res)
+    }
+
+    pub async fn get_for_update(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        keys: Vec<Bytes>,
+    ) -> Result<GetResult, Error> {
+        let client = self.get_range_client(range_id).await?;
+        let res = client.get_for_update(tx, range_id, keys).await;
+        self.handle_response(range_id, &client, res)
+    }
+
+    pub async fn get_at_epoch(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        keys: Vec<Bytes>,
+        epoch: u64,
+    ) -> Result<GetResult, Error> {
+        let client = self.get_range_client(range_id).await?;
+        let res = client.get_at_epoch(tx, range_id, keys, epoch).await;
+        self.handle_response(range_id, &client, res)
+    }
+
+    pub async fn scan(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        key_range: &KeyRange,
+        limit: Option<usize>,
+    ) -> Result<ScanResult, Error> {
+        let client = self.get_range_client(range_id).await?;
+        let res = client.scan(tx, range_id, key_range, limit).await;
+        self.handle_response(range_id, &client, res)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn prepare_transaction(
         &self,
         tx: Arc<TransactionInfo>,
@@ -57,12 +141,16 @@ impl RangeClient {
         has_reads: bool,
         writes: &[Record],
         deletes: &[Bytes],
+        conditions: &[Condition],
+        increments: &[Increment],
     ) -> Result<PrepareOk, Error> {
         let client = self.get_range_client(range_id).await?;
-        client
-            .prepare_transaction(tx, range_id, has_reads, writes, deletes)
-            .await
-            .map_err(|e| self.handle_rangeserver_err(range_id, e))
+        let res = client
+            .prepare_transaction(
+                tx, range_id, has_reads, writes, deletes, conditions, increments,
+            )
+            .await;
+        self.handle_response(range_id, &client, res)
     }
 
     pub async fn abort_transaction(
@@ -71,10 +159,22 @@ impl RangeClient {
         range_id: &FullRangeId,
     ) -> Result<(), Error> {
         let client = self.get_range_client(range_id).await?;
-        client
-            .abort_transaction(tx, range_id)
-            .await
-            .map_err(|e| self.handle_rangeserver_err(range_id, e))
+        let res = client.abort_transaction(tx, range_id).await;
+        self.handle_response(range_id, &client, res)
+    }
+
+    pub async fn validate_transaction(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        has_reads: bool,
+        has_writes: bool,
+    ) -> Result<i64, Error> {
+        let client = self.get_range_client(range_id).await?;
+        let res = client
+            .validate_transaction(tx, range_id, has_reads, has_writes)
+            .await;
+        self.handle_response(range_id, &client, res)
     }
 
     pub async fn commit_transaction(
@@ -84,19 +184,56 @@ impl RangeClient {
         epoch: u64,
     ) -> Result<(), Error> {
         let client = self.get_range_client(range_id).await?;
-        client
-            .commit_transaction(tx, range_id, epoch)
-            .await
-            .map_err(|e| self.handle_rangeserver_err(range_id, e))
+        let res = client.commit_transaction(tx, range_id, epoch).await;
+        self.handle_response(range_id, &client, res)
+    }
+
+    pub async fn extend_epoch_lease(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        min_upper_bound: u64,
+    ) -> Result<PrepareOk, Error> {
+        let client = self.get_range_client(range_id).await?;
+        let res = client
+            .extend_epoch_lease(tx, range_id, min_upper_bound)
+            .await;
+        self.handle_response(range_id, &client, res)
+    }
+
+    pub async fn get_conflict_stats(&self, range_id: &FullRangeId) -> Result<ConflictStats, Error> {
+        let client = self.get_range_client(range_id).await?;
+        let res = client.get_conflict_stats(range_id).await;
+        self.handle_response(range_id, &client, res)
     }
 }
 
 impl RangeClient {
     async fn get_range_client(&self, range_id: &FullRangeId) -> Result<Arc<Client>, Error> {
+        let client = self.connect_to_range(range_id).await?;
+        // Each range has a single owner, so there is no closer host to prefer,
+        // but account for the requests that leave the zone.
+        if client.host_info().identity.zone == self.zone {
+            self.same_zone_requests.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.cross_zone_requests.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(client)
+    }
+
+    async fn connect_to_range(&self, range_id: &FullRangeId) -> Result<Arc<Client>, Error> {
         let host_info = match self.range_assignment_oracle.host_of_range(range_id).await {
             None => return Err(Error::RangeIsNotLoaded),
             Some(host_info) => host_info,
         };
+        // Fail fast rather than wait out another timeout against a server
+        // that looks down, and look for the range elsewhere in case it was
+        // moved off that server.
+        if !self.circuit_breakers.allows(&host_info.identity) {
+            self.range_assignment_oracle
+                .maybe_refresh_host_of_range(range_id);
+            return Err(Error::ConnectionClosed);
+        }
 
         // Check if we already have a started client to the range server.
         let existing_client = {
@@ -119,7 +256,13 @@ impl RangeClient {
         let client = match existing_client {
             Some(c) => c,
             None => {
-                let client = Client::new(self.fast_network.clone(), host_info.clone(), None).await;
+                let client = Client::new(
+                    self.fast_network.clone(),
+                    host_info.clone(),
+                    None,
+                    RetryPolicy::default(),
+                )
+                .await;
                 {
                     let mut range_clients = self.range_clients.write().await;
                     match range_clients.get(&host_info.identity) {
@@ -148,11 +291,35 @@ impl RangeClient {
         Ok(client)
     }
 
+    fn handle_response<T>(
+        &self,
+        range_id: &FullRangeId,
+        client: &Client,
+        res: Result<T, Error>,
+    ) -> Result<T, Error> {
+        let server = &client.host_info().identity;
+        match &res {
+            Err(Error::Timeout) | Err(Error::ConnectionClosed) => {
+                if self.circuit_breakers.record_failure(server) {
+                    self.range_assignment_oracle
+                        .maybe_refresh_host_of_range(range_id);
+                }
+            }
+            // Any other answer shows the server is up.
+            _ => self.circuit_breakers.record_success(server),
+        }
+        res.map_err(|e| self.handle_rangeserver_err(range_id, e))
+    }
+
     fn handle_rangeserver_err(&self, range_id: &FullRangeId, error: Error) -> Error {
         match error {
-            Error::RangeIsNotLoaded | Error::RangeOwnershipLost => self
+            Error::RangeIsNotLoaded | Error::RangeOwnershipLost | Error::RangeDraining => self
                 .range_assignment_oracle
                 .maybe_refresh_host_of_range(range_id),
+            Error::PrepareBacklogFull { retry_after } | Error::WriteStalled { retry_after } => {
+                self.overloads.record(range_id, Some(retry_after))
+            }
+            Error::Overloaded => self.overloads.record(range_id, None),
             Error::InvalidRequestFormat
             | Error::RangeDoesNotExist
             | Error::KeyIsOutOfRange
@@ -161,6 +328,16 @@ impl RangeClient {
             | Error::UnknownTransaction
             | Error::CacheIsFull
             | Error::PrefetchError
+            | Error::KeyspaceIsReadOnly
+            | Error::RangeFaulted
+            | Error::RangeBusy
+            | Error::RangeFrozen
+            | Error::SnapshotTooOld
+            | Error::LockWaitTimeout
+            | Error::KeyspaceDoesNotExist
+            | Error::WriteRejected
+            | Error::ConditionFailed
+            | Error::InvalidIncrement
             | Error::TransactionAborted(_)
             | Error::InternalError(_) => (),
         };
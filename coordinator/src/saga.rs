@@ -0,0 +1,293 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::keyspace::Keyspace;
+use futures::future::BoxFuture;
+use uuid::Uuid;
+
+use crate::{
+    coordinator::{Coordinator, RunOptions},
+    error::{Error, TransactionAbortReason},
+    transaction::Transaction,
+};
+
+/// An action or compensation of a saga step. It runs in the transaction that
+/// also records the saga's progress, and gets the saga's id, e.g. to key
+/// whatever it writes for later steps or for its compensation to find.
+pub type StepFn = Arc<
+    dyn for<'a> Fn(&'a mut Transaction, Uuid) -> BoxFuture<'a, Result<(), Error>> + Send + Sync,
+>;
+
+struct Step {
+    action: StepFn,
+    compensation: StepFn,
+}
+
+/// Where a saga is at, as recorded in its keyspace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SagaState {
+    /// The steps before `next_step` are done.
+    Running {
+        next_step: usize,
+    },
+    /// Step `failed_step` failed, and the steps before `next_to_undo` are yet
+    /// to be compensated, last one first.
+    Compensating {
+        failed_step: usize,
+        next_to_undo: usize,
+    },
+    Completed,
+    /// Step `failed_step` failed, and the steps before it were compensated.
+    Compensated {
+        failed_step: usize,
+    },
+}
+
+/// Recipe for workflows too long to run as a single transaction.
+///
+/// A saga is a sequence of steps, each with an action and a compensation
+/// undoing it. Every action runs in its own transaction, and if one fails,
+/// the compensations of the steps done so far run in reverse order, each in
+/// its own transaction as well. The saga's progress is stored under its id in
+/// a dedicated keyspace, and written in the same transaction as the action or
+/// compensation it records, so each of them takes effect exactly once however
+/// often the saga is interrupted and resumed.
+///
+/// A step fails when its action returns an error that running it again would
+/// not fix, e.g. `ConditionFailed` or an `InternalError` of the action's own.
+/// Transient errors such as timeouts or retryable aborts, once
+/// `RunOptions::max_attempts` is used up, stop the saga where it is instead,
+/// and `Saga::run` returns them. Compensations should not fail for other
+/// reasons, as the saga cannot make progress until they succeed.
+///
+/// Resuming a saga, e.g. after a crash, is calling `Saga::run` again with the
+/// same id, on a saga with the same steps in the same order.
+pub struct Saga {
+    coordinator: Arc<Coordinator>,
+    keyspace: Keyspace,
+    options: RunOptions,
+    steps: Vec<Step>,
+}
+
+impl Saga {
+    pub fn new(coordinator: Arc<Coordinator>, keyspace: Keyspace, mut options: RunOptions) -> Saga {
+        options
+            .labels
+            .insert("recipe".to_string(), "saga".to_string());
+        Saga {
+            coordinator,
+            keyspace,
+            options,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Adds a step after the ones added so far. Both `action` and
+    /// `compensation` are called as in `|tx, saga_id| Box::pin(async move {
+    /// ... })`, and may run several times, like the body of
+    /// `Coordinator::run_transaction`.
+    pub fn step<A, C>(mut self, action: A, compensation: C) -> Saga
+    where
+        A: for<'a> Fn(&'a mut Transaction, Uuid) -> BoxFuture<'a, Result<(), Error>>
+            + Send
+            + Sync
+            + 'static,
+        C: for<'a> Fn(&'a mut Transaction, Uuid) -> BoxFuture<'a, Result<(), Error>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.steps.push(Step {
+            action: Arc::new(action),
+            compensation: Arc::new(compensation),
+        });
+        self
+    }
+
+    fn encode(state: SagaState) -> Bytes {
+        let (tag, a, b) = match state {
+            SagaState::Running { next_step } => (0, next_step, 0),
+            SagaState::Compensating {
+                failed_step,
+                next_to_undo,
+            } => (1, failed_step, next_to_undo),
+            SagaState::Completed => (2, 0, 0),
+            SagaState::Compensated { failed_step } => (3, failed_step, 0),
+        };
+        let mut buf = BytesMut::with_capacity(9);
+        buf.put_u8(tag);
+        buf.put_u32(a as u32);
+        buf.put_u32(b as u32);
+        buf.freeze()
+    }
+
+    fn decode(mut val: Bytes) -> Result<SagaState, Error> {
+        if val.len() != 9 {
+            return Err(Error::InternalError(Arc::new(std::io::Error::other(
+                "malformed saga state",
+            ))));
+        }
+        let tag = val.get_u8();
+        let a = val.get_u32() as usize;
+        let b = val.get_u32() as usize;
+        match tag {
+            0 => Ok(SagaState::Running { next_step: a }),
+            1 => Ok(SagaState::Compensating {
+                failed_step: a,
+                next_to_undo: b,
+            }),
+            2 => Ok(SagaState::Completed),
+            3 => Ok(SagaState::Compensated { failed_step: a }),
+            _ => Err(Error::InternalError(Arc::new(std::io::Error::other(
+                format!("unknown saga state {}", tag),
+            )))),
+        }
+    }
+
+    async fn load(
+        tx: &mut Transaction,
+        keyspace: &Keyspace,
+        saga_id: Uuid,
+    ) -> Result<Option<SagaState>, Error> {
+        let val = tx
+            .get(keyspace, Bytes::copy_from_slice(saga_id.as_bytes()))
+            .await?;
+        val.map(Self::decode).transpose()
+    }
+
+    /// Returns where the saga is at, None if it never ran.
+    pub async fn state(&self, saga_id: Uuid) -> Result<Option<SagaState>, Error> {
+        let keyspace = self.keyspace.clone();
+        self.coordinator
+            .run_transaction(&self.options, |tx| {
+                let keyspace = keyspace.clone();
+                Box::pin(async move { Self::load(tx, &keyspace, saga_id).await })
+            })
+            .await
+    }
+
+    /// Runs the saga with the given id from where it is at, until it either
+    /// completed or compensated, and returns which. Returns an error if it
+    /// stopped before that, in which case it resumes from there on the next
+    /// call.
+    pub async fn run(&self, saga_id: Uuid) -> Result<SagaState, Error> {
+        loop {
+            // The step whose action failed in the last attempt, if any.
+            let failed_action = Arc::new(Mutex::new(None));
+            let res = self
+                .coordinator
+                .run_transaction(&self.options, |tx| {
+                    let keyspace = self.keyspace.clone();
+                    let failed_action = failed_action.clone();
+                    let steps: Vec<(StepFn, StepFn)> = self
+                        .steps
+                        .iter()
+                        .map(|s| (s.action.clone(), s.compensation.clone()))
+                        .collect();
+                    Box::pin(async move {
+                        *failed_action.lock().unwrap() = None;
+                        let state = Self::load(tx, &keyspace, saga_id)
+                            .await?
+                            .unwrap_or(SagaState::Running { next_step: 0 });
+                        let next = match state {
+                            SagaState::Running { next_step } if next_step >= steps.len() => {
+                                SagaState::Completed
+                            }
+                            SagaState::Running { next_step } => {
+                                if let Err(e) = (steps[next_step].0)(tx, saga_id).await {
+                                    *failed_action.lock().unwrap() = Some(next_step);
+                                    return Err(e);
+                                }
+                                SagaState::Running {
+                                    next_step: next_step + 1,
+                                }
+                            }
+                            SagaState::Compensating {
+                                failed_step,
+                                next_to_undo: 0,
+                            } => SagaState::Compensated { failed_step },
+                            SagaState::Compensating {
+                                failed_step,
+                                next_to_undo,
+                            } => {
+                                (steps[next_to_undo - 1].1)(tx, saga_id).await?;
+                                SagaState::Compensating {
+                                    failed_step,
+                                    next_to_undo: next_to_undo - 1,
+                                }
+                            }
+                            done => return Ok(done),
+                        };
+                        tx.put(
+                            &keyspace,
+                            Bytes::copy_from_slice(saga_id.as_bytes()),
+                            Self::encode(next),
+                        )
+                        .await?;
+                        Ok(next)
+                    })
+                })
+                .await;
+            let failed_action = *failed_action.lock().unwrap();
+            match (res, failed_action) {
+                (Ok(state @ (SagaState::Completed | SagaState::Compensated { .. })), _) => {
+                    return Ok(state)
+                }
+                (Ok(_), _) => {}
+                (Err(e), Some(failed_step)) if !is_transient(&e) => {
+                    self.start_compensating(saga_id, failed_step).await?
+                }
+                (Err(e), _) => return Err(e),
+            }
+        }
+    }
+
+    // Records that the action of `failed_step` failed, unless another run of
+    // the saga got past it in the meantime.
+    async fn start_compensating(&self, saga_id: Uuid, failed_step: usize) -> Result<(), Error> {
+        let keyspace = self.keyspace.clone();
+        self.coordinator
+            .run_transaction(&self.options, |tx| {
+                let keyspace = keyspace.clone();
+                Box::pin(async move {
+                    let state = Self::load(tx, &keyspace, saga_id)
+                        .await?
+                        .unwrap_or(SagaState::Running { next_step: 0 });
+                    if state
+                        == (SagaState::Running {
+                            next_step: failed_step,
+                        })
+                    {
+                        let compensating = SagaState::Compensating {
+                            failed_step,
+                            next_to_undo: failed_step,
+                        };
+                        tx.put(
+                            &keyspace,
+                            Bytes::copy_from_slice(saga_id.as_bytes()),
+                            Self::encode(compensating),
+                        )
+                        .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await
+    }
+}
+
+// Whether the saga should stop where it is on the error rather than
+// compensate, as running the same step again may succeed.
+fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::TransactionAborted(reason) => {
+            reason.is_retryable() || matches!(reason, TransactionAbortReason::TransactionTimeout)
+        }
+        Error::Timeout
+        | Error::RangeFrozen
+        | Error::RangeServerUnavailable
+        | Error::InsufficientTimeRemaining
+        | Error::TransactionDoneButStateUnknown => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,220 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use common::keyspace::Keyspace;
+use tokio::sync::Mutex;
+
+use crate::{
+    coordinator::{Coordinator, RunOptions},
+    error::Error,
+};
+
+/// Options controlling how a `Sequence` reserves IDs.
+///
+/// Monotonicity guarantees depend on `block_size`:
+/// - IDs handed out by a single `Sequence` instance are always strictly
+///   increasing and never repeat, regardless of the block size.
+/// - IDs handed out by different `Sequence` instances (e.g. on different
+///   frontends) sharing the same counter key are unique, but are only ordered
+///   by block: an instance may still be handing out IDs from an older block
+///   after another instance reserved a newer one.
+/// - With `block_size == 1` every ID is reserved by its own transaction, so IDs
+///   are globally monotonic in commit order, at the cost of a transaction on
+///   the counter key per ID.
+#[derive(Clone, Debug)]
+pub struct SequenceOptions {
+    /// Number of IDs reserved by each allocation transaction. Must be non-zero.
+    pub block_size: u64,
+    /// Overall timeout applied to each allocation transaction.
+    pub transaction_timeout: Duration,
+    /// Number of times a block reservation is attempted before giving up when
+    /// the allocation transaction gets aborted for a retryable reason (e.g.
+    /// due to contention).
+    pub max_attempts: u32,
+}
+
+struct Block {
+    next: u64,
+    end: u64,
+}
+
+/// Allocates monotonically increasing u64 IDs backed by a single counter key.
+///
+/// Rather than incrementing the counter once per ID, the sequence reserves a
+/// block of `block_size` IDs in one transaction and serves subsequent IDs from
+/// that block locally, only going back to the counter key once it is used up.
+/// IDs in a reserved block that are never handed out (e.g. because the process
+/// restarts) are skipped, so sequences may have gaps.
+pub struct Sequence {
+    coordinator: Arc<Coordinator>,
+    keyspace: Keyspace,
+    counter_key: Bytes,
+    options: SequenceOptions,
+    block: Mutex<Block>,
+}
+
+impl Sequence {
+    pub fn new(
+        coordinator: Arc<Coordinator>,
+        keyspace: Keyspace,
+        counter_key: Bytes,
+        options: SequenceOptions,
+    ) -> Sequence {
+        assert!(options.block_size > 0, "block_size must be non-zero");
+        Sequence {
+            coordinator,
+            keyspace,
+            counter_key,
+            options,
+            block: Mutex::new(Block { next: 0, end: 0 }),
+        }
+    }
+
+    /// Returns the next ID of the sequence, reserving a new block if the
+    /// currently cached one is exhausted.
+    pub async fn next(&self) -> Result<u64, Error> {
+        // Holding the lock across the reservation ensures IDs handed out by
+        // this instance are strictly increasing.
+        let mut block = self.block.lock().await;
+        if block.next == block.end {
+            let (start, end) = self.reserve_block().await?;
+            block.next = start;
+            block.end = end;
+        }
+        let id = block.next;
+        block.next += 1;
+        Ok(id)
+    }
+
+    async fn reserve_block(&self) -> Result<(u64, u64), Error> {
+        let options = RunOptions {
+            transaction_timeout: self.options.transaction_timeout,
+            labels: BTreeMap::from([("recipe".to_string(), "sequence".to_string())]),
+            max_attempts: self.options.max_attempts,
+            ..RunOptions::default()
+        };
+        let block_size = self.options.block_size;
+        self.coordinator
+            .run_transaction(&options, |tx| {
+                let keyspace = self.keyspace.clone();
+                let counter_key = self.counter_key.clone();
+                Box::pin(async move {
+                    let start = match tx.get(&keyspace, counter_key.clone()).await? {
+                        None => 0,
+                        Some(val) => u64::from_be_bytes(
+                            <[u8; 8]>::try_from(val.as_ref())
+                                .map_err(|e| Error::InternalError(Arc::new(e)))?,
+                        ),
+                    };
+                    let end = start.checked_add(block_size).ok_or_else(|| {
+                        Error::InternalError(Arc::new(std::io::Error::other(
+                            "sequence exhausted the u64 space",
+                        )))
+                    })?;
+                    tx.put(
+                        &keyspace,
+                        counter_key,
+                        Bytes::copy_from_slice(&end.to_be_bytes()),
+                    )
+                    .await?;
+                    Ok((start, end))
+                })
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::for_testing::{self, TestContext};
+
+    fn options(block_size: u64) -> SequenceOptions {
+        SequenceOptions {
+            block_size,
+            transaction_timeout: Duration::from_secs(10),
+            max_attempts: 1,
+        }
+    }
+
+    fn sequence(context: &TestContext, counter_key: &Bytes, block_size: u64) -> Sequence {
+        Sequence::new(
+            context.coordinator.clone(),
+            context.keyspace.clone(),
+            counter_key.clone(),
+            options(block_size),
+        )
+    }
+
+    async fn counter(context: &TestContext, counter_key: &Bytes) -> Option<u64> {
+        let mut tx = context.start_transaction(Duration::from_secs(10)).await;
+        let value = tx
+            .get(&context.keyspace, counter_key.clone())
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        value.map(|value| u64::from_be_bytes(<[u8; 8]>::try_from(value.as_ref()).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn ids_are_served_from_reserved_blocks() {
+        let context = for_testing::setup().await;
+        let counter_key = Bytes::from_static(b"counter");
+        let sequence = sequence(&context, &counter_key, 3);
+        let mut ids = Vec::new();
+        for _ in 0..7 {
+            ids.push(sequence.next().await.unwrap());
+        }
+        assert_eq!(ids, (0..7).collect::<Vec<u64>>());
+        // Three blocks were reserved, the last one only partly used.
+        assert_eq!(counter(&context, &counter_key).await, Some(9));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn sequences_sharing_a_counter_hand_out_unique_increasing_ids() {
+        let context = for_testing::setup().await;
+        let counter_key = Bytes::from_static(b"counter");
+        let sequences = [
+            sequence(&context, &counter_key, 2),
+            sequence(&context, &counter_key, 2),
+        ];
+        let mut ids: [Vec<u64>; 2] = Default::default();
+        for i in 0..10 {
+            ids[i % 2].push(sequences[i % 2].next().await.unwrap());
+        }
+        for ids in &ids {
+            assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+        let mut all: Vec<u64> = ids.concat();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 10);
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn exhausted_sequences_fail_and_leave_the_counter_alone() {
+        let context = for_testing::setup().await;
+        let counter_key = Bytes::from_static(b"counter");
+        let mut tx = context.start_transaction(Duration::from_secs(10)).await;
+        tx.put(
+            &context.keyspace,
+            counter_key.clone(),
+            Bytes::copy_from_slice(&(u64::MAX - 1).to_be_bytes()),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let sequence = sequence(&context, &counter_key, 4);
+        assert!(matches!(
+            sequence.next().await,
+            Err(Error::InternalError(_))
+        ));
+        // The reservation released its lock on the counter when it aborted,
+        // so reading it does not wait.
+        assert_eq!(counter(&context, &counter_key).await, Some(u64::MAX - 1));
+        context.tear_down().await
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Keeps count of the tasks the coordinator spawned for its transactions, and
+/// cancels them all when the coordinator shuts down.
+pub(crate) struct TaskAccounting {
+    in_flight: AtomicUsize,
+    shutdown: CancellationToken,
+}
+
+impl TaskAccounting {
+    pub fn new(shutdown: CancellationToken) -> TaskAccounting {
+        TaskAccounting {
+            in_flight: AtomicUsize::new(0),
+            shutdown,
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Spawns `task` on `runtime` for the coordinator itself rather than for
+    /// a transaction, so it is not counted in flight. It gets cancelled when
+    /// the coordinator shuts down.
+    pub fn spawn_background<F>(&self, runtime: &tokio::runtime::Handle, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown = self.shutdown.clone();
+        runtime.spawn(async move {
+            tokio::select! {
+                () = shutdown.cancelled() => (),
+                () = task => (),
+            }
+        });
+    }
+}
+
+struct InFlightGuard(Arc<TaskAccounting>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Spawns the tasks of a single transaction (e.g. the prepare, commit and
+/// abort fan-out to its participants). All of them get cancelled once the
+/// transaction is done with them, or when the coordinator shuts down.
+pub(crate) struct TransactionTasks {
+    runtime: tokio::runtime::Handle,
+    accounting: Arc<TaskAccounting>,
+    cancellation_token: CancellationToken,
+}
+
+impl TransactionTasks {
+    pub fn new(runtime: tokio::runtime::Handle, accounting: Arc<TaskAccounting>) -> Self {
+        let cancellation_token = accounting.shutdown.child_token();
+        TransactionTasks {
+            runtime,
+            accounting,
+            cancellation_token,
+        }
+    }
+
+    /// Spawns `task` into `join_set`. The task yields None if it got cancelled
+    /// before completing.
+    pub fn spawn<F>(&self, join_set: &mut JoinSet<Option<F::Output>>, task: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.accounting.in_flight.fetch_add(1, Ordering::Relaxed);
+        let guard = InFlightGuard(self.accounting.clone());
+        let cancellation_token = self.cancellation_token.clone();
+        join_set.spawn_on(
+            async move {
+                let _guard = guard;
+                tokio::select! {
+                    () = cancellation_token.cancelled() => None,
+                    res = task => Some(res),
+                }
+            },
+            &self.runtime,
+        );
+    }
+
+    /// Cancels all the tasks spawned so far and any spawned later.
+    pub fn cancel(&self) {
+        self.cancellation_token.cancel()
+    }
+}
+
+impl Drop for TransactionTasks {
+    fn drop(&mut self) {
+        self.cancel()
+    }
+}
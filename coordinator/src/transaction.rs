@@ -1,57 +1,262 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     str::FromStr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
 use common::{
-    constants, full_range_id::FullRangeId, keyspace::Keyspace, keyspace_id::KeyspaceId,
-    membership::range_assignment_oracle::RangeAssignmentOracle, record::Record,
-    transaction_info::TransactionInfo,
+    clock::{self, Clock},
+    colocation::Colocation,
+    constants,
+    epoch_lease::EpochLease,
+    full_range_id::FullRangeId,
+    host_info::HostIdentity,
+    key_range::KeyRange,
+    keyspace::Keyspace,
+    keyspace_id::KeyspaceId,
+    membership::range_assignment_oracle::RangeAssignmentOracle,
+    record::{Condition, Increment, Record},
+    transaction_info::{IsolationLevel, TransactionInfo},
 };
-use epoch_reader::reader::EpochReader;
+use epoch_reader::source::EpochSource;
+use futures::future::join_all;
 use proto::universe::universe_client::UniverseClient;
 use proto::universe::{
-    get_keyspace_info_request::KeyspaceInfoSearchField, GetKeyspaceInfoRequest,
-    Keyspace as ProtoKeyspace,
+    get_keyspace_info_request::KeyspaceInfoSearchField, CheckCrossNamespaceAccessRequest,
+    GetKeyspaceInfoRequest, Keyspace as ProtoKeyspace,
 };
 use tokio::task::JoinSet;
 use uuid::Uuid;
 
 use crate::{
     error::{Error, TransactionAbortReason},
+    external::ExternalParticipant,
+    instrumentation::{Instrumentation, RequestInfo, RequestOutcome},
+    lifecycle_log::{LifecycleLogger, TransactionTimeline},
+    metrics::FanOutRecorder,
+    outcome::{Decision, OutcomeNotifier, TransactionOutcome},
+    participants::ParticipantRegistry,
     rangeclient::RangeClient,
+    tasks::TransactionTasks,
 };
 use tx_state_store::client::Client as TxStateStoreClient;
 use tx_state_store::client::OpResult;
 
+// Fraction (1/N) of a transaction's overall timeout that is held back for
+// prepare and commit. Reads only get to use the rest.
+const COMMIT_TIME_RESERVE_DIVISOR: u32 = 4;
+
+// How many times commit asks ranges to extend their epoch leases before
+// giving up. Extending can raise the commit epoch, which can in turn expire
+// other leases, hence more than one round.
+const MAX_LEASE_EXTENSION_ROUNDS: usize = 3;
+
 enum State {
     Running,
     Preparing,
     Aborted,
-    Committed,
+    Committed { epoch: u64 },
+    // Neither committing nor aborting could be recorded in the
+    // tx_state_store, so the transaction may have committed or not.
+    Unknown,
 }
 
 struct ParticipantRange {
     readset: HashSet<Bytes>,
-    writeset: HashMap<Bytes, Bytes>,
-    deleteset: HashSet<Bytes>,
+    // Whether the transaction scanned the range, which reads it as a whole
+    // rather than the keys in the readset.
+    scanned: bool,
+    // The latest write of each key, None for a delete. Writing a key again
+    // replaces (and frees) its previous value, so update-heavy loops only
+    // buffer one value per key.
+    writes: HashMap<Bytes, Option<Bytes>>,
+    // Conditions of put_if calls, checked by the range at prepare.
+    conditions: Vec<Condition>,
+    // The summed deltas of increments of keys not otherwise written, applied
+    // by the range at prepare.
+    increments: HashMap<Bytes, i64>,
+    // TTLs of the buffered puts that have one.
+    ttls: HashMap<Bytes, Duration>,
     leader_sequence_number: u64,
 }
 
+// What rolling back to a savepoint restores of a participant range.
+struct RangeSavepoint {
+    writes: HashMap<Bytes, Option<Bytes>>,
+    conditions: usize,
+    increments: HashMap<Bytes, i64>,
+    ttls: HashMap<Bytes, Duration>,
+}
+
+struct Savepoint {
+    // Tells the savepoint apart from the discarded ones that held its place
+    // in `Transaction::savepoints` before it.
+    generation: u64,
+    ranges: HashMap<FullRangeId, RangeSavepoint>,
+}
+
+impl ParticipantRange {
+    fn has_reads(&self) -> bool {
+        self.scanned || !self.readset.is_empty()
+    }
+
+    // Nothing to prepare: no reads, and no buffered writes, e.g. because
+    // they all followed their keys to another range.
+    fn is_empty(&self) -> bool {
+        !self.has_reads()
+            && self.writes.is_empty()
+            && self.conditions.is_empty()
+            && self.increments.is_empty()
+    }
+
+    // Bytes of the keys and values the prepare carries, without framing.
+    fn prepare_payload_bytes(&self) -> usize {
+        self.writes
+            .iter()
+            .map(|(k, v)| k.len() + v.as_ref().map_or(0, |v| v.len()))
+            .chain(
+                self.conditions
+                    .iter()
+                    .map(|c| c.key.len() + c.expected.as_ref().map_or(0, |v| v.len())),
+            )
+            .chain(self.increments.keys().map(|k| k.len() + 8))
+            .sum()
+    }
+}
+
+/// Counters about the work a transaction did, for spotting wasteful access
+/// patterns.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TransactionStats {
+    /// Puts, deletes and increments issued, including ones overwriting an
+    /// earlier write of the same key.
+    pub writes: u64,
+    /// Puts, deletes and increments of a key the transaction had already
+    /// written.
+    pub duplicate_writes: u64,
+    /// Bytes of keys and values currently buffered for commit.
+    pub buffered_write_bytes: usize,
+    /// Times a participant range was asked to extend its epoch lease so the
+    /// transaction could commit.
+    pub epoch_lease_extensions: u64,
+}
+
+/// What `Transaction::validate` found.
+#[derive(Clone, Debug)]
+pub enum Validation {
+    /// Every participant range would currently accept the prepare.
+    WouldCommit,
+    /// Committing now would fail with this error.
+    WouldFail(Error),
+}
+
+/// What committing the transaction would do as of now, as found by
+/// `Transaction::explain`.
+#[derive(Clone, Debug)]
+pub struct ExplainReport {
+    /// The ranges commit would prepare the transaction on, in the order the
+    /// transaction first used them.
+    pub participants: Vec<ParticipantPlan>,
+    /// Messages commit would send to range servers: a prepare and a commit
+    /// to every participant. Epoch lease extensions and resent messages come
+    /// on top.
+    pub range_server_messages: usize,
+    /// Estimated size of all the prepares together.
+    pub prepare_payload_bytes: usize,
+    /// The order the transaction takes the range locks in. Ranges that were
+    /// read got locked by their first read, unless their keyspace reads
+    /// optimistically. The others are locked by their prepares, which commit
+    /// sends to all of them at once.
+    pub lock_order: Vec<FullRangeId>,
+    /// How well the transaction kept to colocation groups, None if it used
+    /// no keys of a keyspace that colocates keys.
+    pub colocation: Option<ColocationReport>,
+}
+
+/// How the keys a transaction read one by one or wrote fall into the
+/// colocation groups of their keyspaces. A transaction sticking to one group
+/// involves a single range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColocationReport {
+    /// Groups the transaction used keys of.
+    pub groups: usize,
+    /// Participant ranges holding those keys.
+    pub ranges: usize,
+    /// Groups whose keys the transaction found in more than one range, which
+    /// only ranges split before the keyspace colocated its keys can cause.
+    pub split_groups: usize,
+}
+
+/// What commit would do on one participant range.
+#[derive(Clone, Debug)]
+pub struct ParticipantPlan {
+    pub range_id: FullRangeId,
+    /// The range server the range is assigned to, None if not known.
+    pub server: Option<HostIdentity>,
+    /// Keys read one by one, excluding those served from buffered writes.
+    pub keys_read: usize,
+    /// Whether the range was scanned, which reads it as a whole.
+    pub scanned: bool,
+    pub puts: usize,
+    pub deletes: usize,
+    /// Conditions of `put_if` calls the range checks at prepare.
+    pub conditions: usize,
+    /// Counters the range increments at prepare.
+    pub increments: usize,
+    /// Bytes of the keys and values the prepare carries, without framing.
+    pub prepare_payload_bytes: usize,
+}
+
 pub struct Transaction {
     id: Uuid,
     transaction_info: Arc<TransactionInfo>,
     universe_client: UniverseClient<tonic::transport::Channel>,
     state: State,
     participant_ranges: HashMap<FullRangeId, ParticipantRange>,
+    // Participant ranges in the order they were first used, and the ones
+    // read in the order they were first read.
+    participant_order: Vec<FullRangeId>,
+    read_order: Vec<FullRangeId>,
+    external_participants: Vec<Arc<dyn ExternalParticipant>>,
+    // The buffered writes of each range as of each savepoint still held,
+    // oldest first.
+    savepoints: Vec<Savepoint>,
+    next_savepoint_generation: u64,
+    stats: TransactionStats,
     resolved_keyspaces: HashMap<Keyspace, KeyspaceId>,
+    // The ranges the transaction was pinned to and their servers, once
+    // pinned.
+    pinned: Option<HashMap<FullRangeId, HostIdentity>>,
+    // Resolved keyspaces that were in read-only mode at resolution time.
+    read_only_keyspaces: HashSet<Keyspace>,
+    // Colocation policies of the resolved keyspaces that have one.
+    colocations: HashMap<KeyspaceId, Colocation>,
     range_client: Arc<RangeClient>,
     range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
-    epoch_reader: Arc<EpochReader>,
+    epoch_reader: Arc<dyn EpochSource>,
     tx_state_store: Arc<TxStateStoreClient>,
-    runtime: tokio::runtime::Handle,
+    outcome_notifier: Arc<OutcomeNotifier>,
+    participant_registry: Arc<ParticipantRegistry>,
+    fan_out: Arc<FanOutRecorder>,
+    lifecycle_logger: Option<Arc<LifecycleLogger>>,
+    timeline: Option<TransactionTimeline>,
+    instrumentation: Option<Arc<dyn Instrumentation>>,
+    tasks: TransactionTasks,
+    clock: Arc<dyn Clock>,
+    // When the transaction got started on the coordinator, which its timeout
+    // counts from.
+    started: chrono::DateTime<chrono::Utc>,
+}
+
+/// Identifies a point in a transaction that `Transaction::rollback_to` can
+/// return to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SavepointId {
+    transaction_id: Uuid,
+    index: usize,
+    generation: u64,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Hash)]
@@ -65,10 +270,16 @@ impl Transaction {
         // Keyspace name to id must be stable within the same transaction, to avoid
         // scenarios in which we write different keyspaces if a keyspace is deleted
         // and then another one is created with the same name within the span of the
-        // transaction.
+        // transaction. The same goes for a keyspace being renamed, so a keyspace
+        // found through an alias of its old name is also remembered by its
+        // current name.
         if let Some(k) = self.resolved_keyspaces.get(keyspace) {
             return Ok(*k);
         };
+        let namespace = &keyspace.namespace;
+        self.check_cross_namespace_access(namespace).await?;
+        self.participant_registry
+            .add_namespace(&self.transaction_info, namespace);
         let keyspace_info_request = GetKeyspaceInfoRequest {
             keyspace_info_search_field: Some(KeyspaceInfoSearchField::Keyspace(ProtoKeyspace {
                 namespace: keyspace.namespace.clone(),
@@ -80,7 +291,10 @@ impl Transaction {
             .universe_client
             .get_keyspace_info(keyspace_info_request)
             .await
-            .map_err(|e| Error::InternalError(Arc::new(e)))?;
+            .map_err(|e| match e.code() {
+                tonic::Code::NotFound => Error::KeyspaceDoesNotExist,
+                _ => Error::InternalError(Arc::new(e)),
+            })?;
 
         let keyspace_info = keyspace_info_response
             .into_inner()
@@ -88,25 +302,92 @@ impl Transaction {
             .ok_or(Error::KeyspaceDoesNotExist)?;
 
         let keyspace_id = KeyspaceId::from_str(&keyspace_info.keyspace_id).unwrap();
-        self.resolved_keyspaces
-            .insert(keyspace.clone(), keyspace_id);
+        if let Some(colocation) = keyspace_info
+            .colocation
+            .as_ref()
+            .and_then(Colocation::from_proto)
+        {
+            self.colocations.insert(keyspace_id, colocation);
+        }
+        let mut names = vec![keyspace.clone()];
+        if keyspace_info.name != keyspace.name {
+            let current = Keyspace {
+                namespace: keyspace.namespace.clone(),
+                name: keyspace_info.name.clone(),
+            };
+            if !self.resolved_keyspaces.contains_key(&current) {
+                names.push(current);
+            }
+        }
+        for name in names {
+            if keyspace_info.read_only {
+                self.read_only_keyspaces.insert(name.clone());
+            }
+            self.resolved_keyspaces.insert(name, keyspace_id);
+        }
         Ok(keyspace_id)
     }
 
-    async fn resolve_full_record_key(
+    fn namespaces(&self) -> BTreeSet<String> {
+        self.resolved_keyspaces
+            .keys()
+            .map(|k| k.namespace.clone())
+            .collect()
+    }
+
+    // A transaction may only span several namespaces if the policy of each
+    // of them allows all the others, which is checked with the universe
+    // every time the transaction reaches a new namespace.
+    async fn check_cross_namespace_access(&mut self, namespace: &str) -> Result<(), Error> {
+        let mut namespaces = self.namespaces();
+        if namespaces.is_empty() || !namespaces.insert(namespace.to_string()) {
+            return Ok(());
+        }
+        let response = self
+            .universe_client
+            .check_cross_namespace_access(CheckCrossNamespaceAccessRequest {
+                namespaces: namespaces.iter().cloned().collect(),
+            })
+            .await
+            .map_err(|e| Error::InternalError(Arc::new(e)))?
+            .into_inner();
+        if response.allowed {
+            return Ok(());
+        }
+        if let Some(logger) = &self.lifecycle_logger {
+            logger.audit_cross_namespace(&self.transaction_info, &namespaces, "denied");
+        }
+        let denied = response.denied.into_iter().next().unwrap_or_default();
+        Err(Error::CrossNamespaceAccessDenied {
+            namespace: denied.namespace,
+            peer: denied.peer,
+        })
+    }
+
+    async fn resolve_range_id(
         &mut self,
         keyspace: &Keyspace,
-        key: Bytes,
-    ) -> Result<FullRecordKey, Error> {
+        key: &Bytes,
+    ) -> Result<FullRangeId, Error> {
         let keyspace_id = self.resolve_keyspace(keyspace).await?;
-        let range_id = match self
+        match self
             .range_assignment_oracle
             .full_range_id_of_key(keyspace_id, key.clone())
             .await
         {
-            None => return Err(Error::KeyspaceDoesNotExist),
-            Some(id) => id,
-        };
+            None => Err(Error::KeyspaceDoesNotExist),
+            Some(id) => Ok(id),
+        }
+    }
+
+    async fn resolve_full_record_key(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+    ) -> Result<FullRecordKey, Error> {
+        let range_id = self.resolve_range_id(keyspace, &key).await?;
+        self.check_pinned(&range_id).await?;
+        self.move_buffered_write(range_id, &key);
         let full_record_key = FullRecordKey {
             key: key.clone(),
             range_id,
@@ -114,244 +395,1776 @@ impl Transaction {
         Ok(full_record_key)
     }
 
+    // A split or merge since the transaction last wrote `key` may have moved
+    // it to another range. Its buffered write follows it to `range_id`, so
+    // that a key is only ever buffered under one range, and gets and scans
+    // both see its latest write.
+    fn move_buffered_write(&mut self, range_id: FullRangeId, key: &Bytes) {
+        let previous: Vec<FullRangeId> = self
+            .participant_ranges
+            .iter()
+            .filter(|(id, range)| {
+                **id != range_id
+                    && id.keyspace_id == range_id.keyspace_id
+                    && (range.writes.contains_key(key) || range.increments.contains_key(key))
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for previous in previous {
+            let range = self.participant_ranges.get_mut(&previous).unwrap();
+            let write = range.writes.remove(key);
+            let increment = range.increments.remove(key);
+            let ttl = range.ttls.remove(key);
+            let range = self.get_participant_range(range_id);
+            if let Some(write) = write {
+                range.writes.insert(key.clone(), write);
+            }
+            if let Some(increment) = increment {
+                range.increments.insert(key.clone(), increment);
+            }
+            if let Some(ttl) = ttl {
+                range.ttls.insert(key.clone(), ttl);
+            }
+        }
+    }
+
+    // At commit, every buffered write and condition follows its key to the
+    // range holding it now, as the range it was buffered under may have been
+    // split or merged away in the meantime. Ranges left with nothing to
+    // prepare are dropped: only reads take locks, so they hold none of the
+    // transaction's. Ranges that were read keep their reads, and a prepare
+    // reaching one that is gone aborts retryably.
+    async fn reroute_buffered_writes(&mut self) -> Result<(), Error> {
+        let mut moved_writes = Vec::new();
+        let mut moved_conditions = Vec::new();
+        for (range_id, range) in &self.participant_ranges {
+            let keys = range
+                .writes
+                .keys()
+                .chain(range.increments.keys())
+                .map(|key| (key, None))
+                .chain(
+                    range
+                        .conditions
+                        .iter()
+                        .enumerate()
+                        .map(|(i, c)| (&c.key, Some(i))),
+                );
+            for (key, condition) in keys {
+                let Some(current) = self
+                    .range_assignment_oracle
+                    .full_range_id_of_key(range_id.keyspace_id, key.clone())
+                    .await
+                else {
+                    continue;
+                };
+                if current == *range_id {
+                    continue;
+                }
+                // Pinned transactions do not follow keys off their ranges.
+                if self
+                    .pinned
+                    .as_ref()
+                    .is_some_and(|pinned| !pinned.contains_key(&current))
+                {
+                    return Err(Error::TransactionAborted(
+                        TransactionAbortReason::RangePartitioningChanged,
+                    ));
+                }
+                match condition {
+                    None => moved_writes.push((current, key.clone())),
+                    Some(i) => moved_conditions.push((*range_id, i, current)),
+                }
+            }
+        }
+        for (range_id, key) in moved_writes {
+            self.move_buffered_write(range_id, &key);
+        }
+        // Removed last first, so the indices of the others still hold.
+        for (range_id, i, current) in moved_conditions.into_iter().rev() {
+            let condition = self
+                .participant_ranges
+                .get_mut(&range_id)
+                .unwrap()
+                .conditions
+                .remove(i);
+            self.get_participant_range(current)
+                .conditions
+                .push(condition);
+        }
+        let empty: Vec<FullRangeId> = self
+            .participant_ranges
+            .iter()
+            .filter(|(_, range)| range.is_empty())
+            .map(|(range_id, _)| *range_id)
+            .collect();
+        for range_id in empty {
+            self.participant_ranges.remove(&range_id);
+            self.participant_order.retain(|id| *id != range_id);
+            self.participant_registry.remove_range(self.id, &range_id);
+        }
+        Ok(())
+    }
+
+    /// Pins the transaction to the ranges holding `keys`, and to the servers
+    /// leading those ranges now. Each server is looked up and connected to
+    /// up front, so a range with no known server, or whose server looks
+    /// down, fails here with `Error::RangeServerUnavailable` instead of
+    /// partway through the transaction. From then on, keys outside the
+    /// pinned ranges fail with `Error::RangeNotPinned`, and a pinned range
+    /// moving to another server aborts the transaction rather than being
+    /// followed there. Pinning again adds to the pinned ranges. If pinning
+    /// fails, the transaction is left as it was.
+    pub async fn pin(&mut self, keys: &[(Keyspace, Bytes)]) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("pin", None);
+        let res = self.pin_inner(keys).await;
+        self.record_op("pin", None, op_start, &res);
+        res
+    }
+
+    async fn pin_inner(&mut self, keys: &[(Keyspace, Bytes)]) -> Result<(), Error> {
+        self.check_still_running()?;
+        let mut range_ids = HashSet::new();
+        for (keyspace, key) in keys {
+            range_ids.insert(self.resolve_range_id(keyspace, key).await?);
+        }
+        let range_ids: Vec<FullRangeId> = range_ids.into_iter().collect();
+        let leaders = join_all(
+            range_ids
+                .iter()
+                .map(|range_id| self.range_client.leader_of(range_id)),
+        )
+        .await;
+        let mut pinned = HashMap::with_capacity(range_ids.len());
+        for (range_id, leader) in range_ids.into_iter().zip(leaders) {
+            let leader = leader.map_err(|e| match e {
+                rangeclient::client::Error::RangeIsNotLoaded => Error::RangeServerUnavailable,
+                e => Self::error_from_rangeclient_error(e),
+            })?;
+            pinned.insert(range_id, leader);
+        }
+        self.pinned.get_or_insert_with(HashMap::new).extend(pinned);
+        Ok(())
+    }
+
+    // Once pinned, the transaction only touches the pinned ranges, and only
+    // while they stay on the servers they were pinned to.
+    async fn check_pinned(&mut self, range_id: &FullRangeId) -> Result<(), Error> {
+        let leader = match &self.pinned {
+            None => return Ok(()),
+            Some(pinned) => match pinned.get(range_id) {
+                None => return Err(Error::RangeNotPinned),
+                Some(leader) => leader.clone(),
+            },
+        };
+        let current = self
+            .range_assignment_oracle
+            .host_of_range(range_id)
+            .await
+            .map(|host| host.identity);
+        if current != Some(leader) {
+            let _ = self.record_abort().await;
+            return Err(Error::TransactionAborted(
+                TransactionAbortReason::RangeLeadershipChanged,
+            ));
+        }
+        Ok(())
+    }
+
+    // Fails early for writes to keyspaces in read-only mode. Range servers
+    // enforce this again at prepare time, since the keyspace could be made
+    // read-only after we resolved it.
+    fn check_writable(&self, keyspace: &Keyspace) -> Result<(), Error> {
+        if self.read_only_keyspaces.contains(keyspace) {
+            return Err(Error::KeyspaceIsReadOnly);
+        }
+        Ok(())
+    }
+
     fn check_still_running(&self) -> Result<(), Error> {
         match self.state {
             State::Running => Ok(()),
             State::Aborted => Err(Error::TransactionAborted(TransactionAbortReason::Other)),
-            State::Preparing | State::Committed => Err(Error::TransactionNoLongerRunning),
+            State::Preparing | State::Committed { .. } => Err(Error::TransactionNoLongerRunning),
+            State::Unknown => Err(Error::TransactionDoneButStateUnknown),
+        }
+    }
+
+    fn remaining_time(&self) -> Duration {
+        let elapsed = (self.clock.now() - self.started)
+            .to_std()
+            .unwrap_or(Duration::ZERO);
+        self.transaction_info
+            .overall_timeout
+            .saturating_sub(elapsed)
+    }
+
+    // Time a read is allowed to take without eating into the time reserved for
+    // prepare and commit.
+    fn read_budget(&self) -> Result<Duration, Error> {
+        let reserve = self.transaction_info.overall_timeout / COMMIT_TIME_RESERVE_DIVISOR;
+        let remaining = self.remaining_time();
+        if remaining <= reserve {
+            return Err(Error::InsufficientTimeRemaining);
         }
+        Ok(remaining - reserve)
     }
 
     fn get_participant_range(&mut self, range_id: FullRangeId) -> &mut ParticipantRange {
+        if !self.participant_ranges.contains_key(&range_id) {
+            self.participant_registry
+                .add(&self.transaction_info, range_id);
+            self.participant_order.push(range_id);
+        }
         self.participant_ranges
             .entry(range_id)
             .or_insert_with(|| ParticipantRange {
                 readset: HashSet::new(),
-                writeset: HashMap::new(),
-                deleteset: HashSet::new(),
+                scanned: false,
+                writes: HashMap::new(),
+                conditions: Vec::new(),
+                increments: HashMap::new(),
+                ttls: HashMap::new(),
                 leader_sequence_number: 0,
             });
         self.participant_ranges.get_mut(&range_id).unwrap()
     }
 
-    pub async fn get(&mut self, keyspace: &Keyspace, key: Bytes) -> Result<Option<Bytes>, Error> {
+    fn record_read(&mut self, range_id: FullRangeId) {
+        if !self.read_order.contains(&range_id) {
+            self.read_order.push(range_id);
+        }
+    }
+
+    pub(crate) fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub(crate) fn begin_op(&self, op: &'static str, keyspace: Option<&Keyspace>) {
+        if let Some(instrumentation) = self.instrumentation.as_ref() {
+            instrumentation.on_request_start(&self.request_info(op, keyspace));
+        }
+    }
+
+    pub(crate) fn record_op<T>(
+        &mut self,
+        op: &'static str,
+        keyspace: Option<&Keyspace>,
+        op_start: Instant,
+        result: &Result<T, Error>,
+    ) {
+        if let Some(instrumentation) = self.instrumentation.as_ref() {
+            instrumentation.on_request_end(
+                &self.request_info(op, keyspace),
+                &RequestOutcome {
+                    duration: op_start.elapsed(),
+                    error: result.as_ref().err(),
+                },
+            );
+        }
+        if let Some(timeline) = self.timeline.as_mut() {
+            timeline.record(op, keyspace, op_start, result);
+        }
+        let outcome = match self.state {
+            State::Running | State::Preparing => return,
+            State::Aborted => "aborted",
+            State::Committed { .. } => "committed",
+            State::Unknown => "unknown",
+        };
+        if let Some(timeline) = self.timeline.take() {
+            timeline.finish(outcome);
+        }
+    }
+
+    fn request_info<'a>(
+        &'a self,
+        op: &'static str,
+        keyspace: Option<&'a Keyspace>,
+    ) -> RequestInfo<'a> {
+        RequestInfo {
+            operation: op,
+            transaction_id: self.id,
+            labels: &self.transaction_info.labels,
+            keyspace,
+        }
+    }
+
+    pub async fn get(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+    ) -> Result<Option<Bytes>, Error> {
+        let op_start = Instant::now();
+        self.begin_op("get", Some(keyspace));
+        let res = self.get_inner(keyspace, key.into()).await;
+        self.record_op("get", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn get_inner(&mut self, keyspace: &Keyspace, key: Bytes) -> Result<Option<Bytes>, Error> {
         self.check_still_running()?;
         let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
         let participant_range = self.get_participant_range(full_record_key.range_id);
         // Read-your-writes.
-        if let Some(v) = participant_range.writeset.get(&key) {
-            return Ok(Some(v.clone()));
-        }
-        if participant_range.deleteset.contains(&key) {
-            return Ok(None);
+        if let Some(v) = participant_range.writes.get(&key) {
+            return Ok(v.clone());
         }
+        // Only reads that reach a range count against the read budget.
+        let budget = self.read_budget()?;
+        let deadline = self.clock.instant() + budget;
         // TODO(tamer): errors.
-        let get_result = self
-            .range_client
-            .get(
+        let get_result = clock::timeout_at(
+            self.clock.as_ref(),
+            deadline,
+            self.range_client.get(
                 self.transaction_info.clone(),
                 &full_record_key.range_id,
                 vec![key.clone()],
-            )
-            .await
-            .unwrap();
+            ),
+        )
+        .await
+        .ok_or(Error::Timeout)?
+        .map_err(Self::error_from_rangeclient_error)?;
+        self.check_leader_sequence_number(
+            full_record_key.range_id,
+            get_result.leader_sequence_number,
+        )
+        .await?;
+        self.record_read(full_record_key.range_id);
         let participant_range = self.get_participant_range(full_record_key.range_id);
-        let current_range_leader_seq_num = get_result.leader_sequence_number;
-        if current_range_leader_seq_num != constants::INVALID_LEADER_SEQUENCE_NUMBER
-            && participant_range.leader_sequence_number
-                == constants::UNSET_LEADER_SEQUENCE_NUMBER as u64
-        {
-            participant_range.leader_sequence_number = current_range_leader_seq_num as u64;
-        };
-        if current_range_leader_seq_num != participant_range.leader_sequence_number as i64 {
-            let _ = self.record_abort().await;
-            return Err(Error::TransactionAborted(
-                TransactionAbortReason::RangeLeadershipChanged,
-            ));
-        }
         participant_range.readset.insert(key.clone());
 
         let val = get_result.vals.first().unwrap().clone();
-        Ok(val)
+        self.fold_increment(full_record_key.range_id, &key, val)
     }
 
-    pub async fn put(&mut self, keyspace: &Keyspace, key: Bytes, val: Bytes) -> Result<(), Error> {
-        self.check_still_running()?;
-        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
-        let participant_range = self.get_participant_range(full_record_key.range_id);
-        participant_range.deleteset.remove(&key);
-        participant_range.writeset.insert(key, val.clone());
-        Ok(())
+    /// Gets the value of `key` like `get`, but locks the range holding it
+    /// until the transaction finishes, even in keyspaces with optimistic
+    /// reads or in transactions with snapshot isolation. Meant for keys the
+    /// transaction goes on to write, e.g. a hot counter, so that concurrent
+    /// writers queue up behind each other instead of the later ones aborting
+    /// at prepare. A snapshot transaction aborts right away if the key was
+    /// written since its snapshot.
+    pub async fn get_for_update(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+    ) -> Result<Option<Bytes>, Error> {
+        let op_start = Instant::now();
+        self.begin_op("get_for_update", Some(keyspace));
+        let res = self.get_for_update_inner(keyspace, key.into()).await;
+        self.record_op("get_for_update", Some(keyspace), op_start, &res);
+        res
     }
 
-    pub async fn del(&mut self, keyspace: &Keyspace, key: Bytes) -> Result<(), Error> {
+    async fn get_for_update_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+    ) -> Result<Option<Bytes>, Error> {
         self.check_still_running()?;
+        let budget = self.read_budget()?;
+        let deadline = self.clock.instant() + budget;
         let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        // Unlike `get`, the range is asked even if the transaction wrote the
+        // key already, to take its lock.
+        let get_result = clock::timeout_at(
+            self.clock.as_ref(),
+            deadline,
+            self.range_client.get_for_update(
+                self.transaction_info.clone(),
+                &full_record_key.range_id,
+                vec![key.clone()],
+            ),
+        )
+        .await
+        .ok_or(Error::Timeout)?
+        .map_err(Self::error_from_rangeclient_error)?;
+        self.check_leader_sequence_number(
+            full_record_key.range_id,
+            get_result.leader_sequence_number,
+        )
+        .await?;
+        self.record_read(full_record_key.range_id);
         let participant_range = self.get_participant_range(full_record_key.range_id);
-        participant_range.writeset.remove(&key);
-        participant_range.deleteset.insert(key);
-        Ok(())
+        participant_range.readset.insert(key.clone());
+        // Read-your-writes.
+        if let Some(v) = participant_range.writes.get(&key) {
+            return Ok(v.clone());
+        }
+        let val = get_result.vals.first().unwrap().clone();
+        self.fold_increment(full_record_key.range_id, &key, val)
     }
 
-    async fn record_abort(&mut self) -> Result<(), Error> {
-        // We can directly set the state to Aborted here since given a transaction
-        //  cannot commit on its own without us deciding to commit it.
-        self.state = State::Aborted;
-        // Record the abort.
-        // TODO(tamer): handle errors here.
-        let mut abort_join_set = JoinSet::new();
-        for range_id in self.participant_ranges.keys() {
-            let range_id = *range_id;
-            let range_client = self.range_client.clone();
-            let transaction_info = self.transaction_info.clone();
-            abort_join_set.spawn_on(
-                async move {
-                    range_client
-                        .abort_transaction(transaction_info, &range_id)
-                        .await
-                },
-                &self.runtime,
-            );
-        }
-        let outcome = self
-            .tx_state_store
-            .try_abort_transaction(self.id)
-            .await
-            .unwrap();
-        match outcome {
-            OpResult::TransactionIsAborted => (),
-            OpResult::TransactionIsCommitted(_) => {
-                panic!("transaction committed without coordinator consent!")
-            }
-        }
-        while abort_join_set.join_next().await.is_some() {}
-        Ok(())
+    /// Gets the value `key` had as of `epoch`, i.e. what the transactions
+    /// committed at lower epochs wrote. The read takes no locks and is not
+    /// validated at commit, so it neither conflicts with nor waits behind
+    /// writers, e.g. for analytics reading a consistent view across several
+    /// keyspaces. It does not see the transaction's own writes. Ranges can
+    /// only serve epochs since their last compaction, otherwise this fails
+    /// with `Error::SnapshotTooOld`.
+    pub async fn get_snapshot(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+        epoch: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        let op_start = Instant::now();
+        self.begin_op("get_snapshot", Some(keyspace));
+        let res = self.get_snapshot_inner(keyspace, key.into(), epoch).await;
+        self.record_op("get_snapshot", Some(keyspace), op_start, &res);
+        res
     }
 
-    pub async fn abort(&mut self) -> Result<(), Error> {
-        match self.state {
-            State::Aborted => return Ok(()),
-            _ => {
-                self.check_still_running()?;
-            }
-        };
-        self.record_abort().await
+    async fn get_snapshot_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+        epoch: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        self.check_still_running()?;
+        // Epoch 0 asks range servers for a regular read.
+        if epoch == 0 {
+            return Err(Error::SnapshotTooOld);
+        }
+        let budget = self.read_budget()?;
+        let deadline = self.clock.instant() + budget;
+        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        let get_result = clock::timeout_at(
+            self.clock.as_ref(),
+            deadline,
+            self.range_client.get_at_epoch(
+                self.transaction_info.clone(),
+                &full_record_key.range_id,
+                vec![key],
+                epoch,
+            ),
+        )
+        .await
+        .ok_or(Error::Timeout)?
+        .map_err(Self::error_from_rangeclient_error)?;
+        Ok(get_result.vals.first().unwrap().clone())
     }
 
-    fn error_from_rangeclient_error(_err: rangeclient::client::Error) -> Error {
-        // TODO(tamer): handle
-        panic!("encountered rangeclient error, translation not yet implemented.")
+    /// Gets several keys of the keyspace at once, with a single request to
+    /// each range they fall in. The values are returned in the order of
+    /// `keys`.
+    pub async fn get_many(
+        &mut self,
+        keyspace: &Keyspace,
+        keys: Vec<Bytes>,
+    ) -> Result<Vec<Option<Bytes>>, Error> {
+        let op_start = Instant::now();
+        self.begin_op("get_many", Some(keyspace));
+        let res = self.get_many_inner(keyspace, keys).await;
+        self.record_op("get_many", Some(keyspace), op_start, &res);
+        res
     }
 
-    pub async fn commit(&mut self) -> Result<(), Error> {
+    async fn get_many_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        keys: Vec<Bytes>,
+    ) -> Result<Vec<Option<Bytes>>, Error> {
         self.check_still_running()?;
-        self.state = State::Preparing;
-        let mut prepare_join_set = JoinSet::new();
-        for (range_id, info) in &self.participant_ranges {
-            let range_id = *range_id;
+        let mut vals = vec![None; keys.len()];
+        // Positions in `keys` of the keys to read from each range.
+        let mut positions_by_range: HashMap<FullRangeId, Vec<usize>> = HashMap::new();
+        // Keys given more than once are only read at their first position,
+        // and copied from it to the others, so that an increment folded into
+        // the first read is seen at all of them.
+        let mut first_positions: HashMap<&Bytes, usize> = HashMap::new();
+        let mut duplicates = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            if let Some(&first) = first_positions.get(key) {
+                duplicates.push((i, first));
+                continue;
+            }
+            first_positions.insert(key, i);
+            let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+            let participant_range = self.get_participant_range(full_record_key.range_id);
+            // Read-your-writes.
+            match participant_range.writes.get(key) {
+                Some(v) => vals[i] = v.clone(),
+                None => positions_by_range
+                    .entry(full_record_key.range_id)
+                    .or_default()
+                    .push(i),
+            }
+        }
+        if positions_by_range.is_empty() {
+            return Ok(Self::copy_duplicates(vals, &duplicates));
+        }
+        let budget = self.read_budget()?;
+        let deadline = self.clock.instant() + budget;
+        let mut get_join_set = JoinSet::new();
+        for (range_id, positions) in positions_by_range {
             let range_client = self.range_client.clone();
             let transaction_info = self.transaction_info.clone();
-            let has_reads = !info.readset.is_empty();
-            let writes: Vec<Record> = info
-                .writeset
-                .iter()
-                .map(|(k, v)| Record {
-                    key: k.clone(),
-                    val: v.clone(),
-                })
-                .collect();
-            let deletes: Vec<Bytes> = info.deleteset.iter().cloned().collect();
-            prepare_join_set.spawn_on(
-                async move {
-                    range_client
-                        .prepare_transaction(
-                            transaction_info,
-                            &range_id,
-                            has_reads,
-                            &writes,
-                            &deletes,
-                        )
-                        .await
-                },
-                &self.runtime,
-            );
+            let range_keys = positions.iter().map(|&i| keys[i].clone()).collect();
+            self.tasks.spawn(&mut get_join_set, async move {
+                let res = range_client
+                    .get(transaction_info, &range_id, range_keys)
+                    .await;
+                (range_id, positions, res)
+            });
         }
-        let mut epoch = self.epoch_reader.read_epoch().await.unwrap();
-        let mut epoch_leases = Vec::new();
-
-        while let Some(res) = prepare_join_set.join_next().await {
-            let res = match res {
-                Err(_) => {
-                    let _ = self.record_abort().await;
-                    return Err(Error::TransactionAborted(
-                        TransactionAbortReason::PrepareFailed,
-                    ));
+        loop {
+            let res =
+                match clock::timeout_at(self.clock.as_ref(), deadline, get_join_set.join_next())
+                    .await
+                {
+                    None => return Err(Error::Timeout),
+                    Some(None) => break,
+                    Some(Some(res)) => res,
+                };
+            let (range_id, positions, res) = match res {
+                Err(_) | Ok(None) => {
+                    return Err(Error::InternalError(Arc::new(std::io::Error::other(
+                        "get task failed",
+                    ))))
                 }
-                Ok(res) => res,
+                Ok(Some(res)) => res,
             };
-            let res = res.map_err(Self::error_from_rangeclient_error)?;
-            epoch_leases.push(res.epoch_lease);
-            if res.highest_known_epoch > epoch {
-                epoch = res.highest_known_epoch;
+            let get_result = res.map_err(Self::error_from_rangeclient_error)?;
+            self.check_leader_sequence_number(range_id, get_result.leader_sequence_number)
+                .await?;
+            self.record_read(range_id);
+            for (i, val) in positions.into_iter().zip(get_result.vals) {
+                let participant_range = self.get_participant_range(range_id);
+                participant_range.readset.insert(keys[i].clone());
+                vals[i] = self.fold_increment(range_id, &keys[i], val)?;
             }
         }
+        Ok(Self::copy_duplicates(vals, &duplicates))
+    }
 
-        for lease in &epoch_leases {
-            if lease.lower_bound_inclusive <= epoch && lease.upper_bound_inclusive >= epoch {
-                continue;
-            }
-            // Uh-oh, lease expired, must abort.
-            let _ = self.record_abort().await;
-            return Err(Error::TransactionAborted(
-                TransactionAbortReason::RangeLeaseExpired,
-            ));
+    fn copy_duplicates(
+        mut vals: Vec<Option<Bytes>>,
+        duplicates: &[(usize, usize)],
+    ) -> Vec<Option<Bytes>> {
+        for &(i, first) in duplicates {
+            vals[i] = vals[first].clone();
         }
+        vals
+    }
 
-        // At this point we are prepared!
-        // Attempt to commit.
-        match self
+    // A transaction must only ever observe one leader of each range it reads,
+    // so it aborts if a read reached a different leader than earlier ones.
+    async fn check_leader_sequence_number(
+        &mut self,
+        range_id: FullRangeId,
+        current_range_leader_seq_num: i64,
+    ) -> Result<(), Error> {
+        let participant_range = self.get_participant_range(range_id);
+        if current_range_leader_seq_num != constants::INVALID_LEADER_SEQUENCE_NUMBER
+            && participant_range.leader_sequence_number
+                == constants::UNSET_LEADER_SEQUENCE_NUMBER as u64
+        {
+            participant_range.leader_sequence_number = current_range_leader_seq_num as u64;
+        };
+        if current_range_leader_seq_num != participant_range.leader_sequence_number as i64 {
+            let _ = self.record_abort().await;
+            return Err(Error::TransactionAborted(
+                TransactionAbortReason::RangeLeadershipChanged,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `limit` records with keys from `start_key` (inclusive)
+    /// to `end_key` (exclusive), in key order, including the transaction's
+    /// own buffered writes. A missing key leaves that end of the range open.
+    ///
+    /// Every range overlapping the keys is read in full as far as conflicts
+    /// go, so scans take (or, for optimistic reads, validate) the whole range.
+    pub async fn scan(
+        &mut self,
+        keyspace: &Keyspace,
+        start_key: Option<Bytes>,
+        end_key: Option<Bytes>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let op_start = Instant::now();
+        self.begin_op("scan", Some(keyspace));
+        let key_range = KeyRange {
+            lower_bound_inclusive: start_key,
+            upper_bound_exclusive: end_key,
+        };
+        let res = self.scan_inner(keyspace, key_range, limit).await;
+        self.record_op("scan", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn scan_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        self.check_still_running()?;
+        let budget = self.read_budget()?;
+        let deadline = self.clock.instant() + budget;
+        let keyspace_id = self.resolve_keyspace(keyspace).await?;
+        let range_ids = self
+            .range_assignment_oracle
+            .full_range_ids_of_key_range(keyspace_id, &key_range)
+            .await
+            .ok_or(Error::KeyspaceDoesNotExist)?;
+        for range_id in &range_ids {
+            self.check_pinned(range_id).await?;
+        }
+        if limit == Some(0) || key_range.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Buffered deletes may hide some of the records read, so each range
+        // is asked for enough more to still fill the limit. They are counted
+        // whichever range they were buffered under, see below.
+        let deletes: usize = self
+            .participant_ranges
+            .iter()
+            .filter(|(range_id, _)| range_id.keyspace_id == keyspace_id)
+            .map(|(_, info)| {
+                info.writes
+                    .iter()
+                    .filter(|(k, v)| v.is_none() && key_range.includes((*k).clone()))
+                    .count()
+            })
+            .sum();
+        let scans = range_ids.iter().map(|range_id| {
+            let range_limit = limit.map(|limit| limit + deletes);
+            let transaction_info = self.transaction_info.clone();
+            let range_client = &self.range_client;
+            let key_range = &key_range;
+            async move {
+                let res = range_client
+                    .scan(transaction_info, range_id, key_range, range_limit)
+                    .await;
+                (*range_id, range_limit, res)
+            }
+        });
+        let results = clock::timeout_at(self.clock.as_ref(), deadline, join_all(scans))
+            .await
+            .ok_or(Error::Timeout)?;
+
+        let mut records = BTreeMap::new();
+        // A range that returned all the records it was asked for may hold
+        // more past the last one, so nothing beyond it can be returned.
+        let mut cutoff: Option<Bytes> = None;
+        for (range_id, range_limit, res) in results {
+            let scan_result = res.map_err(Self::error_from_rangeclient_error)?;
+            self.check_leader_sequence_number(range_id, scan_result.leader_sequence_number)
+                .await?;
+            self.record_read(range_id);
+            self.get_participant_range(range_id).scanned = true;
+            if range_limit.is_some_and(|l| scan_result.records.len() >= l) {
+                let (last, _) = scan_result.records.last().unwrap();
+                cutoff = Some(match cutoff {
+                    Some(cutoff) if cutoff < last => cutoff,
+                    _ => last.clone(),
+                });
+            }
+            records.extend(scan_result.records);
+        }
+        // Read-your-writes. Writes are buffered under the range that held
+        // their key when last written, which a split or merge since may have
+        // replaced with the ones scanned, so the writes buffered under every
+        // range of the keyspace are overlaid. A key is only buffered under
+        // one range, see `move_buffered_write`.
+        let written_ranges: Vec<FullRangeId> = self
+            .participant_ranges
+            .keys()
+            .filter(|range_id| range_id.keyspace_id == keyspace_id)
+            .copied()
+            .collect();
+        for range_id in &written_ranges {
+            let info = &self.participant_ranges[range_id];
+            for (key, val) in &info.writes {
+                if !key_range.includes(key.clone()) {
+                    continue;
+                }
+                match val {
+                    Some(val) => records.insert(key.clone(), val.clone()),
+                    None => records.remove(key),
+                };
+            }
+        }
+        // Past the cutoff the values the increments apply to are unknown,
+        // and nothing is returned anyway.
+        for range_id in &written_ranges {
+            let info = &self.participant_ranges[range_id];
+            let keys: Vec<Bytes> = info
+                .increments
+                .keys()
+                .filter(|key| key_range.includes((*key).clone()))
+                .filter(|key| cutoff.as_ref().is_none_or(|cutoff| *key <= cutoff))
+                .cloned()
+                .collect();
+            for key in keys {
+                let val = records.get(&key).cloned();
+                if let Some(val) = self.fold_increment(*range_id, &key, val)? {
+                    records.insert(key, val);
+                }
+            }
+        }
+        Ok(records
+            .into_iter()
+            .take_while(|(key, _)| cutoff.as_ref().is_none_or(|cutoff| key <= cutoff))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// Buffers a write of the key until commit. Keys and values are kept as
+    /// given, without copying, until they are encoded into the prepare
+    /// request, so a `Vec` or `Bytes` owned by the caller can be handed over
+    /// as is.
+    pub async fn put(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+        val: impl Into<Bytes>,
+    ) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("put", Some(keyspace));
+        let res = self.put_inner(keyspace, key.into(), val.into()).await;
+        self.record_op("put", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn put_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+        val: Bytes,
+    ) -> Result<(), Error> {
+        self.check_still_running()?;
+        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        self.check_writable(keyspace)?;
+        self.buffer_write(full_record_key.range_id, key, Some(val));
+        Ok(())
+    }
+
+    /// Buffers a write of the key until commit, like `put`, after which the
+    /// key expires once `ttl` has passed. The TTL counts from when the range
+    /// applies the commit; an expired key reads as absent. Writing the key
+    /// again in the transaction replaces the TTL along with the value.
+    pub async fn put_with_ttl(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+        val: impl Into<Bytes>,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("put_with_ttl", Some(keyspace));
+        let res = self
+            .put_with_ttl_inner(keyspace, key.into(), val.into(), ttl)
+            .await;
+        self.record_op("put_with_ttl", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn put_with_ttl_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+        val: Bytes,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        self.check_still_running()?;
+        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        self.check_writable(keyspace)?;
+        let range_id = full_record_key.range_id;
+        self.buffer_write(range_id, key.clone(), Some(val));
+        self.get_participant_range(range_id).ttls.insert(key, ttl);
+        Ok(())
+    }
+
+    /// Buffers a write of the key until commit, like `put`, that only takes
+    /// effect if the key then holds `expected`, or is absent if `expected`
+    /// is None. The range checks this at prepare, without the transaction
+    /// having to read the key first, and aborts the transaction with
+    /// `Error::ConditionFailed` if it does not hold.
+    ///
+    /// The condition is on the value committed by other transactions: writes
+    /// of the key earlier in this transaction don't count.
+    pub async fn put_if(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+        expected: Option<Bytes>,
+        val: impl Into<Bytes>,
+    ) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("put_if", Some(keyspace));
+        let res = self
+            .put_if_inner(keyspace, key.into(), expected, val.into())
+            .await;
+        self.record_op("put_if", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn put_if_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+        expected: Option<Bytes>,
+        val: Bytes,
+    ) -> Result<(), Error> {
+        self.check_still_running()?;
+        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        self.check_writable(keyspace)?;
+        self.get_participant_range(full_record_key.range_id)
+            .conditions
+            .push(Condition {
+                key: key.clone(),
+                expected,
+            });
+        self.buffer_write(full_record_key.range_id, key, Some(val));
+        Ok(())
+    }
+
+    /// Adds `delta` to the counter at the key, see `Increment` for how
+    /// counters are stored. Unlike reading the counter and writing it back,
+    /// this takes no lock until commit: the range applies the increment at
+    /// prepare, under its lock, so concurrent increments of a counter queue
+    /// up rather than abort each other.
+    ///
+    /// Reading the key later in the transaction returns the incremented value,
+    /// and from then on the transaction depends on the value read like on
+    /// any other read. Commit fails with `Error::InvalidIncrement` if the key
+    /// holds something other than a counter, or the sum overflows.
+    pub async fn increment(
+        &mut self,
+        keyspace: &Keyspace,
+        key: impl Into<Bytes>,
+        delta: i64,
+    ) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("increment", Some(keyspace));
+        let res = self.increment_inner(keyspace, key.into(), delta).await;
+        self.record_op("increment", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn increment_inner(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Bytes,
+        delta: i64,
+    ) -> Result<(), Error> {
+        self.check_still_running()?;
+        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        self.check_writable(keyspace)?;
+        let participant_range = self.get_participant_range(full_record_key.range_id);
+        // A key the transaction wrote already holds the value to add to.
+        if let Some(val) = participant_range.writes.get(&key) {
+            let increment = Increment {
+                key: key.clone(),
+                delta,
+            };
+            let val = increment
+                .apply(val.as_deref())
+                .ok_or(Error::InvalidIncrement)?;
+            self.buffer_write(full_record_key.range_id, key, Some(val));
+            return Ok(());
+        }
+        let (sum, is_duplicate) = match participant_range.increments.get(&key) {
+            None => (delta, false),
+            Some(pending) => (
+                pending.checked_add(delta).ok_or(Error::InvalidIncrement)?,
+                true,
+            ),
+        };
+        participant_range.increments.insert(key, sum);
+        self.stats.writes += 1;
+        if is_duplicate {
+            self.stats.duplicate_writes += 1;
+        }
+        Ok(())
+    }
+
+    // Once the transaction reads a key it incremented, it knows the value the
+    // increment applies to, so the increment turns into a plain write of the
+    // sum. Returns the value as the transaction sees it.
+    fn fold_increment(
+        &mut self,
+        range_id: FullRangeId,
+        key: &Bytes,
+        val: Option<Bytes>,
+    ) -> Result<Option<Bytes>, Error> {
+        let participant_range = self.get_participant_range(range_id);
+        let Some(delta) = participant_range.increments.get(key).copied() else {
+            return Ok(val);
+        };
+        let increment = Increment {
+            key: key.clone(),
+            delta,
+        };
+        let val = increment
+            .apply(val.as_deref())
+            .ok_or(Error::InvalidIncrement)?;
+        participant_range.increments.remove(key);
+        participant_range
+            .writes
+            .insert(key.clone(), Some(val.clone()));
+        Ok(Some(val))
+    }
+
+    pub async fn del(&mut self, keyspace: &Keyspace, key: impl Into<Bytes>) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("del", Some(keyspace));
+        let res = self.del_inner(keyspace, key.into()).await;
+        self.record_op("del", Some(keyspace), op_start, &res);
+        res
+    }
+
+    async fn del_inner(&mut self, keyspace: &Keyspace, key: Bytes) -> Result<(), Error> {
+        self.check_still_running()?;
+        let full_record_key = self.resolve_full_record_key(keyspace, key.clone()).await?;
+        self.check_writable(keyspace)?;
+        self.buffer_write(full_record_key.range_id, key, None);
+        Ok(())
+    }
+
+    fn buffer_write(&mut self, range_id: FullRangeId, key: Bytes, val: Option<Bytes>) {
+        let participant_range = self.get_participant_range(range_id);
+        // A put or delete replaces a pending increment of the key.
+        let had_increment = participant_range.increments.remove(&key).is_some();
+        participant_range.ttls.remove(&key);
+        let is_duplicate = participant_range.writes.insert(key, val).is_some() || had_increment;
+        self.stats.writes += 1;
+        if is_duplicate {
+            self.stats.duplicate_writes += 1;
+        }
+    }
+
+    pub fn stats(&self) -> TransactionStats {
+        let buffered_write_bytes = self
+            .participant_ranges
+            .values()
+            .flat_map(|range| {
+                let writes = range
+                    .writes
+                    .iter()
+                    .map(|(k, v)| k.len() + v.as_ref().map_or(0, |v| v.len()));
+                let increments = range.increments.keys().map(|k| k.len() + 8);
+                writes.chain(increments)
+            })
+            .sum();
+        TransactionStats {
+            buffered_write_bytes,
+            ..self.stats
+        }
+    }
+
+    /// Has `participant` take part in the commit of the transaction, to
+    /// commit changes outside of the database along with it, e.g. to an
+    /// outbox in another database. It is prepared along with the ranges of
+    /// the transaction, and told the outcome once decided.
+    pub fn enroll(&mut self, participant: Arc<dyn ExternalParticipant>) -> Result<(), Error> {
+        self.check_still_running()?;
+        self.external_participants.push(participant);
+        Ok(())
+    }
+
+    /// Marks the current point of the transaction, to later undo the writes
+    /// made after it with `rollback_to` without aborting the transaction.
+    pub fn savepoint(&mut self) -> Result<SavepointId, Error> {
+        self.check_still_running()?;
+        let ranges = self
+            .participant_ranges
+            .iter()
+            .map(|(range_id, range)| {
+                let savepoint = RangeSavepoint {
+                    writes: range.writes.clone(),
+                    conditions: range.conditions.len(),
+                    increments: range.increments.clone(),
+                    ttls: range.ttls.clone(),
+                };
+                (*range_id, savepoint)
+            })
+            .collect();
+        let generation = self.next_savepoint_generation;
+        self.next_savepoint_generation += 1;
+        self.savepoints.push(Savepoint { generation, ranges });
+        Ok(SavepointId {
+            transaction_id: self.id,
+            index: self.savepoints.len() - 1,
+            generation,
+        })
+    }
+
+    /// Undoes the puts, deletes, conditional puts and increments made since
+    /// `savepoint`, which stays in place to roll back to again. Savepoints
+    /// taken after it are discarded.
+    ///
+    /// Reads made since are kept: their values may have shaped what the
+    /// transaction did next, so the transaction still only commits if they
+    /// hold, and the range locks taken for them stay held until it ends.
+    pub fn rollback_to(&mut self, savepoint: SavepointId) -> Result<(), Error> {
+        self.check_still_running()?;
+        let held = self
+            .savepoints
+            .get(savepoint.index)
+            .is_some_and(|held| held.generation == savepoint.generation);
+        if savepoint.transaction_id != self.id || !held {
+            return Err(Error::UnknownSavepoint);
+        }
+        self.savepoints.truncate(savepoint.index + 1);
+        let snapshot = &self.savepoints[savepoint.index].ranges;
+        for (range_id, range) in self.participant_ranges.iter_mut() {
+            // Ranges first used after the savepoint stay participants, with
+            // nothing to write.
+            match snapshot.get(range_id) {
+                Some(savepoint) => {
+                    range.writes = savepoint.writes.clone();
+                    range.conditions.truncate(savepoint.conditions);
+                    range.increments = savepoint.increments.clone();
+                    range.ttls = savepoint.ttls.clone();
+                }
+                None => {
+                    range.writes.clear();
+                    range.conditions.clear();
+                    range.increments.clear();
+                    range.ttls.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports what committing the transaction would do as of now: which
+    /// ranges and servers it would involve, how many messages it would take
+    /// and how large they would be, in which order the range locks get taken,
+    /// and how well it kept to colocation groups. Neither commits nor affects
+    /// a later commit, so it helps with tuning access patterns, e.g. to touch
+    /// fewer ranges.
+    pub async fn explain(&self) -> Result<ExplainReport, Error> {
+        self.check_still_running()?;
+        let mut participants = Vec::with_capacity(self.participant_order.len());
+        for range_id in &self.participant_order {
+            let info = &self.participant_ranges[range_id];
+            let server = self
+                .range_assignment_oracle
+                .host_of_range(range_id)
+                .await
+                .map(|host| host.identity);
+            let puts = info.writes.values().filter(|v| v.is_some()).count();
+            participants.push(ParticipantPlan {
+                range_id: *range_id,
+                server,
+                keys_read: info.readset.len(),
+                scanned: info.scanned,
+                puts,
+                deletes: info.writes.len() - puts,
+                conditions: info.conditions.len(),
+                increments: info.increments.len(),
+                prepare_payload_bytes: info.prepare_payload_bytes(),
+            });
+        }
+        let lock_order = self
+            .read_order
+            .iter()
+            .chain(
+                self.participant_order
+                    .iter()
+                    .filter(|range_id| !self.read_order.contains(range_id)),
+            )
+            .copied()
+            .collect();
+        Ok(ExplainReport {
+            range_server_messages: 2 * participants.len(),
+            prepare_payload_bytes: participants.iter().map(|p| p.prepare_payload_bytes).sum(),
+            participants,
+            lock_order,
+            colocation: self.colocation_report(),
+        })
+    }
+
+    fn colocation_report(&self) -> Option<ColocationReport> {
+        // The ranges each group's keys were found in.
+        let mut groups: HashMap<(KeyspaceId, &[u8]), HashSet<FullRangeId>> = HashMap::new();
+        for (range_id, info) in &self.participant_ranges {
+            let Some(colocation) = self.colocations.get(&range_id.keyspace_id) else {
+                continue;
+            };
+            let keys = info.readset.iter().chain(info.writes.keys());
+            for key in keys.chain(info.increments.keys()) {
+                groups
+                    .entry((range_id.keyspace_id, colocation.group_prefix(key)))
+                    .or_default()
+                    .insert(*range_id);
+            }
+        }
+        if groups.is_empty() {
+            return None;
+        }
+        let ranges: HashSet<&FullRangeId> = groups.values().flatten().collect();
+        Some(ColocationReport {
+            groups: groups.len(),
+            ranges: ranges.len(),
+            split_groups: groups.values().filter(|ranges| ranges.len() > 1).count(),
+        })
+    }
+
+    fn notify_outcome(&self, decision: Decision) {
+        // Once decided, a transaction can no longer be force-aborted so there
+        // is no need to keep track of its participants.
+        self.participant_registry.remove(self.id);
+        let namespaces = self.namespaces();
+        if let Some(logger) = &self.lifecycle_logger {
+            if namespaces.len() > 1 {
+                let outcome = match decision {
+                    Decision::Committed { .. } => "committed",
+                    Decision::Aborted => "aborted",
+                };
+                logger.audit_cross_namespace(&self.transaction_info, &namespaces, outcome);
+            }
+        }
+        self.outcome_notifier.notify(TransactionOutcome {
+            id: self.id,
+            namespaces: namespaces.into_iter().collect(),
+            labels: self.transaction_info.labels.clone(),
+            decision,
+        });
+    }
+
+    async fn record_abort(&mut self) -> Result<(), Error> {
+        // We can directly set the state to Aborted here since given a transaction
+        //  cannot commit on its own without us deciding to commit it.
+        self.state = State::Aborted;
+        let abort_join_set = self.send_range_aborts();
+        // Record the abort.
+        // TODO(tamer): handle errors here.
+        let outcome = self
+            .tx_state_store
+            .try_abort_transaction(self.id)
+            .await
+            .unwrap();
+        match outcome {
+            OpResult::TransactionIsAborted => (),
+            OpResult::TransactionIsCommitted(_) => {
+                panic!("transaction committed without coordinator consent!")
+            }
+        }
+        self.finish_abort(abort_join_set).await;
+        Ok(())
+    }
+
+    // Tells the participant ranges that the transaction aborted, in the
+    // background until `finish_abort` waits for them.
+    fn send_range_aborts(&self) -> JoinSet<Option<Result<(), rangeclient::client::Error>>> {
+        let mut abort_join_set = JoinSet::new();
+        for range_id in self.participant_ranges.keys() {
+            let range_id = *range_id;
+            let range_client = self.range_client.clone();
+            let transaction_info = self.transaction_info.clone();
+            self.tasks.spawn(&mut abort_join_set, async move {
+                range_client
+                    .abort_transaction(transaction_info, &range_id)
+                    .await
+            });
+        }
+        abort_join_set
+    }
+
+    // Once the abort is recorded in the tx_state_store, notifies the
+    // subscribers and the external participants, and waits for the ranges.
+    async fn finish_abort(
+        &mut self,
+        mut abort_join_set: JoinSet<Option<Result<(), rangeclient::client::Error>>>,
+    ) {
+        self.notify_outcome(Decision::Aborted);
+        join_all(
+            self.external_participants
+                .iter()
+                .map(|participant| participant.abort(self.id)),
+        )
+        .await;
+        while abort_join_set.join_next().await.is_some() {}
+        // The transaction is over, nothing it spawned is needed anymore.
+        self.tasks.cancel();
+    }
+
+    pub async fn abort(&mut self) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("abort", None);
+        let res = self.abort_inner().await;
+        self.record_op("abort", None, op_start, &res);
+        res
+    }
+
+    async fn abort_inner(&mut self) -> Result<(), Error> {
+        match self.state {
+            State::Aborted => return Ok(()),
+            _ => {
+                self.check_still_running()?;
+            }
+        };
+        self.record_abort().await
+    }
+
+    fn error_from_rangeclient_error(err: rangeclient::client::Error) -> Error {
+        match err {
+            rangeclient::client::Error::KeyspaceIsReadOnly => Error::KeyspaceIsReadOnly,
+            rangeclient::client::Error::WriteRejected => Error::WriteRejected,
+            rangeclient::client::Error::ConditionFailed => Error::ConditionFailed,
+            rangeclient::client::Error::InvalidIncrement => Error::InvalidIncrement,
+            rangeclient::client::Error::RangeFrozen => Error::RangeFrozen,
+            rangeclient::client::Error::SnapshotTooOld => Error::SnapshotTooOld,
+            // The range is moving to another server, which a retry reaches.
+            rangeclient::client::Error::RangeDraining => {
+                Error::TransactionAborted(TransactionAbortReason::RangeLeadershipChanged)
+            }
+            // The range is being reloaded after storage errors, possibly on
+            // another server, and its locks are lost either way.
+            rangeclient::client::Error::RangeFaulted => {
+                Error::TransactionAborted(TransactionAbortReason::RangeLeadershipChanged)
+            }
+            rangeclient::client::Error::Timeout => Error::Timeout,
+            rangeclient::client::Error::ConnectionClosed => Error::RangeServerUnavailable,
+            rangeclient::client::Error::KeyspaceDoesNotExist => {
+                Error::TransactionAborted(TransactionAbortReason::KeyspaceDropped)
+            }
+            rangeclient::client::Error::Overloaded => {
+                Error::TransactionAborted(TransactionAbortReason::RangeOverloaded)
+            }
+            rangeclient::client::Error::LockWaitTimeout => {
+                Error::TransactionAborted(TransactionAbortReason::LockWaitTimeout)
+            }
+            rangeclient::client::Error::TransactionAborted(
+                rangeclient::client::TransactionAbortReason::Deadlock,
+            ) => Error::TransactionAborted(TransactionAbortReason::DeadlockPrevention),
+            // The wire status carries no other reason, but range servers
+            // otherwise only abort transactions over conflicts.
+            rangeclient::client::Error::TransactionAborted(_) => {
+                Error::TransactionAborted(TransactionAbortReason::Conflict)
+            }
+            // The range was split or merged away, or is being split, since
+            // the transaction resolved its keys. A retry resolves them again.
+            rangeclient::client::Error::RangeIsNotLoaded
+            | rangeclient::client::Error::RangeDoesNotExist
+            | rangeclient::client::Error::KeyIsOutOfRange
+            | rangeclient::client::Error::RangeBusy => {
+                Error::TransactionAborted(TransactionAbortReason::RangePartitioningChanged)
+            }
+            rangeclient::client::Error::RangeOwnershipLost => {
+                Error::TransactionAborted(TransactionAbortReason::RangeLeadershipChanged)
+            }
+            // The range no longer knows the transaction, e.g. because it was
+            // reloaded, so the locks it held are gone.
+            rangeclient::client::Error::UnknownTransaction => {
+                Error::TransactionAborted(TransactionAbortReason::TransactionLockLost)
+            }
+            // Prepares wait these out, other requests give up.
+            rangeclient::client::Error::PrepareBacklogFull { .. }
+            | rangeclient::client::Error::WriteStalled { .. } => {
+                Error::TransactionAborted(TransactionAbortReason::RangeOverloaded)
+            }
+            rangeclient::client::Error::InternalError(e) => Error::InternalError(e),
+            e @ (rangeclient::client::Error::InvalidRequestFormat
+            | rangeclient::client::Error::CacheIsFull
+            | rangeclient::client::Error::PrefetchError) => Error::InternalError(Arc::new(
+                std::io::Error::other(format!("range server error: {:?}", e)),
+            )),
+        }
+    }
+
+    /// Runs the checks that committing would start with, without committing
+    /// or giving up any locks: that time is left, that every range read from
+    /// is still led by the same leader and still holds the transaction's
+    /// locks (or, for optimistic reads, has not committed anything since),
+    /// and that the keyspaces written to still exist and are writable.
+    ///
+    /// This is a point-in-time answer: a transaction that validates can still
+    /// abort at commit, e.g. if its epoch lease expires or another
+    /// transaction commits in between. Errors reaching a range are returned
+    /// as is, since they say nothing about whether the commit would succeed.
+    pub async fn validate(&mut self) -> Result<Validation, Error> {
+        let op_start = Instant::now();
+        self.begin_op("validate", None);
+        let res = self.validate_inner().await;
+        self.record_op("validate", None, op_start, &res);
+        res
+    }
+
+    async fn validate_inner(&mut self) -> Result<Validation, Error> {
+        self.check_still_running()?;
+        if self.remaining_time().is_zero() {
+            return Ok(Validation::WouldFail(Error::TransactionAborted(
+                TransactionAbortReason::TransactionTimeout,
+            )));
+        }
+        let mut validate_join_set = JoinSet::new();
+        for (range_id, info) in &self.participant_ranges {
+            // Commit drops these rather than prepare them.
+            if info.is_empty() {
+                continue;
+            }
+            let range_id = *range_id;
+            let range_client = self.range_client.clone();
+            let transaction_info = self.transaction_info.clone();
+            // As in prepare, snapshot reads are not checked at all.
+            let has_reads =
+                info.has_reads() && self.transaction_info.isolation == IsolationLevel::Serializable;
+            let has_writes = !info.writes.is_empty() || !info.increments.is_empty();
+            let leader_sequence_number = info.leader_sequence_number;
+            self.tasks.spawn(&mut validate_join_set, async move {
+                let res = range_client
+                    .validate_transaction(transaction_info, &range_id, has_reads, has_writes)
+                    .await;
+                (range_id, has_reads, leader_sequence_number, res)
+            });
+        }
+        let mut validation = Validation::WouldCommit;
+        while let Some(res) = validate_join_set.join_next().await {
+            let (range_id, has_reads, leader_sequence_number, res) = match res {
+                Err(_) | Ok(None) => {
+                    return Err(Error::InternalError(Arc::new(std::io::Error::other(
+                        "validation task failed",
+                    ))))
+                }
+                Ok(Some(res)) => res,
+            };
+            let failure = match res {
+                Ok(current) => {
+                    if has_reads && current as u64 != leader_sequence_number {
+                        Error::TransactionAborted(TransactionAbortReason::RangeLeadershipChanged)
+                    } else {
+                        continue;
+                    }
+                }
+                // Commit waits these out.
+                Err(rangeclient::client::Error::PrepareBacklogFull { .. }) => continue,
+                Err(
+                    e @ (rangeclient::client::Error::KeyspaceIsReadOnly
+                    | rangeclient::client::Error::KeyspaceDoesNotExist
+                    | rangeclient::client::Error::RangeFrozen
+                    | rangeclient::client::Error::RangeDraining
+                    | rangeclient::client::Error::Overloaded
+                    | rangeclient::client::Error::TransactionAborted(_)),
+                ) => Self::error_from_rangeclient_error(e),
+                Err(e) => {
+                    return Err(Error::InternalError(Arc::new(std::io::Error::other(
+                        format!("failed to validate on range {:?}: {:?}", range_id, e),
+                    ))))
+                }
+            };
+            validation = Validation::WouldFail(failure);
+        }
+        Ok(validation)
+    }
+
+    /// The epoch the transaction committed in, once it has committed. Reads
+    /// that must observe the transaction can wait for it with
+    /// `Coordinator::wait_for_epoch`.
+    pub fn commit_epoch(&self) -> Option<u64> {
+        match self.state {
+            State::Committed { epoch } => Some(epoch),
+            _ => None,
+        }
+    }
+
+    pub async fn commit(&mut self) -> Result<(), Error> {
+        let op_start = Instant::now();
+        self.begin_op("commit", None);
+        let res = self.commit_inner().await;
+        self.record_op("commit", None, op_start, &res);
+        res
+    }
+
+    async fn commit_inner(&mut self) -> Result<(), Error> {
+        let epoch = self.prepare_for_commit().await?;
+        let outcome = match self
             .tx_state_store
             .try_commit_transaction(self.id, epoch)
             .await
-            .unwrap()
         {
+            Ok(outcome) => outcome,
+            Err(_) => return self.settle_unknown_commit(epoch).await,
+        };
+        self.apply_commit_decision(epoch, outcome)?;
+        self.notify_participants_of_commit(epoch).await;
+        Ok(())
+    }
+
+    // Prepares the transaction on all its participants and returns the epoch
+    // it can commit in. Aborts the transaction on failure.
+    pub(crate) async fn prepare_for_commit(&mut self) -> Result<u64, Error> {
+        self.check_still_running()?;
+        let remaining = self.remaining_time();
+        if remaining.is_zero() {
+            let _ = self.record_abort().await;
+            return Err(Error::TransactionAborted(
+                TransactionAbortReason::TransactionTimeout,
+            ));
+        }
+        if let Err(e) = self.reroute_buffered_writes().await {
+            let _ = self.record_abort().await;
+            return Err(e);
+        }
+        let prepare_deadline = self.clock.instant() + remaining;
+        self.state = State::Preparing;
+        self.fan_out.record_prepare(
+            self.participant_ranges
+                .values()
+                .map(|info| info.prepare_payload_bytes()),
+        );
+        let mut prepare_join_set = JoinSet::new();
+        for (range_id, info) in &self.participant_ranges {
+            let range_id = *range_id;
+            let range_client = self.range_client.clone();
+            let transaction_info = self.transaction_info.clone();
+            let has_reads = info.has_reads();
+            let mut writes: Vec<Record> = Vec::new();
+            let mut deletes: Vec<Bytes> = Vec::new();
+            for (k, v) in &info.writes {
+                match v {
+                    Some(v) => writes.push(match info.ttls.get(k) {
+                        Some(ttl) => Record::with_ttl(k.clone(), v.clone(), *ttl),
+                        None => Record::new(k.clone(), v.clone()),
+                    }),
+                    None => deletes.push(k.clone()),
+                }
+            }
+            let conditions = info.conditions.clone();
+            let increments: Vec<Increment> = info
+                .increments
+                .iter()
+                .map(|(key, delta)| Increment {
+                    key: key.clone(),
+                    delta: *delta,
+                })
+                .collect();
+            let clock = self.clock.clone();
+            self.tasks.spawn(&mut prepare_join_set, async move {
+                let started = clock.instant();
+                loop {
+                    match range_client
+                        .prepare_transaction(
+                            transaction_info.clone(),
+                            &range_id,
+                            has_reads,
+                            &writes,
+                            &deletes,
+                            &conditions,
+                            &increments,
+                        )
+                        .await
+                    {
+                        // The range is throttling prepares until its backlog
+                        // drains or its writes speed up. The prepare deadline
+                        // bounds the retries.
+                        Err(
+                            rangeclient::client::Error::PrepareBacklogFull { retry_after }
+                            | rangeclient::client::Error::WriteStalled { retry_after },
+                        ) => clock.sleep(retry_after).await,
+                        res => return (range_id, clock.instant() - started, res),
+                    }
+                }
+            });
+        }
+        let mut epoch = match clock::timeout_at(
+            self.clock.as_ref(),
+            prepare_deadline,
+            self.epoch_reader.read_epoch(),
+        )
+        .await
+        {
+            Some(Ok(epoch)) => epoch,
+            Some(Err(_)) => {
+                prepare_join_set.abort_all();
+                let _ = self.record_abort().await;
+                return Err(Error::TransactionAborted(
+                    TransactionAbortReason::EpochUnavailable,
+                ));
+            }
+            None => {
+                prepare_join_set.abort_all();
+                let _ = self.record_abort().await;
+                return Err(Error::TransactionAborted(
+                    TransactionAbortReason::TransactionTimeout,
+                ));
+            }
+        };
+        let mut epoch_leases = HashMap::new();
+        let mut prepare_latencies = Vec::with_capacity(self.participant_ranges.len());
+
+        loop {
+            let res = match clock::timeout_at(
+                self.clock.as_ref(),
+                prepare_deadline,
+                prepare_join_set.join_next(),
+            )
+            .await
+            {
+                None => {
+                    // Ran out of time before all participants prepared.
+                    prepare_join_set.abort_all();
+                    let _ = self.record_abort().await;
+                    return Err(Error::TransactionAborted(
+                        TransactionAbortReason::TransactionTimeout,
+                    ));
+                }
+                Some(None) => break,
+                Some(Some(res)) => res,
+            };
+            let res = match res {
+                // The prepare task either panicked or got cancelled.
+                Err(_) | Ok(None) => {
+                    let _ = self.record_abort().await;
+                    return Err(Error::TransactionAborted(
+                        TransactionAbortReason::PrepareFailed,
+                    ));
+                }
+                Ok(Some(res)) => res,
+            };
+            let (range_id, latency, res) = res;
+            let res = match res {
+                Err(e) => {
+                    let err = Self::error_from_rangeclient_error(e);
+                    prepare_join_set.abort_all();
+                    let _ = self.record_abort().await;
+                    return Err(err);
+                }
+                Ok(res) => res,
+            };
+            prepare_latencies.push(latency);
+            epoch_leases.insert(range_id, res.epoch_lease);
+            if res.highest_known_epoch > epoch {
+                epoch = res.highest_known_epoch;
+            }
+        }
+
+        self.fan_out.record_prepared(prepare_latencies);
+
+        let epoch = match self
+            .extend_expired_leases(epoch, epoch_leases, prepare_deadline)
+            .await
+        {
+            Ok(epoch) => epoch,
+            Err(e) => {
+                let _ = self.record_abort().await;
+                return Err(e);
+            }
+        };
+
+        if let Err(e) = self.prepare_external_participants(prepare_deadline).await {
+            let _ = self.record_abort().await;
+            return Err(e);
+        }
+
+        // At this point we are prepared!
+        Ok(epoch)
+    }
+
+    // Prepares the external participants, once all the ranges did.
+    async fn prepare_external_participants(
+        &self,
+        prepare_deadline: tokio::time::Instant,
+    ) -> Result<(), Error> {
+        if self.external_participants.is_empty() {
+            return Ok(());
+        }
+        let prepared = clock::timeout_at(
+            self.clock.as_ref(),
+            prepare_deadline,
+            join_all(
+                self.external_participants
+                    .iter()
+                    .map(|participant| participant.prepare(self.id)),
+            ),
+        )
+        .await
+        .ok_or(Error::TransactionAborted(
+            TransactionAbortReason::TransactionTimeout,
+        ))?;
+        if prepared.iter().any(|res| res.is_err()) {
+            return Err(Error::TransactionAborted(
+                TransactionAbortReason::ExternalParticipantFailed,
+            ));
+        }
+        Ok(())
+    }
+
+    // Returns the epoch the transaction can commit in once every participant's
+    // epoch lease covers it. Leases that end before that epoch, typically of
+    // ranges that prepared early in a long prepare phase, are extended by
+    // their ranges instead of aborting the transaction, up to
+    // MAX_LEASE_EXTENSION_ROUNDS times and within the prepare deadline.
+    async fn extend_expired_leases(
+        &mut self,
+        mut epoch: u64,
+        mut epoch_leases: HashMap<FullRangeId, EpochLease>,
+        prepare_deadline: tokio::time::Instant,
+    ) -> Result<u64, Error> {
+        let expired_err = Error::TransactionAborted(TransactionAbortReason::RangeLeaseExpired);
+        let mut rounds = 0;
+        loop {
+            // A lease starting after the epoch means the range was reloaded
+            // since it saw the transaction, so extending can't help.
+            if epoch_leases
+                .values()
+                .any(|lease| lease.lower_bound_inclusive > epoch)
+            {
+                return Err(expired_err);
+            }
+            let expired: Vec<FullRangeId> = epoch_leases
+                .iter()
+                .filter(|(_, lease)| lease.upper_bound_inclusive < epoch)
+                .map(|(range_id, _)| *range_id)
+                .collect();
+            if expired.is_empty() {
+                return Ok(epoch);
+            }
+            if rounds == MAX_LEASE_EXTENSION_ROUNDS {
+                return Err(expired_err);
+            }
+            rounds += 1;
+            self.stats.epoch_lease_extensions += expired.len() as u64;
+            let mut extend_join_set = JoinSet::new();
+            for range_id in expired {
+                let range_client = self.range_client.clone();
+                let transaction_info = self.transaction_info.clone();
+                self.tasks.spawn(&mut extend_join_set, async move {
+                    let res = range_client
+                        .extend_epoch_lease(transaction_info, &range_id, epoch)
+                        .await;
+                    (range_id, res)
+                });
+            }
+            loop {
+                let res = match clock::timeout_at(
+                    self.clock.as_ref(),
+                    prepare_deadline,
+                    extend_join_set.join_next(),
+                )
+                .await
+                {
+                    None => {
+                        extend_join_set.abort_all();
+                        return Err(Error::TransactionAborted(
+                            TransactionAbortReason::TransactionTimeout,
+                        ));
+                    }
+                    Some(None) => break,
+                    Some(Some(Ok(Some(res)))) => res,
+                    // The extension task either panicked or got cancelled.
+                    Some(Some(Err(_) | Ok(None))) => return Err(expired_err),
+                };
+                match res {
+                    (range_id, Ok(res)) => {
+                        epoch_leases.insert(range_id, res.epoch_lease);
+                        epoch = std::cmp::max(epoch, res.highest_known_epoch);
+                    }
+                    // The range lost the transaction's prepare, e.g. because
+                    // it was reloaded, so the transaction can't commit.
+                    (_, Err(_)) => {
+                        extend_join_set.abort_all();
+                        return Err(expired_err);
+                    }
+                }
+            }
+        }
+    }
+
+    // Records the outcome of trying to commit the prepared transaction in the
+    // tx_state_store.
+    pub(crate) fn apply_commit_decision(
+        &mut self,
+        epoch: u64,
+        outcome: OpResult,
+    ) -> Result<(), Error> {
+        match outcome {
             OpResult::TransactionIsAborted => {
                 // Somebody must have aborted the transaction (maybe due to timeout)
                 // so unfortunately the commit was not successful.
+                self.state = State::Aborted;
+                self.notify_outcome(Decision::Aborted);
+                self.tasks.cancel();
                 return Err(Error::TransactionAborted(TransactionAbortReason::Other));
             }
             OpResult::TransactionIsCommitted(i) => assert!(i.epoch == epoch),
         };
 
         // Transaction Committed!
-        self.state = State::Committed;
+        self.state = State::Committed { epoch };
+        self.notify_outcome(Decision::Committed { epoch });
+        Ok(())
+    }
+
+    // Writing the commit decision of the prepared transaction failed, so it
+    // may or may not be in the tx_state_store. Aborting settles it: the abort
+    // only goes through if the commit did not. If that fails too, the
+    // participants keep the transaction prepared until they find its
+    // decision in the tx_state_store themselves.
+    pub(crate) async fn settle_unknown_commit(&mut self, epoch: u64) -> Result<(), Error> {
+        match self.tx_state_store.try_abort_transaction(self.id).await {
+            Ok(OpResult::TransactionIsAborted) => {
+                // The abort is recorded already, only the participants are
+                // left to tell.
+                self.state = State::Aborted;
+                let abort_join_set = self.send_range_aborts();
+                self.finish_abort(abort_join_set).await;
+                Err(Error::TransactionAborted(TransactionAbortReason::Other))
+            }
+            Ok(outcome) => {
+                self.apply_commit_decision(epoch, outcome)?;
+                self.notify_participants_of_commit(epoch).await;
+                Ok(())
+            }
+            Err(_) => {
+                self.state = State::Unknown;
+                // The participants resolve it from the tx_state_store on their
+                // own, nothing is left for this coordinator to do.
+                self.participant_registry.remove(self.id);
+                self.tasks.cancel();
+                Err(Error::TransactionDoneButStateUnknown)
+            }
+        }
+    }
+
+    pub(crate) async fn notify_participants_of_commit(&mut self, epoch: u64) {
         // notify participants so they can quickly release locks.
         let mut commit_join_set = JoinSet::new();
         for range_id in self.participant_ranges.keys() {
             let range_id = *range_id;
             let range_client = self.range_client.clone();
             let transaction_info = self.transaction_info.clone();
-            commit_join_set.spawn_on(
-                async move {
-                    range_client
-                        .commit_transaction(transaction_info, &range_id, epoch)
-                        .await
-                },
-                &self.runtime,
-            );
+            self.tasks.spawn(&mut commit_join_set, async move {
+                range_client
+                    .commit_transaction(transaction_info, &range_id, epoch)
+                    .await
+            });
         }
+        join_all(
+            self.external_participants
+                .iter()
+                .map(|participant| participant.commit(self.id, epoch)),
+        )
+        .await;
         while commit_join_set.join_next().await.is_some() {}
-        Ok(())
+        self.tasks.cancel();
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         transaction_info: Arc<TransactionInfo>,
         universe_client: UniverseClient<tonic::transport::Channel>,
         range_client: Arc<RangeClient>,
         range_assignment_oracle: Arc<dyn RangeAssignmentOracle>,
-        epoch_reader: Arc<EpochReader>,
+        epoch_reader: Arc<dyn EpochSource>,
         tx_state_store: Arc<TxStateStoreClient>,
-        runtime: tokio::runtime::Handle,
+        outcome_notifier: Arc<OutcomeNotifier>,
+        participant_registry: Arc<ParticipantRegistry>,
+        fan_out: Arc<FanOutRecorder>,
+        lifecycle_logger: Option<Arc<LifecycleLogger>>,
+        timeline: Option<TransactionTimeline>,
+        instrumentation: Option<Arc<dyn Instrumentation>>,
+        tasks: TransactionTasks,
+        clock: Arc<dyn Clock>,
     ) -> Transaction {
         Transaction {
             id: transaction_info.id,
@@ -359,12 +2172,756 @@ impl Transaction {
             universe_client,
             state: State::Running,
             participant_ranges: HashMap::new(),
+            participant_order: Vec::new(),
+            read_order: Vec::new(),
+            external_participants: Vec::new(),
+            savepoints: Vec::new(),
+            next_savepoint_generation: 0,
+            stats: TransactionStats::default(),
             resolved_keyspaces: HashMap::new(),
+            pinned: None,
+            read_only_keyspaces: HashSet::new(),
+            colocations: HashMap::new(),
             range_client,
             range_assignment_oracle,
             epoch_reader,
             tx_state_store,
-            runtime,
+            outcome_notifier,
+            participant_registry,
+            fan_out,
+            lifecycle_logger,
+            timeline,
+            instrumentation,
+            tasks,
+            started: clock.now(),
+            clock,
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        // A transaction dropped before it was decided can no longer be
+        // finished through this coordinator either, so stop tracking it.
+        self.participant_registry.remove(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        external::ExternalError,
+        for_testing::{self, MAX_EPOCH_LEASE_EXTENSION},
+    };
+    use async_trait::async_trait;
+    use tx_state_store::client::OpResult;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    async fn outcome(
+        context: &for_testing::TestContext,
+        transaction: &Transaction,
+    ) -> Option<OpResult> {
+        tx_state_store::client::Client::in_memory(context.tx_state_store.clone())
+            .get_transaction_outcome(transaction.id())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn commits_in_the_current_epoch() {
+        let context = for_testing::setup().await;
+        context.epoch_source.set_epoch(5);
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(tx.commit_epoch(), Some(5));
+        assert_eq!(tx.stats().epoch_lease_extensions, 0);
+        assert!(matches!(
+            outcome(&context, &tx).await,
+            Some(OpResult::TransactionIsCommitted(info)) if info.epoch == 5
+        ));
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let value = tx
+            .get(&context.keyspace, Bytes::from_static(b"k"))
+            .await
+            .unwrap();
+        assert_eq!(value, Some(Bytes::from_static(b"v")));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn expired_leases_are_extended_at_commit() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        // Past the lease the range loaded with, but within how far it may
+        // extend it.
+        let epoch = MAX_EPOCH_LEASE_EXTENSION / 2;
+        context.epoch_source.set_epoch(epoch);
+        tx.commit().await.unwrap();
+        assert_eq!(tx.commit_epoch(), Some(epoch));
+        assert_eq!(tx.stats().epoch_lease_extensions, 1);
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn leases_that_cannot_be_extended_far_enough_abort() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        context
+            .epoch_source
+            .set_epoch(MAX_EPOCH_LEASE_EXTENSION * 10);
+        let err = tx.commit().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TransactionAborted(TransactionAbortReason::RangeLeaseExpired)
+        ));
+        assert_eq!(
+            tx.stats().epoch_lease_extensions,
+            MAX_LEASE_EXTENSION_ROUNDS as u64
+        );
+        assert!(matches!(
+            outcome(&context, &tx).await,
+            Some(OpResult::TransactionIsAborted)
+        ));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn transactions_past_their_timeout_abort_at_commit() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        context.clock.advance(TIMEOUT);
+        let err = tx.commit().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TransactionAborted(TransactionAbortReason::TransactionTimeout)
+        ));
+        assert!(matches!(
+            outcome(&context, &tx).await,
+            Some(OpResult::TransactionIsAborted)
+        ));
+        context.tear_down().await
+    }
+
+    async fn start_snapshot_transaction(context: &for_testing::TestContext) -> Transaction {
+        let started = context.clock.now();
+        context
+            .coordinator
+            .start_transaction(Arc::new(TransactionInfo {
+                id: Uuid::new_v4(),
+                started,
+                first_started: started,
+                overall_timeout: TIMEOUT,
+                labels: Default::default(),
+                isolation: IsolationLevel::Snapshot,
+                snapshot_epoch: None,
+            }))
+            .await
+    }
+
+    #[tokio::test]
+    async fn snapshot_transactions_that_read_validate() {
+        let mut context = for_testing::setup().await;
+        // So that there is an epoch that is over to take a snapshot at.
+        context.advance_epoch().await;
+        let mut tx = start_snapshot_transaction(&context).await;
+        assert_eq!(tx.transaction_info.snapshot_epoch, Some(1));
+        tx.get(&context.keyspace, "k").await.unwrap();
+        tx.put(&context.keyspace, "other", "v").await.unwrap();
+        assert!(matches!(
+            tx.validate().await.unwrap(),
+            Validation::WouldCommit
+        ));
+        tx.commit().await.unwrap();
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn buffered_writes_are_read_past_the_read_budget() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        // Into the time held back for commit.
+        context.clock.advance(TIMEOUT - TIMEOUT / 8);
+        assert_eq!(
+            tx.get(&context.keyspace, Bytes::from_static(b"k"))
+                .await
+                .unwrap(),
+            Some(Bytes::from_static(b"v"))
+        );
+        assert!(matches!(
+            tx.get(&context.keyspace, Bytes::from_static(b"other"))
+                .await,
+            Err(Error::InsufficientTimeRemaining)
+        ));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn dropped_transactions_leave_the_participant_registry() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "k", "v").await.unwrap();
+        let registry = tx.participant_registry.clone();
+        let id = tx.id();
+        assert_eq!(registry.get(id).ranges.len(), 1);
+        drop(tx);
+        assert!(registry.get(id).ranges.is_empty());
+        assert!(registry.get(id).namespaces.is_empty());
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn commits_the_tx_state_store_cannot_record_end_in_an_unknown_state() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        context.tx_state_store.set_available(false);
+        let err = tx.commit().await.unwrap_err();
+        assert!(matches!(err, Error::TransactionDoneButStateUnknown));
+        assert!(tx.participant_registry.get(tx.id()).ranges.is_empty());
+        assert!(matches!(
+            tx.abort().await,
+            Err(Error::TransactionDoneButStateUnknown)
+        ));
+        context.tx_state_store.set_available(true);
+        assert!(outcome(&context, &tx).await.is_none());
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn unavailable_epochs_abort_retryably() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(
+            &context.keyspace,
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"v"),
+        )
+        .await
+        .unwrap();
+        context.epoch_source.set_available(false);
+        let err = tx.commit().await.unwrap_err();
+        let Error::TransactionAborted(reason) = err else {
+            panic!("unexpected error {:?}", err);
+        };
+        assert!(matches!(reason, TransactionAbortReason::EpochUnavailable));
+        assert!(reason.is_retryable());
+        assert!(matches!(
+            outcome(&context, &tx).await,
+            Some(OpResult::TransactionIsAborted)
+        ));
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn snapshot_transactions_read_and_scan_as_of_their_snapshot() {
+        let mut context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "k", "old").await.unwrap();
+        tx.commit().await.unwrap();
+        // Snapshots are taken as of the last epoch that is over.
+        for _ in 0..tx.commit_epoch().unwrap() {
+            context.advance_epoch().await;
+        }
+        let mut reader = start_snapshot_transaction(&context).await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "k", "new").await.unwrap();
+        tx.put(&context.keyspace, "l", "new").await.unwrap();
+        tx.commit().await.unwrap();
+
+        assert_eq!(
+            reader.get(&context.keyspace, "k").await.unwrap(),
+            Some(Bytes::from_static(b"old"))
+        );
+        assert_eq!(
+            scan_all(&context, &mut reader).await,
+            vec![(Bytes::from("k"), Bytes::from("old"))]
+        );
+        reader.commit().await.unwrap();
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn snapshot_transactions_that_cannot_read_the_epoch_still_conflict() {
+        let mut context = for_testing::setup().await;
+        context.advance_epoch().await;
+        // Both run as serializable, since they get no snapshot to check
+        // their writes against.
+        context.epoch_source.set_available(false);
+        let mut first = start_snapshot_transaction(&context).await;
+        context.clock.advance(Duration::from_millis(1));
+        let mut second = start_snapshot_transaction(&context).await;
+        context.epoch_source.set_available(true);
+        for tx in [&first, &second] {
+            assert_eq!(tx.transaction_info.isolation, IsolationLevel::Serializable);
         }
+
+        // Each reads the counter to increment it.
+        first.get(&context.keyspace, "counter").await.unwrap();
+        let second_read = second.get(&context.keyspace, "counter").await;
+        first.put(&context.keyspace, "counter", "1").await.unwrap();
+        first.commit().await.unwrap();
+        // The younger one can't read under the older one's lock, so it can't
+        // overwrite the increment.
+        assert!(matches!(
+            second_read,
+            Err(Error::TransactionAborted(
+                TransactionAbortReason::DeadlockPrevention
+            ))
+        ));
+        context.tear_down().await
+    }
+
+    async fn scan_all(
+        context: &for_testing::TestContext,
+        tx: &mut Transaction,
+    ) -> Vec<(Bytes, Bytes)> {
+        tx.scan(&context.keyspace, None, None, None).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_made_before_a_split_are_overwritten_after_it() {
+        let mut context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let key = Bytes::from_static(b"x");
+        tx.put(&context.keyspace, key.clone(), Bytes::from_static(b"old"))
+            .await
+            .unwrap();
+        context.split("m").await;
+        tx.put(&context.keyspace, key.clone(), Bytes::from_static(b"new"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tx.get(&context.keyspace, key.clone()).await.unwrap(),
+            Some(Bytes::from_static(b"new"))
+        );
+        assert_eq!(
+            scan_all(&context, &mut tx).await,
+            vec![(key, Bytes::from_static(b"new"))]
+        );
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn writes_made_before_a_split_are_deleted_after_it() {
+        let mut context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let (key, other) = (Bytes::from_static(b"x"), Bytes::from_static(b"y"));
+        tx.put(&context.keyspace, key.clone(), Bytes::from_static(b"old"))
+            .await
+            .unwrap();
+        tx.put(
+            &context.keyspace,
+            other.clone(),
+            Bytes::from_static(b"kept"),
+        )
+        .await
+        .unwrap();
+        context.split("m").await;
+        tx.del(&context.keyspace, key.clone()).await.unwrap();
+
+        assert_eq!(tx.get(&context.keyspace, key).await.unwrap(), None);
+        assert_eq!(
+            scan_all(&context, &mut tx).await,
+            vec![(other, Bytes::from_static(b"kept"))]
+        );
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn transactions_commit_across_a_split() {
+        let mut context = for_testing::setup().await;
+        context.split("m").await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let (low, high) = (Bytes::from_static(b"a"), Bytes::from_static(b"z"));
+        tx.put(&context.keyspace, low.clone(), Bytes::from_static(b"1"))
+            .await
+            .unwrap();
+        tx.put(&context.keyspace, high.clone(), Bytes::from_static(b"2"))
+            .await
+            .unwrap();
+        // The new ranges are served from the current epoch on, so neither
+        // needs its lease extended.
+        tx.commit().await.unwrap();
+        assert_eq!(tx.stats().epoch_lease_extensions, 0);
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        assert_eq!(
+            scan_all(&context, &mut tx).await,
+            vec![
+                (low, Bytes::from_static(b"1")),
+                (high, Bytes::from_static(b"2"))
+            ]
+        );
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn writes_made_before_a_split_commit_after_it() {
+        let mut context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let (key, other) = (Bytes::from_static(b"x"), Bytes::from_static(b"y"));
+        tx.put(&context.keyspace, key.clone(), Bytes::from_static(b"old"))
+            .await
+            .unwrap();
+        tx.put(
+            &context.keyspace,
+            other.clone(),
+            Bytes::from_static(b"kept"),
+        )
+        .await
+        .unwrap();
+        context.split("m").await;
+        // Only one of the writes is made again after the split, the other
+        // one is still buffered under the range that was split.
+        tx.put(&context.keyspace, key.clone(), Bytes::from_static(b"new"))
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        assert_eq!(
+            scan_all(&context, &mut tx).await,
+            vec![
+                (key, Bytes::from_static(b"new")),
+                (other, Bytes::from_static(b"kept"))
+            ]
+        );
+        context.tear_down().await
+    }
+
+    fn counter(value: i64) -> Bytes {
+        Bytes::copy_from_slice(&value.to_be_bytes())
+    }
+
+    #[tokio::test]
+    async fn get_many_reads_keys_of_several_ranges_in_order() {
+        let mut context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "a", "1").await.unwrap();
+        tx.put(&context.keyspace, "x", "2").await.unwrap();
+        tx.put(&context.keyspace, "n", counter(40)).await.unwrap();
+        tx.commit().await.unwrap();
+        context.split("m").await;
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "b", "buffered").await.unwrap();
+        tx.increment(&context.keyspace, "n", 2).await.unwrap();
+        let keys = ["x", "b", "a", "n", "missing"].map(Bytes::from);
+        let vals = tx.get_many(&context.keyspace, keys.to_vec()).await.unwrap();
+        assert_eq!(
+            vals,
+            vec![
+                Some(Bytes::from_static(b"2")),
+                Some(Bytes::from_static(b"buffered")),
+                Some(Bytes::from_static(b"1")),
+                Some(counter(42)),
+                None,
+            ]
+        );
+        // Reading the counter turned its increment into a write of the sum.
+        let report = tx.explain().await.unwrap();
+        assert_eq!(report.participants.len(), 2);
+        assert!(report.participants.iter().all(|p| p.increments == 0));
+        tx.commit().await.unwrap();
+
+        // Both ranges of the split committed their writes.
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let keys = vec![Bytes::from("b"), Bytes::from("n")];
+        assert_eq!(
+            tx.get_many(&context.keyspace, keys).await.unwrap(),
+            vec![Some(Bytes::from_static(b"buffered")), Some(counter(42))]
+        );
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn get_many_reads_duplicated_keys_once() {
+        let context = for_testing::setup().await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "n", counter(40)).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.increment(&context.keyspace, "n", 2).await.unwrap();
+        let keys = ["n", "missing", "n", "missing"].map(Bytes::from);
+        assert_eq!(
+            tx.get_many(&context.keyspace, keys.to_vec()).await.unwrap(),
+            vec![Some(counter(42)), None, Some(counter(42)), None]
+        );
+        assert_eq!(
+            tx.get(&context.keyspace, "n").await.unwrap(),
+            Some(counter(42))
+        );
+        tx.commit().await.unwrap();
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn explain_reports_what_commit_would_do() {
+        let mut context = for_testing::setup().await;
+        context.split("m").await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "x", "value").await.unwrap();
+        tx.del(&context.keyspace, "y").await.unwrap();
+        tx.get(&context.keyspace, "a").await.unwrap();
+
+        let report = tx.explain().await.unwrap();
+        let [written, read] = &report.participants[..] else {
+            panic!("unexpected participants {:?}", report.participants);
+        };
+        assert_eq!(
+            (written.puts, written.deletes, written.keys_read),
+            (1, 1, 0)
+        );
+        assert_eq!(written.prepare_payload_bytes, "xvaluey".len());
+        assert_eq!((read.puts, read.deletes, read.keys_read), (0, 0, 1));
+        assert_eq!(read.prepare_payload_bytes, 0);
+        assert!(written.server.is_some() && read.server.is_some());
+        assert_eq!(report.range_server_messages, 4);
+        assert_eq!(report.prepare_payload_bytes, "xvaluey".len());
+        // The range read is locked by its read, before the one only written
+        // to is locked by its prepare.
+        assert_eq!(report.lock_order, vec![read.range_id, written.range_id]);
+        assert!(report.colocation.is_none());
+
+        // Explaining leaves the transaction to commit as usual.
+        tx.commit().await.unwrap();
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        assert_eq!(
+            scan_all(&context, &mut tx).await,
+            vec![(Bytes::from("x"), Bytes::from("value"))]
+        );
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn rolling_back_to_a_savepoint_undoes_the_writes_made_since() {
+        let mut context = for_testing::setup().await;
+        context.split("m").await;
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "a", "1").await.unwrap();
+        let outer = tx.savepoint().unwrap();
+        tx.put(&context.keyspace, "a", "2").await.unwrap();
+        let inner = tx.savepoint().unwrap();
+        tx.put(&context.keyspace, "a", "3").await.unwrap();
+        // A range first used after both savepoints.
+        tx.put(&context.keyspace, "x", "new").await.unwrap();
+
+        tx.rollback_to(inner).unwrap();
+        assert_eq!(
+            tx.get(&context.keyspace, "a").await.unwrap(),
+            Some(Bytes::from_static(b"2"))
+        );
+        assert_eq!(tx.get(&context.keyspace, "x").await.unwrap(), None);
+        tx.rollback_to(outer).unwrap();
+        assert_eq!(
+            tx.get(&context.keyspace, "a").await.unwrap(),
+            Some(Bytes::from_static(b"1"))
+        );
+        // Rolling back to `outer` discarded `inner`.
+        assert!(matches!(
+            tx.rollback_to(inner),
+            Err(Error::UnknownSavepoint)
+        ));
+        // Nor does a savepoint taken in its place bring it back.
+        let replacement = tx.savepoint().unwrap();
+        tx.put(&context.keyspace, "a", "4").await.unwrap();
+        assert!(matches!(
+            tx.rollback_to(inner),
+            Err(Error::UnknownSavepoint)
+        ));
+        assert_eq!(
+            tx.get(&context.keyspace, "a").await.unwrap(),
+            Some(Bytes::from_static(b"4"))
+        );
+        tx.rollback_to(replacement).unwrap();
+        let mut other = context.start_transaction(TIMEOUT).await;
+        let foreign = other.savepoint().unwrap();
+        assert!(matches!(
+            tx.rollback_to(foreign),
+            Err(Error::UnknownSavepoint)
+        ));
+        tx.commit().await.unwrap();
+
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        let vals = tx
+            .get_many(&context.keyspace, vec![Bytes::from("a"), Bytes::from("x")])
+            .await
+            .unwrap();
+        assert_eq!(vals, vec![Some(Bytes::from_static(b"1")), None]);
+        context.tear_down().await
+    }
+
+    // Records what it was asked to do, and fails to prepare if told to.
+    struct RecordingParticipant {
+        fail_prepare: bool,
+        calls: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    impl RecordingParticipant {
+        fn new(fail_prepare: bool) -> Arc<RecordingParticipant> {
+            Arc::new(RecordingParticipant {
+                fail_prepare,
+                calls: Default::default(),
+            })
+        }
+
+        fn calls(&self) -> Vec<&'static str> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl ExternalParticipant for RecordingParticipant {
+        async fn prepare(&self, _: Uuid) -> Result<(), ExternalError> {
+            self.calls.lock().unwrap().push("prepare");
+            if self.fail_prepare {
+                return Err("outbox unavailable".into());
+            }
+            Ok(())
+        }
+
+        async fn commit(&self, _: Uuid, _: u64) {
+            self.calls.lock().unwrap().push("commit");
+        }
+
+        async fn abort(&self, _: Uuid) {
+            self.calls.lock().unwrap().push("abort");
+        }
+    }
+
+    #[tokio::test]
+    async fn external_participants_commit_along_with_the_ranges() {
+        let context = for_testing::setup().await;
+        let participant = RecordingParticipant::new(false);
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "k", "v").await.unwrap();
+        tx.enroll(participant.clone()).unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(participant.calls(), vec!["prepare", "commit"]);
+        context.tear_down().await
+    }
+
+    #[tokio::test]
+    async fn failing_external_prepares_abort_the_transaction() {
+        let context = for_testing::setup().await;
+        let (failing, prepared) = (
+            RecordingParticipant::new(true),
+            RecordingParticipant::new(false),
+        );
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        tx.put(&context.keyspace, "k", "v").await.unwrap();
+        tx.enroll(failing.clone()).unwrap();
+        tx.enroll(prepared.clone()).unwrap();
+        assert!(matches!(
+            tx.commit().await,
+            Err(Error::TransactionAborted(
+                TransactionAbortReason::ExternalParticipantFailed
+            ))
+        ));
+        assert_eq!(failing.calls(), vec!["prepare", "abort"]);
+        assert_eq!(prepared.calls(), vec!["prepare", "abort"]);
+        assert!(matches!(
+            outcome(&context, &tx).await,
+            Some(OpResult::TransactionIsAborted)
+        ));
+
+        // The range was aborted too: it released its lock and dropped the
+        // prepared write.
+        let mut tx = context.start_transaction(TIMEOUT).await;
+        assert_eq!(tx.get(&context.keyspace, "k").await.unwrap(), None);
+        tx.put(&context.keyspace, "k", "other").await.unwrap();
+        tx.commit().await.unwrap();
+        context.tear_down().await
+    }
+
+    #[test]
+    fn faulted_ranges_abort_retryably() {
+        let error =
+            Transaction::error_from_rangeclient_error(rangeclient::client::Error::RangeFaulted);
+        let Error::TransactionAborted(reason) = error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert!(matches!(
+            reason,
+            TransactionAbortReason::RangeLeadershipChanged
+        ));
+        assert!(reason.is_retryable());
+    }
+
+    #[test]
+    fn ranges_moved_by_splits_abort_retryably() {
+        for error in [
+            rangeclient::client::Error::RangeIsNotLoaded,
+            rangeclient::client::Error::RangeOwnershipLost,
+            rangeclient::client::Error::KeyIsOutOfRange,
+            rangeclient::client::Error::RangeBusy,
+        ] {
+            let error = Transaction::error_from_rangeclient_error(error);
+            let Error::TransactionAborted(reason) = error else {
+                panic!("unexpected error {:?}", error);
+            };
+            assert!(reason.is_retryable());
+        }
+    }
+
+    #[test]
+    fn deadlocks_reported_by_ranges_abort_for_deadlock_prevention() {
+        let status = rangeclient::client::Error::TransactionAborted(
+            rangeclient::client::TransactionAbortReason::Deadlock,
+        )
+        .to_flatbuf_status();
+        let error = rangeclient::client::Error::from_flatbuf_status(status).unwrap_err();
+        let error = Transaction::error_from_rangeclient_error(error);
+        let Error::TransactionAborted(reason) = error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert!(matches!(reason, TransactionAbortReason::DeadlockPrevention));
+        assert!(reason.is_retryable());
     }
 }
@@ -0,0 +1,229 @@
+use clap::Parser;
+use std::{
+    fs::read_to_string,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+use common::{
+    config::Config,
+    host_info::{HostIdentity, HostInfo},
+    network::{
+        fast_network::FastNetwork,
+        for_testing::in_memory_fast_network::{InMemoryFastNetwork, InMemoryNetwork},
+    },
+    region::{Region, Zone},
+};
+use epoch::storage::in_memory::InMemoryEpochStorage;
+use frontend::range_assignment_oracle::RangeAssignmentOracle;
+use proto::universe::universe_client::UniverseClient;
+use rangeserver::{
+    embedded::EmbeddedRangeServer, epoch_supplier::reader::Reader, server::Server,
+    storage::in_memory::InMemoryStorage,
+};
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+// The config used when none is given on the command line.
+const DEFAULT_CONFIG: &str = include_str!("../../configs/config.json");
+
+#[derive(Parser, Debug)]
+#[command(name = "atomix-dev")]
+#[command(about = "Runs all the Atomix components in a single process", long_about = None)]
+struct Args {
+    /// Defaults to configs/config.json, built into the binary.
+    #[arg(long)]
+    config: Option<String>,
+
+    #[arg(long, default_value = "test-region")]
+    region: String,
+
+    #[arg(long, default_value = "a")]
+    zone: String,
+
+    #[arg(long, default_value = "test_server")]
+    range_server_identity: String,
+}
+
+fn socket_addr(addr: impl ToSocketAddrs) -> SocketAddr {
+    addr.to_socket_addrs().unwrap().next().unwrap()
+}
+
+// Waits until a gRPC server accepts connections on `addr`, so that the
+// components that connect to it on startup find it there.
+async fn wait_until_listening(addr: SocketAddr) {
+    while TcpStream::connect(addr).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn spawn_poll_loop(fast_network: Arc<InMemoryFastNetwork>, cancellation_token: CancellationToken) {
+    tokio::spawn(async move {
+        while !cancellation_token.is_cancelled() {
+            fast_network.poll();
+            tokio::task::yield_now().await
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let config: Config = match &args.config {
+        Some(path) => serde_json::from_str(&read_to_string(path).unwrap()).unwrap(),
+        None => serde_json::from_str(DEFAULT_CONFIG).unwrap(),
+    };
+    let region = Region {
+        cloud: None,
+        name: args.region,
+    };
+    let zone = Zone {
+        region: region.clone(),
+        name: args.zone,
+    };
+    let region_config = config.regions.get(&region).unwrap().clone();
+    let runtime = tokio::runtime::Handle::current();
+    let cancellation_token = CancellationToken::new();
+    // All the fast network traffic between the components stays in memory.
+    let network = InMemoryNetwork::new();
+
+    info!("Starting universe");
+    let universe_addr = socket_addr(&config.universe.proto_server_addr);
+    let universe_storage =
+        universe::storage::cassandra::Cassandra::new(config.cassandra.cql_addr.to_string()).await;
+    tokio::spawn(async move {
+        if let Err(e) =
+            universe::server::run_universe_server(universe_addr.to_string(), universe_storage).await
+        {
+            error!("Universe exited: {}", e);
+        }
+    });
+    wait_until_listening(universe_addr).await;
+
+    info!("Starting warden");
+    let warden_addr = socket_addr(&region_config.warden_address);
+    {
+        let cql_addr = config.cassandra.cql_addr.to_string();
+        let region = region.clone();
+        let runtime = runtime.clone();
+        let cancellation_token = cancellation_token.clone();
+        tokio::spawn(async move {
+            if let Err(e) = warden::server::run_warden_server(
+                warden_addr.to_string(),
+                format!("http://{}", universe_addr),
+                cql_addr,
+                region,
+                runtime,
+                cancellation_token,
+            )
+            .await
+            {
+                error!("Warden exited: {}", e);
+            }
+        });
+    }
+    wait_until_listening(warden_addr).await;
+
+    info!("Starting epoch service");
+    let epoch_server = Arc::new(epoch::server::Server::new(
+        InMemoryEpochStorage::new(),
+        config.clone(),
+    ));
+    tokio::spawn(epoch::server::Server::start(
+        epoch_server,
+        cancellation_token.clone(),
+    ));
+    wait_until_listening(socket_addr(&config.epoch.proto_server_addr)).await;
+
+    info!("Starting epoch publishers");
+    let publisher_set = region_config
+        .epoch_publishers
+        .iter()
+        .find(|&s| s.zone == zone)
+        .unwrap()
+        .clone();
+    for publisher_config in &publisher_set.publishers {
+        let fast_network = network.endpoint(socket_addr(&publisher_config.fast_network_addr));
+        spawn_poll_loop(fast_network.clone(), cancellation_token.clone());
+        let server = epoch_publisher::server::Server::new(
+            config.clone(),
+            publisher_config.clone(),
+            runtime.clone(),
+        );
+        epoch_publisher::server::Server::start(
+            server,
+            fast_network,
+            runtime.clone(),
+            cancellation_token.clone(),
+        )
+        .await;
+    }
+
+    info!("Starting range server");
+    let range_server_network =
+        network.endpoint(socket_addr(&config.range_server.fast_network_addr));
+    let epoch_supplier = Arc::new(Reader::new(
+        range_server_network.clone(),
+        runtime.clone(),
+        runtime.clone(),
+        publisher_set,
+        cancellation_token.clone(),
+    ));
+    let host_info = HostInfo {
+        identity: HostIdentity {
+            name: args.range_server_identity,
+            zone: zone.clone(),
+        },
+        address: socket_addr(&config.range_server.proto_server_addr),
+        warden_connection_epoch: 0,
+        labels: Default::default(),
+    };
+    let range_server = Server::new(
+        config.clone(),
+        host_info,
+        Arc::new(InMemoryStorage::new()),
+        epoch_supplier,
+        runtime.clone(),
+    );
+    let range_server = EmbeddedRangeServer::start(
+        range_server,
+        range_server_network,
+        cancellation_token.clone(),
+    )
+    .await
+    .unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1)
+    });
+
+    info!("Starting frontend");
+    let frontend_network = network.endpoint(socket_addr(&config.frontend.fast_network_addr));
+    spawn_poll_loop(frontend_network.clone(), cancellation_token.clone());
+    let universe_client = UniverseClient::connect(format!("http://{}", universe_addr))
+        .await
+        .unwrap();
+    let frontend_addr = socket_addr(&config.frontend.proto_server_addr);
+    let frontend = frontend::frontend::Server::new(
+        config,
+        zone,
+        frontend_network,
+        Arc::new(RangeAssignmentOracle::new(universe_client)),
+        runtime.clone(),
+        runtime,
+        cancellation_token.clone(),
+    )
+    .await;
+    frontend::frontend::Server::start(frontend).await;
+    wait_until_listening(frontend_addr).await;
+
+    info!("Atomix is up, frontend listening on {}", frontend_addr);
+    tokio::signal::ctrl_c().await.unwrap();
+    info!("Shutting down");
+    cancellation_token.cancel();
+    if let Err(e) = range_server.shutdown().await {
+        error!("Range server exited: {}", e);
+    }
+}
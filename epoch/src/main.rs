@@ -4,6 +4,7 @@ use clap::Parser;
 use common::config::Config;
 use epoch::server;
 use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 #[derive(Parser, Debug)]
 #[command(name = "epoch")]
@@ -11,6 +12,11 @@ use tokio_util::sync::CancellationToken;
 struct Args {
     #[arg(long, default_value = "configs/config.json")]
     config: String,
+
+    /// Only check that every epoch publisher set keeps a quorum if any one
+    /// of its failure domains fails, print the problems found, and exit.
+    #[arg(long)]
+    check_publishers: bool,
 }
 
 #[tokio::main]
@@ -18,6 +24,20 @@ async fn main() {
     tracing_subscriber::fmt::init();
     let args = Args::parse();
     let config: Config = serde_json::from_str(&read_to_string(&args.config).unwrap()).unwrap();
+    let problems = config.check_epoch_publishers();
+    if args.check_publishers {
+        if problems.is_empty() {
+            println!("every epoch publisher set spans its failure domains");
+            return;
+        }
+        for problem in problems {
+            println!("{}", problem);
+        }
+        std::process::exit(1);
+    }
+    for problem in problems {
+        warn!("{}", problem);
+    }
     let storage = epoch::storage::cassandra::Cassandra::new(
         config.cassandra.cql_addr.to_string(),
         "GLOBAL".to_string(),
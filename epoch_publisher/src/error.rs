@@ -13,6 +13,10 @@ pub enum Error {
     EpochUnknown,
     #[error("Unknown Internal Error")]
     InternalError,
+    /// Too few publishers of the set answered to agree on the epoch, as
+    /// opposed to enough answering without agreeing.
+    #[error("Only {reachable} epoch publishers reachable, a quorum needs {quorum}")]
+    QuorumUnreachable { reachable: u64, quorum: u64 },
 }
 
 impl Error {
@@ -40,6 +40,7 @@ fn get_config(epoch_address: SocketAddr) -> Config {
         // Not used in these tests.
         warden_address: "127.0.0.1:1".parse().unwrap(),
         epoch_publishers: HashSet::new(),
+        cassandra_datacenter: None,
     };
     let epoch_config = EpochConfig {
         // Not used in these tests.
@@ -51,6 +52,19 @@ fn get_config(epoch_address: SocketAddr) -> Config {
             range_maintenance_duration: time::Duration::from_secs(1),
             proto_server_addr: HostPort::from_str("127.0.0.1:50054").unwrap(),
             fast_network_addr: HostPort::from_str("127.0.0.1:50055").unwrap(),
+            preflight_epoch_advance_timeout: None,
+            max_pending_prepares_per_range: None,
+            max_epoch_lease_extension: None,
+            handoff_drain_timeout: None,
+            lock_table: Default::default(),
+            conflict_stats: Default::default(),
+            write_stall: Default::default(),
+            checksum_verification: Default::default(),
+            decision_log: Default::default(),
+            expiry: Default::default(),
+            fast_network_transport: Default::default(),
+            mirroring: Default::default(),
+            transaction_recovery: Default::default(),
         },
         universe: UniverseConfig {
             proto_server_addr: "127.0.0.1:123".parse().unwrap(),
@@ -59,11 +73,15 @@ fn get_config(epoch_address: SocketAddr) -> Config {
             proto_server_addr: HostPort::from_str("127.0.0.1:50057").unwrap(),
             fast_network_addr: HostPort::from_str("127.0.0.1:50058").unwrap(),
             transaction_overall_timeout: time::Duration::from_secs(10),
+            fast_network_transport: Default::default(),
         },
         cassandra: CassandraConfig {
             cql_addr: "127.0.0.1:9042".parse().unwrap(),
+            consistency: Default::default(),
         },
         regions: std::collections::HashMap::new(),
+        admin: Default::default(),
+        quic: Default::default(),
         epoch: epoch_config,
     };
     config.regions.insert(region, region_config);
@@ -76,6 +94,7 @@ fn get_publisher_config(fast_network_addr: SocketAddr) -> EpochPublisherConfig {
         name: "ep1".to_string(),
         backend_addr,
         fast_network_addr: HostPort::from_socket_addr(fast_network_addr),
+        failure_domain: None,
     }
 }
 
@@ -130,6 +149,7 @@ fn get_server_host_info(address: SocketAddr) -> HostInfo {
         },
         address,
         warden_connection_epoch: 0,
+        labels: Default::default(),
     }
 }
 
@@ -0,0 +1 @@
+pub mod epoch_source;
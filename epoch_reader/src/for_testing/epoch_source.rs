@@ -0,0 +1,52 @@
+use crate::source::EpochSource as Trait;
+use async_trait::async_trait;
+use epoch_publisher::error::Error;
+use std::sync::RwLock;
+
+struct State {
+    epoch: u64,
+    available: bool,
+}
+
+/// An epoch source whose epoch is set by the test.
+pub struct EpochSource {
+    state: RwLock<State>,
+}
+
+impl Default for EpochSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EpochSource {
+    pub fn new() -> EpochSource {
+        EpochSource {
+            state: RwLock::new(State {
+                epoch: 0,
+                available: true,
+            }),
+        }
+    }
+
+    pub fn set_epoch(&self, epoch: u64) {
+        self.state.write().unwrap().epoch = epoch;
+    }
+
+    /// While unavailable, reads fail with EpochUnknown, as if no majority of
+    /// the publishers could be reached.
+    pub fn set_available(&self, available: bool) {
+        self.state.write().unwrap().available = available;
+    }
+}
+
+#[async_trait]
+impl Trait for EpochSource {
+    async fn read_epoch(&self) -> Result<u64, Error> {
+        let state = self.state.read().unwrap();
+        if !state.available {
+            return Err(Error::EpochUnknown);
+        }
+        Ok(state.epoch)
+    }
+}
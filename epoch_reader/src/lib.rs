@@ -1 +1,4 @@
+pub mod for_testing;
 pub mod reader;
+pub mod regional;
+pub mod source;
@@ -1,4 +1,10 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use common::{
     config::EpochPublisherSet,
@@ -9,13 +15,16 @@ use epoch_publisher::{client::EpochPublisherClient, error::Error};
 use std::net::ToSocketAddrs;
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
-use tracing::warn;
+use tracing::{info, warn};
 
 /// EpochReader reads the latest epoch from an EpochPublisherSet.
 pub struct EpochReader {
     clients: Vec<Arc<EpochPublisherClient>>,
     runtime: tokio::runtime::Handle,
     publisher_majority_count: u64,
+    // Whether the last read found fewer than a majority of the publishers
+    // reachable, so that entering and leaving that state is only logged once.
+    degraded: AtomicBool,
 }
 
 impl EpochReader {
@@ -44,6 +53,8 @@ impl EpochReader {
                         .unwrap(),
 
                     warden_connection_epoch: 0,
+
+                    labels: Default::default(),
                 };
                 EpochPublisherClient::new(
                     fast_network.clone(),
@@ -59,6 +70,7 @@ impl EpochReader {
             clients,
             runtime: runtime.clone(),
             publisher_majority_count: half_round_down + 1,
+            degraded: AtomicBool::new(false),
         }
     }
 
@@ -77,6 +89,8 @@ impl EpochReader {
 
         // Now see if any value is returned by a majority of publishers, and return it.
         let mut epoch_value_counts = HashMap::<u64, u64>::new();
+        // Publishers that answered, even if only with an error.
+        let mut reachable = 0;
         while let Some(res) = join_set.join_next().await {
             let res = match res {
                 Err(e) => {
@@ -88,16 +102,50 @@ impl EpochReader {
             let epoch = match res {
                 Err(e) => {
                     warn!("Error reading epoch from publisher: {:?}", e);
+                    if e != Error::Timeout {
+                        reachable += 1;
+                    }
                     continue;
                 }
                 Ok(epoch) => epoch,
             };
+            reachable += 1;
             let current_count = epoch_value_counts.get(&epoch).unwrap_or(&0);
             if current_count + 1 >= self.publisher_majority_count {
+                self.set_degraded(false, reachable);
                 return Ok(epoch);
             }
             epoch_value_counts.insert(epoch, current_count + 1);
         }
+        if reachable < self.publisher_majority_count {
+            self.set_degraded(true, reachable);
+            return Err(Error::QuorumUnreachable {
+                reachable,
+                quorum: self.publisher_majority_count,
+            });
+        }
+        self.set_degraded(false, reachable);
         Err(Error::EpochUnknown)
     }
+
+    /// Whether the last read of the epoch failed for lack of a reachable
+    /// majority of the publishers.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    fn set_degraded(&self, degraded: bool, reachable: u64) {
+        if self.degraded.swap(degraded, Ordering::Relaxed) == degraded {
+            return;
+        }
+        if degraded {
+            warn!(
+                reachable,
+                quorum = self.publisher_majority_count,
+                "Epoch publishers are degraded: too few are reachable to read the epoch"
+            );
+        } else {
+            info!("A majority of the epoch publishers is reachable again");
+        }
+    }
 }
@@ -0,0 +1,132 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use common::{
+    config::{Config, EpochPublisherSet},
+    network::fast_network::FastNetwork,
+    region::Zone,
+};
+use epoch_publisher::error::Error;
+use tokio::sync::OnceCell;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::{reader::EpochReader, source::EpochSource};
+
+struct Candidate {
+    publisher_set: EpochPublisherSet,
+    remote: bool,
+    // Created on first use, so remote publishers are only contacted once a
+    // failover actually happens.
+    reader: OnceCell<EpochReader>,
+}
+
+/// Reads the epoch from the publisher sets of the reader's own region, and
+/// fails over to the publisher sets of other regions when none of the local
+/// ones answer. Every set publishes the same global epoch, so this only
+/// changes latency, not the epochs returned.
+///
+/// Sets are tried in order: the one in the reader's zone, the other ones in
+/// its region, then the ones in other regions.
+pub struct RegionalEpochReader {
+    candidates: Vec<Candidate>,
+    fast_network: Arc<dyn FastNetwork>,
+    runtime: tokio::runtime::Handle,
+    bg_runtime: tokio::runtime::Handle,
+    cancellation_token: CancellationToken,
+    cross_region_reads: AtomicU64,
+}
+
+impl RegionalEpochReader {
+    /// Fails if no epoch publishers are configured at all.
+    pub fn new(
+        config: &Config,
+        zone: &Zone,
+        fast_network: Arc<dyn FastNetwork>,
+        runtime: tokio::runtime::Handle,
+        bg_runtime: tokio::runtime::Handle,
+        cancellation_token: CancellationToken,
+    ) -> Result<RegionalEpochReader, String> {
+        let mut candidates = Vec::new();
+        for (region, region_config) in &config.regions {
+            for publisher_set in &region_config.epoch_publishers {
+                if publisher_set.publishers.is_empty() {
+                    continue;
+                }
+                candidates.push(Candidate {
+                    publisher_set: publisher_set.clone(),
+                    remote: *region != zone.region,
+                    reader: OnceCell::new(),
+                });
+            }
+        }
+        if candidates.is_empty() {
+            return Err(format!("no epoch publishers configured for zone {}", zone));
+        }
+        // Sort by name within each tier so that every reader in a zone fails
+        // over to the same set.
+        candidates.sort_by_key(|c| {
+            (
+                c.remote,
+                c.publisher_set.zone != *zone,
+                c.publisher_set.name.clone(),
+            )
+        });
+        Ok(RegionalEpochReader {
+            candidates,
+            fast_network,
+            runtime,
+            bg_runtime,
+            cancellation_token,
+            cross_region_reads: AtomicU64::new(0),
+        })
+    }
+
+    pub async fn read_epoch(&self) -> Result<u64, Error> {
+        let mut last_err = Error::EpochUnknown;
+        for candidate in &self.candidates {
+            let reader = candidate
+                .reader
+                .get_or_init(|| async {
+                    EpochReader::new(
+                        self.fast_network.clone(),
+                        self.runtime.clone(),
+                        self.bg_runtime.clone(),
+                        candidate.publisher_set.clone(),
+                        self.cancellation_token.clone(),
+                    )
+                })
+                .await;
+            if candidate.remote {
+                self.cross_region_reads.fetch_add(1, Ordering::Relaxed);
+            }
+            match reader.read_epoch().await {
+                Ok(epoch) => return Ok(epoch),
+                Err(e) => {
+                    warn!(
+                        "Failed to read epoch from publisher set {}: {:?}",
+                        candidate.publisher_set.name, e
+                    );
+                    last_err = e;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    /// How many times the epoch was read from a publisher set in another
+    /// region, successfully or not.
+    pub fn cross_region_reads(&self) -> u64 {
+        self.cross_region_reads.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl EpochSource for RegionalEpochReader {
+    async fn read_epoch(&self) -> Result<u64, Error> {
+        RegionalEpochReader::read_epoch(self).await
+    }
+}
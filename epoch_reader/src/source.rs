@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+use epoch_publisher::error::Error;
+
+use crate::reader::EpochReader;
+
+/// Where a coordinator gets the current epoch from when committing
+/// transactions. Implemented by EpochReader, but can be swapped out for a
+/// mock in tests, or for any other source of epochs.
+#[async_trait]
+pub trait EpochSource: Send + Sync + 'static {
+    // Values returned must satisfy the Global Epoch Invariant:
+    // If a call returns a value e, then all subsequent calls must return a value
+    // greater than or equal to e-1.
+    async fn read_epoch(&self) -> Result<u64, Error>;
+}
+
+#[async_trait]
+impl EpochSource for EpochReader {
+    async fn read_epoch(&self) -> Result<u64, Error> {
+        EpochReader::read_epoch(self).await
+    }
+}
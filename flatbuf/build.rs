@@ -1,22 +1,75 @@
 use flatc_rust;
 
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-fn main() {
-    let epoch_publisher_out_dir = "target/epoch_publisher/";
-    println!("cargo:rerun-if-changed=src/epoch_publisher/schema.fbs");
-    flatc_rust::run(flatc_rust::Args {
-        inputs: &[Path::new("src/epoch_publisher/schema.fbs")],
-        out_dir: Path::new(epoch_publisher_out_dir),
-        ..Default::default()
-    })
-    .expect("flatc");
-    println!("cargo:rerun-if-changed=src/rangeserver/schema.fbs");
-    let range_server_out_dir = "target/rangeserver/";
+#[path = "build/compat.rs"]
+mod compat;
+
+// Each schema lives in src/<name>/ and its generated code goes to
+// target/<name>/.
+const SCHEMAS: &[&str] = &["epoch_publisher", "rangeserver"];
+
+fn generate(input: &Path, out_dir: &Path) {
+    println!("cargo:rerun-if-changed={}", input.display());
     flatc_rust::run(flatc_rust::Args {
-        inputs: &[Path::new("src/rangeserver/schema.fbs")],
-        out_dir: Path::new(range_server_out_dir),
+        inputs: &[input],
+        out_dir,
         ..Default::default()
     })
     .expect("flatc");
 }
+
+/// Returns the frozen versions of a schema, sorted by version.
+fn frozen_versions(schema: &str) -> Vec<(String, PathBuf)> {
+    let dir = format!("src/{}/versions", schema);
+    println!("cargo:rerun-if-changed={}", dir);
+    let mut versions: Vec<(u32, PathBuf)> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir, e))
+        .map(|entry| entry.unwrap().path())
+        .filter_map(|path| {
+            let version = path
+                .file_stem()?
+                .to_str()?
+                .strip_prefix('v')?
+                .parse()
+                .ok()?;
+            Some((version, path))
+        })
+        .collect();
+    versions.sort();
+    versions
+        .into_iter()
+        .map(|(version, path)| (format!("v{}", version), path))
+        .collect()
+}
+
+fn main() {
+    for schema in SCHEMAS {
+        let current_path = format!("src/{}/schema.fbs", schema);
+        generate(
+            Path::new(&current_path),
+            Path::new(&format!("target/{}/", schema)),
+        );
+
+        // Fail the build if the current schema can't talk to any of the frozen
+        // versions, rather than finding out in a mixed-version cluster.
+        let current = fs::read_to_string(&current_path).unwrap();
+        for (version, path) in frozen_versions(schema) {
+            let frozen = fs::read_to_string(&path).unwrap();
+            let violations = compat::check(&frozen, &current);
+            if !violations.is_empty() {
+                panic!(
+                    "{} is not backwards compatible with {}:\n  {}",
+                    current_path,
+                    path.display(),
+                    violations.join("\n  ")
+                );
+            }
+            generate(
+                &path,
+                Path::new(&format!("target/{}/versions/{}/", schema, version)),
+            );
+        }
+    }
+}
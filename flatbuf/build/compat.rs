@@ -0,0 +1,209 @@
+//! Checks that a flatbuffers schema is a forward-compatible evolution of a
+//! previously frozen version of it. This only understands the subset of the
+//! schema language that our schemas use (tables, structs and enums).
+
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq)]
+struct Field {
+    name: String,
+    type_: String,
+    default: Option<String>,
+    deprecated: bool,
+}
+
+#[derive(Debug)]
+enum Definition {
+    Table(Vec<Field>),
+    Struct(Vec<Field>),
+    Enum {
+        underlying: String,
+        values: Vec<(String, i64)>,
+    },
+}
+
+struct Schema {
+    definitions: HashMap<String, Definition>,
+    root_type: Option<String>,
+}
+
+fn strip_comments(src: &str) -> String {
+    src.lines()
+        .map(|l| match l.find("//") {
+            None => l,
+            Some(i) => &l[..i],
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_fields(body: &str) -> Vec<Field> {
+    body.split(';')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(|f| {
+            let (decl, attributes) = match f.find('(') {
+                None => (f, ""),
+                Some(i) => (&f[..i], &f[i..]),
+            };
+            let (decl, default) = match decl.split_once('=') {
+                None => (decl, None),
+                Some((decl, default)) => (decl, Some(default.trim().to_string())),
+            };
+            let (name, type_) = decl
+                .split_once(':')
+                .unwrap_or_else(|| panic!("malformed field declaration: {}", f));
+            Field {
+                name: name.trim().to_string(),
+                type_: type_.split_whitespace().collect(),
+                default,
+                deprecated: attributes.contains("deprecated"),
+            }
+        })
+        .collect()
+}
+
+fn parse_enum_values(body: &str) -> Vec<(String, i64)> {
+    let mut next = 0;
+    body.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            let (name, value) = match v.split_once('=') {
+                None => (v, next),
+                Some((name, value)) => (
+                    name,
+                    value
+                        .trim()
+                        .parse()
+                        .unwrap_or_else(|_| panic!("malformed enum value: {}", v)),
+                ),
+            };
+            next = value + 1;
+            (name.trim().to_string(), value)
+        })
+        .collect()
+}
+
+fn parse(src: &str) -> Schema {
+    let src = strip_comments(src);
+    let mut definitions = HashMap::new();
+    let mut root_type = None;
+    let mut rest = src.as_str();
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+        let (keyword, after) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+        match keyword {
+            "table" | "struct" | "enum" => {
+                let open = after.find('{').expect("missing '{'");
+                let close = after.find('}').expect("missing '}'");
+                let header = after[..open].trim();
+                let body = &after[open + 1..close];
+                let definition = match keyword {
+                    "table" => (header.to_string(), Definition::Table(parse_fields(body))),
+                    "struct" => (header.to_string(), Definition::Struct(parse_fields(body))),
+                    _ => {
+                        let (name, underlying) = header
+                            .split_once(':')
+                            .unwrap_or_else(|| panic!("enum {} has no underlying type", header));
+                        (
+                            name.trim().to_string(),
+                            Definition::Enum {
+                                underlying: underlying.trim().to_string(),
+                                values: parse_enum_values(body),
+                            },
+                        )
+                    }
+                };
+                definitions.insert(definition.0, definition.1);
+                rest = &after[close + 1..];
+            }
+            _ => {
+                // namespace, root_type, include, attribute, ... all end in ';'.
+                let end = rest.find(';').map_or(rest.len(), |i| i + 1);
+                if keyword == "root_type" {
+                    root_type = Some(after[..end - keyword.len() - 1].trim().to_string());
+                }
+                rest = &rest[end..];
+            }
+        }
+    }
+    Schema {
+        definitions,
+        root_type,
+    }
+}
+
+fn check_fields(name: &str, frozen: &[Field], current: &[Field], violations: &mut Vec<String>) {
+    if current.len() < frozen.len() {
+        violations.push(format!("{}: fields were removed", name));
+    }
+    for (old, new) in frozen.iter().zip(current) {
+        if old.name != new.name || old.type_ != new.type_ {
+            violations.push(format!(
+                "{}: field `{}:{}` was replaced by `{}:{}`, fields must only be appended",
+                name, old.name, old.type_, new.name, new.type_
+            ));
+        } else if old.default != new.default {
+            violations.push(format!("{}.{}: default value changed", name, old.name));
+        } else if old.deprecated && !new.deprecated {
+            violations.push(format!("{}.{}: field was un-deprecated", name, old.name));
+        }
+    }
+}
+
+/// Returns a description of every way in which `current` breaks
+/// compatibility with `frozen`.
+pub fn check(frozen: &str, current: &str) -> Vec<String> {
+    let frozen = parse(frozen);
+    let current = parse(current);
+    let mut violations = Vec::new();
+    if frozen.root_type != current.root_type {
+        violations.push("root_type changed".to_string());
+    }
+    for (name, old) in &frozen.definitions {
+        let new = match current.definitions.get(name) {
+            None => {
+                violations.push(format!("{}: was removed", name));
+                continue;
+            }
+            Some(new) => new,
+        };
+        match (old, new) {
+            (Definition::Table(old), Definition::Table(new)) => {
+                check_fields(name, old, new, &mut violations)
+            }
+            (Definition::Struct(old), Definition::Struct(new)) => {
+                // The layout of structs is fixed, they can't even be extended.
+                if old != new {
+                    violations.push(format!("{}: structs can't be changed", name));
+                }
+            }
+            (
+                Definition::Enum {
+                    underlying: old_underlying,
+                    values: old_values,
+                },
+                Definition::Enum {
+                    underlying: new_underlying,
+                    values: new_values,
+                },
+            ) => {
+                if old_underlying != new_underlying {
+                    violations.push(format!("{}: underlying type changed", name));
+                }
+                for (value_name, value) in old_values {
+                    if !new_values.contains(&(value_name.clone(), *value)) {
+                        violations.push(format!(
+                            "{}::{}: was removed or renumbered",
+                            name, value_name
+                        ));
+                    }
+                }
+            }
+            _ => violations.push(format!("{}: kind of definition changed", name)),
+        }
+    }
+    violations.sort();
+    violations
+}
@@ -5,3 +5,23 @@ pub mod epoch_publisher_flatbuffers;
 #[allow(non_snake_case)]
 #[path = "../target/rangeserver/schema_generated.rs"]
 pub mod rangeserver_flatbuffers;
+
+// Code generated from the frozen schema versions, only used to produce golden
+// messages for the compatibility tests.
+#[cfg(test)]
+#[allow(non_snake_case)]
+#[path = "../target/rangeserver/versions/v1/v1_generated.rs"]
+mod rangeserver_flatbuffers_v1;
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+#[path = "../target/rangeserver/versions/v2/v2_generated.rs"]
+mod rangeserver_flatbuffers_v2;
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+#[path = "../target/epoch_publisher/versions/v1/v1_generated.rs"]
+mod epoch_publisher_flatbuffers_v1;
+
+#[cfg(test)]
+mod compatibility_tests;
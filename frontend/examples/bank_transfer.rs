@@ -0,0 +1,156 @@
+//! Moves money between accounts from several concurrent workers, then checks
+//! that no money was created or destroyed and that no balance went negative.
+//!
+//! Run against `atomix-dev`:
+//!
+//! ```sh
+//! cargo run --bin atomix-dev &
+//! cargo run -p frontend --example bank_transfer
+//! ```
+
+mod util;
+
+use clap::Parser;
+use tokio::task::JoinSet;
+use tonic::Status;
+use util::{Client, ConnectArgs, Transaction, MAX_ATTEMPTS};
+
+#[derive(Parser, Debug)]
+#[command(about = "Concurrent bank transfers with an invariant check", long_about = None)]
+struct Args {
+    #[command(flatten)]
+    connect: ConnectArgs,
+
+    #[arg(long, default_value_t = 10)]
+    accounts: u64,
+
+    #[arg(long, default_value_t = 100)]
+    initial_balance: u64,
+
+    #[arg(long, default_value_t = 4)]
+    workers: u64,
+
+    /// Transfers done by each worker.
+    #[arg(long, default_value_t = 50)]
+    transfers: u64,
+}
+
+fn account_key(account: u64) -> String {
+    format!("account/{:08}", account)
+}
+
+fn decode_balance(value: Option<Vec<u8>>) -> Result<u64, String> {
+    let value = value.ok_or("account is missing")?;
+    let bytes = value.try_into().map_err(|_| "balance is not 8 bytes")?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Returns false if `from` did not have enough money, in which case nothing
+/// is written.
+async fn transfer(tx: &mut Transaction, from: u64, to: u64, amount: u64) -> Result<bool, Status> {
+    let from_balance =
+        decode_balance(tx.get(&account_key(from)).await?).map_err(Status::data_loss)?;
+    let to_balance = decode_balance(tx.get(&account_key(to)).await?).map_err(Status::data_loss)?;
+    if from_balance < amount {
+        return Ok(false);
+    }
+    tx.put(
+        &account_key(from),
+        (from_balance - amount).to_be_bytes().to_vec(),
+    )
+    .await?;
+    tx.put(
+        &account_key(to),
+        (to_balance + amount).to_be_bytes().to_vec(),
+    )
+    .await?;
+    Ok(true)
+}
+
+async fn run_transfer(client: &Client, from: u64, to: u64, amount: u64) -> Result<bool, Status> {
+    let mut last_err = None;
+    for _ in 0..MAX_ATTEMPTS {
+        let mut tx = client.begin("bank_transfer").await?;
+        let result = match transfer(&mut tx, from, to, amount).await {
+            Ok(done) => tx.commit().await.map(|()| done),
+            Err(e) => {
+                tx.abort().await;
+                Err(e)
+            }
+        };
+        match result {
+            Ok(done) => return Ok(done),
+            // Conflicting transfers abort each other, so just try again.
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+// Deterministic so that runs are reproducible.
+fn pick(seed: u64, modulo: u64) -> u64 {
+    seed.wrapping_mul(0x9e3779b97f4a7c15).rotate_left(17) % modulo
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    assert!(args.accounts >= 2, "need at least two accounts");
+    let client = Client::connect(&args.connect, "bank_transfer").await?;
+
+    let mut tx = client.begin("bank_transfer").await?;
+    for account in 0..args.accounts {
+        tx.put(
+            &account_key(account),
+            args.initial_balance.to_be_bytes().to_vec(),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    let mut workers = JoinSet::new();
+    for worker in 0..args.workers {
+        let client = client.clone();
+        let accounts = args.accounts;
+        let transfers = args.transfers;
+        let max_amount = args.initial_balance;
+        workers.spawn(async move {
+            let mut done = 0;
+            for i in 0..transfers {
+                let seed = worker * transfers + i;
+                let from = pick(seed, accounts);
+                let to = (from + 1 + pick(seed + 1, accounts - 1)) % accounts;
+                let amount = 1 + pick(seed + 2, max_amount);
+                if run_transfer(&client, from, to, amount).await? {
+                    done += 1;
+                }
+            }
+            Ok::<u64, Status>(done)
+        });
+    }
+    let mut done = 0;
+    while let Some(result) = workers.join_next().await {
+        done += result??;
+    }
+    println!(
+        "{} of {} transfers went through",
+        done,
+        args.workers * args.transfers
+    );
+
+    let mut tx = client.begin("bank_transfer").await?;
+    let mut total = 0;
+    for account in 0..args.accounts {
+        // Balances are unsigned, so a transfer that overdrew an account
+        // would show up as a huge balance and break the total.
+        let balance = decode_balance(tx.get(&account_key(account)).await?)?;
+        total = u64::checked_add(total, balance).ok_or("total balance overflowed")?;
+    }
+    tx.commit().await?;
+    let expected = args.accounts * args.initial_balance;
+    if total != expected {
+        return Err(format!("total balance is {}, expected {}", total, expected).into());
+    }
+    println!("total balance is {} as expected", total);
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! A URL shortener on top of the frontend API. Codes are allocated from a
+//! counter in the same transaction that stores the mapping, so two URLs never
+//! get the same code and a URL shortened twice keeps its first code.
+//!
+//! Run against `atomix-dev`:
+//!
+//! ```sh
+//! cargo run --bin atomix-dev &
+//! cargo run -p frontend --example url_shortener -- https://example.com/a https://example.com/b
+//! ```
+
+mod util;
+
+use clap::Parser;
+use tonic::Status;
+use util::{Client, ConnectArgs, Transaction, MAX_ATTEMPTS};
+
+#[derive(Parser, Debug)]
+#[command(about = "A URL shortener", long_about = None)]
+struct Args {
+    #[command(flatten)]
+    connect: ConnectArgs,
+
+    /// URLs to shorten. Each one is then resolved back and checked, and the
+    /// last one is deleted.
+    #[arg(default_values_t = [
+        "https://example.com/".to_string(),
+        "https://example.com/docs".to_string(),
+        "https://example.com/".to_string(),
+    ])]
+    urls: Vec<String>,
+}
+
+const NEXT_ID_KEY: &str = "next_id";
+const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+fn encode_id(mut id: u64) -> String {
+    let mut code = Vec::new();
+    loop {
+        code.push(ALPHABET[(id % ALPHABET.len() as u64) as usize]);
+        id /= ALPHABET.len() as u64;
+        if id == 0 {
+            break;
+        }
+    }
+    code.reverse();
+    String::from_utf8(code).unwrap()
+}
+
+fn code_key(code: &str) -> String {
+    format!("code/{}", code)
+}
+
+fn url_key(url: &str) -> String {
+    format!("url/{}", url)
+}
+
+// Everything stored is UTF-8, so this is only lossy on corruption.
+fn utf8(value: Vec<u8>) -> String {
+    String::from_utf8_lossy(&value).into_owned()
+}
+
+async fn shorten_in(tx: &mut Transaction, url: &str) -> Result<String, Status> {
+    if let Some(code) = tx.get(&url_key(url)).await? {
+        return Ok(utf8(code));
+    }
+    let id = match tx.get(NEXT_ID_KEY).await? {
+        Some(value) => u64::from_be_bytes(
+            value
+                .try_into()
+                .map_err(|_| Status::data_loss("counter is not 8 bytes"))?,
+        ),
+        None => 0,
+    };
+    let code = encode_id(id);
+    tx.put(NEXT_ID_KEY, (id + 1).to_be_bytes().to_vec()).await?;
+    tx.put(&code_key(&code), url.as_bytes().to_vec()).await?;
+    tx.put(&url_key(url), code.as_bytes().to_vec()).await?;
+    Ok(code)
+}
+
+async fn shorten(client: &Client, url: &str) -> Result<String, Status> {
+    let mut last_err = None;
+    for _ in 0..MAX_ATTEMPTS {
+        let mut tx = client.begin("url_shortener").await?;
+        let result = match shorten_in(&mut tx, url).await {
+            Ok(code) => tx.commit().await.map(|()| code),
+            Err(e) => {
+                tx.abort().await;
+                Err(e)
+            }
+        };
+        match result {
+            Ok(code) => return Ok(code),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+async fn resolve(client: &Client, code: &str) -> Result<Option<String>, Status> {
+    let mut tx = client.begin("url_shortener").await?;
+    let url = tx.get(&code_key(code)).await?;
+    tx.commit().await?;
+    Ok(url.map(utf8))
+}
+
+async fn remove(client: &Client, code: &str) -> Result<(), Status> {
+    let mut tx = client.begin("url_shortener").await?;
+    if let Some(url) = tx.get(&code_key(code)).await? {
+        tx.delete(&url_key(&utf8(url))).await?;
+        tx.delete(&code_key(code)).await?;
+    }
+    tx.commit().await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let client = Client::connect(&args.connect, "url_shortener").await?;
+
+    let mut codes = Vec::new();
+    for url in &args.urls {
+        let code = shorten(&client, url).await?;
+        println!("{} -> {}", url, code);
+        codes.push(code);
+    }
+    for (url, code) in args.urls.iter().zip(&codes) {
+        let resolved = resolve(&client, code).await?;
+        if resolved.as_deref() != Some(url.as_str()) {
+            return Err(format!("{} resolved to {:?}, expected {}", code, resolved, url).into());
+        }
+    }
+
+    if let Some(code) = codes.last() {
+        remove(&client, code).await?;
+        if let Some(url) = resolve(&client, code).await? {
+            return Err(format!("{} still resolves to {} after removal", code, url).into());
+        }
+        println!("removed {}", code);
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! A thin wrapper over the frontend gRPC API shared by the examples.
+
+// Not every example uses every helper.
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use common::region::{Region, Zone};
+use proto::frontend::{
+    frontend_client::FrontendClient, AbortRequest, CommitRequest, DeleteRequest, GetRequest,
+    Keyspace, PutRequest, StartTransactionRequest,
+};
+use proto::universe::{CreateKeyspaceRequest, KeyRangeRequest};
+use tonic::{transport::Channel, Status};
+use uuid::Uuid;
+
+/// How many times a transaction is retried after aborting before giving up.
+pub const MAX_ATTEMPTS: usize = 20;
+
+/// Flags shared by all the examples. The defaults match `atomix-dev` run with
+/// its built-in config.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConnectArgs {
+    /// The proto server address of the frontend.
+    #[arg(long, default_value = "127.0.0.1:50057")]
+    pub frontend: String,
+
+    #[arg(long, default_value = "test-region")]
+    pub region: String,
+
+    #[arg(long, default_value = "a")]
+    pub zone: String,
+
+    /// How long to wait for the frontend to come up.
+    #[arg(long, default_value_t = 60)]
+    pub connect_timeout_secs: u64,
+}
+
+#[derive(Clone)]
+pub struct Client {
+    client: FrontendClient<Channel>,
+    keyspace: Keyspace,
+}
+
+impl Client {
+    /// Connects to the frontend and creates a fresh keyspace named after the
+    /// example, so that runs don't see each other's data.
+    pub async fn connect(
+        args: &ConnectArgs,
+        example: &str,
+    ) -> Result<Client, Box<dyn std::error::Error>> {
+        let deadline = Instant::now() + Duration::from_secs(args.connect_timeout_secs);
+        let mut client = loop {
+            match FrontendClient::connect(format!("http://{}", args.frontend)).await {
+                Ok(client) => break client,
+                Err(e) if Instant::now() > deadline => return Err(e.into()),
+                Err(_) => tokio::time::sleep(Duration::from_millis(200)).await,
+            }
+        };
+        let keyspace = Keyspace {
+            namespace: "examples".to_string(),
+            name: format!("{}_{}", example, Uuid::new_v4().simple()),
+        };
+        let zone = Zone {
+            region: Region {
+                cloud: None,
+                name: args.region.clone(),
+            },
+            name: args.zone.clone(),
+        };
+        client
+            .create_keyspace(CreateKeyspaceRequest {
+                namespace: keyspace.namespace.clone(),
+                name: keyspace.name.clone(),
+                primary_zone: Some(zone.into()),
+                base_key_ranges: vec![KeyRangeRequest {
+                    lower_bound_inclusive: vec![],
+                    upper_bound_exclusive: vec![],
+                }],
+                optimistic_reads: None,
+            })
+            .await?;
+        Ok(Client { client, keyspace })
+    }
+
+    pub async fn begin(&self, label: &str) -> Result<Transaction, Status> {
+        let mut client = self.client.clone();
+        let response = client
+            .start_transaction(StartTransactionRequest {
+                labels: [("example".to_string(), label.to_string())].into(),
+            })
+            .await?;
+        Ok(Transaction {
+            client,
+            keyspace: self.keyspace.clone(),
+            id: response.into_inner().transaction_id,
+        })
+    }
+}
+
+/// A transaction in the example's keyspace. Dropping it without committing
+/// leaves it to time out, so examples abort explicitly on failure.
+pub struct Transaction {
+    client: FrontendClient<Channel>,
+    keyspace: Keyspace,
+    id: String,
+}
+
+impl Transaction {
+    pub async fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>, Status> {
+        let response = self
+            .client
+            .get(GetRequest {
+                transaction_id: self.id.clone(),
+                keyspace: Some(self.keyspace.clone()),
+                key: key.as_bytes().to_vec(),
+            })
+            .await?;
+        Ok(response.into_inner().value)
+    }
+
+    pub async fn put(&mut self, key: &str, value: Vec<u8>) -> Result<(), Status> {
+        self.client
+            .put(PutRequest {
+                transaction_id: self.id.clone(),
+                keyspace: Some(self.keyspace.clone()),
+                key: key.as_bytes().to_vec(),
+                value,
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&mut self, key: &str) -> Result<(), Status> {
+        self.client
+            .delete(DeleteRequest {
+                transaction_id: self.id.clone(),
+                keyspace: Some(self.keyspace.clone()),
+                key: key.as_bytes().to_vec(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn commit(mut self) -> Result<(), Status> {
+        self.client
+            .commit(CommitRequest {
+                transaction_id: self.id.clone(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Best effort, the transaction times out anyway if this fails.
+    pub async fn abort(mut self) {
+        let _ = self
+            .client
+            .abort(AbortRequest {
+                transaction_id: self.id.clone(),
+            })
+            .await;
+    }
+}
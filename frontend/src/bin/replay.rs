@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use frontend::client::{Client, ClientConfig, Discovery};
+use frontend::journal;
+
+#[derive(Parser, Debug)]
+#[command(name = "frontend-replay")]
+#[command(about = "Replays a client journal against a cluster", long_about = None)]
+struct Args {
+    /// The journal, as written by a client with `ClientConfig::journal` set.
+    #[arg(long)]
+    journal: PathBuf,
+
+    /// The frontends of the cluster to replay against, as `host:port`.
+    #[arg(long, value_delimiter = ',', default_value = "127.0.0.1:50057")]
+    frontends: Vec<String>,
+
+    /// Only replay the transaction with this id.
+    #[arg(long)]
+    transaction_id: Option<String>,
+}
+
+// Transactions are replayed one at a time, in the order they finished. That
+// is an order they could have run in, but not necessarily the one they did,
+// so divergences point at what to look at rather than proving an anomaly.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let mut transactions = journal::read(&args.journal)?;
+    if let Some(id) = &args.transaction_id {
+        transactions.retain(|t| t.transaction_id == *id);
+    }
+    let client =
+        Client::connect(Discovery::Static(args.frontends), ClientConfig::default()).await?;
+    let mut diverged = 0;
+    for transaction in &transactions {
+        let divergences = journal::replay(&client, transaction).await?;
+        if divergences.is_empty() {
+            continue;
+        }
+        diverged += 1;
+        println!("{}:", transaction.transaction_id);
+        for divergence in divergences {
+            println!("  {}", divergence);
+        }
+    }
+    println!(
+        "Replayed {} transactions, {} diverged",
+        transactions.len(),
+        diverged
+    );
+    Ok(())
+}
@@ -0,0 +1,611 @@
+//! Client for applications using several frontends without a load balancer
+//! in front of them. It finds the frontends from a static list or from DNS,
+//! keeps probing them, and starts each transaction on the healthy frontend
+//! with the fewest of this client's transactions in flight.
+//!
+//! A transaction lives in the memory of the frontend that started it, so all
+//! its requests stick to that frontend. If the frontend goes away before the
+//! transaction read anything, the transaction moves to another frontend by
+//! starting over there and replaying its writes, which frontends only buffer
+//! until commit anyway. Once it read something it can no longer move, since
+//! the reads could come out differently after the application acted on them,
+//! and it fails as it would with a single frontend. Commits never move: a
+//! commit that got lost on the way may still have happened.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use proto::frontend::{
+    frontend_client::FrontendClient, AbortRequest, CommitRequest, DeleteRequest, GetRequest,
+    Keyspace, PingRequest, PutRequest, StartTransactionRequest,
+};
+use proto::universe::{CreateKeyspaceRequest, CreateKeyspaceResponse};
+use tokio::task::JoinSet;
+use tonic::{
+    transport::{Channel, Endpoint},
+    Code, Response, Status,
+};
+use tracing::{info, warn};
+
+use crate::journal::{self, Journal, Outcome, Recorder};
+
+/// Where to find the frontends.
+#[derive(Clone, Debug)]
+pub enum Discovery {
+    /// Fixed `host:port` addresses.
+    Static(Vec<String>),
+    /// All the addresses `name` resolves to. They are looked up again before
+    /// every round of probes, so frontends come and go by updating DNS.
+    Dns { name: String, port: u16 },
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    pub probe_interval: Duration,
+    /// A frontend that does not answer a probe in time is avoided until it
+    /// answers one again.
+    pub probe_timeout: Duration,
+    /// Journals every transaction, to replay them when debugging. See
+    /// `journal`.
+    pub journal: Option<Arc<Journal>>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            probe_interval: Duration::from_secs(5),
+            probe_timeout: Duration::from_secs(1),
+            journal: None,
+        }
+    }
+}
+
+struct Frontend {
+    addr: String,
+    client: FrontendClient<Channel>,
+    healthy: AtomicBool,
+    // Transactions of this client currently pinned to the frontend.
+    active: AtomicUsize,
+}
+
+impl Frontend {
+    fn mark_unhealthy(&self) {
+        if self.healthy.swap(false, Ordering::Relaxed) {
+            warn!("Frontend {} is unreachable", self.addr);
+        }
+    }
+}
+
+// Counts a transaction against its frontend for as long as it is pinned.
+struct Pinned(Arc<Frontend>);
+
+impl Pinned {
+    fn new(frontend: Arc<Frontend>) -> Pinned {
+        frontend.active.fetch_add(1, Ordering::Relaxed);
+        Pinned(frontend)
+    }
+}
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Healthy frontends first, then the least loaded. Scanning from `start`
+// spreads ties over all the frontends rather than piling on the first one.
+fn least_loaded(
+    frontends: &[Arc<Frontend>],
+    start: usize,
+    excluding: &[String],
+) -> Option<Arc<Frontend>> {
+    let n = frontends.len();
+    (0..n)
+        .map(|i| &frontends[(start + i) % n])
+        .filter(|f| !excluding.contains(&f.addr))
+        .min_by_key(|f| {
+            (
+                !f.healthy.load(Ordering::Relaxed),
+                f.active.load(Ordering::Relaxed),
+            )
+        })
+        .cloned()
+}
+
+// The frontend is gone, or restarted and lost the transaction.
+fn lost_transaction(status: &Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::NotFound)
+}
+
+struct Inner {
+    discovery: Discovery,
+    config: ClientConfig,
+    frontends: Mutex<Vec<Arc<Frontend>>>,
+    next: AtomicUsize,
+}
+
+impl Inner {
+    async fn discover(&self) -> Option<Vec<String>> {
+        match &self.discovery {
+            Discovery::Static(addrs) => Some(addrs.clone()),
+            Discovery::Dns { name, port } => {
+                match tokio::net::lookup_host((name.as_str(), *port)).await {
+                    Ok(addrs) => Some(addrs.map(|addr| addr.to_string()).collect()),
+                    Err(e) => {
+                        // Keeps using the frontends found so far.
+                        warn!("Failed to resolve frontends at {}: {}", name, e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_frontends(&self, addrs: Vec<String>) {
+        let mut frontends = self.frontends.lock().unwrap();
+        let mut updated = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            if updated.iter().any(|f: &Arc<Frontend>| f.addr == addr) {
+                continue;
+            }
+            if let Some(existing) = frontends.iter().find(|f| f.addr == addr) {
+                updated.push(existing.clone());
+                continue;
+            }
+            let endpoint = match Endpoint::from_shared(format!("http://{}", addr)) {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    warn!("Ignoring invalid frontend address {}: {}", addr, e);
+                    continue;
+                }
+            };
+            info!("Discovered frontend {}", addr);
+            let channel = endpoint
+                .connect_timeout(self.config.probe_timeout)
+                .connect_lazy();
+            updated.push(Arc::new(Frontend {
+                addr,
+                client: FrontendClient::new(channel),
+                // Until the first probe says otherwise.
+                healthy: AtomicBool::new(false),
+                active: AtomicUsize::new(0),
+            }));
+        }
+        // Transactions pinned to dropped frontends keep them alive until they
+        // finish.
+        *frontends = updated;
+    }
+
+    async fn refresh(&self) {
+        if let Some(addrs) = self.discover().await {
+            self.update_frontends(addrs);
+        }
+        let frontends = self.frontends.lock().unwrap().clone();
+        let mut probes = JoinSet::new();
+        for frontend in frontends {
+            let timeout = self.config.probe_timeout;
+            probes.spawn(async move {
+                let mut client = frontend.client.clone();
+                let healthy = matches!(
+                    tokio::time::timeout(timeout, client.ping(PingRequest {})).await,
+                    Ok(Ok(_))
+                );
+                if !healthy {
+                    frontend.mark_unhealthy();
+                } else if !frontend.healthy.swap(true, Ordering::Relaxed) {
+                    info!("Frontend {} is healthy", frontend.addr);
+                }
+            });
+        }
+        while probes.join_next().await.is_some() {}
+    }
+
+    fn pick(&self, excluding: &[String]) -> Option<Arc<Frontend>> {
+        let frontends = self.frontends.lock().unwrap();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        least_loaded(&frontends, start, excluding)
+    }
+
+    // Starts a transaction on the best frontend that is not in `failed`,
+    // moving on to the next one while they are unreachable.
+    async fn start_transaction(
+        &self,
+        labels: &HashMap<String, String>,
+        failed: &mut Vec<String>,
+    ) -> Result<(Pinned, String), Status> {
+        loop {
+            let frontend = self
+                .pick(failed)
+                .ok_or_else(|| Status::unavailable("No reachable frontend"))?;
+            let request = StartTransactionRequest {
+                labels: labels.clone(),
+            };
+            match frontend.client.clone().start_transaction(request).await {
+                Ok(response) => {
+                    return Ok((Pinned::new(frontend), response.into_inner().transaction_id))
+                }
+                Err(e) if e.code() == Code::Unavailable => {
+                    frontend.mark_unhealthy();
+                    failed.push(frontend.addr.clone());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Cheap to clone, all clones share the same frontends.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+impl Client {
+    /// Finds and probes the frontends once before returning, then keeps
+    /// probing them in the background for as long as the client is in use.
+    pub async fn connect(discovery: Discovery, config: ClientConfig) -> Result<Client, Status> {
+        let inner = Arc::new(Inner {
+            discovery,
+            config,
+            frontends: Mutex::new(Vec::new()),
+            next: AtomicUsize::new(0),
+        });
+        inner.refresh().await;
+        if inner.frontends.lock().unwrap().is_empty() {
+            return Err(Status::unavailable("No frontend found"));
+        }
+        let weak: Weak<Inner> = Arc::downgrade(&inner);
+        let probe_interval = inner.config.probe_interval;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(probe_interval).await;
+                let Some(inner) = weak.upgrade() else {
+                    return;
+                };
+                inner.refresh().await;
+            }
+        });
+        Ok(Client { inner })
+    }
+
+    /// Addresses of the frontends that answered their last probe.
+    pub fn healthy_frontends(&self) -> Vec<String> {
+        self.inner
+            .frontends
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|f| f.healthy.load(Ordering::Relaxed))
+            .map(|f| f.addr.clone())
+            .collect()
+    }
+
+    pub async fn create_keyspace(
+        &self,
+        request: CreateKeyspaceRequest,
+    ) -> Result<CreateKeyspaceResponse, Status> {
+        let frontend = self
+            .inner
+            .pick(&[])
+            .ok_or_else(|| Status::unavailable("No reachable frontend"))?;
+        let response = frontend.client.clone().create_keyspace(request).await?;
+        Ok(response.into_inner())
+    }
+
+    pub async fn start_transaction(
+        &self,
+        labels: HashMap<String, String>,
+    ) -> Result<Transaction, Status> {
+        let (frontend, id) = self
+            .inner
+            .start_transaction(&labels, &mut Vec::new())
+            .await?;
+        let recorder = self
+            .inner
+            .config
+            .journal
+            .clone()
+            .map(|journal| Recorder::new(journal, &labels));
+        Ok(Transaction {
+            inner: self.inner.clone(),
+            frontend,
+            id,
+            labels,
+            replay: Some(Vec::new()),
+            recorder,
+        })
+    }
+}
+
+enum Write {
+    Put {
+        keyspace: Keyspace,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        keyspace: Keyspace,
+        key: Vec<u8>,
+    },
+}
+
+/// A transaction pinned to the frontend that started it. Dropping it without
+/// committing or aborting leaves it to time out on the frontend.
+pub struct Transaction {
+    inner: Arc<Inner>,
+    frontend: Pinned,
+    id: String,
+    labels: HashMap<String, String>,
+    // The writes so far, in order, to replay them on another frontend. None
+    // once the transaction read anything, as it can then no longer move.
+    replay: Option<Vec<Write>>,
+    // None unless the client journals transactions, and once it finished.
+    recorder: Option<Recorder>,
+}
+
+impl Transaction {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Address of the frontend the transaction is pinned to.
+    pub fn frontend(&self) -> &str {
+        &self.frontend.0.addr
+    }
+
+    pub async fn get(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, Status> {
+        let issued = Instant::now();
+        let result = self
+            .with_failover(|mut client, transaction_id| {
+                let request = GetRequest {
+                    transaction_id,
+                    keyspace: Some(keyspace.clone()),
+                    key: key.clone(),
+                };
+                async move { client.get(request).await }
+            })
+            .await
+            .map(|response| response.value);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_get(issued, keyspace, &key, &result);
+        }
+        let value = result?;
+        self.replay = None;
+        Ok(value)
+    }
+
+    pub async fn put(
+        &mut self,
+        keyspace: &Keyspace,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Result<(), Status> {
+        let issued = Instant::now();
+        let result = self
+            .with_failover(|mut client, transaction_id| {
+                let request = PutRequest {
+                    transaction_id,
+                    keyspace: Some(keyspace.clone()),
+                    key: key.clone(),
+                    value: value.clone(),
+                };
+                async move { client.put(request).await }
+            })
+            .await
+            .map(|_| ());
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_put(issued, keyspace, &key, &value, &result);
+        }
+        result?;
+        if let Some(replay) = self.replay.as_mut() {
+            replay.push(Write::Put {
+                keyspace: keyspace.clone(),
+                key,
+                value,
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn delete(&mut self, keyspace: &Keyspace, key: Vec<u8>) -> Result<(), Status> {
+        let issued = Instant::now();
+        let result = self
+            .with_failover(|mut client, transaction_id| {
+                let request = DeleteRequest {
+                    transaction_id,
+                    keyspace: Some(keyspace.clone()),
+                    key: key.clone(),
+                };
+                async move { client.delete(request).await }
+            })
+            .await
+            .map(|_| ());
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_delete(issued, keyspace, &key, &result);
+        }
+        result?;
+        if let Some(replay) = self.replay.as_mut() {
+            replay.push(Write::Delete {
+                keyspace: keyspace.clone(),
+                key,
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn commit(mut self) -> Result<(), Status> {
+        let result = self
+            .frontend
+            .0
+            .client
+            .clone()
+            .commit(CommitRequest {
+                transaction_id: self.id.clone(),
+            })
+            .await
+            .map(|_| ());
+        self.finish_journal(journal::outcome(&result, Outcome::Committed));
+        result
+    }
+
+    pub async fn abort(mut self) -> Result<(), Status> {
+        let result = self
+            .frontend
+            .0
+            .client
+            .clone()
+            .abort(AbortRequest {
+                transaction_id: self.id.clone(),
+            })
+            .await
+            .map(|_| ());
+        self.finish_journal(journal::outcome(&result, Outcome::Aborted));
+        result
+    }
+
+    fn finish_journal(&mut self, outcome: Outcome) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(&self.id, &self.frontend.0.addr, outcome);
+        }
+    }
+
+    async fn with_failover<T, F, Fut>(&mut self, op: F) -> Result<T, Status>
+    where
+        F: Fn(FrontendClient<Channel>, String) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        let mut failed = Vec::new();
+        loop {
+            let status = match op(self.frontend.0.client.clone(), self.id.clone()).await {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status) => status,
+            };
+            if self.replay.is_none() || !lost_transaction(&status) {
+                return Err(status);
+            }
+            if status.code() == Code::Unavailable {
+                self.frontend.0.mark_unhealthy();
+            }
+            failed.push(self.frontend.0.addr.clone());
+            self.move_to_another_frontend(&mut failed).await?;
+        }
+    }
+
+    // Starts over on a frontend not in `failed` and replays the writes there.
+    async fn move_to_another_frontend(&mut self, failed: &mut Vec<String>) -> Result<(), Status> {
+        'frontends: loop {
+            let (frontend, id) = self.inner.start_transaction(&self.labels, failed).await?;
+            let mut client = frontend.0.client.clone();
+            for write in self.replay.iter().flatten() {
+                let result = match write {
+                    Write::Put {
+                        keyspace,
+                        key,
+                        value,
+                    } => client
+                        .put(PutRequest {
+                            transaction_id: id.clone(),
+                            keyspace: Some(keyspace.clone()),
+                            key: key.clone(),
+                            value: value.clone(),
+                        })
+                        .await
+                        .map(|_| ()),
+                    Write::Delete { keyspace, key } => client
+                        .delete(DeleteRequest {
+                            transaction_id: id.clone(),
+                            keyspace: Some(keyspace.clone()),
+                            key: key.clone(),
+                        })
+                        .await
+                        .map(|_| ()),
+                };
+                match result {
+                    Ok(()) => (),
+                    Err(status) if lost_transaction(&status) => {
+                        if status.code() == Code::Unavailable {
+                            frontend.0.mark_unhealthy();
+                        }
+                        failed.push(frontend.0.addr.clone());
+                        continue 'frontends;
+                    }
+                    Err(status) => return Err(status),
+                }
+            }
+            info!(
+                "Moved transaction {} from frontend {} to {} as {}",
+                self.id, self.frontend.0.addr, frontend.0.addr, id
+            );
+            self.frontend = frontend;
+            self.id = id;
+            return Ok(());
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.finish_journal(Outcome::Abandoned);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frontend(addr: &str, healthy: bool, active: usize) -> Arc<Frontend> {
+        let channel = Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect_lazy();
+        Arc::new(Frontend {
+            addr: addr.to_string(),
+            client: FrontendClient::new(channel),
+            healthy: AtomicBool::new(healthy),
+            active: AtomicUsize::new(active),
+        })
+    }
+
+    #[tokio::test]
+    async fn picks_least_loaded_healthy_frontend() {
+        let frontends = vec![
+            frontend("a:1", true, 3),
+            frontend("b:1", false, 0),
+            frontend("c:1", true, 1),
+        ];
+        for start in 0..3 {
+            let picked = least_loaded(&frontends, start, &[]).unwrap();
+            assert_eq!(picked.addr, "c:1");
+        }
+        let picked = least_loaded(&frontends, 0, &["c:1".to_string()]).unwrap();
+        assert_eq!(picked.addr, "a:1");
+        // Unhealthy frontends are still tried once nothing else is left.
+        let excluding = ["a:1".to_string(), "c:1".to_string()];
+        let picked = least_loaded(&frontends, 0, &excluding).unwrap();
+        assert_eq!(picked.addr, "b:1");
+        let excluding = ["a:1".to_string(), "b:1".to_string(), "c:1".to_string()];
+        assert!(least_loaded(&frontends, 0, &excluding).is_none());
+    }
+
+    #[tokio::test]
+    async fn spreads_ties_and_tracks_pinned_transactions() {
+        let frontends = vec![frontend("a:1", true, 0), frontend("b:1", true, 0)];
+        let first = Pinned::new(least_loaded(&frontends, 0, &[]).unwrap());
+        let second = Pinned::new(least_loaded(&frontends, 0, &[]).unwrap());
+        assert_ne!(first.0.addr, second.0.addr);
+        drop(first);
+        let third = least_loaded(&frontends, 1, &[]).unwrap();
+        assert_ne!(third.addr, second.0.addr);
+        assert_eq!(frontends[0].active.load(Ordering::Relaxed), 0);
+    }
+}
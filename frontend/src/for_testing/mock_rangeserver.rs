@@ -68,6 +68,7 @@ impl MockRangeServer {
                     &RecordArgs {
                         key: Some(key),
                         value,
+                        ..Default::default()
                     },
                 ));
             }
@@ -152,6 +153,7 @@ impl MockRangeServer {
                 status: Status::Ok,
                 epoch_lease: epoch_lease,
                 highest_known_epoch: epoch,
+                retry_after_us: 0,
             },
         );
 
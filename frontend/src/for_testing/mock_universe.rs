@@ -1,4 +1,5 @@
 use once_cell::sync::Lazy;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tonic::{Request, Response, Status};
 
@@ -8,11 +9,22 @@ use proto::universe::{
     get_keyspace_info_request::KeyspaceInfoSearchField,
     universe_client::UniverseClient,
     universe_server::{Universe, UniverseServer},
-    CreateKeyspaceRequest, CreateKeyspaceResponse, GetKeyspaceInfoRequest, GetKeyspaceInfoResponse,
-    KeyspaceInfo, ListKeyspacesRequest, ListKeyspacesResponse,
+    CheckCrossNamespaceAccessRequest, CheckCrossNamespaceAccessResponse, CreateKeyspaceRequest,
+    CreateKeyspaceResponse, DeleteKeyspaceAliasRequest, DeleteKeyspaceAliasResponse,
+    GetKeyspaceInfoRequest, GetKeyspaceInfoResponse, GetNamespaceDefaultsRequest,
+    GetNamespaceDefaultsResponse, GetNamespacePolicyRequest, GetNamespacePolicyResponse,
+    KeyspaceAlias, KeyspaceInfo, ListKeyspaceAliasesRequest, ListKeyspaceAliasesResponse,
+    ListKeyspacesRequest, ListKeyspacesResponse, NamespaceDefaults, NamespacePolicy,
+    RenameKeyspaceRequest, RenameKeyspaceResponse, SetKeyspaceColocationRequest,
+    SetKeyspaceColocationResponse, SetKeyspacePlacementRequest, SetKeyspacePlacementResponse,
+    SetKeyspaceReadOnlyRequest, SetKeyspaceReadOnlyResponse, SetKeyspaceValidationPolicyRequest,
+    SetKeyspaceValidationPolicyResponse, SetNamespaceDefaultsRequest, SetNamespaceDefaultsResponse,
+    SetNamespacePolicyRequest, SetNamespacePolicyResponse, SplitKeyRangeRequest,
+    SplitKeyRangeResponse,
 };
 use tokio::sync::oneshot;
 use tracing::info;
+use universe::{namespace_defaults, namespace_policy};
 use uuid::Uuid;
 
 static RUNTIME: Lazy<tokio::runtime::Runtime> =
@@ -20,6 +32,10 @@ static RUNTIME: Lazy<tokio::runtime::Runtime> =
 
 pub struct MockUniverse {
     keyspaces_info: Arc<Mutex<Vec<KeyspaceInfo>>>,
+    namespace_policies: Mutex<HashMap<String, NamespacePolicy>>,
+    namespace_defaults: Mutex<HashMap<String, NamespaceDefaults>>,
+    // (namespace, alias) -> name
+    keyspace_aliases: Mutex<HashMap<(String, String), String>>,
     server_shutdown_tx: Option<oneshot::Sender<()>>,
 }
 
@@ -51,10 +67,22 @@ impl Universe for MockUniverse {
         };
         let keyspace_info = KeyspaceInfo {
             keyspace_id: Uuid::new_v4().to_string(),
-            namespace: req_inner.namespace,
             name: req_inner.name,
             primary_zone: req_inner.primary_zone,
             base_key_ranges,
+            read_only: false,
+            optimistic_reads: namespace_defaults::optimistic_reads(
+                req_inner.optimistic_reads,
+                self.namespace_defaults
+                    .lock()
+                    .unwrap()
+                    .get(&req_inner.namespace),
+            ),
+            namespace: req_inner.namespace,
+            validation_policy: None,
+            validation_policy_inherited: false,
+            placement: None,
+            colocation: None,
         };
         self.keyspaces_info
             .lock()
@@ -77,30 +105,256 @@ impl Universe for MockUniverse {
         _request: Request<GetKeyspaceInfoRequest>,
     ) -> Result<Response<GetKeyspaceInfoResponse>, Status> {
         let keyspace_info_search_field = _request.into_inner().keyspace_info_search_field.unwrap();
+        let mut found = None;
         match keyspace_info_search_field {
             KeyspaceInfoSearchField::KeyspaceId(keyspace_id) => {
                 for keyspace_info in self.keyspaces_info.lock().unwrap().iter() {
                     if keyspace_info.keyspace_id == keyspace_id {
-                        return Ok(Response::new(GetKeyspaceInfoResponse {
-                            keyspace_info: Some(keyspace_info.clone()),
-                        }));
+                        found = Some(keyspace_info.clone());
+                        break;
                     }
                 }
             }
             KeyspaceInfoSearchField::Keyspace(keyspace) => {
+                let alias = self
+                    .keyspace_aliases
+                    .lock()
+                    .unwrap()
+                    .get(&(keyspace.namespace.clone(), keyspace.name.clone()))
+                    .cloned();
                 for keyspace_info in self.keyspaces_info.lock().unwrap().iter() {
                     if keyspace_info.namespace == keyspace.namespace
-                        && keyspace_info.name == keyspace.name
+                        && (keyspace_info.name == keyspace.name
+                            || Some(&keyspace_info.name) == alias.as_ref())
                     {
-                        return Ok(Response::new(GetKeyspaceInfoResponse {
-                            keyspace_info: Some(keyspace_info.clone()),
-                        }));
+                        found = Some(keyspace_info.clone());
+                        break;
                     }
                 }
             }
         }
+        let mut keyspace_info = found.ok_or_else(|| Status::not_found("Keyspace not found"))?;
+        let defaults = self
+            .namespace_defaults
+            .lock()
+            .unwrap()
+            .get(&keyspace_info.namespace)
+            .cloned();
+        namespace_defaults::apply(&mut keyspace_info, defaults.as_ref());
+        Ok(Response::new(GetKeyspaceInfoResponse {
+            keyspace_info: Some(keyspace_info),
+        }))
+    }
+
+    async fn set_keyspace_read_only(
+        &self,
+        _request: Request<SetKeyspaceReadOnlyRequest>,
+    ) -> Result<Response<SetKeyspaceReadOnlyResponse>, Status> {
+        let req_inner = _request.into_inner();
+        let keyspace = req_inner.keyspace.unwrap();
+        for keyspace_info in self.keyspaces_info.lock().unwrap().iter_mut() {
+            if keyspace_info.namespace == keyspace.namespace && keyspace_info.name == keyspace.name
+            {
+                keyspace_info.read_only = req_inner.read_only;
+                return Ok(Response::new(SetKeyspaceReadOnlyResponse {}));
+            }
+        }
+        Err(Status::not_found("Keyspace not found"))
+    }
+
+    async fn set_keyspace_validation_policy(
+        &self,
+        request: Request<SetKeyspaceValidationPolicyRequest>,
+    ) -> Result<Response<SetKeyspaceValidationPolicyResponse>, Status> {
+        let req_inner = request.into_inner();
+        let keyspace = req_inner.keyspace.unwrap();
+        for keyspace_info in self.keyspaces_info.lock().unwrap().iter_mut() {
+            if keyspace_info.namespace == keyspace.namespace && keyspace_info.name == keyspace.name
+            {
+                keyspace_info.validation_policy = req_inner.validation_policy;
+                return Ok(Response::new(SetKeyspaceValidationPolicyResponse {}));
+            }
+        }
+        Err(Status::not_found("Keyspace not found"))
+    }
+
+    async fn set_keyspace_placement(
+        &self,
+        request: Request<SetKeyspacePlacementRequest>,
+    ) -> Result<Response<SetKeyspacePlacementResponse>, Status> {
+        let req_inner = request.into_inner();
+        let keyspace = req_inner.keyspace.unwrap();
+        for keyspace_info in self.keyspaces_info.lock().unwrap().iter_mut() {
+            if keyspace_info.namespace == keyspace.namespace && keyspace_info.name == keyspace.name
+            {
+                keyspace_info.placement = req_inner.placement;
+                return Ok(Response::new(SetKeyspacePlacementResponse {}));
+            }
+        }
+        Err(Status::not_found("Keyspace not found"))
+    }
+
+    async fn set_keyspace_colocation(
+        &self,
+        request: Request<SetKeyspaceColocationRequest>,
+    ) -> Result<Response<SetKeyspaceColocationResponse>, Status> {
+        let req_inner = request.into_inner();
+        let keyspace = req_inner.keyspace.unwrap();
+        for keyspace_info in self.keyspaces_info.lock().unwrap().iter_mut() {
+            if keyspace_info.namespace == keyspace.namespace && keyspace_info.name == keyspace.name
+            {
+                keyspace_info.colocation = req_inner.colocation;
+                return Ok(Response::new(SetKeyspaceColocationResponse {}));
+            }
+        }
         Err(Status::not_found("Keyspace not found"))
     }
+
+    async fn split_key_range(
+        &self,
+        request: Request<SplitKeyRangeRequest>,
+    ) -> Result<Response<SplitKeyRangeResponse>, Status> {
+        let req_inner = request.into_inner();
+        for keyspace_info in self.keyspaces_info.lock().unwrap().iter_mut() {
+            if keyspace_info.keyspace_id != req_inner.keyspace_id {
+                continue;
+            }
+            let ranges = &mut keyspace_info.base_key_ranges;
+            if let Some(position) = ranges
+                .iter()
+                .position(|r| r.base_range_uuid == req_inner.range_id)
+            {
+                ranges.splice(position..position + 1, req_inner.new_ranges);
+            }
+            return Ok(Response::new(SplitKeyRangeResponse {}));
+        }
+        Err(Status::not_found("Keyspace not found"))
+    }
+
+    async fn set_namespace_policy(
+        &self,
+        request: Request<SetNamespacePolicyRequest>,
+    ) -> Result<Response<SetNamespacePolicyResponse>, Status> {
+        let req_inner = request.into_inner();
+        let mut policies = self.namespace_policies.lock().unwrap();
+        match req_inner.policy {
+            Some(policy) => policies.insert(req_inner.namespace, policy),
+            None => policies.remove(&req_inner.namespace),
+        };
+        Ok(Response::new(SetNamespacePolicyResponse {}))
+    }
+
+    async fn get_namespace_policy(
+        &self,
+        request: Request<GetNamespacePolicyRequest>,
+    ) -> Result<Response<GetNamespacePolicyResponse>, Status> {
+        let policies = self.namespace_policies.lock().unwrap();
+        Ok(Response::new(GetNamespacePolicyResponse {
+            policy: policies.get(&request.into_inner().namespace).cloned(),
+        }))
+    }
+
+    async fn set_namespace_defaults(
+        &self,
+        request: Request<SetNamespaceDefaultsRequest>,
+    ) -> Result<Response<SetNamespaceDefaultsResponse>, Status> {
+        let req_inner = request.into_inner();
+        let mut defaults = self.namespace_defaults.lock().unwrap();
+        match req_inner.defaults {
+            Some(namespace_defaults) => defaults.insert(req_inner.namespace, namespace_defaults),
+            None => defaults.remove(&req_inner.namespace),
+        };
+        Ok(Response::new(SetNamespaceDefaultsResponse {}))
+    }
+
+    async fn get_namespace_defaults(
+        &self,
+        request: Request<GetNamespaceDefaultsRequest>,
+    ) -> Result<Response<GetNamespaceDefaultsResponse>, Status> {
+        let defaults = self.namespace_defaults.lock().unwrap();
+        Ok(Response::new(GetNamespaceDefaultsResponse {
+            defaults: defaults.get(&request.into_inner().namespace).cloned(),
+        }))
+    }
+
+    async fn check_cross_namespace_access(
+        &self,
+        request: Request<CheckCrossNamespaceAccessRequest>,
+    ) -> Result<Response<CheckCrossNamespaceAccessResponse>, Status> {
+        let mut namespaces = request.into_inner().namespaces;
+        namespaces.sort();
+        namespaces.dedup();
+        let denied =
+            namespace_policy::denied_pairs(&namespaces, &self.namespace_policies.lock().unwrap());
+        Ok(Response::new(CheckCrossNamespaceAccessResponse {
+            allowed: denied.is_empty(),
+            denied,
+        }))
+    }
+
+    async fn rename_keyspace(
+        &self,
+        request: Request<RenameKeyspaceRequest>,
+    ) -> Result<Response<RenameKeyspaceResponse>, Status> {
+        let req_inner = request.into_inner();
+        let keyspace = req_inner.keyspace.unwrap();
+        let mut keyspaces_info = self.keyspaces_info.lock().unwrap();
+        if keyspaces_info
+            .iter()
+            .any(|k| k.namespace == keyspace.namespace && k.name == req_inner.new_name)
+        {
+            return Err(Status::already_exists("Keyspace already exists"));
+        }
+        let Some(keyspace_info) = keyspaces_info
+            .iter_mut()
+            .find(|k| k.namespace == keyspace.namespace && k.name == keyspace.name)
+        else {
+            return Err(Status::not_found("Keyspace not found"));
+        };
+        keyspace_info.name = req_inner.new_name.clone();
+        let mut aliases = self.keyspace_aliases.lock().unwrap();
+        aliases.remove(&(keyspace.namespace.clone(), req_inner.new_name.clone()));
+        for ((namespace, _), name) in aliases.iter_mut() {
+            if *namespace == keyspace.namespace && *name == keyspace.name {
+                *name = req_inner.new_name.clone();
+            }
+        }
+        if req_inner.keep_alias {
+            aliases.insert((keyspace.namespace, keyspace.name), req_inner.new_name);
+        }
+        Ok(Response::new(RenameKeyspaceResponse {}))
+    }
+
+    async fn list_keyspace_aliases(
+        &self,
+        request: Request<ListKeyspaceAliasesRequest>,
+    ) -> Result<Response<ListKeyspaceAliasesResponse>, Status> {
+        let namespace = request.into_inner().namespace;
+        let aliases = self
+            .keyspace_aliases
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((n, _), _)| *n == namespace)
+            .map(|((_, alias), name)| KeyspaceAlias {
+                alias: alias.clone(),
+                name: name.clone(),
+            })
+            .collect();
+        Ok(Response::new(ListKeyspaceAliasesResponse { aliases }))
+    }
+
+    async fn delete_keyspace_alias(
+        &self,
+        request: Request<DeleteKeyspaceAliasRequest>,
+    ) -> Result<Response<DeleteKeyspaceAliasResponse>, Status> {
+        let req_inner = request.into_inner();
+        self.keyspace_aliases
+            .lock()
+            .unwrap()
+            .remove(&(req_inner.namespace, req_inner.alias));
+        Ok(Response::new(DeleteKeyspaceAliasResponse {}))
+    }
 }
 
 impl MockUniverse {
@@ -115,6 +369,9 @@ impl MockUniverse {
         RUNTIME.spawn(async move {
             let universe_server = MockUniverse {
                 keyspaces_info: keyspaces_info_clone,
+                namespace_policies: Mutex::new(HashMap::new()),
+                namespace_defaults: Mutex::new(HashMap::new()),
+                keyspace_aliases: Mutex::new(HashMap::new()),
                 server_shutdown_tx: Some(signal_tx),
             };
             let addr = addr.parse().unwrap();
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use common::{
-    config::Config, keyspace::Keyspace, network::fast_network::FastNetwork, region::Zone,
-    transaction_info::TransactionInfo,
+    config::Config, keyspace::Keyspace, network::fast_network::FastNetwork,
+    profiling::ProfilingService, region::Zone, transaction_info::TransactionInfo,
 };
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -19,8 +19,8 @@ use tracing::instrument;
 use proto::frontend::frontend_server::{Frontend, FrontendServer};
 use proto::frontend::{
     AbortRequest, AbortResponse, CommitRequest, CommitResponse, DeleteRequest, DeleteResponse,
-    GetRequest, GetResponse, PutRequest, PutResponse, StartTransactionRequest,
-    StartTransactionResponse,
+    GetRequest, GetResponse, PingRequest, PingResponse, PutRequest, PutResponse,
+    StartTransactionRequest, StartTransactionResponse,
 };
 use proto::universe::universe_client::UniverseClient;
 use proto::universe::{CreateKeyspaceRequest, CreateKeyspaceResponse};
@@ -86,14 +86,19 @@ impl Frontend for ProtoServer {
 
         // Generate a new transaction id
         let transaction_id = Uuid::new_v4();
+        let started = Utc::now();
         let transaction_info = Arc::new(TransactionInfo {
             id: transaction_id,
-            started: Utc::now(),
+            started,
+            first_started: started,
+            labels: request.get_ref().labels.clone().into_iter().collect(),
             overall_timeout: self
                 .parent_server
                 .config
                 .frontend
                 .transaction_overall_timeout,
+            isolation: Default::default(),
+            snapshot_epoch: None,
         });
 
         let transaction = self
@@ -175,7 +180,7 @@ impl Frontend for ProtoServer {
     ///   - status: Success message
     #[instrument(skip(self))]
     async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, TStatus> {
-        let req = request.get_ref();
+        let req = request.into_inner();
 
         let transaction_id = Uuid::parse_str(&req.transaction_id).map_err(|e| {
             TStatus::invalid_argument(format!("Invalid transaction ID format: {}", e))
@@ -188,8 +193,10 @@ impl Frontend for ProtoServer {
             namespace: keyspace_proto.namespace.clone(),
             name: keyspace_proto.name.clone(),
         };
-        let key = bytes::Bytes::copy_from_slice(&req.key);
-        let value = bytes::Bytes::copy_from_slice(&req.value);
+        // Take over the request's buffers rather than copying them, values
+        // can be large.
+        let key = bytes::Bytes::from(req.key);
+        let value = bytes::Bytes::from(req.value);
 
         // Get the transaction
         let transaction = {
@@ -356,6 +363,13 @@ impl Frontend for ProtoServer {
             status: "Commit request processed successfully".to_string(),
         }))
     }
+
+    async fn ping(
+        &self,
+        _request: Request<PingRequest>,
+    ) -> Result<Response<PingResponse>, TStatus> {
+        Ok(Response::new(PingResponse {}))
+    }
 }
 
 // Implementation of the Frontend service
@@ -387,7 +401,16 @@ impl Server {
             cancellation_token,
         )
         .await;
+        Self::with_coordinator(config, coordinator, bg_runtime)
+    }
 
+    /// Returns a server running transactions on `coordinator`, for when it
+    /// needs more than `new` sets up, e.g. a separate epoch network.
+    pub fn with_coordinator(
+        config: Config,
+        coordinator: Coordinator,
+        bg_runtime: tokio::runtime::Handle,
+    ) -> Arc<Self> {
         Arc::new(Server {
             config,
             coordinator,
@@ -409,10 +432,12 @@ impl Server {
             .unwrap()
             .next()
             .unwrap();
+        let profiler = ProfilingService::new(&server.config.admin).into_server();
 
         server.bg_runtime.spawn(async move {
             if let Err(e) = TServer::builder()
                 .add_service(FrontendServer::new(proto_server))
+                .add_service(profiler)
                 .serve(addr)
                 .await
             {
@@ -0,0 +1,464 @@
+//! Opt-in journal of what the transactions of a `Client` did, so that an
+//! anomaly seen by an application can be replayed against a test cluster.
+//!
+//! Each finished transaction is written as one line of JSON, holding its
+//! operations in order with their keys, value sizes, timings and outcomes.
+//! Values are only recorded if asked to, since they may hold user data; a
+//! replay then writes zeroes of the recorded size instead.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use proto::frontend::Keyspace;
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+use tracing::warn;
+
+use crate::client::Client;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum OperationKind {
+    Get {
+        namespace: String,
+        keyspace: String,
+        key: Vec<u8>,
+        /// The size of the value read, None if there was none.
+        value_size: Option<usize>,
+        value: Option<Vec<u8>>,
+    },
+    Put {
+        namespace: String,
+        keyspace: String,
+        key: Vec<u8>,
+        value_size: usize,
+        value: Option<Vec<u8>>,
+    },
+    Delete {
+        namespace: String,
+        keyspace: String,
+        key: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    #[serde(flatten)]
+    pub kind: OperationKind,
+    /// When the operation was issued, since the transaction started.
+    pub offset_us: u64,
+    pub duration_us: u64,
+    /// The status code and message if the operation failed.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", content = "error", rename_all = "snake_case")]
+pub enum Outcome {
+    Committed,
+    Aborted,
+    /// Committing or aborting failed, with the status code and message.
+    Failed(String),
+    /// Dropped without committing or aborting.
+    Abandoned,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournaledTransaction {
+    /// The id on the frontend the transaction finished on.
+    pub transaction_id: String,
+    pub frontend: String,
+    pub labels: HashMap<String, String>,
+    /// Microseconds since the Unix epoch.
+    pub started_at_us: i64,
+    pub operations: Vec<Operation>,
+    /// When the transaction finished, since it started.
+    pub finished_after_us: u64,
+    pub outcome: Outcome,
+}
+
+/// Where the transactions of a client are journaled. Writes are buffered and
+/// flushed after each transaction.
+pub struct Journal {
+    writer: Mutex<Box<dyn Write + Send>>,
+    record_values: bool,
+}
+
+impl std::fmt::Debug for Journal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Journal")
+            .field("record_values", &self.record_values)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Journal {
+    pub fn new(writer: Box<dyn Write + Send>, record_values: bool) -> Journal {
+        Journal {
+            writer: Mutex::new(writer),
+            record_values,
+        }
+    }
+
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn to_file(path: &Path, record_values: bool) -> std::io::Result<Journal> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Journal::new(Box::new(BufWriter::new(file)), record_values))
+    }
+
+    fn write(&self, transaction: &JournaledTransaction) {
+        let mut line = serde_json::to_vec(transaction).unwrap();
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        // The journal is a debugging aid, so failing to write it must not
+        // fail the application.
+        if let Err(e) = writer.write_all(&line).and_then(|()| writer.flush()) {
+            warn!(
+                "Failed to journal transaction {}: {}",
+                transaction.transaction_id, e
+            );
+        }
+    }
+}
+
+/// Reads back the transactions of a journal, in the order they finished.
+pub fn read(path: &Path) -> std::io::Result<Vec<JournaledTransaction>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|line| {
+            serde_json::from_str(&line?)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })
+        .collect()
+}
+
+fn status_to_string(status: &Status) -> String {
+    format!("{:?}: {}", status.code(), status.message())
+}
+
+// Journals one transaction while it runs.
+pub(crate) struct Recorder {
+    journal: Arc<Journal>,
+    started: Instant,
+    transaction: JournaledTransaction,
+}
+
+impl Recorder {
+    pub fn new(journal: Arc<Journal>, labels: &HashMap<String, String>) -> Recorder {
+        Recorder {
+            journal,
+            started: Instant::now(),
+            transaction: JournaledTransaction {
+                transaction_id: String::new(),
+                frontend: String::new(),
+                labels: labels.clone(),
+                started_at_us: chrono::Utc::now().timestamp_micros(),
+                operations: Vec::new(),
+                finished_after_us: 0,
+                outcome: Outcome::Abandoned,
+            },
+        }
+    }
+
+    pub fn record_get(
+        &mut self,
+        issued: Instant,
+        keyspace: &Keyspace,
+        key: &[u8],
+        result: &Result<Option<Vec<u8>>, Status>,
+    ) {
+        let value = result.as_ref().ok().and_then(|v| v.as_ref());
+        let kind = OperationKind::Get {
+            namespace: keyspace.namespace.clone(),
+            keyspace: keyspace.name.clone(),
+            key: key.to_vec(),
+            value_size: value.map(|v| v.len()),
+            value: value.filter(|_| self.journal.record_values).cloned(),
+        };
+        self.record(issued, kind, result.as_ref().err());
+    }
+
+    pub fn record_put(
+        &mut self,
+        issued: Instant,
+        keyspace: &Keyspace,
+        key: &[u8],
+        value: &[u8],
+        result: &Result<(), Status>,
+    ) {
+        let kind = OperationKind::Put {
+            namespace: keyspace.namespace.clone(),
+            keyspace: keyspace.name.clone(),
+            key: key.to_vec(),
+            value_size: value.len(),
+            value: self.journal.record_values.then(|| value.to_vec()),
+        };
+        self.record(issued, kind, result.as_ref().err());
+    }
+
+    pub fn record_delete(
+        &mut self,
+        issued: Instant,
+        keyspace: &Keyspace,
+        key: &[u8],
+        result: &Result<(), Status>,
+    ) {
+        let kind = OperationKind::Delete {
+            namespace: keyspace.namespace.clone(),
+            keyspace: keyspace.name.clone(),
+            key: key.to_vec(),
+        };
+        self.record(issued, kind, result.as_ref().err());
+    }
+
+    fn record(&mut self, issued: Instant, kind: OperationKind, error: Option<&Status>) {
+        self.transaction.operations.push(Operation {
+            kind,
+            offset_us: (issued - self.started).as_micros() as u64,
+            duration_us: issued.elapsed().as_micros() as u64,
+            error: error.map(status_to_string),
+        });
+    }
+
+    /// Writes the transaction to the journal.
+    pub fn finish(mut self, transaction_id: &str, frontend: &str, outcome: Outcome) {
+        self.transaction.transaction_id = transaction_id.to_string();
+        self.transaction.frontend = frontend.to_string();
+        self.transaction.finished_after_us = self.started.elapsed().as_micros() as u64;
+        self.transaction.outcome = outcome;
+        self.journal.write(&self.transaction);
+    }
+}
+
+pub(crate) fn outcome(result: &Result<(), Status>, success: Outcome) -> Outcome {
+    match result {
+        Ok(()) => success,
+        Err(status) => Outcome::Failed(status_to_string(status)),
+    }
+}
+
+/// Compares what an operation did when replayed with what was journaled.
+/// Returns a description of the difference, if any.
+fn diverges(
+    recorded: &Operation,
+    value_read: Option<&[u8]>,
+    error: Option<&Status>,
+) -> Option<String> {
+    match (&recorded.error, error) {
+        (None, Some(status)) => return Some(format!("failed with {}", status_to_string(status))),
+        (Some(recorded), None) => return Some(format!("succeeded, was {}", recorded)),
+        (Some(_), Some(_)) | (None, None) => (),
+    }
+    let OperationKind::Get {
+        value_size, value, ..
+    } = &recorded.kind
+    else {
+        return None;
+    };
+    if error.is_some() {
+        return None;
+    }
+    if value_read.map(|v| v.len()) != *value_size {
+        return Some(format!(
+            "read a value of size {:?}, was {:?}",
+            value_read.map(|v| v.len()),
+            value_size
+        ));
+    }
+    match (value, value_read) {
+        (Some(value), Some(value_read)) if value[..] != *value_read => {
+            Some("read a different value of the same size".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Runs the transaction again through `client`, with the same operations in
+/// the same order, and ends it the way it ended. Returns how it diverged from
+/// the journal, one description per diverging operation, in order.
+pub async fn replay(
+    client: &Client,
+    journaled: &JournaledTransaction,
+) -> Result<Vec<String>, Status> {
+    let mut transaction = client.start_transaction(journaled.labels.clone()).await?;
+    let mut divergences = Vec::new();
+    for (i, operation) in journaled.operations.iter().enumerate() {
+        let (value_read, error) = match &operation.kind {
+            OperationKind::Get {
+                namespace,
+                keyspace,
+                key,
+                ..
+            } => {
+                let keyspace = Keyspace {
+                    namespace: namespace.clone(),
+                    name: keyspace.clone(),
+                };
+                match transaction.get(&keyspace, key.clone()).await {
+                    Ok(value) => (value, None),
+                    Err(status) => (None, Some(status)),
+                }
+            }
+            OperationKind::Put {
+                namespace,
+                keyspace,
+                key,
+                value_size,
+                value,
+            } => {
+                let keyspace = Keyspace {
+                    namespace: namespace.clone(),
+                    name: keyspace.clone(),
+                };
+                let value = value.clone().unwrap_or_else(|| vec![0; *value_size]);
+                (
+                    None,
+                    transaction.put(&keyspace, key.clone(), value).await.err(),
+                )
+            }
+            OperationKind::Delete {
+                namespace,
+                keyspace,
+                key,
+            } => {
+                let keyspace = Keyspace {
+                    namespace: namespace.clone(),
+                    name: keyspace.clone(),
+                };
+                (None, transaction.delete(&keyspace, key.clone()).await.err())
+            }
+        };
+        if let Some(divergence) = diverges(operation, value_read.as_deref(), error.as_ref()) {
+            divergences.push(format!("operation {}: {}", i, divergence));
+        }
+    }
+    let replayed = match journaled.outcome {
+        Outcome::Committed | Outcome::Failed(_) => {
+            outcome(&transaction.commit().await, Outcome::Committed)
+        }
+        Outcome::Aborted => outcome(&transaction.abort().await, Outcome::Aborted),
+        Outcome::Abandoned => {
+            drop(transaction);
+            Outcome::Abandoned
+        }
+    };
+    let same = match (&journaled.outcome, &replayed) {
+        // The messages may well differ, e.g. in the transaction id.
+        (Outcome::Failed(_), Outcome::Failed(_)) => true,
+        (journaled, replayed) => journaled == replayed,
+    };
+    if !same {
+        divergences.push(format!(
+            "finished as {:?}, was {:?}",
+            replayed, journaled.outcome
+        ));
+    }
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn get(value: Option<&[u8]>, record_values: bool, error: Option<&str>) -> Operation {
+        Operation {
+            kind: OperationKind::Get {
+                namespace: "ns".to_string(),
+                keyspace: "ks".to_string(),
+                key: b"key".to_vec(),
+                value_size: value.map(|v| v.len()),
+                value: value.filter(|_| record_values).map(|v| v.to_vec()),
+            },
+            offset_us: 10,
+            duration_us: 5,
+            error: error.map(|e| e.to_string()),
+        }
+    }
+
+    #[test]
+    fn entries_round_trip_through_a_file() {
+        let path = std::env::temp_dir().join(format!("atomix-journal-{}", uuid::Uuid::new_v4()));
+        let journal = Journal::to_file(&path, false).unwrap();
+        let keyspace = Keyspace {
+            namespace: "ns".to_string(),
+            name: "ks".to_string(),
+        };
+        let mut recorder = Recorder::new(Arc::new(journal), &HashMap::new());
+        let issued = Instant::now();
+        recorder.record_get(issued, &keyspace, b"key", &Ok(Some(b"abc".to_vec())));
+        let issued = Instant::now();
+        recorder.record_put(issued, &keyspace, b"key", b"value", &Ok(()));
+        let issued = Instant::now();
+        let not_found = Status::not_found("gone");
+        recorder.record_delete(issued, &keyspace, b"key", &Err(not_found.clone()));
+        recorder.finish("tx1", "a:1", outcome(&Err(not_found), Outcome::Committed));
+
+        let transactions = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(transactions.len(), 1);
+        let transaction = &transactions[0];
+        assert_eq!(transaction.transaction_id, "tx1");
+        assert_eq!(transaction.frontend, "a:1");
+        assert_eq!(
+            transaction.outcome,
+            Outcome::Failed("NotFound: gone".to_string())
+        );
+        let kinds: Vec<_> = transaction.operations.iter().map(|o| &o.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &OperationKind::Get {
+                    namespace: "ns".to_string(),
+                    keyspace: "ks".to_string(),
+                    key: b"key".to_vec(),
+                    value_size: Some(3),
+                    value: None,
+                },
+                &OperationKind::Put {
+                    namespace: "ns".to_string(),
+                    keyspace: "ks".to_string(),
+                    key: b"key".to_vec(),
+                    value_size: 5,
+                    value: None,
+                },
+                &OperationKind::Delete {
+                    namespace: "ns".to_string(),
+                    keyspace: "ks".to_string(),
+                    key: b"key".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(
+            transaction.operations[2].error.as_deref(),
+            Some("NotFound: gone")
+        );
+    }
+
+    #[test]
+    fn replayed_reads_are_compared_with_the_journal() {
+        let recorded = get(Some(b"abc"), true, None);
+        assert_eq!(diverges(&recorded, Some(b"abc"), None), None);
+        assert!(diverges(&recorded, Some(b"abd"), None).is_some());
+        assert!(diverges(&recorded, None, None).is_some());
+        assert!(diverges(&recorded, None, Some(&Status::new(Code::Aborted, "x"))).is_some());
+
+        // Without the value, only its size can be compared.
+        let recorded = get(Some(b"abc"), false, None);
+        assert_eq!(diverges(&recorded, Some(b"abd"), None), None);
+        assert!(diverges(&recorded, Some(b"abcd"), None).is_some());
+
+        let recorded = get(None, false, Some("Aborted: x"));
+        assert_eq!(
+            diverges(&recorded, None, Some(&Status::new(Code::Aborted, "x"))),
+            None
+        );
+        assert!(diverges(&recorded, None, None).is_some());
+    }
+}
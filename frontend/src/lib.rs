@@ -1,4 +1,6 @@
+pub mod client;
 pub mod error;
 pub mod for_testing;
 pub mod frontend;
+pub mod journal;
 pub mod range_assignment_oracle;
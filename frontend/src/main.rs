@@ -1,15 +1,21 @@
 use clap::Parser;
 use common::{
-    config::Config,
-    network::{fast_network::FastNetwork, for_testing::udp_fast_network::UdpFastNetwork},
+    config::{Config, FastNetworkTransport},
+    network::{
+        fast_network::FastNetwork,
+        for_testing::udp_fast_network::UdpFastNetwork,
+        quic_fast_network::{QuicFastNetwork, QuicTls},
+        tcp_fast_network::TcpFastNetwork,
+    },
     region::{Region, Zone},
 };
 use std::{
     fs::read_to_string,
-    net::{ToSocketAddrs, UdpSocket},
+    net::{SocketAddr, TcpListener, ToSocketAddrs, UdpSocket},
     sync::Arc,
 };
 
+use coordinator::coordinator::Coordinator;
 use frontend::frontend::Server;
 use tokio::runtime::Builder;
 use tokio_util::sync::CancellationToken;
@@ -54,14 +60,47 @@ fn main() {
         .unwrap()
         .next()
         .unwrap();
-    let fast_network = Arc::new(UdpFastNetwork::new(
-        UdpSocket::bind(fast_network_addr).unwrap(),
-    ));
-    let fast_network_clone = fast_network.clone();
+    // The epoch publishers only serve over UDP, so with TCP or QUIC the
+    // coordinator reads epochs on a network of its own.
+    let epoch_network = || -> Option<Arc<dyn FastNetwork>> {
+        let addr = SocketAddr::new(fast_network_addr.ip(), 0);
+        Some(Arc::new(UdpFastNetwork::new(
+            UdpSocket::bind(addr).unwrap(),
+        )))
+    };
+    let (fast_network, epoch_network): (Arc<dyn FastNetwork>, Option<Arc<dyn FastNetwork>>) =
+        match config.frontend.fast_network_transport {
+            FastNetworkTransport::Udp => (
+                Arc::new(UdpFastNetwork::new(
+                    UdpSocket::bind(fast_network_addr).unwrap(),
+                )),
+                None,
+            ),
+            FastNetworkTransport::Tcp => (
+                Arc::new(TcpFastNetwork::new(
+                    TcpListener::bind(fast_network_addr).unwrap(),
+                )),
+                epoch_network(),
+            ),
+            FastNetworkTransport::Quic => {
+                let tls = QuicTls::from_config(&config.quic)
+                    .unwrap_or_else(|e| panic!("failed to load the QUIC certificate: {}", e));
+                let socket = UdpSocket::bind(fast_network_addr).unwrap();
+                (
+                    Arc::new(QuicFastNetwork::new(socket, &tls, runtime.handle().clone()).unwrap()),
+                    epoch_network(),
+                )
+            }
+        };
+    let polled: Vec<_> = std::iter::once(fast_network.clone())
+        .chain(epoch_network.clone())
+        .collect();
 
     runtime.spawn(async move {
         loop {
-            fast_network_clone.poll();
+            for network in &polled {
+                network.poll();
+            }
             tokio::task::yield_now().await
         }
     });
@@ -78,16 +117,36 @@ fn main() {
             .await
             .unwrap();
         let range_assignment_oracle = Arc::new(RangeAssignmentOracle::new(client));
-        let server = Server::new(
-            config,
-            zone,
-            fast_network.clone(),
-            range_assignment_oracle,
-            runtime_handle,
-            bg_runtime_clone,
-            ct_clone,
-        )
-        .await;
+        let server = match epoch_network {
+            None => {
+                Server::new(
+                    config,
+                    zone,
+                    fast_network.clone(),
+                    range_assignment_oracle,
+                    runtime_handle,
+                    bg_runtime_clone,
+                    ct_clone,
+                )
+                .await
+            }
+            Some(epoch_network) => {
+                let coordinator = Coordinator::builder(
+                    config.clone(),
+                    zone,
+                    range_assignment_oracle,
+                    fast_network.clone(),
+                )
+                .runtime(runtime_handle)
+                .bg_runtime(bg_runtime_clone.clone())
+                .cancellation_token(ct_clone)
+                .epoch_network(epoch_network)
+                .build()
+                .await
+                .unwrap();
+                Server::with_coordinator(config, coordinator, bg_runtime_clone)
+            }
+        };
 
         Server::start(server).await;
     });
@@ -63,6 +63,36 @@ impl RangeAssignmentOracleTrait for RangeAssignmentOracle {
         None
     }
 
+    async fn full_range_ids_of_key_range(
+        &self,
+        keyspace_id: KeyspaceId,
+        key_range: &KeyRange,
+    ) -> Option<Vec<FullRangeId>> {
+        let keyspace_info_request = GetKeyspaceInfoRequest {
+            keyspace_info_search_field: Some(KeyspaceInfoSearchField::KeyspaceId(
+                keyspace_id.id.to_string(),
+            )),
+        };
+        let mut client = self.universe_client.clone();
+        let keyspace_info = client
+            .get_keyspace_info(keyspace_info_request)
+            .await
+            .ok()?
+            .into_inner()
+            .keyspace_info?;
+        Some(
+            keyspace_info
+                .base_key_ranges
+                .iter()
+                .filter(|range| !KeyRange::from(*range).intersection(key_range).is_empty())
+                .map(|range| FullRangeId {
+                    keyspace_id,
+                    range_id: Uuid::parse_str(&range.base_range_uuid).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
     async fn host_of_range(&self, range_id: &FullRangeId) -> Option<HostInfo> {
         //  TODO: Ask warden for the host of the range
         //  Hardcoding RangeServer address for now to work my way through the tests
@@ -83,6 +113,8 @@ impl RangeAssignmentOracleTrait for RangeAssignmentOracle {
             address: "127.0.0.1:50055".parse().unwrap(),
 
             warden_connection_epoch: 0,
+
+            labels: Default::default(),
         })
     }
     fn maybe_refresh_host_of_range(&self, range_id: &FullRangeId) {
@@ -97,9 +129,18 @@ mod tests {
     use proto::universe::universe_client::UniverseClient;
     use proto::universe::{
         universe_server::{Universe, UniverseServer},
-        CreateKeyspaceRequest, CreateKeyspaceResponse, GetKeyspaceInfoRequest,
-        GetKeyspaceInfoResponse, KeyRange as ProtoKeyRange, KeyspaceInfo, ListKeyspacesRequest,
-        ListKeyspacesResponse, Region as ProtoRegion, Zone as ProtoZone,
+        CheckCrossNamespaceAccessRequest, CheckCrossNamespaceAccessResponse, CreateKeyspaceRequest,
+        CreateKeyspaceResponse, DeleteKeyspaceAliasRequest, DeleteKeyspaceAliasResponse,
+        GetKeyspaceInfoRequest, GetKeyspaceInfoResponse, GetNamespaceDefaultsRequest,
+        GetNamespaceDefaultsResponse, GetNamespacePolicyRequest, GetNamespacePolicyResponse,
+        KeyRange as ProtoKeyRange, KeyspaceInfo, ListKeyspaceAliasesRequest,
+        ListKeyspaceAliasesResponse, ListKeyspacesRequest, ListKeyspacesResponse,
+        Region as ProtoRegion, RenameKeyspaceRequest, RenameKeyspaceResponse,
+        SetKeyspaceColocationRequest, SetKeyspaceColocationResponse, SetKeyspacePlacementRequest,
+        SetKeyspacePlacementResponse, SetKeyspaceReadOnlyRequest, SetKeyspaceReadOnlyResponse,
+        SetKeyspaceValidationPolicyRequest, SetKeyspaceValidationPolicyResponse,
+        SetNamespaceDefaultsRequest, SetNamespaceDefaultsResponse, SetNamespacePolicyRequest,
+        SetNamespacePolicyResponse, SplitKeyRangeRequest, SplitKeyRangeResponse, Zone as ProtoZone,
     };
     use std::sync::{Arc, Mutex};
     use tokio::sync::oneshot;
@@ -128,6 +169,12 @@ mod tests {
                 upper_bound_exclusive: vec![10],
                 base_range_uuid: Uuid::new_v4().to_string(),
             }],
+            read_only: false,
+            optimistic_reads: false,
+            validation_policy: None,
+            validation_policy_inherited: false,
+            placement: None,
+            colocation: None,
         }
     }
 
@@ -176,6 +223,97 @@ mod tests {
             }
             Err(Status::not_found("Keyspace not found"))
         }
+
+        async fn set_keyspace_read_only(
+            &self,
+            _request: Request<SetKeyspaceReadOnlyRequest>,
+        ) -> Result<Response<SetKeyspaceReadOnlyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_keyspace_validation_policy(
+            &self,
+            _request: Request<SetKeyspaceValidationPolicyRequest>,
+        ) -> Result<Response<SetKeyspaceValidationPolicyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_keyspace_placement(
+            &self,
+            _request: Request<SetKeyspacePlacementRequest>,
+        ) -> Result<Response<SetKeyspacePlacementResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_keyspace_colocation(
+            &self,
+            _request: Request<SetKeyspaceColocationRequest>,
+        ) -> Result<Response<SetKeyspaceColocationResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn split_key_range(
+            &self,
+            _request: Request<SplitKeyRangeRequest>,
+        ) -> Result<Response<SplitKeyRangeResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_namespace_policy(
+            &self,
+            _request: Request<SetNamespacePolicyRequest>,
+        ) -> Result<Response<SetNamespacePolicyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn get_namespace_policy(
+            &self,
+            _request: Request<GetNamespacePolicyRequest>,
+        ) -> Result<Response<GetNamespacePolicyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn check_cross_namespace_access(
+            &self,
+            _request: Request<CheckCrossNamespaceAccessRequest>,
+        ) -> Result<Response<CheckCrossNamespaceAccessResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn rename_keyspace(
+            &self,
+            _request: Request<RenameKeyspaceRequest>,
+        ) -> Result<Response<RenameKeyspaceResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn list_keyspace_aliases(
+            &self,
+            _request: Request<ListKeyspaceAliasesRequest>,
+        ) -> Result<Response<ListKeyspaceAliasesResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn delete_keyspace_alias(
+            &self,
+            _request: Request<DeleteKeyspaceAliasRequest>,
+        ) -> Result<Response<DeleteKeyspaceAliasResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_namespace_defaults(
+            &self,
+            _request: Request<SetNamespaceDefaultsRequest>,
+        ) -> Result<Response<SetNamespaceDefaultsResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn get_namespace_defaults(
+            &self,
+            _request: Request<GetNamespaceDefaultsRequest>,
+        ) -> Result<Response<GetNamespaceDefaultsResponse>, Status> {
+            unreachable!()
+        }
     }
 
     static RUNTIME: Lazy<tokio::runtime::Runtime> =
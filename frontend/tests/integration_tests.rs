@@ -67,6 +67,19 @@ async fn init_config() -> Config {
             range_maintenance_duration: time::Duration::from_secs(1),
             proto_server_addr: "127.0.0.1:50054".parse().unwrap(),
             fast_network_addr: "127.0.0.1:50055".parse().unwrap(),
+            preflight_epoch_advance_timeout: None,
+            max_pending_prepares_per_range: None,
+            max_epoch_lease_extension: None,
+            handoff_drain_timeout: None,
+            lock_table: Default::default(),
+            conflict_stats: Default::default(),
+            write_stall: Default::default(),
+            checksum_verification: Default::default(),
+            decision_log: Default::default(),
+            expiry: Default::default(),
+            fast_network_transport: Default::default(),
+            mirroring: Default::default(),
+            transaction_recovery: Default::default(),
         },
         universe: UniverseConfig {
             proto_server_addr: "127.0.0.1:50056".parse().unwrap(),
@@ -75,11 +88,15 @@ async fn init_config() -> Config {
             proto_server_addr: "127.0.0.1:50057".parse().unwrap(),
             fast_network_addr: "127.0.0.1:50058".parse().unwrap(),
             transaction_overall_timeout: time::Duration::from_secs(10),
+            fast_network_transport: Default::default(),
         },
         cassandra: CassandraConfig {
             cql_addr: "127.0.0.1:9042".parse().unwrap(),
+            consistency: Default::default(),
         },
         regions: std::collections::HashMap::new(),
+        admin: Default::default(),
+        quic: Default::default(),
         epoch: epoch_config,
     };
     let epoch_publishers = HashSet::from([EpochPublisher {
@@ -87,6 +104,7 @@ async fn init_config() -> Config {
         // Not used in these tests.
         backend_addr: "127.0.0.1:50051".parse().unwrap(),
         fast_network_addr: "127.0.0.1:50052".parse().unwrap(),
+        failure_domain: None,
     }]);
     let epoch_publishers_set = EpochPublisherSet {
         name: "ps1".to_string(),
@@ -96,6 +114,7 @@ async fn init_config() -> Config {
     let region_config = RegionConfig {
         warden_address: "127.0.0.1:50053".parse().unwrap(),
         epoch_publishers: HashSet::from([epoch_publishers_set]),
+        cassandra_datacenter: None,
     };
     config.regions.insert(make_zone().region, region_config);
     config
@@ -226,6 +245,7 @@ async fn test_frontend() {
             name: context.keyspace.name.clone(),
             primary_zone: Some(context.zone.clone()),
             base_key_ranges: context.base_key_ranges.clone(),
+            optimistic_reads: None,
         })
         .await
         .unwrap();
@@ -235,7 +255,7 @@ async fn test_frontend() {
     // ----- Start transaction -----
     let response = context
         .client
-        .start_transaction(StartTransactionRequest {})
+        .start_transaction(StartTransactionRequest::default())
         .await
         .unwrap();
     let transaction_id = Uuid::parse_str(&response.get_ref().transaction_id).unwrap();
@@ -290,7 +310,7 @@ async fn test_frontend() {
     // ----- Start new transaction -----
     let response = context
         .client
-        .start_transaction(StartTransactionRequest {})
+        .start_transaction(StartTransactionRequest::default())
         .await
         .unwrap();
     let transaction_id = Uuid::parse_str(&response.get_ref().transaction_id).unwrap();
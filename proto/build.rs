@@ -56,6 +56,17 @@ fn main() {
         )
         .unwrap();
 
+    let profiling_out_dir = "target/profiling";
+    fs::create_dir_all(profiling_out_dir).unwrap();
+    tonic_build::configure()
+        .build_server(true)
+        .out_dir(profiling_out_dir)
+        .compile(
+            &["src/profiling.proto"],
+            &["src"], // specify the root location to search proto dependencies
+        )
+        .unwrap();
+
     let frontend_out_dir = "target/frontend";
     fs::create_dir_all(frontend_out_dir).unwrap();
     tonic_build::configure()
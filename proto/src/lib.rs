@@ -4,6 +4,8 @@ pub mod epoch;
 pub mod epoch_publisher;
 #[path = "../target/frontend/frontend.rs"]
 pub mod frontend;
+#[path = "../target/profiling/profiling.rs"]
+pub mod profiling;
 #[path = "../target/rangeserver/rangeserver.rs"]
 pub mod rangeserver;
 #[path = "../target/universe/universe.rs"]
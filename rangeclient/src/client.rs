@@ -2,27 +2,116 @@ use bytes::Bytes;
 use common::network::fast_network::FastNetwork;
 use common::util;
 use common::{
-    epoch_lease::EpochLease, full_range_id::FullRangeId, host_info::HostInfo, record::Record,
+    epoch_lease::EpochLease,
+    full_range_id::FullRangeId,
+    host_info::HostInfo,
+    key_range::KeyRange,
+    record::{Condition, Increment, Record},
     transaction_info::TransactionInfo,
 };
+use flatbuf::rangeserver_flatbuffers::range_server::Condition as FlatbufCondition;
+use flatbuf::rangeserver_flatbuffers::range_server::ConflictCounts as FlatbufConflictCounts;
+use flatbuf::rangeserver_flatbuffers::range_server::Increment as FlatbufIncrement;
 use flatbuf::rangeserver_flatbuffers::range_server::Record as FlatbufRecord;
-use flatbuf::rangeserver_flatbuffers::range_server::TransactionInfo as FlatbufTransactionInfo;
 use flatbuf::rangeserver_flatbuffers::range_server::*;
 use flatbuffers::FlatBufferBuilder;
 use proto::rangeserver::range_server_client::RangeServerClient;
-use proto::rangeserver::{PrefetchRequest, RangeId, RangeKey};
+use proto::rangeserver::{
+    bulk_get_request, BulkGetChunk, BulkGetRequest, PrefetchRequest, RangeId, RangeKey,
+};
+pub use rangeserver::conflict_stats::{ConflictCounts, ConflictStats};
 use rangeserver::error::Error as RangeServerError;
+pub use rangeserver::transaction_abort_reason::TransactionAbortReason;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::ops::DerefMut;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{oneshot, RwLock};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tonic::transport::Channel;
-use tonic::Request;
+use tonic::{Code, Request, Streaming};
 use uuid::Uuid;
 
 pub type Error = RangeServerError;
+
+// Allowance for the flatbuffer framing of a whole message, and of each key or
+// value in it, when sizing request builders up front.
+const MESSAGE_OVERHEAD: usize = 1024;
+const PER_ENTRY_OVERHEAD: usize = 64;
+
+// Rough upper bound of the encoded size of a request carrying `entries`, so
+// its builder never has to grow (and copy everything written so far) while
+// large values are added to it.
+fn encoded_size_hint<'a>(entries: impl Iterator<Item = &'a [u8]>) -> usize {
+    MESSAGE_OVERHEAD
+        + entries
+            .map(|entry| entry.len() + PER_ENTRY_OVERHEAD)
+            .sum::<usize>()
+}
+
+/// How the client retries requests the range server did not answer in time.
+/// Every attempt resends the request with the same request id, waiting
+/// exponentially longer between attempts.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts made in total, including the first one. 1 disables retries.
+    pub max_attempts: u32,
+    /// How long to wait for the response to an attempt. Requests may wait on
+    /// locks at the range server, so this should leave room for that.
+    pub attempt_timeout: Duration,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Fraction of each backoff that gets randomly taken off it, so clients
+    /// that lost requests at the same time don't retry in lockstep.
+    pub jitter: f64,
+    /// Only retry requests the range server can safely get more than once.
+    /// Prepares are the only ones that aren't.
+    pub retry_only_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            attempt_timeout: Duration::from_secs(2),
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            jitter: 0.2,
+            retry_only_idempotent: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn attempts(&self, idempotent: bool) -> u32 {
+        if idempotent || !self.retry_only_idempotent {
+            self.max_attempts.max(1)
+        } else {
+            1
+        }
+    }
+
+    // Time to wait before the given retry, counting from 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+        backoff.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * rand::random::<f64>())
+    }
+}
+
+// When the transaction runs out of time, as far as requests made on its
+// behalf are concerned.
+fn transaction_deadline(tx: &TransactionInfo) -> Instant {
+    let elapsed = (chrono::Utc::now() - tx.started)
+        .to_std()
+        .unwrap_or(Duration::ZERO);
+    Instant::now() + tx.overall_timeout.saturating_sub(elapsed)
+}
+
 pub struct PrepareOk {
     pub highest_known_epoch: u64,
     pub epoch_lease: EpochLease,
@@ -34,6 +123,12 @@ pub struct GetResult {
     pub leader_sequence_number: i64,
 }
 
+#[derive(Debug)]
+pub struct ScanResult {
+    pub records: Vec<(Bytes, Bytes)>,
+    pub leader_sequence_number: i64,
+}
+
 struct StartedState {
     // TODO: make more typeful and store more information to e.g. allow timing out.
     outstanding_requests: HashMap<Uuid, oneshot::Sender<Result<Bytes, RangeServerError>>>,
@@ -51,6 +146,7 @@ pub struct RangeClient {
     range_server_info: HostInfo,
     state: RwLock<State>,
     proto_client: Option<Arc<RangeServerClient<Channel>>>,
+    retry_policy: RetryPolicy,
 }
 
 impl RangeClient {
@@ -60,6 +156,7 @@ impl RangeClient {
         // TODO(tamer): make a "RangeServerHostIdentity" and have proto server
         // be a required field on it.
         proto_server_addr: Option<SocketAddr>,
+        retry_policy: RetryPolicy,
     ) -> Arc<RangeClient> {
         let proto_client = match proto_server_addr {
             None => None,
@@ -73,6 +170,7 @@ impl RangeClient {
             range_server_info: host_info,
             state: RwLock::new(State::NotStarted),
             proto_client,
+            retry_policy,
         })
     }
 
@@ -114,11 +212,51 @@ impl RangeClient {
         tx: Arc<TransactionInfo>,
         range_id: &FullRangeId,
         keys: Vec<Bytes>,
+    ) -> Result<GetResult, RangeServerError> {
+        self.get_inner(tx, range_id, keys, 0, false).await
+    }
+
+    /// Reads the keys, taking the lock of the range even if the transaction
+    /// or the keyspace reads without locking.
+    pub async fn get_for_update(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        keys: Vec<Bytes>,
+    ) -> Result<GetResult, RangeServerError> {
+        self.get_inner(tx, range_id, keys, 0, true).await
+    }
+
+    /// Reads the keys as of `epoch`, without locking them or registering the
+    /// transaction on the range. `epoch` must not be 0.
+    pub async fn get_at_epoch(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        keys: Vec<Bytes>,
+        epoch: u64,
+    ) -> Result<GetResult, RangeServerError> {
+        self.get_inner(tx, range_id, keys, epoch, false).await
+    }
+
+    // Reads the latest values if `snapshot_epoch` is 0.
+    async fn get_inner(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        keys: Vec<Bytes>,
+        snapshot_epoch: u64,
+        for_update: bool,
     ) -> Result<GetResult, RangeServerError> {
         // TODO: gracefully handle malformed messages instead of unwrapping and crashing.
-        // TODO: too much copying :(
         let req_id = Uuid::new_v4();
-        let mut fbb = FlatBufferBuilder::new();
+        let labels = tx
+            .labels
+            .iter()
+            .flat_map(|(k, v)| [k.as_bytes(), v.as_bytes()]);
+        let mut fbb = FlatBufferBuilder::with_capacity(encoded_size_hint(
+            keys.iter().map(|k| k.as_ref()).chain(labels),
+        ));
         let transaction_id = Some(Uuidu128::create(
             &mut fbb,
             &util::flatbuf::serialize_uuid(tx.id),
@@ -129,15 +267,10 @@ impl RangeClient {
             &util::flatbuf::serialize_uuid(req_id),
         ));
         // TODO: only supply the transaction info on the first request to the RS.
-        let transaction_info = Some(FlatbufTransactionInfo::create(
-            &mut fbb,
-            &TransactionInfoArgs {
-                overall_timeout_us: tx.overall_timeout.as_micros() as u32,
-            },
-        ));
+        let transaction_info = Some(util::flatbuf::serialize_transaction_info(&mut fbb, &tx));
         let mut keys_vector = Vec::new();
         for key in keys {
-            let k = Some(fbb.create_vector(key.to_vec().as_slice()));
+            let k = Some(fbb.create_vector(&key));
             let key = Key::create(&mut fbb, &KeyArgs { k });
             keys_vector.push(key)
         }
@@ -150,22 +283,20 @@ impl RangeClient {
                 range_id,
                 transaction_info,
                 keys,
+                snapshot_epoch,
+                for_update,
             },
         );
         fbb.finish(fbb_root, None);
-        let (tx, rx) = oneshot::channel();
-        self.record_outstanding_request(req_id, tx).await?;
-        let get_request_bytes = Bytes::copy_from_slice(fbb.finished_data());
-        let mut envelope_fbb = FlatBufferBuilder::new();
-        let request_bytes =
-            self.create_msg_envelope(&mut envelope_fbb, MessageType::Get, get_request_bytes);
-        self.fast_network
-            .send(
-                self.range_server_info.address,
-                Bytes::copy_from_slice(request_bytes),
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::Get,
+                fbb.finished_data(),
+                Some(transaction_deadline(&tx)),
+                true,
             )
-            .unwrap();
-        let response = rx.await.unwrap()?;
+            .await?;
         let msg = response.to_vec();
         let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
         match envelope.type_() {
@@ -193,6 +324,247 @@ impl RangeClient {
         }
     }
 
+    /// Reads the live records of the range within `key_range`, in key order.
+    pub async fn scan(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        key_range: &KeyRange,
+        limit: Option<usize>,
+    ) -> Result<ScanResult, RangeServerError> {
+        let req_id = Uuid::new_v4();
+        let mut fbb = FlatBufferBuilder::new();
+        let transaction_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(tx.id),
+        ));
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(req_id),
+        ));
+        let transaction_info = Some(util::flatbuf::serialize_transaction_info(&mut fbb, &tx));
+        let mut bound = |key: &Option<Bytes>| {
+            key.as_ref().map(|key| {
+                let k = Some(fbb.create_vector(key));
+                Key::create(&mut fbb, &KeyArgs { k })
+            })
+        };
+        let lower_bound_inclusive = bound(&key_range.lower_bound_inclusive);
+        let upper_bound_exclusive = bound(&key_range.upper_bound_exclusive);
+        let fbb_root = ScanRequest::create(
+            &mut fbb,
+            &ScanRequestArgs {
+                request_id,
+                transaction_id,
+                transaction_info,
+                range_id,
+                lower_bound_inclusive,
+                upper_bound_exclusive,
+                limit: limit.map_or(0, |limit| limit.min(u32::MAX as usize) as u32),
+            },
+        );
+        fbb.finish(fbb_root, None);
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::Scan,
+                fbb.finished_data(),
+                Some(transaction_deadline(&tx)),
+                true,
+            )
+            .await?;
+        let msg = response.to_vec();
+        let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
+        match envelope.type_() {
+            MessageType::Scan => {
+                let response_msg =
+                    flatbuffers::root::<ScanResponse>(envelope.bytes().unwrap().bytes()).unwrap();
+                let () = rangeserver::error::Error::from_flatbuf_status(response_msg.status())?;
+                let mut records = Vec::new();
+                for record in response_msg.records().iter() {
+                    for rec in record.iter() {
+                        let key = Bytes::copy_from_slice(rec.key().unwrap().k().unwrap().bytes());
+                        let val = Bytes::copy_from_slice(rec.value().unwrap().bytes());
+                        records.push((key, val));
+                    }
+                }
+                Ok(ScanResult {
+                    records,
+                    leader_sequence_number: response_msg.leader_sequence_number(),
+                })
+            }
+            _ => Err(RangeServerError::InvalidRequestFormat),
+        }
+    }
+
+    /// Reads the live records of the range whose keys start with `prefix`, in
+    /// key order.
+    pub async fn get_prefix(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        prefix: Bytes,
+        limit: Option<usize>,
+    ) -> Result<ScanResult, RangeServerError> {
+        let req_id = Uuid::new_v4();
+        let mut fbb = FlatBufferBuilder::new();
+        let transaction_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(tx.id),
+        ));
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(req_id),
+        ));
+        let transaction_info = Some(util::flatbuf::serialize_transaction_info(&mut fbb, &tx));
+        let prefix = Some(fbb.create_vector(&prefix));
+        let fbb_root = GetPrefixRequest::create(
+            &mut fbb,
+            &GetPrefixRequestArgs {
+                request_id,
+                transaction_id,
+                transaction_info,
+                range_id,
+                prefix,
+                limit: limit.map_or(0, |limit| limit.min(u32::MAX as usize) as u32),
+            },
+        );
+        fbb.finish(fbb_root, None);
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::GetPrefix,
+                fbb.finished_data(),
+                Some(transaction_deadline(&tx)),
+                true,
+            )
+            .await?;
+        let msg = response.to_vec();
+        let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
+        match envelope.type_() {
+            MessageType::GetPrefix => {
+                let response_msg =
+                    flatbuffers::root::<GetPrefixResponse>(envelope.bytes().unwrap().bytes())
+                        .unwrap();
+                let () = rangeserver::error::Error::from_flatbuf_status(response_msg.status())?;
+                let mut records = Vec::new();
+                for record in response_msg.records().iter() {
+                    for rec in record.iter() {
+                        let key = Bytes::copy_from_slice(rec.key().unwrap().k().unwrap().bytes());
+                        let val = Bytes::copy_from_slice(rec.value().unwrap().bytes());
+                        records.push((key, val));
+                    }
+                }
+                Ok(ScanResult {
+                    records,
+                    leader_sequence_number: response_msg.leader_sequence_number(),
+                })
+            }
+            _ => Err(RangeServerError::InvalidRequestFormat),
+        }
+    }
+
+    /// Like `scan`, but reads the records in chunks of up to `chunk_size`
+    /// records, one request per chunk, so scans are not limited to what a
+    /// single response can hold. Chunks are only requested as the stream is
+    /// read.
+    pub fn scan_stream(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        chunk_size: u32,
+    ) -> ScanStream<'_> {
+        ScanStream {
+            client: self,
+            tx,
+            range_id: *range_id,
+            key_range,
+            remaining: limit,
+            chunk_size: chunk_size.max(1),
+            done: false,
+        }
+    }
+
+    async fn scan_chunk(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        key_range: &KeyRange,
+        chunk_size: u32,
+    ) -> Result<(ScanResult, bool), RangeServerError> {
+        let req_id = Uuid::new_v4();
+        let mut fbb = FlatBufferBuilder::new();
+        let transaction_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(tx.id),
+        ));
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(req_id),
+        ));
+        let transaction_info = Some(util::flatbuf::serialize_transaction_info(&mut fbb, &tx));
+        let mut bound = |key: &Option<Bytes>| {
+            key.as_ref().map(|key| {
+                let k = Some(fbb.create_vector(key));
+                Key::create(&mut fbb, &KeyArgs { k })
+            })
+        };
+        let lower_bound_inclusive = bound(&key_range.lower_bound_inclusive);
+        let upper_bound_exclusive = bound(&key_range.upper_bound_exclusive);
+        let fbb_root = ScanChunkRequest::create(
+            &mut fbb,
+            &ScanChunkRequestArgs {
+                request_id,
+                transaction_id,
+                transaction_info,
+                range_id,
+                lower_bound_inclusive,
+                upper_bound_exclusive,
+                chunk_size,
+            },
+        );
+        fbb.finish(fbb_root, None);
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::ScanChunk,
+                fbb.finished_data(),
+                Some(transaction_deadline(&tx)),
+                true,
+            )
+            .await?;
+        let msg = response.to_vec();
+        let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
+        match envelope.type_() {
+            MessageType::ScanChunk => {
+                let response_msg =
+                    flatbuffers::root::<ScanChunkResponse>(envelope.bytes().unwrap().bytes())
+                        .unwrap();
+                let () = rangeserver::error::Error::from_flatbuf_status(response_msg.status())?;
+                let mut records = Vec::new();
+                for record in response_msg.records().iter() {
+                    for rec in record.iter() {
+                        let key = Bytes::copy_from_slice(rec.key().unwrap().k().unwrap().bytes());
+                        let val = Bytes::copy_from_slice(rec.value().unwrap().bytes());
+                        records.push((key, val));
+                    }
+                }
+                let result = ScanResult {
+                    records,
+                    leader_sequence_number: response_msg.leader_sequence_number(),
+                };
+                Ok((result, response_msg.done()))
+            }
+            _ => Err(RangeServerError::InvalidRequestFormat),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn prepare_transaction(
         &self,
         tx: Arc<TransactionInfo>,
@@ -200,11 +572,24 @@ impl RangeClient {
         has_reads: bool,
         writes: &[Record],
         deletes: &[Bytes],
+        conditions: &[Condition],
+        increments: &[Increment],
     ) -> Result<PrepareOk, RangeServerError> {
         // TODO: gracefully handle malformed messages instead of unwrapping and crashing.
-        // TODO: too much copying :(
         let req_id = Uuid::new_v4();
-        let mut fbb = FlatBufferBuilder::new();
+        let mut fbb = FlatBufferBuilder::with_capacity(encoded_size_hint(
+            deletes
+                .iter()
+                .map(|k| k.as_ref())
+                .chain(writes.iter().flat_map(|r| [r.key.as_ref(), r.val.as_ref()]))
+                .chain(conditions.iter().flat_map(|c| {
+                    [
+                        c.key.as_ref(),
+                        c.expected.as_ref().map_or(&[][..], |v| v.as_ref()),
+                    ]
+                }))
+                .chain(increments.iter().map(|i| i.key.as_ref())),
+        ));
         let transaction_id = Some(Uuidu128::create(
             &mut fbb,
             &util::flatbuf::serialize_uuid(tx.id),
@@ -216,25 +601,58 @@ impl RangeClient {
         ));
         let mut deletes_vector = Vec::new();
         for key in deletes {
-            let k = Some(fbb.create_vector(key.to_vec().as_slice()));
+            let k = Some(fbb.create_vector(key));
             let key = Key::create(&mut fbb, &KeyArgs { k });
             deletes_vector.push(key)
         }
         let deletes = Some(fbb.create_vector(&deletes_vector));
         let mut puts_vector = Vec::new();
         for record in writes {
-            let k = Some(fbb.create_vector(record.key.to_vec().as_slice()));
+            let k = Some(fbb.create_vector(&record.key));
             let key = Key::create(&mut fbb, &KeyArgs { k });
-            let value = fbb.create_vector(record.val.to_vec().as_slice());
+            let value = fbb.create_vector(&record.val);
             puts_vector.push(FlatbufRecord::create(
                 &mut fbb,
                 &RecordArgs {
                     key: Some(key),
                     value: Some(value),
+                    // Rounded up, since 0 would mean the record never expires.
+                    ttl_ms: record.ttl.map_or(0, |ttl| {
+                        u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1)
+                    }),
                 },
             ));
         }
         let puts = Some(fbb.create_vector(&puts_vector));
+        let mut conditions_vector = Vec::new();
+        for condition in conditions {
+            let k = Some(fbb.create_vector(&condition.key));
+            let key = Key::create(&mut fbb, &KeyArgs { k });
+            let expected_value = condition.expected.as_ref().map(|v| fbb.create_vector(v));
+            conditions_vector.push(FlatbufCondition::create(
+                &mut fbb,
+                &ConditionArgs {
+                    key: Some(key),
+                    expected_value,
+                    expect_absent: condition.expected.is_none(),
+                },
+            ));
+        }
+        let conditions = Some(fbb.create_vector(&conditions_vector));
+        let mut increments_vector = Vec::new();
+        for increment in increments {
+            let k = Some(fbb.create_vector(&increment.key));
+            let key = Key::create(&mut fbb, &KeyArgs { k });
+            increments_vector.push(FlatbufIncrement::create(
+                &mut fbb,
+                &IncrementArgs {
+                    key: Some(key),
+                    delta: increment.delta,
+                },
+            ));
+        }
+        let increments = Some(fbb.create_vector(&increments_vector));
+        let transaction_info = Some(util::flatbuf::serialize_transaction_info(&mut fbb, &tx));
         let fbb_root = PrepareRequest::create(
             &mut fbb,
             &PrepareRequestArgs {
@@ -244,25 +662,22 @@ impl RangeClient {
                 has_reads,
                 puts,
                 deletes,
+                conditions,
+                transaction_info,
+                increments,
+                prepared_at_us: 0,
             },
         );
         fbb.finish(fbb_root, None);
-        let (tx, rx) = oneshot::channel();
-        self.record_outstanding_request(req_id, tx).await?;
-        let prepare_request_bytes = Bytes::copy_from_slice(fbb.finished_data());
-        let mut envelope_fbb = FlatBufferBuilder::new();
-        let request_bytes = self.create_msg_envelope(
-            &mut envelope_fbb,
-            MessageType::Prepare,
-            prepare_request_bytes,
-        );
-        self.fast_network
-            .send(
-                self.range_server_info.address,
-                Bytes::copy_from_slice(request_bytes),
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::Prepare,
+                fbb.finished_data(),
+                Some(transaction_deadline(&tx)),
+                false,
             )
-            .unwrap();
-        let response = rx.await.unwrap()?;
+            .await?;
         let msg = response.to_vec();
         let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
         match envelope.type_() {
@@ -270,7 +685,17 @@ impl RangeClient {
                 let response_msg =
                     flatbuffers::root::<PrepareResponse>(envelope.bytes().unwrap().bytes())
                         .unwrap();
-                let () = rangeserver::error::Error::from_flatbuf_status(response_msg.status())?;
+                let retry_after = Duration::from_micros(response_msg.retry_after_us());
+                let () = rangeserver::error::Error::from_flatbuf_status(response_msg.status())
+                    .map_err(|e| match e {
+                        RangeServerError::PrepareBacklogFull { .. } => {
+                            RangeServerError::PrepareBacklogFull { retry_after }
+                        }
+                        RangeServerError::WriteStalled { .. } => {
+                            RangeServerError::WriteStalled { retry_after }
+                        }
+                        e => e,
+                    })?;
                 let epoch_lease = response_msg.epoch_lease().unwrap();
                 return Ok(PrepareOk {
                     highest_known_epoch: response_msg.highest_known_epoch(),
@@ -290,7 +715,6 @@ impl RangeClient {
         range_id: &FullRangeId,
     ) -> Result<(), RangeServerError> {
         // TODO: gracefully handle malformed messages instead of unwrapping and crashing.
-        // TODO: too much copying :(
         let req_id = Uuid::new_v4();
         let mut fbb = FlatBufferBuilder::new();
         let transaction_id = Some(Uuidu128::create(
@@ -311,19 +735,9 @@ impl RangeClient {
             },
         );
         fbb.finish(fbb_root, None);
-        let (tx, rx) = oneshot::channel();
-        self.record_outstanding_request(req_id, tx).await?;
-        let abort_request_bytes = Bytes::copy_from_slice(fbb.finished_data());
-        let mut envelope_fbb = FlatBufferBuilder::new();
-        let request_bytes =
-            self.create_msg_envelope(&mut envelope_fbb, MessageType::Abort, abort_request_bytes);
-        self.fast_network
-            .send(
-                self.range_server_info.address,
-                Bytes::copy_from_slice(request_bytes),
-            )
-            .unwrap();
-        let response = rx.await.unwrap()?;
+        let response = self
+            .send_request(req_id, MessageType::Abort, fbb.finished_data(), None, true)
+            .await?;
         let msg = response.to_vec();
         let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
         match envelope.type_() {
@@ -345,7 +759,6 @@ impl RangeClient {
         epoch: u64,
     ) -> Result<(), RangeServerError> {
         // TODO: gracefully handle malformed messages instead of unwrapping and crashing.
-        // TODO: too much copying :(
         let req_id = Uuid::new_v4();
         let mut fbb = FlatBufferBuilder::new();
         let transaction_id = Some(Uuidu128::create(
@@ -368,19 +781,9 @@ impl RangeClient {
             },
         );
         fbb.finish(fbb_root, None);
-        let (tx, rx) = oneshot::channel();
-        self.record_outstanding_request(req_id, tx).await?;
-        let commit_request_bytes = Bytes::copy_from_slice(fbb.finished_data());
-        let mut envelope_fbb = FlatBufferBuilder::new();
-        let request_bytes =
-            self.create_msg_envelope(&mut envelope_fbb, MessageType::Commit, commit_request_bytes);
-        self.fast_network
-            .send(
-                self.range_server_info.address,
-                Bytes::copy_from_slice(request_bytes),
-            )
-            .unwrap();
-        let response = rx.await.unwrap()?;
+        let response = self
+            .send_request(req_id, MessageType::Commit, fbb.finished_data(), None, true)
+            .await?;
         let msg = response.to_vec();
         let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
         match envelope.type_() {
@@ -394,6 +797,211 @@ impl RangeClient {
         }
     }
 
+    /// Asks the range server whether it would currently accept a prepare of
+    /// the transaction, without preparing it. Returns the leader sequence
+    /// number of the range.
+    pub async fn validate_transaction(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        has_reads: bool,
+        has_writes: bool,
+    ) -> Result<i64, RangeServerError> {
+        // TODO: gracefully handle malformed messages instead of unwrapping and crashing.
+        let req_id = Uuid::new_v4();
+        let mut fbb = FlatBufferBuilder::new();
+        let transaction_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(tx.id),
+        ));
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(req_id),
+        ));
+        let fbb_root = ValidateRequest::create(
+            &mut fbb,
+            &ValidateRequestArgs {
+                request_id,
+                transaction_id,
+                range_id,
+                has_reads,
+                has_writes,
+            },
+        );
+        fbb.finish(fbb_root, None);
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::Validate,
+                fbb.finished_data(),
+                Some(transaction_deadline(&tx)),
+                true,
+            )
+            .await?;
+        let msg = response.to_vec();
+        let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
+        match envelope.type_() {
+            MessageType::Validate => {
+                let response_msg =
+                    flatbuffers::root::<ValidateResponse>(envelope.bytes().unwrap().bytes())
+                        .unwrap();
+                let () = rangeserver::error::Error::from_flatbuf_status(response_msg.status())
+                    .map_err(|e| match e {
+                        RangeServerError::PrepareBacklogFull { .. } => {
+                            RangeServerError::PrepareBacklogFull {
+                                retry_after: Duration::from_micros(response_msg.retry_after_us()),
+                            }
+                        }
+                        e => e,
+                    })?;
+                Ok(response_msg.leader_sequence_number())
+            }
+            _ => Err(RangeServerError::InvalidRequestFormat),
+        }
+    }
+
+    /// Asks a range the transaction is prepared on to extend its epoch lease
+    /// to at least `min_upper_bound`. The range may extend it less than that,
+    /// so the returned lease must be checked.
+    pub async fn extend_epoch_lease(
+        &self,
+        tx: Arc<TransactionInfo>,
+        range_id: &FullRangeId,
+        min_upper_bound: u64,
+    ) -> Result<PrepareOk, RangeServerError> {
+        let req_id = Uuid::new_v4();
+        let mut fbb = FlatBufferBuilder::new();
+        let transaction_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(tx.id),
+        ));
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(req_id),
+        ));
+        let fbb_root = ExtendEpochLeaseRequest::create(
+            &mut fbb,
+            &ExtendEpochLeaseRequestArgs {
+                request_id,
+                transaction_id,
+                range_id,
+                min_upper_bound,
+            },
+        );
+        fbb.finish(fbb_root, None);
+        // Part of committing, which the caller bounds itself.
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::ExtendEpochLease,
+                fbb.finished_data(),
+                None,
+                true,
+            )
+            .await?;
+        let msg = response.to_vec();
+        let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
+        match envelope.type_() {
+            MessageType::ExtendEpochLease => {
+                let response_msg = flatbuffers::root::<ExtendEpochLeaseResponse>(
+                    envelope.bytes().unwrap().bytes(),
+                )
+                .unwrap();
+                rangeserver::error::Error::from_flatbuf_status(response_msg.status())?;
+                let epoch_lease = response_msg
+                    .epoch_lease()
+                    .ok_or(RangeServerError::InvalidRequestFormat)?;
+                Ok(PrepareOk {
+                    highest_known_epoch: response_msg.highest_known_epoch(),
+                    epoch_lease: EpochLease {
+                        lower_bound_inclusive: epoch_lease.lower_bound_inclusive(),
+                        upper_bound_inclusive: epoch_lease.upper_bound_inclusive(),
+                    },
+                })
+            }
+            _ => Err(RangeServerError::InvalidRequestFormat),
+        }
+    }
+
+    /// Reads the conflicts the range has seen since it was loaded, by key
+    /// prefix. Only served by the range server the range is loaded on.
+    pub async fn get_conflict_stats(
+        &self,
+        range_id: &FullRangeId,
+    ) -> Result<ConflictStats, RangeServerError> {
+        let req_id = Uuid::new_v4();
+        let mut fbb = FlatBufferBuilder::new();
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(req_id),
+        ));
+        let fbb_root = GetConflictStatsRequest::create(
+            &mut fbb,
+            &GetConflictStatsRequestArgs {
+                request_id,
+                range_id,
+            },
+        );
+        fbb.finish(fbb_root, None);
+        let response = self
+            .send_request(
+                req_id,
+                MessageType::GetConflictStats,
+                fbb.finished_data(),
+                None,
+                true,
+            )
+            .await?;
+        let msg = response.to_vec();
+        let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
+        match envelope.type_() {
+            MessageType::GetConflictStats => {
+                let response_msg = flatbuffers::root::<GetConflictStatsResponse>(
+                    envelope.bytes().unwrap().bytes(),
+                )
+                .unwrap();
+                rangeserver::error::Error::from_flatbuf_status(response_msg.status())?;
+                let counts = |c: Option<FlatbufConflictCounts>| {
+                    c.map_or(ConflictCounts::default(), |c| ConflictCounts {
+                        wait_die: c.wait_die(),
+                        lock_lost: c.lock_lost(),
+                        read_conflicts: c.read_conflicts(),
+                        overloaded: c.overloaded(),
+                    })
+                };
+                let prefixes = response_msg
+                    .prefixes()
+                    .iter()
+                    .flatten()
+                    .map(|p| {
+                        let prefix = p.prefix().map_or(&[][..], |b| b.bytes());
+                        (Bytes::copy_from_slice(prefix), counts(p.counts()))
+                    })
+                    .collect();
+                let labels = response_msg
+                    .labels()
+                    .iter()
+                    .flatten()
+                    .map(|l| {
+                        let label = l.label();
+                        let key = label.and_then(|l| l.key()).unwrap_or_default();
+                        let value = label.and_then(|l| l.value()).unwrap_or_default();
+                        ((key.to_string(), value.to_string()), counts(l.counts()))
+                    })
+                    .collect();
+                Ok(ConflictStats {
+                    prefixes,
+                    other: counts(response_msg.other()),
+                    labels,
+                })
+            }
+            _ => Err(RangeServerError::InvalidRequestFormat),
+        }
+    }
+
     fn get_request_id_from_response(msg: Bytes) -> Uuid {
         let msg = msg.to_vec();
         let envelope = flatbuffers::root::<ResponseEnvelope>(msg.as_slice()).unwrap();
@@ -403,6 +1011,21 @@ impl RangeClient {
                     flatbuffers::root::<GetResponse>(envelope.bytes().unwrap().bytes()).unwrap();
                 msg.request_id()
             }
+            MessageType::Scan => {
+                let msg =
+                    flatbuffers::root::<ScanResponse>(envelope.bytes().unwrap().bytes()).unwrap();
+                msg.request_id()
+            }
+            MessageType::ScanChunk => {
+                let msg = flatbuffers::root::<ScanChunkResponse>(envelope.bytes().unwrap().bytes())
+                    .unwrap();
+                msg.request_id()
+            }
+            MessageType::GetPrefix => {
+                let msg = flatbuffers::root::<GetPrefixResponse>(envelope.bytes().unwrap().bytes())
+                    .unwrap();
+                msg.request_id()
+            }
             MessageType::Prepare => {
                 let msg = flatbuffers::root::<PrepareResponse>(envelope.bytes().unwrap().bytes())
                     .unwrap();
@@ -418,6 +1041,25 @@ impl RangeClient {
                     flatbuffers::root::<CommitResponse>(envelope.bytes().unwrap().bytes()).unwrap();
                 msg.request_id()
             }
+            MessageType::Validate => {
+                let msg = flatbuffers::root::<ValidateResponse>(envelope.bytes().unwrap().bytes())
+                    .unwrap();
+                msg.request_id()
+            }
+            MessageType::ExtendEpochLease => {
+                let msg = flatbuffers::root::<ExtendEpochLeaseResponse>(
+                    envelope.bytes().unwrap().bytes(),
+                )
+                .unwrap();
+                msg.request_id()
+            }
+            MessageType::GetConflictStats => {
+                let msg = flatbuffers::root::<GetConflictStatsResponse>(
+                    envelope.bytes().unwrap().bytes(),
+                )
+                .unwrap();
+                msg.request_id()
+            }
             _ => panic!("unknown response message type"), // TODO: return and log unknown message type error.
         };
         common::util::flatbuf::deserialize_uuid(req_id.unwrap())
@@ -450,8 +1092,11 @@ impl RangeClient {
                                 }
                                 State::Started(started_state) => &mut started_state.outstanding_requests,
                             };
-                            let sender = outstanding_requests.remove(&req_id).unwrap();
-                            sender.send(Ok(msg)).unwrap()
+                            // Responses to requests that were given up on, or to
+                            // attempts that got answered already, are dropped.
+                            if let Some(sender) = outstanding_requests.remove(&req_id) {
+                                let _ = sender.send(Ok(msg));
+                            }
                         }
                     }
                 }
@@ -459,22 +1104,71 @@ impl RangeClient {
         }
     }
 
-    fn create_msg_envelope<'a>(
-        &self,
-        fbb: &'a mut FlatBufferBuilder<'a>,
-        msg_type: MessageType,
-        bytes: Bytes,
-    ) -> &'a [u8] {
-        let bytes = fbb.create_vector(bytes.to_vec().as_slice());
+    // Copying the request into its envelope is the only copy made of it after
+    // it was encoded: the envelope's buffer is handed to the network as is.
+    fn create_msg_envelope(msg_type: MessageType, msg: &[u8]) -> Bytes {
+        let mut fbb = FlatBufferBuilder::with_capacity(msg.len() + MESSAGE_OVERHEAD);
+        let bytes = fbb.create_vector(msg);
         let fbb_root = RequestEnvelope::create(
-            fbb,
+            &mut fbb,
             &RequestEnvelopeArgs {
                 type_: msg_type,
                 bytes: Some(bytes),
             },
         );
         fbb.finish(fbb_root, None);
-        fbb.finished_data()
+        let (buf, head) = fbb.collapse();
+        Bytes::from(buf).slice(head..)
+    }
+
+    // Sends a request and waits for its response, resending it as the retry
+    // policy allows when an attempt goes unanswered. Retries stop at the
+    // deadline, if any, but the last attempt still waits a full attempt
+    // timeout. Commits and aborts settle transactions that may be past their
+    // deadline already, so they don't get one.
+    async fn send_request(
+        &self,
+        req_id: Uuid,
+        msg_type: MessageType,
+        msg: &[u8],
+        deadline: Option<Instant>,
+        idempotent: bool,
+    ) -> Result<Bytes, RangeServerError> {
+        let (tx, mut rx) = oneshot::channel();
+        self.record_outstanding_request(req_id, tx).await?;
+        let request_bytes = Self::create_msg_envelope(msg_type, msg);
+        let policy = &self.retry_policy;
+        let attempts = policy.attempts(idempotent);
+        for attempt in 1..=attempts {
+            self.fast_network
+                .send(self.range_server_info.address, request_bytes.clone())
+                .unwrap();
+            let now = Instant::now();
+            let last =
+                attempt == attempts || deadline.is_some_and(|d| now + policy.attempt_timeout >= d);
+            let wait = match deadline {
+                Some(deadline) if last => deadline
+                    .saturating_duration_since(now)
+                    .max(policy.attempt_timeout),
+                _ => policy.attempt_timeout,
+            };
+            if let Ok(response) = tokio::time::timeout(wait, &mut rx).await {
+                return response.unwrap_or(Err(RangeServerError::ConnectionClosed));
+            }
+            if last {
+                break;
+            }
+            tokio::time::sleep(policy.backoff(attempt)).await;
+        }
+        self.forget_outstanding_request(req_id).await;
+        Err(RangeServerError::Timeout)
+    }
+
+    async fn forget_outstanding_request(&self, req_id: Uuid) {
+        let mut state = self.state.write().await;
+        if let State::Started(started_state) = state.deref_mut() {
+            started_state.outstanding_requests.remove(&req_id);
+        }
     }
 
     async fn record_outstanding_request(
@@ -542,16 +1236,167 @@ impl RangeClient {
             range_key: range_keys,
         };
 
-        // Send the request
-        match client.prefetch(Request::new(request)).await {
-            Ok(response) => {
-                println!("RESPONSE={:?}", response);
-                Ok(())
+        // Send the request, retrying when it got lost or the server was
+        // briefly unavailable.
+        let policy = &self.retry_policy;
+        let deadline = transaction_deadline(&tx);
+        let attempts = policy.attempts(true);
+        let mut attempt = 1;
+        loop {
+            let result = tokio::time::timeout(
+                policy.attempt_timeout,
+                client.prefetch(Request::new(request.clone())),
+            )
+            .await;
+            let retriable = match &result {
+                Ok(Ok(response)) => {
+                    println!("RESPONSE={:?}", response);
+                    return Ok(());
+                }
+                Ok(Err(status)) => {
+                    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+                }
+                Err(_) => true,
+            };
+            let backoff = policy.backoff(attempt);
+            if !retriable || attempt == attempts || Instant::now() + backoff >= deadline {
+                println!("Failed prefetch: {:?}", result);
+                return Err(RangeServerError::PrefetchError);
+            }
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Reads many keys of a range, or every key within a key range, outside
+    /// of any transaction. The records are streamed over the proto server
+    /// rather than the fast network, so this suits jobs reading more than a
+    /// few datagrams hold, such as analytics or warming caches. A
+    /// `chunk_size` of 0 leaves it to the server.
+    pub async fn bulk_get(
+        &self,
+        range_id: &FullRangeId,
+        selection: BulkGetSelection,
+        chunk_size: u32,
+    ) -> Result<BulkGetStream, RangeServerError> {
+        let mut client = match &self.proto_client {
+            None => {
+                return Err(RangeServerError::InternalError(Arc::new(
+                    std::io::Error::other("the range client has no proto server address"),
+                )))
             }
-            Err(e) => {
-                println!("Failed prefetch: {:?}", e);
-                Err(RangeServerError::PrefetchError)
+            Some(proto_client) => (**proto_client).clone(),
+        };
+        let selection = match selection {
+            BulkGetSelection::Keys(keys) => {
+                bulk_get_request::Selection::Keys(bulk_get_request::Keys {
+                    keys: keys.into_iter().map(|k| k.to_vec()).collect(),
+                })
             }
+            BulkGetSelection::KeyRange(key_range) => {
+                bulk_get_request::Selection::KeyRange(bulk_get_request::KeyRange {
+                    lower_bound_inclusive: key_range.lower_bound_inclusive.map(|b| b.to_vec()),
+                    upper_bound_exclusive: key_range.upper_bound_exclusive.map(|b| b.to_vec()),
+                })
+            }
+        };
+        let request = BulkGetRequest {
+            range: Some(RangeId {
+                keyspace_id: range_id.keyspace_id.id.to_string(),
+                range_id: range_id.range_id.to_string(),
+            }),
+            selection: Some(selection),
+            chunk_size,
+        };
+        let chunks = client
+            .bulk_get(Request::new(request))
+            .await
+            .map_err(error_from_status)?
+            .into_inner();
+        Ok(BulkGetStream { chunks })
+    }
+}
+
+/// The records a bulk get reads.
+pub enum BulkGetSelection {
+    Keys(Vec<Bytes>),
+    KeyRange(KeyRange),
+}
+
+/// The records of a bulk get, as the range server streams them back.
+pub struct BulkGetStream {
+    chunks: Streaming<BulkGetChunk>,
+}
+
+impl BulkGetStream {
+    /// Returns the next chunk of records, None once all were read. Keys that
+    /// don't exist have no value. Each chunk reads the newest committed
+    /// values when the server gets to it, so chunks may reflect different
+    /// transactions.
+    pub async fn next_chunk(
+        &mut self,
+    ) -> Result<Option<Vec<(Bytes, Option<Bytes>)>>, RangeServerError> {
+        let chunk = self.chunks.message().await.map_err(error_from_status)?;
+        Ok(chunk.map(|chunk| {
+            chunk
+                .records
+                .into_iter()
+                .map(|record| (Bytes::from(record.key), record.value.map(Bytes::from)))
+                .collect()
+        }))
+    }
+}
+
+/// The records of a scan, read from the range server a chunk at a time.
+pub struct ScanStream<'a> {
+    client: &'a RangeClient,
+    tx: Arc<TransactionInfo>,
+    range_id: FullRangeId,
+    // What is left to read: the lower bound moves past every chunk read.
+    key_range: KeyRange,
+    remaining: Option<usize>,
+    chunk_size: u32,
+    done: bool,
+}
+
+impl ScanStream<'_> {
+    /// Returns the next chunk of records in key order, None once all were
+    /// read. Every chunk carries the leader sequence number of the range
+    /// server that read it, which callers must check stayed the same to know
+    /// the chunks come from the same leader.
+    pub async fn next_chunk(&mut self) -> Result<Option<ScanResult>, RangeServerError> {
+        if self.done || self.remaining == Some(0) {
+            return Ok(None);
+        }
+        let chunk_size = match self.remaining {
+            Some(remaining) => self.chunk_size.min(remaining.min(u32::MAX as usize) as u32),
+            None => self.chunk_size,
+        };
+        let (chunk, done) = self
+            .client
+            .scan_chunk(self.tx.clone(), &self.range_id, &self.key_range, chunk_size)
+            .await?;
+        self.done = done;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= chunk.records.len().min(*remaining);
+        }
+        if let Some((last_key, _)) = chunk.records.last() {
+            // The smallest key greater than the last one read.
+            let mut next_key = last_key.to_vec();
+            next_key.push(0);
+            self.key_range.lower_bound_inclusive = Some(Bytes::from(next_key));
         }
+        Ok(Some(chunk))
+    }
+}
+
+fn error_from_status(status: tonic::Status) -> RangeServerError {
+    match status.code() {
+        Code::FailedPrecondition => RangeServerError::RangeIsNotLoaded,
+        Code::OutOfRange => RangeServerError::KeyIsOutOfRange,
+        Code::InvalidArgument => RangeServerError::InvalidRequestFormat,
+        Code::Unavailable => RangeServerError::RangeFaulted,
+        Code::DeadlineExceeded => RangeServerError::Timeout,
+        _ => RangeServerError::InternalError(Arc::new(status)),
     }
 }
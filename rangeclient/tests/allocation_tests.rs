@@ -0,0 +1,152 @@
+use bytes::Bytes;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use common::{
+    full_range_id::FullRangeId,
+    host_info::{HostIdentity, HostInfo},
+    keyspace_id::KeyspaceId,
+    network::fast_network::FastNetwork,
+    record::Record,
+    region::{Region, Zone},
+    transaction_info::TransactionInfo,
+};
+use rangeclient::client::{RangeClient, RetryPolicy};
+use uuid::Uuid;
+
+// Counts the bytes allocated by the whole test binary, which is why these
+// tests live in their own file.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Records what is sent and how much was allocated by then. Never replies.
+#[derive(Default)]
+struct CapturingNetwork {
+    sent: Mutex<Vec<(Bytes, usize)>>,
+    // Kept so that the client does not see the network as closed.
+    senders: Mutex<Vec<mpsc::UnboundedSender<Bytes>>>,
+}
+
+impl FastNetwork for CapturingNetwork {
+    fn send(&self, _to: SocketAddr, payload: Bytes) -> Result<(), std::io::Error> {
+        let allocated = ALLOCATED.load(Ordering::Relaxed);
+        self.sent.lock().unwrap().push((payload, allocated));
+        Ok(())
+    }
+
+    fn listen_default(&self) -> mpsc::UnboundedReceiver<(SocketAddr, Bytes)> {
+        mpsc::unbounded_channel().1
+    }
+
+    fn register(&self, _from: SocketAddr) -> mpsc::UnboundedReceiver<Bytes> {
+        let (s, r) = mpsc::unbounded_channel();
+        self.senders.lock().unwrap().push(s);
+        r
+    }
+
+    fn poll(&self) -> bool {
+        false
+    }
+}
+
+fn host_info() -> HostInfo {
+    HostInfo {
+        identity: HostIdentity {
+            name: "test_server".into(),
+            zone: Zone {
+                region: Region {
+                    cloud: None,
+                    name: "test-region".into(),
+                },
+                name: "a".into(),
+            },
+        },
+        address: "127.0.0.1:50055".parse().unwrap(),
+        warden_connection_epoch: 0,
+        labels: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn prepare_does_not_copy_values_before_sending() {
+    const VALUE_SIZE: usize = 8 << 20;
+    let network = Arc::new(CapturingNetwork::default());
+    let client = RangeClient::new(network.clone(), host_info(), None, RetryPolicy::default()).await;
+    RangeClient::start(
+        client.clone(),
+        tokio::runtime::Handle::current(),
+        CancellationToken::new(),
+    )
+    .await;
+    let started = chrono::Utc::now();
+    let tx = Arc::new(TransactionInfo {
+        id: Uuid::new_v4(),
+        started,
+        first_started: started,
+        overall_timeout: Duration::from_secs(10),
+        labels: BTreeMap::new(),
+        isolation: Default::default(),
+        snapshot_epoch: None,
+    });
+    let range_id = FullRangeId {
+        keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+        range_id: Uuid::new_v4(),
+    };
+    let writes = vec![Record::new(b"key".to_vec(), vec![7u8; VALUE_SIZE])];
+
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    // Nobody answers, so only wait until the request was sent.
+    let _ = tokio::time::timeout(
+        Duration::from_millis(100),
+        client.prepare_transaction(tx, &range_id, false, &writes, &[], &[], &[]),
+    )
+    .await;
+
+    let sent = network.sent.lock().unwrap();
+    let (payload, allocated_at_send) = sent.first().unwrap();
+    assert!(payload.len() > VALUE_SIZE);
+    // Encoding the request and wrapping it into its envelope take one copy
+    // of the value each. Anything beyond that is an intermediate copy.
+    let allocated = allocated_at_send - start;
+    assert!(
+        allocated < 2 * VALUE_SIZE + (1 << 20),
+        "allocated {} bytes to send a {} byte value",
+        allocated,
+        VALUE_SIZE
+    );
+}
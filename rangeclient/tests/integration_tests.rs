@@ -15,14 +15,16 @@ use common::{
     },
     full_range_id::FullRangeId,
     host_info::{HostIdentity, HostInfo},
+    key_range::KeyRange,
     keyspace_id::KeyspaceId,
     network::{fast_network::FastNetwork, for_testing::udp_fast_network::UdpFastNetwork},
-    record::Record,
+    record::{Condition, Increment, Record},
     region::{Region, Zone},
-    transaction_info::TransactionInfo,
+    transaction_info::{IsolationLevel, TransactionInfo},
 };
-use rangeclient::client::RangeClient;
+use rangeclient::client::{BulkGetSelection, RangeClient, RetryPolicy};
 use rangeserver::{
+    error::Error,
     for_testing::{epoch_supplier::EpochSupplier, mock_warden::MockWarden},
     server::Server,
 };
@@ -35,7 +37,9 @@ struct TestContext {
     cancellation_token: CancellationToken,
     server_runtime: tokio::runtime::Runtime,
     client_runtime: tokio::runtime::Runtime,
-    storage_context: rangeserver::storage::cassandra::for_testing::TestContext,
+    storage_context: rangeserver::storage::in_memory::for_testing::TestContext,
+    mock_warden: MockWarden,
+    epoch_supplier: Arc<EpochSupplier>,
 }
 
 fn get_config(warden_address: HostPort) -> Config {
@@ -46,6 +50,7 @@ fn get_config(warden_address: HostPort) -> Config {
     let region_config = RegionConfig {
         warden_address: warden_address,
         epoch_publishers: HashSet::new(),
+        cassandra_datacenter: None,
     };
     let epoch_config = EpochConfig {
         // Not used in these tests.
@@ -57,6 +62,19 @@ fn get_config(warden_address: HostPort) -> Config {
             range_maintenance_duration: time::Duration::from_secs(1),
             proto_server_addr: HostPort::from_str("127.0.0.1:50054").unwrap(),
             fast_network_addr: HostPort::from_str("127.0.0.1:50055").unwrap(),
+            preflight_epoch_advance_timeout: None,
+            max_pending_prepares_per_range: None,
+            max_epoch_lease_extension: None,
+            handoff_drain_timeout: None,
+            lock_table: Default::default(),
+            conflict_stats: Default::default(),
+            write_stall: Default::default(),
+            checksum_verification: Default::default(),
+            decision_log: Default::default(),
+            expiry: Default::default(),
+            fast_network_transport: Default::default(),
+            mirroring: Default::default(),
+            transaction_recovery: Default::default(),
         },
         universe: UniverseConfig {
             proto_server_addr: "127.0.0.1:123".parse().unwrap(),
@@ -65,11 +83,15 @@ fn get_config(warden_address: HostPort) -> Config {
             proto_server_addr: "127.0.0.1:124".parse().unwrap(),
             fast_network_addr: HostPort::from_str("127.0.0.1:125").unwrap(),
             transaction_overall_timeout: time::Duration::from_secs(10),
+            fast_network_transport: Default::default(),
         },
         cassandra: CassandraConfig {
             cql_addr: "127.0.0.1:9042".parse().unwrap(),
+            consistency: Default::default(),
         },
         regions: std::collections::HashMap::new(),
+        admin: Default::default(),
+        quic: Default::default(),
         epoch: epoch_config,
     };
     config.regions.insert(region, region_config);
@@ -93,6 +115,7 @@ fn get_server_host_info(address: SocketAddr) -> HostInfo {
         },
         address,
         warden_connection_epoch: 0,
+        labels: Default::default(),
     }
 }
 
@@ -102,7 +125,7 @@ async fn setup_server(
     warden_address: HostPort,
     proto_server_listener: TcpListener,
     epoch_supplier: Arc<EpochSupplier>,
-    storage_context: &rangeserver::storage::cassandra::for_testing::TestContext,
+    storage_context: &rangeserver::storage::in_memory::for_testing::TestContext,
 ) -> tokio::runtime::Runtime {
     let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
     let server_address = server_socket.local_addr().unwrap();
@@ -114,7 +137,7 @@ async fn setup_server(
             tokio::task::yield_now().await
         }
     });
-    let storage = storage_context.cassandra.clone();
+    let storage = storage_context.storage.clone();
 
     runtime.spawn(async move {
         let config = get_config(warden_address);
@@ -160,6 +183,7 @@ async fn setup_client(
         fast_network,
         get_server_host_info(server_address),
         Some(proto_server_address),
+        RetryPolicy::default(),
     )
     .await;
     RangeClient::start(
@@ -184,8 +208,8 @@ async fn setup() -> TestContext {
     let proto_server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let proto_server_address = proto_server_listener.local_addr().unwrap();
     let cancellation_token = CancellationToken::new();
-    let storage_context: rangeserver::storage::cassandra::for_testing::TestContext =
-        rangeserver::storage::cassandra::for_testing::init().await;
+    let storage_context: rangeserver::storage::in_memory::for_testing::TestContext =
+        rangeserver::storage::in_memory::for_testing::init().await;
     let server_runtime = setup_server(
         server_socket,
         cancellation_token.clone(),
@@ -219,6 +243,8 @@ async fn setup() -> TestContext {
         server_runtime,
         client_runtime,
         storage_context,
+        mock_warden,
+        epoch_supplier,
     }
 }
 
@@ -230,10 +256,15 @@ async fn tear_down(context: TestContext) {
 }
 
 fn start_transaction() -> Arc<TransactionInfo> {
+    let started = chrono::Utc::now();
     Arc::new(TransactionInfo {
         id: Uuid::new_v4(),
-        started: chrono::Utc::now(),
+        started,
+        first_started: started,
         overall_timeout: time::Duration::from_secs(10),
+        labels: std::collections::BTreeMap::new(),
+        isolation: Default::default(),
+        snapshot_epoch: None,
     })
 }
 
@@ -304,14 +335,54 @@ async fn commit_no_writes() {
     let deletes = vec![];
     let prepare_ok = context
         .client
-        .prepare_transaction(tx.clone(), &range_id, true, &writes, &deletes)
+        .prepare_transaction(tx.clone(), &range_id, true, &writes, &deletes, &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn moved_range_drains_before_handoff() {
+    let context = setup().await;
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let tx = start_transaction();
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let writes = vec![Record {
+        key: key.clone(),
+        val: Bytes::from_static(b"written while moving"),
+        ttl: None,
+    }];
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, false, &writes, &[], &[], &[])
         .await
         .unwrap();
+    context.mock_warden.unassign(&range_id).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+    // New transactions are turned away while the prepared one finishes.
+    let read = context
+        .client
+        .get(start_transaction(), &range_id, vec![key])
+        .await;
+    assert!(matches!(read, Err(Error::RangeDraining)));
+    assert!(!context.mock_warden.is_handed_off(&range_id).await);
     context
         .client
         .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
         .await
         .unwrap();
+    while !context.mock_warden.is_handed_off(&range_id).await {
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+    }
     tear_down(context).await
 }
 
@@ -339,17 +410,19 @@ async fn read_modify_write() {
     let record1 = Record {
         key: key1.clone(),
         val: val1.clone(),
+        ttl: None,
     };
     let val2 = Bytes::from_static(b"I have a different value!");
     let record2 = Record {
         key: key2.clone(),
         val: val2.clone(),
+        ttl: None,
     };
     let writes = vec![record1, record2];
     let deletes = vec![];
     let prepare_ok = context
         .client
-        .prepare_transaction(tx.clone(), &range_id, true, &writes, &deletes)
+        .prepare_transaction(tx.clone(), &range_id, true, &writes, &deletes, &[], &[])
         .await
         .unwrap();
     context
@@ -367,6 +440,548 @@ async fn read_modify_write() {
     tear_down(context).await
 }
 
+#[tokio::test]
+async fn read_at_epoch() {
+    let context = setup().await;
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let mut committed_at = Vec::new();
+    for val in ["first", "second"] {
+        let tx = start_transaction();
+        let record = Record {
+            key: key.clone(),
+            val: Bytes::from(val),
+            ttl: None,
+        };
+        let prepare_ok = context
+            .client
+            .prepare_transaction(tx.clone(), &range_id, false, &[record], &[], &[], &[])
+            .await
+            .unwrap();
+        // Commit the second write at a later epoch than the first.
+        let epoch = committed_at
+            .last()
+            .map_or(0, |epoch| epoch + 1)
+            .max(prepare_ok.highest_known_epoch);
+        context
+            .client
+            .commit_transaction(tx, &range_id, epoch)
+            .await
+            .unwrap();
+        committed_at.push(epoch);
+    }
+    // Reads at an epoch wait for the range to know it is over.
+    context.epoch_supplier.set_epoch(committed_at[1] + 1).await;
+    let vals = context
+        .client
+        .get_at_epoch(
+            start_transaction(),
+            &range_id,
+            vec![key.clone()],
+            committed_at[0],
+        )
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals, vec![Some(Bytes::from_static(b"first"))]);
+    let vals = context
+        .client
+        .get_at_epoch(start_transaction(), &range_id, vec![key], committed_at[1])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals, vec![Some(Bytes::from_static(b"second"))]);
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn conditional_put() {
+    let context = setup().await;
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let first = Bytes::from_static(b"first");
+    let second = Bytes::from_static(b"second");
+    let if_absent = vec![Condition {
+        key: key.clone(),
+        expected: None,
+    }];
+
+    let tx = start_transaction();
+    let prepare_ok = context
+        .client
+        .prepare_transaction(
+            tx.clone(),
+            &range_id,
+            false,
+            &[Record::new(key.clone(), first.clone())],
+            &[],
+            &if_absent,
+            &[],
+        )
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+
+    let tx = start_transaction();
+    let res = context
+        .client
+        .prepare_transaction(
+            tx.clone(),
+            &range_id,
+            false,
+            &[Record::new(key.clone(), second.clone())],
+            &[],
+            &if_absent,
+            &[],
+        )
+        .await;
+    assert!(matches!(res, Err(Error::ConditionFailed)));
+    context
+        .client
+        .abort_transaction(tx, &range_id)
+        .await
+        .unwrap();
+
+    let tx = start_transaction();
+    let prepare_ok = context
+        .client
+        .prepare_transaction(
+            tx.clone(),
+            &range_id,
+            false,
+            &[Record::new(key.clone(), second.clone())],
+            &[],
+            &[Condition {
+                key: key.clone(),
+                expected: Some(first),
+            }],
+            &[],
+        )
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+    let vals = context
+        .client
+        .get(start_transaction(), &range_id, vec![key])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals[0], Some(second));
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn increment() {
+    let context = setup().await;
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    for delta in [3, 4] {
+        let tx = start_transaction();
+        let prepare_ok = context
+            .client
+            .prepare_transaction(
+                tx.clone(),
+                &range_id,
+                false,
+                &[],
+                &[],
+                &[],
+                &[Increment {
+                    key: key.clone(),
+                    delta,
+                }],
+            )
+            .await
+            .unwrap();
+        context
+            .client
+            .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+            .await
+            .unwrap();
+    }
+    let vals = context
+        .client
+        .get(start_transaction(), &range_id, vec![key])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals[0], Some(Bytes::copy_from_slice(&7i64.to_be_bytes())));
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn scan_stream() {
+    let context = setup().await;
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let prefix = Uuid::new_v4().as_bytes().to_vec();
+    let key = |i: u8| {
+        let mut key = prefix.clone();
+        key.push(i);
+        Bytes::from(key)
+    };
+    let writes: Vec<Record> = (0..5)
+        .map(|i| Record::new(key(i), Bytes::from(vec![i])))
+        .collect();
+    let tx = start_transaction();
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, false, &writes, &[], &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+    let key_range = KeyRange {
+        lower_bound_inclusive: Some(key(0)),
+        upper_bound_exclusive: Some(key(5)),
+    };
+    let reader = start_transaction();
+    let mut stream =
+        context
+            .client
+            .scan_stream(reader.clone(), &range_id, key_range.clone(), None, 2);
+    let mut chunk_sizes = Vec::new();
+    let mut records = Vec::new();
+    while let Some(chunk) = stream.next_chunk().await.unwrap() {
+        chunk_sizes.push(chunk.records.len());
+        records.extend(chunk.records);
+    }
+    assert_eq!(chunk_sizes, vec![2, 2, 1]);
+    let expected: Vec<(Bytes, Bytes)> = writes.into_iter().map(|r| (r.key, r.val)).collect();
+    assert_eq!(records, expected);
+    // The limit caps the records across chunks.
+    let mut stream = context
+        .client
+        .scan_stream(reader, &range_id, key_range, Some(3), 2);
+    let mut read = 0;
+    while let Some(chunk) = stream.next_chunk().await.unwrap() {
+        read += chunk.records.len();
+    }
+    assert_eq!(read, 3);
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn get_prefix() {
+    let context = setup().await;
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let prefix = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let key = |suffix: &[u8]| Bytes::from([&prefix[..], suffix].concat());
+    let writes = vec![
+        Record::new(key(b"/a"), Bytes::from_static(b"1")),
+        Record::new(key(b"/b"), Bytes::from_static(b"2")),
+        Record::new(key(b"/c"), Bytes::from_static(b"3")),
+        // Shares all but the last byte of the prefix.
+        Record::new(
+            Bytes::from([&prefix[..prefix.len() - 1], &[prefix[prefix.len() - 1] ^ 1]].concat()),
+            Bytes::from_static(b"4"),
+        ),
+    ];
+    let tx = start_transaction();
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, false, &writes, &[], &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+    let reader = start_transaction();
+    let result = context
+        .client
+        .get_prefix(reader.clone(), &range_id, prefix.clone(), None)
+        .await
+        .unwrap();
+    let keys: Vec<Bytes> = result.records.into_iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec![key(b"/a"), key(b"/b"), key(b"/c")]);
+    let result = context
+        .client
+        .get_prefix(reader, &range_id, prefix, Some(2))
+        .await
+        .unwrap();
+    assert_eq!(result.records.len(), 2);
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn expiring_put() {
+    let context = setup().await;
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let val = Bytes::from_static(b"short-lived");
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let tx = start_transaction();
+    let writes = vec![Record::with_ttl(
+        key.clone(),
+        val.clone(),
+        time::Duration::from_millis(500),
+    )];
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, false, &writes, &[], &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+    // The reads share a transaction, which holds the range's lock after the
+    // first.
+    let reader = start_transaction();
+    let vals = context
+        .client
+        .get(reader.clone(), &range_id, vec![key.clone()])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals[0], Some(val));
+    tokio::time::sleep(time::Duration::from_millis(600)).await;
+    let vals = context
+        .client
+        .get(reader, &range_id, vec![key])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals[0], None);
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn snapshot_isolation_allows_write_skew() {
+    let context = setup().await;
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let checking = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let savings = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let balance = |b: i64| Bytes::from(b.to_string());
+    let tx = start_transaction();
+    let writes = vec![
+        Record::new(checking.clone(), balance(60)),
+        Record::new(savings.clone(), balance(60)),
+    ];
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, false, &writes, &[], &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+
+    // Two withdrawals of 100, each checking that the two balances together
+    // cover it and taking it from a different one. Snapshot reads don't lock,
+    // so both read before either writes, and nothing checks their reads.
+    let snapshot_transaction = || {
+        let mut tx = (*start_transaction()).clone();
+        tx.isolation = IsolationLevel::Snapshot;
+        Arc::new(tx)
+    };
+    let withdrawals = [
+        (snapshot_transaction(), checking.clone()),
+        (snapshot_transaction(), savings.clone()),
+    ];
+    let mut new_balances = Vec::new();
+    for (tx, from) in &withdrawals {
+        let vals = context
+            .client
+            .get(
+                tx.clone(),
+                &range_id,
+                vec![checking.clone(), savings.clone()],
+            )
+            .await
+            .unwrap()
+            .vals;
+        let balances: Vec<i64> = vals
+            .iter()
+            .map(|v| {
+                std::str::from_utf8(v.as_ref().unwrap())
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        assert!(balances.iter().sum::<i64>() >= 100);
+        let current = if *from == checking {
+            balances[0]
+        } else {
+            balances[1]
+        };
+        new_balances.push(Record::new(from.clone(), balance(current - 100)));
+    }
+    for ((tx, _), write) in withdrawals.iter().zip(new_balances) {
+        let prepare_ok = context
+            .client
+            .prepare_transaction(tx.clone(), &range_id, true, &[write], &[], &[], &[])
+            .await
+            .unwrap();
+        context
+            .client
+            .commit_transaction(tx.clone(), &range_id, prepare_ok.highest_known_epoch)
+            .await
+            .unwrap();
+    }
+
+    // Both committed, overdrawing the customer, as documented for
+    // `IsolationLevel::Snapshot`. With serializable isolation the second
+    // withdrawal would have waited for the first one's lock, or failed its
+    // prepare with optimistic reads.
+    let reader = start_transaction();
+    let vals = context
+        .client
+        .get(reader, &range_id, vec![checking, savings])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals, vec![Some(balance(-40)), Some(balance(-40))]);
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn snapshot_isolation_first_committer_wins() {
+    let context = setup().await;
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let write = |val: &'static str| [Record::new(key.clone(), Bytes::from(val))];
+    let tx = start_transaction();
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, false, &write("first"), &[], &[], &[])
+        .await
+        .unwrap();
+    let snapshot_epoch = prepare_ok.highest_known_epoch;
+    context
+        .client
+        .commit_transaction(tx, &range_id, snapshot_epoch)
+        .await
+        .unwrap();
+    let mut snapshot_tx = (*start_transaction()).clone();
+    snapshot_tx.isolation = IsolationLevel::Snapshot;
+    snapshot_tx.snapshot_epoch = Some(snapshot_epoch);
+    let snapshot_tx = Arc::new(snapshot_tx);
+
+    // Another transaction writes the key after the snapshot.
+    let tx = start_transaction();
+    context
+        .client
+        .prepare_transaction(
+            tx.clone(),
+            &range_id,
+            false,
+            &write("second"),
+            &[],
+            &[],
+            &[],
+        )
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, snapshot_epoch + 1)
+        .await
+        .unwrap();
+    context.epoch_supplier.set_epoch(snapshot_epoch + 2).await;
+
+    // The snapshot transaction still reads what was there at its snapshot,
+    // and can't overwrite what was written since.
+    let vals = context
+        .client
+        .get(snapshot_tx.clone(), &range_id, vec![key.clone()])
+        .await
+        .unwrap()
+        .vals;
+    assert_eq!(vals, vec![Some(Bytes::from_static(b"first"))]);
+    let res = context
+        .client
+        .prepare_transaction(snapshot_tx, &range_id, true, &write("third"), &[], &[], &[])
+        .await;
+    assert!(matches!(res, Err(Error::TransactionAborted(_))));
+    tear_down(context).await
+}
+
+#[tokio::test]
+async fn get_for_update_locks_the_range() {
+    let context = setup().await;
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+    let mut reader = (*start_transaction()).clone();
+    reader.isolation = IsolationLevel::Snapshot;
+    let reader = Arc::new(reader);
+    context
+        .client
+        .get(reader.clone(), &range_id, vec![key.clone()])
+        .await
+        .unwrap();
+    // Snapshot reads don't lock, so a younger writer goes through.
+    let writer = start_transaction();
+    let write = [Record::new(key.clone(), Bytes::from_static(b"value"))];
+    context
+        .client
+        .prepare_transaction(writer.clone(), &range_id, false, &write, &[], &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .abort_transaction(writer, &range_id)
+        .await
+        .unwrap();
+
+    context
+        .client
+        .get_for_update(reader, &range_id, vec![key.clone()])
+        .await
+        .unwrap();
+    // Now the reader holds the lock, and a younger writer dies rather than
+    // waiting for an older holder.
+    let writer = start_transaction();
+    let res = context
+        .client
+        .prepare_transaction(writer, &range_id, false, &write, &[], &[], &[])
+        .await;
+    assert!(matches!(res, Err(Error::TransactionAborted(_))));
+    tear_down(context).await
+}
+
 #[tokio::test]
 async fn test_prefetch_with_value() {
     let context = setup().await;
@@ -387,17 +1002,19 @@ async fn test_prefetch_with_value() {
     let record1 = Record {
         key: key1.clone(),
         val: val1.clone(),
+        ttl: None,
     };
     let val2 = Bytes::from_static(b"I have a different value!");
     let record2 = Record {
         key: key2.clone(),
         val: val2.clone(),
+        ttl: None,
     };
     let writes = vec![record1, record2];
     let deletes = vec![];
     let prepare_ok = context
         .client
-        .prepare_transaction(tx.clone(), &range_id, true, &writes, &deletes)
+        .prepare_transaction(tx.clone(), &range_id, true, &writes, &deletes, &[], &[])
         .await
         .unwrap();
     context
@@ -427,3 +1044,85 @@ async fn test_prefetch_no_value() {
     assert_eq!(vals, ());
     tear_down(context).await;
 }
+
+#[tokio::test]
+async fn bulk_get() {
+    let context = setup().await;
+    let tx = start_transaction();
+    let range_id = FullRangeId {
+        keyspace_id: context.storage_context.keyspace_id,
+        range_id: context.storage_context.range_id,
+    };
+    let records: Vec<Record> = (0..5u8)
+        .map(|i| Record {
+            key: Bytes::from(vec![b'k', i]),
+            val: Bytes::from(vec![b'v', i]),
+            ttl: None,
+        })
+        .collect();
+    let keys: Vec<Bytes> = records.iter().map(|r| r.key.clone()).collect();
+    let _ = context
+        .client
+        .get(tx.clone(), &range_id, keys.clone())
+        .await
+        .unwrap();
+    let prepare_ok = context
+        .client
+        .prepare_transaction(tx.clone(), &range_id, true, &records, &[], &[], &[])
+        .await
+        .unwrap();
+    context
+        .client
+        .commit_transaction(tx, &range_id, prepare_ok.highest_known_epoch)
+        .await
+        .unwrap();
+
+    let missing = Bytes::from_static(b"missing");
+    let mut stream = context
+        .client
+        .bulk_get(
+            &range_id,
+            BulkGetSelection::Keys(vec![keys[0].clone(), missing.clone()]),
+            1,
+        )
+        .await
+        .unwrap();
+    let mut read = vec![];
+    while let Some(chunk) = stream.next_chunk().await.unwrap() {
+        assert_eq!(chunk.len(), 1);
+        read.extend(chunk);
+    }
+    assert_eq!(
+        read,
+        vec![
+            (keys[0].clone(), Some(records[0].val.clone())),
+            (missing, None)
+        ]
+    );
+
+    let mut stream = context
+        .client
+        .bulk_get(
+            &range_id,
+            BulkGetSelection::KeyRange(KeyRange {
+                lower_bound_inclusive: Some(keys[1].clone()),
+                upper_bound_exclusive: Some(Bytes::from_static(b"l")),
+            }),
+            2,
+        )
+        .await
+        .unwrap();
+    let mut read = vec![];
+    while let Some(chunk) = stream.next_chunk().await.unwrap() {
+        assert!(chunk.len() <= 2);
+        read.extend(chunk);
+    }
+    assert_eq!(
+        read,
+        records[1..]
+            .iter()
+            .map(|r| (r.key.clone(), Some(r.val.clone())))
+            .collect::<Vec<_>>()
+    );
+    tear_down(context).await
+}
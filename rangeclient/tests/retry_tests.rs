@@ -0,0 +1,172 @@
+use bytes::Bytes;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use common::{
+    full_range_id::FullRangeId,
+    host_info::{HostIdentity, HostInfo},
+    keyspace_id::KeyspaceId,
+    network::fast_network::FastNetwork,
+    record::Record,
+    region::{Region, Zone},
+    transaction_info::TransactionInfo,
+};
+use rangeclient::client::{Error, RangeClient, RetryPolicy};
+use uuid::Uuid;
+
+// Records what is sent, as if every datagram got lost. Never replies.
+#[derive(Default)]
+struct LossyNetwork {
+    sent: Mutex<Vec<Bytes>>,
+    // Kept so that the client does not see the network as closed.
+    senders: Mutex<Vec<mpsc::UnboundedSender<Bytes>>>,
+}
+
+impl FastNetwork for LossyNetwork {
+    fn send(&self, _to: SocketAddr, payload: Bytes) -> Result<(), std::io::Error> {
+        self.sent.lock().unwrap().push(payload);
+        Ok(())
+    }
+
+    fn listen_default(&self) -> mpsc::UnboundedReceiver<(SocketAddr, Bytes)> {
+        mpsc::unbounded_channel().1
+    }
+
+    fn register(&self, _from: SocketAddr) -> mpsc::UnboundedReceiver<Bytes> {
+        let (s, r) = mpsc::unbounded_channel();
+        self.senders.lock().unwrap().push(s);
+        r
+    }
+
+    fn poll(&self) -> bool {
+        false
+    }
+}
+
+fn host_info() -> HostInfo {
+    HostInfo {
+        identity: HostIdentity {
+            name: "test_server".into(),
+            zone: Zone {
+                region: Region {
+                    cloud: None,
+                    name: "test-region".into(),
+                },
+                name: "a".into(),
+            },
+        },
+        address: "127.0.0.1:50056".parse().unwrap(),
+        warden_connection_epoch: 0,
+        labels: Default::default(),
+    }
+}
+
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        attempt_timeout: Duration::from_millis(20),
+        initial_backoff: Duration::from_millis(5),
+        max_backoff: Duration::from_millis(10),
+        jitter: 0.5,
+        retry_only_idempotent: true,
+    }
+}
+
+async fn setup(network: Arc<LossyNetwork>) -> Arc<RangeClient> {
+    let client = RangeClient::new(network, host_info(), None, retry_policy()).await;
+    RangeClient::start(
+        client.clone(),
+        tokio::runtime::Handle::current(),
+        CancellationToken::new(),
+    )
+    .await;
+    client
+}
+
+fn start_transaction(overall_timeout: Duration) -> Arc<TransactionInfo> {
+    let started = chrono::Utc::now();
+    Arc::new(TransactionInfo {
+        id: Uuid::new_v4(),
+        started,
+        first_started: started,
+        overall_timeout,
+        labels: BTreeMap::new(),
+        isolation: Default::default(),
+        snapshot_epoch: None,
+    })
+}
+
+fn range_id() -> FullRangeId {
+    FullRangeId {
+        keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+        range_id: Uuid::new_v4(),
+    }
+}
+
+#[tokio::test]
+async fn get_is_resent_until_attempts_run_out() {
+    let network = Arc::new(LossyNetwork::default());
+    let client = setup(network.clone()).await;
+    // The last attempt waits out the transaction's deadline, so keep it short.
+    let tx = start_transaction(Duration::from_millis(200));
+    let result = client
+        .get(tx, &range_id(), vec![Bytes::from_static(b"key")])
+        .await;
+    assert!(matches!(result, Err(Error::Timeout)));
+
+    // Every attempt is the same request, so a late response to any of them
+    // is taken as the response.
+    let sent = network.sent.lock().unwrap();
+    assert_eq!(sent.len(), 3);
+    assert!(sent.iter().all(|payload| *payload == sent[0]));
+}
+
+#[tokio::test]
+async fn prepare_is_not_resent() {
+    let network = Arc::new(LossyNetwork::default());
+    let client = setup(network.clone()).await;
+    let tx = start_transaction(Duration::from_millis(50));
+    let writes = vec![Record::new(b"key".to_vec(), b"value".to_vec())];
+    let result = client
+        .prepare_transaction(tx, &range_id(), false, &writes, &[], &[], &[])
+        .await;
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert_eq!(network.sent.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn retries_stop_at_the_transaction_deadline() {
+    let network = Arc::new(LossyNetwork::default());
+    let client = setup(network.clone()).await;
+    // Too short for a second attempt to fit in.
+    let tx = start_transaction(Duration::from_millis(10));
+    let result = client
+        .get(tx, &range_id(), vec![Bytes::from_static(b"key")])
+        .await;
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert_eq!(network.sent.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn retried_transactions_get_the_whole_timeout() {
+    let network = Arc::new(LossyNetwork::default());
+    let client = setup(network.clone()).await;
+    // A retry of a transaction whose first attempt started longer ago than
+    // the timeout, which only orders it against other transactions.
+    let overall_timeout = Duration::from_millis(200);
+    let tx = Arc::new(TransactionInfo {
+        first_started: chrono::Utc::now() - overall_timeout * 2,
+        ..(*start_transaction(overall_timeout)).clone()
+    });
+    let result = client
+        .get(tx, &range_id(), vec![Bytes::from_static(b"key")])
+        .await;
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert_eq!(network.sent.lock().unwrap().len(), 3);
+}
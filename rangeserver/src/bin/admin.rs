@@ -0,0 +1,638 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use prost::Message;
+use proto::profiling::{profiler_client::ProfilerClient, GetMemoryStatsRequest, ProfileCpuRequest};
+use proto::rangeserver::{
+    range_server_client::RangeServerClient, ChecksumReport, CleanupOrphanedPreparesRequest,
+    CompactRangeRequest, ExportRangeSnapshotRequest, GetChecksumStatsRequest,
+    GetCompactionStatsRequest, GetConflictStatsRequest, GetLockTableOccupancyRequest,
+    GetReadStatsRequest, GetVersionsRequest, GetWriteStallStatusRequest,
+    ListInFlightTransactionsRequest, RangeId, SetRangeFrozenRequest, SplitRangeRequest,
+    TransactionOutcome, VerifyRangeChecksumsRequest,
+};
+use rangeserver::read_stats::{ReadHint, ReadStats, DEFAULT_MIN_GETS};
+
+#[derive(Parser, Debug)]
+#[command(name = "rangeserver-admin")]
+#[command(about = "Inspects the state of a range server", long_about = None)]
+struct Args {
+    /// The proto server address of the range server.
+    #[arg(long, default_value = "127.0.0.1:50054")]
+    address: String,
+
+    /// The `admin.token` of the process, needed by the profiling commands.
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists the transactions holding locks or prepared on a loaded range.
+    ListInFlightTransactions {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+    },
+    /// Lists the stored versions of a key, newest first.
+    GetVersions {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        /// The key, as UTF-8.
+        #[arg(long)]
+        key: String,
+        #[arg(long, default_value_t = 10)]
+        limit: u32,
+    },
+    /// Reports how full the lock table of a loaded range is.
+    LockTableOccupancy {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+    },
+    /// Writes a snapshot of a loaded range to a file, as a RangeSnapshot
+    /// protobuf message (see rangeserver.proto).
+    ExportRangeSnapshot {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Reports the conflicts seen on a loaded range, most conflicted key
+    /// prefixes first, then by transaction label.
+    ConflictStats {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        /// Only print this many prefixes, and this many labels.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Reports how many stored versions of a loaded range are dead.
+    CompactionStats {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+    },
+    /// Removes the dead versions of a loaded range from storage, e.g. to
+    /// reclaim space after bulk deletions.
+    CompactRange {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        /// Keep the versions of this many of the most recent epochs.
+        #[arg(long, default_value_t = 0)]
+        retain_epochs: u64,
+    },
+    /// Splits a loaded range in two at a key. The new ranges stay on the same
+    /// range server.
+    SplitRange {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        /// The first key of the second new range, as UTF-8.
+        #[arg(long)]
+        split_key: String,
+    },
+    /// Reports whether writes to the storage of a loaded range are stalled.
+    WriteStallStatus {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+    },
+    /// Stops a loaded range from taking new transactions, e.g. to isolate it
+    /// during an incident while keeping its state around for diagnosis.
+    /// Transactions already prepared on it can still finish.
+    FreezeRange {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        /// Let the range take new transactions again instead.
+        #[arg(long)]
+        unfreeze: bool,
+    },
+    /// Resolves the transactions left prepared on a loaded range for longer
+    /// than a threshold by their outcome in the transaction state store, for
+    /// when their coordinators never finished them. Undecided transactions
+    /// get aborted.
+    CleanupOrphanedPrepares {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        #[arg(long, default_value_t = 60)]
+        older_than_secs: u64,
+        /// Only print what would be done.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Checksums the records of a loaded range in storage, and checks the
+    /// records the prefetch buffer holds for it against them.
+    VerifyChecksums {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+        /// Print at most this many of the keys that differ.
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Reports the checksum verifications run on a loaded range.
+    ChecksumStats {
+        #[arg(long)]
+        keyspace_id: String,
+        #[arg(long)]
+        range_id: String,
+    },
+    /// Reports how the gets served by the loaded ranges were answered, from
+    /// the prefetch buffer or from storage, ranges reading storage the most
+    /// first. Flags the ranges that prefetching more, or keeping prefetched
+    /// values cached for longer, would have read storage less.
+    ReadEfficiencyReport {
+        /// Only flag ranges that served at least this many gets.
+        #[arg(long, default_value_t = DEFAULT_MIN_GETS)]
+        min_gets: u64,
+        /// Only print this many ranges.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Samples the CPU of the process and writes a pprof profile to a file.
+    /// Also works against the proto address of a frontend.
+    ProfileCpu {
+        #[arg(long, default_value_t = 10)]
+        seconds: u32,
+        /// Samples per second, the server's default if unset.
+        #[arg(long, default_value_t = 0)]
+        frequency: u32,
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Reports the memory use of the process. Also works against the proto
+    /// address of a frontend.
+    MemoryStats,
+}
+
+fn with_admin_token<T>(message: T, token: &Option<String>) -> Result<tonic::Request<T>, String> {
+    let mut request = tonic::Request::new(message);
+    if let Some(token) = token {
+        let value = format!("Bearer {}", token)
+            .parse()
+            .map_err(|_| "admin token is not a valid header value".to_string())?;
+        request.metadata_mut().insert("authorization", value);
+    }
+    Ok(request)
+}
+
+fn format_ratio(ratio: Option<f64>) -> String {
+    ratio.map_or("-".to_string(), |ratio| format!("{:.2}", ratio))
+}
+
+fn hint_text(hint: ReadHint) -> &'static str {
+    match hint {
+        ReadHint::PrefetchMore => "most gets miss the prefetch buffer, prefetch their keys",
+        ReadHint::PrefetchedValuesGoUnread => {
+            "most prefetched values go unread, cache them longer or prefetch less"
+        }
+    }
+}
+
+fn print_checksum_report(report: &ChecksumReport) {
+    let checksum: String = report
+        .storage_checksum
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    println!(
+        "verified_at_us={} storage_checksum={} keys={} cached_keys={} divergent_keys={}",
+        report.verified_at_us, checksum, report.keys, report.cached_keys, report.divergent_keys
+    );
+    for key in &report.divergent_key_samples {
+        println!("{:?}", String::from_utf8_lossy(key));
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let address = format!("http://{}", args.address);
+    let mut client = RangeServerClient::connect(address.clone()).await?;
+    match args.command {
+        Command::ListInFlightTransactions {
+            keyspace_id,
+            range_id,
+        } => {
+            let response = client
+                .list_in_flight_transactions(ListInFlightTransactionsRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            for tx in response.transactions {
+                println!(
+                    "{} age_us={} locks={} prepared={} prepared_age_us={} labels={:?}",
+                    tx.transaction_id,
+                    tx.age_us.map_or("-".to_string(), |age| age.to_string()),
+                    tx.lock_count,
+                    tx.prepared,
+                    tx.prepared_age_us
+                        .map_or("-".to_string(), |age| age.to_string()),
+                    tx.labels
+                );
+            }
+        }
+        Command::GetVersions {
+            keyspace_id,
+            range_id,
+            key,
+            limit,
+        } => {
+            let response = client
+                .get_versions(GetVersionsRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                    key: key.into_bytes(),
+                    limit,
+                })
+                .await?
+                .into_inner();
+            for version in response.versions {
+                let value = match version.value_hash {
+                    _ if version.is_tombstone => "tombstone".to_string(),
+                    Some(hash) => format!("value_hash={:016x}", hash),
+                    None => "value_hash=-".to_string(),
+                };
+                println!(
+                    "epoch={} transaction_id={} {}",
+                    version.epoch,
+                    version.transaction_id.as_deref().unwrap_or("-"),
+                    value
+                );
+            }
+        }
+        Command::LockTableOccupancy {
+            keyspace_id,
+            range_id,
+        } => {
+            let occupancy = client
+                .get_lock_table_occupancy(GetLockTableOccupancyRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            println!(
+                "holder_age_us={} waiters={} waiting_for_release={} spilled_waiters={} rejected={}",
+                occupancy
+                    .holder_age_us
+                    .map_or("-".to_string(), |age| age.to_string()),
+                occupancy.waiters,
+                occupancy.waiting_for_release,
+                occupancy.spilled_waiters,
+                occupancy.rejected
+            );
+        }
+        Command::ExportRangeSnapshot {
+            keyspace_id,
+            range_id,
+            output,
+        } => {
+            let snapshot = client
+                .export_range_snapshot(ExportRangeSnapshotRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            std::fs::write(&output, snapshot.encode_to_vec())?;
+            println!(
+                "wrote {} records and {} prepared transactions to {}",
+                snapshot.records.len(),
+                snapshot.prepared_transactions.len(),
+                output.display()
+            );
+        }
+        Command::ConflictStats {
+            keyspace_id,
+            range_id,
+            limit,
+        } => {
+            let stats = client
+                .get_conflict_stats(GetConflictStatsRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            let rows = stats
+                .prefixes
+                .into_iter()
+                .take(limit)
+                .map(|p| (String::from_utf8_lossy(&p.prefix).into_owned(), p.counts))
+                .chain(std::iter::once(("<other>".to_string(), stats.other)))
+                .chain(
+                    stats
+                        .labels
+                        .into_iter()
+                        .take(limit)
+                        .map(|l| (format!("{}={}", l.key, l.value), l.counts)),
+                );
+            for (prefix, counts) in rows {
+                let counts = counts.unwrap_or_default();
+                println!(
+                    "{:?} wait_die={} lock_lost={} read_conflicts={} overloaded={}",
+                    prefix,
+                    counts.wait_die,
+                    counts.lock_lost,
+                    counts.read_conflicts,
+                    counts.overloaded
+                );
+            }
+        }
+        Command::CompactionStats {
+            keyspace_id,
+            range_id,
+        } => {
+            let stats = client
+                .get_compaction_stats(GetCompactionStatsRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            println!(
+                "keys={} live_keys={} versions={} tombstones={} reclaimable_versions={} compactions={} purged_versions={}",
+                stats.keys,
+                stats.live_keys,
+                stats.versions,
+                stats.tombstones,
+                stats.reclaimable_versions,
+                stats.compactions,
+                stats.purged_versions
+            );
+        }
+        Command::CompactRange {
+            keyspace_id,
+            range_id,
+            retain_epochs,
+        } => {
+            let response = client
+                .compact_range(CompactRangeRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                    retain_epochs,
+                })
+                .await?
+                .into_inner();
+            println!(
+                "removed {} versions of {} keys up to epoch {}",
+                response.purged_versions, response.purged_keys, response.horizon_epoch
+            );
+        }
+        Command::SplitRange {
+            keyspace_id,
+            range_id,
+            split_key,
+        } => {
+            let response = client
+                .split_range(SplitRangeRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                    split_key: split_key.into_bytes(),
+                })
+                .await?
+                .into_inner();
+            let bound = |b: &Option<Vec<u8>>| match b {
+                None => "unbounded".to_string(),
+                Some(b) => format!("{:?}", String::from_utf8_lossy(b)),
+            };
+            for range in response.new_ranges {
+                println!(
+                    "{} [{}, {})",
+                    range.range_id,
+                    bound(&range.lower_bound_inclusive),
+                    bound(&range.upper_bound_exclusive)
+                );
+            }
+        }
+        Command::WriteStallStatus {
+            keyspace_id,
+            range_id,
+        } => {
+            let status = client
+                .get_write_stall_status(GetWriteStallStatusRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            println!(
+                "stalled_for_us={} last_apply_latency_us={} stalls={}",
+                status
+                    .stalled_for_us
+                    .map_or("-".to_string(), |us| us.to_string()),
+                status.last_apply_latency_us,
+                status.stalls
+            );
+        }
+        Command::FreezeRange {
+            keyspace_id,
+            range_id,
+            unfreeze,
+        } => {
+            let frozen = !unfreeze;
+            let response = client
+                .set_range_frozen(SetRangeFrozenRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                    frozen,
+                })
+                .await?
+                .into_inner();
+            println!(
+                "frozen={} was_frozen={} prepared_transactions={}",
+                frozen, response.was_frozen, response.prepared_transactions
+            );
+        }
+        Command::VerifyChecksums {
+            keyspace_id,
+            range_id,
+            limit,
+        } => {
+            let report = client
+                .verify_range_checksums(VerifyRangeChecksumsRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                    max_divergent_keys: limit,
+                })
+                .await?
+                .into_inner();
+            print_checksum_report(&report);
+        }
+        Command::ChecksumStats {
+            keyspace_id,
+            range_id,
+        } => {
+            let stats = client
+                .get_checksum_stats(GetChecksumStatsRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                })
+                .await?
+                .into_inner();
+            println!(
+                "verifications={} divergences={}",
+                stats.verifications, stats.divergences
+            );
+            if let Some(last) = stats.last {
+                print_checksum_report(&last);
+            }
+        }
+        Command::CleanupOrphanedPrepares {
+            keyspace_id,
+            range_id,
+            older_than_secs,
+            dry_run,
+        } => {
+            let response = client
+                .cleanup_orphaned_prepares(CleanupOrphanedPreparesRequest {
+                    range: Some(RangeId {
+                        keyspace_id,
+                        range_id,
+                    }),
+                    older_than_us: older_than_secs.saturating_mul(1_000_000),
+                    dry_run,
+                })
+                .await?
+                .into_inner();
+            for prepare in &response.prepares {
+                let outcome = match prepare.outcome() {
+                    TransactionOutcome::Undecided => "undecided".to_string(),
+                    TransactionOutcome::Aborted => "aborted".to_string(),
+                    TransactionOutcome::Committed => {
+                        format!(
+                            "committed epoch={}",
+                            prepare.commit_epoch.unwrap_or_default()
+                        )
+                    }
+                };
+                println!(
+                    "{} prepared_age_us={} {}",
+                    prepare.transaction_id, prepare.prepared_age_us, outcome
+                );
+            }
+            println!(
+                "{} {} orphaned prepares",
+                if dry_run { "found" } else { "resolved" },
+                response.prepares.len()
+            );
+        }
+        Command::ReadEfficiencyReport { min_gets, limit } => {
+            let mut ranges = client
+                .get_read_stats(GetReadStatsRequest { ranges: Vec::new() })
+                .await?
+                .into_inner()
+                .ranges;
+            ranges.sort_by_key(|r| std::cmp::Reverse(r.storage_reads));
+            for range in ranges.into_iter().take(limit) {
+                let stats = ReadStats {
+                    gets: range.gets,
+                    cache_hits: range.cache_hits,
+                    storage_reads: range.storage_reads,
+                    prefetches: range.prefetches,
+                    prefetch_storage_reads: range.prefetch_storage_reads,
+                };
+                let range_id = range.range.unwrap_or_default();
+                println!(
+                    "{}/{} gets={} cache_hit_ratio={} storage_reads_per_get={} prefetches={} prefetch_storage_reads={}",
+                    range_id.keyspace_id,
+                    range_id.range_id,
+                    stats.gets,
+                    format_ratio(stats.cache_hit_ratio()),
+                    format_ratio(stats.storage_reads_per_get()),
+                    stats.prefetches,
+                    stats.prefetch_storage_reads
+                );
+                for hint in stats.hints(min_gets) {
+                    println!("  {}", hint_text(hint));
+                }
+            }
+        }
+        Command::ProfileCpu {
+            seconds,
+            frequency,
+            output,
+        } => {
+            let mut profiler = ProfilerClient::connect(address).await?;
+            let request = ProfileCpuRequest {
+                duration_ms: seconds.saturating_mul(1000),
+                frequency_hz: frequency,
+            };
+            let profile = profiler
+                .profile_cpu(with_admin_token(request, &args.admin_token)?)
+                .await?
+                .into_inner()
+                .profile;
+            std::fs::write(&output, &profile)?;
+            println!(
+                "wrote {} byte profile to {}",
+                profile.len(),
+                output.display()
+            );
+        }
+        Command::MemoryStats => {
+            let mut profiler = ProfilerClient::connect(address).await?;
+            let stats = profiler
+                .get_memory_stats(with_admin_token(
+                    GetMemoryStatsRequest {},
+                    &args.admin_token,
+                )?)
+                .await?
+                .into_inner();
+            println!(
+                "resident_bytes={} peak_resident_bytes={} virtual_bytes={}",
+                stats.resident_bytes, stats.peak_resident_bytes, stats.virtual_bytes
+            );
+        }
+    }
+    Ok(())
+}
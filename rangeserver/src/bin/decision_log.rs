@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use flatbuf::rangeserver_flatbuffers::range_server::PrepareRequest;
+use prost::Message;
+use proto::rangeserver::RangeSnapshot;
+use rangeserver::decision_log::{self, Event};
+
+#[derive(Parser, Debug)]
+#[command(name = "rangeserver-decision-log")]
+#[command(
+    about = "Reads the decision logs of ranges and replays them on top of range snapshots",
+    long_about = None
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints the entries of a range's log directory, oldest first.
+    Dump {
+        /// The directory the range logs to, `<keyspace id>/<range id>` under
+        /// the configured `decision_log.directory`.
+        #[arg(long)]
+        log: PathBuf,
+    },
+    /// Applies the commits of a range's log on top of a snapshot of the range
+    /// written by `rangeserver-admin export-range-snapshot`, reports anything
+    /// unexpected in its history, and writes the resulting snapshot.
+    Replay {
+        #[arg(long)]
+        log: PathBuf,
+        #[arg(long)]
+        snapshot: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+fn describe(event: &Event) -> String {
+    match event {
+        Event::Prepared {
+            highest_known_epoch,
+            prepare_request,
+        } => match flatbuffers::root::<PrepareRequest>(prepare_request) {
+            Ok(prepare) => format!(
+                "prepared highest_known_epoch={} puts={} deletes={}",
+                highest_known_epoch,
+                prepare.puts().map_or(0, |p| p.len()),
+                prepare.deletes().map_or(0, |d| d.len())
+            ),
+            Err(e) => format!("prepared with an unreadable request: {}", e),
+        },
+        Event::Committed { epoch } => format!("committed epoch={}", epoch),
+        Event::Aborted => "aborted".to_string(),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    match args.command {
+        Command::Dump { log } => {
+            let (range_id, entries) = decision_log::read_directory(&log)?;
+            println!("range {:?}", range_id);
+            for entry in entries {
+                println!(
+                    "{} {} {}",
+                    entry.logged_at.to_rfc3339(),
+                    entry.transaction_id,
+                    describe(&entry.event)
+                );
+            }
+        }
+        Command::Replay {
+            log,
+            snapshot,
+            output,
+        } => {
+            let (range_id, entries) = decision_log::read_directory(&log)?;
+            let snapshot = RangeSnapshot::decode(std::fs::read(&snapshot)?.as_slice())?;
+            if let Some(range) = &snapshot.range {
+                if range.keyspace_id != range_id.keyspace_id.id.to_string()
+                    || range.range_id != range_id.range_id.to_string()
+                {
+                    return Err(format!(
+                        "the snapshot is of range {:?}, the log of range {:?}",
+                        range, range_id
+                    )
+                    .into());
+                }
+            }
+            let replay = decision_log::replay(snapshot, &entries);
+            for finding in &replay.findings {
+                println!(
+                    "{} {} {:?}",
+                    finding.logged_at.to_rfc3339(),
+                    finding.transaction_id,
+                    finding.anomaly
+                );
+            }
+            std::fs::write(&output, replay.snapshot.encode_to_vec())?;
+            println!(
+                "replayed {} entries: {} commits applied, {} already in the snapshot, {} findings; wrote {} records and {} prepared transactions to {}",
+                entries.len(),
+                replay.applied,
+                replay.already_in_snapshot,
+                replay.findings.len(),
+                replay.snapshot.records.len(),
+                replay.snapshot.prepared_transactions.len(),
+                output.display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,275 @@
+#[cfg(feature = "rocksdb")]
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use clap::Parser;
+use common::{full_range_id::FullRangeId, key_range::KeyRange, keyspace_id::KeyspaceId};
+use proto::rangeserver::{range_server_client::RangeServerClient, RangeId, SetRangeFrozenRequest};
+use proto::universe::{
+    get_keyspace_info_request::KeyspaceInfoSearchField, universe_client::UniverseClient,
+    GetKeyspaceInfoRequest, Keyspace, SetKeyspacePlacementRequest,
+};
+use rangeserver::storage::{cassandra::Cassandra, migration::RangeMigration, Storage};
+use tonic::transport::Channel;
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(name = "rangeserver-migrate")]
+#[command(
+    about = "Moves the ranges of a keyspace to another storage backend while they keep being served",
+    long_about = None
+)]
+struct Args {
+    /// The proto server address of the universe.
+    #[arg(long)]
+    universe_address: String,
+
+    #[arg(long)]
+    keyspace_id: String,
+
+    /// A node of the Cassandra cluster the ranges are stored in now.
+    #[arg(long, default_value = "127.0.0.1:9042")]
+    source_cassandra: String,
+
+    /// A node of the Cassandra cluster to move the ranges to.
+    #[arg(long)]
+    destination_cassandra: Option<String>,
+
+    /// The RocksDB directory to move the ranges to.
+    #[cfg(feature = "rocksdb")]
+    #[arg(long)]
+    destination_rocksdb: Option<PathBuf>,
+
+    /// The proto server address of a range server serving the ranges from
+    /// the source now. Can be repeated, and should list all of them.
+    #[arg(long = "range-server")]
+    range_servers: Vec<String>,
+
+    /// The label, as key=value, of the range servers serving ranges from the
+    /// destination. Cutting over requires it of the keyspace, so that the
+    /// warden moves the ranges there.
+    #[arg(long, value_parser = parse_label)]
+    destination_label: (String, String),
+
+    /// Stop catching up once a pass copies at most this many versions of a
+    /// range.
+    #[arg(long, default_value_t = 1000)]
+    small_enough: usize,
+
+    #[arg(long, default_value_t = 10)]
+    max_passes: u32,
+
+    /// How long the ranges may stay frozen waiting for their prepared
+    /// transactions to finish before giving up on cutting over.
+    #[arg(long, default_value_t = 30)]
+    freeze_timeout_secs: u64,
+
+    /// Only copy the ranges and catch up, leaving them served from the
+    /// source. Running again picks up from scratch.
+    #[arg(long)]
+    no_cut_over: bool,
+}
+
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    label
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected key=value, got {}", label))
+}
+
+type DynamicErr = Box<dyn std::error::Error>;
+
+// A range frozen at its range server for the cutover.
+struct FrozenRange {
+    client: RangeServerClient<Channel>,
+    range: RangeId,
+}
+
+async fn set_frozen(
+    client: &mut RangeServerClient<Channel>,
+    range: &RangeId,
+    frozen: bool,
+) -> Result<u32, tonic::Status> {
+    let response = client
+        .set_range_frozen(SetRangeFrozenRequest {
+            range: Some(range.clone()),
+            frozen,
+        })
+        .await?;
+    Ok(response.into_inner().prepared_transactions)
+}
+
+async fn unfreeze_all(frozen: &mut [FrozenRange]) {
+    for range in frozen {
+        if let Err(e) = set_frozen(&mut range.client, &range.range, false).await {
+            eprintln!("failed to unfreeze range {}: {}", range.range.range_id, e);
+        }
+    }
+}
+
+// Freezes each range at whichever range server has it loaded, and waits for
+// the transactions prepared on them to finish. Ranges loaded nowhere take no
+// commits, so there is nothing to freeze for them.
+async fn freeze_ranges(
+    range_servers: &[String],
+    ranges: &[FullRangeId],
+    timeout: Duration,
+) -> Result<Vec<FrozenRange>, DynamicErr> {
+    let mut clients = Vec::new();
+    for address in range_servers {
+        clients.push(RangeServerClient::connect(format!("http://{}", address)).await?);
+    }
+    let mut frozen = Vec::new();
+    for range_id in ranges {
+        let range = RangeId {
+            keyspace_id: range_id.keyspace_id.id.to_string(),
+            range_id: range_id.range_id.to_string(),
+        };
+        for client in &clients {
+            let mut client = client.clone();
+            if set_frozen(&mut client, &range, true).await.is_ok() {
+                frozen.push(FrozenRange { client, range });
+                break;
+            }
+        }
+    }
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut prepared = 0;
+        let mut moved = None;
+        for range in frozen.iter_mut() {
+            match set_frozen(&mut range.client, &range.range, true).await {
+                Ok(count) => prepared += count,
+                Err(e) => {
+                    moved = Some(format!(
+                        "range {} moved while freezing: {}",
+                        range.range.range_id, e
+                    ));
+                    break;
+                }
+            }
+        }
+        if let Some(e) = moved {
+            unfreeze_all(&mut frozen).await;
+            return Err(e.into());
+        }
+        if prepared == 0 {
+            return Ok(frozen);
+        }
+        if Instant::now() >= deadline {
+            unfreeze_all(&mut frozen).await;
+            return Err(format!(
+                "{} transactions still prepared after {:?}, giving up",
+                prepared, timeout
+            )
+            .into());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+async fn migrate<D: Storage>(
+    args: &Args,
+    source: &Cassandra,
+    destination: &D,
+) -> Result<(), DynamicErr> {
+    let keyspace_id = KeyspaceId::new(Uuid::parse_str(&args.keyspace_id)?);
+    let mut universe = UniverseClient::connect(format!("http://{}", args.universe_address)).await?;
+    let keyspace_info = universe
+        .get_keyspace_info(GetKeyspaceInfoRequest {
+            keyspace_info_search_field: Some(KeyspaceInfoSearchField::KeyspaceId(
+                args.keyspace_id.clone(),
+            )),
+        })
+        .await?
+        .into_inner()
+        .keyspace_info
+        .ok_or("keyspace not found")?;
+    let bound = |bound: &Vec<u8>| Some(Bytes::from(bound.clone())).filter(|b| !b.is_empty());
+    let mut ranges = Vec::new();
+    for range in &keyspace_info.base_key_ranges {
+        let range_id = FullRangeId {
+            keyspace_id,
+            range_id: Uuid::parse_str(&range.base_range_uuid)?,
+        };
+        let key_range = KeyRange {
+            lower_bound_inclusive: bound(&range.lower_bound_inclusive),
+            upper_bound_exclusive: bound(&range.upper_bound_exclusive),
+        };
+        ranges.push((
+            RangeMigration::new(source, destination, range_id),
+            range_id,
+            key_range,
+        ));
+    }
+
+    for (migration, range_id, _) in ranges.iter_mut() {
+        let last_pass = migration
+            .catch_up(args.small_enough, args.max_passes)
+            .await?;
+        let progress = migration.progress();
+        println!(
+            "range {} copied versions={} passes={} last_pass={}",
+            range_id.range_id, progress.versions_copied, progress.passes, last_pass
+        );
+    }
+    if args.no_cut_over {
+        return Ok(());
+    }
+
+    let range_ids: Vec<_> = ranges.iter().map(|(_, range_id, _)| *range_id).collect();
+    let mut frozen = freeze_ranges(
+        &args.range_servers,
+        &range_ids,
+        Duration::from_secs(args.freeze_timeout_secs),
+    )
+    .await?;
+    println!("froze {} of {} ranges", frozen.len(), ranges.len());
+    for (migration, range_id, key_range) in ranges.iter_mut() {
+        if let Err(e) = migration.cut_over(key_range.clone()).await {
+            unfreeze_all(&mut frozen).await;
+            return Err(format!("failed to cut over range {}: {}", range_id.range_id, e).into());
+        }
+    }
+
+    // The warden moves the ranges to the range servers serving them from the
+    // destination, where they are not frozen. The frozen ranges have nothing
+    // left to drain, so this takes no longer than loading them there.
+    let mut placement = keyspace_info.placement.unwrap_or_default();
+    let (key, value) = args.destination_label.clone();
+    placement.required_labels.insert(key, value);
+    let placed = universe
+        .set_keyspace_placement(SetKeyspacePlacementRequest {
+            keyspace: Some(Keyspace {
+                namespace: keyspace_info.namespace.clone(),
+                name: keyspace_info.name.clone(),
+            }),
+            placement: Some(placement),
+        })
+        .await;
+    if let Err(e) = placed {
+        unfreeze_all(&mut frozen).await;
+        return Err(format!("failed to move the keyspace to the destination: {}", e).into());
+    }
+    println!("cut over {} ranges", ranges.len());
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), DynamicErr> {
+    let args = Args::parse();
+    let source = Cassandra::new(args.source_cassandra.clone()).await;
+    #[cfg(feature = "rocksdb")]
+    if let Some(path) = &args.destination_rocksdb {
+        let destination = rangeserver::storage::rocksdb::RocksDbStorage::open(path)?;
+        return migrate(&args, &source, &destination).await;
+    }
+    match &args.destination_cassandra {
+        Some(address) => {
+            let destination = Cassandra::new(address.clone()).await;
+            migrate(&args, &source, &destination).await
+        }
+        None => Err("no destination given".into()),
+    }
+}
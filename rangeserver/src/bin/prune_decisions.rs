@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use common::clock::SystemClock;
+use proto::rangeserver::{range_server_client::RangeServerClient, GetOldestPreparedRequest};
+use tx_state_store::client::{Client as TxStateStoreClient, GcConfig, RetentionPolicy};
+
+#[derive(Parser, Debug)]
+#[command(name = "rangeserver-prune-decisions")]
+#[command(
+    about = "Deletes the transaction decisions no range may still ask the transaction state store for",
+    long_about = None
+)]
+struct Args {
+    /// A node of the Cassandra cluster of the transaction state store.
+    #[arg(long, default_value = "127.0.0.1:9042")]
+    cassandra: String,
+
+    /// The proto server address of a range server. Must be repeated for
+    /// every range server of the cluster, as each reports the transactions
+    /// prepared on its ranges.
+    #[arg(long = "range-server", required = true)]
+    range_servers: Vec<String>,
+
+    /// The longest overall timeout transactions are started with.
+    #[arg(long, default_value_t = 60)]
+    max_transaction_timeout_secs: u64,
+
+    /// How long a range may stay unloaded before another range server
+    /// recovers its prepared transactions.
+    #[arg(long, default_value_t = 3600)]
+    max_recovery_delay_secs: u64,
+
+    /// How far apart the clocks of range servers and Cassandra nodes may be.
+    #[arg(long, default_value_t = 60)]
+    max_clock_skew_secs: u64,
+
+    /// How long decisions are kept however early range servers are done
+    /// with them.
+    #[arg(long, default_value_t = 0)]
+    retention_secs: u64,
+
+    /// Keep running, pruning decisions this often, rather than once.
+    #[arg(long)]
+    interval_secs: Option<u64>,
+
+    /// Only print the horizon decisions would be pruned before.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+type DynamicErr = Box<dyn std::error::Error>;
+
+// Asks every range server for the oldest transaction prepared on its ranges,
+// and returns how long ago it prepared, None if there is none. Fails unless
+// every range of every range server reported, as any of them may be holding
+// on to an older one.
+async fn oldest_prepared_age(range_servers: &[String]) -> Result<Option<Duration>, DynamicErr> {
+    let mut oldest = None;
+    for address in range_servers {
+        let mut client = RangeServerClient::connect(format!("http://{}", address)).await?;
+        let response = client
+            .get_oldest_prepared(GetOldestPreparedRequest {})
+            .await?
+            .into_inner();
+        if response.unreported_ranges > 0 {
+            return Err(format!(
+                "{} ranges of {} did not report their prepared transactions",
+                response.unreported_ranges, address
+            )
+            .into());
+        }
+        let age = response.oldest_prepared_age_us.map(Duration::from_micros);
+        println!(
+            "{}: ranges={} oldest_prepared_age={:?}",
+            address, response.ranges, age
+        );
+        oldest = oldest.into_iter().chain(age).max();
+    }
+    Ok(oldest)
+}
+
+// When the oldest transaction prepared on the range servers prepared.
+async fn oldest_prepared(range_servers: &[String]) -> Result<Option<DateTime<Utc>>, DynamicErr> {
+    let oldest_age = oldest_prepared_age(range_servers).await?;
+    let now = Utc::now();
+    match oldest_age {
+        None => Ok(None),
+        Some(age) => Ok(Some(now - chrono::Duration::from_std(age)?)),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), DynamicErr> {
+    let args = Args::parse();
+    let policy = RetentionPolicy {
+        max_transaction_timeout: Duration::from_secs(args.max_transaction_timeout_secs),
+        max_recovery_delay: Duration::from_secs(args.max_recovery_delay_secs),
+        max_clock_skew: Duration::from_secs(args.max_clock_skew_secs),
+        retention: Duration::from_secs(args.retention_secs),
+    };
+    // A dry run only prints the horizon once.
+    if let Some(interval_secs) = args.interval_secs.filter(|_| !args.dry_run) {
+        tracing_subscriber::fmt::init();
+        let tx_state_store = TxStateStoreClient::connect(args.cassandra).await;
+        let config = GcConfig {
+            interval: Duration::from_secs(interval_secs),
+            retention: policy,
+        };
+        let range_servers = args.range_servers;
+        tx_state_store
+            .run_gc(&SystemClock, config, || oldest_prepared(&range_servers))
+            .await;
+        return Ok(());
+    }
+    let oldest_prepared = oldest_prepared(&args.range_servers).await?;
+    let horizon = policy.horizon(Utc::now(), oldest_prepared);
+    println!("pruning decisions made before {}", horizon);
+    if args.dry_run {
+        return Ok(());
+    }
+    let tx_state_store = TxStateStoreClient::connect(args.cassandra).await;
+    let pruned = tx_state_store.prune_decisions(horizon).await?;
+    println!("pruned {} decisions", pruned);
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! Merkle-tree checksums of the records of a range, to check that two copies
+//! of the range's records agree without comparing them record by record. Keys
+//! are spread over a fixed number of buckets by their hash, so trees built
+//! over the same keys line up, and comparing two trees narrows a difference
+//! down to the buckets holding it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+// The tree has 2^DEPTH leaf buckets. A key's bucket is the first byte of its
+// hash, so this can't go past 8.
+const DEPTH: u32 = 8;
+const BUCKETS: usize = 1 << DEPTH;
+
+pub type Checksum = [u8; 32];
+
+/// What a checksum verification of a range found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChecksumReport {
+    pub verified_at: DateTime<Utc>,
+    /// Root of the tree over the newest value of each live key of the range
+    /// in storage.
+    pub storage_checksum: Checksum,
+    pub keys: u64,
+    /// Keys of the range the prefetch buffer holds, checked against storage.
+    pub cached_keys: u64,
+    /// Cached keys whose value differs from the one in storage.
+    pub divergent_keys: u64,
+    /// Up to the number of divergent keys asked for.
+    pub divergent_key_samples: Vec<Bytes>,
+}
+
+/// The checksum verifications run on a range since it was loaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChecksumStats {
+    pub verifications: u64,
+    /// Verifications that found divergent keys.
+    pub divergences: u64,
+    pub last: Option<ChecksumReport>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleTree {
+    // The root is at 1 and the children of node i at 2i and 2i + 1, so the
+    // leaves are the last BUCKETS nodes. Node 0 is unused.
+    nodes: Vec<Checksum>,
+}
+
+fn bucket(key: &[u8]) -> usize {
+    Sha256::digest(key)[0] as usize
+}
+
+impl MerkleTree {
+    /// Builds the tree over `records`, which must be in key order. A record
+    /// without a value stands for a key known to be absent, which checksums
+    /// differently from not listing the key at all.
+    pub fn build<'a>(records: impl IntoIterator<Item = (&'a [u8], Option<&'a [u8]>)>) -> Self {
+        let mut leaves: Vec<Sha256> = (0..BUCKETS).map(|_| Sha256::new()).collect();
+        for (key, value) in records {
+            let leaf = &mut leaves[bucket(key)];
+            leaf.update((key.len() as u64).to_be_bytes());
+            leaf.update(key);
+            match value {
+                None => leaf.update([0]),
+                Some(value) => {
+                    leaf.update([1]);
+                    leaf.update((value.len() as u64).to_be_bytes());
+                    leaf.update(value);
+                }
+            }
+        }
+        let mut nodes = vec![Checksum::default(); 2 * BUCKETS];
+        for (i, leaf) in leaves.into_iter().enumerate() {
+            nodes[BUCKETS + i] = leaf.finalize().into();
+        }
+        for i in (1..BUCKETS).rev() {
+            let mut node = Sha256::new();
+            node.update(nodes[2 * i]);
+            node.update(nodes[2 * i + 1]);
+            nodes[i] = node.finalize().into();
+        }
+        MerkleTree { nodes }
+    }
+
+    pub fn root(&self) -> Checksum {
+        self.nodes[1]
+    }
+
+    /// Returns the buckets whose records differ between the two trees,
+    /// descending only into the subtrees whose checksums differ.
+    fn divergent_buckets(&self, other: &MerkleTree) -> BTreeSet<usize> {
+        let mut buckets = BTreeSet::new();
+        let mut pending = vec![1];
+        while let Some(i) = pending.pop() {
+            if self.nodes[i] == other.nodes[i] {
+                continue;
+            }
+            if i >= BUCKETS {
+                buckets.insert(i - BUCKETS);
+            } else {
+                pending.extend([2 * i, 2 * i + 1]);
+            }
+        }
+        buckets
+    }
+}
+
+/// Checks the records of a range held in a cache against those in storage.
+/// `cached` maps each cached key to its value, or to None if the cache knows
+/// the key to be absent. Returns how many cached keys diverge from
+/// `stored`, and up to `limit` of them in key order.
+pub(crate) fn divergent_keys(
+    cached: &BTreeMap<Bytes, Option<Bytes>>,
+    stored: &BTreeMap<Bytes, Bytes>,
+    limit: usize,
+) -> (u64, Vec<Bytes>) {
+    let cached_tree = MerkleTree::build(cached.iter().map(|(k, v)| (&k[..], v.as_deref())));
+    let stored_tree = MerkleTree::build(
+        cached
+            .keys()
+            .map(|k| (&k[..], stored.get(k).map(|v| &v[..]))),
+    );
+    let buckets = cached_tree.divergent_buckets(&stored_tree);
+    let mut count = 0;
+    let mut samples = Vec::new();
+    for (key, value) in cached {
+        if !buckets.contains(&bucket(key)) || value.as_ref() == stored.get(key) {
+            continue;
+        }
+        count += 1;
+        if samples.len() < limit {
+            samples.push(key.clone());
+        }
+    }
+    (count, samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(entries: &[(&'static [u8], &'static [u8])]) -> BTreeMap<Bytes, Bytes> {
+        entries
+            .iter()
+            .map(|(k, v)| (Bytes::from_static(k), Bytes::from_static(v)))
+            .collect()
+    }
+
+    fn tree(records: &BTreeMap<Bytes, Bytes>) -> MerkleTree {
+        MerkleTree::build(records.iter().map(|(k, v)| (&k[..], Some(&v[..]))))
+    }
+
+    #[test]
+    fn root_depends_on_every_record() {
+        let base = records(&[(b"a", b"1"), (b"b", b"2"), (b"c", b"3")]);
+        assert_eq!(tree(&base).root(), tree(&base.clone()).root());
+        let changed = records(&[(b"a", b"1"), (b"b", b"x"), (b"c", b"3")]);
+        assert_ne!(tree(&base).root(), tree(&changed).root());
+        let missing = records(&[(b"a", b"1"), (b"c", b"3")]);
+        assert_ne!(tree(&base).root(), tree(&missing).root());
+        // Moving bytes between key and value changes the checksum too.
+        let shifted = records(&[(b"a", b"1"), (b"b2", b""), (b"c", b"3")]);
+        assert_ne!(tree(&base).root(), tree(&shifted).root());
+    }
+
+    #[test]
+    fn finds_cached_keys_diverging_from_storage() {
+        let stored = records(&[(b"a", b"1"), (b"b", b"2"), (b"c", b"3"), (b"d", b"4")]);
+        let mut cached: BTreeMap<Bytes, Option<Bytes>> = BTreeMap::from([
+            (Bytes::from_static(b"a"), Some(Bytes::from_static(b"1"))),
+            (Bytes::from_static(b"c"), Some(Bytes::from_static(b"3"))),
+            (Bytes::from_static(b"z"), None),
+        ]);
+        assert_eq!(divergent_keys(&cached, &stored, 10), (0, vec![]));
+
+        cached.insert(Bytes::from_static(b"c"), Some(Bytes::from_static(b"stale")));
+        // Cached as absent, but stored.
+        cached.insert(Bytes::from_static(b"d"), None);
+        // Cached, but deleted from storage.
+        cached.insert(Bytes::from_static(b"z"), Some(Bytes::from_static(b"26")));
+        assert_eq!(
+            divergent_keys(&cached, &stored, 10),
+            (
+                3,
+                vec![
+                    Bytes::from_static(b"c"),
+                    Bytes::from_static(b"d"),
+                    Bytes::from_static(b"z")
+                ]
+            )
+        );
+        assert_eq!(
+            divergent_keys(&cached, &stored, 1),
+            (3, vec![Bytes::from_static(b"c")])
+        );
+    }
+}
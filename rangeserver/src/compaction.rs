@@ -0,0 +1,214 @@
+//! Garbage collection of record versions. The storage layer keeps a version
+//! of each record per epoch it was written in, but reads only ever see the
+//! newest one, so the older versions only take up space. Likewise, reads
+//! don't see records whose newest version expired.
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+
+use crate::storage::{is_expired, RecordVersion};
+
+/// Versions from this many of the most recent epochs are never collected,
+/// since commits still being applied may write at those epochs.
+pub const MIN_RETAINED_EPOCHS: u64 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VersionStats {
+    /// Keys with at least one stored version, tombstones included.
+    pub keys: u64,
+    /// Keys whose newest version is not a tombstone.
+    pub live_keys: u64,
+    pub versions: u64,
+    pub tombstones: u64,
+    /// Versions a compaction keeping the minimum number of epochs would
+    /// remove.
+    pub reclaimable_versions: u64,
+}
+
+/// What the storage of a range holds, and what was reclaimed from it since
+/// the range was loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompactionStats {
+    pub versions: VersionStats,
+    pub compactions: u64,
+    pub purged_versions: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactionOutcome {
+    /// Versions at or below this epoch were collected.
+    pub horizon: u64,
+    pub purged_keys: u64,
+    pub purged_versions: u64,
+}
+
+/// What an expiry pass removed from a range.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExpiryOutcome {
+    pub expired_keys: u64,
+    pub purged_versions: u64,
+}
+
+/// What to remove from a range to collect the versions at or below
+/// `horizon` that no read can see anymore.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct CompactionPlan {
+    pub stats: VersionStats,
+    /// Each key paired with the epoch at or below which all its versions go.
+    pub purges: Vec<(Bytes, u64)>,
+    pub purged_versions: u64,
+}
+
+/// `versions` must be ordered by key and then newest first, as returned by
+/// `Storage::scan_versions`.
+///
+/// Of the versions of a key at or below the horizon, only the newest is kept,
+/// and not even that one if it is a tombstone.
+pub(crate) fn plan(versions: &[(Bytes, RecordVersion)], horizon: u64) -> CompactionPlan {
+    let mut plan = CompactionPlan::default();
+    for key_versions in versions.chunk_by(|(a, _), (b, _)| a == b) {
+        let key = &key_versions[0].0;
+        plan.stats.keys += 1;
+        plan.stats.versions += key_versions.len() as u64;
+        plan.stats.tombstones += key_versions
+            .iter()
+            .filter(|(_, v)| v.value.is_none())
+            .count() as u64;
+        if key_versions[0].1.value.is_some() {
+            plan.stats.live_keys += 1;
+        }
+        let Some(first_old) = key_versions.iter().position(|(_, v)| v.epoch <= horizon) else {
+            continue;
+        };
+        let newest_old = &key_versions[first_old].1;
+        let (up_to, purged) = if newest_old.value.is_none() {
+            (newest_old.epoch, key_versions.len() - first_old)
+        } else if let Some((_, next)) = key_versions.get(first_old + 1) {
+            (next.epoch, key_versions.len() - first_old - 1)
+        } else {
+            continue;
+        };
+        plan.purges.push((key.clone(), up_to));
+        plan.purged_versions += purged as u64;
+    }
+    plan.stats.reclaimable_versions = plan.purged_versions;
+    plan
+}
+
+/// Returns the keys whose newest version expired by `now`, each paired with
+/// the epoch of that version. All of a key's versions go, since purging only
+/// the expired one would bring back the older ones it hides. `versions` must
+/// be ordered as for `plan`.
+pub(crate) fn plan_expiry(
+    versions: &[(Bytes, RecordVersion)],
+    now: DateTime<Utc>,
+) -> (Vec<(Bytes, u64)>, ExpiryOutcome) {
+    let mut purges = Vec::new();
+    let mut outcome = ExpiryOutcome::default();
+    for key_versions in versions.chunk_by(|(a, _), (b, _)| a == b) {
+        let (key, newest) = &key_versions[0];
+        if newest.value.is_none() || !is_expired(newest.expires_at, now) {
+            continue;
+        }
+        purges.push((key.clone(), newest.epoch));
+        outcome.expired_keys += 1;
+        outcome.purged_versions += key_versions.len() as u64;
+    }
+    (purges, outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(
+        key: &'static [u8],
+        epoch: u64,
+        value: Option<&'static [u8]>,
+    ) -> (Bytes, RecordVersion) {
+        (
+            Bytes::from_static(key),
+            RecordVersion {
+                epoch,
+                transaction_id: None,
+                value: value.map(Bytes::from_static),
+                expires_at: None,
+            },
+        )
+    }
+
+    #[test]
+    fn keeps_newest_version_at_or_below_horizon() {
+        let versions = vec![
+            version(b"a", 9, Some(b"a9")),
+            version(b"a", 5, Some(b"a5")),
+            version(b"a", 3, Some(b"a3")),
+            version(b"a", 1, None),
+            // Nothing old enough to collect.
+            version(b"b", 8, Some(b"b8")),
+            version(b"b", 7, Some(b"b7")),
+            // A single old version is kept.
+            version(b"c", 2, Some(b"c2")),
+        ];
+        let plan = plan(&versions, 5);
+        assert_eq!(plan.purges, vec![(Bytes::from_static(b"a"), 3)]);
+        assert_eq!(plan.purged_versions, 2);
+        assert_eq!(
+            plan.stats,
+            VersionStats {
+                keys: 3,
+                live_keys: 3,
+                versions: 7,
+                tombstones: 1,
+                reclaimable_versions: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn drops_old_tombstones_entirely() {
+        let versions = vec![
+            version(b"a", 4, None),
+            version(b"a", 2, Some(b"a2")),
+            version(b"b", 6, Some(b"b6")),
+            version(b"b", 4, None),
+        ];
+        let plan = plan(&versions, 5);
+        assert_eq!(
+            plan.purges,
+            vec![(Bytes::from_static(b"a"), 4), (Bytes::from_static(b"b"), 4)]
+        );
+        assert_eq!(plan.purged_versions, 3);
+        assert_eq!(plan.stats.live_keys, 1);
+        assert_eq!(plan.stats.tombstones, 2);
+    }
+
+    #[test]
+    fn expires_keys_whose_newest_version_expired() {
+        let now = Utc::now();
+        let expiring = |key, epoch, expires_at| {
+            let (key, mut version) = version(key, epoch, Some(b"v"));
+            version.expires_at = Some(expires_at);
+            (key, version)
+        };
+        let versions = vec![
+            // The expired version hides an older one that never expires.
+            expiring(b"a", 5, now - chrono::Duration::seconds(1)),
+            version(b"a", 3, Some(b"a3")),
+            // Only older versions expired.
+            version(b"b", 6, Some(b"b6")),
+            expiring(b"b", 4, now - chrono::Duration::seconds(1)),
+            expiring(b"c", 7, now + chrono::Duration::seconds(1)),
+            version(b"d", 2, None),
+        ];
+        let (purges, outcome) = plan_expiry(&versions, now);
+        assert_eq!(purges, vec![(Bytes::from_static(b"a"), 5)]);
+        assert_eq!(
+            outcome,
+            ExpiryOutcome {
+                expired_keys: 1,
+                purged_versions: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Counts the conflicts each range sees, grouped by key prefix and by the
+//! labels of the transactions involved, so that application teams can find
+//! which of their entities and call sites are contended without going through
+//! the operators. Counts are kept in memory from when the range
+//! was loaded, and are advisory only.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use common::config::ConflictStatsConfig;
+
+use crate::error::Error;
+use crate::transaction_abort_reason::TransactionAbortReason;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConflictCounts {
+    /// Lock requests refused because an older transaction held the lock.
+    pub wait_die: u64,
+    /// Transactions that lost their lock before preparing.
+    pub lock_lost: u64,
+    /// Optimistic reads invalidated by a commit before the reader prepared.
+    pub read_conflicts: u64,
+    /// Lock requests refused because the lock table was full.
+    pub overloaded: u64,
+}
+
+impl ConflictCounts {
+    pub fn total(&self) -> u64 {
+        self.wait_die + self.lock_lost + self.read_conflicts + self.overloaded
+    }
+
+    fn add(&mut self, kind: Kind) {
+        match kind {
+            Kind::WaitDie => self.wait_die += 1,
+            Kind::LockLost => self.lock_lost += 1,
+            Kind::ReadConflict => self.read_conflicts += 1,
+            Kind::Overloaded => self.overloaded += 1,
+        }
+    }
+
+    fn merge(&mut self, other: &ConflictCounts) {
+        self.wait_die += other.wait_die;
+        self.lock_lost += other.lock_lost;
+        self.read_conflicts += other.read_conflicts;
+        self.overloaded += other.overloaded;
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConflictStats {
+    /// Most conflicted prefixes first.
+    pub prefixes: Vec<(Bytes, ConflictCounts)>,
+    /// Conflicts with no key to attribute them to, or on prefixes seen after
+    /// the tracker was full.
+    pub other: ConflictCounts,
+    /// The same conflicts again, by label of the transaction that ran into
+    /// them, most conflicted first. Conflicts of unlabelled transactions, or
+    /// on labels seen after the tracker was full, are not counted here.
+    pub labels: Vec<((String, String), ConflictCounts)>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    WaitDie,
+    LockLost,
+    ReadConflict,
+    Overloaded,
+}
+
+impl Kind {
+    fn of(error: &Error) -> Option<Kind> {
+        match error {
+            Error::TransactionAborted(TransactionAbortReason::Deadlock) => Some(Kind::WaitDie),
+            Error::TransactionAborted(TransactionAbortReason::TransactionLockLost) => {
+                Some(Kind::LockLost)
+            }
+            Error::TransactionAborted(TransactionAbortReason::ReadConflict) => {
+                Some(Kind::ReadConflict)
+            }
+            Error::Overloaded => Some(Kind::Overloaded),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    prefixes: HashMap<Bytes, ConflictCounts>,
+    other: ConflictCounts,
+    labels: HashMap<(String, String), ConflictCounts>,
+}
+
+pub(crate) struct ConflictTracker {
+    config: ConflictStatsConfig,
+    counts: Mutex<Counts>,
+}
+
+impl ConflictTracker {
+    pub fn new(config: ConflictStatsConfig) -> ConflictTracker {
+        ConflictTracker {
+            config,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        let prefix = &key[..key.len().min(self.config.prefix_len)];
+        match self
+            .config
+            .delimiter
+            .and_then(|d| prefix.iter().position(|b| *b == d))
+        {
+            Some(pos) => &prefix[..=pos],
+            None => prefix,
+        }
+    }
+
+    /// Counts `error` once against each distinct prefix of `keys`, and once
+    /// against each of `labels`, if it is a conflict. Other errors are
+    /// ignored.
+    pub fn record<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        labels: &BTreeMap<String, String>,
+        error: &Error,
+    ) {
+        let Some(kind) = Kind::of(error) else {
+            return;
+        };
+        let prefixes: HashSet<&[u8]> = keys.into_iter().map(|key| self.prefix(key)).collect();
+        let mut counts = self.counts.lock().unwrap();
+        if prefixes.is_empty() {
+            counts.other.add(kind);
+        }
+        for prefix in prefixes {
+            if let Some(c) = counts.prefixes.get_mut(prefix) {
+                c.add(kind);
+            } else if counts.prefixes.len() < self.config.max_prefixes {
+                let mut c = ConflictCounts::default();
+                c.add(kind);
+                counts.prefixes.insert(Bytes::copy_from_slice(prefix), c);
+            } else {
+                counts.other.add(kind);
+            }
+        }
+        for (key, value) in labels {
+            let label = (key.clone(), value.clone());
+            if let Some(c) = counts.labels.get_mut(&label) {
+                c.add(kind);
+            } else if counts.labels.len() < self.config.max_labels {
+                let mut c = ConflictCounts::default();
+                c.add(kind);
+                counts.labels.insert(label, c);
+            }
+        }
+    }
+
+    /// Adds counts kept by a previous tracker of the range, e.g. the one of
+    /// the process this one took over from. Prefixes that do not fit go to
+    /// `other`, just like new ones do.
+    pub fn restore(&self, stats: &ConflictStats) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.other.merge(&stats.other);
+        for (prefix, c) in &stats.prefixes {
+            if let Some(existing) = counts.prefixes.get_mut(prefix) {
+                existing.merge(c);
+            } else if counts.prefixes.len() < self.config.max_prefixes {
+                counts.prefixes.insert(prefix.clone(), *c);
+            } else {
+                counts.other.merge(c);
+            }
+        }
+        for (label, c) in &stats.labels {
+            if let Some(existing) = counts.labels.get_mut(label) {
+                existing.merge(c);
+            } else if counts.labels.len() < self.config.max_labels {
+                counts.labels.insert(label.clone(), *c);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ConflictStats {
+        let counts = self.counts.lock().unwrap();
+        let mut prefixes: Vec<_> = counts
+            .prefixes
+            .iter()
+            .map(|(prefix, c)| (prefix.clone(), *c))
+            .collect();
+        prefixes.sort_by(|(a, ac), (b, bc)| bc.total().cmp(&ac.total()).then_with(|| a.cmp(b)));
+        let mut labels: Vec<_> = counts
+            .labels
+            .iter()
+            .map(|(label, c)| (label.clone(), *c))
+            .collect();
+        labels.sort_by(|(a, ac), (b, bc)| bc.total().cmp(&ac.total()).then_with(|| a.cmp(b)));
+        ConflictStats {
+            prefixes,
+            other: counts.other,
+            labels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(prefix_len: usize, delimiter: Option<u8>, max_prefixes: usize) -> ConflictTracker {
+        ConflictTracker::new(ConflictStatsConfig {
+            prefix_len,
+            delimiter,
+            max_prefixes,
+            ..Default::default()
+        })
+    }
+
+    fn no_labels() -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn label(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    const WAIT_DIE: Error = Error::TransactionAborted(TransactionAbortReason::Deadlock);
+
+    #[test]
+    fn groups_by_prefix() {
+        let tracker = tracker(4, None, 16);
+        tracker.record([&b"user1"[..]], &no_labels(), &WAIT_DIE);
+        tracker.record(
+            [&b"user2"[..], &b"cart1"[..]],
+            &no_labels(),
+            &Error::Overloaded,
+        );
+        // Counted once even though both keys share the prefix.
+        tracker.record([&b"user1"[..], &b"user2"[..]], &no_labels(), &WAIT_DIE);
+        tracker.record(
+            [&b"ab"[..]],
+            &no_labels(),
+            &Error::TransactionAborted(TransactionAbortReason::ReadConflict),
+        );
+        // Not a conflict.
+        tracker.record([&b"user1"[..]], &no_labels(), &Error::KeyIsOutOfRange);
+        tracker.record(
+            [],
+            &no_labels(),
+            &Error::TransactionAborted(TransactionAbortReason::TransactionLockLost),
+        );
+
+        let stats = tracker.stats();
+        let prefixes: Vec<_> = stats.prefixes.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                Bytes::from_static(b"user"),
+                Bytes::from_static(b"ab"),
+                Bytes::from_static(b"cart"),
+            ]
+        );
+        assert_eq!(
+            stats.prefixes[0].1,
+            ConflictCounts {
+                wait_die: 2,
+                overloaded: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(stats.prefixes[1].1.read_conflicts, 1);
+        assert_eq!(stats.other.lock_lost, 1);
+        assert_eq!(stats.other.total(), 1);
+    }
+
+    #[test]
+    fn delimiter_shortens_prefix() {
+        let tracker = tracker(8, Some(b'/'), 16);
+        tracker.record(
+            [&b"user/1234/cart"[..], &b"user/99"[..]],
+            &no_labels(),
+            &WAIT_DIE,
+        );
+        tracker.record([&b"verylongkey"[..]], &no_labels(), &WAIT_DIE);
+        let stats = tracker.stats();
+        assert_eq!(
+            stats.prefixes,
+            vec![
+                (
+                    Bytes::from_static(b"user/"),
+                    ConflictCounts {
+                        wait_die: 1,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    Bytes::from_static(b"verylong"),
+                    ConflictCounts {
+                        wait_die: 1,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn overflow_goes_to_other() {
+        let tracker = tracker(1, None, 2);
+        for key in [&b"a"[..], b"b", b"c", b"a"] {
+            tracker.record([key], &no_labels(), &WAIT_DIE);
+        }
+        let stats = tracker.stats();
+        assert_eq!(stats.prefixes.len(), 2);
+        assert_eq!(stats.prefixes[0].0, Bytes::from_static(b"a"));
+        assert_eq!(stats.prefixes[0].1.wait_die, 2);
+        assert_eq!(stats.other.wait_die, 1);
+    }
+
+    #[test]
+    fn restore_merges_counts() {
+        let previous = tracker(1, None, 2);
+        for key in [&b"a"[..], b"b", b"c"] {
+            previous.record([key], &no_labels(), &WAIT_DIE);
+        }
+        let tracker = tracker(1, None, 2);
+        tracker.record([&b"a"[..]], &no_labels(), &Error::Overloaded);
+        tracker.record([&b"d"[..]], &no_labels(), &WAIT_DIE);
+        tracker.restore(&previous.stats());
+        let stats = tracker.stats();
+        assert_eq!(
+            stats.prefixes,
+            vec![
+                (
+                    Bytes::from_static(b"a"),
+                    ConflictCounts {
+                        wait_die: 1,
+                        overloaded: 1,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    Bytes::from_static(b"d"),
+                    ConflictCounts {
+                        wait_die: 1,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        // "b" no longer fits, and "c" was in other already.
+        assert_eq!(stats.other.wait_die, 2);
+    }
+
+    #[test]
+    fn groups_by_label() {
+        let tracker = ConflictTracker::new(ConflictStatsConfig {
+            max_labels: 3,
+            ..Default::default()
+        });
+        let checkout = labels(&[("service", "cart"), ("endpoint", "checkout")]);
+        tracker.record([&b"user1"[..]], &checkout, &WAIT_DIE);
+        tracker.record([&b"user1"[..]], &checkout, &Error::Overloaded);
+        tracker.record(
+            [&b"user1"[..]],
+            &labels(&[("service", "cart"), ("endpoint", "view")]),
+            &WAIT_DIE,
+        );
+        // Not a conflict.
+        tracker.record([&b"user1"[..]], &checkout, &Error::KeyIsOutOfRange);
+        // The tracker is full.
+        tracker.record(
+            [&b"user1"[..]],
+            &labels(&[("service", "search")]),
+            &WAIT_DIE,
+        );
+
+        let stats = tracker.stats();
+        assert_eq!(
+            stats.labels,
+            vec![
+                (
+                    label("service", "cart"),
+                    ConflictCounts {
+                        wait_die: 2,
+                        overloaded: 1,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    label("endpoint", "checkout"),
+                    ConflictCounts {
+                        wait_die: 1,
+                        overloaded: 1,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    label("endpoint", "view"),
+                    ConflictCounts {
+                        wait_die: 1,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        // Labels don't change how conflicts are counted by prefix.
+        assert_eq!(stats.prefixes[0].1.total(), 4);
+        assert_eq!(stats.other.total(), 0);
+    }
+
+    #[test]
+    fn restore_merges_label_counts() {
+        let previous = tracker(1, None, 2);
+        previous.record([&b"a"[..]], &labels(&[("service", "cart")]), &WAIT_DIE);
+        let tracker = tracker(1, None, 2);
+        tracker.record(
+            [&b"a"[..]],
+            &labels(&[("service", "cart")]),
+            &Error::Overloaded,
+        );
+        tracker.restore(&previous.stats());
+        assert_eq!(
+            tracker.stats().labels,
+            vec![(
+                label("service", "cart"),
+                ConflictCounts {
+                    wait_die: 1,
+                    overloaded: 1,
+                    ..Default::default()
+                }
+            )]
+        );
+    }
+}
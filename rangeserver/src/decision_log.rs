@@ -0,0 +1,677 @@
+//! An optional log of the prepares each range accepts and the commits and
+//! aborts it applies, with their epochs. The range server never reads it
+//! back: it is there to reconstruct the history of a range on top of a
+//! snapshot of it (see `replay`) when investigating a suspected atomicity
+//! violation, with `rangeserver-decision-log`.
+//!
+//! Each range logs to its own directory, `<keyspace id>/<range id>` under
+//! the configured one, in numbered files of which the newest
+//! `max_files` are kept. A range starts a new file whenever it is loaded, so
+//! a file cut short by a crash is never appended to. A file is a header
+//! followed by entries:
+//!
+//! ```text
+//! header: MAGIC, keyspace id (16 bytes), range id (16 bytes)
+//! entry:  length of the rest of the entry (u32), kind (u8),
+//!         transaction id (16 bytes), logged at (i64, microseconds since the
+//!         Unix epoch), then for
+//!           prepares: highest known epoch (u64), PrepareRequest flatbuffer
+//!           commits:  epoch (u64)
+//!           aborts:   nothing
+//! ```
+//!
+//! Integers are big-endian. A file that ends in a partial entry ends at the
+//! last complete one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use common::config::DecisionLogConfig;
+use common::full_range_id::FullRangeId;
+use common::keyspace_id::KeyspaceId;
+use flatbuf::rangeserver_flatbuffers::range_server::PrepareRequest;
+use proto::rangeserver::{
+    PreparedTransaction as ProtoPreparedTransaction, RangeSnapshot as ProtoRangeSnapshot,
+    SnapshotRecord,
+};
+use uuid::Uuid;
+
+const MAGIC: &[u8; 8] = b"ATXDLOG1";
+const HEADER_LEN: u64 = MAGIC.len() as u64 + 32;
+const FILE_EXTENSION: &str = "dlog";
+
+const PREPARED: u8 = 1;
+const COMMITTED: u8 = 2;
+const ABORTED: u8 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The range accepted the prepare, logged as it went to the WAL.
+    Prepared {
+        highest_known_epoch: u64,
+        prepare_request: Bytes,
+    },
+    /// The range applied the writes of the transaction in `epoch`.
+    Committed { epoch: u64 },
+    /// The range dropped the transaction's locks and prepare.
+    Aborted,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub transaction_id: Uuid,
+    pub logged_at: DateTime<Utc>,
+    pub event: Event,
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn encode_entry(entry: &Entry) -> Vec<u8> {
+    let mut body = Vec::new();
+    let (kind, payload): (u8, Vec<u8>) = match &entry.event {
+        Event::Prepared {
+            highest_known_epoch,
+            prepare_request,
+        } => {
+            let mut payload = highest_known_epoch.to_be_bytes().to_vec();
+            payload.extend_from_slice(prepare_request);
+            (PREPARED, payload)
+        }
+        Event::Committed { epoch } => (COMMITTED, epoch.to_be_bytes().to_vec()),
+        Event::Aborted => (ABORTED, Vec::new()),
+    };
+    body.push(kind);
+    body.extend_from_slice(entry.transaction_id.as_bytes());
+    body.extend_from_slice(&entry.logged_at.timestamp_micros().to_be_bytes());
+    body.extend_from_slice(&payload);
+    let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&body);
+    frame
+}
+
+fn decode_entry(body: &[u8]) -> io::Result<Entry> {
+    if body.len() < 25 {
+        return Err(invalid_data("entry too short"));
+    }
+    let transaction_id = Uuid::from_slice(&body[1..17]).map_err(invalid_data)?;
+    let logged_at = i64::from_be_bytes(body[17..25].try_into().unwrap());
+    let logged_at =
+        DateTime::from_timestamp_micros(logged_at).ok_or_else(|| invalid_data("bad timestamp"))?;
+    let payload = &body[25..];
+    let epoch = || -> io::Result<u64> {
+        let epoch = payload
+            .get(..8)
+            .ok_or_else(|| invalid_data("missing epoch"))?;
+        Ok(u64::from_be_bytes(epoch.try_into().unwrap()))
+    };
+    let event = match body[0] {
+        PREPARED => Event::Prepared {
+            highest_known_epoch: epoch()?,
+            prepare_request: Bytes::copy_from_slice(&payload[8..]),
+        },
+        COMMITTED => Event::Committed { epoch: epoch()? },
+        ABORTED => Event::Aborted,
+        kind => return Err(invalid_data(format!("unknown entry kind {}", kind))),
+    };
+    Ok(Entry {
+        transaction_id,
+        logged_at,
+        event,
+    })
+}
+
+/// The directory the range logs to under `root`.
+pub fn range_directory(root: &Path, range_id: &FullRangeId) -> PathBuf {
+    root.join(range_id.keyspace_id.id.to_string())
+        .join(range_id.range_id.to_string())
+}
+
+// The numbers of the log files in the directory, in order.
+fn file_numbers(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == FILE_EXTENSION) {
+            if let Some(n) = path.file_stem().and_then(|s| s.to_str()?.parse().ok()) {
+                numbers.push(n);
+            }
+        }
+    }
+    numbers.sort();
+    Ok(numbers)
+}
+
+fn file_path(dir: &Path, number: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", number, FILE_EXTENSION))
+}
+
+struct CurrentFile {
+    file: File,
+    number: u64,
+    len: u64,
+}
+
+/// The decision log of one range.
+pub struct DecisionLog {
+    range_id: FullRangeId,
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    // Opened on the first append.
+    current: Mutex<Option<CurrentFile>>,
+}
+
+impl DecisionLog {
+    /// The log of the range, or None if the config disables logging.
+    pub fn new(config: &DecisionLogConfig, range_id: FullRangeId) -> Option<DecisionLog> {
+        let root = config.directory.as_ref()?;
+        Some(DecisionLog {
+            range_id,
+            dir: range_directory(root, &range_id),
+            max_file_bytes: config.max_file_bytes,
+            max_files: config.max_files.max(1),
+            current: Mutex::new(None),
+        })
+    }
+
+    pub fn append(&self, entry: &Entry) -> io::Result<()> {
+        let frame = encode_entry(entry);
+        let mut current = self.current.lock().unwrap();
+        let rotate = match current.as_ref() {
+            None => true,
+            Some(file) => {
+                file.len > HEADER_LEN && file.len + frame.len() as u64 > self.max_file_bytes
+            }
+        };
+        if rotate {
+            *current = Some(self.start_file(current.as_ref().map(|file| file.number))?);
+        }
+        let file = current.as_mut().unwrap();
+        file.file.write_all(&frame)?;
+        file.len += frame.len() as u64;
+        Ok(())
+    }
+
+    // Starts the file after `previous`, or after the newest one in the
+    // directory, and deletes the oldest files past the limit.
+    fn start_file(&self, previous: Option<u64>) -> io::Result<CurrentFile> {
+        fs::create_dir_all(&self.dir)?;
+        let mut numbers = file_numbers(&self.dir)?;
+        let number = previous.or(numbers.last().copied()).map_or(0, |n| n + 1);
+        let mut file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(file_path(&self.dir, number))?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(self.range_id.keyspace_id.id.as_bytes());
+        header.extend_from_slice(self.range_id.range_id.as_bytes());
+        file.write_all(&header)?;
+        numbers.push(number);
+        let excess = numbers.len().saturating_sub(self.max_files);
+        for old in &numbers[..excess] {
+            fs::remove_file(file_path(&self.dir, *old))?;
+        }
+        Ok(CurrentFile {
+            file,
+            number,
+            len: HEADER_LEN,
+        })
+    }
+}
+
+/// Reads one log file, returning the range it belongs to and its entries.
+pub fn read_file(path: &Path) -> io::Result<(FullRangeId, Vec<Entry>)> {
+    let data = fs::read(path)?;
+    if data.len() < HEADER_LEN as usize || &data[..MAGIC.len()] != MAGIC {
+        return Err(invalid_data(format!(
+            "{} is not a decision log",
+            path.display()
+        )));
+    }
+    let keyspace_id = Uuid::from_slice(&data[8..24]).map_err(invalid_data)?;
+    let range_id = Uuid::from_slice(&data[24..40]).map_err(invalid_data)?;
+    let range_id = FullRangeId {
+        keyspace_id: KeyspaceId::new(keyspace_id),
+        range_id,
+    };
+    let mut entries = Vec::new();
+    let mut pos = HEADER_LEN as usize;
+    while pos + 4 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let Some(body) = data.get(pos + 4..pos + 4 + len) else {
+            break;
+        };
+        entries.push(decode_entry(body)?);
+        pos += 4 + len;
+    }
+    Ok((range_id, entries))
+}
+
+/// Reads the files of a range's log directory, oldest first. Returns the
+/// range and the entries of all of them in the order they were logged.
+pub fn read_directory(dir: &Path) -> io::Result<(FullRangeId, Vec<Entry>)> {
+    let mut range = None;
+    let mut entries = Vec::new();
+    for number in file_numbers(dir)? {
+        let (range_id, file_entries) = read_file(&file_path(dir, number))?;
+        if range.is_some_and(|range| range != range_id) {
+            return Err(invalid_data(format!(
+                "{} holds the logs of more than one range",
+                dir.display()
+            )));
+        }
+        range = Some(range_id);
+        entries.extend(file_entries);
+    }
+    let range = range.ok_or_else(|| invalid_data(format!("no logs in {}", dir.display())))?;
+    Ok((range, entries))
+}
+
+/// Something in a range's history that should not have happened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Anomaly {
+    /// Committed without a prepare in the log or the snapshot.
+    CommitWithoutPrepare,
+    /// Committed, or prepared, after the range aborted it.
+    DecidedAfterAbort,
+    /// Aborted, prepared, or committed again, after the range committed it.
+    DecidedAfterCommit,
+    /// Committed in an earlier epoch than a transaction committed before it.
+    EpochRegressed { previous: u64, epoch: u64 },
+    /// Only some of the transaction's writes are in the snapshot. Compaction
+    /// removes versions no read can see anymore, so for transactions that
+    /// committed long before the snapshot this can also be that.
+    PartiallyInSnapshot { missing: usize, writes: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub transaction_id: Uuid,
+    pub logged_at: DateTime<Utc>,
+    pub anomaly: Anomaly,
+}
+
+/// What replaying a log on top of a snapshot did.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    /// The snapshot with the logged commits applied and the logged prepares
+    /// still pending, as of the last entry.
+    pub snapshot: ProtoRangeSnapshot,
+    /// Commits whose writes were applied on top of the snapshot.
+    pub applied: usize,
+    /// Commits whose writes were all in the snapshot already.
+    pub already_in_snapshot: usize,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Decided {
+    Committed,
+    Aborted,
+}
+
+/// Replays `entries` of a range's log on top of a snapshot of the range,
+/// checking its history along the way.
+///
+/// The log usually starts before the snapshot was taken, so a commit whose
+/// writes are all in the snapshot is taken to be in it already. Writes are
+/// identified by their key, epoch and transaction.
+pub fn replay(mut snapshot: ProtoRangeSnapshot, entries: &[Entry]) -> Replay {
+    let mut records: BTreeMap<Vec<u8>, Vec<SnapshotRecord>> = BTreeMap::new();
+    for record in std::mem::take(&mut snapshot.records) {
+        records.entry(record.key.clone()).or_default().push(record);
+    }
+    let mut prepared: HashMap<Uuid, Bytes> = snapshot
+        .prepared_transactions
+        .iter()
+        .filter_map(|tx| {
+            let id = Uuid::parse_str(&tx.transaction_id).ok()?;
+            Some((id, Bytes::from(tx.prepare_request.clone())))
+        })
+        .collect();
+    let mut decided: HashMap<Uuid, Decided> = HashMap::new();
+    let mut last_commit_epoch = None;
+    let mut applied = 0;
+    let mut already_in_snapshot = 0;
+    let mut findings = Vec::new();
+    let mut found = |entry: &Entry, anomaly: Anomaly| {
+        findings.push(Finding {
+            transaction_id: entry.transaction_id,
+            logged_at: entry.logged_at,
+            anomaly,
+        })
+    };
+    for entry in entries {
+        let tx_id = entry.transaction_id;
+        match decided.get(&tx_id) {
+            Some(Decided::Aborted) if !matches!(entry.event, Event::Aborted) => {
+                found(entry, Anomaly::DecidedAfterAbort)
+            }
+            Some(Decided::Committed) => found(entry, Anomaly::DecidedAfterCommit),
+            _ => (),
+        }
+        match &entry.event {
+            Event::Prepared {
+                highest_known_epoch,
+                prepare_request,
+            } => {
+                snapshot.highest_known_epoch =
+                    snapshot.highest_known_epoch.max(*highest_known_epoch);
+                prepared.insert(tx_id, prepare_request.clone());
+            }
+            Event::Aborted => {
+                prepared.remove(&tx_id);
+                decided.entry(tx_id).or_insert(Decided::Aborted);
+            }
+            Event::Committed { epoch } => {
+                snapshot.highest_known_epoch = snapshot.highest_known_epoch.max(*epoch);
+                if let Some(previous) = last_commit_epoch.filter(|previous| previous > epoch) {
+                    found(
+                        entry,
+                        Anomaly::EpochRegressed {
+                            previous,
+                            epoch: *epoch,
+                        },
+                    );
+                }
+                last_commit_epoch = Some(*epoch);
+                decided.entry(tx_id).or_insert(Decided::Committed);
+                let Some(prepare_request) = prepared.remove(&tx_id) else {
+                    found(entry, Anomaly::CommitWithoutPrepare);
+                    continue;
+                };
+                let writes = match writes_of(&prepare_request) {
+                    Ok(writes) => writes,
+                    Err(_) => {
+                        found(entry, Anomaly::CommitWithoutPrepare);
+                        continue;
+                    }
+                };
+                let transaction_id = Some(tx_id.to_string());
+                let total = writes.len();
+                let missing: Vec<_> = writes
+                    .into_iter()
+                    .filter(|write| {
+                        !records.get(&write.key).is_some_and(|versions| {
+                            versions.iter().any(|version| {
+                                version.epoch == *epoch && version.transaction_id == transaction_id
+                            })
+                        })
+                    })
+                    .collect();
+                match missing.len() {
+                    0 if total > 0 => already_in_snapshot += 1,
+                    0 => (),
+                    n if n == total => applied += 1,
+                    n => found(
+                        entry,
+                        Anomaly::PartiallyInSnapshot {
+                            missing: n,
+                            writes: total,
+                        },
+                    ),
+                }
+                for write in missing {
+                    let versions = records.entry(write.key.clone()).or_default();
+                    // The range counts TTLs from when it applied the commit,
+                    // which is about when it logged it.
+                    let expires_at_us = (write.ttl_ms > 0).then(|| {
+                        (entry.logged_at.timestamp_micros() as u64)
+                            .saturating_add(write.ttl_ms.saturating_mul(1000))
+                    });
+                    versions.push(SnapshotRecord {
+                        key: write.key,
+                        epoch: *epoch,
+                        transaction_id: transaction_id.clone(),
+                        value: write.value,
+                        expires_at_us,
+                    });
+                    // Newest first. The sort is stable, so a version is put
+                    // after those of the same epoch already there.
+                    versions.sort_by_key(|version| std::cmp::Reverse(version.epoch));
+                }
+            }
+        }
+    }
+    snapshot.records = records.into_values().flatten().collect();
+    let mut prepared: Vec<_> = prepared.into_iter().collect();
+    prepared.sort_by_key(|(id, _)| *id);
+    snapshot.prepared_transactions = prepared
+        .into_iter()
+        .map(|(id, prepare)| ProtoPreparedTransaction {
+            transaction_id: id.to_string(),
+            prepare_request: prepare.to_vec(),
+        })
+        .collect();
+    if let Some(last) = entries.last() {
+        snapshot.taken_at_us = snapshot
+            .taken_at_us
+            .max(last.logged_at.timestamp_micros() as u64);
+    }
+    Replay {
+        snapshot,
+        applied,
+        already_in_snapshot,
+        findings,
+    }
+}
+
+// A put of a prepare, or a delete if `value` is None.
+struct LoggedWrite {
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+    ttl_ms: u64,
+}
+
+fn writes_of(prepare_request: &[u8]) -> Result<Vec<LoggedWrite>, flatbuffers::InvalidFlatbuffer> {
+    let prepare = flatbuffers::root::<PrepareRequest>(prepare_request)?;
+    let mut writes = Vec::new();
+    for put in prepare.puts().iter().flatten() {
+        let key = put.key().and_then(|k| k.k()).map_or(&[][..], |k| k.bytes());
+        let value = put.value().map_or(&[][..], |v| v.bytes());
+        writes.push(LoggedWrite {
+            key: key.to_vec(),
+            value: Some(value.to_vec()),
+            ttl_ms: put.ttl_ms(),
+        });
+    }
+    for del in prepare.deletes().iter().flatten() {
+        let key = del.k().map_or(&[][..], |k| k.bytes());
+        writes.push(LoggedWrite {
+            key: key.to_vec(),
+            value: None,
+            ttl_ms: 0,
+        });
+    }
+    Ok(writes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flatbuf::rangeserver_flatbuffers::range_server::{
+        Key, KeyArgs, PrepareRequestArgs, Record, RecordArgs,
+    };
+    use flatbuffers::FlatBufferBuilder;
+
+    fn range_id() -> FullRangeId {
+        FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        }
+    }
+
+    fn prepare_request(puts: &[(&[u8], &[u8])], deletes: &[&[u8]]) -> Bytes {
+        let mut fbb = FlatBufferBuilder::new();
+        let mut puts_vector = Vec::new();
+        for (k, v) in puts {
+            let k = Some(fbb.create_vector(k));
+            let key = Key::create(&mut fbb, &KeyArgs { k });
+            let value = Some(fbb.create_vector(v));
+            puts_vector.push(Record::create(
+                &mut fbb,
+                &RecordArgs {
+                    key: Some(key),
+                    value,
+                    ..Default::default()
+                },
+            ));
+        }
+        let puts = Some(fbb.create_vector(&puts_vector));
+        let mut deletes_vector = Vec::new();
+        for k in deletes {
+            let k = Some(fbb.create_vector(k));
+            deletes_vector.push(Key::create(&mut fbb, &KeyArgs { k }));
+        }
+        let deletes = Some(fbb.create_vector(&deletes_vector));
+        let root = PrepareRequest::create(
+            &mut fbb,
+            &PrepareRequestArgs {
+                puts,
+                deletes,
+                ..Default::default()
+            },
+        );
+        fbb.finish(root, None);
+        Bytes::copy_from_slice(fbb.finished_data())
+    }
+
+    fn entry(transaction_id: Uuid, event: Event) -> Entry {
+        Entry {
+            transaction_id,
+            logged_at: DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap(),
+            event,
+        }
+    }
+
+    #[test]
+    fn entries_are_read_back_across_files() {
+        let root = std::env::temp_dir().join(format!("decision-log-{}", Uuid::new_v4()));
+        let range_id = range_id();
+        let config = DecisionLogConfig {
+            directory: Some(root.clone()),
+            // Small enough for every entry to start a new file.
+            max_file_bytes: 64,
+            max_files: 2,
+        };
+        let log = DecisionLog::new(&config, range_id).unwrap();
+        let tx = Uuid::new_v4();
+        let entries = [
+            entry(
+                tx,
+                Event::Prepared {
+                    highest_known_epoch: 4,
+                    prepare_request: prepare_request(&[(b"a", b"1")], &[]),
+                },
+            ),
+            entry(tx, Event::Committed { epoch: 5 }),
+            entry(Uuid::new_v4(), Event::Aborted),
+        ];
+        for entry in &entries {
+            log.append(entry).unwrap();
+        }
+        let dir = range_directory(&root, &range_id);
+        // The oldest file was deleted.
+        assert_eq!(file_numbers(&dir).unwrap(), vec![1, 2]);
+        assert_eq!(
+            read_directory(&dir).unwrap(),
+            (range_id, entries[1..].to_vec())
+        );
+
+        // A partial entry at the end, as left by a crash, is ignored.
+        let last = file_path(&dir, 2);
+        let len = fs::metadata(&last).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&last)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        assert_eq!(read_file(&last).unwrap(), (range_id, vec![]));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn replays_commits_missing_from_the_snapshot() {
+        let in_snapshot = Uuid::new_v4();
+        let partial = Uuid::new_v4();
+        let after = Uuid::new_v4();
+        let aborted = Uuid::new_v4();
+        let record = |key: &[u8], epoch, tx: Uuid, value: Option<&[u8]>| SnapshotRecord {
+            key: key.to_vec(),
+            epoch,
+            transaction_id: Some(tx.to_string()),
+            value: value.map(|v| v.to_vec()),
+            expires_at_us: None,
+        };
+        let snapshot = ProtoRangeSnapshot {
+            records: vec![
+                record(b"a", 1, in_snapshot, Some(b"1")),
+                record(b"b", 2, partial, Some(b"2")),
+            ],
+            ..Default::default()
+        };
+        let prepared = |puts, deletes| Event::Prepared {
+            highest_known_epoch: 1,
+            prepare_request: prepare_request(puts, deletes),
+        };
+        let entries = [
+            entry(in_snapshot, prepared(&[(b"a", b"1")], &[])),
+            entry(in_snapshot, Event::Committed { epoch: 1 }),
+            entry(partial, prepared(&[(b"b", b"2"), (b"c", b"2")], &[])),
+            entry(partial, Event::Committed { epoch: 2 }),
+            entry(after, prepared(&[(b"b", b"3")], &[b"a"])),
+            entry(after, Event::Committed { epoch: 3 }),
+            entry(aborted, prepared(&[(b"d", b"4")], &[])),
+            entry(aborted, Event::Aborted),
+            entry(aborted, Event::Committed { epoch: 2 }),
+        ];
+        let replay = replay(snapshot, &entries);
+        assert_eq!(replay.applied, 1);
+        assert_eq!(replay.already_in_snapshot, 1);
+        assert_eq!(
+            replay
+                .findings
+                .iter()
+                .map(|f| (f.transaction_id, f.anomaly.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    partial,
+                    Anomaly::PartiallyInSnapshot {
+                        missing: 1,
+                        writes: 2
+                    }
+                ),
+                (aborted, Anomaly::DecidedAfterAbort),
+                (
+                    aborted,
+                    Anomaly::EpochRegressed {
+                        previous: 3,
+                        epoch: 2
+                    }
+                ),
+                (aborted, Anomaly::CommitWithoutPrepare),
+            ]
+        );
+        assert_eq!(
+            replay.snapshot.records,
+            vec![
+                record(b"a", 3, after, None),
+                record(b"a", 1, in_snapshot, Some(b"1")),
+                record(b"b", 3, after, Some(b"3")),
+                record(b"b", 2, partial, Some(b"2")),
+                record(b"c", 2, partial, Some(b"2")),
+            ]
+        );
+        assert_eq!(replay.snapshot.highest_known_epoch, 3);
+        assert!(replay.snapshot.prepared_transactions.is_empty());
+    }
+}
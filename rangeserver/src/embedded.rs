@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use common::network::fast_network::FastNetwork;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    server::{DynamicErr, Server},
+    storage::Storage,
+};
+
+/// A range server running inside the calling process, e.g. next to a
+/// coordinator in the same binary, or in a test harness on top of an
+/// in-memory network.
+///
+/// Unlike a standalone range server it does not serve the gRPC API, so it
+/// binds no sockets of its own. It still connects to the warden of its region.
+pub struct EmbeddedRangeServer<S>
+where
+    S: Storage,
+{
+    server: Arc<Server<S>>,
+    cancellation_token: CancellationToken,
+    done: oneshot::Receiver<Result<(), DynamicErr>>,
+}
+
+impl<S> EmbeddedRangeServer<S>
+where
+    S: Storage,
+{
+    /// Starts serving requests arriving on `fast_network`, which the embedded
+    /// server polls itself until it is shut down.
+    pub async fn start(
+        server: Arc<Server<S>>,
+        fast_network: Arc<dyn FastNetwork>,
+        cancellation_token: CancellationToken,
+    ) -> Result<EmbeddedRangeServer<S>, DynamicErr> {
+        let fast_network_clone = fast_network.clone();
+        let cancellation_token_for_poll = cancellation_token.clone();
+        tokio::spawn(async move {
+            while !cancellation_token_for_poll.is_cancelled() {
+                fast_network_clone.poll();
+                tokio::task::yield_now().await
+            }
+        });
+        let done = match Server::start_with_listener(
+            server.clone(),
+            fast_network,
+            cancellation_token.clone(),
+            None,
+        )
+        .await
+        {
+            Ok(done) => done,
+            Err(e) => {
+                cancellation_token.cancel();
+                return Err(e);
+            }
+        };
+        Ok(EmbeddedRangeServer {
+            server,
+            cancellation_token,
+            done,
+        })
+    }
+
+    pub fn server(&self) -> &Arc<Server<S>> {
+        &self.server
+    }
+
+    /// Stops the server and waits for its connection to the warden to close.
+    pub async fn shutdown(self) -> Result<(), DynamicErr> {
+        self.cancellation_token.cancel();
+        self.done.await?
+    }
+}
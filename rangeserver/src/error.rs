@@ -20,6 +20,49 @@ pub enum Error {
     UnknownTransaction,
     CacheIsFull,
     PrefetchError,
+    KeyspaceIsReadOnly,
+    /// The range hit persistent storage errors and is being reloaded.
+    RangeFaulted,
+    /// The keyspace of the range was dropped, possibly recreated under a new
+    /// id, since the transaction resolved it.
+    KeyspaceDoesNotExist,
+    /// Too many transactions are prepared on the range waiting for a
+    /// decision. The prepare can be retried after `retry_after`.
+    PrepareBacklogFull {
+        retry_after: std::time::Duration,
+    },
+    /// The lock table of the range is full, see `LockTableConfig`.
+    Overloaded,
+    /// Applying writes to the range's storage has been slow for a sustained
+    /// period, see `WriteStallConfig`. Prepares that write can be retried
+    /// after `retry_after`.
+    WriteStalled {
+        retry_after: std::time::Duration,
+    },
+    /// A write breaks the validation policy of the keyspace.
+    WriteRejected,
+    /// A condition of the prepare does not hold, see `Condition`.
+    ConditionFailed,
+    /// An increment of the prepare applies to a value that is not a counter,
+    /// or overflows it, see `Increment`.
+    InvalidIncrement,
+    /// Transactions hold locks or are prepared on the range, so it can't be
+    /// split until they finish.
+    RangeBusy,
+    /// An operator froze the range, so it rejects new transactions until
+    /// unfrozen. Transactions already prepared on it can still finish.
+    RangeFrozen,
+    /// The range is being moved to another range server, so it only serves
+    /// the transactions already running on it. Others can retry once the
+    /// range is on its new server.
+    RangeDraining,
+    /// A read at an epoch whose versions the range may have already
+    /// collected, or from before the range was last loaded.
+    SnapshotTooOld,
+    /// The transaction waited in line for a lock of the range for longer
+    /// than `LockTableConfig::wait_timeout`, and gave up its place. Unlike a
+    /// `TransactionAborted`, the range kept nothing of the transaction.
+    LockWaitTimeout,
     TransactionAborted(TransactionAbortReason),
     InternalError(Arc<dyn std::error::Error + Send + Sync>),
 }
@@ -30,7 +73,9 @@ impl Error {
             StorageError::RangeDoesNotExist => Self::RangeDoesNotExist,
             StorageError::RangeOwnershipLost => Self::RangeOwnershipLost,
             StorageError::Timeout => Self::Timeout,
-            StorageError::InternalError(_) => Self::InternalError(Arc::new(e)),
+            StorageError::Unavailable(_) | StorageError::InternalError(_) => {
+                Self::InternalError(Arc::new(e))
+            }
         }
     }
 
@@ -63,6 +108,7 @@ impl Error {
             Self::RangeOwnershipLost => Status::RangeOwnershipLost,
             Self::Timeout => Status::Timeout,
             Self::UnknownTransaction => Status::UnknownTransaction,
+            Self::TransactionAborted(TransactionAbortReason::Deadlock) => Status::Deadlock,
             Self::TransactionAborted(_) => Status::TransactionAborted,
             Self::CacheIsFull => Status::CacheIsFull,
             Self::InternalError(_) => Status::InternalError,
@@ -70,6 +116,21 @@ impl Error {
             // returned from the server.
             Self::ConnectionClosed => Status::InternalError,
             Self::PrefetchError => Status::PrefetchError,
+            Self::KeyspaceIsReadOnly => Status::KeyspaceIsReadOnly,
+            Self::RangeFaulted => Status::RangeFaulted,
+            Self::KeyspaceDoesNotExist => Status::KeyspaceDoesNotExist,
+            Self::PrepareBacklogFull { .. } => Status::PrepareBacklogFull,
+            Self::Overloaded => Status::Overloaded,
+            Self::WriteRejected => Status::WriteRejected,
+            Self::ConditionFailed => Status::ConditionFailed,
+            Self::InvalidIncrement => Status::InvalidIncrement,
+            Self::WriteStalled { .. } => Status::WriteStalled,
+            Self::RangeFrozen => Status::RangeFrozen,
+            Self::RangeDraining => Status::RangeDraining,
+            Self::SnapshotTooOld => Status::SnapshotTooOld,
+            Self::LockWaitTimeout => Status::LockWaitTimeout,
+            // Only returned by admin operations, never to clients.
+            Self::RangeBusy => Status::InternalError,
         }
     }
 
@@ -93,6 +154,26 @@ impl Error {
                 Err(Self::InternalError(Arc::new(std::fmt::Error)))
             }
             Status::PrefetchError => Err(Self::PrefetchError),
+            Status::KeyspaceIsReadOnly => Err(Self::KeyspaceIsReadOnly),
+            Status::RangeFaulted => Err(Self::RangeFaulted),
+            Status::KeyspaceDoesNotExist => Err(Self::KeyspaceDoesNotExist),
+            // The hint is not part of the status, see PrepareResponse.
+            Status::PrepareBacklogFull => Err(Self::PrepareBacklogFull {
+                retry_after: std::time::Duration::ZERO,
+            }),
+            Status::Overloaded => Err(Self::Overloaded),
+            Status::WriteRejected => Err(Self::WriteRejected),
+            Status::ConditionFailed => Err(Self::ConditionFailed),
+            Status::InvalidIncrement => Err(Self::InvalidIncrement),
+            Status::RangeFrozen => Err(Self::RangeFrozen),
+            Status::RangeDraining => Err(Self::RangeDraining),
+            Status::SnapshotTooOld => Err(Self::SnapshotTooOld),
+            Status::LockWaitTimeout => Err(Self::LockWaitTimeout),
+            Status::Deadlock => Err(Self::TransactionAborted(TransactionAbortReason::Deadlock)),
+            // The hint is not part of the status, see PrepareResponse.
+            Status::WriteStalled => Err(Self::WriteStalled {
+                retry_after: std::time::Duration::ZERO,
+            }),
             _ => Err(Self::InternalError(Arc::new(std::fmt::Error))),
         }
     }
@@ -4,11 +4,13 @@ use std::{
     sync::Arc,
 };
 
-use common::full_range_id::FullRangeId;
+use common::{full_range_id::FullRangeId, keyspace_id::KeyspaceId};
 use proto::warden::{
     warden_server::{Warden, WardenServer},
     warden_update::Update::{FullAssignment, IncrementalAssignment},
-    RegisterRangeServerRequest, WardenUpdate,
+    HeartbeatRequest, HeartbeatResponse, RegisterRangeServerRequest, ReportRangeFaultRequest,
+    ReportRangeFaultResponse, ReportRangeHandedOffRequest, ReportRangeHandedOffResponse,
+    ReportRangeSplitRequest, ReportRangeSplitResponse, WardenUpdate,
 };
 use tokio::{
     net::TcpListener,
@@ -22,6 +24,7 @@ struct WardenState {
     range_to_host: RwLock<HashMap<Uuid, String>>,
     host_ranges: RwLock<HashMap<String, HashSet<FullRangeId>>>,
     rs_connections: RwLock<HashMap<String, mpsc::Sender<Result<WardenUpdate, Status>>>>,
+    handed_off: RwLock<HashSet<FullRangeId>>,
 }
 pub struct MockWarden {
     state: Arc<WardenState>,
@@ -46,6 +49,7 @@ impl MockWarden {
             range_to_host: RwLock::new(HashMap::new()),
             host_ranges: RwLock::new(HashMap::new()),
             rs_connections: RwLock::new(HashMap::new()),
+            handed_off: RwLock::new(HashSet::new()),
         });
 
         MockWarden {
@@ -136,6 +140,11 @@ impl MockWarden {
         connections.get(host).is_some()
     }
 
+    /// Whether a range server reported that it handed off the range.
+    pub async fn is_handed_off(&self, range: &FullRangeId) -> bool {
+        self.state.handed_off.read().await.contains(range)
+    }
+
     pub async fn disconnect(&self, host: &String) {
         let mut connections = self.state.rs_connections.write().await;
         connections.remove(host);
@@ -169,4 +178,81 @@ impl Warden for WardenState {
         tx.send(Ok(warden_update)).await.unwrap();
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    async fn report_range_fault(
+        &self,
+        _request: Request<ReportRangeFaultRequest>,
+    ) -> Result<Response<ReportRangeFaultResponse>, Status> {
+        Ok(Response::new(ReportRangeFaultResponse {}))
+    }
+
+    // Moves the new ranges onto the host right away, as the warden does once
+    // it applied the split.
+    async fn report_range_split(
+        &self,
+        request: Request<ReportRangeSplitRequest>,
+    ) -> Result<Response<ReportRangeSplitResponse>, Status> {
+        let request = request.into_inner();
+        let host = request.range_server.unwrap().identity;
+        let parse = |range: &proto::warden::RangeId| FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::parse_str(&range.keyspace_id).unwrap()),
+            range_id: Uuid::parse_str(&range.range_id).unwrap(),
+        };
+        let parent = parse(request.range.as_ref().unwrap());
+        let children: Vec<_> = request
+            .new_ranges
+            .iter()
+            .map(|r| parse(r.range.as_ref().unwrap()))
+            .collect();
+        {
+            let mut range_to_host = self.range_to_host.write().await;
+            let mut host_ranges = self.host_ranges.write().await;
+            if range_to_host.get(&parent.range_id) != Some(&host) {
+                return Err(Status::failed_precondition("range is not assigned to host"));
+            }
+            range_to_host.remove(&parent.range_id);
+            let ranges = host_ranges.get_mut(&host).unwrap();
+            ranges.remove(&parent);
+            for child in &children {
+                range_to_host.insert(child.range_id, host.clone());
+                ranges.insert(*child);
+            }
+        }
+        let incremental = proto::warden::IncrementalAssignment {
+            version: 2,
+            previous_version: 1,
+            load: children.iter().map(range_id_proto).collect(),
+            unload: vec![range_id_proto(&parent)],
+        };
+        let warden_update = WardenUpdate {
+            update: Some(IncrementalAssignment(incremental)),
+        };
+        let connections = self.rs_connections.read().await;
+        if let Some(sender) = connections.get(&host) {
+            sender.send(Ok(warden_update)).await.unwrap();
+        }
+        Ok(Response::new(ReportRangeSplitResponse {}))
+    }
+
+    async fn report_range_handed_off(
+        &self,
+        request: Request<ReportRangeHandedOffRequest>,
+    ) -> Result<Response<ReportRangeHandedOffResponse>, Status> {
+        let range = request.into_inner().range.unwrap();
+        let range = FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::parse_str(&range.keyspace_id).unwrap()),
+            range_id: Uuid::parse_str(&range.range_id).unwrap(),
+        };
+        self.handed_off.write().await.insert(range);
+        Ok(Response::new(ReportRangeHandedOffResponse {}))
+    }
+
+    async fn heartbeat(
+        &self,
+        request: Request<HeartbeatRequest>,
+    ) -> Result<Response<HeartbeatResponse>, Status> {
+        let host = request.into_inner().range_server.unwrap().identity;
+        let registered = self.rs_connections.read().await.contains_key(&host);
+        Ok(Response::new(HeartbeatResponse { registered }))
+    }
 }
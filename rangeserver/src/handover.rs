@@ -0,0 +1,439 @@
+//! Hands a range server over to a new process on the same host, e.g. to
+//! upgrade its binary, without closing its sockets or losing the counters of
+//! its ranges.
+//!
+//! The running process listens on a Unix socket. The new process connects to
+//! it on startup and receives the listening sockets of the old one, passed as
+//! file descriptors, along with a `HandoverState`. The old process stops
+//! serving as it sends them and then exits, and the ranges are loaded again
+//! by the new process as the warden reassigns them, picking up their counters
+//! from the handed over state.
+
+use std::collections::HashMap;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use common::full_range_id::FullRangeId;
+use nix::sys::socket::{
+    getsockopt, recvmsg, sendmsg, sockopt, ControlMessage, ControlMessageOwned, MsgFlags, SockType,
+};
+use prost::Message;
+use proto::rangeserver::{
+    ConflictCounts as ProtoConflictCounts, HandoverState, LabelConflicts, PrefixConflicts, RangeId,
+    RangeSoftState,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::conflict_stats::{ConflictCounts, ConflictStats};
+use crate::range_manager::SoftState;
+use crate::server::{full_range_id_from_proto, Server};
+use crate::storage::Storage;
+
+/// The version of the `HandoverState` format written by this build.
+pub const HANDOVER_FORMAT_VERSION: u32 = 1;
+
+/// The socket the fast network is served on, depending on its transport.
+pub enum FastNetworkSocket {
+    Udp(UdpSocket),
+    Tcp(TcpListener),
+}
+
+impl FastNetworkSocket {
+    fn try_clone(&self) -> io::Result<FastNetworkSocket> {
+        Ok(match self {
+            FastNetworkSocket::Udp(socket) => FastNetworkSocket::Udp(socket.try_clone()?),
+            FastNetworkSocket::Tcp(listener) => FastNetworkSocket::Tcp(listener.try_clone()?),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            FastNetworkSocket::Udp(socket) => socket.local_addr(),
+            FastNetworkSocket::Tcp(listener) => listener.local_addr(),
+        }
+    }
+
+    // Which of the two `fd` is, taken from its socket type.
+    fn from_fd(fd: OwnedFd) -> io::Result<FastNetworkSocket> {
+        match getsockopt(fd.as_raw_fd(), sockopt::SockType)? {
+            SockType::Datagram => Ok(FastNetworkSocket::Udp(UdpSocket::from(fd))),
+            SockType::Stream => Ok(FastNetworkSocket::Tcp(TcpListener::from(fd))),
+            other => Err(invalid_data(format!(
+                "unexpected fast network socket type {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+impl AsRawFd for FastNetworkSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            FastNetworkSocket::Udp(socket) => socket.as_raw_fd(),
+            FastNetworkSocket::Tcp(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+/// The sockets a range server serves on, in the order they are passed.
+pub struct Sockets {
+    pub proto_server_listener: TcpListener,
+    pub fast_network_socket: FastNetworkSocket,
+}
+
+impl Sockets {
+    /// Duplicates the sockets, so that the copies can be handed over while
+    /// the originals are being served on.
+    pub fn try_clone(&self) -> io::Result<Sockets> {
+        Ok(Sockets {
+            proto_server_listener: self.proto_server_listener.try_clone()?,
+            fast_network_socket: self.fast_network_socket.try_clone()?,
+        })
+    }
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn conflict_counts_to_proto(counts: &ConflictCounts) -> ProtoConflictCounts {
+    ProtoConflictCounts {
+        wait_die: counts.wait_die,
+        lock_lost: counts.lock_lost,
+        read_conflicts: counts.read_conflicts,
+        overloaded: counts.overloaded,
+    }
+}
+
+fn conflict_counts_from_proto(counts: Option<&ProtoConflictCounts>) -> ConflictCounts {
+    counts
+        .map(|c| ConflictCounts {
+            wait_die: c.wait_die,
+            lock_lost: c.lock_lost,
+            read_conflicts: c.read_conflicts,
+            overloaded: c.overloaded,
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn soft_state_to_proto(
+    range_id: &FullRangeId,
+    soft_state: &SoftState,
+) -> RangeSoftState {
+    RangeSoftState {
+        range: Some(RangeId {
+            keyspace_id: range_id.keyspace_id.id.to_string(),
+            range_id: range_id.range_id.to_string(),
+        }),
+        conflict_prefixes: soft_state
+            .conflicts
+            .prefixes
+            .iter()
+            .map(|(prefix, counts)| PrefixConflicts {
+                prefix: prefix.to_vec(),
+                counts: Some(conflict_counts_to_proto(counts)),
+            })
+            .collect(),
+        other_conflicts: Some(conflict_counts_to_proto(&soft_state.conflicts.other)),
+        compactions: soft_state.compactions,
+        purged_versions: soft_state.purged_versions,
+        write_stalls: soft_state.write_stalls,
+        lock_requests_rejected: soft_state.lock_requests_rejected,
+        conflict_labels: soft_state
+            .conflicts
+            .labels
+            .iter()
+            .map(|((key, value), counts)| LabelConflicts {
+                key: key.clone(),
+                value: value.clone(),
+                counts: Some(conflict_counts_to_proto(counts)),
+            })
+            .collect(),
+    }
+}
+
+/// Returns the soft state of each range in `state`, or why it can't be used.
+pub(crate) fn soft_states_from_proto(
+    state: &HandoverState,
+) -> Result<HashMap<Uuid, SoftState>, String> {
+    if state.format_version != HANDOVER_FORMAT_VERSION {
+        return Err(format!(
+            "unsupported handover format version {}, expected {}",
+            state.format_version, HANDOVER_FORMAT_VERSION
+        ));
+    }
+    state
+        .ranges
+        .iter()
+        .map(|range| {
+            let range_id = full_range_id_from_proto(range.range.as_ref())?;
+            let soft_state = SoftState {
+                conflicts: ConflictStats {
+                    prefixes: range
+                        .conflict_prefixes
+                        .iter()
+                        .map(|p| {
+                            (
+                                Bytes::from(p.prefix.clone()),
+                                conflict_counts_from_proto(p.counts.as_ref()),
+                            )
+                        })
+                        .collect(),
+                    other: conflict_counts_from_proto(range.other_conflicts.as_ref()),
+                    labels: range
+                        .conflict_labels
+                        .iter()
+                        .map(|l| {
+                            (
+                                (l.key.clone(), l.value.clone()),
+                                conflict_counts_from_proto(l.counts.as_ref()),
+                            )
+                        })
+                        .collect(),
+                },
+                compactions: range.compactions,
+                purged_versions: range.purged_versions,
+                write_stalls: range.write_stalls,
+                lock_requests_rejected: range.lock_requests_rejected,
+            };
+            Ok((range_id.range_id, soft_state))
+        })
+        .collect()
+}
+
+// The sockets go along with the length of the encoded state, which follows.
+fn send(stream: &UnixStream, sockets: &Sockets, state: &HandoverState) -> io::Result<()> {
+    let payload = state.encode_to_vec();
+    let header = (payload.len() as u32).to_be_bytes();
+    let fds = [
+        sockets.proto_server_listener.as_raw_fd(),
+        sockets.fast_network_socket.as_raw_fd(),
+    ];
+    let sent = sendmsg::<()>(
+        stream.as_raw_fd(),
+        &[IoSlice::new(&header)],
+        &[ControlMessage::ScmRights(&fds)],
+        MsgFlags::empty(),
+        None,
+    )?;
+    let mut stream = stream;
+    stream.write_all(&header[sent..])?;
+    stream.write_all(&payload)
+}
+
+fn receive(stream: &UnixStream) -> io::Result<(Sockets, HandoverState)> {
+    let mut header = [0u8; 4];
+    let mut cmsg_buffer = nix::cmsg_space!([RawFd; 2]);
+    let (received, fds) = {
+        let mut iov = [IoSliceMut::new(&mut header)];
+        let msg = recvmsg::<()>(
+            stream.as_raw_fd(),
+            &mut iov,
+            Some(&mut cmsg_buffer),
+            MsgFlags::MSG_CMSG_CLOEXEC,
+        )?;
+        let fds: Vec<RawFd> = msg
+            .cmsgs()
+            .filter_map(|cmsg| match cmsg {
+                ControlMessageOwned::ScmRights(fds) => Some(fds),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        (msg.bytes, fds)
+    };
+    // SAFETY: the descriptors were just received, so nothing else owns them.
+    let mut fds = fds
+        .into_iter()
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+    let (Some(listener), Some(socket)) = (fds.next(), fds.next()) else {
+        return Err(invalid_data(
+            "the previous process did not pass its sockets",
+        ));
+    };
+    let sockets = Sockets {
+        proto_server_listener: TcpListener::from(listener),
+        fast_network_socket: FastNetworkSocket::from_fd(socket)?,
+    };
+
+    let mut stream = stream;
+    stream.read_exact(&mut header[received..])?;
+    let mut payload = vec![0u8; u32::from_be_bytes(header) as usize];
+    stream.read_exact(&mut payload)?;
+    let state = HandoverState::decode(&payload[..]).map_err(invalid_data)?;
+    Ok((sockets, state))
+}
+
+/// Takes over from the range server listening at `path`, if any, and returns
+/// its sockets and the state it handed over. Returns None if no process is
+/// listening, in which case the caller should start from scratch.
+pub fn take_over(path: &Path) -> io::Result<Option<(Sockets, HandoverState)>> {
+    let stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(e)
+            if e.kind() == io::ErrorKind::NotFound
+                || e.kind() == io::ErrorKind::ConnectionRefused =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    };
+    let (sockets, state) = receive(&stream)?;
+    info!(
+        path = %path.display(),
+        ranges = state.ranges.len(),
+        "Took over from the previous range server"
+    );
+    Ok(Some((sockets, state)))
+}
+
+/// Waits for a new process to take over from `server` at `path`, and hands
+/// it `sockets` along with the soft state of the loaded ranges. Cancels
+/// `cancellation_token` first, so that the server stops taking requests.
+///
+/// Returns once the new process has everything, after which the caller should
+/// exit: the sockets are shared with the new process until then.
+pub async fn serve_successor<S: Storage>(
+    path: &Path,
+    server: Arc<Server<S>>,
+    sockets: Sockets,
+    cancellation_token: CancellationToken,
+) -> io::Result<()> {
+    // Left behind by a process that exited without handing over.
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    let (stream, _) = listener.accept().await?;
+    info!(path = %path.display(), "Handing over to a new range server");
+    cancellation_token.cancel();
+    let state = server.handover_state().await;
+    let stream = stream.into_std()?;
+    stream.set_nonblocking(false)?;
+    let res = tokio::task::spawn_blocking(move || send(&stream, &sockets, &state))
+        .await
+        .map_err(io::Error::other)?;
+    if let Err(e) = std::fs::remove_file(path) {
+        warn!(path = %path.display(), "Failed to remove the handover socket: {}", e);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::keyspace_id::KeyspaceId;
+
+    fn soft_state() -> SoftState {
+        SoftState {
+            conflicts: ConflictStats {
+                prefixes: vec![(
+                    Bytes::from_static(b"user"),
+                    ConflictCounts {
+                        wait_die: 3,
+                        ..Default::default()
+                    },
+                )],
+                other: ConflictCounts {
+                    overloaded: 1,
+                    ..Default::default()
+                },
+                labels: vec![(
+                    ("service".to_string(), "cart".to_string()),
+                    ConflictCounts {
+                        wait_die: 2,
+                        ..Default::default()
+                    },
+                )],
+            },
+            compactions: 2,
+            purged_versions: 40,
+            write_stalls: 1,
+            lock_requests_rejected: 5,
+        }
+    }
+
+    #[test]
+    fn sockets_and_state_are_handed_over() {
+        let range_id = FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        };
+        let state = HandoverState {
+            format_version: HANDOVER_FORMAT_VERSION,
+            ranges: vec![soft_state_to_proto(&range_id, &soft_state())],
+        };
+        let sockets = Sockets {
+            proto_server_listener: TcpListener::bind("127.0.0.1:0").unwrap(),
+            fast_network_socket: FastNetworkSocket::Udp(UdpSocket::bind("127.0.0.1:0").unwrap()),
+        };
+        let proto_server_addr = sockets.proto_server_listener.local_addr().unwrap();
+        let fast_network_addr = sockets.fast_network_socket.local_addr().unwrap();
+        let (old, new) = UnixStream::pair().unwrap();
+        let sent = state.clone();
+        let sender = std::thread::spawn(move || send(&old, &sockets, &sent));
+        let (received, received_state) = receive(&new).unwrap();
+        sender.join().unwrap().unwrap();
+
+        assert_eq!(received_state, state);
+        let soft_states = soft_states_from_proto(&received_state).unwrap();
+        assert_eq!(soft_states.get(&range_id.range_id), Some(&soft_state()));
+        // The received sockets are the ones the old process was serving on,
+        // and stay usable after it closed them.
+        assert_eq!(
+            received.proto_server_listener.local_addr().unwrap(),
+            proto_server_addr
+        );
+        assert_eq!(
+            received.fast_network_socket.local_addr().unwrap(),
+            fast_network_addr
+        );
+        std::net::TcpStream::connect(proto_server_addr).unwrap();
+        received.proto_server_listener.accept().unwrap();
+    }
+
+    #[test]
+    fn tcp_fast_network_listener_is_handed_over() {
+        let state = HandoverState {
+            format_version: HANDOVER_FORMAT_VERSION,
+            ranges: Vec::new(),
+        };
+        let sockets = Sockets {
+            proto_server_listener: TcpListener::bind("127.0.0.1:0").unwrap(),
+            fast_network_socket: FastNetworkSocket::Tcp(TcpListener::bind("127.0.0.1:0").unwrap()),
+        };
+        let fast_network_addr = sockets.fast_network_socket.local_addr().unwrap();
+        let (old, new) = UnixStream::pair().unwrap();
+        let sender = std::thread::spawn(move || send(&old, &sockets, &state));
+        let (received, _) = receive(&new).unwrap();
+        sender.join().unwrap().unwrap();
+
+        let FastNetworkSocket::Tcp(listener) = received.fast_network_socket else {
+            panic!("the fast network socket was not received as a TCP listener");
+        };
+        assert_eq!(listener.local_addr().unwrap(), fast_network_addr);
+    }
+
+    #[test]
+    fn unknown_format_version_is_refused() {
+        let state = HandoverState {
+            format_version: HANDOVER_FORMAT_VERSION + 1,
+            ranges: Vec::new(),
+        };
+        assert!(soft_states_from_proto(&state).is_err());
+    }
+
+    #[test]
+    fn nothing_to_take_over_from() {
+        let path = std::env::temp_dir().join(format!("atomix-handover-{}", Uuid::new_v4()));
+        assert!(take_over(&path).unwrap().is_none());
+    }
+}
@@ -1,5 +1,9 @@
+use uuid::Uuid;
+
 #[derive(Clone, Copy)]
 pub struct KeyVersion {
     pub epoch: u64,
     pub version_counter: u64,
+    // The transaction that wrote this version.
+    pub transaction_id: Uuid,
 }
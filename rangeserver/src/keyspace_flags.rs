@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{colocation::Colocation, config::Config, keyspace_id::KeyspaceId};
+use proto::universe::{
+    get_keyspace_info_request::KeyspaceInfoSearchField, universe_client::UniverseClient,
+    GetKeyspaceInfoRequest,
+};
+use tokio::sync::{Mutex, OnceCell};
+use tonic::transport::{Channel, Endpoint};
+use tracing::warn;
+
+use crate::validation::ValidationPolicy;
+
+// How long a cached flag is trusted before asking the universe again. This
+// bounds how long it takes for a keyspace to effectively become read-only
+// after it was marked as such.
+const FLAG_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy)]
+struct Flags {
+    read_only: bool,
+    optimistic_reads: bool,
+    validation_policy: Option<ValidationPolicy>,
+    colocation: Option<Colocation>,
+    // Keyspace ids are never reused, so once a keyspace is gone it is gone
+    // for good, and this is never re-fetched.
+    exists: bool,
+}
+
+struct CachedFlags {
+    flags: Flags,
+    fetched_at: Instant,
+}
+
+/// Caches per-keyspace flags (whether it is read-only, whether its reads are
+/// optimistic, how its writes are validated, which keys it colocates, and
+/// whether it still exists) fetched from the universe.
+///
+/// If the universe can't be reached the last known value is used, or the
+/// keyspace is assumed to exist and be writable if it was never fetched, so a
+/// universe outage does not make every keyspace unavailable for writes.
+///
+/// All fetches share one connection to the universe, and a keyspace is only
+/// fetched by one caller at a time: the others wait for its result.
+pub struct KeyspaceFlags {
+    universe_addr: String,
+    client: OnceCell<UniverseClient<Channel>>,
+    cache: Mutex<HashMap<KeyspaceId, Arc<Mutex<Option<CachedFlags>>>>>,
+}
+
+impl KeyspaceFlags {
+    pub fn new(config: &Config) -> KeyspaceFlags {
+        KeyspaceFlags {
+            universe_addr: format!("http://{}", config.universe.proto_server_addr),
+            client: OnceCell::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Connects on first use, and reconnects by itself after that.
+    async fn client(
+        &self,
+    ) -> Result<UniverseClient<Channel>, Box<dyn std::error::Error + Send + Sync>> {
+        let client = self
+            .client
+            .get_or_try_init(|| async {
+                let endpoint = Endpoint::from_shared(self.universe_addr.clone())?;
+                Ok::<_, tonic::transport::Error>(UniverseClient::new(endpoint.connect_lazy()))
+            })
+            .await?;
+        Ok(client.clone())
+    }
+
+    pub async fn is_read_only(&self, keyspace_id: KeyspaceId) -> bool {
+        self.flags(keyspace_id).await.read_only
+    }
+
+    /// Whether reads in the keyspace skip locking, see `ReadMode::Optimistic`.
+    /// This is fixed when the keyspace is created.
+    pub async fn has_optimistic_reads(&self, keyspace_id: KeyspaceId) -> bool {
+        self.flags(keyspace_id).await.optimistic_reads
+    }
+
+    /// The checks writes to the keyspace must pass, if any. Like read-only
+    /// mode, a new policy takes up to `FLAG_TTL` to be enforced.
+    pub async fn validation_policy(&self, keyspace_id: KeyspaceId) -> Option<ValidationPolicy> {
+        self.flags(keyspace_id).await.validation_policy
+    }
+
+    /// Which keys of the keyspace must stay in the same range, if any.
+    pub async fn colocation(&self, keyspace_id: KeyspaceId) -> Option<Colocation> {
+        self.flags(keyspace_id).await.colocation
+    }
+
+    /// False if the keyspace was dropped. A transaction that resolved the
+    /// keyspace before that must not write into its ranges any more.
+    pub async fn exists(&self, keyspace_id: KeyspaceId) -> bool {
+        self.flags(keyspace_id).await.exists
+    }
+
+    async fn flags(&self, keyspace_id: KeyspaceId) -> Flags {
+        let entry = self
+            .cache
+            .lock()
+            .await
+            .entry(keyspace_id)
+            .or_default()
+            .clone();
+        // Held across the fetch, so that callers missing the cache together
+        // wait for the first one's fetch instead of each doing their own.
+        let mut cached = entry.lock().await;
+        let last_known = match cached.as_ref() {
+            Some(cached) if !cached.flags.exists => return cached.flags,
+            Some(cached) if cached.fetched_at.elapsed() < FLAG_TTL => return cached.flags,
+            Some(cached) => Some(cached.flags),
+            None => None,
+        };
+        let flags = match self.fetch_flags(keyspace_id).await {
+            Ok(flags) => flags,
+            Err(e) => {
+                warn!(
+                    "Failed to fetch flags of keyspace {}, using last known value: {}",
+                    keyspace_id.id, e
+                );
+                last_known.unwrap_or(Flags {
+                    read_only: false,
+                    optimistic_reads: false,
+                    validation_policy: None,
+                    colocation: None,
+                    exists: true,
+                })
+            }
+        };
+        // Cache failures too, to avoid hammering an unavailable universe.
+        *cached = Some(CachedFlags {
+            flags,
+            fetched_at: Instant::now(),
+        });
+        flags
+    }
+
+    async fn fetch_flags(
+        &self,
+        keyspace_id: KeyspaceId,
+    ) -> Result<Flags, Box<dyn std::error::Error + Send + Sync>> {
+        let mut client = self.client().await?;
+        let response = client
+            .get_keyspace_info(GetKeyspaceInfoRequest {
+                keyspace_info_search_field: Some(KeyspaceInfoSearchField::KeyspaceId(
+                    keyspace_id.id.to_string(),
+                )),
+            })
+            .await;
+        let keyspace_info = match response {
+            Ok(response) => response.into_inner().keyspace_info,
+            Err(status) if status.code() == tonic::Code::NotFound => None,
+            Err(status) => return Err(status.into()),
+        };
+        Ok(match keyspace_info {
+            Some(info) => Flags {
+                read_only: info.read_only,
+                optimistic_reads: info.optimistic_reads,
+                validation_policy: info
+                    .validation_policy
+                    .as_ref()
+                    .map(ValidationPolicy::from_proto),
+                colocation: info.colocation.as_ref().and_then(Colocation::from_proto),
+                exists: true,
+            },
+            None => Flags {
+                read_only: false,
+                optimistic_reads: false,
+                validation_policy: None,
+                colocation: None,
+                exists: false,
+            },
+        })
+    }
+}
@@ -1,12 +1,23 @@
 pub mod cache;
+pub mod checksum;
+pub mod compaction;
+pub mod conflict_stats;
+pub mod decision_log;
+pub mod embedded;
 pub mod epoch_supplier;
 pub mod error;
 pub mod for_testing;
+pub mod handover;
 mod key_version;
+mod keyspace_flags;
+pub mod mirroring;
 mod prefetching_buffer;
+pub mod preflight;
 mod range_manager;
+pub mod read_stats;
 pub mod server;
 pub mod storage;
 pub mod transaction_abort_reason;
+mod validation;
 mod wal;
 mod warden_handler;
@@ -1,21 +1,32 @@
 use clap::Parser;
 use std::{
     fs::read_to_string,
-    net::{ToSocketAddrs, UdpSocket},
+    net::{SocketAddr, TcpListener as StdTcpListener, ToSocketAddrs, UdpSocket},
+    path::Path,
     sync::Arc,
 };
 
 use common::{
-    config::Config,
+    config::{Config, FastNetworkTransport},
     host_info::{HostIdentity, HostInfo},
-    network::{fast_network::FastNetwork, for_testing::udp_fast_network::UdpFastNetwork},
+    network::{
+        fast_network::FastNetwork,
+        for_testing::udp_fast_network::UdpFastNetwork,
+        quic_fast_network::{QuicFastNetwork, QuicTls},
+        tcp_fast_network::TcpFastNetwork,
+    },
     region::{Region, Zone},
 };
-use rangeserver::{cache::memtabledb::MemTableDB, server::Server, storage::cassandra::Cassandra};
+use rangeserver::{
+    cache::memtabledb::MemTableDB,
+    handover::{self, FastNetworkSocket, Sockets},
+    server::Server,
+    storage::cassandra::Cassandra,
+};
 use tokio::net::TcpListener;
 use tokio::runtime::Builder;
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::{error, info, warn};
 
 #[derive(Parser, Debug)]
 #[command(name = "rangeserver")]
@@ -35,15 +46,27 @@ struct Args {
 
     #[arg(long, default_value = "127.0.0.1:50054")]
     address: String,
+
+    /// Unix socket to take over from the range server running on this host,
+    /// if any, and to hand over to the next one on. See
+    /// `rangeserver::handover`.
+    #[arg(long)]
+    handover_socket: Option<String>,
+
+    /// Attribute of this host that keyspaces can require of the range
+    /// servers their ranges go on, as key=value. Can be repeated.
+    #[arg(long = "label", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
 }
 
-fn main() {
-    tracing_subscriber::fmt::init();
-    let args = Args::parse();
-    let config: Config = serde_json::from_str(&read_to_string(&args.config).unwrap()).unwrap();
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    label
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected key=value, got {}", label))
+}
 
-    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
-    let runtime_handle = runtime.handle().clone();
+fn bind_sockets(config: &Config) -> Sockets {
     let fast_network_addr = config
         .range_server
         .fast_network_addr
@@ -51,13 +74,97 @@ fn main() {
         .unwrap()
         .next()
         .unwrap();
-    let fast_network = Arc::new(UdpFastNetwork::new(
-        UdpSocket::bind(fast_network_addr).unwrap(),
-    ));
-    let fast_network_clone = fast_network.clone();
+    let fast_network_socket = match config.range_server.fast_network_transport {
+        FastNetworkTransport::Udp | FastNetworkTransport::Quic => {
+            UdpSocket::bind(fast_network_addr).map(FastNetworkSocket::Udp)
+        }
+        FastNetworkTransport::Tcp => {
+            StdTcpListener::bind(fast_network_addr).map(FastNetworkSocket::Tcp)
+        }
+    }
+    .unwrap_or_else(|e| {
+        panic!(
+            "failed to bind range_server.fast_network_addr {}: {}",
+            fast_network_addr, e
+        )
+    });
+    let proto_server_addr = config
+        .range_server
+        .proto_server_addr
+        .to_socket_addrs()
+        .unwrap()
+        .next()
+        .unwrap();
+    let proto_server_listener = StdTcpListener::bind(proto_server_addr).unwrap_or_else(|e| {
+        panic!(
+            "failed to bind range_server.proto_server_addr {}: {}",
+            proto_server_addr, e
+        )
+    });
+    Sockets {
+        proto_server_listener,
+        fast_network_socket,
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let config: Config = serde_json::from_str(&read_to_string(&args.config).unwrap()).unwrap();
+
+    let handover_socket = args.handover_socket.clone();
+    let taken_over = handover_socket.as_deref().and_then(|path| {
+        handover::take_over(Path::new(path))
+            .unwrap_or_else(|e| panic!("failed to take over from {}: {}", path, e))
+    });
+    let (sockets, handover_state) = match taken_over {
+        Some((sockets, state)) => (sockets, Some(state)),
+        None => (bind_sockets(&config), None),
+    };
+    // The process taking over from this one gets copies of the sockets.
+    let successor_sockets = handover_socket
+        .as_ref()
+        .map(|_| sockets.try_clone().unwrap());
+
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let runtime_handle = runtime.handle().clone();
+    // The epoch publishers only serve over UDP, so with TCP or QUIC epochs
+    // are read on a network of their own.
+    let epoch_network = |addr: SocketAddr| -> Arc<dyn FastNetwork> {
+        let addr = SocketAddr::new(addr.ip(), 0);
+        Arc::new(UdpFastNetwork::new(UdpSocket::bind(addr).unwrap()))
+    };
+    let (fast_network, epoch_network): (Arc<dyn FastNetwork>, Arc<dyn FastNetwork>) = match (
+        sockets.fast_network_socket,
+        config.range_server.fast_network_transport,
+    ) {
+        (FastNetworkSocket::Tcp(listener), _) => {
+            let epoch_network = epoch_network(listener.local_addr().unwrap());
+            (Arc::new(TcpFastNetwork::new(listener)), epoch_network)
+        }
+        (FastNetworkSocket::Udp(socket), FastNetworkTransport::Quic) => {
+            let tls = QuicTls::from_config(&config.quic)
+                .unwrap_or_else(|e| panic!("failed to load the QUIC certificate: {}", e));
+            let epoch_network = epoch_network(socket.local_addr().unwrap());
+            (
+                Arc::new(QuicFastNetwork::new(socket, &tls, runtime_handle.clone()).unwrap()),
+                epoch_network,
+            )
+        }
+        (FastNetworkSocket::Udp(socket), _) => {
+            let fast_network = Arc::new(UdpFastNetwork::new(socket));
+            (fast_network.clone(), fast_network)
+        }
+    };
+    let mut polled = vec![fast_network.clone()];
+    if !Arc::ptr_eq(&fast_network, &epoch_network) {
+        polled.push(epoch_network.clone());
+    }
     runtime.spawn(async move {
         loop {
-            fast_network_clone.poll();
+            for network in &polled {
+                network.poll();
+            }
             tokio::task::yield_now().await
         }
     });
@@ -76,6 +183,7 @@ fn main() {
             },
             address: args.address.parse().unwrap(),
             warden_connection_epoch: 0,
+            labels: args.labels.into_iter().collect(),
         };
         let region_config = config.regions.get(&host_info.identity.zone.region).unwrap();
         let publisher_set = region_config
@@ -83,21 +191,20 @@ fn main() {
             .iter()
             .find(|&s| s.zone == host_info.identity.zone)
             .unwrap();
-        let proto_server_addr = config
-            .range_server
-            .proto_server_addr
-            .to_socket_addrs()
-            .unwrap()
-            .next()
-            .unwrap();
-        let proto_server_listener = TcpListener::bind(proto_server_addr).await.unwrap();
+        sockets.proto_server_listener.set_nonblocking(true).unwrap();
+        let proto_server_listener = TcpListener::from_std(sockets.proto_server_listener).unwrap();
         info!("Connecting to Cassandra at {}", config.cassandra.cql_addr);
-        let storage = Arc::new(Cassandra::new(config.cassandra.cql_addr.to_string()).await);
+        let datacenter = config.cassandra_datacenter(&host_info.identity.zone.region);
+        match &datacenter {
+            Some(datacenter) => info!("Using local Cassandra datacenter {}", datacenter),
+            None => info!("No local Cassandra datacenter configured"),
+        }
+        let storage = Arc::new(Cassandra::from_config(&config.cassandra, datacenter).await);
         // TODO: set number of threads and pin to cores.
         let bg_runtime = Builder::new_multi_thread().enable_all().build().unwrap();
 
         let epoch_supplier = Arc::new(rangeserver::epoch_supplier::reader::Reader::new(
-            fast_network.clone(),
+            epoch_network,
             runtime_handle,
             bg_runtime.handle().clone(),
             publisher_set.clone(),
@@ -110,14 +217,40 @@ fn main() {
             epoch_supplier,
             bg_runtime.handle().clone(),
         );
+        if let Some(state) = handover_state {
+            if let Err(e) = server.restore_handover_state(&state) {
+                warn!(
+                    "Ignoring the state handed over by the previous range server: {}",
+                    e
+                );
+            }
+        }
+        let serve_cancellation_token = CancellationToken::new();
         let res = Server::start(
-            server,
+            server.clone(),
             fast_network,
-            CancellationToken::new(),
+            serve_cancellation_token.clone(),
             proto_server_listener,
         )
         .await
-        .unwrap();
+        .unwrap_or_else(|e| {
+            error!("{}", e);
+            std::process::exit(1)
+        });
+        if let (Some(path), Some(sockets)) = (handover_socket, successor_sockets) {
+            tokio::spawn(async move {
+                let path = Path::new(&path);
+                match handover::serve_successor(path, server, sockets, serve_cancellation_token)
+                    .await
+                {
+                    Ok(()) => {
+                        info!("Handed over to the new range server, exiting");
+                        std::process::exit(0)
+                    }
+                    Err(e) => error!("Failed to hand over to a new range server: {}", e),
+                }
+            });
+        }
         res.await.unwrap()
     });
     info!("Starting RangeServer...");
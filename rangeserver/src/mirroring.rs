@@ -0,0 +1,209 @@
+//! Copies a sample of the gets a range server serves to a shadow range
+//! server, e.g. one running a new version or storing its ranges in a new
+//! backend, and logs where the shadow's answers diverge. The shadow reads the
+//! keys outside of any transaction, with `BulkGet`, so mirroring takes no
+//! locks on it, and the gets it copies are answered without waiting for it.
+//!
+//! The shadow reads the latest committed values a little after the range
+//! server did, so commits in between also show up as divergences. Those are
+//! rare for a given key, while divergences that keep coming back for the
+//! same keys are the ones worth looking into.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use bytes::Bytes;
+use common::config::MirroringConfig;
+use common::full_range_id::FullRangeId;
+use proto::rangeserver::{
+    bulk_get_request, range_server_client::RangeServerClient, BulkGetRequest, RangeId,
+};
+use tokio::sync::Semaphore;
+use tonic::transport::{Channel, Endpoint};
+use tracing::warn;
+
+// Divergent keys logged per get, on top of their count.
+const MAX_LOGGED_KEYS: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MirrorStats {
+    /// Gets copied to the shadow and answered by it.
+    pub mirrored: u64,
+    /// Copied gets the shadow answered differently.
+    pub diverged: u64,
+    /// Copied gets the shadow failed to answer.
+    pub failed: u64,
+    /// Gets sampled but not copied, as too many were in flight.
+    pub skipped: u64,
+}
+
+#[derive(Default)]
+struct Counts {
+    mirrored: AtomicU64,
+    diverged: AtomicU64,
+    failed: AtomicU64,
+    skipped: AtomicU64,
+}
+
+pub(crate) struct Mirror {
+    endpoint: Endpoint,
+    // Connected on first use, from within the runtime.
+    client: OnceLock<RangeServerClient<Channel>>,
+    fraction: f64,
+    in_flight: Arc<Semaphore>,
+    counts: Counts,
+}
+
+impl Mirror {
+    /// Returns None if mirroring is disabled, or the shadow's address is
+    /// invalid.
+    pub fn new(config: &MirroringConfig) -> Option<Arc<Mirror>> {
+        let addr = config.shadow_proto_addr.as_ref()?;
+        if config.fraction <= 0.0 || config.max_in_flight == 0 {
+            return None;
+        }
+        let endpoint = match Endpoint::from_shared(format!("http://{}", addr)) {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                warn!(shadow = %addr, "not mirroring gets, invalid shadow address: {}", e);
+                return None;
+            }
+        };
+        Some(Arc::new(Mirror {
+            endpoint,
+            client: OnceLock::new(),
+            fraction: config.fraction.min(1.0),
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight)),
+            counts: Counts::default(),
+        }))
+    }
+
+    /// Copies the get that read `reads` from the range to the shadow if it is
+    /// sampled, and compares the answers in the background.
+    pub fn maybe_mirror(self: &Arc<Self>, range_id: FullRangeId, reads: &[(Bytes, Option<Bytes>)]) {
+        if reads.is_empty() || rand::random::<f64>() >= self.fraction {
+            return;
+        }
+        let permit = match self.in_flight.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.counts.skipped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        let mirror = self.clone();
+        let reads = reads.to_vec();
+        tokio::spawn(async move {
+            let _permit = permit;
+            match mirror.shadow_get(range_id, &reads).await {
+                Ok(shadow) => {
+                    mirror.counts.mirrored.fetch_add(1, Ordering::Relaxed);
+                    let divergent = divergent_keys(&reads, &shadow);
+                    if !divergent.is_empty() {
+                        mirror.counts.diverged.fetch_add(1, Ordering::Relaxed);
+                        let logged: Vec<_> = divergent.iter().take(MAX_LOGGED_KEYS).collect();
+                        warn!(
+                            range_id = ?range_id,
+                            "shadow diverged on {} of {} keys: {:?}",
+                            divergent.len(),
+                            reads.len(),
+                            logged
+                        );
+                    }
+                }
+                Err(_) => {
+                    mirror.counts.failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+    }
+
+    async fn shadow_get(
+        &self,
+        range_id: FullRangeId,
+        reads: &[(Bytes, Option<Bytes>)],
+    ) -> Result<HashMap<Bytes, Option<Bytes>>, tonic::Status> {
+        let mut client = self
+            .client
+            .get_or_init(|| RangeServerClient::new(self.endpoint.connect_lazy()))
+            .clone();
+        let request = BulkGetRequest {
+            range: Some(RangeId {
+                keyspace_id: range_id.keyspace_id.id.to_string(),
+                range_id: range_id.range_id.to_string(),
+            }),
+            selection: Some(bulk_get_request::Selection::Keys(bulk_get_request::Keys {
+                keys: reads.iter().map(|(key, _)| key.to_vec()).collect(),
+            })),
+            chunk_size: 0,
+        };
+        let mut stream = client.bulk_get(request).await?.into_inner();
+        let mut shadow = HashMap::new();
+        while let Some(chunk) = stream.message().await? {
+            for record in chunk.records {
+                shadow.insert(Bytes::from(record.key), record.value.map(Bytes::from));
+            }
+        }
+        Ok(shadow)
+    }
+
+    pub fn stats(&self) -> MirrorStats {
+        MirrorStats {
+            mirrored: self.counts.mirrored.load(Ordering::Relaxed),
+            diverged: self.counts.diverged.load(Ordering::Relaxed),
+            failed: self.counts.failed.load(Ordering::Relaxed),
+            skipped: self.counts.skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// The keys whose value the shadow did not answer the same, including those
+// it did not answer at all.
+fn divergent_keys(
+    reads: &[(Bytes, Option<Bytes>)],
+    shadow: &HashMap<Bytes, Option<Bytes>>,
+) -> Vec<Bytes> {
+    reads
+        .iter()
+        .filter(|(key, value)| shadow.get(key) != Some(value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::config::HostPort;
+    use std::str::FromStr;
+
+    fn key(k: &'static str) -> Bytes {
+        Bytes::from_static(k.as_bytes())
+    }
+
+    #[test]
+    fn missing_and_different_values_diverge() {
+        let reads = vec![
+            (key("a"), Some(key("1"))),
+            (key("b"), None),
+            (key("c"), Some(key("3"))),
+            (key("d"), None),
+        ];
+        let shadow = HashMap::from([
+            (key("a"), Some(key("1"))),
+            (key("b"), Some(key("2"))),
+            (key("d"), None),
+        ]);
+        assert_eq!(divergent_keys(&reads, &shadow), vec![key("b"), key("c")]);
+    }
+
+    #[test]
+    fn mirroring_needs_a_shadow_and_a_fraction() {
+        let mut config = MirroringConfig::default();
+        assert!(Mirror::new(&config).is_none());
+        config.shadow_proto_addr = Some(HostPort::from_str("127.0.0.1:50100").unwrap());
+        assert!(Mirror::new(&config).is_some());
+        config.fraction = 0.0;
+        assert!(Mirror::new(&config).is_none());
+    }
+}
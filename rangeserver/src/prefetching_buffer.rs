@@ -1,10 +1,14 @@
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use common::key_range::KeyRange;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
 use tokio::sync::{watch, Mutex};
 use uuid::Uuid;
 
+use crate::storage::is_expired;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum KeyState {
     Fetched,        // Key has been fetched and is in the BTree
@@ -15,6 +19,7 @@ pub enum KeyState {
 #[derive(Debug)]
 struct State {
     pub prefetch_store: BTreeMap<Bytes, Bytes>, // stores key / value
+    pub expiries: HashMap<Bytes, DateTime<Utc>>, // stores key -> expiry, for values that expire
     pub key_state: HashMap<Bytes, KeyState>,    // stores key -> current fetch state
     pub transaction_keys: HashMap<Uuid, BTreeSet<Bytes>>, // stores transaction_id -> set of requested keys
     pub key_transactions: HashMap<Bytes, BTreeSet<Uuid>>, // stores key -> set of transaction_ids
@@ -32,6 +37,7 @@ impl PrefetchingBuffer {
         PrefetchingBuffer {
             state: Mutex::new(State {
                 prefetch_store: BTreeMap::new(),
+                expiries: HashMap::new(),
                 key_state: HashMap::new(),
                 transaction_keys: HashMap::new(),
                 key_transactions: HashMap::new(),
@@ -42,9 +48,18 @@ impl PrefetchingBuffer {
         }
     }
 
-    /// Returns the value for a key from the prefetch_store
-    pub async fn get_from_buffer(&self, key: Bytes) -> Result<Option<Bytes>, ()> {
+    /// Returns the value for a key from the prefetch_store. A value that
+    /// expired by `now` is not returned, so the caller reads it from storage
+    /// instead.
+    pub async fn get_from_buffer(
+        &self,
+        key: Bytes,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Bytes>, ()> {
         let cur_state = self.state.lock().await;
+        if is_expired(cur_state.expiries.get(&key).copied(), now) {
+            return Ok(None);
+        }
         if let Some(KeyState::Fetched) = cur_state.key_state.get(&key.clone()) {
             let val = cur_state.prefetch_store.get(&key);
             if let Some(value) = val {
@@ -57,6 +72,30 @@ impl PrefetchingBuffer {
         }
     }
 
+    /// Returns the fetched keys within `key_range`, each with its value, or
+    /// None if it was fetched but does not exist or expired by `now`
+    pub async fn fetched_entries(
+        &self,
+        key_range: &KeyRange,
+        now: DateTime<Utc>,
+    ) -> BTreeMap<Bytes, Option<Bytes>> {
+        let cur_state = self.state.lock().await;
+        cur_state
+            .key_state
+            .iter()
+            .filter(|(key, state)| {
+                **state == KeyState::Fetched && key_range.includes((*key).clone())
+            })
+            .map(|(key, _)| {
+                let value = match is_expired(cur_state.expiries.get(key).copied(), now) {
+                    true => None,
+                    false => cur_state.prefetch_store.get(key).cloned(),
+                };
+                (key.clone(), value)
+            })
+            .collect()
+    }
+
     /// Processes prefetch request by adding transaction / key request to
     /// the appropriate data structures and returns the state of they key,
     /// which is either Requested, Loading, or Fetched. If Loading, the
@@ -200,6 +239,7 @@ impl PrefetchingBuffer {
         &self,
         key: Bytes,
         value: Option<Bytes>,
+        expires_at: Option<DateTime<Utc>>,
         fetch_sequence_number: u64,
     ) {
         let mut cur_state = self.state.lock().await;
@@ -211,6 +251,7 @@ impl PrefetchingBuffer {
                 if let Some(data) = value {
                     cur_state.prefetch_store.insert(key.clone(), data);
                 }
+                set_expiry(&mut cur_state, key.clone(), expires_at);
                 // Update key_state to reflect fetch completion
                 cur_state.key_state.insert(key.clone(), KeyState::Fetched);
                 // Notify all watchers of the state change
@@ -265,11 +306,12 @@ impl PrefetchingBuffer {
     }
 
     /// Update the prefetch_store and update the key_state
-    pub async fn upsert(&self, key: Bytes, value: Bytes) {
+    pub async fn upsert(&self, key: Bytes, value: Bytes, expires_at: Option<DateTime<Utc>>) {
         let mut cur_state = self.state.lock().await;
         // If key is in key_state, it is being requested by a transaction
         if cur_state.key_state.contains_key(&key.clone()) {
             let _ = cur_state.prefetch_store.insert(key.clone(), value);
+            set_expiry(&mut cur_state, key.clone(), expires_at);
             // If the key is still loading in a prefetch, change to fetched
             if let KeyState::Loading(_) = cur_state.key_state.get(&key).unwrap() {
                 cur_state.key_state.insert(key.clone(), KeyState::Fetched);
@@ -288,6 +330,7 @@ impl PrefetchingBuffer {
         let mut cur_state = self.state.lock().await;
         if cur_state.key_state.contains_key(&key.clone()) {
             let _ = cur_state.prefetch_store.remove(&key.clone());
+            let _ = cur_state.expiries.remove(&key);
             // If the key is still loading in a prefetch, change to fetched
             if let KeyState::Loading(_) = cur_state.key_state.get(&key).unwrap() {
                 cur_state.key_state.insert(key.clone(), KeyState::Fetched);
@@ -304,6 +347,7 @@ impl PrefetchingBuffer {
     /// It needs be called with the lock held
     async fn evict_key(&self, key: Bytes, state: &mut State) {
         let _ = state.prefetch_store.remove(&key);
+        let _ = state.expiries.remove(&key);
         let _ = state.key_state.remove(&key); // might be None
     }
 
@@ -329,6 +373,17 @@ impl PrefetchingBuffer {
     }
 }
 
+fn set_expiry(state: &mut State, key: Bytes, expires_at: Option<DateTime<Utc>>) {
+    match expires_at {
+        Some(expires_at) => {
+            state.expiries.insert(key, expires_at);
+        }
+        None => {
+            state.expiries.remove(&key);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -499,7 +554,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Check that the future is now marked as ready
@@ -532,7 +587,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Check that processing a new request for the same key returns it as fetched
@@ -567,7 +622,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Check that the value in the BTree for that key is as expected
@@ -610,7 +665,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Process transaction is complete for fake_transaction with a new value
@@ -620,7 +675,7 @@ mod tests {
         // Update buffer
         let new_value = Bytes::from("updated testing value");
         prefetching_buffer
-            .upsert(fake_key.clone(), new_value.clone())
+            .upsert(fake_key.clone(), new_value.clone(), None)
             .await;
 
         let cur_state = prefetching_buffer.state.lock().await;
@@ -630,6 +685,61 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_expired_value_is_not_returned() {
+        let prefetching_buffer = PrefetchingBuffer::new();
+
+        let fake_transaction = Uuid::from_str("fae86b67-36dd-41fa-a201-f18d3051bca5").unwrap();
+        let fake_key = Bytes::from("testing!");
+
+        let _ = prefetching_buffer
+            .process_prefetch_request(fake_transaction, fake_key.clone())
+            .await;
+
+        let now = Utc::now();
+        let later = now + chrono::Duration::hours(2);
+        let fake_value = Some(Bytes::from("testing value"));
+        let cur_state = prefetching_buffer.state.lock().await;
+        let n = cur_state.fetch_sequence_number;
+        drop(cur_state);
+        prefetching_buffer
+            .fetch_complete(
+                fake_key.clone(),
+                fake_value.clone(),
+                Some(now + chrono::Duration::hours(1)),
+                n,
+            )
+            .await;
+        assert_eq!(
+            prefetching_buffer
+                .get_from_buffer(fake_key.clone(), now)
+                .await,
+            Ok(fake_value)
+        );
+        assert_eq!(
+            prefetching_buffer
+                .get_from_buffer(fake_key.clone(), later)
+                .await,
+            Ok(None)
+        );
+        assert_eq!(
+            prefetching_buffer
+                .fetched_entries(&KeyRange::all(), later)
+                .await,
+            BTreeMap::from([(fake_key.clone(), None)])
+        );
+
+        // Writing the key without a TTL clears its expiry.
+        let new_value = Bytes::from("updated testing value");
+        prefetching_buffer
+            .upsert(fake_key.clone(), new_value.clone(), None)
+            .await;
+        assert_eq!(
+            prefetching_buffer.get_from_buffer(fake_key, later).await,
+            Ok(Some(new_value))
+        );
+    }
+
     #[tokio::test]
     async fn test_process_transaction_delete() {
         let prefetching_buffer = PrefetchingBuffer::new();
@@ -663,7 +773,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Process transaction is complete for fake_transaction with a new value
@@ -710,7 +820,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Process transaction is complete for fake_transaction with a new value
@@ -720,7 +830,7 @@ mod tests {
         // Update buffer
         let new_value = Bytes::from("updated testing value");
         let _ = prefetching_buffer
-            .upsert(fake_key.clone(), new_value.clone())
+            .upsert(fake_key.clone(), new_value.clone(), None)
             .await;
 
         // Remove prefetch request
@@ -796,7 +906,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Process transaction is complete for fake_transaction with a new value
@@ -806,7 +916,7 @@ mod tests {
         // Update buffer
         let new_value = Bytes::from("updated testing value");
         let _ = prefetching_buffer
-            .upsert(fake_key.clone(), new_value.clone())
+            .upsert(fake_key.clone(), new_value.clone(), None)
             .await;
 
         // Remove prefetch request
@@ -854,7 +964,7 @@ mod tests {
         let n = cur_state.fetch_sequence_number;
         drop(cur_state);
         let _ = prefetching_buffer
-            .fetch_complete(fake_key.clone(), fake_value.clone(), n)
+            .fetch_complete(fake_key.clone(), fake_value.clone(), None, n)
             .await;
 
         // Process transaction is complete for fake_transaction with a new value
@@ -864,7 +974,7 @@ mod tests {
         // Update buffer
         let new_value = Bytes::from("updated testing value");
         let _ = prefetching_buffer
-            .upsert(fake_key.clone(), new_value.clone())
+            .upsert(fake_key.clone(), new_value.clone(), None)
             .await;
 
         // Remove prefetch request
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// Outcome of a single startup check.
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was observed if the check passed, or what went wrong and how to
+    /// fix it if it did not.
+    pub detail: String,
+    pub elapsed_ms: u128,
+}
+
+/// Readiness report produced by the checks the range server runs before
+/// starting to serve.
+#[derive(Debug, Default, Serialize)]
+pub struct PreflightReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl PreflightReport {
+    pub fn record(&mut self, name: &'static str, started: Instant, result: Result<String, String>) {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.checks.push(CheckResult {
+            name,
+            passed,
+            detail,
+            elapsed_ms: started.elapsed().as_millis(),
+        });
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "range server preflight checks failed:")?;
+        for check in self.checks.iter().filter(|c| !c.passed) {
+            write!(f, "\n  {}: {}", check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PreflightReport {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_failed_checks() {
+        let mut report = PreflightReport::default();
+        report.record("config", Instant::now(), Ok("config is consistent".into()));
+        assert!(report.is_ready());
+        report.record("storage", Instant::now(), Err("storage is down".into()));
+        assert!(!report.is_ready());
+        let message = report.to_string();
+        assert!(message.contains("storage: storage is down"));
+        assert!(!message.contains("config"));
+    }
+}
@@ -1,10 +1,21 @@
 pub mod r#impl;
 mod lock_table;
+mod read_versions;
+pub mod storage_health;
+mod write_stall;
 
+use crate::checksum::{ChecksumReport, ChecksumStats};
+use crate::compaction::{CompactionOutcome, CompactionStats, ExpiryOutcome};
+use crate::conflict_stats::ConflictStats;
 use crate::error::Error;
+use crate::read_stats::ReadStats;
+use crate::storage::RecordVersion;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use common::key_range::KeyRange;
 use common::transaction_info::TransactionInfo;
 use flatbuf::rangeserver_flatbuffers::range_server::*;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tonic::async_trait;
 use uuid::Uuid;
@@ -14,11 +25,125 @@ pub struct GetResult {
     pub leader_sequence_number: i64,
 }
 
+pub struct ScanResult {
+    pub records: Vec<(Bytes, Bytes)>,
+    pub leader_sequence_number: i64,
+}
+
 pub struct PrepareResult {
     pub highest_known_epoch: u64,
     pub epoch_lease: (u64, u64),
 }
 
+pub struct InFlightTransaction {
+    pub id: Uuid,
+    pub labels: BTreeMap<String, String>,
+    /// When the transaction acquired its locks, None if it holds none.
+    pub lock_acquired: Option<DateTime<Utc>>,
+    pub lock_count: u32,
+    pub prepared: bool,
+    /// When the transaction prepared on the range, None if it has not.
+    pub prepared_at: Option<DateTime<Utc>>,
+}
+
+/// How full the lock table of a range is. A holder that stays old while
+/// waiters pile up usually means its coordinator abandoned it.
+pub struct LockTableOccupancy {
+    /// How long the current holder has held the lock, None if it is free.
+    pub holder_age: Option<std::time::Duration>,
+    pub waiters: usize,
+    pub waiting_for_release: usize,
+    pub spilled_waiters: usize,
+    /// Lock requests rejected with `Overloaded` since the range was loaded.
+    pub rejected: u64,
+}
+
+/// Whether applying commits to storage is stalled on a range, see
+/// `WriteStallConfig`.
+pub struct WriteStallStatus {
+    /// How long the range has been stalled, None if it is not.
+    pub stalled_for: Option<std::time::Duration>,
+    pub last_apply_latency: std::time::Duration,
+    /// Times the range stalled since it was loaded.
+    pub stalls: u64,
+}
+
+/// A consistent view of a range, taken while no commit is being applied to
+/// it. See `RangeSnapshot` in rangeserver.proto for the exported format.
+pub struct RangeSnapshot {
+    pub key_range: KeyRange,
+    pub leader_sequence_number: u64,
+    pub epoch_lease: (u64, u64),
+    pub highest_known_epoch: u64,
+    /// Ordered by key and then newest first.
+    pub records: Vec<(Bytes, RecordVersion)>,
+    /// The raw PrepareRequest of each transaction prepared on the range.
+    pub prepared: Vec<(Uuid, Bytes)>,
+}
+
+/// What was left running on a range once it stopped draining.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrainOutcome {
+    /// True if every transaction finished before the timeout.
+    pub drained: bool,
+    /// Transactions still prepared on the range, which the next range server
+    /// to load it takes over.
+    pub prepared: usize,
+    /// Transactions holding or waiting for the range lock without having
+    /// prepared. Waiters are let go with `TransactionLockLost` when the
+    /// drain times out, and the holder loses the lock when the range is
+    /// unloaded: neither is handed over to the next range server.
+    pub unprepared: usize,
+}
+
+/// One of the two ranges a range is split into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitRange {
+    pub range_id: Uuid,
+    pub key_range: KeyRange,
+}
+
+/// The counters of a range that a process taking over from this one carries
+/// on with, see `HandoverState` in rangeserver.proto.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SoftState {
+    pub conflicts: ConflictStats,
+    pub compactions: u64,
+    pub purged_versions: u64,
+    pub write_stalls: u64,
+    pub lock_requests_rejected: u64,
+}
+
+/// How a read synchronizes with the transactions writing to the range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadMode {
+    /// Take the range lock, so what was read can't change until the
+    /// transaction finishes.
+    Locking,
+    /// Read without locking. The transaction is then aborted at prepare if
+    /// anything it read on the range was written since, so isolation stays
+    /// serializable. Saves waiting for the lock on reads, at the cost of
+    /// aborts under contention.
+    Optimistic,
+    /// Read the values as of an epoch, without locking or checking the reads
+    /// at prepare, e.g. for transactions with snapshot isolation. See
+    /// `IsolationLevel::Snapshot` for the anomalies this allows. Waits for the
+    /// commits that can still land at or before the epoch, so that every read
+    /// at the same epoch sees the same values.
+    AtEpoch(u64),
+}
+
+/// Decides the transactions found prepared on a range when it loads. Only
+/// needed when there are several of them: prepares hold the range lock until
+/// their transaction finishes, so all but one of them are stale, and only
+/// their decisions tell which.
+#[async_trait]
+pub trait PrepareResolver: Send + Sync + 'static {
+    /// Aborts the transaction unless it was decided already. Returns the
+    /// epoch it committed at, or None if it is aborted.
+    async fn abort_unless_decided(&self, transaction_id: Uuid) -> Result<Option<u64>, Error>;
+}
+
 #[async_trait]
 pub trait RangeManager {
     /// Load and manage the range.
@@ -32,7 +157,31 @@ pub trait RangeManager {
     /// Request prefetching a key from storage and pinning to memory.
     async fn prefetch(&self, transaction_id: Uuid, key: Bytes) -> Result<(), Error>;
     /// Get the value associated with a key.
-    async fn get(&self, tx: Arc<TransactionInfo>, key: Bytes) -> Result<GetResult, Error>;
+    async fn get(
+        &self,
+        tx: Arc<TransactionInfo>,
+        key: Bytes,
+        mode: ReadMode,
+    ) -> Result<GetResult, Error>;
+    /// Get up to `limit` records with keys within both `key_range` and the
+    /// range, in key order.
+    async fn scan(
+        &self,
+        tx: Arc<TransactionInfo>,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        mode: ReadMode,
+    ) -> Result<ScanResult, Error>;
+    /// Get the newest committed values of `keys` outside of any transaction,
+    /// so without taking the range lock.
+    async fn read_committed(&self, keys: &[Bytes]) -> Result<Vec<Option<Bytes>>, Error>;
+    /// Get up to `limit` committed records with keys within both `key_range`
+    /// and the range, in key order, outside of any transaction.
+    async fn scan_committed(
+        &self,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error>;
     /// Run the prepare phase of two-phase commit.
     /// If prepare ever returns success, the implementation must be able to
     /// (eventually) commit the transaction no matter what, unless we get an
@@ -45,6 +194,11 @@ pub trait RangeManager {
         tx: Arc<TransactionInfo>,
         prepare: PrepareRequest<'_>,
     ) -> Result<PrepareResult, Error>;
+    /// Run the checks prepare would run for the transaction, without
+    /// preparing it, taking locks or writing anything. Returns the leader
+    /// sequence number of the range, for the caller to compare with the one
+    /// its reads saw.
+    async fn validate(&self, tx: Arc<TransactionInfo>, has_reads: bool) -> Result<i64, Error>;
     /// Abort the transaction.
     async fn abort(&self, tx_id: Uuid, abort: AbortRequest<'_>) -> Result<(), Error>;
     /// Run the commit phase of two-phase commit.
@@ -52,9 +206,73 @@ pub trait RangeManager {
     /// decide the transaction outcome.
     /// A call to commit can fail only for intermittent reasons, and must be
     /// idempotent and safe to retry any number of times.
-    async fn commit(
+    async fn commit(&self, tx_id: Uuid, commit: CommitRequest<'_>) -> Result<(), Error>;
+    /// List the transactions currently holding locks or prepared on the range.
+    async fn list_in_flight_transactions(&self) -> Result<Vec<InFlightTransaction>, Error>;
+    /// Report how full the lock table of the range is.
+    async fn lock_table_occupancy(&self) -> Result<LockTableOccupancy, Error>;
+    /// Take a snapshot of the records, metadata and prepared transactions of
+    /// the range. Commits wait until it is taken.
+    async fn export_snapshot(&self) -> Result<RangeSnapshot, Error>;
+    /// Split the range at `split_key` into two new ranges, the second one
+    /// starting at `split_key`, and unload it. The records of the range are
+    /// copied into the new ranges, which are left for the warden to assign.
+    /// Fails with `RangeBusy` if any transaction holds or waits for the lock
+    /// of the range or is prepared on it.
+    async fn split(&self, split_key: Bytes) -> Result<[SplitRange; 2], Error>;
+    /// Extend the epoch lease of the range to at least `min_upper_bound`, so
+    /// that the transaction, which must be prepared on the range, can commit
+    /// in that epoch. The lease is only extended as far as the config allows,
+    /// so callers must check the returned lease.
+    async fn extend_epoch_lease(
         &self,
         tx_id: Uuid,
-        commit: CommitRequest<'_>,
-    ) -> Result<(), Error>;
+        min_upper_bound: u64,
+    ) -> Result<PrepareResult, Error>;
+    /// Report the conflicts seen on the range since it was loaded, grouped
+    /// by key prefix.
+    async fn conflict_stats(&self) -> Result<ConflictStats, Error>;
+    /// Report how the gets served since the range was loaded were answered,
+    /// from the prefetch buffer or from storage.
+    async fn read_stats(&self) -> Result<ReadStats, Error>;
+    /// Report how many versions the storage holds for the range, and how
+    /// many of them a compaction could reclaim.
+    async fn compaction_stats(&self) -> Result<CompactionStats, Error>;
+    /// Remove the versions of the range no read can see anymore, keeping
+    /// those from the last `retain_epochs` epochs. Commits wait until done.
+    async fn compact(&self, retain_epochs: u64) -> Result<CompactionOutcome, Error>;
+    /// Remove the records of the range whose value expired. Reads already
+    /// skip them, this reclaims their space. Commits wait until done.
+    async fn expire(&self) -> Result<ExpiryOutcome, Error>;
+    /// Checksum the range's records in storage, and check the records the
+    /// prefetch buffer holds for the range against them, keeping up to
+    /// `max_divergent_keys` of the keys that differ. Commits wait until done.
+    async fn verify_checksums(&self, max_divergent_keys: usize) -> Result<ChecksumReport, Error>;
+    /// Report the checksum verifications run on the range since it was
+    /// loaded.
+    async fn checksum_stats(&self) -> Result<ChecksumStats, Error>;
+    /// Report whether writes to the range's storage are stalled.
+    async fn write_stall_status(&self) -> Result<WriteStallStatus, Error>;
+    /// Stop admitting new transactions on the range, which is being moved to
+    /// another range server, and wait up to `timeout` for those running on it
+    /// to finish. Newcomers are rejected with `RangeDraining`. The range stays
+    /// loaded, and it is up to the caller to unload it afterwards: the
+    /// transactions still prepared then are recovered by the next range
+    /// server to load the range, along with their hold of the range lock,
+    /// while the others lose their lock. Returns early if the drain is
+    /// cancelled.
+    async fn drain(&self, timeout: std::time::Duration) -> Result<DrainOutcome, Error>;
+    /// Admit new transactions on the range again after `drain`, when the
+    /// move was called off.
+    async fn cancel_drain(&self) -> Result<(), Error>;
+    /// Freeze or unfreeze the range. A frozen range rejects reads and
+    /// prepares of new transactions with `RangeFrozen`, while those already
+    /// prepared on it can still commit or abort. The range stays loaded, and
+    /// frozen until unfrozen or unloaded. Returns whether it was frozen.
+    async fn set_frozen(&self, frozen: bool) -> Result<bool, Error>;
+    /// Read the counters to hand over to the process replacing this one.
+    async fn soft_state(&self) -> Result<SoftState, Error>;
+    /// Add the counters handed over by the process this one replaced to those
+    /// kept since the range was loaded.
+    async fn restore_soft_state(&self, soft_state: SoftState) -> Result<(), Error>;
 }
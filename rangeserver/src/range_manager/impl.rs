@@ -1,35 +1,105 @@
-use super::{GetResult, PrepareResult, RangeManager as Trait};
+use super::{
+    ChecksumReport, ChecksumStats, CompactionOutcome, CompactionStats, ConflictStats, DrainOutcome,
+    ExpiryOutcome, GetResult, InFlightTransaction, LockTableOccupancy, PrepareResolver,
+    PrepareResult, RangeManager as Trait, RangeSnapshot, ReadMode, ReadStats, ScanResult,
+    SoftState, SplitRange, WriteStallStatus,
+};
 
 use crate::{
-    epoch_supplier::EpochSupplier, error::Error, key_version::KeyVersion,
-    range_manager::lock_table, storage::RangeInfo, storage::Storage,
-    transaction_abort_reason::TransactionAbortReason, wal::Wal,
+    checksum::{self, MerkleTree},
+    compaction::{self, MIN_RETAINED_EPOCHS},
+    conflict_stats::ConflictTracker,
+    decision_log::{self, DecisionLog},
+    epoch_supplier::EpochSupplier,
+    error::Error,
+    key_version::KeyVersion,
+    range_manager::lock_table,
+    range_manager::read_versions::ReadVersions,
+    range_manager::storage_health::StorageHealth,
+    range_manager::write_stall::WriteStallDetector,
+    read_stats::ReadTracker,
+    storage::RangeInfo,
+    storage::{is_expired, Storage},
+    transaction_abort_reason::TransactionAbortReason,
+    wal::Wal,
 };
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use common::clock::Clock;
 use common::config::Config;
 use common::full_range_id::FullRangeId;
-use common::transaction_info::TransactionInfo;
+use common::key_range::KeyRange;
+use common::record::Increment as CounterIncrement;
+use common::transaction_info::{IsolationLevel, TransactionInfo};
+use common::util;
 
 use uuid::Uuid;
 
 use crate::prefetching_buffer::KeyState;
 use crate::prefetching_buffer::PrefetchingBuffer;
 use flatbuf::rangeserver_flatbuffers::range_server::*;
+use flatbuffers::FlatBufferBuilder;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 use tokio::sync::RwLock;
 use tonic::async_trait;
+use tracing::{info, warn};
+
+// Used when the config does not set max_pending_prepares_per_range.
+const DEFAULT_MAX_PENDING_PREPARES: usize = 1024;
+
+// How long a split waits for the requests in flight on the range to finish.
+const SPLIT_QUIESCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often a draining range checks whether its transactions finished.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// How long a read at an epoch waits for the commits that can still land at or
+// before it, and how often it checks on them.
+const SNAPSHOT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+const SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct PendingPrepare {
+    record: Bytes,
+    prepared_at: DateTime<Utc>,
+    // The highest known epoch of the range when the transaction prepared.
+    // It commits in that epoch or a later one.
+    commits_from: u64,
+}
 
 struct LoadedState {
     range_info: RangeInfo,
     highest_known_epoch: HighestKnownEpoch,
     lock_table: lock_table::LockTable,
     // TODO: need more efficient representation of prepares than raw bytes.
-    pending_prepare_records: Mutex<HashMap<Uuid, Bytes>>,
+    pending_prepare_records: Mutex<HashMap<Uuid, PendingPrepare>>,
+    // What the transactions reading the range optimistically read, and what
+    // was written since.
+    read_versions: ReadVersions,
+    // Held shared by commits while they apply their writes, and exclusively
+    // while exporting a snapshot, so snapshots never see half a commit.
+    apply_latch: RwLock<()>,
+    conflicts: ConflictTracker,
+    // Compactions run on the range since it was loaded, and the versions
+    // they removed.
+    compactions: AtomicU64,
+    purged_versions: AtomicU64,
+    write_stall: WriteStallDetector,
+    checksums: Mutex<ChecksumStats>,
+    // Set by operators to stop the range from taking new transactions.
+    frozen: AtomicBool,
+    // Set once the range is being moved to another range server.
+    draining: AtomicBool,
+    // Reads at epochs below this one may miss versions, either collected by
+    // compactions or from before the range was loaded.
+    snapshot_floor: AtomicU64,
 }
 
 enum State {
@@ -39,6 +109,63 @@ enum State {
     Unloaded,
 }
 
+// A write of a committed transaction.
+enum CommittedWrite {
+    Put {
+        key: Bytes,
+        value: Bytes,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    Delete {
+        key: Bytes,
+    },
+}
+
+// The epoch a transaction with snapshot isolation reads as of, None for
+// serializable ones. Nothing would check the reads or the writes of a
+// snapshot transaction without one against concurrent commits, so it is
+// rejected.
+fn snapshot_epoch(tx: &TransactionInfo) -> Result<Option<u64>, Error> {
+    match tx.isolation {
+        IsolationLevel::Serializable => Ok(None),
+        IsolationLevel::Snapshot => tx
+            .snapshot_epoch
+            .map(Some)
+            .ok_or(Error::InvalidRequestFormat),
+    }
+}
+
+// The writes of a prepared transaction that committed at `committed_at`.
+fn committed_writes(
+    prepare: &PrepareRequest<'_>,
+    committed_at: DateTime<Utc>,
+) -> Vec<CommittedWrite> {
+    let mut writes = Vec::new();
+    for put in prepare.puts().iter().flatten() {
+        // TODO: too much copying :(
+        let key = Bytes::copy_from_slice(put.key().unwrap().k().unwrap().bytes());
+        let value = Bytes::copy_from_slice(put.value().unwrap().bytes());
+        // TTLs count from when the commit is applied.
+        let expires_at = match put.ttl_ms() {
+            0 => None,
+            ttl_ms => Some(
+                committed_at
+                    + chrono::Duration::milliseconds(i64::try_from(ttl_ms).unwrap_or(i64::MAX)),
+            ),
+        };
+        writes.push(CommittedWrite::Put {
+            key,
+            value,
+            expires_at,
+        });
+    }
+    for del in prepare.deletes().iter().flatten() {
+        let key = Bytes::copy_from_slice(del.k().unwrap().bytes());
+        writes.push(CommittedWrite::Delete { key });
+    }
+    writes
+}
+
 struct HighestKnownEpoch {
     val: RwLock<u64>,
 }
@@ -73,6 +200,14 @@ where
     state: Arc<RwLock<State>>,
     prefetching_buffer: Arc<PrefetchingBuffer>,
     bg_runtime: tokio::runtime::Handle,
+    storage_health: Arc<StorageHealth>,
+    clock: Arc<dyn Clock>,
+    // Held while changing the epoch lease, by the renewal task and by lease
+    // extensions, which are the only ones changing it.
+    lease_latch: Arc<Mutex<()>>,
+    decision_log: Option<DecisionLog>,
+    reads: ReadTracker,
+    prepare_resolver: Arc<dyn PrepareResolver>,
 }
 
 #[async_trait]
@@ -142,13 +277,18 @@ where
             .await;
 
         match keystate {
-            KeyState::Fetched => Ok(()), // key has previously been fetched
+            KeyState::Fetched => {
+                // key has previously been fetched
+                self.reads.record_prefetch(false);
+                Ok(())
+            }
             KeyState::Loading(_) => Err(Error::PrefetchError), // Something is wrong if loading was returned
             KeyState::Requested(fetch_sequence_number) =>
             // key has just been requested - start fetch
             {
                 // Fetch from database
-                let val = match self.prefetch_get(key.clone()).await {
+                self.reads.record_prefetch(true);
+                let (val, expires_at) = match self.prefetch_get(key.clone()).await {
                     Ok(value) => value,
                     Err(_) => {
                         self.prefetching_buffer
@@ -159,22 +299,54 @@ where
                 };
                 // Successfully fetched from database -> add to buffer and update records
                 self.prefetching_buffer
-                    .fetch_complete(key.clone(), val, fetch_sequence_number)
+                    .fetch_complete(key.clone(), val, expires_at, fetch_sequence_number)
                     .await;
                 Ok(())
             }
         }
     }
 
-    async fn get(&self, tx: Arc<TransactionInfo>, key: Bytes) -> Result<GetResult, Error> {
+    async fn get(
+        &self,
+        tx: Arc<TransactionInfo>,
+        key: Bytes,
+        mode: ReadMode,
+    ) -> Result<GetResult, Error> {
         let s = self.state.read().await;
         match s.deref() {
             State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
             State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
+                self.check_frozen(state, tx.id).await?;
+                self.check_draining(state, tx.id).await?;
+                let snapshot_epoch = snapshot_epoch(&tx)?;
                 if !state.range_info.key_range.includes(key.clone()) {
                     return Err(Error::KeyIsOutOfRange);
                 };
-                self.acquire_range_lock(state, tx.clone()).await?;
+                match mode {
+                    ReadMode::Locking => {
+                        if let Err(e) = self.acquire_range_lock(state, tx.clone()).await {
+                            state.conflicts.record([&key[..]], &tx.labels, &e);
+                            return Err(e);
+                        }
+                        // A snapshot transaction reading for update would
+                        // otherwise only find out at prepare that the key
+                        // changed since its snapshot.
+                        if let Some(snapshot_epoch) = snapshot_epoch {
+                            self.check_written_since(&key, snapshot_epoch).await?;
+                        }
+                    }
+                    ReadMode::Optimistic => state.read_versions.record_read(tx.id, key.clone()),
+                    ReadMode::AtEpoch(epoch) => {
+                        self.reads.record_get(false);
+                        return Ok(GetResult {
+                            val: self.read_at_epoch(state, key, epoch).await?,
+                            leader_sequence_number: state.range_info.leader_sequence_number as i64,
+                        });
+                    }
+                }
 
                 let mut get_result = GetResult {
                     val: None,
@@ -184,17 +356,16 @@ where
                 // check prefetch buffer
                 let value = self
                     .prefetching_buffer
-                    .get_from_buffer(key.clone())
+                    .get_from_buffer(key.clone(), self.clock.now())
                     .await
                     .map_err(|_| Error::PrefetchError)?;
+                self.reads.record_get(value.is_some());
                 if let Some(val) = value {
                     get_result.val = Some(val);
                 } else {
                     let val = self
-                        .storage
-                        .get(self.range_id, key.clone())
-                        .await
-                        .map_err(Error::from_storage_error)?;
+                        .storage_health
+                        .check(self.storage.get(self.range_id, key.clone()).await)?;
 
                     get_result.val = val.clone();
                 }
@@ -204,6 +375,106 @@ where
         }
     }
 
+    async fn scan(
+        &self,
+        tx: Arc<TransactionInfo>,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        mode: ReadMode,
+    ) -> Result<ScanResult, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
+                self.check_frozen(state, tx.id).await?;
+                self.check_draining(state, tx.id).await?;
+                snapshot_epoch(&tx)?;
+                let key_range = key_range.intersection(&state.range_info.key_range);
+                match mode {
+                    ReadMode::Locking => {
+                        if let Err(e) = self.acquire_range_lock(state, tx.clone()).await {
+                            let start = key_range.lower_bound_inclusive.clone().unwrap_or_default();
+                            state.conflicts.record([&start[..]], &tx.labels, &e);
+                            return Err(e);
+                        }
+                    }
+                    ReadMode::Optimistic => {
+                        state.read_versions.record_scan(tx.id, key_range.clone())
+                    }
+                    ReadMode::AtEpoch(epoch) => {
+                        return Ok(ScanResult {
+                            records: self.scan_at_epoch(state, key_range, limit, epoch).await?,
+                            leader_sequence_number: state.range_info.leader_sequence_number as i64,
+                        });
+                    }
+                }
+                // Commits apply their writes to storage before releasing the
+                // range lock, so storage is as fresh as the prefetch buffer.
+                let records = if key_range.is_empty() {
+                    Vec::new()
+                } else {
+                    self.storage_health
+                        .check(self.storage.scan(self.range_id, key_range, limit).await)?
+                };
+                Ok(ScanResult {
+                    records,
+                    leader_sequence_number: state.range_info.leader_sequence_number as i64,
+                })
+            }
+        }
+    }
+
+    async fn read_committed(&self, keys: &[Bytes]) -> Result<Vec<Option<Bytes>>, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
+                if !keys
+                    .iter()
+                    .all(|key| state.range_info.key_range.includes(key.clone()))
+                {
+                    return Err(Error::KeyIsOutOfRange);
+                }
+                let mut vals = Vec::with_capacity(keys.len());
+                for key in keys {
+                    vals.push(
+                        self.storage_health
+                            .check(self.storage.get(self.range_id, key.clone()).await)?,
+                    );
+                }
+                Ok(vals)
+            }
+        }
+    }
+
+    async fn scan_committed(
+        &self,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
+                let key_range = key_range.intersection(&state.range_info.key_range);
+                if key_range.is_empty() {
+                    return Ok(Vec::new());
+                }
+                self.storage_health
+                    .check(self.storage.scan(self.range_id, key_range, limit).await)
+            }
+        }
+    }
+
     async fn prepare(
         &self,
         tx: Arc<TransactionInfo>,
@@ -215,49 +486,175 @@ where
                 return Err(Error::RangeIsNotLoaded)
             }
             State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
+                self.check_frozen(state, tx.id).await?;
+                self.check_draining(state, tx.id).await?;
+                let snapshot_epoch = snapshot_epoch(&tx)?;
                 // Sanity check that the written keys are all within this range.
                 // TODO: check delete and write sets are non-overlapping.
+                let mut written = Vec::new();
                 for put in prepare.puts().iter() {
                     for put in put.iter() {
                         // TODO: too much copying :(
                         let key = Bytes::copy_from_slice(put.key().unwrap().k().unwrap().bytes());
-                        if !state.range_info.key_range.includes(key) {
+                        if !state.range_info.key_range.includes(key.clone()) {
                             return Err(Error::KeyIsOutOfRange);
                         }
+                        written.push(key);
                     }
                 }
                 for del in prepare.deletes().iter() {
                     for del in del.iter() {
                         let key = Bytes::copy_from_slice(del.k().unwrap().bytes());
-                        if !state.range_info.key_range.includes(key) {
+                        if !state.range_info.key_range.includes(key.clone()) {
                             return Err(Error::KeyIsOutOfRange);
                         }
+                        written.push(key);
+                    }
+                }
+                let mut conditions = Vec::new();
+                for condition in prepare.conditions().iter().flatten() {
+                    let key = Bytes::copy_from_slice(condition.key().unwrap().k().unwrap().bytes());
+                    if !state.range_info.key_range.includes(key.clone()) {
+                        return Err(Error::KeyIsOutOfRange);
                     }
+                    let expected = if condition.expect_absent() {
+                        None
+                    } else {
+                        Some(condition.expected_value().map_or(&[][..], |v| v.bytes()))
+                    };
+                    conditions.push((key, expected));
                 }
+                // Increments commute, so only puts and deletes conflict with
+                // the writes of other snapshot transactions.
+                let overwritten = written.len();
+                let mut increments = Vec::new();
+                for increment in prepare.increments().iter().flatten() {
+                    let key = Bytes::copy_from_slice(increment.key().unwrap().k().unwrap().bytes());
+                    if !state.range_info.key_range.includes(key.clone()) {
+                        return Err(Error::KeyIsOutOfRange);
+                    }
+                    written.push(key.clone());
+                    increments.push(CounterIncrement {
+                        key,
+                        delta: increment.delta(),
+                    });
+                }
+                // Conflicts at prepare are attributed to the keys the
+                // transaction was trying to write.
+                let conflict = |e: Error| {
+                    state
+                        .conflicts
+                        .record(written.iter().map(|k| &k[..]), &tx.labels, &e);
+                    e
+                };
                 // Validate the transaction lock is not lost, this is essential to ensure 2PL
                 // invariants still hold.
-
-                if prepare.has_reads() && !state.lock_table.is_currently_holding(tx.id).await {
-                    return Err(Error::TransactionAborted(
+                // Snapshot reads are not checked at all.
+                let has_reads = prepare.has_reads() && tx.isolation == IsolationLevel::Serializable;
+                let read_optimistically = state.read_versions.is_reader(tx.id);
+                if has_reads
+                    && !read_optimistically
+                    && !state.lock_table.is_currently_holding(tx.id).await
+                {
+                    return Err(conflict(Error::TransactionAborted(
                         TransactionAbortReason::TransactionLockLost,
-                    ));
+                    )));
+                }
+                self.check_prepare_backlog(state, tx.id).await?;
+                if !written.is_empty() {
+                    self.check_write_stall(state, tx.id).await?;
                 }
 
-                self.acquire_range_lock(state, tx.clone()).await?;
-                {
+                self.acquire_range_lock(state, tx.clone())
+                    .await
+                    .map_err(conflict)?;
+                // Nothing can commit on the range while we hold the lock, so
+                // if none of the optimistic reads went stale until now they
+                // never will.
+                if read_optimistically && !state.read_versions.reads_are_current(tx.id) {
+                    return Err(conflict(Error::TransactionAborted(
+                        TransactionAbortReason::ReadConflict,
+                    )));
+                }
+                // Likewise, the values the conditions are checked against
+                // can't change until the transaction commits or aborts.
+                for (key, expected) in conditions {
+                    let current = self
+                        .storage_health
+                        .check(self.storage.get(self.range_id, key).await)?;
+                    if current.as_deref() != expected {
+                        return Err(Error::ConditionFailed);
+                    }
+                }
+                // The counters are read under the lock for the same reason.
+                let mut counters = BTreeMap::new();
+                for increment in &increments {
+                    let current = match counters.remove(&increment.key) {
+                        Some(value) => Some(value),
+                        None => self
+                            .storage_health
+                            .check(self.storage.get(self.range_id, increment.key.clone()).await)?,
+                    };
+                    let value = increment
+                        .apply(current.as_deref())
+                        .ok_or(Error::InvalidIncrement)?;
+                    counters.insert(increment.key.clone(), value);
+                }
+                // First committer wins among snapshot transactions: nothing
+                // else can write the keys until this one finishes either.
+                if let Some(snapshot_epoch) = snapshot_epoch {
+                    for key in &written[..overwritten] {
+                        self.check_written_since(key, snapshot_epoch).await?;
+                    }
+                }
+                let record = {
                     // TODO: probably don't need holding that latch while writing to the WAL.
                     // but needs careful thinking.
                     let mut pending_prepare_records = state.pending_prepare_records.lock().await;
+                    // A retried prepare keeps its original time.
+                    let prepared_at = pending_prepare_records
+                        .get(&tx.id)
+                        .map_or_else(|| self.clock.now(), |pending| pending.prepared_at);
+                    let record =
+                        Bytes::from(encode_prepare_record(&prepare, &tx, counters, prepared_at));
                     self.wal
-                        .append_prepare(prepare)
+                        .append_prepare(flatbuffers::root::<PrepareRequest>(&record).unwrap())
                         .await
                         .map_err(Error::from_wal_error)?;
+                    self.storage_health.check(
+                        self.storage
+                            .persist_prepare(
+                                self.range_id,
+                                tx.id,
+                                record.clone(),
+                                state.range_info.leader_sequence_number,
+                            )
+                            .await,
+                    )?;
 
+                    let commits_from = state.highest_known_epoch.read().await;
                     pending_prepare_records
-                        .insert(tx.id, Bytes::copy_from_slice(prepare._tab.buf()));
-                }
+                        .entry(tx.id)
+                        .or_insert_with(|| PendingPrepare {
+                            record: Bytes::new(),
+                            prepared_at,
+                            commits_from,
+                        })
+                        .record = record.clone();
+                    record
+                };
 
                 let highest_known_epoch = state.highest_known_epoch.read().await;
+                self.log_decision(
+                    tx.id,
+                    decision_log::Event::Prepared {
+                        highest_known_epoch,
+                        prepare_request: record,
+                    },
+                );
 
                 Ok(PrepareResult {
                     highest_known_epoch,
@@ -267,13 +664,46 @@ where
         }
     }
 
-    async fn abort(&self, tx_id : Uuid, abort: AbortRequest<'_>) -> Result<(), Error> {
+    async fn validate(&self, tx: Arc<TransactionInfo>, has_reads: bool) -> Result<i64, Error> {
+        let tx_id = tx.id;
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
+                self.check_frozen(state, tx_id).await?;
+                self.check_draining(state, tx_id).await?;
+                snapshot_epoch(&tx)?;
+                // As in prepare, snapshot reads are not checked at all.
+                if has_reads && tx.isolation == IsolationLevel::Serializable {
+                    if state.read_versions.is_reader(tx_id) {
+                        if !state.read_versions.reads_are_current(tx_id) {
+                            return Err(Error::TransactionAborted(
+                                TransactionAbortReason::ReadConflict,
+                            ));
+                        }
+                    } else if !state.lock_table.is_currently_holding(tx_id).await {
+                        return Err(Error::TransactionAborted(
+                            TransactionAbortReason::TransactionLockLost,
+                        ));
+                    }
+                }
+                self.check_prepare_backlog(state, tx_id).await?;
+                Ok(state.range_info.leader_sequence_number as i64)
+            }
+        }
+    }
+
+    async fn abort(&self, tx_id: Uuid, abort: AbortRequest<'_>) -> Result<(), Error> {
         let s = self.state.read().await;
         match s.deref() {
             State::NotLoaded | State::Unloaded | State::Loading(_) => {
                 return Err(Error::RangeIsNotLoaded)
             }
             State::Loaded(state) => {
+                state.read_versions.forget(tx_id);
                 if !state.lock_table.is_currently_holding(tx_id).await {
                     return Ok(());
                 }
@@ -285,7 +715,18 @@ where
                         .append_abort(abort)
                         .await
                         .map_err(Error::from_wal_error)?;
+                    self.storage_health.check(
+                        self.storage
+                            .remove_prepare(
+                                self.range_id,
+                                tx_id,
+                                state.range_info.leader_sequence_number,
+                            )
+                            .await,
+                    )?;
                 }
+                state.pending_prepare_records.lock().await.remove(&tx_id);
+                self.log_decision(tx_id, decision_log::Event::Aborted);
                 state.lock_table.release().await;
 
                 let _ = self
@@ -297,22 +738,22 @@ where
         }
     }
 
-    async fn commit(
-        &self,
-        tx_id: Uuid,
-        commit: CommitRequest<'_>,
-    ) -> Result<(), Error> {
+    async fn commit(&self, tx_id: Uuid, commit: CommitRequest<'_>) -> Result<(), Error> {
         let s = self.state.read().await;
         match s.deref() {
             State::NotLoaded | State::Unloaded | State::Loading(_) => {
                 return Err(Error::RangeIsNotLoaded)
             }
             State::Loaded(state) => {
+                if self.storage_health.is_faulted() {
+                    return Err(Error::RangeFaulted);
+                }
                 if !state.lock_table.is_currently_holding(tx_id).await {
                     // it must be that we already finished committing, but perhaps the coordinator didn't
                     // realize that, so we just return success.
                     return Ok(());
                 }
+                let _applying = state.apply_latch.read().await;
                 state.highest_known_epoch.maybe_update(commit.epoch()).await;
 
                 // TODO: handle potential duplicates here.
@@ -323,7 +764,7 @@ where
                 let prepare_record_bytes = {
                     let mut pending_prepare_records = state.pending_prepare_records.lock().await;
                     // TODO: handle prior removals.
-                    pending_prepare_records.remove(&tx_id).unwrap().clone()
+                    pending_prepare_records.remove(&tx_id).unwrap().record
                 };
 
                 let prepare_record =
@@ -333,6 +774,7 @@ where
                     // TODO: version counter should be an internal counter per range.
                     // Remove from the commit message.
                     version_counter: commit.vid() as u64,
+                    transaction_id: tx_id,
                 };
                 // TODO: we shouldn't be doing a storage operation per individual key put or delete.
                 // Instead we should write them in batches, and whenever we do multiple operations they
@@ -340,36 +782,44 @@ where
                 // We should also add retries in case of intermittent failures. Note that all our
                 // storage operations here are idempotent and safe to retry any number of times.
                 // We also don't need to be holding the state latch for that long.
-                for put in prepare_record.puts().iter() {
-                    for put in put.iter() {
-                        // TODO: too much copying :(
-                        let key = Bytes::copy_from_slice(put.key().unwrap().k().unwrap().bytes());
-                        let val = Bytes::copy_from_slice(put.value().unwrap().bytes());
-
-                        // TODO: we should do the storage writes lazily in the background
-                        self.storage
-                            .upsert(self.range_id, key.clone(), val.clone(), version)
-                            .await
-                            .map_err(Error::from_storage_error)?;
-
-                        // Update the prefetch buffer if this key has been requested by a prefetch call
-                        self.prefetching_buffer.upsert(key, val).await;
-                    }
+                let apply_started = self.clock.instant();
+                let mut written = Vec::new();
+                for write in committed_writes(&prepare_record, self.clock.now()) {
+                    written.push(
+                        Self::apply_write(
+                            self.storage.as_ref(),
+                            &self.storage_health,
+                            &self.prefetching_buffer,
+                            self.range_id,
+                            version,
+                            write,
+                        )
+                        .await?,
+                    );
                 }
-                for del in prepare_record.deletes().iter() {
-                    for del in del.iter() {
-                        let key = Bytes::copy_from_slice(del.k().unwrap().bytes());
-
-                        // TODO: we should do the storage writes lazily in the background
-                        self.storage
-                            .delete(self.range_id, key.clone(), version)
-                            .await
-                            .map_err(Error::from_storage_error)?;
 
-                        // Delete the key from the prefetch buffer if this key has been requested by a prefetch call
-                        self.prefetching_buffer.delete(key).await;
-                    }
+                self.storage_health.check(
+                    self.storage
+                        .remove_prepare(
+                            self.range_id,
+                            tx_id,
+                            state.range_info.leader_sequence_number,
+                        )
+                        .await,
+                )?;
+                if !written.is_empty() {
+                    state.read_versions.record_commit(written);
+                    state
+                        .write_stall
+                        .record_apply(self.clock.instant() - apply_started);
                 }
+                state.read_versions.forget(tx_id);
+                self.log_decision(
+                    tx_id,
+                    decision_log::Event::Committed {
+                        epoch: commit.epoch(),
+                    },
+                );
 
                 // We apply the writes to storage before releasing the lock since we send all
                 // gets to storage directly. We should implement a memtable to allow us to release
@@ -383,140 +833,1109 @@ where
             }
         }
     }
-}
 
-impl<S, W> RangeManager<S, W>
-where
-    S: Storage,
-    W: Wal,
-{
-    pub fn new(
-        range_id: FullRangeId,
-        config: Config,
-        storage: Arc<S>,
-        epoch_supplier: Arc<dyn EpochSupplier>,
-        wal: W,
-        prefetching_buffer: Arc<PrefetchingBuffer>,
-        bg_runtime: tokio::runtime::Handle,
-    ) -> Arc<Self> {
-        Arc::new(RangeManager {
-            range_id,
-            config,
-            storage,
-            epoch_supplier,
-            wal: Arc::new(wal),
-            state: Arc::new(RwLock::new(State::NotLoaded)),
-            prefetching_buffer,
-            bg_runtime,
-        })
+    async fn list_in_flight_transactions(&self) -> Result<Vec<InFlightTransaction>, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                let pending_prepare_records = state.pending_prepare_records.lock().await;
+                let mut in_flight = Vec::new();
+                let holder = state.lock_table.current_holder().await;
+                if let Some((tx, when_acquired)) = &holder {
+                    in_flight.push(InFlightTransaction {
+                        id: tx.id,
+                        labels: tx.labels.clone(),
+                        lock_acquired: Some(*when_acquired),
+                        // There is a single lock for the whole range.
+                        lock_count: 1,
+                        prepared: pending_prepare_records.contains_key(&tx.id),
+                        prepared_at: pending_prepare_records
+                            .get(&tx.id)
+                            .map(|prepare| prepare.prepared_at),
+                    });
+                }
+                for (tx_id, prepare) in pending_prepare_records.iter() {
+                    if holder.as_ref().is_some_and(|(tx, _)| tx.id == *tx_id) {
+                        continue;
+                    }
+                    in_flight.push(InFlightTransaction {
+                        id: *tx_id,
+                        labels: BTreeMap::new(),
+                        lock_acquired: None,
+                        lock_count: 0,
+                        prepared: true,
+                        prepared_at: Some(prepare.prepared_at),
+                    });
+                }
+                Ok(in_flight)
+            }
+        }
     }
 
-    async fn load_inner(&self) -> Result<LoadedState, Error> {
-        let epoch_supplier = self.epoch_supplier.clone();
-        let storage = self.storage.clone();
-        let wal = self.wal.clone();
-        let range_id = self.range_id;
-        let bg_runtime = self.bg_runtime.clone();
-        let state = self.state.clone();
-        let lease_renewal_interval = self.config.range_server.range_maintenance_duration;
-        let epoch_duration = self.config.epoch.epoch_duration;
-        // Calculate how many epochs we need for the desired lease duration.
-        // TODO(yanniszark): Put this in the config.
-        let intended_lease_duration = Duration::from_secs(2);
-        let num_epochs_per_lease = intended_lease_duration
-            .as_nanos()
-            .checked_div(epoch_duration.as_nanos())
-            .and_then(|n| u64::try_from(n).ok())
-            .unwrap();
-        // Ensure that we have at least one epoch per lease.
-        let num_epochs_per_lease = std::cmp::max(1, num_epochs_per_lease);
+    async fn lock_table_occupancy(&self) -> Result<LockTableOccupancy, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => Ok(state.lock_table.occupancy().await),
+        }
+    }
 
-        self.bg_runtime
-            .spawn(async move {
-                // TODO: handle all errors instead of panicking.
-                let epoch = epoch_supplier
-                    .read_epoch()
-                    .await
-                    .map_err(Error::from_epoch_supplier_error)?;
-                let mut range_info = storage
-                    .take_ownership_and_load_range(range_id)
-                    .await
-                    .map_err(Error::from_storage_error)?;
-                // Epoch read from the provider can be 1 less than the true epoch. The highest known epoch
-                // of a range cannot move backward even across range load/unloads, so to maintain that guarantee
-                // we just wait for the epoch to advance once.
-                epoch_supplier
-                    .wait_until_epoch(epoch + 1, chrono::Duration::seconds(10))
+    async fn export_snapshot(&self) -> Result<RangeSnapshot, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                let _no_commits = state.apply_latch.write().await;
+                let prepared = state
+                    .pending_prepare_records
+                    .lock()
                     .await
-                    .map_err(Error::from_epoch_supplier_error)?;
-                // Get a new epoch lease.
-                let highest_known_epoch = epoch + 1;
-                let new_epoch_lease_lower_bound =
-                    std::cmp::max(highest_known_epoch, range_info.epoch_lease.1 + 1);
-                let new_epoch_lease_upper_bound =
-                    new_epoch_lease_lower_bound + num_epochs_per_lease;
-                storage
-                    .renew_epoch_lease(
-                        range_id,
-                        (new_epoch_lease_lower_bound, new_epoch_lease_upper_bound),
-                        range_info.leader_sequence_number,
-                    )
+                    .iter()
+                    .map(|(id, prepare)| (*id, prepare.record.clone()))
+                    .collect();
+                let records = self
+                    .storage
+                    .scan_versions(self.range_id)
                     .await
                     .map_err(Error::from_storage_error)?;
-                range_info.epoch_lease = (new_epoch_lease_lower_bound, new_epoch_lease_upper_bound);
-                wal.sync().await.map_err(Error::from_wal_error)?;
-                // Create a recurrent task to renew.
-                bg_runtime.spawn(async move {
-                    Self::renew_epoch_lease_task(
-                        range_id,
-                        epoch_supplier,
-                        storage,
-                        state,
-                        lease_renewal_interval,
-                        num_epochs_per_lease,
-                    )
-                    .await
-                });
-                // TODO: apply WAL here!
-                Ok(LoadedState {
-                    range_info,
-                    highest_known_epoch: HighestKnownEpoch::new(highest_known_epoch),
-                    lock_table: lock_table::LockTable::new(),
-                    pending_prepare_records: Mutex::new(HashMap::new()),
+                Ok(RangeSnapshot {
+                    key_range: state.range_info.key_range.clone(),
+                    leader_sequence_number: state.range_info.leader_sequence_number,
+                    epoch_lease: state.range_info.epoch_lease,
+                    highest_known_epoch: state.highest_known_epoch.read().await,
+                    records,
+                    prepared,
                 })
-            })
-            .await
-            .unwrap()
+            }
+        }
     }
 
-    async fn renew_epoch_lease_task(
-        range_id: FullRangeId,
-        epoch_supplier: Arc<dyn EpochSupplier>,
-        storage: Arc<S>,
-        state: Arc<RwLock<State>>,
-        lease_renewal_interval: std::time::Duration,
-        num_epochs_per_lease: u64,
-    ) -> Result<(), Error> {
-        loop {
-            let leader_sequence_number: u64;
-            let old_lease: (u64, u64);
-            let epoch = epoch_supplier
-                .read_epoch()
-                .await
-                .map_err(Error::from_epoch_supplier_error)?;
+    async fn split(&self, split_key: Bytes) -> Result<[SplitRange; 2], Error> {
+        // Requests hold the state while they wait for the range lock, so
+        // only wait for them a bounded time, and check for transactions
+        // that could keep it held first.
+        {
+            let s = self.state.read().await;
+            match s.deref() {
+                State::NotLoaded | State::Unloaded | State::Loading(_) => {
+                    return Err(Error::RangeIsNotLoaded)
+                }
+                State::Loaded(state) => Self::check_can_split(state, &split_key).await?,
+            }
+        }
+        let mut s = tokio::time::timeout(SPLIT_QUIESCE_TIMEOUT, self.state.write())
+            .await
+            .map_err(|_| Error::RangeBusy)?;
+        let state = match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => {
+                return Err(Error::RangeIsNotLoaded)
+            }
+            State::Loaded(state) => state,
+        };
+        if self.storage_health.is_faulted() {
+            return Err(Error::RangeFaulted);
+        }
+        Self::check_can_split(state, &split_key).await?;
+
+        let key_range = &state.range_info.key_range;
+        let children = [
+            SplitRange {
+                range_id: Uuid::new_v4(),
+                key_range: KeyRange {
+                    lower_bound_inclusive: key_range.lower_bound_inclusive.clone(),
+                    upper_bound_exclusive: Some(split_key.clone()),
+                },
+            },
+            SplitRange {
+                range_id: Uuid::new_v4(),
+                key_range: KeyRange {
+                    lower_bound_inclusive: Some(split_key.clone()),
+                    upper_bound_exclusive: key_range.upper_bound_exclusive.clone(),
+                },
+            },
+        ];
+        let records = self
+            .storage
+            .scan_versions(self.range_id)
+            .await
+            .map_err(Error::from_storage_error)?;
+        // The new ranges must only ever be served in epochs past those this
+        // one committed in. With no prepares left and no requests running,
+        // nothing can commit here past its highest known epoch anymore, so
+        // the new ranges can start right after it rather than after the end
+        // of this one's lease, which may be far ahead of the current epoch.
+        let highest_known_epoch = state.highest_known_epoch.read().await;
+        for child in &children {
+            let child_id = FullRangeId {
+                keyspace_id: self.range_id.keyspace_id,
+                range_id: child.range_id,
+            };
+            self.storage
+                .create_range(
+                    child_id,
+                    child.key_range.clone(),
+                    (highest_known_epoch, highest_known_epoch),
+                )
+                .await
+                .map_err(Error::from_storage_error)?;
+            for (key, version) in records
+                .iter()
+                .filter(|(key, _)| child.key_range.includes(key.clone()))
+            {
+                let key_version = KeyVersion {
+                    epoch: version.epoch,
+                    version_counter: 0,
+                    transaction_id: version.transaction_id.unwrap_or(Uuid::nil()),
+                };
+                match &version.value {
+                    Some(value) => {
+                        self.storage
+                            .upsert(
+                                child_id,
+                                key.clone(),
+                                value.clone(),
+                                key_version,
+                                version.expires_at,
+                            )
+                            .await
+                    }
+                    None => {
+                        self.storage
+                            .delete(child_id, key.clone(), key_version)
+                            .await
+                    }
+                }
+                .map_err(Error::from_storage_error)?;
+            }
+        }
+        info!(
+            "Split range {:?} at {:?} into {:?} and {:?}",
+            self.range_id, split_key, children[0].range_id, children[1].range_id
+        );
+        *s = State::Unloaded;
+        Ok(children)
+    }
+
+    async fn extend_epoch_lease(
+        &self,
+        tx_id: Uuid,
+        min_upper_bound: u64,
+    ) -> Result<PrepareResult, Error> {
+        let _lease_guard = self.lease_latch.lock().await;
+        let (old_lease, leader_sequence_number) = {
+            let s = self.state.read().await;
+            match s.deref() {
+                State::NotLoaded | State::Unloaded | State::Loading(_) => {
+                    return Err(Error::RangeIsNotLoaded)
+                }
+                State::Loaded(state) => {
+                    if self.storage_health.is_faulted() {
+                        return Err(Error::RangeFaulted);
+                    }
+                    // Only transactions prepared on the range can hold its
+                    // lease up.
+                    if !state
+                        .pending_prepare_records
+                        .lock()
+                        .await
+                        .contains_key(&tx_id)
+                    {
+                        return Err(Error::UnknownTransaction);
+                    }
+                    if state.range_info.epoch_lease.1 >= min_upper_bound {
+                        return Ok(PrepareResult {
+                            highest_known_epoch: state.highest_known_epoch.read().await,
+                            epoch_lease: state.range_info.epoch_lease,
+                        });
+                    }
+                    (
+                        state.range_info.epoch_lease,
+                        state.range_info.leader_sequence_number,
+                    )
+                }
+            }
+        };
+        // A lease far in the future would keep any other range server from
+        // taking over the range for that long, so never go further ahead than
+        // allowed, even if that leaves the transaction unable to commit.
+        let epoch = self
+            .epoch_supplier
+            .read_epoch()
+            .await
+            .map_err(Error::from_epoch_supplier_error)?;
+        let max_extension = self
+            .config
+            .range_server
+            .max_epoch_lease_extension
+            .unwrap_or(2 * self.num_epochs_per_lease());
+        let new_lease = (
+            old_lease.0,
+            std::cmp::min(min_upper_bound, epoch + max_extension),
+        );
+        if new_lease.1 > old_lease.1 {
+            self.storage_health.check(
+                self.storage
+                    .renew_epoch_lease(self.range_id, new_lease, leader_sequence_number)
+                    .await,
+            )?;
+        }
+        let mut s = self.state.write().await;
+        match s.deref_mut() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                if new_lease.1 > old_lease.1 {
+                    state.range_info.epoch_lease = new_lease;
+                }
+                Ok(PrepareResult {
+                    highest_known_epoch: state.highest_known_epoch.read().await,
+                    epoch_lease: state.range_info.epoch_lease,
+                })
+            }
+        }
+    }
+
+    async fn conflict_stats(&self) -> Result<ConflictStats, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => Ok(state.conflicts.stats()),
+        }
+    }
+
+    async fn read_stats(&self) -> Result<ReadStats, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(_) => Ok(self.reads.stats()),
+        }
+    }
+
+    async fn compaction_stats(&self) -> Result<CompactionStats, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                let versions = self
+                    .storage
+                    .scan_versions(self.range_id)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                let horizon = state
+                    .highest_known_epoch
+                    .read()
+                    .await
+                    .saturating_sub(MIN_RETAINED_EPOCHS);
+                Ok(CompactionStats {
+                    versions: compaction::plan(&versions, horizon).stats,
+                    compactions: state.compactions.load(Ordering::Relaxed),
+                    purged_versions: state.purged_versions.load(Ordering::Relaxed),
+                })
+            }
+        }
+    }
+
+    async fn compact(&self, retain_epochs: u64) -> Result<CompactionOutcome, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                // Commits could otherwise write a version the plan does not
+                // know about while it is being carried out.
+                let _no_commits = state.apply_latch.write().await;
+                let versions = self
+                    .storage
+                    .scan_versions(self.range_id)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                let horizon = state
+                    .highest_known_epoch
+                    .read()
+                    .await
+                    .saturating_sub(retain_epochs.max(MIN_RETAINED_EPOCHS));
+                let plan = compaction::plan(&versions, horizon);
+                let outcome = CompactionOutcome {
+                    horizon,
+                    purged_keys: plan.purges.len() as u64,
+                    purged_versions: plan.purged_versions,
+                };
+                // Snapshot reads below the horizon must fail from before the
+                // history they need starts going away.
+                state.snapshot_floor.fetch_max(horizon, Ordering::SeqCst);
+                self.storage
+                    .purge_versions(self.range_id, plan.purges)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                state.compactions.fetch_add(1, Ordering::Relaxed);
+                state
+                    .purged_versions
+                    .fetch_add(outcome.purged_versions, Ordering::Relaxed);
+                Ok(outcome)
+            }
+        }
+    }
+
+    async fn expire(&self) -> Result<ExpiryOutcome, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                // A commit in the same epoch could otherwise write a version
+                // of an expired key just before it gets purged.
+                let _no_commits = state.apply_latch.write().await;
+                let versions = self
+                    .storage
+                    .scan_versions(self.range_id)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                let (purges, outcome) = compaction::plan_expiry(&versions, self.clock.now());
+                if purges.is_empty() {
+                    return Ok(outcome);
+                }
+                let keys: Vec<Bytes> = purges.iter().map(|(key, _)| key.clone()).collect();
+                self.storage
+                    .purge_versions(self.range_id, purges)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                for key in keys {
+                    self.prefetching_buffer.delete(key).await;
+                }
+                Ok(outcome)
+            }
+        }
+    }
+
+    async fn verify_checksums(&self, max_divergent_keys: usize) -> Result<ChecksumReport, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                // Commits could otherwise change storage and the buffer in
+                // between reading the two, making them look divergent.
+                let _no_commits = state.apply_latch.write().await;
+                let stored: BTreeMap<Bytes, Bytes> = self
+                    .storage
+                    .scan(self.range_id, state.range_info.key_range.clone(), None)
+                    .await
+                    .map_err(Error::from_storage_error)?
+                    .into_iter()
+                    .collect();
+                let cached = self
+                    .prefetching_buffer
+                    .fetched_entries(&state.range_info.key_range, self.clock.now())
+                    .await;
+                let (divergent_keys, divergent_key_samples) =
+                    checksum::divergent_keys(&cached, &stored, max_divergent_keys);
+                let report = ChecksumReport {
+                    verified_at: self.clock.now(),
+                    storage_checksum: MerkleTree::build(
+                        stored.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+                    )
+                    .root(),
+                    keys: stored.len() as u64,
+                    cached_keys: cached.len() as u64,
+                    divergent_keys,
+                    divergent_key_samples,
+                };
+                let mut stats = state.checksums.lock().await;
+                stats.verifications += 1;
+                if divergent_keys > 0 {
+                    stats.divergences += 1;
+                }
+                stats.last = Some(report.clone());
+                Ok(report)
+            }
+        }
+    }
+
+    async fn checksum_stats(&self) -> Result<ChecksumStats, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => Ok(state.checksums.lock().await.clone()),
+        }
+    }
+
+    async fn write_stall_status(&self) -> Result<WriteStallStatus, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => Ok(state.write_stall.status()),
+        }
+    }
+
+    async fn drain(&self, timeout: Duration) -> Result<DrainOutcome, Error> {
+        let deadline = self.clock.instant() + timeout;
+        match self.state.read().await.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => {
+                return Err(Error::RangeIsNotLoaded)
+            }
+            State::Loaded(state) => state.draining.store(true, Ordering::SeqCst),
+        }
+        loop {
+            {
+                let s = self.state.read().await;
+                let state = match s.deref() {
+                    State::NotLoaded | State::Unloaded | State::Loading(_) => {
+                        return Err(Error::RangeIsNotLoaded)
+                    }
+                    State::Loaded(state) => state,
+                };
+                let occupancy = state.lock_table.occupancy().await;
+                let pending_prepare_records = state.pending_prepare_records.lock().await;
+                let drained = occupancy.holder_age.is_none()
+                    && occupancy.waiters == 0
+                    && pending_prepare_records.is_empty();
+                let cancelled = !state.draining.load(Ordering::SeqCst);
+                let timed_out = self.clock.instant() >= deadline;
+                if drained || cancelled || timed_out {
+                    if !drained && !cancelled {
+                        // The waiters can't be handed over to the next owner
+                        // of the range, they go back to their coordinators to
+                        // retry there. They also hold the state while they
+                        // wait, which would hold up unloading the range.
+                        state.lock_table.drop_waiters().await;
+                    }
+                    let holder = state.lock_table.current_holder().await;
+                    let holder_unprepared =
+                        holder.is_some_and(|(tx, _)| !pending_prepare_records.contains_key(&tx.id));
+                    return Ok(DrainOutcome {
+                        drained,
+                        prepared: pending_prepare_records.len(),
+                        unprepared: occupancy.waiters + holder_unprepared as usize,
+                    });
+                }
+            }
+            self.clock.sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn cancel_drain(&self) -> Result<(), Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                if state.draining.swap(false, Ordering::SeqCst) {
+                    info!(range_id = ?self.range_id, "range drain cancelled");
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn set_frozen(&self, frozen: bool) -> Result<bool, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                let was_frozen = state.frozen.swap(frozen, Ordering::SeqCst);
+                if was_frozen != frozen {
+                    info!(range_id = ?self.range_id, frozen, "range freeze changed");
+                }
+                Ok(was_frozen)
+            }
+        }
+    }
+
+    async fn soft_state(&self) -> Result<SoftState, Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => Ok(SoftState {
+                conflicts: state.conflicts.stats(),
+                compactions: state.compactions.load(Ordering::Relaxed),
+                purged_versions: state.purged_versions.load(Ordering::Relaxed),
+                write_stalls: state.write_stall.status().stalls,
+                lock_requests_rejected: state.lock_table.occupancy().await.rejected,
+            }),
+        }
+    }
+
+    async fn restore_soft_state(&self, soft_state: SoftState) -> Result<(), Error> {
+        let s = self.state.read().await;
+        match s.deref() {
+            State::NotLoaded | State::Unloaded | State::Loading(_) => Err(Error::RangeIsNotLoaded),
+            State::Loaded(state) => {
+                state.conflicts.restore(&soft_state.conflicts);
+                state
+                    .compactions
+                    .fetch_add(soft_state.compactions, Ordering::Relaxed);
+                state
+                    .purged_versions
+                    .fetch_add(soft_state.purged_versions, Ordering::Relaxed);
+                state.write_stall.restore_stalls(soft_state.write_stalls);
+                state
+                    .lock_table
+                    .restore_rejected(soft_state.lock_requests_rejected);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<S, W> RangeManager<S, W>
+where
+    S: Storage,
+    W: Wal,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        range_id: FullRangeId,
+        config: Config,
+        storage: Arc<S>,
+        epoch_supplier: Arc<dyn EpochSupplier>,
+        wal: W,
+        prefetching_buffer: Arc<PrefetchingBuffer>,
+        bg_runtime: tokio::runtime::Handle,
+        fault_sender: mpsc::UnboundedSender<FullRangeId>,
+        clock: Arc<dyn Clock>,
+        prepare_resolver: Arc<dyn PrepareResolver>,
+    ) -> Arc<Self> {
+        Arc::new(RangeManager {
+            storage_health: Arc::new(StorageHealth::new(range_id, fault_sender)),
+            range_id,
+            storage,
+            epoch_supplier,
+            wal: Arc::new(wal),
+            state: Arc::new(RwLock::new(State::NotLoaded)),
+            prefetching_buffer,
+            bg_runtime,
+            clock,
+            lease_latch: Arc::new(Mutex::new(())),
+            decision_log: DecisionLog::new(&config.range_server.decision_log, range_id),
+            reads: ReadTracker::default(),
+            prepare_resolver,
+            config,
+        })
+    }
+
+    pub fn range_id(&self) -> &FullRangeId {
+        &self.range_id
+    }
+
+    // Failing to log is not worth failing the transaction over, the log is
+    // only there for investigations.
+    fn log_decision(&self, transaction_id: Uuid, event: decision_log::Event) {
+        let Some(log) = &self.decision_log else {
+            return;
+        };
+        let entry = decision_log::Entry {
+            transaction_id,
+            logged_at: self.clock.now(),
+            event,
+        };
+        if let Err(e) = log.append(&entry) {
+            warn!(range_id = ?self.range_id, "failed to append to the decision log: {}", e);
+        }
+    }
+
+    // Both new ranges of a split must be non-empty, and no transaction may
+    // be in the middle of using the range.
+    async fn check_can_split(state: &LoadedState, split_key: &Bytes) -> Result<(), Error> {
+        let key_range = &state.range_info.key_range;
+        if split_key.is_empty()
+            || !key_range.includes(split_key.clone())
+            || key_range.lower_bound_inclusive.as_ref() == Some(split_key)
+        {
+            return Err(Error::KeyIsOutOfRange);
+        }
+        let occupancy = state.lock_table.occupancy().await;
+        if occupancy.holder_age.is_some()
+            || occupancy.waiters > 0
+            || !state.pending_prepare_records.lock().await.is_empty()
+        {
+            return Err(Error::RangeBusy);
+        }
+        Ok(())
+    }
+
+    // Rejects new prepares while too many transactions are prepared on the
+    // range waiting for their coordinators to decide, so that the backlog
+    // can't grow without bounds. Retried prepares are always let through.
+    async fn check_prepare_backlog(&self, state: &LoadedState, tx_id: Uuid) -> Result<(), Error> {
+        let max_pending = self
+            .config
+            .range_server
+            .max_pending_prepares_per_range
+            .unwrap_or(DEFAULT_MAX_PENDING_PREPARES);
+        let pending_prepare_records = state.pending_prepare_records.lock().await;
+        if pending_prepare_records.len() >= max_pending
+            && !pending_prepare_records.contains_key(&tx_id)
+        {
+            // Transactions are decided at epoch granularity, so give the
+            // coordinators about an epoch to drain the backlog.
+            return Err(Error::PrepareBacklogFull {
+                retry_after: self.config.epoch.epoch_duration,
+            });
+        }
+        Ok(())
+    }
+
+    // Rejects new work while an operator has frozen the range. Transactions
+    // already prepared on it are let through, so that retried prepares still
+    // succeed and the transactions can finish.
+    async fn check_frozen(&self, state: &LoadedState, tx_id: Uuid) -> Result<(), Error> {
+        if state.frozen.load(Ordering::SeqCst)
+            && !state
+                .pending_prepare_records
+                .lock()
+                .await
+                .contains_key(&tx_id)
+        {
+            return Err(Error::RangeFrozen);
+        }
+        Ok(())
+    }
+
+    // Rejects transactions that are new to the range while it is being moved
+    // to another range server. Those that already read from it, hold or wait
+    // for its lock or prepared on it are let through to finish.
+    async fn check_draining(&self, state: &LoadedState, tx_id: Uuid) -> Result<(), Error> {
+        if state.draining.load(Ordering::SeqCst)
+            && !state.read_versions.is_reader(tx_id)
+            && !state.lock_table.is_currently_holding(tx_id).await
+            && !state
+                .pending_prepare_records
+                .lock()
+                .await
+                .contains_key(&tx_id)
+        {
+            return Err(Error::RangeDraining);
+        }
+        Ok(())
+    }
+
+    // Reads `key` as of `epoch`.
+    async fn read_at_epoch(
+        &self,
+        state: &LoadedState,
+        key: Bytes,
+        epoch: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        let _no_commits = self.settle_epoch(state, epoch).await?;
+        self.storage_health
+            .check(self.storage.get_at_epoch(self.range_id, key, epoch).await)
+    }
+
+    // Scans `key_range`, already limited to the range, as of `epoch`.
+    async fn scan_at_epoch(
+        &self,
+        state: &LoadedState,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let _no_commits = self.settle_epoch(state, epoch).await?;
+        if key_range.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.storage_health.check(
+            self.storage
+                .scan_at_epoch(self.range_id, key_range, limit, epoch)
+                .await,
+        )
+    }
+
+    // Waits until storage holds everything committed at or before `epoch`,
+    // and returns holding off commits and compactions while it is read.
+    // Commits land in the highest known epoch of the range when they
+    // prepared or a later one, so once that is past `epoch`, only the
+    // transactions prepared before can still commit at or before it, and
+    // this waits for them.
+    async fn settle_epoch<'a>(
+        &self,
+        state: &'a LoadedState,
+        epoch: u64,
+    ) -> Result<tokio::sync::RwLockWriteGuard<'a, ()>, Error> {
+        if epoch < state.snapshot_floor.load(Ordering::SeqCst) {
+            return Err(Error::SnapshotTooOld);
+        }
+        let deadline = self.clock.instant() + SNAPSHOT_READ_TIMEOUT;
+        if state.highest_known_epoch.read().await <= epoch {
+            self.epoch_supplier
+                .wait_until_epoch(
+                    epoch + 1,
+                    chrono::Duration::from_std(SNAPSHOT_READ_TIMEOUT).unwrap(),
+                )
+                .await
+                .map_err(Error::from_epoch_supplier_error)?;
+            state.highest_known_epoch.maybe_update(epoch + 1).await;
+        }
+        while state
+            .pending_prepare_records
+            .lock()
+            .await
+            .values()
+            .any(|prepare| prepare.commits_from <= epoch)
+        {
+            if self.clock.instant() >= deadline {
+                return Err(Error::Timeout);
+            }
+            self.clock.sleep(SNAPSHOT_POLL_INTERVAL).await;
+        }
+        // Commits stop being pending before they apply their writes, so wait
+        // out the ones applying. Holding the latch also keeps compactions
+        // out, which may have raised the floor past the epoch while waiting.
+        let no_commits = state.apply_latch.write().await;
+        if epoch < state.snapshot_floor.load(Ordering::SeqCst) {
+            return Err(Error::SnapshotTooOld);
+        }
+        Ok(no_commits)
+    }
+
+    // Aborts snapshot transactions writing a key that was written after their
+    // snapshot. Only holds until the transaction releases the range lock.
+    async fn check_written_since(&self, key: &Bytes, snapshot_epoch: u64) -> Result<(), Error> {
+        let newest = self.storage_health.check(
+            self.storage
+                .get_versions(self.range_id, key.clone(), 1)
+                .await,
+        )?;
+        if newest.first().is_some_and(|v| v.epoch > snapshot_epoch) {
+            return Err(Error::TransactionAborted(
+                TransactionAbortReason::WriteConflict,
+            ));
+        }
+        Ok(())
+    }
+
+    // Rejects new prepares that write while the range's writes are stalled,
+    // so that clients back off instead of timing out. Retried prepares are
+    // always let through.
+    async fn check_write_stall(&self, state: &LoadedState, tx_id: Uuid) -> Result<(), Error> {
+        if let Some(retry_after) = state.write_stall.retry_after() {
+            if !state
+                .pending_prepare_records
+                .lock()
+                .await
+                .contains_key(&tx_id)
+            {
+                return Err(Error::WriteStalled { retry_after });
+            }
+        }
+        Ok(())
+    }
+
+    fn num_epochs_per_lease(&self) -> u64 {
+        let epoch_duration = self.config.epoch.epoch_duration;
+        // Calculate how many epochs we need for the desired lease duration.
+        // TODO(yanniszark): Put this in the config.
+        let intended_lease_duration = Duration::from_secs(2);
+        let num_epochs_per_lease = intended_lease_duration
+            .as_nanos()
+            .checked_div(epoch_duration.as_nanos())
+            .and_then(|n| u64::try_from(n).ok())
+            .unwrap();
+        // Ensure that we have at least one epoch per lease.
+        std::cmp::max(1, num_epochs_per_lease)
+    }
+
+    async fn load_inner(&self) -> Result<LoadedState, Error> {
+        let epoch_supplier = self.epoch_supplier.clone();
+        let storage = self.storage.clone();
+        let wal = self.wal.clone();
+        let range_id = self.range_id;
+        let bg_runtime = self.bg_runtime.clone();
+        let state = self.state.clone();
+        let storage_health = self.storage_health.clone();
+        let clock = self.clock.clone();
+        let lock_table_config = self.config.range_server.lock_table.clone();
+        let conflict_stats_config = self.config.range_server.conflict_stats.clone();
+        let write_stall_config = self.config.range_server.write_stall.clone();
+        let lease_renewal_interval = self.config.range_server.range_maintenance_duration;
+        let num_epochs_per_lease = self.num_epochs_per_lease();
+        let lease_latch = self.lease_latch.clone();
+        let prefetching_buffer = self.prefetching_buffer.clone();
+        let prepare_resolver = self.prepare_resolver.clone();
+
+        self.bg_runtime
+            .spawn(async move {
+                // TODO: handle all errors instead of panicking.
+                let epoch = epoch_supplier
+                    .read_epoch()
+                    .await
+                    .map_err(Error::from_epoch_supplier_error)?;
+                let mut range_info = storage
+                    .take_ownership_and_load_range(range_id)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                // Epoch read from the provider can be 1 less than the true epoch. The highest known epoch
+                // of a range cannot move backward even across range load/unloads, so to maintain that guarantee
+                // we just wait for the epoch to advance once.
+                epoch_supplier
+                    .wait_until_epoch(epoch + 1, chrono::Duration::seconds(10))
+                    .await
+                    .map_err(Error::from_epoch_supplier_error)?;
+                // Get a new epoch lease.
+                let highest_known_epoch = epoch + 1;
+                let new_epoch_lease_lower_bound =
+                    std::cmp::max(highest_known_epoch, range_info.epoch_lease.1 + 1);
+                let new_epoch_lease_upper_bound =
+                    new_epoch_lease_lower_bound + num_epochs_per_lease;
+                storage
+                    .renew_epoch_lease(
+                        range_id,
+                        (new_epoch_lease_lower_bound, new_epoch_lease_upper_bound),
+                        range_info.leader_sequence_number,
+                    )
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                range_info.epoch_lease = (new_epoch_lease_lower_bound, new_epoch_lease_upper_bound);
+                wal.sync().await.map_err(Error::from_wal_error)?;
+                let prepares = storage
+                    .load_prepares(range_id)
+                    .await
+                    .map_err(Error::from_storage_error)?;
+                // Create a recurrent task to renew.
+                let lease_clock = clock.clone();
+                let lease_storage = storage.clone();
+                let lease_storage_health = storage_health.clone();
+                bg_runtime.spawn(async move {
+                    Self::renew_epoch_lease_task(
+                        range_id,
+                        epoch_supplier,
+                        lease_storage,
+                        lease_storage_health,
+                        lease_clock,
+                        state,
+                        lease_latch,
+                        lease_renewal_interval,
+                        num_epochs_per_lease,
+                    )
+                    .await
+                });
+                // TODO: apply WAL here!
+                let loaded_state = LoadedState {
+                    range_info,
+                    highest_known_epoch: HighestKnownEpoch::new(highest_known_epoch),
+                    lock_table: lock_table::LockTable::new(clock.clone(), lock_table_config),
+                    pending_prepare_records: Mutex::new(HashMap::new()),
+                    read_versions: ReadVersions::default(),
+                    apply_latch: RwLock::new(()),
+                    conflicts: ConflictTracker::new(conflict_stats_config),
+                    compactions: AtomicU64::new(0),
+                    purged_versions: AtomicU64::new(0),
+                    write_stall: WriteStallDetector::new(
+                        range_id,
+                        write_stall_config,
+                        clock.clone(),
+                    ),
+                    checksums: Mutex::new(ChecksumStats::default()),
+                    frozen: AtomicBool::new(false),
+                    draining: AtomicBool::new(false),
+                    snapshot_floor: AtomicU64::new(highest_known_epoch),
+                };
+                if prepares.len() > 1 {
+                    for (id, record) in prepares {
+                        Self::finish_stale_prepare(
+                            range_id,
+                            &loaded_state,
+                            storage.as_ref(),
+                            &storage_health,
+                            &prefetching_buffer,
+                            prepare_resolver.as_ref(),
+                            clock.as_ref(),
+                            id,
+                            record,
+                        )
+                        .await?;
+                    }
+                } else {
+                    Self::recover_prepares(range_id, &loaded_state, clock.as_ref(), prepares)
+                        .await?;
+                }
+                Ok(loaded_state)
+            })
+            .await
+            .unwrap()
+    }
+
+    // Picks up the transaction left prepared by the previous owner of the
+    // range, if any, holding the range lock for it until the coordinator
+    // commits or aborts it just like it would have with the previous owner.
+    async fn recover_prepares(
+        range_id: FullRangeId,
+        state: &LoadedState,
+        clock: &dyn Clock,
+        prepares: Vec<(Uuid, Bytes)>,
+    ) -> Result<(), Error> {
+        for (id, record) in prepares {
+            let prepare = flatbuffers::root::<PrepareRequest>(&record)
+                .map_err(|e| Error::InternalError(Arc::new(e)))?;
+            let started = prepare
+                .transaction_info()
+                .and_then(|info| util::flatbuf::deserialize_started(&info))
+                .unwrap_or_else(|| clock.now());
+            let tx = Arc::new(match prepare.transaction_info() {
+                Some(info) => TransactionInfo {
+                    id,
+                    started,
+                    first_started: started,
+                    overall_timeout: Duration::from_micros(info.overall_timeout_us() as u64),
+                    labels: util::flatbuf::deserialize_labels(&info),
+                    isolation: util::flatbuf::deserialize_isolation(&info),
+                    snapshot_epoch: util::flatbuf::deserialize_snapshot_epoch(&info),
+                },
+                None => TransactionInfo {
+                    id,
+                    started,
+                    first_started: started,
+                    overall_timeout: Duration::ZERO,
+                    labels: Default::default(),
+                    isolation: Default::default(),
+                    snapshot_epoch: None,
+                },
+            });
+            // Records logged before the prepare time was kept lack it. The
+            // transaction prepared no earlier than it started, so that keeps
+            // the horizon reported for it safe.
+            let prepared_at = Some(prepare.prepared_at_us())
+                .filter(|prepared_at| *prepared_at > 0)
+                .and_then(DateTime::from_timestamp_micros)
+                .unwrap_or(tx.started);
+            // Nobody else can hold the lock of a range that is being loaded.
+            state.lock_table.acquire(tx).await?.await.map_err(|_| {
+                Error::TransactionAborted(TransactionAbortReason::TransactionLockLost)
+            })?;
+            info!(transaction_id = %id, range_id = ?range_id, "recovered prepared transaction");
+            state.pending_prepare_records.lock().await.insert(
+                id,
+                PendingPrepare {
+                    record,
+                    prepared_at,
+                    // Its commit epoch is unknown.
+                    commits_from: 0,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    // Finishes a transaction left prepared alongside others, which happens
+    // when transactions lost the range lock without their prepare being
+    // removed. Which of them still holds the lock is unknown, so rather than
+    // picking one, they are all decided, undecided ones by aborting them, and
+    // the writes of the committed ones applied.
+    #[allow(clippy::too_many_arguments)]
+    async fn finish_stale_prepare(
+        range_id: FullRangeId,
+        state: &LoadedState,
+        storage: &S,
+        storage_health: &StorageHealth,
+        prefetching_buffer: &PrefetchingBuffer,
+        prepare_resolver: &dyn PrepareResolver,
+        clock: &dyn Clock,
+        id: Uuid,
+        record: Bytes,
+    ) -> Result<(), Error> {
+        let commit_epoch = prepare_resolver.abort_unless_decided(id).await?;
+        if let Some(epoch) = commit_epoch {
+            let prepare = flatbuffers::root::<PrepareRequest>(&record)
+                .map_err(|e| Error::InternalError(Arc::new(e)))?;
+            state.highest_known_epoch.maybe_update(epoch).await;
+            let version = KeyVersion {
+                epoch,
+                version_counter: 0,
+                transaction_id: id,
+            };
+            for write in committed_writes(&prepare, clock.now()) {
+                Self::apply_write(
+                    storage,
+                    storage_health,
+                    prefetching_buffer,
+                    range_id,
+                    version,
+                    write,
+                )
+                .await?;
+            }
+        }
+        storage_health.check(
+            storage
+                .remove_prepare(range_id, id, state.range_info.leader_sequence_number)
+                .await,
+        )?;
+        info!(
+            transaction_id = %id,
+            range_id = ?range_id,
+            commit_epoch,
+            "finished stale prepared transaction"
+        );
+        Ok(())
+    }
+
+    // Applies a write of a committed transaction to storage and to the
+    // prefetch buffer, and returns the key written.
+    async fn apply_write(
+        storage: &S,
+        storage_health: &StorageHealth,
+        prefetching_buffer: &PrefetchingBuffer,
+        range_id: FullRangeId,
+        version: KeyVersion,
+        write: CommittedWrite,
+    ) -> Result<Bytes, Error> {
+        // TODO: we should do the storage writes lazily in the background
+        match write {
+            CommittedWrite::Put {
+                key,
+                value,
+                expires_at,
+            } => {
+                storage_health.check(
+                    storage
+                        .upsert(range_id, key.clone(), value.clone(), version, expires_at)
+                        .await,
+                )?;
+                // Update the prefetch buffer if this key has been requested by a prefetch call
+                prefetching_buffer
+                    .upsert(key.clone(), value, expires_at)
+                    .await;
+                Ok(key)
+            }
+            CommittedWrite::Delete { key } => {
+                storage_health.check(storage.delete(range_id, key.clone(), version).await)?;
+                // Delete the key from the prefetch buffer if this key has been requested by a prefetch call
+                prefetching_buffer.delete(key.clone()).await;
+                Ok(key)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn renew_epoch_lease_task(
+        range_id: FullRangeId,
+        epoch_supplier: Arc<dyn EpochSupplier>,
+        storage: Arc<S>,
+        storage_health: Arc<StorageHealth>,
+        clock: Arc<dyn Clock>,
+        state: Arc<RwLock<State>>,
+        lease_latch: Arc<Mutex<()>>,
+        lease_renewal_interval: std::time::Duration,
+        num_epochs_per_lease: u64,
+    ) -> Result<(), Error> {
+        loop {
+            let leader_sequence_number: u64;
+            let old_lease: (u64, u64);
+            let epoch = epoch_supplier
+                .read_epoch()
+                .await
+                .map_err(Error::from_epoch_supplier_error)?;
             let highest_known_epoch = epoch + 1;
+            let lease_guard = lease_latch.lock().await;
             if let State::Loaded(state) = state.read().await.deref() {
                 old_lease = state.range_info.epoch_lease;
                 leader_sequence_number = state.range_info.leader_sequence_number;
             } else {
-                tokio::time::sleep(lease_renewal_interval).await;
+                drop(lease_guard);
+                clock.sleep(lease_renewal_interval).await;
                 continue;
             }
             // How far are we from the current lease expiring? Check so we don't
             // end up taking the lease for an unbounded amount of epochs.
             let num_epochs_left = old_lease.1.saturating_sub(epoch);
             if num_epochs_left > 2 * num_epochs_per_lease {
-                tokio::time::sleep(lease_renewal_interval).await;
+                drop(lease_guard);
+                clock.sleep(lease_renewal_interval).await;
                 continue;
             }
             let new_epoch_lease_lower_bound = std::cmp::max(highest_known_epoch, old_lease.1 + 1);
@@ -527,19 +1946,28 @@ where
             if (new_epoch_lease_lower_bound - old_lease.1) == 1 {
                 new_lease = (old_lease.0, new_epoch_lease_upper_bound);
             }
-            // TODO: We should handle some errors here. For example:
-            // - If the error seems transient (e.g., a timeout), we should retry.
-            // - If the error is something like RangeOwnershipLost, we should unload the range.
-            storage
+            // TODO: If the error is something like RangeOwnershipLost, we should unload the range.
+            let renewal = storage
                 .renew_epoch_lease(range_id, new_lease, leader_sequence_number)
-                .await
-                .map_err(Error::from_storage_error)?;
+                .await;
+            if let Err(e) = storage_health.check(renewal) {
+                match e {
+                    // Retry transient failures, unless they faulted the range.
+                    Error::Timeout | Error::InternalError(_) if !storage_health.is_faulted() => {
+                        drop(lease_guard);
+                        clock.sleep(lease_renewal_interval).await;
+                        continue;
+                    }
+                    _ => return Err(e),
+                }
+            }
 
             if let State::Loaded(state) = state.write().await.deref_mut() {
-                // This should never happen as only this task changes the epoch lease.
+                // This should never happen as the lease is only changed
+                // while holding the lease latch.
                 assert_eq!(
                     state.range_info.epoch_lease, old_lease,
-                    "Epoch lease changed by someone else, but only this task should be changing it!"
+                    "Epoch lease changed without holding the lease latch!"
                 );
                 state.range_info.epoch_lease = new_lease;
                 state
@@ -549,8 +1977,9 @@ where
             } else {
                 return Err(Error::RangeIsNotLoaded);
             }
+            drop(lease_guard);
             // Sleep for a while before renewing the lease again.
-            tokio::time::sleep(lease_renewal_interval).await;
+            clock.sleep(lease_renewal_interval).await;
         }
     }
 
@@ -559,28 +1988,161 @@ where
         state: &LoadedState,
         tx: Arc<TransactionInfo>,
     ) -> Result<(), Error> {
-        let receiver = state.lock_table.acquire(tx.clone()).await?;
-        // TODO: allow timing out locks when transaction timeouts are implemented.
-        receiver.await.unwrap();
-        Ok(())
+        let receiver = match state.lock_table.acquire(tx.clone()).await {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                info!(
+                    transaction_id = %tx.id,
+                    labels = ?tx.labels,
+                    range_id = ?self.range_id,
+                    "failed to acquire range lock: {:?}",
+                    e
+                );
+                return Err(e);
+            }
+        };
+        let res = state.lock_table.wait(tx.id, receiver).await;
+        if let Err(Error::LockWaitTimeout) = res {
+            info!(
+                transaction_id = %tx.id,
+                labels = ?tx.labels,
+                range_id = ?self.range_id,
+                "timed out waiting for range lock"
+            );
+        }
+        res
     }
 
-    /// Get from database without acquiring any locks
-    /// This is a very basic copy of the 'get' function
-    pub async fn prefetch_get(&self, key: Bytes) -> Result<Option<Bytes>, Error> {
-        let val = self
-            .storage
-            .get(self.range_id, key.clone())
-            .await
-            .map_err(Error::from_storage_error)?;
-        Ok(val)
+    /// Get from database without acquiring any locks, along with when the
+    /// value expires, if it does
+    pub async fn prefetch_get(
+        &self,
+        key: Bytes,
+    ) -> Result<(Option<Bytes>, Option<DateTime<Utc>>), Error> {
+        // Only the newest version is asked for, which storage always keeps.
+        let newest = self
+            .storage_health
+            .check(
+                self.storage
+                    .get_versions(self.range_id, key.clone(), 1)
+                    .await,
+            )?
+            .pop();
+        match newest {
+            Some(version) if !is_expired(version.expires_at, self.clock.now()) => {
+                Ok((version.value, version.expires_at))
+            }
+            _ => Ok((None, None)),
+        }
+    }
+}
+
+// Re-encodes the prepare as the range logs it: with the new values of the
+// counters it increments as puts, and without the increments, so committing
+// and anything else that reads the prepare record only ever sees plain
+// writes, and with the time the transaction was first prepared at.
+fn encode_prepare_record(
+    prepare: &PrepareRequest<'_>,
+    tx: &TransactionInfo,
+    counters: BTreeMap<Bytes, Bytes>,
+    prepared_at: DateTime<Utc>,
+) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+    let request_id = prepare.request_id().map(|id| {
+        Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(util::flatbuf::deserialize_uuid(id)),
+        )
+    });
+    let transaction_id = Some(Uuidu128::create(
+        &mut fbb,
+        &util::flatbuf::serialize_uuid(tx.id),
+    ));
+    let range_id = prepare
+        .range_id()
+        .and_then(|id| util::flatbuf::deserialize_range_id(&id))
+        .map(|id| util::flatbuf::serialize_range_id(&mut fbb, &id));
+    let mut puts_vector = Vec::new();
+    let original_puts = prepare.puts().into_iter().flatten().map(|put| {
+        (
+            put.key().and_then(|k| k.k()).map_or(&[][..], |k| k.bytes()),
+            put.value().map_or(&[][..], |v| v.bytes()),
+            put.ttl_ms(),
+        )
+    });
+    let counter_puts = counters.iter().map(|(k, v)| (&k[..], &v[..], 0));
+    for (k, v, ttl_ms) in original_puts.chain(counter_puts) {
+        let k = Some(fbb.create_vector(k));
+        let key = Key::create(&mut fbb, &KeyArgs { k });
+        let value = Some(fbb.create_vector(v));
+        puts_vector.push(Record::create(
+            &mut fbb,
+            &RecordArgs {
+                key: Some(key),
+                value,
+                ttl_ms,
+            },
+        ));
+    }
+    let puts = Some(fbb.create_vector(&puts_vector));
+    let mut deletes_vector = Vec::new();
+    for del in prepare.deletes().into_iter().flatten() {
+        let k = Some(fbb.create_vector(del.k().map_or(&[][..], |k| k.bytes())));
+        deletes_vector.push(Key::create(&mut fbb, &KeyArgs { k }));
     }
+    let deletes = Some(fbb.create_vector(&deletes_vector));
+    let mut conditions_vector = Vec::new();
+    for condition in prepare.conditions().into_iter().flatten() {
+        let k = Some(
+            fbb.create_vector(
+                condition
+                    .key()
+                    .and_then(|k| k.k())
+                    .map_or(&[][..], |k| k.bytes()),
+            ),
+        );
+        let key = Key::create(&mut fbb, &KeyArgs { k });
+        let expected_value = condition
+            .expected_value()
+            .map(|v| fbb.create_vector(v.bytes()));
+        conditions_vector.push(Condition::create(
+            &mut fbb,
+            &ConditionArgs {
+                key: Some(key),
+                expected_value,
+                expect_absent: condition.expect_absent(),
+            },
+        ));
+    }
+    let conditions = Some(fbb.create_vector(&conditions_vector));
+    let transaction_info = prepare
+        .transaction_info()
+        .map(|_| util::flatbuf::serialize_transaction_info(&mut fbb, tx));
+    let fbb_root = PrepareRequest::create(
+        &mut fbb,
+        &PrepareRequestArgs {
+            request_id,
+            transaction_id,
+            range_id,
+            has_reads: prepare.has_reads(),
+            puts,
+            deletes,
+            conditions,
+            transaction_info,
+            increments: None,
+            prepared_at_us: prepared_at.timestamp_micros(),
+        },
+    );
+    fbb.finish(fbb_root, None);
+    fbb.finished_data().to_vec()
 }
 
 #[cfg(test)]
 mod tests {
+    use common::clock::SystemClock;
     use common::config::{
-        CassandraConfig, EpochConfig, FrontendConfig, HostPort, RangeServerConfig, UniverseConfig,
+        CassandraConfig, ConflictStatsConfig, EpochConfig, FrontendConfig, HostPort,
+        RangeServerConfig, UniverseConfig,
     };
     use common::transaction_info::TransactionInfo;
     use common::util;
@@ -628,6 +2190,38 @@ mod tests {
             writes: Vec<(Bytes, Bytes)>,
             deletes: Vec<Bytes>,
             has_reads: bool,
+        ) -> Result<(), Error> {
+            self.prepare_conditional_transaction(tx, writes, deletes, Vec::new(), has_reads)
+                .await
+        }
+
+        async fn prepare_conditional_transaction(
+            &self,
+            tx: Arc<TransactionInfo>,
+            writes: Vec<(Bytes, Bytes)>,
+            deletes: Vec<Bytes>,
+            conditions: Vec<(Bytes, Option<Bytes>)>,
+            has_reads: bool,
+        ) -> Result<(), Error> {
+            self.prepare_transaction_with_increments(
+                tx,
+                writes,
+                deletes,
+                conditions,
+                Vec::new(),
+                has_reads,
+            )
+            .await
+        }
+
+        async fn prepare_transaction_with_increments(
+            &self,
+            tx: Arc<TransactionInfo>,
+            writes: Vec<(Bytes, Bytes)>,
+            deletes: Vec<Bytes>,
+            conditions: Vec<(Bytes, Option<Bytes>)>,
+            increments: Vec<(Bytes, i64)>,
+            has_reads: bool,
         ) -> Result<(), Error> {
             let mut fbb = FlatBufferBuilder::new();
             let transaction_id = Some(Uuidu128::create(
@@ -648,6 +2242,7 @@ mod tests {
                     &RecordArgs {
                         key: Some(key),
                         value: Some(value),
+                        ..Default::default()
                     },
                 ));
             }
@@ -659,6 +2254,34 @@ mod tests {
                 del_vector.push(key);
             }
             let deletes = Some(fbb.create_vector(&del_vector));
+            let mut condition_vector = Vec::new();
+            for (k, expected) in conditions {
+                let k = Some(fbb.create_vector(k.to_vec().as_slice()));
+                let key = Key::create(&mut fbb, &KeyArgs { k });
+                let expected_value = expected.as_ref().map(|v| fbb.create_vector(v.as_ref()));
+                condition_vector.push(Condition::create(
+                    &mut fbb,
+                    &ConditionArgs {
+                        key: Some(key),
+                        expected_value,
+                        expect_absent: expected.is_none(),
+                    },
+                ));
+            }
+            let conditions = Some(fbb.create_vector(&condition_vector));
+            let mut increment_vector = Vec::new();
+            for (k, delta) in increments {
+                let k = Some(fbb.create_vector(k.to_vec().as_slice()));
+                let key = Key::create(&mut fbb, &KeyArgs { k });
+                increment_vector.push(Increment::create(
+                    &mut fbb,
+                    &IncrementArgs {
+                        key: Some(key),
+                        delta,
+                    },
+                ));
+            }
+            let increments = Some(fbb.create_vector(&increment_vector));
             let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, &self.range_id));
             let fbb_root = PrepareRequest::create(
                 &mut fbb,
@@ -669,6 +2292,10 @@ mod tests {
                     has_reads,
                     puts,
                     deletes,
+                    conditions,
+                    transaction_info: None,
+                    increments,
+                    prepared_at_us: 0,
                 },
             );
             fbb.finish(fbb_root, None);
@@ -706,6 +2333,17 @@ mod tests {
         }
     }
 
+    // Decisions of transactions, which are aborted unless set otherwise.
+    #[derive(Default)]
+    struct Decisions(std::sync::Mutex<HashMap<Uuid, u64>>);
+
+    #[async_trait]
+    impl PrepareResolver for Decisions {
+        async fn abort_unless_decided(&self, transaction_id: Uuid) -> Result<Option<u64>, Error> {
+            Ok(self.0.lock().unwrap().get(&transaction_id).copied())
+        }
+    }
+
     struct TestContext {
         rm: Arc<RM>,
         storage_context: crate::storage::cassandra::for_testing::TestContext,
@@ -731,6 +2369,19 @@ mod tests {
                 range_maintenance_duration: time::Duration::from_secs(1),
                 proto_server_addr: HostPort::from_str("127.0.0.1:50054").unwrap(),
                 fast_network_addr: HostPort::from_str("127.0.0.1:50055").unwrap(),
+                preflight_epoch_advance_timeout: None,
+                max_pending_prepares_per_range: None,
+                max_epoch_lease_extension: None,
+                handoff_drain_timeout: None,
+                lock_table: Default::default(),
+                conflict_stats: Default::default(),
+                write_stall: Default::default(),
+                checksum_verification: Default::default(),
+                decision_log: Default::default(),
+                expiry: Default::default(),
+                fast_network_transport: Default::default(),
+                mirroring: Default::default(),
+                transaction_recovery: Default::default(),
             },
             universe: UniverseConfig {
                 proto_server_addr: "127.0.0.1:123".parse().unwrap(),
@@ -739,73 +2390,906 @@ mod tests {
                 proto_server_addr: "127.0.0.1:124".parse().unwrap(),
                 fast_network_addr: HostPort::from_str("127.0.0.1:125").unwrap(),
                 transaction_overall_timeout: time::Duration::from_secs(10),
+                fast_network_transport: Default::default(),
             },
             cassandra: CassandraConfig {
                 cql_addr: HostPort {
                     host: "127.0.0.1".to_string(),
                     port: 9042,
                 },
+                consistency: Default::default(),
             },
             regions: std::collections::HashMap::new(),
+            admin: Default::default(),
+            quic: Default::default(),
             epoch: epoch_config,
         };
-        let rm = Arc::new(RM {
-            range_id,
-            config,
-            storage: cassandra,
+        let rm = Arc::new(RM {
+            range_id,
+            config,
+            storage: cassandra,
+            wal: Arc::new(InMemoryWal::new()),
+            epoch_supplier: epoch_supplier.clone(),
+            state: Arc::new(RwLock::new(State::NotLoaded)),
+            prefetching_buffer,
+            bg_runtime: tokio::runtime::Handle::current().clone(),
+            storage_health: Arc::new(StorageHealth::new(range_id, mpsc::unbounded_channel().0)),
+            clock: Arc::new(SystemClock),
+            lease_latch: Arc::new(Mutex::new(())),
+            decision_log: None,
+            reads: Default::default(),
+            prepare_resolver: Arc::new(Decisions::default()),
+        });
+        let rm_copy = rm.clone();
+        let init_handle = tokio::spawn(async move { rm_copy.load().await.unwrap() });
+        // Give some delay so the RM can see the epoch advancing.
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        epoch_supplier.set_epoch(1).await;
+        init_handle.await.unwrap();
+        TestContext {
+            rm,
+            storage_context,
+        }
+    }
+
+    fn start_transaction() -> Arc<TransactionInfo> {
+        let started = chrono::Utc::now();
+        Arc::new(TransactionInfo {
+            id: Uuid::new_v4(),
+            started,
+            first_started: started,
+            overall_timeout: time::Duration::from_secs(10),
+            labels: std::collections::BTreeMap::new(),
+            isolation: Default::default(),
+            snapshot_epoch: None,
+        })
+    }
+
+    // A transaction with snapshot isolation, reading as of before anything
+    // the tests commit.
+    fn start_snapshot_transaction() -> Arc<TransactionInfo> {
+        let mut tx = (*start_transaction()).clone();
+        tx.isolation = IsolationLevel::Snapshot;
+        tx.snapshot_epoch = Some(0);
+        Arc::new(tx)
+    }
+
+    #[tokio::test]
+    async fn basic_get_put() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx1 = start_transaction();
+        assert!(rm
+            .get(tx1.clone(), key.clone(), ReadMode::Locking)
+            .await
+            .unwrap()
+            .val
+            .is_none());
+        rm.abort_transaction(tx1.clone()).await;
+        let tx2 = start_transaction();
+        let val = Bytes::from_static(b"I have a value!");
+        rm.prepare_transaction(
+            tx2.clone(),
+            Vec::from([(key.clone(), val.clone())]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx2.clone()).await.unwrap();
+        let tx3 = start_transaction();
+        let val_after_commit = rm
+            .get(tx3.clone(), key.clone(), ReadMode::Locking)
+            .await
+            .unwrap()
+            .val
+            .unwrap();
+        assert!(val_after_commit == val);
+    }
+
+    #[tokio::test]
+    async fn split_copies_records_into_new_ranges() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let low_key = Bytes::from_static(b"apple");
+        let high_key = Bytes::from_static(b"zucchini");
+        let tx = start_transaction();
+        rm.prepare_transaction(
+            tx.clone(),
+            Vec::from([
+                (low_key.clone(), Bytes::from_static(b"1")),
+                (high_key.clone(), Bytes::from_static(b"2")),
+            ]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            rm.split(Bytes::from_static(b"m")).await,
+            Err(Error::RangeBusy)
+        ));
+        rm.commit_transaction(tx).await.unwrap();
+        let highest_known_epoch = match rm.state.read().await.deref() {
+            State::Loaded(state) => state.highest_known_epoch.read().await,
+            _ => panic!("range not loaded"),
+        };
+
+        let [left, right] = rm.split(Bytes::from_static(b"m")).await.unwrap();
+        assert!(rm.is_unloaded().await);
+        assert_eq!(
+            left.key_range.upper_bound_exclusive,
+            Some(Bytes::from_static(b"m"))
+        );
+        assert_eq!(
+            right.key_range.lower_bound_inclusive,
+            Some(Bytes::from_static(b"m"))
+        );
+        let storage = &context.storage_context.cassandra;
+        let child = |range_id| FullRangeId {
+            keyspace_id: context.storage_context.keyspace_id,
+            range_id,
+        };
+        assert_eq!(
+            storage
+                .get(child(left.range_id), low_key.clone())
+                .await
+                .unwrap(),
+            Some(Bytes::from_static(b"1"))
+        );
+        assert_eq!(
+            storage
+                .get(child(left.range_id), high_key.clone())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            storage.get(child(right.range_id), high_key).await.unwrap(),
+            Some(Bytes::from_static(b"2"))
+        );
+        let lease = storage
+            .take_ownership_and_load_range(child(right.range_id))
+            .await
+            .unwrap();
+        assert_eq!(lease.key_range, right.key_range);
+        // Served from the epoch after the last one the range knew of, not
+        // from after the end of its lease.
+        assert_eq!(
+            lease.epoch_lease,
+            (highest_known_epoch, highest_known_epoch)
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_reports_lost_locks_and_read_conflicts() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+
+        let tx1 = start_transaction();
+        rm.get(tx1.clone(), key.clone(), ReadMode::Locking)
+            .await
+            .unwrap();
+        rm.validate(tx1.clone(), true).await.unwrap();
+        rm.abort_transaction(tx1.clone()).await;
+        assert!(matches!(
+            rm.validate(tx1.clone(), true).await,
+            Err(Error::TransactionAborted(
+                TransactionAbortReason::TransactionLockLost
+            ))
+        ));
+
+        let tx2 = start_transaction();
+        rm.get(tx2.clone(), key.clone(), ReadMode::Optimistic)
+            .await
+            .unwrap();
+        rm.validate(tx2.clone(), true).await.unwrap();
+        let tx3 = start_transaction();
+        rm.prepare_transaction(
+            tx3.clone(),
+            Vec::from([(key.clone(), Bytes::from_static(b"new value"))]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx3.clone()).await.unwrap();
+        assert!(matches!(
+            rm.validate(tx2.clone(), true).await,
+            Err(Error::TransactionAborted(
+                TransactionAbortReason::ReadConflict
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn new_owner_recovers_prepared_transaction() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let val = Bytes::from_static(b"prepared before the crash");
+        let tx = start_transaction();
+        rm.prepare_transaction(
+            tx.clone(),
+            Vec::from([(key.clone(), val.clone())]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        let prepared_at = rm.list_in_flight_transactions().await.unwrap()[0]
+            .prepared_at
+            .unwrap();
+
+        // The range server crashes, and another one loads the range.
+        let epoch_supplier = Arc::new(EpochSupplier::new());
+        epoch_supplier.set_epoch(1).await;
+        let successor = Arc::new(RM {
+            range_id: rm.range_id,
+            config: rm.config.clone(),
+            storage: rm.storage.clone(),
+            wal: Arc::new(InMemoryWal::new()),
+            epoch_supplier: epoch_supplier.clone(),
+            state: Arc::new(RwLock::new(State::NotLoaded)),
+            prefetching_buffer: Arc::new(PrefetchingBuffer::new()),
+            bg_runtime: tokio::runtime::Handle::current().clone(),
+            storage_health: Arc::new(StorageHealth::new(rm.range_id, mpsc::unbounded_channel().0)),
+            clock: Arc::new(SystemClock),
+            lease_latch: Arc::new(Mutex::new(())),
+            decision_log: None,
+            reads: Default::default(),
+            prepare_resolver: Arc::new(Decisions::default()),
+        });
+        let successor_copy = successor.clone();
+        let load_handle = tokio::spawn(async move { successor_copy.load().await.unwrap() });
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        epoch_supplier.set_epoch(2).await;
+        load_handle.await.unwrap();
+
+        let in_flight = successor.list_in_flight_transactions().await.unwrap();
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].id, tx.id);
+        assert!(in_flight[0].prepared);
+        // It has been prepared since before the crash, not since the load.
+        assert_eq!(
+            in_flight[0].prepared_at.unwrap().timestamp_micros(),
+            prepared_at.timestamp_micros()
+        );
+        successor.commit_transaction(tx.clone()).await.unwrap();
+        let reader = start_transaction();
+        let read = successor
+            .get(reader, key.clone(), ReadMode::Locking)
+            .await
+            .unwrap();
+        assert_eq!(read.val, Some(val));
+        assert!(successor
+            .storage
+            .load_prepares(successor.range_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn stale_prepares_are_finished_on_load() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let val = Bytes::from_static(b"committed before the crash");
+        let committed = start_transaction();
+        rm.prepare_transaction(
+            committed.clone(),
+            Vec::from([(key.clone(), val.clone())]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        // Another transaction's prepare was left behind, writing the same.
+        let leader_sequence_number = match rm.state.read().await.deref() {
+            State::Loaded(state) => state.range_info.leader_sequence_number,
+            _ => panic!("range not loaded"),
+        };
+        let (_, record) = rm.storage.load_prepares(rm.range_id).await.unwrap()[0].clone();
+        let aborted = start_transaction();
+        rm.storage
+            .persist_prepare(rm.range_id, aborted.id, record, leader_sequence_number)
+            .await
+            .unwrap();
+
+        // The range server crashes after the commit decision, and another one
+        // loads the range.
+        let decisions = Decisions::default();
+        decisions.0.lock().unwrap().insert(committed.id, 1);
+        let epoch_supplier = Arc::new(EpochSupplier::new());
+        epoch_supplier.set_epoch(1).await;
+        let successor = Arc::new(RM {
+            range_id: rm.range_id,
+            config: rm.config.clone(),
+            storage: rm.storage.clone(),
             wal: Arc::new(InMemoryWal::new()),
             epoch_supplier: epoch_supplier.clone(),
             state: Arc::new(RwLock::new(State::NotLoaded)),
-            prefetching_buffer,
+            prefetching_buffer: Arc::new(PrefetchingBuffer::new()),
             bg_runtime: tokio::runtime::Handle::current().clone(),
+            storage_health: Arc::new(StorageHealth::new(rm.range_id, mpsc::unbounded_channel().0)),
+            clock: Arc::new(SystemClock),
+            lease_latch: Arc::new(Mutex::new(())),
+            decision_log: None,
+            reads: Default::default(),
+            prepare_resolver: Arc::new(decisions),
         });
-        let rm_copy = rm.clone();
-        let init_handle = tokio::spawn(async move { rm_copy.load().await.unwrap() });
-        // Give some delay so the RM can see the epoch advancing.
+        let successor_copy = successor.clone();
+        let load_handle = tokio::spawn(async move { successor_copy.load().await.unwrap() });
         tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-        epoch_supplier.set_epoch(1).await;
-        init_handle.await.unwrap();
-        TestContext {
-            rm,
-            storage_context,
-        }
+        epoch_supplier.set_epoch(2).await;
+        load_handle.await.unwrap();
+
+        assert!(successor
+            .list_in_flight_transactions()
+            .await
+            .unwrap()
+            .is_empty());
+        let read = successor
+            .get(start_transaction(), key.clone(), ReadMode::Locking)
+            .await
+            .unwrap();
+        assert_eq!(read.val, Some(val));
+        assert!(successor
+            .storage
+            .load_prepares(successor.range_id)
+            .await
+            .unwrap()
+            .is_empty());
     }
 
-    fn start_transaction() -> Arc<TransactionInfo> {
-        Arc::new(TransactionInfo {
-            id: Uuid::new_v4(),
-            started: chrono::Utc::now(),
-            overall_timeout: time::Duration::from_secs(10),
-        })
+    #[tokio::test]
+    async fn validate_does_not_check_snapshot_reads() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx = start_snapshot_transaction();
+        rm.get(tx.clone(), key, ReadMode::AtEpoch(0)).await.unwrap();
+        rm.validate(tx.clone(), true).await.unwrap();
     }
 
     #[tokio::test]
-    async fn basic_get_put() {
+    async fn snapshot_transactions_without_an_epoch_are_rejected() {
         let context = init().await;
         let rm = context.rm.clone();
         let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let mut tx = (*start_snapshot_transaction()).clone();
+        tx.snapshot_epoch = None;
+        let tx = Arc::new(tx);
+        assert!(matches!(
+            rm.get(tx.clone(), key.clone(), ReadMode::Locking).await,
+            Err(Error::InvalidRequestFormat)
+        ));
+        assert!(matches!(
+            rm.prepare_transaction(
+                tx.clone(),
+                Vec::from([(key, Bytes::from_static(b"value"))]),
+                Vec::new(),
+                false,
+            )
+            .await,
+            Err(Error::InvalidRequestFormat)
+        ));
+    }
+
+    #[tokio::test]
+    async fn write_skew_is_only_prevented_in_serializable_mode() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        // Two withdrawals each check that the two balances of a customer
+        // cover them, and each take from a different balance.
+        let checking = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let savings = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        for isolation in [IsolationLevel::Serializable, IsolationLevel::Snapshot] {
+            let (mode, start): (_, fn() -> Arc<TransactionInfo>) = match isolation {
+                IsolationLevel::Serializable => (ReadMode::Optimistic, start_transaction),
+                IsolationLevel::Snapshot => (ReadMode::AtEpoch(0), start_snapshot_transaction),
+            };
+            let (tx1, tx2) = (start(), start());
+            for tx in [&tx1, &tx2] {
+                for key in [&checking, &savings] {
+                    rm.get(tx.clone(), key.clone(), mode).await.unwrap();
+                }
+            }
+            rm.prepare_transaction(
+                tx1.clone(),
+                Vec::from([(checking.clone(), Bytes::from_static(b"-40"))]),
+                Vec::new(),
+                true,
+            )
+            .await
+            .unwrap();
+            rm.commit_transaction(tx1.clone()).await.unwrap();
+            let second = rm
+                .prepare_transaction(
+                    tx2.clone(),
+                    Vec::from([(savings.clone(), Bytes::from_static(b"-40"))]),
+                    Vec::new(),
+                    true,
+                )
+                .await;
+            match isolation {
+                IsolationLevel::Serializable => assert!(matches!(
+                    second,
+                    Err(Error::TransactionAborted(
+                        TransactionAbortReason::ReadConflict
+                    ))
+                )),
+                // Documented on `IsolationLevel::Snapshot`.
+                IsolationLevel::Snapshot => {
+                    second.unwrap();
+                    rm.commit_transaction(tx2.clone()).await.unwrap();
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn optimistic_reads_only_conflict_with_writes_to_what_they_read() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let read = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let written = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+
         let tx1 = start_transaction();
+        rm.get(tx1.clone(), read.clone(), ReadMode::Optimistic)
+            .await
+            .unwrap();
+        let tx2 = start_transaction();
+        rm.prepare_transaction(
+            tx2.clone(),
+            Vec::from([(written.clone(), Bytes::from_static(b"value"))]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx2.clone()).await.unwrap();
+        rm.validate(tx1.clone(), true).await.unwrap();
+        rm.prepare_transaction(
+            tx1.clone(),
+            Vec::from([(written.clone(), Bytes::from_static(b"other value"))]),
+            Vec::new(),
+            true,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx1.clone()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn conflict_stats_attribute_prepare_conflicts_to_written_keys() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+
+        let mut tx1 = (*start_transaction()).clone();
+        tx1.labels.insert("service".into(), "cart".into());
+        let tx1 = Arc::new(tx1);
+        rm.get(tx1.clone(), key.clone(), ReadMode::Optimistic)
+            .await
+            .unwrap();
+        let tx2 = start_transaction();
+        rm.prepare_transaction(
+            tx2.clone(),
+            Vec::from([(key.clone(), Bytes::from_static(b"first"))]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx2.clone()).await.unwrap();
         assert!(rm
-            .get(tx1.clone(), key.clone())
+            .prepare_transaction(
+                tx1.clone(),
+                Vec::from([(key.clone(), Bytes::from_static(b"second"))]),
+                Vec::new(),
+                true,
+            )
+            .await
+            .is_err());
+
+        let stats = rm.conflict_stats().await.unwrap();
+        let prefix = key.slice(..ConflictStatsConfig::default().prefix_len);
+        let (_, counts) = stats.prefixes.iter().find(|(p, _)| *p == prefix).unwrap();
+        assert_eq!(counts.read_conflicts, 1);
+        // And to the labels of the transaction that ran into them.
+        assert_eq!(stats.labels.len(), 1);
+        assert_eq!(stats.labels[0].0, ("service".into(), "cart".into()));
+        assert_eq!(stats.labels[0].1.read_conflicts, 1);
+    }
+
+    #[tokio::test]
+    async fn read_stats_count_prefetch_buffer_hits() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let (prefetched, other) = (
+            Bytes::copy_from_slice(Uuid::new_v4().as_bytes()),
+            Bytes::copy_from_slice(Uuid::new_v4().as_bytes()),
+        );
+        let tx = start_transaction();
+        rm.prefetch(tx.id, prefetched.clone()).await.unwrap();
+        rm.get(tx.clone(), prefetched, ReadMode::Optimistic)
+            .await
+            .unwrap();
+        rm.get(tx.clone(), other, ReadMode::Optimistic)
+            .await
+            .unwrap();
+
+        let stats = rm.read_stats().await.unwrap();
+        assert_eq!(stats.gets, 2);
+        assert_eq!(stats.cache_hits, 1);
+        // The prefetch and the get that missed the buffer.
+        assert_eq!(stats.storage_reads, 2);
+        assert_eq!(stats.prefetch_storage_reads, 1);
+    }
+
+    #[tokio::test]
+    async fn prepare_checks_conditions() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let first = Bytes::from_static(b"first");
+        let second = Bytes::from_static(b"second");
+
+        let tx1 = start_transaction();
+        rm.prepare_conditional_transaction(
+            tx1.clone(),
+            Vec::from([(key.clone(), first.clone())]),
+            Vec::new(),
+            Vec::from([(key.clone(), None)]),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx1).await.unwrap();
+
+        // The key is no longer absent.
+        let tx2 = start_transaction();
+        assert!(matches!(
+            rm.prepare_conditional_transaction(
+                tx2.clone(),
+                Vec::from([(key.clone(), second.clone())]),
+                Vec::new(),
+                Vec::from([(key.clone(), None)]),
+                false,
+            )
+            .await,
+            Err(Error::ConditionFailed)
+        ));
+        rm.abort_transaction(tx2).await;
+
+        let tx3 = start_transaction();
+        rm.prepare_conditional_transaction(
+            tx3.clone(),
+            Vec::from([(key.clone(), second.clone())]),
+            Vec::new(),
+            Vec::from([(key.clone(), Some(first))]),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx3).await.unwrap();
+        let tx4 = start_transaction();
+        let val = rm
+            .get(tx4, key, ReadMode::Locking)
             .await
             .unwrap()
             .val
-            .is_none());
-        rm.abort_transaction(tx1.clone()).await;
+            .unwrap();
+        assert_eq!(val, second);
+    }
+
+    #[tokio::test]
+    async fn prepare_applies_increments() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let counter = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let other = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+
+        // An absent counter starts at 0, and increments of the same counter
+        // add up.
+        let tx1 = start_transaction();
+        rm.prepare_transaction_with_increments(
+            tx1.clone(),
+            Vec::from([(other.clone(), Bytes::from_static(b"not a counter"))]),
+            Vec::new(),
+            Vec::new(),
+            Vec::from([(counter.clone(), 5), (counter.clone(), -2)]),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx1).await.unwrap();
+
         let tx2 = start_transaction();
-        let val = Bytes::from_static(b"I have a value!");
-        rm.prepare_transaction(
+        rm.prepare_transaction_with_increments(
             tx2.clone(),
-            Vec::from([(key.clone(), val.clone())]),
             Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::from([(counter.clone(), 10)]),
             false,
         )
         .await
         .unwrap();
-        rm.commit_transaction(tx2.clone()).await.unwrap();
+        rm.commit_transaction(tx2).await.unwrap();
+
         let tx3 = start_transaction();
-        let val_after_commit = rm.get(tx3.clone(), key.clone()).await.unwrap().val.unwrap();
-        assert!(val_after_commit == val);
+        assert!(matches!(
+            rm.prepare_transaction_with_increments(
+                tx3.clone(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::from([(other.clone(), 1)]),
+                false,
+            )
+            .await,
+            Err(Error::InvalidIncrement)
+        ));
+        rm.abort_transaction(tx3).await;
+
+        let tx4 = start_transaction();
+        let val = rm
+            .get(tx4, counter, ReadMode::Locking)
+            .await
+            .unwrap()
+            .val
+            .unwrap();
+        assert_eq!(val, Bytes::copy_from_slice(&13i64.to_be_bytes()));
+    }
+
+    #[tokio::test]
+    async fn verify_checksums_reports_stale_prefetched_values() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx = start_transaction();
+        rm.prepare_transaction(
+            tx.clone(),
+            Vec::from([(key.clone(), Bytes::from_static(b"fresh"))]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        rm.commit_transaction(tx).await.unwrap();
+
+        let reader = start_transaction();
+        rm.prefetch(reader.id, key.clone()).await.unwrap();
+        let report = rm.verify_checksums(10).await.unwrap();
+        assert_eq!(report.cached_keys, 1);
+        assert_eq!(report.divergent_keys, 0);
+
+        // Only the buffer changes, as if an update never reached it.
+        rm.prefetching_buffer
+            .upsert(key.clone(), Bytes::from_static(b"stale"), None)
+            .await;
+        let stale = rm.verify_checksums(10).await.unwrap();
+        assert_eq!(stale.storage_checksum, report.storage_checksum);
+        assert_eq!(stale.divergent_keys, 1);
+        assert_eq!(stale.divergent_key_samples, vec![key]);
+
+        let stats = rm.checksum_stats().await.unwrap();
+        assert_eq!(stats.verifications, 2);
+        assert_eq!(stats.divergences, 1);
+        assert_eq!(stats.last, Some(stale));
+    }
+
+    #[tokio::test]
+    async fn extend_epoch_lease_is_bounded() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx = start_transaction();
+        assert!(matches!(
+            rm.extend_epoch_lease(tx.id, u64::MAX).await,
+            Err(Error::UnknownTransaction)
+        ));
+        rm.prepare_transaction(
+            tx.clone(),
+            Vec::from([(key.clone(), Bytes::from_static(b"value"))]),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+        let lease = match rm.state.read().await.deref() {
+            State::Loaded(state) => state.range_info.epoch_lease,
+            _ => panic!("Range is not loaded"),
+        };
+
+        let wanted = lease.1 + 1;
+        let extended = rm.extend_epoch_lease(tx.id, wanted).await.unwrap();
+        assert!(extended.epoch_lease.1 >= wanted);
+        assert_eq!(extended.epoch_lease.0, lease.0);
+
+        // Never extended further than the config allows, however far ahead
+        // the coordinator asks for.
+        let epoch = rm.epoch_supplier.read_epoch().await.unwrap();
+        let capped = rm.extend_epoch_lease(tx.id, u64::MAX).await.unwrap();
+        assert!(
+            capped.epoch_lease.1
+                <= std::cmp::max(
+                    extended.epoch_lease.1,
+                    epoch + 2 * rm.num_epochs_per_lease()
+                )
+        );
+        rm.commit_transaction(tx.clone()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn in_flight_transactions_report_when_prepared() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx = start_transaction();
+        let write = Vec::from([(key.clone(), Bytes::from_static(b"value"))]);
+        rm.prepare_transaction(tx.clone(), write.clone(), Vec::new(), false)
+            .await
+            .unwrap();
+        let prepared_at = rm.list_in_flight_transactions().await.unwrap()[0]
+            .prepared_at
+            .unwrap();
+        // A retried prepare keeps the time of the first one.
+        rm.prepare_transaction(tx.clone(), write, Vec::new(), false)
+            .await
+            .unwrap();
+        let in_flight = rm.list_in_flight_transactions().await.unwrap();
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].id, tx.id);
+        assert_eq!(in_flight[0].prepared_at, Some(prepared_at));
+        rm.commit_transaction(tx.clone()).await.unwrap();
+        assert!(rm.list_in_flight_transactions().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn frozen_range_only_lets_prepared_transactions_finish() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let val = Bytes::from_static(b"prepared before the freeze");
+        let tx = start_transaction();
+        let write = Vec::from([(key.clone(), val.clone())]);
+        rm.prepare_transaction(tx.clone(), write.clone(), Vec::new(), false)
+            .await
+            .unwrap();
+        assert!(!rm.set_frozen(true).await.unwrap());
+
+        let new_tx = start_transaction();
+        assert!(matches!(
+            rm.get(new_tx.clone(), key.clone(), ReadMode::Optimistic)
+                .await,
+            Err(Error::RangeFrozen)
+        ));
+        assert!(matches!(
+            rm.prepare_transaction(new_tx.clone(), write.clone(), Vec::new(), false)
+                .await,
+            Err(Error::RangeFrozen)
+        ));
+        // The prepared transaction can still be retried and committed.
+        rm.prepare_transaction(tx.clone(), write, Vec::new(), false)
+            .await
+            .unwrap();
+        rm.commit_transaction(tx).await.unwrap();
+
+        assert!(rm.set_frozen(false).await.unwrap());
+        let read = rm.get(new_tx, key, ReadMode::Locking).await.unwrap();
+        assert_eq!(read.val, Some(val));
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_prepared_transactions() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx = start_transaction();
+        let write = Vec::from([(key.clone(), Bytes::from_static(b"prepared"))]);
+        rm.prepare_transaction(tx.clone(), write, Vec::new(), false)
+            .await
+            .unwrap();
+
+        // Running out of time reports what is left.
+        let outcome = rm.drain(Duration::from_millis(50)).await.unwrap();
+        assert_eq!(
+            outcome,
+            DrainOutcome {
+                drained: false,
+                prepared: 1,
+                unprepared: 0
+            }
+        );
+        let new_tx = start_transaction();
+        assert!(matches!(
+            rm.get(new_tx, key.clone(), ReadMode::Locking).await,
+            Err(Error::RangeDraining)
+        ));
+
+        let drain = {
+            let rm = rm.clone();
+            tokio::spawn(async move { rm.drain(Duration::from_secs(5)).await })
+        };
+        rm.commit_transaction(tx).await.unwrap();
+        let outcome = drain.await.unwrap().unwrap();
+        assert!(outcome.drained);
+    }
+
+    #[tokio::test]
+    async fn drains_that_time_out_let_lock_waiters_go() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        // Older than the holder, so that it waits for the lock.
+        let waiter = start_transaction();
+        let holder = start_transaction();
+        rm.get(holder, key.clone(), ReadMode::Locking)
+            .await
+            .unwrap();
+        let wait = {
+            let rm = rm.clone();
+            let key = key.clone();
+            tokio::spawn(async move { rm.get(waiter, key, ReadMode::Locking).await })
+        };
+        while rm.lock_table_occupancy().await.unwrap().waiters == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let outcome = rm.drain(Duration::from_millis(50)).await.unwrap();
+        assert_eq!(
+            outcome,
+            DrainOutcome {
+                drained: false,
+                prepared: 0,
+                unprepared: 2
+            }
+        );
+        assert!(matches!(
+            wait.await.unwrap(),
+            Err(Error::TransactionAborted(
+                TransactionAbortReason::TransactionLockLost
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelled_drains_admit_new_transactions() {
+        let context = init().await;
+        let rm = context.rm.clone();
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let tx = start_transaction();
+        let write = Vec::from([(key.clone(), Bytes::from_static(b"prepared"))]);
+        rm.prepare_transaction(tx, write, Vec::new(), false)
+            .await
+            .unwrap();
+
+        let drain = {
+            let rm = rm.clone();
+            tokio::spawn(async move { rm.drain(Duration::from_secs(60)).await })
+        };
+        // Transactions that read the range before it drained are let
+        // through, so each try is a new one.
+        while !matches!(
+            rm.get(start_transaction(), key.clone(), ReadMode::Optimistic)
+                .await,
+            Err(Error::RangeDraining)
+        ) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        rm.cancel_drain().await.unwrap();
+        let outcome = drain.await.unwrap().unwrap();
+        assert!(!outcome.drained);
+        assert_eq!(outcome.prepared, 1);
+        rm.get(start_transaction(), key, ReadMode::Optimistic)
+            .await
+            .unwrap();
     }
 
     #[tokio::test]
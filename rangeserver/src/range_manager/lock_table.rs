@@ -1,13 +1,27 @@
-use crate::{error::Error, transaction_abort_reason::TransactionAbortReason};
+use crate::{
+    error::Error, range_manager::LockTableOccupancy,
+    transaction_abort_reason::TransactionAbortReason,
+};
 use chrono::DateTime;
+use common::clock::Clock;
+use common::config::{LockTableConfig, LockTableOverflowPolicy};
 use common::transaction_info::TransactionInfo;
-use uuid::Uuid;
 use std::collections::VecDeque;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 use tokio::sync::oneshot;
 use tokio::sync::RwLock;
+use uuid::Uuid;
 
 type UtcDateTime = DateTime<chrono::Utc>;
+
+// Orders transactions for deadlock prevention, older ones first. The start
+// timestamp comes from the coordinator, so all range servers agree on it,
+// and the id breaks ties.
+fn age(tx: &TransactionInfo) -> (UtcDateTime, Uuid) {
+    (tx.first_started, tx.id)
+}
+
 pub struct CurrentLockHolder {
     transaction: Arc<TransactionInfo>,
     when_acquired: UtcDateTime,
@@ -20,52 +34,198 @@ pub struct LockRequest {
     when_requested: UtcDateTime,
 }
 
+// A LockRequest that did not fit in the lock table. It only holds a weak
+// reference to the transaction, so a transaction abandoned by its coordinator
+// does not stay in memory because it is queued here. If the transaction is
+// gone by the time the request is granted, the request is dropped and the
+// waiter sees its sender closed.
+struct SpilledLockRequest {
+    transaction_age: (UtcDateTime, Uuid),
+    transaction: Weak<TransactionInfo>,
+    sender: oneshot::Sender<()>,
+    when_requested_us: i64,
+}
+
+impl SpilledLockRequest {
+    fn new(req: LockRequest) -> SpilledLockRequest {
+        SpilledLockRequest {
+            transaction_age: age(&req.transaction),
+            transaction: Arc::downgrade(&req.transaction),
+            sender: req.sender,
+            when_requested_us: req.when_requested.timestamp_micros(),
+        }
+    }
+
+    fn restore(self) -> Option<LockRequest> {
+        Some(LockRequest {
+            transaction: self.transaction.upgrade()?,
+            sender: self.sender,
+            when_requested: DateTime::from_timestamp_micros(self.when_requested_us)?,
+        })
+    }
+}
+
 struct State {
     current_holder: Option<CurrentLockHolder>,
     waiting_for_release: VecDeque<LockRequest>,
     waiting_to_acquire: VecDeque<LockRequest>,
+    // Readers waiting for a release only need to be woken up.
+    spilled_waiting_for_release: Vec<oneshot::Sender<()>>,
+    // Queued behind waiting_to_acquire, in order.
+    spilled_waiting_to_acquire: VecDeque<SpilledLockRequest>,
+}
+
+impl State {
+    fn num_entries(&self) -> usize {
+        self.current_holder.iter().len()
+            + self.waiting_for_release.len()
+            + self.waiting_to_acquire.len()
+    }
+
+    fn last_waiter_age(&self) -> Option<(UtcDateTime, Uuid)> {
+        self.spilled_waiting_to_acquire
+            .back()
+            .map(|r| r.transaction_age)
+            .or_else(|| self.waiting_to_acquire.back().map(|r| age(&r.transaction)))
+    }
+
+    // Skips waiters that stopped waiting without taking themselves out of
+    // the queue.
+    fn pop_next_waiter(&mut self) -> Option<LockRequest> {
+        while let Some(req) = self.waiting_to_acquire.pop_front() {
+            if !req.sender.is_closed() {
+                return Some(req);
+            }
+        }
+        while let Some(spilled) = self.spilled_waiting_to_acquire.pop_front() {
+            if let Some(req) = spilled.restore() {
+                if !req.sender.is_closed() {
+                    return Some(req);
+                }
+            }
+        }
+        None
+    }
+
+    // Takes the transaction out of the queue to acquire the lock, keeping
+    // the others in order. Returns whether it was queued.
+    fn remove_waiter(&mut self, tx_id: Uuid) -> bool {
+        if let Some(i) = self
+            .waiting_to_acquire
+            .iter()
+            .position(|r| r.transaction.id == tx_id)
+        {
+            self.waiting_to_acquire.remove(i);
+            return true;
+        }
+        match self
+            .spilled_waiting_to_acquire
+            .iter()
+            .position(|r| r.transaction_age.1 == tx_id)
+        {
+            Some(i) => {
+                self.spilled_waiting_to_acquire.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Moves spilled waiters back into the table as room frees up.
+    fn unspill(&mut self, limits: &LockTableConfig) {
+        while self.waiting_to_acquire.len() < limits.max_waiters
+            && self.num_entries() < limits.max_entries
+        {
+            match self.spilled_waiting_to_acquire.pop_front() {
+                None => break,
+                Some(spilled) => {
+                    if let Some(req) = spilled.restore() {
+                        self.waiting_to_acquire.push_back(req);
+                    }
+                }
+            }
+        }
+    }
 }
 
 // Implements transaction lock table for the range.
 // Currently there is just a single lock for the entire range despite having
 // "Table" in the name, but we might partition the lock to allow for more
 // concurrency down the line.
+//
+// The number of requests it tracks is bounded by `LockTableConfig`, so that
+// abandoned transactions piling up behind a stuck holder can't grow it
+// without bounds.
+//
+// Waiters are granted the lock first come, first served. One that waits for
+// longer than `LockTableConfig::wait_timeout` leaves the queue, and those
+// behind it keep their order.
 pub struct LockTable {
     state: RwLock<State>,
+    clock: Arc<dyn Clock>,
+    limits: LockTableConfig,
+    num_rejected: AtomicU64,
 }
 
 impl LockTable {
-    pub fn new() -> LockTable {
+    pub fn new(clock: Arc<dyn Clock>, limits: LockTableConfig) -> LockTable {
         LockTable {
             state: RwLock::new(State {
                 current_holder: None,
                 waiting_for_release: VecDeque::new(),
                 waiting_to_acquire: VecDeque::new(),
+                spilled_waiting_for_release: Vec::new(),
+                spilled_waiting_to_acquire: VecDeque::new(),
             }),
+            clock,
+            limits,
+            num_rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds rejections counted by a previous lock table of the range.
+    pub fn restore_rejected(&self, rejected: u64) {
+        self.num_rejected.fetch_add(rejected, Ordering::Relaxed);
+    }
+
+    // Called when a request does not fit in the table. Returns whether it
+    // should be spilled rather than rejected.
+    fn on_overflow(&self) -> Result<(), Error> {
+        match self.limits.overflow_policy {
+            LockTableOverflowPolicy::Spill => Ok(()),
+            LockTableOverflowPolicy::Reject => {
+                self.num_rejected.fetch_add(1, Ordering::Relaxed);
+                Err(Error::Overloaded)
+            }
         }
     }
     pub async fn maybe_wait_for_current_holder(
         &self,
         tx: Arc<TransactionInfo>,
-    ) -> oneshot::Receiver<()> {
+    ) -> Result<oneshot::Receiver<()>, Error> {
         let (s, r) = oneshot::channel();
         let mut state = self.state.write().await;
         match &state.current_holder {
             None => s.send(()).unwrap(),
             Some(_) => {
-                let req = LockRequest {
-                    transaction: tx.clone(),
-                    sender: s,
-                    when_requested: chrono::Utc::now(),
-                };
-                state.waiting_for_release.push_back(req);
+                if state.num_entries() >= self.limits.max_entries {
+                    self.on_overflow()?;
+                    state.spilled_waiting_for_release.push(s);
+                } else {
+                    let req = LockRequest {
+                        transaction: tx.clone(),
+                        sender: s,
+                        when_requested: self.clock.now(),
+                    };
+                    state.waiting_for_release.push_back(req);
+                }
             }
         };
-        r
+        Ok(r)
     }
 
     pub async fn acquire(&self, tx: Arc<TransactionInfo>) -> Result<oneshot::Receiver<()>, Error> {
-        let when_requested = chrono::Utc::now();
+        let when_requested = self.clock.now();
         let (s, r) = oneshot::channel();
         let mut state = self.state.write().await;
         match &state.current_holder {
@@ -84,20 +244,33 @@ impl LockTable {
                     s.send(()).unwrap();
                     Ok(r)
                 } else {
-                    let highest_waiter = state
-                        .waiting_to_acquire
-                        .back()
-                        .map_or(current_holder.transaction.id, |r| r.transaction.id);
-                    if highest_waiter > tx.id {
+                    // Wait-die: only transactions older than all those ahead
+                    // of them wait, younger ones are aborted right away.
+                    let youngest_ahead = state
+                        .last_waiter_age()
+                        .unwrap_or(age(&current_holder.transaction));
+                    if age(&tx) > youngest_ahead {
                         // TODO: allow for skipping these checks if locks are ordered!
-                        Err(Error::TransactionAborted(TransactionAbortReason::WaitDie))
+                        Err(Error::TransactionAborted(TransactionAbortReason::Deadlock))
                     } else {
                         let req = LockRequest {
                             transaction: tx.clone(),
                             sender: s,
-                            when_requested: chrono::Utc::now(),
+                            when_requested: self.clock.now(),
                         };
-                        state.waiting_to_acquire.push_back(req);
+                        // Once anything is spilled, later waiters must be
+                        // spilled too to keep the queue in order.
+                        if !state.spilled_waiting_to_acquire.is_empty()
+                            || state.waiting_to_acquire.len() >= self.limits.max_waiters
+                            || state.num_entries() >= self.limits.max_entries
+                        {
+                            self.on_overflow()?;
+                            state
+                                .spilled_waiting_to_acquire
+                                .push_back(SpilledLockRequest::new(req));
+                        } else {
+                            state.waiting_to_acquire.push_back(req);
+                        }
                         Ok(r)
                     }
                 }
@@ -105,6 +278,35 @@ impl LockTable {
         }
     }
 
+    /// Waits for the lock requested with `acquire` to be granted, for up to
+    /// the configured wait timeout.
+    pub async fn wait(
+        &self,
+        tx_id: Uuid,
+        mut receiver: oneshot::Receiver<()>,
+    ) -> Result<(), Error> {
+        // The request is only dropped without being granted if it was
+        // spilled and the transaction went away in the meantime.
+        let lost = |_| Error::TransactionAborted(TransactionAbortReason::TransactionLockLost);
+        let timeout = match self.limits.wait_timeout {
+            None => return receiver.await.map_err(lost),
+            Some(timeout) => timeout,
+        };
+        tokio::select! {
+            granted = &mut receiver => granted.map_err(lost),
+            () = self.clock.sleep(timeout) => {
+                let mut state = self.state.write().await;
+                if state.remove_waiter(tx_id) {
+                    state.unspill(&self.limits);
+                    return Err(Error::LockWaitTimeout);
+                }
+                // Granted or dropped in the meantime.
+                drop(state);
+                receiver.await.map_err(lost)
+            }
+        }
+    }
+
     pub async fn release(&self) {
         let mut state = self.state.write().await;
         state.current_holder = None;
@@ -112,10 +314,15 @@ impl LockTable {
             let req = state.waiting_for_release.pop_front().unwrap();
             req.sender.send(()).unwrap();
         }
-        match state.waiting_to_acquire.pop_front() {
+        for sender in state.spilled_waiting_for_release.drain(..) {
+            let _ = sender.send(());
+        }
+        let next = state.pop_next_waiter();
+        state.unspill(&self.limits);
+        match next {
             None => (),
             Some(req) => {
-                let when_acquired = chrono::Utc::now();
+                let when_acquired = self.clock.now();
                 let new_holder = CurrentLockHolder {
                     transaction: req.transaction.clone(),
                     when_requested: req.when_requested,
@@ -127,11 +334,201 @@ impl LockTable {
         }
     }
 
-    pub async fn is_currently_holding(&self, tx_id : Uuid) -> bool {
+    /// Takes every transaction waiting to acquire the lock out of the queue,
+    /// and returns how many there were. Their waits fail with
+    /// `TransactionLockLost`, which their coordinators retry. The lock itself
+    /// stays with its holder.
+    pub async fn drop_waiters(&self) -> usize {
+        let mut state = self.state.write().await;
+        let dropped = state.waiting_to_acquire.len() + state.spilled_waiting_to_acquire.len();
+        state.waiting_to_acquire.clear();
+        state.spilled_waiting_to_acquire.clear();
+        dropped
+    }
+
+    /// Returns the transaction currently holding the lock, along with when it
+    /// acquired it.
+    pub async fn current_holder(&self) -> Option<(Arc<TransactionInfo>, UtcDateTime)> {
+        let state = self.state.read().await;
+        state
+            .current_holder
+            .as_ref()
+            .map(|h| (h.transaction.clone(), h.when_acquired))
+    }
+
+    pub async fn is_currently_holding(&self, tx_id: Uuid) -> bool {
         let state = self.state.read().await;
         match &state.current_holder {
             None => false,
             Some(current) => current.transaction.id == tx_id,
         }
     }
+
+    pub async fn occupancy(&self) -> LockTableOccupancy {
+        let state = self.state.read().await;
+        let now = self.clock.now();
+        LockTableOccupancy {
+            holder_age: state
+                .current_holder
+                .as_ref()
+                .and_then(|h| (now - h.when_acquired).to_std().ok()),
+            waiters: state.waiting_to_acquire.len(),
+            waiting_for_release: state.waiting_for_release.len(),
+            spilled_waiters: state.spilled_waiting_to_acquire.len()
+                + state.spilled_waiting_for_release.len(),
+            rejected: self.num_rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::clock::SystemClock;
+    use std::collections::BTreeMap;
+
+    fn lock_table(
+        max_waiters: usize,
+        overflow_policy: LockTableOverflowPolicy,
+        wait_timeout: Option<std::time::Duration>,
+    ) -> LockTable {
+        LockTable::new(
+            Arc::new(SystemClock),
+            LockTableConfig {
+                max_waiters,
+                max_entries: 16,
+                overflow_policy,
+                wait_timeout,
+            },
+        )
+    }
+
+    // Each transaction is older than the previous one so that wait-die lets
+    // every transaction queue up.
+    fn transactions(n: u128) -> Vec<Arc<TransactionInfo>> {
+        let now = chrono::Utc::now();
+        (1..=n)
+            .map(|i| {
+                let started = now - chrono::Duration::seconds(i as i64);
+                Arc::new(TransactionInfo {
+                    id: Uuid::from_u128(i),
+                    started,
+                    first_started: started,
+                    overall_timeout: std::time::Duration::from_secs(10),
+                    labels: BTreeMap::new(),
+                    isolation: Default::default(),
+                    snapshot_epoch: None,
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn rejects_waiters_past_the_bound() {
+        let lock_table = lock_table(1, LockTableOverflowPolicy::Reject, None);
+        let txs = transactions(3);
+        lock_table.acquire(txs[0].clone()).await.unwrap();
+        lock_table.acquire(txs[1].clone()).await.unwrap();
+        assert!(matches!(
+            lock_table.acquire(txs[2].clone()).await,
+            Err(Error::Overloaded)
+        ));
+        let occupancy = lock_table.occupancy().await;
+        assert_eq!(occupancy.waiters, 1);
+        assert_eq!(occupancy.spilled_waiters, 0);
+        assert_eq!(occupancy.rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn spilled_waiters_are_granted_in_order() {
+        let lock_table = lock_table(1, LockTableOverflowPolicy::Spill, None);
+        let txs = transactions(3);
+        lock_table.acquire(txs[0].clone()).await.unwrap();
+        let mut second = lock_table.acquire(txs[1].clone()).await.unwrap();
+        let mut third = lock_table.acquire(txs[2].clone()).await.unwrap();
+        let occupancy = lock_table.occupancy().await;
+        assert_eq!(occupancy.waiters, 1);
+        assert_eq!(occupancy.spilled_waiters, 1);
+
+        lock_table.release().await;
+        second.try_recv().unwrap();
+        assert!(third.try_recv().is_err());
+        assert_eq!(lock_table.occupancy().await.spilled_waiters, 0);
+        lock_table.release().await;
+        third.try_recv().unwrap();
+        assert!(lock_table.is_currently_holding(txs[2].id).await);
+    }
+
+    #[tokio::test]
+    async fn younger_transactions_die_rather_than_wait() {
+        let lock_table = lock_table(4, LockTableOverflowPolicy::Reject, None);
+        let txs = transactions(3);
+        lock_table.acquire(txs[1].clone()).await.unwrap();
+        // Started after the holder, whatever its id.
+        assert!(matches!(
+            lock_table.acquire(txs[0].clone()).await,
+            Err(Error::TransactionAborted(TransactionAbortReason::Deadlock))
+        ));
+        let mut older = lock_table.acquire(txs[2].clone()).await.unwrap();
+        assert_eq!(lock_table.occupancy().await.waiters, 1);
+        lock_table.release().await;
+        older.try_recv().unwrap();
+    }
+
+    #[tokio::test]
+    async fn spilled_waiters_of_finished_transactions_are_dropped() {
+        let lock_table = lock_table(0, LockTableOverflowPolicy::Spill, None);
+        let mut txs = transactions(2);
+        lock_table.acquire(txs[0].clone()).await.unwrap();
+        let mut abandoned = lock_table.acquire(txs.pop().unwrap()).await.unwrap();
+        lock_table.release().await;
+        assert!(matches!(
+            abandoned.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        ));
+        assert!(lock_table.current_holder().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn timed_out_waiters_leave_the_queue_in_order() {
+        let lock_table = lock_table(
+            1,
+            LockTableOverflowPolicy::Spill,
+            Some(std::time::Duration::from_millis(10)),
+        );
+        let txs = transactions(4);
+        lock_table.acquire(txs[0].clone()).await.unwrap();
+        let second = lock_table.acquire(txs[1].clone()).await.unwrap();
+        let mut third = lock_table.acquire(txs[2].clone()).await.unwrap();
+        let mut fourth = lock_table.acquire(txs[3].clone()).await.unwrap();
+        assert!(matches!(
+            lock_table.wait(txs[1].id, second).await,
+            Err(Error::LockWaitTimeout)
+        ));
+        let occupancy = lock_table.occupancy().await;
+        assert_eq!(occupancy.waiters, 1);
+        assert_eq!(occupancy.spilled_waiters, 1);
+
+        lock_table.release().await;
+        third.try_recv().unwrap();
+        assert!(fourth.try_recv().is_err());
+        lock_table.release().await;
+        fourth.try_recv().unwrap();
+        assert!(lock_table.is_currently_holding(txs[3].id).await);
+    }
+
+    #[tokio::test]
+    async fn waiters_granted_before_timing_out_keep_the_lock() {
+        let lock_table = lock_table(
+            4,
+            LockTableOverflowPolicy::Reject,
+            Some(std::time::Duration::from_secs(10)),
+        );
+        let txs = transactions(2);
+        lock_table.acquire(txs[0].clone()).await.unwrap();
+        let second = lock_table.acquire(txs[1].clone()).await.unwrap();
+        lock_table.release().await;
+        lock_table.wait(txs[1].id, second).await.unwrap();
+        assert!(lock_table.is_currently_holding(txs[1].id).await);
+    }
 }
@@ -0,0 +1,218 @@
+use bytes::Bytes;
+use common::key_range::KeyRange;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// How many commits' written keys are kept for the transactions reading the
+// range optimistically. A reader whose reads go further back than that is
+// assumed to have read something stale.
+const MAX_TRACKED_COMMITS: usize = 1024;
+
+#[derive(Default)]
+struct Reads {
+    // The commit count of the range when each key was first read, and when
+    // each key range was first scanned.
+    keys: HashMap<Bytes, u64>,
+    ranges: Vec<(KeyRange, u64)>,
+}
+
+impl Reads {
+    fn oldest(&self) -> Option<u64> {
+        self.keys
+            .values()
+            .chain(self.ranges.iter().map(|(_, version)| version))
+            .min()
+            .copied()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // Bumped once the writes of a committed transaction are applied.
+    commit_count: u64,
+    readers: HashMap<Uuid, Reads>,
+    // The keys written by recent commits, oldest first, along with the commit
+    // count each of them brought the range to. Only kept while some reader
+    // read before them.
+    writes: VecDeque<(u64, Vec<Bytes>)>,
+    // The commits up to this count are no longer in `writes`.
+    forgotten_through: u64,
+}
+
+/// Tracks which version of each key the transactions reading a range
+/// optimistically read, and which keys the commits since wrote, to tell at
+/// prepare whether any of their reads went stale. Checking keys rather than
+/// whether anything at all committed only aborts the readers that actually
+/// conflict, while still catching write skew: two transactions reading the
+/// same keys and each writing some of them can't both commit, since the one
+/// preparing second read a key the first one wrote.
+#[derive(Default)]
+pub struct ReadVersions {
+    state: Mutex<State>,
+}
+
+impl ReadVersions {
+    /// Records that the transaction reads `key`. Must be called before reading
+    /// its value, so that a commit landing meanwhile is noticed at prepare.
+    pub fn record_read(&self, tx_id: Uuid, key: Bytes) {
+        let mut state = self.state.lock().unwrap();
+        let version = state.commit_count;
+        let reads = state.readers.entry(tx_id).or_default();
+        reads.keys.entry(key).or_insert(version);
+    }
+
+    /// Like `record_read`, for every key within `key_range`, including the
+    /// ones that don't exist yet.
+    pub fn record_scan(&self, tx_id: Uuid, key_range: KeyRange) {
+        let mut state = self.state.lock().unwrap();
+        let version = state.commit_count;
+        let reads = state.readers.entry(tx_id).or_default();
+        reads.ranges.push((key_range, version));
+    }
+
+    /// Whether the transaction read the range optimistically.
+    pub fn is_reader(&self, tx_id: Uuid) -> bool {
+        self.state.lock().unwrap().readers.contains_key(&tx_id)
+    }
+
+    /// Whether none of what the transaction read was written since. Only
+    /// stays true for as long as no commit can happen, i.e. while holding the
+    /// range lock.
+    pub fn reads_are_current(&self, tx_id: Uuid) -> bool {
+        let state = self.state.lock().unwrap();
+        let reads = match state.readers.get(&tx_id) {
+            None => return true,
+            Some(reads) => reads,
+        };
+        if reads
+            .oldest()
+            .is_some_and(|version| version < state.forgotten_through)
+        {
+            return false;
+        }
+        for (count, written) in &state.writes {
+            for key in written {
+                if reads.keys.get(key).is_some_and(|version| count > version) {
+                    return false;
+                }
+                if reads
+                    .ranges
+                    .iter()
+                    .any(|(range, version)| count > version && range.includes(key.clone()))
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Records a commit that wrote `written` to the range, once its writes
+    /// are applied.
+    pub fn record_commit(&self, written: Vec<Bytes>) {
+        let mut state = self.state.lock().unwrap();
+        state.commit_count += 1;
+        let count = state.commit_count;
+        if state.readers.is_empty() {
+            state.forgotten_through = count;
+            return;
+        }
+        state.writes.push_back((count, written));
+        if state.writes.len() > MAX_TRACKED_COMMITS {
+            let (dropped, _) = state.writes.pop_front().unwrap();
+            state.forgotten_through = dropped;
+        }
+    }
+
+    /// Stops tracking the reads of a transaction that committed or aborted.
+    pub fn forget(&self, tx_id: Uuid) {
+        let mut state = self.state.lock().unwrap();
+        if state.readers.remove(&tx_id).is_none() {
+            return;
+        }
+        let oldest = state.readers.values().filter_map(Reads::oldest).min();
+        let oldest = oldest.unwrap_or(state.commit_count);
+        while state
+            .writes
+            .front()
+            .is_some_and(|(count, _)| *count <= oldest)
+        {
+            let (dropped, _) = state.writes.pop_front().unwrap();
+            state.forgotten_through = dropped;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(k: &'static str) -> Bytes {
+        Bytes::from_static(k.as_bytes())
+    }
+
+    #[test]
+    fn bank_balance_write_skew_is_detected() {
+        // Two withdrawals each check that the two balances of a customer
+        // cover them, and each take from a different balance.
+        let versions = ReadVersions::default();
+        let (tx1, tx2) = (Uuid::new_v4(), Uuid::new_v4());
+        for tx in [tx1, tx2] {
+            versions.record_read(tx, key("checking"));
+            versions.record_read(tx, key("savings"));
+        }
+        assert!(versions.reads_are_current(tx1));
+        versions.record_commit(vec![key("checking")]);
+        versions.forget(tx1);
+        // The second one read the balance the first one withdrew from.
+        assert!(!versions.reads_are_current(tx2));
+    }
+
+    #[test]
+    fn only_reads_of_written_keys_go_stale() {
+        let versions = ReadVersions::default();
+        let (tx1, tx2) = (Uuid::new_v4(), Uuid::new_v4());
+        versions.record_read(tx1, key("a"));
+        versions.record_scan(
+            tx2,
+            KeyRange {
+                lower_bound_inclusive: Some(key("m")),
+                upper_bound_exclusive: Some(key("p")),
+            },
+        );
+        versions.record_commit(vec![key("b"), key("z")]);
+        assert!(versions.reads_are_current(tx1));
+        assert!(versions.reads_are_current(tx2));
+        // Writing a key that did not exist when scanned invalidates the scan.
+        versions.record_commit(vec![key("n")]);
+        assert!(versions.reads_are_current(tx1));
+        assert!(!versions.reads_are_current(tx2));
+        // Reads after a commit don't conflict with it.
+        versions.record_read(tx1, key("n"));
+        assert!(versions.reads_are_current(tx1));
+        versions.record_commit(vec![key("a")]);
+        assert!(!versions.reads_are_current(tx1));
+    }
+
+    #[test]
+    fn reads_older_than_the_tracked_commits_are_stale() {
+        let versions = ReadVersions::default();
+        let tx = Uuid::new_v4();
+        versions.record_read(tx, key("a"));
+        for _ in 0..MAX_TRACKED_COMMITS {
+            versions.record_commit(vec![key("b")]);
+        }
+        assert!(versions.reads_are_current(tx));
+        versions.record_commit(vec![key("b")]);
+        assert!(!versions.reads_are_current(tx));
+
+        // Once nobody reads, nothing is kept around.
+        versions.forget(tx);
+        versions.record_commit(vec![key("a")]);
+        let tx = Uuid::new_v4();
+        versions.record_read(tx, key("a"));
+        assert!(versions.reads_are_current(tx));
+        assert!(versions.state.lock().unwrap().writes.is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{error::Error, storage::Error as StorageError};
+use common::full_range_id::FullRangeId;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tokio::sync::mpsc;
+use tracing::error;
+
+// Number of consecutive failed storage operations after which a range is
+// considered faulted.
+const FAULT_THRESHOLD: u32 = 5;
+
+/// Tracks whether storage operations of a range keep failing, and reports the
+/// range as faulted once they have failed too many times in a row.
+pub struct StorageHealth {
+    range_id: FullRangeId,
+    consecutive_failures: AtomicU32,
+    faulted: AtomicBool,
+    fault_sender: mpsc::UnboundedSender<FullRangeId>,
+}
+
+impl StorageHealth {
+    pub fn new(
+        range_id: FullRangeId,
+        fault_sender: mpsc::UnboundedSender<FullRangeId>,
+    ) -> StorageHealth {
+        StorageHealth {
+            range_id,
+            consecutive_failures: AtomicU32::new(0),
+            faulted: AtomicBool::new(false),
+            fault_sender,
+        }
+    }
+
+    /// Once faulted, a range stays faulted until it gets reloaded.
+    pub fn is_faulted(&self) -> bool {
+        self.faulted.load(Ordering::Acquire)
+    }
+
+    /// Records the outcome of a storage operation and converts its error, if
+    /// any.
+    pub fn check<T>(&self, result: Result<T, StorageError>) -> Result<T, Error> {
+        match &result {
+            Ok(_) => self.consecutive_failures.store(0, Ordering::Release),
+            // These are answers from the storage layer rather than failures to
+            // reach it.
+            Err(StorageError::RangeDoesNotExist) | Err(StorageError::RangeOwnershipLost) => (),
+            Err(StorageError::Timeout)
+            | Err(StorageError::Unavailable(_))
+            | Err(StorageError::InternalError(_)) => {
+                let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+                if failures >= FAULT_THRESHOLD && !self.faulted.swap(true, Ordering::AcqRel) {
+                    error!(
+                        range_id = ?self.range_id,
+                        "Range faulted after {} consecutive storage failures",
+                        failures
+                    );
+                    // The server may already be shutting down.
+                    let _ = self.fault_sender.send(self.range_id);
+                }
+            }
+        };
+        result.map_err(Error::from_storage_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::keyspace_id::KeyspaceId;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn new_health() -> (StorageHealth, mpsc::UnboundedReceiver<FullRangeId>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let range_id = FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        };
+        (StorageHealth::new(range_id, sender), receiver)
+    }
+
+    fn internal_error() -> Result<(), StorageError> {
+        Err(StorageError::InternalError(Arc::new(std::fmt::Error)))
+    }
+
+    #[test]
+    fn faults_after_consecutive_failures() {
+        let (health, mut receiver) = new_health();
+        for _ in 0..FAULT_THRESHOLD - 1 {
+            assert!(health.check(internal_error()).is_err());
+        }
+        assert!(!health.is_faulted());
+        assert!(receiver.try_recv().is_err());
+        assert!(health.check(Err::<(), _>(StorageError::Timeout)).is_err());
+        assert!(health.is_faulted());
+        assert!(receiver.try_recv().is_ok());
+        // The fault is only reported once.
+        assert!(health.check(internal_error()).is_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn success_resets_failure_count() {
+        let (health, _receiver) = new_health();
+        for _ in 0..FAULT_THRESHOLD - 1 {
+            let _ = health.check(internal_error());
+        }
+        assert!(health.check(Ok(())).is_ok());
+        for _ in 0..FAULT_THRESHOLD - 1 {
+            let _ = health.check(internal_error());
+        }
+        assert!(!health.is_faulted());
+    }
+
+    #[test]
+    fn ownership_loss_is_not_a_failure() {
+        let (health, _receiver) = new_health();
+        for _ in 0..FAULT_THRESHOLD {
+            let _ = health.check(Err::<(), _>(StorageError::RangeOwnershipLost));
+        }
+        assert!(!health.is_faulted());
+    }
+}
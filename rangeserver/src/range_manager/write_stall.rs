@@ -0,0 +1,192 @@
+use common::{clock::Clock, config::WriteStallConfig, full_range_id::FullRangeId};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use super::WriteStallStatus;
+
+#[derive(Default)]
+struct State {
+    // When the current run of slow applies started, and when the last of
+    // them happened.
+    slow_since: Option<Instant>,
+    last_slow: Option<Instant>,
+    last_latency: Duration,
+    stalled_since: Option<Instant>,
+    stalls: u64,
+}
+
+/// Watches how long the writes of commits take to apply to storage, and
+/// reports the range as stalled once they have been slow for a sustained
+/// period, rather than letting it look like random timeouts to clients.
+pub struct WriteStallDetector {
+    range_id: FullRangeId,
+    config: WriteStallConfig,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+impl WriteStallDetector {
+    pub fn new(
+        range_id: FullRangeId,
+        config: WriteStallConfig,
+        clock: Arc<dyn Clock>,
+    ) -> WriteStallDetector {
+        WriteStallDetector {
+            range_id,
+            config,
+            clock,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn record_apply(&self, latency: Duration) {
+        let now = self.clock.instant();
+        let mut state = self.state.lock().unwrap();
+        state.last_latency = latency;
+        if latency > self.config.apply_latency_threshold {
+            state.slow_since.get_or_insert(now);
+            state.last_slow = Some(now);
+        } else {
+            state.slow_since = None;
+            state.last_slow = None;
+        }
+        self.update(&mut state, now);
+    }
+
+    /// How long writers should wait before retrying, if the range is stalled.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        self.update(&mut state, self.clock.instant());
+        state
+            .stalled_since
+            .map(|_| state.last_latency.max(self.config.apply_latency_threshold))
+    }
+
+    /// Adds stalls counted by a previous detector of the range.
+    pub fn restore_stalls(&self, stalls: u64) {
+        self.state.lock().unwrap().stalls += stalls;
+    }
+
+    pub fn status(&self) -> WriteStallStatus {
+        let now = self.clock.instant();
+        let mut state = self.state.lock().unwrap();
+        self.update(&mut state, now);
+        WriteStallStatus {
+            stalled_for: state.stalled_since.map(|since| now - since),
+            last_apply_latency: state.last_latency,
+            stalls: state.stalls,
+        }
+    }
+
+    fn update(&self, state: &mut State, now: Instant) {
+        let sustained_for = self.config.sustained_for;
+        // Stalled writes are turned away, so a range can also recover by not
+        // seeing any slow apply for a while.
+        if state
+            .last_slow
+            .is_some_and(|last| now - last >= sustained_for)
+        {
+            state.slow_since = None;
+            state.last_slow = None;
+        }
+        let stalled = match (state.slow_since, state.last_slow) {
+            (Some(since), Some(last)) => last - since >= sustained_for,
+            _ => false,
+        };
+        match (stalled, state.stalled_since) {
+            (true, None) => {
+                state.stalled_since = Some(now);
+                state.stalls += 1;
+                error!(
+                    range_id = ?self.range_id,
+                    "Range writes stalled: applies to storage took over {:?} for {:?}, the last one {:?}",
+                    self.config.apply_latency_threshold,
+                    sustained_for,
+                    state.last_latency
+                );
+            }
+            (false, Some(since)) => {
+                state.stalled_since = None;
+                info!(
+                    range_id = ?self.range_id,
+                    "Range writes recovered after stalling for {:?}",
+                    now - since
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use common::clock::ManualClock;
+    use common::keyspace_id::KeyspaceId;
+    use uuid::Uuid;
+
+    const SLOW: Duration = Duration::from_secs(2);
+    const FAST: Duration = Duration::from_millis(10);
+
+    fn new_detector() -> (WriteStallDetector, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let range_id = FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        };
+        let detector = WriteStallDetector::new(
+            range_id,
+            WriteStallConfig {
+                apply_latency_threshold: Duration::from_secs(1),
+                sustained_for: Duration::from_secs(10),
+            },
+            clock.clone(),
+        );
+        (detector, clock)
+    }
+
+    #[test]
+    fn stalls_after_sustained_slow_applies() {
+        let (detector, clock) = new_detector();
+        for _ in 0..5 {
+            detector.record_apply(SLOW);
+            clock.advance(Duration::from_secs(2));
+        }
+        assert_eq!(detector.retry_after(), None);
+        detector.record_apply(SLOW);
+        assert_eq!(detector.retry_after(), Some(SLOW));
+        assert_eq!(detector.status().stalls, 1);
+
+        detector.record_apply(FAST);
+        assert_eq!(detector.retry_after(), None);
+        assert_eq!(detector.status().stalled_for, None);
+    }
+
+    #[test]
+    fn fast_apply_resets_the_slow_run() {
+        let (detector, clock) = new_detector();
+        for _ in 0..5 {
+            detector.record_apply(SLOW);
+            clock.advance(Duration::from_secs(2));
+        }
+        detector.record_apply(FAST);
+        detector.record_apply(SLOW);
+        assert_eq!(detector.retry_after(), None);
+        assert_eq!(detector.status().stalls, 0);
+    }
+
+    #[test]
+    fn recovers_without_applies() {
+        let (detector, clock) = new_detector();
+        for _ in 0..6 {
+            detector.record_apply(SLOW);
+            clock.advance(Duration::from_secs(2));
+        }
+        assert!(detector.retry_after().is_some());
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(detector.retry_after(), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Counts how the gets each range serves are answered, from the prefetch
+//! buffer or from storage, to tell which ranges would read less from storage
+//! with more prefetching or with prefetched values cached for longer. Counts
+//! are kept in memory from when the range was loaded, and are advisory only.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Ranges that served fewer gets are not worth tuning, and their ratios are
+// mostly noise.
+pub const DEFAULT_MIN_GETS: u64 = 1000;
+
+// Gets mostly answered from storage below this prefetch buffer hit ratio.
+const LOW_HIT_RATIO: f64 = 0.5;
+
+// Prefetches that went to storage for each get they answered, above which
+// most prefetched values are dropped from the buffer before being read.
+const HIGH_PREFETCH_WASTE: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReadStats {
+    /// Gets served by the range, including reads at an epoch.
+    pub gets: u64,
+    /// Gets answered from the prefetch buffer.
+    pub cache_hits: u64,
+    /// Reads of the range's storage for the gets, either when they missed the
+    /// prefetch buffer or to prefetch them.
+    pub storage_reads: u64,
+    /// Keys of the range asked to be prefetched.
+    pub prefetches: u64,
+    /// Prefetches that had to read storage, the others being in the buffer
+    /// already.
+    pub prefetch_storage_reads: u64,
+}
+
+/// A change that would likely have the range read less from storage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadHint {
+    /// Most gets miss the prefetch buffer, so their callers wait on storage.
+    /// Prefetching the keys ahead of reading them would help.
+    PrefetchMore,
+    /// Most values prefetched are dropped from the buffer unread, so every
+    /// get pays for several storage reads. Either the values should stay
+    /// cached for longer, or fewer keys should be prefetched.
+    PrefetchedValuesGoUnread,
+}
+
+impl ReadStats {
+    /// The share of gets answered from the prefetch buffer, None if there
+    /// were no gets.
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        (self.gets > 0).then(|| self.cache_hits as f64 / self.gets as f64)
+    }
+
+    /// How many times storage was read per get, None if there were no gets.
+    pub fn storage_reads_per_get(&self) -> Option<f64> {
+        (self.gets > 0).then(|| self.storage_reads as f64 / self.gets as f64)
+    }
+
+    /// What would likely make the range read less from storage, for ranges
+    /// that served at least `min_gets` gets.
+    pub fn hints(&self, min_gets: u64) -> Vec<ReadHint> {
+        if self.gets == 0 || self.gets < min_gets {
+            return Vec::new();
+        }
+        let mut hints = Vec::new();
+        if self.cache_hit_ratio().unwrap() < LOW_HIT_RATIO {
+            hints.push(ReadHint::PrefetchMore);
+        }
+        if self.prefetch_storage_reads as f64 > self.cache_hits.max(1) as f64 * HIGH_PREFETCH_WASTE
+        {
+            hints.push(ReadHint::PrefetchedValuesGoUnread);
+        }
+        hints
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ReadTracker {
+    gets: AtomicU64,
+    cache_hits: AtomicU64,
+    storage_reads: AtomicU64,
+    prefetches: AtomicU64,
+    prefetch_storage_reads: AtomicU64,
+}
+
+impl ReadTracker {
+    /// Counts a get, which read storage unless it was a `cache_hit`.
+    pub fn record_get(&self, cache_hit: bool) {
+        self.gets.fetch_add(1, Ordering::Relaxed);
+        if cache_hit {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.storage_reads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a prefetch, which read storage if `fetched`.
+    pub fn record_prefetch(&self, fetched: bool) {
+        self.prefetches.fetch_add(1, Ordering::Relaxed);
+        if fetched {
+            self.prefetch_storage_reads.fetch_add(1, Ordering::Relaxed);
+            self.storage_reads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> ReadStats {
+        ReadStats {
+            gets: self.gets.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            storage_reads: self.storage_reads.load(Ordering::Relaxed),
+            prefetches: self.prefetches.load(Ordering::Relaxed),
+            prefetch_storage_reads: self.prefetch_storage_reads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gets_missing_the_buffer_read_storage() {
+        let tracker = ReadTracker::default();
+        tracker.record_prefetch(true);
+        tracker.record_prefetch(false);
+        tracker.record_get(true);
+        tracker.record_get(true);
+        tracker.record_get(false);
+        tracker.record_get(false);
+        let stats = tracker.stats();
+        assert_eq!(
+            stats,
+            ReadStats {
+                gets: 4,
+                cache_hits: 2,
+                storage_reads: 3,
+                prefetches: 2,
+                prefetch_storage_reads: 1,
+            }
+        );
+        assert_eq!(stats.cache_hit_ratio(), Some(0.5));
+        assert_eq!(stats.storage_reads_per_get(), Some(0.75));
+        assert_eq!(ReadStats::default().cache_hit_ratio(), None);
+    }
+
+    #[test]
+    fn hints_point_at_the_buffer_misses() {
+        let unprefetched = ReadStats {
+            gets: 1000,
+            cache_hits: 100,
+            storage_reads: 900,
+            ..Default::default()
+        };
+        assert_eq!(unprefetched.hints(1000), vec![ReadHint::PrefetchMore]);
+        assert!(unprefetched.hints(1001).is_empty());
+
+        let wasted = ReadStats {
+            gets: 1000,
+            cache_hits: 900,
+            storage_reads: 5000,
+            prefetches: 5000,
+            prefetch_storage_reads: 4900,
+        };
+        assert_eq!(wasted.hints(1000), vec![ReadHint::PrefetchedValuesGoUnread]);
+
+        let well_prefetched = ReadStats {
+            gets: 1000,
+            cache_hits: 950,
+            storage_reads: 1000,
+            prefetches: 950,
+            prefetch_storage_reads: 950,
+        };
+        assert!(well_prefetched.hints(1000).is_empty());
+    }
+}
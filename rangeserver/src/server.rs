@@ -1,36 +1,76 @@
 use bytes::Bytes;
 use common::network::fast_network::FastNetwork;
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedReceiver;
 use tonic::{transport::Server as TServer, Request, Response, Status as TStatus};
 
+use common::key_range::KeyRange;
 use common::keyspace_id::KeyspaceId;
 use common::util;
 use common::{
-    config::Config, constants, full_range_id::FullRangeId, host_info::HostInfo,
-    transaction_info::TransactionInfo,
+    clock::{Clock, SystemClock},
+    config::Config,
+    constants,
+    full_range_id::FullRangeId,
+    hash_partitioning::key_hash,
+    host_info::HostInfo,
+    profiling::ProfilingService,
+    region::Region,
+    transaction_info::{IsolationLevel, TransactionInfo},
 };
-use flatbuffers::FlatBufferBuilder;
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use tokio::net::TcpListener;
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{mpsc, oneshot, OnceCell, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 
+use tracing::{error, info, warn};
+use tx_state_store::client::{Client as TxStateStoreClient, OpResult};
 use uuid::Uuid;
 
+use crate::keyspace_flags::KeyspaceFlags;
+use crate::mirroring::{Mirror, MirrorStats};
+use crate::preflight::PreflightReport;
 use crate::range_manager::r#impl::RangeManager;
-use crate::range_manager::RangeManager as RangeManagerTrait;
+use crate::range_manager::{
+    PrepareResolver, RangeManager as RangeManagerTrait, ReadMode, SoftState, SplitRange,
+};
 use crate::warden_handler::WardenHandler;
 use crate::{
-    epoch_supplier::EpochSupplier, error::Error, for_testing::in_memory_wal::InMemoryWal,
-    storage::Storage,
+    checksum::ChecksumReport,
+    conflict_stats,
+    epoch_supplier::EpochSupplier,
+    error::Error,
+    for_testing::in_memory_wal::InMemoryWal,
+    handover,
+    storage::{Storage, RANGE_SNAPSHOT_FORMAT_VERSION},
 };
 use flatbuf::rangeserver_flatbuffers::range_server::TransactionInfo as FlatbufTransactionInfo;
 use flatbuf::rangeserver_flatbuffers::range_server::*;
 
 use proto::rangeserver::range_server_server::{RangeServer, RangeServerServer};
-use proto::rangeserver::{PrefetchRequest, PrefetchResponse};
+use proto::rangeserver::{
+    bulk_get_chunk, bulk_get_request, split_range_response, BulkGetChunk, BulkGetRequest,
+    ChecksumReport as ProtoChecksumReport, CleanupOrphanedPreparesRequest,
+    CleanupOrphanedPreparesResponse, CompactRangeRequest, CompactRangeResponse,
+    ConflictCounts as ProtoConflictCounts, ExportRangeSnapshotRequest, GetChecksumStatsRequest,
+    GetChecksumStatsResponse, GetCompactionStatsRequest, GetCompactionStatsResponse,
+    GetConflictStatsRequest as ProtoGetConflictStatsRequest,
+    GetConflictStatsResponse as ProtoGetConflictStatsResponse, GetLockTableOccupancyRequest,
+    GetLockTableOccupancyResponse, GetOldestPreparedRequest, GetOldestPreparedResponse,
+    GetReadStatsRequest, GetReadStatsResponse, GetVersionsRequest, GetVersionsResponse,
+    GetWriteStallStatusRequest, GetWriteStallStatusResponse, HandoverState as ProtoHandoverState,
+    InFlightTransaction as ProtoInFlightTransaction, LabelConflicts as ProtoLabelConflicts,
+    ListInFlightTransactionsRequest, ListInFlightTransactionsResponse, OrphanedPrepare,
+    PrefetchRequest, PrefetchResponse, PrefixConflicts as ProtoPrefixConflicts,
+    PreparedTransaction as ProtoPreparedTransaction, RangeId as ProtoRangeId, RangeReadStats,
+    RangeSnapshot as ProtoRangeSnapshot, RecordVersion as ProtoRecordVersion,
+    SetRangeFrozenRequest, SetRangeFrozenResponse, SnapshotRecord, SplitRangeRequest,
+    SplitRangeResponse, TransactionOutcome as ProtoTransactionOutcome, VerifyRangeChecksumsRequest,
+};
 
 use crate::prefetching_buffer::PrefetchingBuffer;
 
@@ -42,11 +82,148 @@ where
     parent_server: Arc<Server<S>>,
 }
 
+// Returns the reason the range is invalid on failure.
+pub(crate) fn full_range_id_from_proto(
+    range: Option<&ProtoRangeId>,
+) -> Result<FullRangeId, String> {
+    let range = range.ok_or_else(|| "Missing range".to_string())?;
+    let keyspace_id = KeyspaceId::new(
+        Uuid::parse_str(&range.keyspace_id)
+            .map_err(|e| format!("Keyspace id is not in the correct format: {:?}", e))?,
+    );
+    let range_id = Uuid::parse_str(&range.range_id)
+        .map_err(|e| format!("Range id is not in the correct format: {:?}", e))?;
+    Ok(FullRangeId {
+        keyspace_id,
+        range_id,
+    })
+}
+
+fn conflict_counts_to_flatbuf<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    counts: &conflict_stats::ConflictCounts,
+) -> WIPOffset<ConflictCounts<'a>> {
+    ConflictCounts::create(
+        fbb,
+        &ConflictCountsArgs {
+            wait_die: counts.wait_die,
+            lock_lost: counts.lock_lost,
+            read_conflicts: counts.read_conflicts,
+            overloaded: counts.overloaded,
+        },
+    )
+}
+
+/// Sends the records selected by a bulk get to `sender` one chunk at a time,
+/// reading each chunk only once the previous one was taken off the channel.
+async fn stream_bulk_get<S: Storage>(
+    range_manager: Arc<RangeManager<S, InMemoryWal>>,
+    selection: bulk_get_request::Selection,
+    chunk_size: usize,
+    sender: mpsc::Sender<Result<BulkGetChunk, TStatus>>,
+) -> Result<(), Error> {
+    let to_proto = |key: Bytes, value: Option<Bytes>| bulk_get_chunk::Record {
+        key: key.to_vec(),
+        value: value.map(|v| v.to_vec()),
+    };
+    match selection {
+        bulk_get_request::Selection::Keys(keys) => {
+            for chunk in keys.keys.chunks(chunk_size) {
+                let keys: Vec<Bytes> = chunk.iter().map(|k| Bytes::copy_from_slice(k)).collect();
+                let vals = range_manager.read_committed(&keys).await?;
+                let records = keys.into_iter().zip(vals).map(|(k, v)| to_proto(k, v));
+                let chunk = BulkGetChunk {
+                    records: records.collect(),
+                };
+                if sender.send(Ok(chunk)).await.is_err() {
+                    // The client went away.
+                    return Ok(());
+                }
+            }
+        }
+        bulk_get_request::Selection::KeyRange(key_range) => {
+            let mut key_range = KeyRange {
+                lower_bound_inclusive: key_range.lower_bound_inclusive.map(Bytes::from),
+                upper_bound_exclusive: key_range.upper_bound_exclusive.map(Bytes::from),
+            };
+            loop {
+                let records = range_manager
+                    .scan_committed(key_range.clone(), Some(chunk_size))
+                    .await?;
+                let Some((last_key, _)) = records.last() else {
+                    return Ok(());
+                };
+                // The smallest key after the last one read.
+                let mut next_key = last_key.to_vec();
+                next_key.push(0);
+                key_range.lower_bound_inclusive = Some(Bytes::from(next_key));
+                let done = records.len() < chunk_size;
+                let chunk = BulkGetChunk {
+                    records: records
+                        .into_iter()
+                        .map(|(k, v)| to_proto(k, Some(v)))
+                        .collect(),
+                };
+                if sender.send(Ok(chunk)).await.is_err() || done {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn bulk_get_status(e: Error) -> TStatus {
+    match e {
+        Error::RangeIsNotLoaded | Error::RangeOwnershipLost => {
+            TStatus::failed_precondition(format!("{:?}", e))
+        }
+        Error::KeyIsOutOfRange => TStatus::out_of_range(format!("{:?}", e)),
+        Error::RangeFaulted => TStatus::unavailable(format!("{:?}", e)),
+        Error::Timeout => TStatus::deadline_exceeded(format!("{:?}", e)),
+        e => TStatus::internal(format!("{:?}", e)),
+    }
+}
+
 #[tonic::async_trait]
 impl<S> RangeServer for ProtoServer<S>
 where
     S: Storage,
 {
+    type BulkGetStream = ReceiverStream<Result<BulkGetChunk, TStatus>>;
+
+    async fn bulk_get(
+        &self,
+        request: Request<BulkGetRequest>,
+    ) -> Result<Response<Self::BulkGetStream>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        let selection = request
+            .selection
+            .ok_or_else(|| TStatus::invalid_argument("Either keys or key_range must be set"))?;
+        let chunk_size = match request.chunk_size {
+            0 => BULK_GET_DEFAULT_CHUNK_SIZE,
+            chunk_size => chunk_size as usize,
+        };
+        let range_manager = self
+            .parent_server
+            .maybe_load_and_get_range(&full_range_id)
+            .await
+            .map_err(bulk_get_status)?;
+        // The channel is bounded, so a slow client holds back the reads
+        // instead of having every chunk buffered here.
+        let (sender, receiver) = mpsc::channel(BULK_GET_BUFFERED_CHUNKS);
+        tokio::spawn(async move {
+            if let Err(e) =
+                stream_bulk_get(range_manager, selection, chunk_size, sender.clone()).await
+            {
+                let _ = sender.send(Err(bulk_get_status(e))).await;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
     async fn prefetch(
         &self,
         request: Request<PrefetchRequest>, // Accept request of type PrefetchRequest
@@ -92,6 +269,570 @@ where
             Err(_) => Err(TStatus::internal("Failed to process prefetch request")),
         }
     }
+
+    async fn list_in_flight_transactions(
+        &self,
+        request: Request<ListInFlightTransactionsRequest>,
+    ) -> Result<Response<ListInFlightTransactionsResponse>, TStatus> {
+        let full_range_id = full_range_id_from_proto(request.get_ref().range.as_ref())
+            .map_err(TStatus::invalid_argument)?;
+
+        // Only inspect the range if it is already loaded, listing should never
+        // cause a range to get loaded.
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let in_flight = range_manager
+            .list_in_flight_transactions()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+
+        let now = self.parent_server.clock.now();
+        let transactions = in_flight
+            .into_iter()
+            .map(|tx| ProtoInFlightTransaction {
+                transaction_id: tx.id.to_string(),
+                age_us: tx.lock_acquired.map(|t| {
+                    (now - t)
+                        .to_std()
+                        .unwrap_or(std::time::Duration::ZERO)
+                        .as_micros() as u64
+                }),
+                lock_count: tx.lock_count,
+                prepared: tx.prepared,
+                labels: tx.labels.into_iter().collect(),
+                prepared_age_us: tx.prepared_at.map(|t| {
+                    (now - t)
+                        .to_std()
+                        .unwrap_or(std::time::Duration::ZERO)
+                        .as_micros() as u64
+                }),
+            })
+            .collect();
+        Ok(Response::new(ListInFlightTransactionsResponse {
+            transactions,
+        }))
+    }
+
+    async fn get_versions(
+        &self,
+        request: Request<GetVersionsRequest>,
+    ) -> Result<Response<GetVersionsResponse>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        // Reads straight from storage, so this works whether or not the range
+        // is loaded, and never loads it.
+        let versions = self
+            .parent_server
+            .storage
+            .get_versions(
+                full_range_id,
+                Bytes::from(request.key),
+                request.limit as usize,
+            )
+            .await
+            .map_err(|e| TStatus::unavailable(format!("Failed to read versions: {}", e)))?;
+        let versions = versions
+            .into_iter()
+            .map(|version| ProtoRecordVersion {
+                epoch: version.epoch,
+                transaction_id: version.transaction_id.map(|id| id.to_string()),
+                is_tombstone: version.value.is_none(),
+                value_hash: version.value.map(|value| key_hash(&value)),
+            })
+            .collect();
+        Ok(Response::new(GetVersionsResponse { versions }))
+    }
+
+    async fn get_lock_table_occupancy(
+        &self,
+        request: Request<GetLockTableOccupancyRequest>,
+    ) -> Result<Response<GetLockTableOccupancyResponse>, TStatus> {
+        let full_range_id = full_range_id_from_proto(request.get_ref().range.as_ref())
+            .map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let occupancy = range_manager
+            .lock_table_occupancy()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        Ok(Response::new(GetLockTableOccupancyResponse {
+            holder_age_us: occupancy.holder_age.map(|age| age.as_micros() as u64),
+            waiters: occupancy.waiters as u64,
+            waiting_for_release: occupancy.waiting_for_release as u64,
+            spilled_waiters: occupancy.spilled_waiters as u64,
+            rejected: occupancy.rejected,
+        }))
+    }
+
+    async fn export_range_snapshot(
+        &self,
+        request: Request<ExportRangeSnapshotRequest>,
+    ) -> Result<Response<ProtoRangeSnapshot>, TStatus> {
+        let range = request.into_inner().range;
+        let full_range_id =
+            full_range_id_from_proto(range.as_ref()).map_err(TStatus::invalid_argument)?;
+        // Prepared transactions only exist on a loaded range, so exporting
+        // never loads it.
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let taken_at = self.parent_server.clock.now();
+        let snapshot = range_manager
+            .export_snapshot()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        Ok(Response::new(ProtoRangeSnapshot {
+            format_version: RANGE_SNAPSHOT_FORMAT_VERSION,
+            range,
+            key_lower_bound_inclusive: snapshot
+                .key_range
+                .lower_bound_inclusive
+                .map(|bound| bound.to_vec()),
+            key_upper_bound_exclusive: snapshot
+                .key_range
+                .upper_bound_exclusive
+                .map(|bound| bound.to_vec()),
+            leader_sequence_number: snapshot.leader_sequence_number,
+            epoch_lease_lower_bound: snapshot.epoch_lease.0,
+            epoch_lease_upper_bound: snapshot.epoch_lease.1,
+            highest_known_epoch: snapshot.highest_known_epoch,
+            records: snapshot
+                .records
+                .into_iter()
+                .map(|(key, version)| SnapshotRecord {
+                    key: key.to_vec(),
+                    epoch: version.epoch,
+                    transaction_id: version.transaction_id.map(|id| id.to_string()),
+                    value: version.value.map(|value| value.to_vec()),
+                    expires_at_us: version
+                        .expires_at
+                        .map(|expires_at| expires_at.timestamp_micros() as u64),
+                })
+                .collect(),
+            prepared_transactions: snapshot
+                .prepared
+                .into_iter()
+                .map(|(id, prepare)| ProtoPreparedTransaction {
+                    transaction_id: id.to_string(),
+                    prepare_request: prepare.to_vec(),
+                })
+                .collect(),
+            taken_at_us: taken_at.timestamp_micros() as u64,
+        }))
+    }
+
+    async fn get_conflict_stats(
+        &self,
+        request: Request<ProtoGetConflictStatsRequest>,
+    ) -> Result<Response<ProtoGetConflictStatsResponse>, TStatus> {
+        let full_range_id = full_range_id_from_proto(request.get_ref().range.as_ref())
+            .map_err(TStatus::invalid_argument)?;
+        let stats = self
+            .parent_server
+            .conflict_stats_inner(&full_range_id)
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        let counts = |c: conflict_stats::ConflictCounts| ProtoConflictCounts {
+            wait_die: c.wait_die,
+            lock_lost: c.lock_lost,
+            read_conflicts: c.read_conflicts,
+            overloaded: c.overloaded,
+        };
+        Ok(Response::new(ProtoGetConflictStatsResponse {
+            prefixes: stats
+                .prefixes
+                .into_iter()
+                .map(|(prefix, c)| ProtoPrefixConflicts {
+                    prefix: prefix.to_vec(),
+                    counts: Some(counts(c)),
+                })
+                .collect(),
+            other: Some(counts(stats.other)),
+            labels: stats
+                .labels
+                .into_iter()
+                .map(|((key, value), c)| ProtoLabelConflicts {
+                    key,
+                    value,
+                    counts: Some(counts(c)),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_compaction_stats(
+        &self,
+        request: Request<GetCompactionStatsRequest>,
+    ) -> Result<Response<GetCompactionStatsResponse>, TStatus> {
+        let full_range_id = full_range_id_from_proto(request.get_ref().range.as_ref())
+            .map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let stats = range_manager
+            .compaction_stats()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        Ok(Response::new(GetCompactionStatsResponse {
+            keys: stats.versions.keys,
+            live_keys: stats.versions.live_keys,
+            versions: stats.versions.versions,
+            tombstones: stats.versions.tombstones,
+            reclaimable_versions: stats.versions.reclaimable_versions,
+            compactions: stats.compactions,
+            purged_versions: stats.purged_versions,
+        }))
+    }
+
+    async fn compact_range(
+        &self,
+        request: Request<CompactRangeRequest>,
+    ) -> Result<Response<CompactRangeResponse>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        // Only the range's owner may change its records, so compacting never
+        // loads it.
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let outcome = range_manager
+            .compact(request.retain_epochs)
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        info!(
+            "Compacted range {:?} up to epoch {}: removed {} versions of {} keys",
+            full_range_id, outcome.horizon, outcome.purged_versions, outcome.purged_keys
+        );
+        Ok(Response::new(CompactRangeResponse {
+            horizon_epoch: outcome.horizon,
+            purged_keys: outcome.purged_keys,
+            purged_versions: outcome.purged_versions,
+        }))
+    }
+
+    async fn split_range(
+        &self,
+        request: Request<SplitRangeRequest>,
+    ) -> Result<Response<SplitRangeResponse>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let mut split_key = Bytes::from(request.split_key);
+        if let Some(colocation) = self
+            .parent_server
+            .keyspace_flags
+            .colocation(full_range_id.keyspace_id)
+            .await
+        {
+            split_key = colocation.align_split_key(&split_key);
+        }
+        let children = range_manager.split(split_key).await.map_err(|e| match e {
+            Error::RangeBusy => TStatus::unavailable(format!("{:?}", e)),
+            e => TStatus::failed_precondition(format!("{:?}", e)),
+        })?;
+        self.parent_server
+            .report_range_split(&full_range_id, &children)
+            .await?;
+        Ok(Response::new(SplitRangeResponse {
+            new_ranges: children
+                .iter()
+                .map(|child| split_range_response::NewRange {
+                    range_id: child.range_id.to_string(),
+                    lower_bound_inclusive: child
+                        .key_range
+                        .lower_bound_inclusive
+                        .as_ref()
+                        .map(|b| b.to_vec()),
+                    upper_bound_exclusive: child
+                        .key_range
+                        .upper_bound_exclusive
+                        .as_ref()
+                        .map(|b| b.to_vec()),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_write_stall_status(
+        &self,
+        request: Request<GetWriteStallStatusRequest>,
+    ) -> Result<Response<GetWriteStallStatusResponse>, TStatus> {
+        let full_range_id = full_range_id_from_proto(request.get_ref().range.as_ref())
+            .map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let status = range_manager
+            .write_stall_status()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        Ok(Response::new(GetWriteStallStatusResponse {
+            stalled_for_us: status.stalled_for.map(|d| d.as_micros() as u64),
+            last_apply_latency_us: status.last_apply_latency.as_micros() as u64,
+            stalls: status.stalls,
+        }))
+    }
+
+    async fn set_range_frozen(
+        &self,
+        request: Request<SetRangeFrozenRequest>,
+    ) -> Result<Response<SetRangeFrozenResponse>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let was_frozen = range_manager
+            .set_frozen(request.frozen)
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        let prepared_transactions = range_manager
+            .list_in_flight_transactions()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?
+            .iter()
+            .filter(|tx| tx.prepared)
+            .count() as u32;
+        Ok(Response::new(SetRangeFrozenResponse {
+            was_frozen,
+            prepared_transactions,
+        }))
+    }
+
+    async fn cleanup_orphaned_prepares(
+        &self,
+        request: Request<CleanupOrphanedPreparesRequest>,
+    ) -> Result<Response<CleanupOrphanedPreparesResponse>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let prepares = self
+            .parent_server
+            .resolve_orphaned_prepares(
+                &range_manager,
+                &full_range_id,
+                Duration::from_micros(request.older_than_us),
+                request.dry_run,
+            )
+            .await?;
+        Ok(Response::new(CleanupOrphanedPreparesResponse { prepares }))
+    }
+
+    async fn verify_range_checksums(
+        &self,
+        request: Request<VerifyRangeChecksumsRequest>,
+    ) -> Result<Response<ProtoChecksumReport>, TStatus> {
+        let request = request.into_inner();
+        let full_range_id =
+            full_range_id_from_proto(request.range.as_ref()).map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let report = range_manager
+            .verify_checksums(request.max_divergent_keys as usize)
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        log_checksum_report(&full_range_id, &report);
+        Ok(Response::new(checksum_report_to_proto(report)))
+    }
+
+    async fn get_checksum_stats(
+        &self,
+        request: Request<GetChecksumStatsRequest>,
+    ) -> Result<Response<GetChecksumStatsResponse>, TStatus> {
+        let full_range_id = full_range_id_from_proto(request.get_ref().range.as_ref())
+            .map_err(TStatus::invalid_argument)?;
+        let range_manager = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.get(&full_range_id.range_id).cloned()
+        }
+        .ok_or_else(|| TStatus::failed_precondition("Range is not loaded"))?;
+        let stats = range_manager
+            .checksum_stats()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+        Ok(Response::new(GetChecksumStatsResponse {
+            verifications: stats.verifications,
+            divergences: stats.divergences,
+            last: stats.last.map(checksum_report_to_proto),
+        }))
+    }
+
+    async fn get_read_stats(
+        &self,
+        request: Request<GetReadStatsRequest>,
+    ) -> Result<Response<GetReadStatsResponse>, TStatus> {
+        let mut range_ids = Vec::new();
+        for range in &request.get_ref().ranges {
+            range_ids
+                .push(full_range_id_from_proto(Some(range)).map_err(TStatus::invalid_argument)?);
+        }
+        let range_managers: Vec<_> = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            if range_ids.is_empty() {
+                range_table.values().cloned().collect()
+            } else {
+                range_ids
+                    .iter()
+                    .filter_map(|id| range_table.get(&id.range_id).cloned())
+                    .collect()
+            }
+        };
+        let mut ranges = Vec::new();
+        for range_manager in range_managers {
+            // Ranges unloaded meanwhile are left out.
+            let Ok(stats) = range_manager.read_stats().await else {
+                continue;
+            };
+            let range_id = range_manager.range_id();
+            ranges.push(RangeReadStats {
+                range: Some(ProtoRangeId {
+                    keyspace_id: range_id.keyspace_id.id.to_string(),
+                    range_id: range_id.range_id.to_string(),
+                }),
+                gets: stats.gets,
+                cache_hits: stats.cache_hits,
+                storage_reads: stats.storage_reads,
+                prefetches: stats.prefetches,
+                prefetch_storage_reads: stats.prefetch_storage_reads,
+            });
+        }
+        Ok(Response::new(GetReadStatsResponse { ranges }))
+    }
+
+    async fn get_oldest_prepared(
+        &self,
+        _request: Request<GetOldestPreparedRequest>,
+    ) -> Result<Response<GetOldestPreparedResponse>, TStatus> {
+        let range_managers: Vec<_> = {
+            let range_table = self.parent_server.loaded_ranges.read().await;
+            range_table.values().cloned().collect()
+        };
+        let mut oldest_prepared = None;
+        let (mut ranges, mut unreported_ranges) = (0, 0);
+        for range_manager in range_managers {
+            match range_manager.list_in_flight_transactions().await {
+                Ok(in_flight) => {
+                    ranges += 1;
+                    let prepared = in_flight.iter().filter_map(|tx| tx.prepared_at);
+                    oldest_prepared = oldest_prepared.into_iter().chain(prepared).min();
+                }
+                Err(_) => unreported_ranges += 1,
+            }
+        }
+        let now = self.parent_server.clock.now();
+        Ok(Response::new(GetOldestPreparedResponse {
+            oldest_prepared_age_us: oldest_prepared.map(|t| {
+                (now - t)
+                    .to_std()
+                    .unwrap_or(std::time::Duration::ZERO)
+                    .as_micros() as u64
+            }),
+            ranges,
+            unreported_ranges,
+        }))
+    }
+}
+
+fn checksum_report_to_proto(report: ChecksumReport) -> ProtoChecksumReport {
+    ProtoChecksumReport {
+        verified_at_us: report.verified_at.timestamp_micros() as u64,
+        storage_checksum: report.storage_checksum.to_vec(),
+        keys: report.keys,
+        cached_keys: report.cached_keys,
+        divergent_keys: report.divergent_keys,
+        divergent_key_samples: report
+            .divergent_key_samples
+            .into_iter()
+            .map(|k| k.to_vec())
+            .collect(),
+    }
+}
+
+fn log_checksum_report(range_id: &FullRangeId, report: &ChecksumReport) {
+    if report.divergent_keys > 0 {
+        warn!(
+            range_id = ?range_id,
+            divergent_keys = report.divergent_keys,
+            samples = ?report.divergent_key_samples,
+            "Prefetch buffer diverges from storage on {} of {} cached keys",
+            report.divergent_keys,
+            report.cached_keys
+        );
+    } else {
+        info!(
+            range_id = ?range_id,
+            keys = report.keys,
+            cached_keys = report.cached_keys,
+            "Verified range checksums"
+        );
+    }
+}
+
+// The tx_state_store, only needed to decide the transactions left prepared on
+// ranges, so connected on first use.
+struct TxStateStore {
+    config: Config,
+    region: Region,
+    client: OnceCell<Arc<TxStateStoreClient>>,
+}
+
+impl TxStateStore {
+    async fn client(&self) -> Arc<TxStateStoreClient> {
+        self.client
+            .get_or_init(|| async {
+                Arc::new(TxStateStoreClient::new(self.config.clone(), self.region.clone()).await)
+            })
+            .await
+            .clone()
+    }
+}
+
+#[tonic::async_trait]
+impl PrepareResolver for TxStateStore {
+    async fn abort_unless_decided(&self, transaction_id: Uuid) -> Result<Option<u64>, Error> {
+        match self
+            .client()
+            .await
+            .try_abort_transaction(transaction_id)
+            .await
+            .map_err(|e| Error::InternalError(Arc::new(e)))?
+        {
+            OpResult::TransactionIsAborted => Ok(None),
+            OpResult::TransactionIsCommitted(info) => Ok(Some(info.epoch)),
+        }
+    }
 }
 
 pub struct Server<S>
@@ -99,6 +840,7 @@ where
     S: Storage,
 {
     config: Config,
+    host_info: HostInfo,
     storage: Arc<S>,
     epoch_supplier: Arc<dyn EpochSupplier>,
     warden_handler: WardenHandler,
@@ -107,9 +849,32 @@ where
     loaded_ranges: RwLock<HashMap<Uuid, Arc<RangeManager<S, InMemoryWal>>>>,
     transaction_table: RwLock<HashMap<Uuid, Arc<TransactionInfo>>>,
     prefetching_buffer: Arc<PrefetchingBuffer>,
+    keyspace_flags: KeyspaceFlags,
+    // Copies a sample of the gets to a shadow range server, if configured.
+    mirror: Option<Arc<Mirror>>,
+    // Range managers report ranges that hit persistent storage errors here.
+    range_fault_sender: mpsc::UnboundedSender<FullRangeId>,
+    range_fault_receiver: std::sync::Mutex<Option<UnboundedReceiver<FullRangeId>>>,
+    tx_state_store: Arc<TxStateStore>,
+    clock: Arc<dyn Clock>,
+    // Handed over by the process this one took over from, applied to each
+    // range as it is loaded again.
+    handed_over: std::sync::Mutex<HashMap<Uuid, SoftState>>,
 }
 
-type DynamicErr = Box<dyn std::error::Error + Sync + Send + 'static>;
+pub type DynamicErr = Box<dyn std::error::Error + Sync + Send + 'static>;
+
+// Upper bound on how long each startup check may wait on a dependency.
+const PREFLIGHT_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Bounds on retrying to reload a faulted range.
+const RANGE_RELOAD_MAX_ATTEMPTS: u32 = 10;
+const RANGE_RELOAD_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const RANGE_RELOAD_MAX_BACKOFF: Duration = Duration::from_secs(10);
+const SPLIT_REPORT_ATTEMPTS: u32 = 3;
+const DEFAULT_HANDOFF_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+const BULK_GET_DEFAULT_CHUNK_SIZE: usize = 1000;
+const BULK_GET_BUFFERED_CHUNKS: usize = 4;
 
 impl<S> Server<S>
 where
@@ -121,10 +886,39 @@ where
         storage: Arc<S>,
         epoch_supplier: Arc<dyn EpochSupplier>,
         bg_runtime: tokio::runtime::Handle,
+    ) -> Arc<Self> {
+        Self::new_with_clock(
+            config,
+            host_info,
+            storage,
+            epoch_supplier,
+            bg_runtime,
+            Arc::new(SystemClock),
+        )
+    }
+
+    /// Like `new`, but with the clock used for leases and lock bookkeeping
+    /// injected, e.g. for tests.
+    pub fn new_with_clock(
+        config: Config,
+        host_info: HostInfo,
+        storage: Arc<S>,
+        epoch_supplier: Arc<dyn EpochSupplier>,
+        bg_runtime: tokio::runtime::Handle,
+        clock: Arc<dyn Clock>,
     ) -> Arc<Self> {
         let warden_handler = WardenHandler::new(&config, &host_info, epoch_supplier.clone());
+        let keyspace_flags = KeyspaceFlags::new(&config);
+        let mirror = Mirror::new(&config.range_server.mirroring);
+        let (range_fault_sender, range_fault_receiver) = mpsc::unbounded_channel();
+        let tx_state_store = Arc::new(TxStateStore {
+            config: config.clone(),
+            region: host_info.identity.zone.region.clone(),
+            client: OnceCell::new(),
+        });
         Arc::new(Server {
             config,
+            host_info,
             storage,
             epoch_supplier,
             warden_handler,
@@ -132,9 +926,164 @@ where
             loaded_ranges: RwLock::new(HashMap::new()),
             transaction_table: RwLock::new(HashMap::new()),
             prefetching_buffer: Arc::new(PrefetchingBuffer::new()),
+            keyspace_flags,
+            mirror,
+            range_fault_sender,
+            range_fault_receiver: std::sync::Mutex::new(Some(range_fault_receiver)),
+            tx_state_store,
+            clock,
+            handed_over: std::sync::Mutex::new(HashMap::new()),
         })
     }
 
+    /// How the gets copied to the shadow range server went, None if
+    /// mirroring is disabled.
+    pub fn mirror_stats(&self) -> Option<MirrorStats> {
+        self.mirror.as_ref().map(|mirror| mirror.stats())
+    }
+
+    /// Collects the soft state of the loaded ranges, for the process taking
+    /// over from this one.
+    pub async fn handover_state(&self) -> ProtoHandoverState {
+        let ranges: Vec<_> = self.loaded_ranges.read().await.values().cloned().collect();
+        let mut state = ProtoHandoverState {
+            format_version: handover::HANDOVER_FORMAT_VERSION,
+            ranges: Vec::new(),
+        };
+        for rm in ranges {
+            // Ranges that are not loaded have nothing worth handing over.
+            if let Ok(soft_state) = rm.soft_state().await {
+                state
+                    .ranges
+                    .push(handover::soft_state_to_proto(rm.range_id(), &soft_state));
+            }
+        }
+        state
+    }
+
+    /// Keeps the soft state handed over by the process this one took over
+    /// from, to apply to the ranges as they are loaded.
+    pub fn restore_handover_state(&self, state: &ProtoHandoverState) -> Result<(), String> {
+        let soft_states = handover::soft_states_from_proto(state)?;
+        *self.handed_over.lock().unwrap() = soft_states;
+        Ok(())
+    }
+
+    async fn tx_state_store(&self) -> Arc<TxStateStoreClient> {
+        self.tx_state_store.client().await
+    }
+
+    // Delivers the outcome of a transaction prepared on the range the way its
+    // coordinator would have: a commit at `commit_epoch`, or an abort.
+    async fn apply_decision(
+        &self,
+        range_manager: &RangeManager<S, InMemoryWal>,
+        range_id: &FullRangeId,
+        tx_id: Uuid,
+        commit_epoch: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut fbb = FlatBufferBuilder::new();
+        let request_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(Uuid::new_v4()),
+        ));
+        let transaction_id = Some(Uuidu128::create(
+            &mut fbb,
+            &util::flatbuf::serialize_uuid(tx_id),
+        ));
+        let range_id = Some(util::flatbuf::serialize_range_id(&mut fbb, range_id));
+        match commit_epoch {
+            Some(epoch) => {
+                let root = CommitRequest::create(
+                    &mut fbb,
+                    &CommitRequestArgs {
+                        request_id,
+                        transaction_id,
+                        range_id,
+                        epoch,
+                        vid: 0,
+                    },
+                );
+                fbb.finish(root, None);
+                let commit = flatbuffers::root::<CommitRequest>(fbb.finished_data()).unwrap();
+                range_manager.commit(tx_id, commit).await
+            }
+            None => {
+                let root = AbortRequest::create(
+                    &mut fbb,
+                    &AbortRequestArgs {
+                        request_id,
+                        transaction_id,
+                        range_id,
+                    },
+                );
+                fbb.finish(root, None);
+                let abort = flatbuffers::root::<AbortRequest>(fbb.finished_data()).unwrap();
+                range_manager.abort(tx_id, abort).await
+            }
+        }
+    }
+
+    // Looks up the transactions prepared on the range for at least
+    // `older_than` in the tx_state_store, and unless `dry_run`, applies their
+    // decision to the range. Undecided ones get aborted.
+    async fn resolve_orphaned_prepares(
+        &self,
+        range_manager: &RangeManager<S, InMemoryWal>,
+        range_id: &FullRangeId,
+        older_than: Duration,
+        dry_run: bool,
+    ) -> Result<Vec<OrphanedPrepare>, TStatus> {
+        let in_flight = range_manager
+            .list_in_flight_transactions()
+            .await
+            .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+
+        let now = self.clock.now();
+        let tx_state_store = self.tx_state_store().await;
+        let mut prepares = Vec::new();
+        for tx in in_flight {
+            let Some(prepared_at) = tx.prepared_at else {
+                continue;
+            };
+            let age = (now - prepared_at).to_std().unwrap_or(Duration::ZERO);
+            if age < older_than {
+                continue;
+            }
+            // Aborting decides undecided transactions for good, so their
+            // coordinators can no longer commit them.
+            let outcome = if dry_run {
+                tx_state_store.get_transaction_outcome(tx.id).await
+            } else {
+                tx_state_store.try_abort_transaction(tx.id).await.map(Some)
+            }
+            .map_err(|e| TStatus::unavailable(format!("{:?}", e)))?;
+            let (outcome, commit_epoch) = match outcome {
+                None => (ProtoTransactionOutcome::Undecided, None),
+                Some(OpResult::TransactionIsAborted) => (ProtoTransactionOutcome::Aborted, None),
+                Some(OpResult::TransactionIsCommitted(info)) => {
+                    (ProtoTransactionOutcome::Committed, Some(info.epoch))
+                }
+            };
+            if !dry_run {
+                self.apply_decision(range_manager, range_id, tx.id, commit_epoch)
+                    .await
+                    .map_err(|e| TStatus::failed_precondition(format!("{:?}", e)))?;
+                info!(
+                    "Resolved transaction {} left prepared on range {:?} for {:?}: {:?}",
+                    tx.id, range_id, age, outcome
+                );
+            }
+            prepares.push(OrphanedPrepare {
+                transaction_id: tx.id.to_string(),
+                prepared_age_us: age.as_micros() as u64,
+                outcome: outcome.into(),
+                commit_epoch,
+            });
+        }
+        Ok(prepares)
+    }
+
     async fn maybe_start_transaction(&self, id: Uuid, info: Option<FlatbufTransactionInfo<'_>>) {
         let info = match info {
             None => return,
@@ -144,13 +1093,23 @@ where
         if (*tx_table).contains_key(&id) {
             return;
         };
+        tx_table.insert(id, Arc::new(self.transaction_info(id, info)));
+    }
+
+    fn transaction_info(&self, id: Uuid, info: FlatbufTransactionInfo<'_>) -> TransactionInfo {
         let overall_timeout = core::time::Duration::from_micros(info.overall_timeout_us() as u64);
-        let tx_info = Arc::new(TransactionInfo {
+        // Older clients don't send it. Only the start of the first attempt is
+        // sent, which is all range servers need.
+        let started = util::flatbuf::deserialize_started(&info).unwrap_or_else(|| self.clock.now());
+        TransactionInfo {
             id,
-            started: chrono::Utc::now(), // TODO: Should be set by the client instead.
+            started,
+            first_started: started,
             overall_timeout,
-        });
-        tx_table.insert(id, tx_info);
+            labels: util::flatbuf::deserialize_labels(&info),
+            isolation: util::flatbuf::deserialize_isolation(&info),
+            snapshot_epoch: util::flatbuf::deserialize_snapshot_epoch(&info),
+        }
     }
 
     async fn get_transaction_info(&self, id: Uuid) -> Result<Arc<TransactionInfo>, Error> {
@@ -161,6 +1120,21 @@ where
         }
     }
 
+    async fn read_mode(&self, range_id: &FullRangeId, tx: &TransactionInfo) -> ReadMode {
+        // Ranges reject snapshot transactions without an epoch.
+        if let (IsolationLevel::Snapshot, Some(epoch)) = (tx.isolation, tx.snapshot_epoch) {
+            ReadMode::AtEpoch(epoch)
+        } else if self
+            .keyspace_flags
+            .has_optimistic_reads(range_id.keyspace_id)
+            .await
+        {
+            ReadMode::Optimistic
+        } else {
+            ReadMode::Locking
+        }
+    }
+
     async fn remove_transaction(&self, id: Uuid) {
         let mut tx_table = self.transaction_table.write().await;
         (*tx_table).remove(&id);
@@ -177,6 +1151,49 @@ where
         }
     }
 
+    // Lets the transactions running on a range the warden moved away finish
+    // before unloading it, then tells the warden so that the next owner can
+    // load it. Whatever is still prepared when the drain times out is
+    // recovered from storage by the next owner. If the warden assigned the
+    // range back in the meantime, the move was called off and the range
+    // stays.
+    async fn hand_off_range(&self, id: &FullRangeId) {
+        let rm = {
+            let range_table = self.loaded_ranges.read().await;
+            range_table.get(&id.range_id).cloned()
+        };
+        if let Some(rm) = rm {
+            let timeout = self
+                .config
+                .range_server
+                .handoff_drain_timeout
+                .unwrap_or(DEFAULT_HANDOFF_DRAIN_TIMEOUT);
+            match rm.drain(timeout).await {
+                Ok(outcome) if outcome.drained => {
+                    info!(range_id = ?id, "drained range before handing it off")
+                }
+                Ok(outcome) => info!(
+                    range_id = ?id,
+                    prepared = outcome.prepared,
+                    unprepared = outcome.unprepared,
+                    "range did not drain in time, handing it off anyway"
+                ),
+                Err(e) => warn!(range_id = ?id, "failed to drain range: {:?}", e),
+            }
+            if self.warden_handler.is_assigned(id).await {
+                if let Err(e) = rm.cancel_drain().await {
+                    warn!(range_id = ?id, "failed to cancel range drain: {:?}", e);
+                }
+                info!(range_id = ?id, "range assigned back while draining, keeping it");
+                return;
+            }
+        }
+        self.maybe_unload_range(id).await;
+        if let Err(e) = self.warden_handler.report_range_handed_off(id).await {
+            warn!(range_id = ?id, "failed to report range handoff: {:?}", e);
+        }
+    }
+
     async fn maybe_load_and_get_range_inner(
         &self,
         id: &FullRangeId,
@@ -210,10 +1227,17 @@ where
                         InMemoryWal::new(),
                         self.prefetching_buffer.clone(),
                         self.bg_runtime.clone(),
+                        self.range_fault_sender.clone(),
+                        self.clock.clone(),
+                        self.tx_state_store.clone(),
                     );
                     (range_table).insert(id.range_id, rm.clone());
                     drop(range_table);
                     rm.load().await?;
+                    let soft_state = self.handed_over.lock().unwrap().remove(&id.range_id);
+                    if let Some(soft_state) = soft_state {
+                        rm.restore_soft_state(soft_state).await?;
+                    }
                     rm.clone()
                 }
             }
@@ -286,10 +1310,30 @@ where
         if request.request_id().is_none() {
             return Err(Error::InvalidRequestFormat);
         }
-        self.maybe_start_transaction(transaction_id, request.transaction_info())
-            .await;
+        let (tx, mode) = match request.snapshot_epoch() {
+            0 => {
+                self.maybe_start_transaction(transaction_id, request.transaction_info())
+                    .await;
+                let tx = self.get_transaction_info(transaction_id).await?;
+                let mode = if request.for_update() {
+                    ReadMode::Locking
+                } else {
+                    self.read_mode(&range_id, &tx).await
+                };
+                (tx, mode)
+            }
+            // Reads at an epoch leave nothing to clean up on the range, and
+            // the range takes no part in the commit of the transaction, so it
+            // is not kept track of.
+            epoch => {
+                let info = request
+                    .transaction_info()
+                    .ok_or(Error::InvalidRequestFormat)?;
+                let tx = Arc::new(self.transaction_info(transaction_id, info));
+                (tx, ReadMode::AtEpoch(epoch))
+            }
+        };
         let rm = self.maybe_load_and_get_range(&range_id).await?;
-        let tx = self.get_transaction_info(transaction_id).await?;
         let mut leader_sequence_number: i64 = constants::UNSET_LEADER_SEQUENCE_NUMBER;
         let mut reads = Vec::new();
 
@@ -299,7 +1343,7 @@ where
             for key in key.iter() {
                 // TODO: too much copying :(
                 let key = Bytes::copy_from_slice(key.k().unwrap().bytes());
-                let get_result = rm.get(tx.clone(), key.clone()).await?;
+                let get_result = rm.get(tx.clone(), key.clone(), mode).await?;
                 match get_result.val {
                     None => {
                         reads.push((key, None));
@@ -319,75 +1363,580 @@ where
                 }
             }
         }
-        Ok((leader_sequence_number, reads))
+        // The shadow reads the latest values, not those as of an epoch.
+        if let Some(mirror) = &self.mirror {
+            if !matches!(mode, ReadMode::AtEpoch(_)) {
+                mirror.maybe_mirror(range_id, &reads);
+            }
+        }
+        Ok((leader_sequence_number, reads))
+    }
+
+    async fn get(
+        &self,
+        network: Arc<dyn FastNetwork>,
+        sender: SocketAddr,
+        request: GetRequest<'_>,
+    ) -> Result<(), DynamicErr> {
+        let mut fbb = FlatBufferBuilder::new();
+        let fbb_root = match request.request_id() {
+            None => GetResponse::create(
+                &mut fbb,
+                &GetResponseArgs {
+                    request_id: None,
+                    status: Status::InvalidRequestFormat,
+                    leader_sequence_number: 0,
+                    records: None,
+                },
+            ),
+            Some(req_id) => {
+                let request_id = util::flatbuf::deserialize_uuid(req_id);
+                let read_result = self.get_inner(request).await;
+
+                // Construct the response
+                let mut records_vector = Vec::new();
+                let (status, leader_sequence_number) = match read_result {
+                    Err(e) => (e.to_flatbuf_status(), -1),
+                    Ok((leader_sequence_number, reads)) => {
+                        for (k, v) in reads {
+                            let k = Some(fbb.create_vector(k.to_vec().as_slice()));
+                            let key = Key::create(&mut fbb, &KeyArgs { k });
+                            let value = v.map(|v| fbb.create_vector(v.to_vec().as_slice()));
+                            records_vector.push(Record::create(
+                                &mut fbb,
+                                &RecordArgs {
+                                    key: Some(key),
+                                    value,
+                                    ..Default::default()
+                                },
+                            ));
+                        }
+                        (Status::Ok, leader_sequence_number)
+                    }
+                };
+                let records = Some(fbb.create_vector(&records_vector));
+                let request_id = Some(Uuidu128::create(
+                    &mut fbb,
+                    &util::flatbuf::serialize_uuid(request_id),
+                ));
+                GetResponse::create(
+                    &mut fbb,
+                    &GetResponseArgs {
+                        request_id,
+                        status,
+                        leader_sequence_number,
+                        records,
+                    },
+                )
+            }
+        };
+
+        fbb.finish(fbb_root, None);
+        self.send_response(network, sender, MessageType::Get, fbb.finished_data())?;
+        Ok(())
+    }
+
+    async fn scan_inner(
+        &self,
+        request: ScanRequest<'_>,
+    ) -> Result<crate::range_manager::ScanResult, Error> {
+        let limit = match request.limit() {
+            0 => None,
+            limit => Some(limit as usize),
+        };
+        self.scan_range(
+            request.range_id(),
+            request.transaction_id(),
+            request.transaction_info(),
+            Self::key_range(
+                request.lower_bound_inclusive(),
+                request.upper_bound_exclusive(),
+            ),
+            limit,
+        )
+        .await
+    }
+
+    fn key_range(
+        lower_bound_inclusive: Option<Key<'_>>,
+        upper_bound_exclusive: Option<Key<'_>>,
+    ) -> KeyRange {
+        let bound =
+            |key: Option<Key<'_>>| key.map(|k| Bytes::copy_from_slice(k.k().unwrap().bytes()));
+        KeyRange {
+            lower_bound_inclusive: bound(lower_bound_inclusive),
+            upper_bound_exclusive: bound(upper_bound_exclusive),
+        }
+    }
+
+    // Scans the range for the fields shared by ScanRequest, ScanChunkRequest
+    // and GetPrefixRequest.
+    async fn scan_range(
+        &self,
+        range_id: Option<RangeId<'_>>,
+        transaction_id: Option<Uuidu128<'_>>,
+        transaction_info: Option<FlatbufTransactionInfo<'_>>,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<crate::range_manager::ScanResult, Error> {
+        let range_id = match range_id {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => id,
+        };
+        let range_id = match util::flatbuf::deserialize_range_id(&range_id) {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => id,
+        };
+        let transaction_id = match transaction_id {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => util::flatbuf::deserialize_uuid(id),
+        };
+        self.maybe_start_transaction(transaction_id, transaction_info)
+            .await;
+        let rm = self.maybe_load_and_get_range(&range_id).await?;
+        let tx = self.get_transaction_info(transaction_id).await?;
+        let mode = self.read_mode(&range_id, &tx).await;
+        rm.scan(tx, key_range, limit, mode).await
+    }
+
+    async fn get_prefix_inner(
+        &self,
+        request: GetPrefixRequest<'_>,
+    ) -> Result<crate::range_manager::ScanResult, Error> {
+        let prefix = match request.prefix() {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(prefix) => Bytes::copy_from_slice(prefix.bytes()),
+        };
+        let limit = match request.limit() {
+            0 => None,
+            limit => Some(limit as usize),
+        };
+        self.scan_range(
+            request.range_id(),
+            request.transaction_id(),
+            request.transaction_info(),
+            KeyRange::with_prefix(prefix),
+            limit,
+        )
+        .await
+    }
+
+    async fn get_prefix(
+        &self,
+        network: Arc<dyn FastNetwork>,
+        sender: SocketAddr,
+        request: GetPrefixRequest<'_>,
+    ) -> Result<(), DynamicErr> {
+        let mut fbb = FlatBufferBuilder::new();
+        let fbb_root = match request.request_id() {
+            None => GetPrefixResponse::create(
+                &mut fbb,
+                &GetPrefixResponseArgs {
+                    request_id: None,
+                    status: Status::InvalidRequestFormat,
+                    leader_sequence_number: 0,
+                    records: None,
+                },
+            ),
+            Some(req_id) => {
+                let request_id = util::flatbuf::deserialize_uuid(req_id);
+                let (status, leader_sequence_number, records) =
+                    match self.get_prefix_inner(request).await {
+                        Err(e) => (e.to_flatbuf_status(), -1, None),
+                        Ok(result) => (
+                            Status::Ok,
+                            result.leader_sequence_number,
+                            Some(Self::create_records(&mut fbb, result.records)),
+                        ),
+                    };
+                let request_id = Some(Uuidu128::create(
+                    &mut fbb,
+                    &util::flatbuf::serialize_uuid(request_id),
+                ));
+                GetPrefixResponse::create(
+                    &mut fbb,
+                    &GetPrefixResponseArgs {
+                        request_id,
+                        status,
+                        leader_sequence_number,
+                        records,
+                    },
+                )
+            }
+        };
+
+        fbb.finish(fbb_root, None);
+        self.send_response(network, sender, MessageType::GetPrefix, fbb.finished_data())?;
+        Ok(())
+    }
+
+    // Returns the records of the chunk, and whether they are the last ones
+    // within the bounds.
+    async fn scan_chunk_inner(
+        &self,
+        request: ScanChunkRequest<'_>,
+    ) -> Result<(crate::range_manager::ScanResult, bool), Error> {
+        let chunk_size = match request.chunk_size() {
+            0 => return Err(Error::InvalidRequestFormat),
+            chunk_size => chunk_size as usize,
+        };
+        // Reads one record past the chunk to tell whether it is the last.
+        let mut result = self
+            .scan_range(
+                request.range_id(),
+                request.transaction_id(),
+                request.transaction_info(),
+                Self::key_range(
+                    request.lower_bound_inclusive(),
+                    request.upper_bound_exclusive(),
+                ),
+                Some(chunk_size + 1),
+            )
+            .await?;
+        let done = result.records.len() <= chunk_size;
+        result.records.truncate(chunk_size);
+        Ok((result, done))
+    }
+
+    async fn scan_chunk(
+        &self,
+        network: Arc<dyn FastNetwork>,
+        sender: SocketAddr,
+        request: ScanChunkRequest<'_>,
+    ) -> Result<(), DynamicErr> {
+        let mut fbb = FlatBufferBuilder::new();
+        let fbb_root = match request.request_id() {
+            None => ScanChunkResponse::create(
+                &mut fbb,
+                &ScanChunkResponseArgs {
+                    request_id: None,
+                    status: Status::InvalidRequestFormat,
+                    leader_sequence_number: 0,
+                    records: None,
+                    done: false,
+                },
+            ),
+            Some(req_id) => {
+                let request_id = util::flatbuf::deserialize_uuid(req_id);
+                let (status, leader_sequence_number, records, done) =
+                    match self.scan_chunk_inner(request).await {
+                        Err(e) => (e.to_flatbuf_status(), -1, None, false),
+                        Ok((result, done)) => {
+                            let records = Self::create_records(&mut fbb, result.records);
+                            (
+                                Status::Ok,
+                                result.leader_sequence_number,
+                                Some(records),
+                                done,
+                            )
+                        }
+                    };
+                let request_id = Some(Uuidu128::create(
+                    &mut fbb,
+                    &util::flatbuf::serialize_uuid(request_id),
+                ));
+                ScanChunkResponse::create(
+                    &mut fbb,
+                    &ScanChunkResponseArgs {
+                        request_id,
+                        status,
+                        leader_sequence_number,
+                        records,
+                        done,
+                    },
+                )
+            }
+        };
+
+        fbb.finish(fbb_root, None);
+        self.send_response(network, sender, MessageType::ScanChunk, fbb.finished_data())?;
+        Ok(())
+    }
+
+    fn create_records<'a>(
+        fbb: &mut FlatBufferBuilder<'a>,
+        records: Vec<(Bytes, Bytes)>,
+    ) -> WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Record<'a>>>> {
+        let mut records_vector = Vec::new();
+        for (k, v) in records {
+            let k = Some(fbb.create_vector(k.to_vec().as_slice()));
+            let key = Key::create(fbb, &KeyArgs { k });
+            let value = Some(fbb.create_vector(v.to_vec().as_slice()));
+            records_vector.push(Record::create(
+                fbb,
+                &RecordArgs {
+                    key: Some(key),
+                    value,
+                    ..Default::default()
+                },
+            ));
+        }
+        fbb.create_vector(&records_vector)
+    }
+
+    async fn scan(
+        &self,
+        network: Arc<dyn FastNetwork>,
+        sender: SocketAddr,
+        request: ScanRequest<'_>,
+    ) -> Result<(), DynamicErr> {
+        let mut fbb = FlatBufferBuilder::new();
+        let fbb_root = match request.request_id() {
+            None => ScanResponse::create(
+                &mut fbb,
+                &ScanResponseArgs {
+                    request_id: None,
+                    status: Status::InvalidRequestFormat,
+                    leader_sequence_number: 0,
+                    records: None,
+                },
+            ),
+            Some(req_id) => {
+                let request_id = util::flatbuf::deserialize_uuid(req_id);
+                let scan_result = self.scan_inner(request).await;
+                let (status, leader_sequence_number, records) = match scan_result {
+                    Err(e) => (
+                        e.to_flatbuf_status(),
+                        -1,
+                        Self::create_records(&mut fbb, Vec::new()),
+                    ),
+                    Ok(result) => (
+                        Status::Ok,
+                        result.leader_sequence_number,
+                        Self::create_records(&mut fbb, result.records),
+                    ),
+                };
+                let records = Some(records);
+                let request_id = Some(Uuidu128::create(
+                    &mut fbb,
+                    &util::flatbuf::serialize_uuid(request_id),
+                ));
+                ScanResponse::create(
+                    &mut fbb,
+                    &ScanResponseArgs {
+                        request_id,
+                        status,
+                        leader_sequence_number,
+                        records,
+                    },
+                )
+            }
+        };
+
+        fbb.finish(fbb_root, None);
+        self.send_response(network, sender, MessageType::Scan, fbb.finished_data())?;
+        Ok(())
+    }
+
+    async fn prepare_inner(
+        &self,
+        request: PrepareRequest<'_>,
+    ) -> Result<crate::range_manager::PrepareResult, Error> {
+        let range_id = match request.range_id() {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => id,
+        };
+        let range_id = match util::flatbuf::deserialize_range_id(&range_id) {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => id,
+        };
+        let transaction_id = match request.transaction_id() {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => util::flatbuf::deserialize_uuid(id),
+        };
+        // The transaction may have resolved the keyspace before it was
+        // dropped, in which case the range is a zombie that must not take
+        // any more writes.
+        if !self.keyspace_flags.exists(range_id.keyspace_id).await {
+            return Err(Error::KeyspaceDoesNotExist);
+        }
+        let has_writes = request.puts().is_some_and(|p| !p.is_empty())
+            || request.deletes().is_some_and(|d| !d.is_empty())
+            || request.increments().is_some_and(|i| !i.is_empty());
+        if has_writes && self.keyspace_flags.is_read_only(range_id.keyspace_id).await {
+            return Err(Error::KeyspaceIsReadOnly);
+        }
+        // Checked before anything is written to the WAL, so rejected writes
+        // never become durable.
+        if has_writes {
+            if let Some(policy) = self
+                .keyspace_flags
+                .validation_policy(range_id.keyspace_id)
+                .await
+            {
+                if let Err(reason) = policy.check_prepare(&request) {
+                    warn!(
+                        "Rejecting prepare of transaction {} on range {:?}: {}",
+                        transaction_id, range_id, reason
+                    );
+                    return Err(Error::WriteRejected);
+                }
+            }
+        }
+        self.maybe_start_transaction(transaction_id, request.transaction_info())
+            .await;
+        let rm = self.maybe_load_and_get_range(&range_id).await?;
+        let tx = self.get_transaction_info(transaction_id).await?;
+        rm.prepare(tx.clone(), request).await
+    }
+
+    async fn prepare(
+        &self,
+        network: Arc<dyn FastNetwork>,
+        sender: SocketAddr,
+        request: PrepareRequest<'_>,
+    ) -> Result<(), DynamicErr> {
+        let mut fbb = FlatBufferBuilder::new();
+        let fbb_root = match request.request_id() {
+            None => PrepareResponse::create(
+                &mut fbb,
+                &PrepareResponseArgs {
+                    request_id: None,
+                    status: Status::InvalidRequestFormat,
+                    epoch_lease: None,
+                    highest_known_epoch: 0,
+                    retry_after_us: 0,
+                },
+            ),
+            Some(req_id) => {
+                let request_id = util::flatbuf::deserialize_uuid(req_id);
+
+                let prepare_result = self.prepare_inner(request).await;
+
+                // Construct the response.
+                let retry_after_us = match &prepare_result {
+                    Err(Error::PrepareBacklogFull { retry_after })
+                    | Err(Error::WriteStalled { retry_after }) => retry_after.as_micros() as u64,
+                    _ => 0,
+                };
+                let (status, epoch_lease, highest_known_epoch) = match prepare_result {
+                    Err(e) => (e.to_flatbuf_status(), None, 0),
+                    Ok(prepare_result) => {
+                        let epoch_lease = Some(EpochLease::create(
+                            &mut fbb,
+                            &EpochLeaseArgs {
+                                lower_bound_inclusive: prepare_result.epoch_lease.0,
+                                upper_bound_inclusive: prepare_result.epoch_lease.1,
+                            },
+                        ));
+                        (Status::Ok, epoch_lease, prepare_result.highest_known_epoch)
+                    }
+                };
+                let request_id = Some(Uuidu128::create(
+                    &mut fbb,
+                    &util::flatbuf::serialize_uuid(request_id),
+                ));
+                PrepareResponse::create(
+                    &mut fbb,
+                    &PrepareResponseArgs {
+                        request_id,
+                        status,
+                        epoch_lease,
+                        highest_known_epoch,
+                        retry_after_us,
+                    },
+                )
+            }
+        };
+
+        fbb.finish(fbb_root, None);
+        self.send_response(network, sender, MessageType::Prepare, fbb.finished_data())?;
+        Ok(())
+    }
+
+    async fn validate_inner(&self, request: ValidateRequest<'_>) -> Result<i64, Error> {
+        let range_id = match request.range_id() {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => id,
+        };
+        let range_id = match util::flatbuf::deserialize_range_id(&range_id) {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => id,
+        };
+        let transaction_id = match request.transaction_id() {
+            None => return Err(Error::InvalidRequestFormat),
+            Some(id) => util::flatbuf::deserialize_uuid(id),
+        };
+        // Same keyspace checks as prepare_inner.
+        if !self.keyspace_flags.exists(range_id.keyspace_id).await {
+            return Err(Error::KeyspaceDoesNotExist);
+        }
+        if request.has_writes() && self.keyspace_flags.is_read_only(range_id.keyspace_id).await {
+            return Err(Error::KeyspaceIsReadOnly);
+        }
+        let tx = match self.get_transaction_info(transaction_id).await {
+            Ok(tx) => tx,
+            // The server never served a read of the transaction, e.g. one
+            // that only wrote to the range. Only its id is needed then.
+            Err(_) => {
+                let started = self.clock.now();
+                Arc::new(TransactionInfo {
+                    id: transaction_id,
+                    started,
+                    first_started: started,
+                    overall_timeout: Duration::ZERO,
+                    labels: Default::default(),
+                    isolation: Default::default(),
+                    snapshot_epoch: None,
+                })
+            }
+        };
+        let rm = self.maybe_load_and_get_range(&range_id).await?;
+        rm.validate(tx, request.has_reads()).await
     }
 
-    async fn get(
+    async fn validate(
         &self,
         network: Arc<dyn FastNetwork>,
         sender: SocketAddr,
-        request: GetRequest<'_>,
+        request: ValidateRequest<'_>,
     ) -> Result<(), DynamicErr> {
         let mut fbb = FlatBufferBuilder::new();
         let fbb_root = match request.request_id() {
-            None => GetResponse::create(
+            None => ValidateResponse::create(
                 &mut fbb,
-                &GetResponseArgs {
+                &ValidateResponseArgs {
                     request_id: None,
                     status: Status::InvalidRequestFormat,
                     leader_sequence_number: 0,
-                    records: None,
+                    retry_after_us: 0,
                 },
             ),
             Some(req_id) => {
                 let request_id = util::flatbuf::deserialize_uuid(req_id);
-                let read_result = self.get_inner(request).await;
-
-                // Construct the response
-                let mut records_vector = Vec::new();
-                let (status, leader_sequence_number) = match read_result {
-                    Err(e) => (e.to_flatbuf_status(), -1),
-                    Ok((leader_sequence_number, reads)) => {
-                        for (k, v) in reads {
-                            let k = Some(fbb.create_vector(k.to_vec().as_slice()));
-                            let key = Key::create(&mut fbb, &KeyArgs { k });
-                            let value = v.map(|v| fbb.create_vector(v.to_vec().as_slice()));
-                            records_vector.push(Record::create(
-                                &mut fbb,
-                                &RecordArgs {
-                                    key: Some(key),
-                                    value,
-                                },
-                            ));
-                        }
-                        (Status::Ok, leader_sequence_number)
-                    }
-                };
-                let records = Some(fbb.create_vector(&records_vector));
+                let (status, leader_sequence_number, retry_after_us) =
+                    match self.validate_inner(request).await {
+                        Ok(leader_sequence_number) => (Status::Ok, leader_sequence_number, 0),
+                        Err(Error::PrepareBacklogFull { retry_after }) => (
+                            Status::PrepareBacklogFull,
+                            0,
+                            retry_after.as_micros() as u64,
+                        ),
+                        Err(e) => (e.to_flatbuf_status(), 0, 0),
+                    };
                 let request_id = Some(Uuidu128::create(
                     &mut fbb,
                     &util::flatbuf::serialize_uuid(request_id),
                 ));
-                GetResponse::create(
+                ValidateResponse::create(
                     &mut fbb,
-                    &GetResponseArgs {
+                    &ValidateResponseArgs {
                         request_id,
                         status,
                         leader_sequence_number,
-                        records,
+                        retry_after_us,
                     },
                 )
             }
         };
-
         fbb.finish(fbb_root, None);
-        self.send_response(network, sender, MessageType::Get, fbb.finished_data())?;
+        self.send_response(network, sender, MessageType::Validate, fbb.finished_data())?;
         Ok(())
     }
 
-    async fn prepare_inner(
+    async fn extend_epoch_lease_inner(
         &self,
-        request: PrepareRequest<'_>,
+        request: ExtendEpochLeaseRequest<'_>,
     ) -> Result<crate::range_manager::PrepareResult, Error> {
         let range_id = match request.range_id() {
             None => return Err(Error::InvalidRequestFormat),
@@ -402,64 +1951,148 @@ where
             Some(id) => util::flatbuf::deserialize_uuid(id),
         };
         let rm = self.maybe_load_and_get_range(&range_id).await?;
-        let tx = self.get_transaction_info(transaction_id).await?;
-        rm.prepare(tx.clone(), request).await
+        rm.extend_epoch_lease(transaction_id, request.min_upper_bound())
+            .await
     }
 
-    async fn prepare(
+    async fn extend_epoch_lease(
         &self,
         network: Arc<dyn FastNetwork>,
         sender: SocketAddr,
-        request: PrepareRequest<'_>,
+        request: ExtendEpochLeaseRequest<'_>,
     ) -> Result<(), DynamicErr> {
         let mut fbb = FlatBufferBuilder::new();
         let fbb_root = match request.request_id() {
-            None => PrepareResponse::create(
+            None => ExtendEpochLeaseResponse::create(
                 &mut fbb,
-                &PrepareResponseArgs {
+                &ExtendEpochLeaseResponseArgs {
                     request_id: None,
                     status: Status::InvalidRequestFormat,
-                    epoch_lease: None,
                     highest_known_epoch: 0,
+                    epoch_lease: None,
                 },
             ),
             Some(req_id) => {
                 let request_id = util::flatbuf::deserialize_uuid(req_id);
-
-                let prepare_result = self.prepare_inner(request).await;
-
-                // Construct the response.
-                let (status, epoch_lease, highest_known_epoch) = match prepare_result {
-                    Err(e) => (e.to_flatbuf_status(), None, 0),
-                    Ok(prepare_result) => {
-                        let epoch_lease = Some(EpochLease::create(
-                            &mut fbb,
-                            &EpochLeaseArgs {
-                                lower_bound_inclusive: prepare_result.epoch_lease.0,
-                                upper_bound_inclusive: prepare_result.epoch_lease.1,
-                            },
-                        ));
-                        (Status::Ok, epoch_lease, prepare_result.highest_known_epoch)
-                    }
-                };
+                let (status, highest_known_epoch, epoch_lease) =
+                    match self.extend_epoch_lease_inner(request).await {
+                        Ok(result) => {
+                            let epoch_lease = EpochLease::create(
+                                &mut fbb,
+                                &EpochLeaseArgs {
+                                    lower_bound_inclusive: result.epoch_lease.0,
+                                    upper_bound_inclusive: result.epoch_lease.1,
+                                },
+                            );
+                            (Status::Ok, result.highest_known_epoch, Some(epoch_lease))
+                        }
+                        Err(e) => (e.to_flatbuf_status(), 0, None),
+                    };
                 let request_id = Some(Uuidu128::create(
                     &mut fbb,
                     &util::flatbuf::serialize_uuid(request_id),
                 ));
-                PrepareResponse::create(
+                ExtendEpochLeaseResponse::create(
                     &mut fbb,
-                    &PrepareResponseArgs {
+                    &ExtendEpochLeaseResponseArgs {
                         request_id,
                         status,
-                        epoch_lease,
                         highest_known_epoch,
+                        epoch_lease,
                     },
                 )
             }
         };
+        fbb.finish(fbb_root, None);
+        self.send_response(
+            network,
+            sender,
+            MessageType::ExtendEpochLease,
+            fbb.finished_data(),
+        )?;
+        Ok(())
+    }
+
+    // Stats only exist on a loaded range, so this never loads it.
+    async fn conflict_stats_inner(
+        &self,
+        range_id: &FullRangeId,
+    ) -> Result<conflict_stats::ConflictStats, Error> {
+        let range_manager = {
+            let range_table = self.loaded_ranges.read().await;
+            range_table.get(&range_id.range_id).cloned()
+        }
+        .ok_or(Error::RangeIsNotLoaded)?;
+        range_manager.conflict_stats().await
+    }
 
+    async fn get_conflict_stats(
+        &self,
+        network: Arc<dyn FastNetwork>,
+        sender: SocketAddr,
+        request: GetConflictStatsRequest<'_>,
+    ) -> Result<(), DynamicErr> {
+        let mut fbb = FlatBufferBuilder::new();
+        let stats = match request.range_id() {
+            None => Err(Error::InvalidRequestFormat),
+            Some(id) => match util::flatbuf::deserialize_range_id(&id) {
+                None => Err(Error::InvalidRequestFormat),
+                Some(id) => self.conflict_stats_inner(&id).await,
+            },
+        };
+        let request_id = request.request_id().map(|id| {
+            Uuidu128::create(
+                &mut fbb,
+                &util::flatbuf::serialize_uuid(util::flatbuf::deserialize_uuid(id)),
+            )
+        });
+        let (status, stats) = match stats {
+            Ok(stats) => (Status::Ok, stats),
+            Err(e) => (
+                e.to_flatbuf_status(),
+                conflict_stats::ConflictStats::default(),
+            ),
+        };
+        let other = Some(conflict_counts_to_flatbuf(&mut fbb, &stats.other));
+        let prefixes: Vec<_> = stats
+            .prefixes
+            .iter()
+            .map(|(prefix, c)| {
+                let prefix = Some(fbb.create_vector(prefix));
+                let counts = Some(conflict_counts_to_flatbuf(&mut fbb, c));
+                PrefixConflicts::create(&mut fbb, &PrefixConflictsArgs { prefix, counts })
+            })
+            .collect();
+        let prefixes = Some(fbb.create_vector(&prefixes));
+        let labels: Vec<_> = stats
+            .labels
+            .iter()
+            .map(|((key, value), c)| {
+                let key = Some(fbb.create_string(key));
+                let value = Some(fbb.create_string(value));
+                let label = Some(Label::create(&mut fbb, &LabelArgs { key, value }));
+                let counts = Some(conflict_counts_to_flatbuf(&mut fbb, c));
+                LabelConflicts::create(&mut fbb, &LabelConflictsArgs { label, counts })
+            })
+            .collect();
+        let labels = Some(fbb.create_vector(&labels));
+        let fbb_root = GetConflictStatsResponse::create(
+            &mut fbb,
+            &GetConflictStatsResponseArgs {
+                request_id,
+                status,
+                prefixes,
+                other,
+                labels,
+            },
+        );
         fbb.finish(fbb_root, None);
-        self.send_response(network, sender, MessageType::Prepare, fbb.finished_data())?;
+        self.send_response(
+            network,
+            sender,
+            MessageType::GetConflictStats,
+            fbb.finished_data(),
+        )?;
         Ok(())
     }
 
@@ -592,12 +2225,22 @@ where
                                     let server = server.clone();
                                     tokio::spawn (async move
                                         {
-                                            // TODO: handle errors here
-                                            server.maybe_load_and_get_range(&id).await
+                                            // The range is loaded again by the
+                                            // next request for it.
+                                            match server.maybe_load_and_get_range(&id).await {
+                                                // Calls off a drain in case the
+                                                // range was being moved away.
+                                                Ok(rm) => {
+                                                    let _ = rm.cancel_drain().await;
+                                                }
+                                                Err(e) => warn!(range_id = ?id, "failed to load assigned range: {:?}", e),
+                                            }
                                         });
                                 }
                                 crate::warden_handler::WardenUpdate::UnloadRange(id) => {
-                                    server.maybe_unload_range(id).await
+                                    let id = *id;
+                                    let server = server.clone();
+                                    tokio::spawn(async move { server.hand_off_range(&id).await });
                                 }
                             }
                         }
@@ -608,6 +2251,227 @@ where
         }
     }
 
+    async fn range_fault_loop(
+        server: Arc<Self>,
+        mut receiver: UnboundedReceiver<FullRangeId>,
+        cancellation_token: CancellationToken,
+    ) -> Result<(), DynamicErr> {
+        loop {
+            tokio::select! {
+                () = cancellation_token.cancelled() => return Ok(()),
+                maybe_range = receiver.recv() => {
+                    match maybe_range {
+                        None => return Err("range fault channel closed!".into()),
+                        Some(id) => {
+                            let server = server.clone();
+                            tokio::spawn(async move { server.recover_faulted_range(id).await });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Verifies the checksums of every loaded range each `interval`, one
+    /// range at a time.
+    async fn checksum_verification_loop(
+        server: Arc<Self>,
+        interval: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        let max_divergent_keys = server
+            .config
+            .range_server
+            .checksum_verification
+            .max_divergent_keys;
+        loop {
+            tokio::select! {
+                () = cancellation_token.cancelled() => return,
+                () = server.clock.sleep(interval) => {}
+            }
+            let ranges: Vec<_> = server
+                .loaded_ranges
+                .read()
+                .await
+                .values()
+                .cloned()
+                .collect();
+            for rm in ranges {
+                match rm.verify_checksums(max_divergent_keys).await {
+                    Ok(report) => log_checksum_report(rm.range_id(), &report),
+                    // Unloaded since we listed it.
+                    Err(Error::RangeIsNotLoaded) => {}
+                    Err(e) => warn!(
+                        range_id = ?rm.range_id(),
+                        "Failed to verify range checksums: {:?}", e
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Resolves the transactions prepared for longer than `prepared_for` on
+    /// every loaded range each `interval`, one range at a time, so that
+    /// those whose coordinator went away stop holding their locks.
+    async fn transaction_recovery_loop(
+        server: Arc<Self>,
+        interval: Duration,
+        prepared_for: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        loop {
+            tokio::select! {
+                () = cancellation_token.cancelled() => return,
+                () = server.clock.sleep(interval) => {}
+            }
+            let ranges: Vec<_> = server
+                .loaded_ranges
+                .read()
+                .await
+                .values()
+                .cloned()
+                .collect();
+            for rm in ranges {
+                if let Err(e) = server
+                    .resolve_orphaned_prepares(&rm, rm.range_id(), prepared_for, false)
+                    .await
+                {
+                    warn!(
+                        range_id = ?rm.range_id(),
+                        "Failed to resolve orphaned prepares: {}", e.message()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Purges the expired records of every loaded range each `interval`, one
+    /// range at a time.
+    async fn expiry_loop(
+        server: Arc<Self>,
+        interval: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        loop {
+            tokio::select! {
+                () = cancellation_token.cancelled() => return,
+                () = server.clock.sleep(interval) => {}
+            }
+            let ranges: Vec<_> = server
+                .loaded_ranges
+                .read()
+                .await
+                .values()
+                .cloned()
+                .collect();
+            for rm in ranges {
+                match rm.expire().await {
+                    Ok(outcome) if outcome.expired_keys > 0 => info!(
+                        range_id = ?rm.range_id(),
+                        expired_keys = outcome.expired_keys,
+                        purged_versions = outcome.purged_versions,
+                        "Purged expired records"
+                    ),
+                    // Unloaded since we listed it.
+                    Ok(_) | Err(Error::RangeIsNotLoaded) => {}
+                    Err(e) => warn!(
+                        range_id = ?rm.range_id(),
+                        "Failed to purge expired records: {:?}", e
+                    ),
+                }
+            }
+        }
+    }
+
+    async fn report_range_fault(&self, id: &FullRangeId, reason: String, recovered: bool) {
+        // Reporting is best effort, recovery does not depend on the warden.
+        if let Err(e) = self
+            .warden_handler
+            .report_range_fault(id, reason, recovered)
+            .await
+        {
+            warn!(range_id = ?id, "Failed to report range fault to warden: {}", e);
+        }
+    }
+
+    /// Tells the warden that `id` was split into `children`, which it then
+    /// assigns to us in its place. Until then neither the range nor its
+    /// children serve requests, the range manager left unloaded by the split
+    /// keeps the range from being loaded again. If the warden can't be told,
+    /// that range manager is dropped instead so the range reloads from its
+    /// records, which the split left untouched.
+    async fn report_range_split(
+        &self,
+        id: &FullRangeId,
+        children: &[SplitRange],
+    ) -> Result<(), TStatus> {
+        let new_ranges: Vec<_> = children
+            .iter()
+            .map(|child| (child.range_id, child.key_range.clone()))
+            .collect();
+        let mut backoff = RANGE_RELOAD_INITIAL_BACKOFF;
+        for attempt in 1..=SPLIT_REPORT_ATTEMPTS {
+            match self
+                .warden_handler
+                .report_range_split(id, &new_ranges)
+                .await
+            {
+                Ok(()) => {
+                    info!(range_id = ?id, ?new_ranges, "Split range");
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(range_id = ?id, attempt, "Failed to report range split to warden: {}", e);
+                }
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, RANGE_RELOAD_MAX_BACKOFF);
+        }
+        self.loaded_ranges.write().await.remove(&id.range_id);
+        Err(TStatus::unavailable(
+            "Failed to report the split to the warden, the range was not split",
+        ))
+    }
+
+    async fn recover_faulted_range(&self, id: FullRangeId) {
+        self.report_range_fault(&id, "persistent storage errors".to_string(), false)
+            .await;
+        let mut backoff = RANGE_RELOAD_INITIAL_BACKOFF;
+        for attempt in 1..=RANGE_RELOAD_MAX_ATTEMPTS {
+            // A faulted range manager cannot recover, so replace it with a
+            // fresh one.
+            self.maybe_unload_range(&id).await;
+            tokio::time::sleep(backoff).await;
+            if !self.warden_handler.is_assigned(&id).await {
+                info!(range_id = ?id, "Faulted range is no longer assigned, not reloading it");
+                return;
+            }
+            match self.maybe_load_and_get_range(&id).await {
+                Ok(_) => {
+                    info!(range_id = ?id, attempt, "Reloaded faulted range");
+                    self.report_range_fault(&id, "reloaded".to_string(), true)
+                        .await;
+                    return;
+                }
+                Err(e) => {
+                    warn!(range_id = ?id, attempt, "Failed to reload faulted range: {:?}", e);
+                }
+            }
+            backoff = std::cmp::min(backoff * 2, RANGE_RELOAD_MAX_BACKOFF);
+        }
+        error!(range_id = ?id, "Giving up on reloading faulted range");
+        self.maybe_unload_range(&id).await;
+        self.report_range_fault(
+            &id,
+            format!(
+                "gave up reloading after {} attempts",
+                RANGE_RELOAD_MAX_ATTEMPTS
+            ),
+            false,
+        )
+        .await;
+    }
+
     async fn handle_message(
         server: Arc<Self>,
         fast_network: Arc<dyn FastNetwork>,
@@ -622,6 +2486,24 @@ where
                 let get_msg = flatbuffers::root::<GetRequest>(envelope.bytes().unwrap().bytes())?;
                 server.get(fast_network.clone(), sender, get_msg).await?
             }
+            MessageType::Scan => {
+                let scan_msg = flatbuffers::root::<ScanRequest>(envelope.bytes().unwrap().bytes())?;
+                server.scan(fast_network.clone(), sender, scan_msg).await?
+            }
+            MessageType::ScanChunk => {
+                let scan_msg =
+                    flatbuffers::root::<ScanChunkRequest>(envelope.bytes().unwrap().bytes())?;
+                server
+                    .scan_chunk(fast_network.clone(), sender, scan_msg)
+                    .await?
+            }
+            MessageType::GetPrefix => {
+                let get_prefix_msg =
+                    flatbuffers::root::<GetPrefixRequest>(envelope.bytes().unwrap().bytes())?;
+                server
+                    .get_prefix(fast_network.clone(), sender, get_prefix_msg)
+                    .await?
+            }
             MessageType::Prepare => {
                 let prepare_msg =
                     flatbuffers::root::<PrepareRequest>(envelope.bytes().unwrap().bytes())?;
@@ -643,6 +2525,29 @@ where
                     .commit(fast_network.clone(), sender, commit_msg)
                     .await?
             }
+            MessageType::Validate => {
+                let validate_msg =
+                    flatbuffers::root::<ValidateRequest>(envelope.bytes().unwrap().bytes())?;
+                server
+                    .validate(fast_network.clone(), sender, validate_msg)
+                    .await?
+            }
+            MessageType::ExtendEpochLease => {
+                let extend_msg = flatbuffers::root::<ExtendEpochLeaseRequest>(
+                    envelope.bytes().unwrap().bytes(),
+                )?;
+                server
+                    .extend_epoch_lease(fast_network.clone(), sender, extend_msg)
+                    .await?
+            }
+            MessageType::GetConflictStats => {
+                let stats_msg = flatbuffers::root::<GetConflictStatsRequest>(
+                    envelope.bytes().unwrap().bytes(),
+                )?;
+                server
+                    .get_conflict_stats(fast_network.clone(), sender, stats_msg)
+                    .await?
+            }
             _ => (), // TODO: return and log unknown message type error.
         };
         Ok(())
@@ -680,12 +2585,153 @@ where
         }
     }
 
+    fn check_config(&self) -> Result<String, String> {
+        let region = &self.host_info.identity.zone.region;
+        if !self.config.regions.contains_key(region) {
+            return Err(format!(
+                "region {:?} of this host has no entry under `regions` in the config",
+                region
+            ));
+        }
+        let addresses = [
+            ("cassandra.cql_addr", &self.config.cassandra.cql_addr),
+            (
+                "universe.proto_server_addr",
+                &self.config.universe.proto_server_addr,
+            ),
+        ];
+        for (name, addr) in addresses {
+            if let Err(e) = addr.to_socket_addrs() {
+                return Err(format!("{} ({}) does not resolve: {}", name, addr, e));
+            }
+        }
+        Ok("config is consistent".to_string())
+    }
+
+    async fn check_storage(&self) -> Result<String, String> {
+        let cql_addr = &self.config.cassandra.cql_addr;
+        match tokio::time::timeout(PREFLIGHT_CHECK_TIMEOUT, self.storage.check_reachable()).await {
+            Ok(Ok(())) => Ok(format!("storage at {} is reachable", cql_addr)),
+            Ok(Err(e)) => Err(format!("storage at {} returned an error: {}", cql_addr, e)),
+            Err(_) => Err(format!(
+                "storage at {} did not respond within {:?}",
+                cql_addr, PREFLIGHT_CHECK_TIMEOUT
+            )),
+        }
+    }
+
+    async fn check_epoch(&self) -> Result<String, String> {
+        let epoch =
+            match tokio::time::timeout(PREFLIGHT_CHECK_TIMEOUT, self.epoch_supplier.read_epoch())
+                .await
+            {
+                Ok(Ok(epoch)) => epoch,
+                Ok(Err(e)) => return Err(format!("failed to read the epoch: {}", e)),
+                Err(_) => {
+                    return Err(format!(
+                        "could not read the epoch within {:?}, check that the epoch publishers of \
+                         this zone are running",
+                        PREFLIGHT_CHECK_TIMEOUT
+                    ))
+                }
+            };
+        let advance_timeout = match self.config.range_server.preflight_epoch_advance_timeout {
+            None => return Ok(format!("read epoch {}", epoch)),
+            Some(timeout) => timeout,
+        };
+        let wait = self.epoch_supplier.wait_until_epoch(
+            epoch + 1,
+            chrono::Duration::from_std(advance_timeout).unwrap(),
+        );
+        match tokio::time::timeout(advance_timeout, wait).await {
+            Ok(Ok(())) => Ok(format!("epoch advanced past {}", epoch)),
+            Ok(Err(e)) => Err(format!("failed waiting for the epoch to advance: {}", e)),
+            Err(_) => Err(format!(
+                "epoch stuck at {} for {:?}, check that the epoch service is running",
+                epoch, advance_timeout
+            )),
+        }
+    }
+
+    async fn check_warden(&self) -> Result<String, String> {
+        let region = &self.host_info.identity.zone.region;
+        let warden_address = match self.config.regions.get(region) {
+            // Already reported by the config check.
+            None => return Err("no warden configured for this region".to_string()),
+            Some(region_config) => &region_config.warden_address,
+        };
+        let connect =
+            tokio::net::TcpStream::connect((warden_address.host.as_str(), warden_address.port));
+        match tokio::time::timeout(PREFLIGHT_CHECK_TIMEOUT, connect).await {
+            Ok(Ok(_)) => Ok(format!("warden at {} is reachable", warden_address)),
+            Ok(Err(e)) => Err(format!(
+                "failed to connect to warden at {}: {}",
+                warden_address, e
+            )),
+            Err(_) => Err(format!(
+                "warden at {} did not accept a connection within {:?}",
+                warden_address, PREFLIGHT_CHECK_TIMEOUT
+            )),
+        }
+    }
+
+    /// Validates the configuration and the reachability of the server's
+    /// dependencies, so that misconfigurations surface at startup rather than
+    /// as failures of background tasks later on.
+    async fn preflight(&self, proto_server_listener: Option<&TcpListener>) -> PreflightReport {
+        let mut report = PreflightReport::default();
+        let started = Instant::now();
+        report.record("config", started, self.check_config());
+        if let Some(proto_server_listener) = proto_server_listener {
+            let listener_result = proto_server_listener
+                .local_addr()
+                .map(|addr| format!("proto server listening on {}", addr))
+                .map_err(|e| format!("proto server listener is unusable: {}", e));
+            report.record("proto_server_listener", started, listener_result);
+        }
+
+        // The remaining checks talk to other services, run them concurrently.
+        let started = Instant::now();
+        let (storage, epoch, warden) = tokio::join!(
+            self.check_storage(),
+            self.check_epoch(),
+            self.check_warden()
+        );
+        report.record("storage", started, storage);
+        report.record("epoch", started, epoch);
+        report.record("warden", started, warden);
+        report
+    }
+
     pub async fn start(
         server: Arc<Self>,
         fast_network: Arc<dyn FastNetwork>,
         cancellation_token: CancellationToken,
         proto_server_listener: TcpListener,
     ) -> Result<oneshot::Receiver<Result<(), DynamicErr>>, DynamicErr> {
+        Self::start_with_listener(
+            server,
+            fast_network,
+            cancellation_token,
+            Some(proto_server_listener),
+        )
+        .await
+    }
+
+    /// Like `start`, but only serves the gRPC API if a listener is given.
+    pub(crate) async fn start_with_listener(
+        server: Arc<Self>,
+        fast_network: Arc<dyn FastNetwork>,
+        cancellation_token: CancellationToken,
+        proto_server_listener: Option<TcpListener>,
+    ) -> Result<oneshot::Receiver<Result<(), DynamicErr>>, DynamicErr> {
+        let report = server.preflight(proto_server_listener.as_ref()).await;
+        if !report.is_ready() {
+            error!(report = report.to_json(), "Range server preflight failed");
+            return Err(Box::new(report));
+        }
+        info!(report = report.to_json(), "Range server preflight passed");
+
         let (warden_s, warden_r) = mpsc::unbounded_channel();
         let server_clone = server.clone();
         let cancellation_token_for_warden_loop = cancellation_token.clone();
@@ -699,22 +2745,66 @@ where
             println!("Warden update loop exited!")
         });
 
-        let prefetch = ProtoServer {
-            parent_server: server.clone(),
-        };
+        if let Some(range_fault_r) = server.range_fault_receiver.lock().unwrap().take() {
+            let server_clone = server.clone();
+            let cancellation_token_for_fault_loop = cancellation_token.clone();
+            server.bg_runtime.spawn(async move {
+                let _ = Self::range_fault_loop(
+                    server_clone,
+                    range_fault_r,
+                    cancellation_token_for_fault_loop,
+                )
+                .await;
+                info!("Range fault loop exited")
+            });
+        }
 
-        // Spawn the gRPC server as a separate task
-        server.bg_runtime.spawn(async move {
-            if let Err(e) = TServer::builder()
-                .add_service(RangeServerServer::new(prefetch))
-                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(
-                    proto_server_listener,
-                ))
-                .await
-            {
-                println!("Server error: {}", e);
-            }
-        });
+        if let Some(interval) = server.config.range_server.checksum_verification.interval {
+            server.bg_runtime.spawn(Self::checksum_verification_loop(
+                server.clone(),
+                interval,
+                cancellation_token.clone(),
+            ));
+        }
+
+        let recovery = &server.config.range_server.transaction_recovery;
+        if let Some(interval) = recovery.interval {
+            server.bg_runtime.spawn(Self::transaction_recovery_loop(
+                server.clone(),
+                interval,
+                recovery.prepared_for,
+                cancellation_token.clone(),
+            ));
+        }
+
+        if let Some(interval) = server.config.range_server.expiry.interval {
+            server.bg_runtime.spawn(Self::expiry_loop(
+                server.clone(),
+                interval,
+                cancellation_token.clone(),
+            ));
+        }
+
+        if let Some(proto_server_listener) = proto_server_listener {
+            let prefetch = ProtoServer {
+                parent_server: server.clone(),
+            };
+            let profiler = ProfilingService::new(&server.config.admin).into_server();
+
+            // Spawn the gRPC server as a separate task
+            server.bg_runtime.spawn(async move {
+                if let Err(e) = TServer::builder()
+                    .add_service(RangeServerServer::new(prefetch))
+                    .add_service(profiler)
+                    .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(
+                        proto_server_listener,
+                    ))
+                    .await
+                {
+                    println!("Server error: {}", e);
+                }
+            });
+        }
 
         let server_ref = server.clone();
         let res = server
@@ -738,6 +2828,7 @@ pub mod tests {
         UniverseConfig,
     };
     use common::host_info::HostIdentity;
+    use common::network::for_testing::in_memory_fast_network::InMemoryNetwork;
     use common::network::for_testing::udp_fast_network::UdpFastNetwork;
     use common::region::{Region, Zone};
     use core::time;
@@ -747,6 +2838,7 @@ pub mod tests {
 
     use super::*;
 
+    use crate::embedded::EmbeddedRangeServer;
     use crate::for_testing::epoch_supplier::EpochSupplier;
     use crate::for_testing::mock_warden::MockWarden;
     use crate::storage::cassandra::Cassandra;
@@ -792,6 +2884,7 @@ pub mod tests {
         let region_config = RegionConfig {
             warden_address: warden_address,
             epoch_publishers: HashSet::new(),
+            cassandra_datacenter: None,
         };
         let epoch_config = EpochConfig {
             // Not used in these tests.
@@ -803,6 +2896,19 @@ pub mod tests {
                 range_maintenance_duration: time::Duration::from_secs(1),
                 proto_server_addr: HostPort::from_str("127.0.0.1:50054").unwrap(),
                 fast_network_addr: HostPort::from_str("127.0.0.1:50055").unwrap(),
+                preflight_epoch_advance_timeout: None,
+                max_pending_prepares_per_range: None,
+                max_epoch_lease_extension: None,
+                handoff_drain_timeout: None,
+                lock_table: Default::default(),
+                conflict_stats: Default::default(),
+                write_stall: Default::default(),
+                checksum_verification: Default::default(),
+                decision_log: Default::default(),
+                expiry: Default::default(),
+                fast_network_transport: Default::default(),
+                mirroring: Default::default(),
+                transaction_recovery: Default::default(),
                 // proto_server_addr: proto_server_listener.local_addr().unwrap(),
             },
             universe: UniverseConfig {
@@ -812,11 +2918,15 @@ pub mod tests {
                 proto_server_addr: HostPort::from_str("127.0.0.1:50056").unwrap(),
                 fast_network_addr: HostPort::from_str("127.0.0.1:50057").unwrap(),
                 transaction_overall_timeout: time::Duration::from_secs(10),
+                fast_network_transport: Default::default(),
             },
             cassandra: CassandraConfig {
                 cql_addr: "127.0.0.1:9042".parse().unwrap(),
+                consistency: Default::default(),
             },
             regions: std::collections::HashMap::new(),
+            admin: Default::default(),
+            quic: Default::default(),
             epoch: epoch_config,
         };
         config.regions.insert(region, region_config);
@@ -828,6 +2938,7 @@ pub mod tests {
             },
             address: "127.0.0.1:50054".parse().unwrap(),
             warden_connection_epoch: epoch_supplier.read_epoch().await.unwrap(),
+            labels: Default::default(),
         };
         let server = Server::new(
             config,
@@ -875,6 +2986,24 @@ pub mod tests {
         ch.await.unwrap().unwrap()
     }
 
+    #[tokio::test]
+    async fn embedded_range_server_connects_to_warden() {
+        let context = init().await;
+        let network = InMemoryNetwork::new();
+        let fast_network = network.endpoint("127.0.0.1:50055".parse().unwrap());
+        let embedded = EmbeddedRangeServer::start(
+            context.server.clone(),
+            fast_network,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        while !context.mock_warden.is_connected(&context.identity).await {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+        embedded.shutdown().await.unwrap()
+    }
+
     #[tokio::test]
     async fn incremental_load_unload() {
         let context = init().await;
@@ -907,9 +3036,11 @@ pub mod tests {
         assert!((context.server.warden_handler.is_assigned(&range_id).await));
         assert!(context.server.is_assigned(&range_id).await);
         context.mock_warden.unassign(&range_id).await;
-        while context.server.warden_handler.is_assigned(&range_id).await {
+        // The range is only handed off once unloaded.
+        while !context.mock_warden.is_handed_off(&range_id).await {
             tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
         }
+        assert!(!(context.server.warden_handler.is_assigned(&range_id).await));
         assert!(!context.server.is_assigned(&range_id).await);
         cancellation_token.cancel();
         ch.await.unwrap().unwrap()
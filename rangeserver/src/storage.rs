@@ -1,9 +1,15 @@
 pub mod cassandra;
+pub mod in_memory;
+pub mod migration;
+pub mod retry;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb;
 
 use std::sync::Arc;
 
 use crate::key_version::KeyVersion;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use common::full_range_id::FullRangeId;
 use common::key_range::KeyRange;
 use thiserror::Error;
@@ -18,10 +24,33 @@ pub struct RangeInfo {
     pub epoch_lease: EpochLease,
 }
 
+/// One version of a record, as kept by the storage layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordVersion {
+    pub epoch: u64,
+    // None for records written before transaction ids were stored.
+    pub transaction_id: Option<Uuid>,
+    // None for a tombstone.
+    pub value: Option<Bytes>,
+    // None for values that never expire.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Whether a value expiring at `expires_at` is expired at `now`. Storage
+/// reads treat expired values like deleted ones.
+pub fn is_expired(expires_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
+/// The version of the `RangeSnapshot` format written by this build.
+pub const RANGE_SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
 #[derive(Clone, Debug, Error)]
 pub enum Error {
     #[error("Timeout Error")]
     Timeout,
+    #[error("Storage layer is temporarily unavailable: {0}")]
+    Unavailable(Arc<dyn std::error::Error + Send + Sync>),
     #[error("No range with this id exists in the storage layer")]
     RangeDoesNotExist,
     #[error("Range ownership claimed by another range server")]
@@ -30,6 +59,14 @@ pub enum Error {
     InternalError(Arc<dyn std::error::Error + Send + Sync>),
 }
 
+impl Error {
+    /// Whether the operation may succeed if tried again. Note that a timed out
+    /// operation may or may not have taken effect.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Timeout | Error::Unavailable(_))
+    }
+}
+
 pub trait Storage: Send + Sync + 'static {
     fn take_ownership_and_load_range(
         &self,
@@ -41,13 +78,26 @@ pub trait Storage: Send + Sync + 'static {
         new_lease: EpochLease,
         leader_sequence_number: u64,
     ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+    /// Creates a range that nobody owns yet, covering `key_range`. Whoever
+    /// takes ownership of it first gets a lease past `epoch_lease`. Does
+    /// nothing if the range already exists.
+    fn create_range(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        epoch_lease: EpochLease,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
 
+    /// Writes a version of the key holding `val`. If `expires_at` is set,
+    /// reads stop seeing the version once the wall clock passes it, but it is
+    /// kept until purged.
     fn upsert(
         &self,
         range_id: FullRangeId,
         key: Bytes,
         val: Bytes,
         version: KeyVersion,
+        expires_at: Option<DateTime<Utc>>,
     ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
     fn delete(
         &self,
@@ -55,9 +105,95 @@ pub trait Storage: Send + Sync + 'static {
         key: Bytes,
         version: KeyVersion,
     ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+    /// Returns the newest value of the key, or None if it is deleted or
+    /// expired.
     fn get(
         &self,
         range_id: FullRangeId,
         key: Bytes,
     ) -> impl std::future::Future<Output = Result<Option<Bytes>, Error>> + Send;
+    /// Returns the value the key had as of `epoch`, i.e. that of its newest
+    /// version at or below it, or None if that version is a tombstone or
+    /// expired. Only exact for epochs no compaction collected versions past.
+    fn get_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        epoch: u64,
+    ) -> impl std::future::Future<Output = Result<Option<Bytes>, Error>> + Send;
+
+    /// Returns the newest value of up to `limit` keys within `key_range`, in
+    /// key order, skipping deleted and expired keys.
+    fn scan(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> impl std::future::Future<Output = Result<Vec<(Bytes, Bytes)>, Error>> + Send;
+    /// Like `scan`, but returns the values the keys had as of `epoch`, as
+    /// `get_at_epoch` does.
+    fn scan_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> impl std::future::Future<Output = Result<Vec<(Bytes, Bytes)>, Error>> + Send;
+
+    /// Returns up to `limit` versions of the key, newest first, expired ones
+    /// included. Only meant for debugging, since old versions are not
+    /// guaranteed to be kept around.
+    fn get_versions(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        limit: usize,
+    ) -> impl std::future::Future<Output = Result<Vec<RecordVersion>, Error>> + Send;
+
+    /// Returns every stored version of every key in the range, ordered by key
+    /// and then newest first. Reads the whole range, so only meant for
+    /// debugging.
+    fn scan_versions(
+        &self,
+        range_id: FullRangeId,
+    ) -> impl std::future::Future<Output = Result<Vec<(Bytes, RecordVersion)>, Error>> + Send;
+
+    /// Removes every version of each key at or below the epoch paired with
+    /// it. Unlike `delete`, this leaves nothing behind for reads to see, so
+    /// it must only remove versions that newer ones already hide.
+    fn purge_versions(
+        &self,
+        range_id: FullRangeId,
+        purges: Vec<(Bytes, u64)>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    /// Keeps the prepare record of a transaction prepared on the range until
+    /// `remove_prepare`, so that whoever loads the range next, e.g. after its
+    /// range server crashed, can still commit or abort the transaction.
+    /// Like `renew_epoch_lease`, fails with `RangeOwnershipLost` unless the
+    /// range is still owned under `leader_sequence_number`, so that a
+    /// previous owner can't leave records behind after the range was loaded
+    /// again, nor remove the ones its successor recovered.
+    fn persist_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        prepare: Bytes,
+        leader_sequence_number: u64,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+    fn remove_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        leader_sequence_number: u64,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+    /// Returns the prepare records persisted for the range, by transaction.
+    fn load_prepares(
+        &self,
+        range_id: FullRangeId,
+    ) -> impl std::future::Future<Output = Result<Vec<(Uuid, Bytes)>, Error>> + Send;
+
+    /// Performs a cheap round trip to the storage layer, to check that it is
+    /// reachable.
+    fn check_reachable(&self) -> impl std::future::Future<Output = Result<(), Error>> + Send;
 }
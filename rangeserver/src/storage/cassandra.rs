@@ -1,22 +1,34 @@
+use super::retry::{Retrier, RetryPolicy};
 use super::*;
 use bytes::Bytes;
+use common::config::{CassandraConfig, CassandraConsistencyConfig, ConsistencyLevel};
 use common::full_range_id::FullRangeId;
 
-use scylla::frame::value::Unset;
+use scylla::frame::response::result::CqlValue;
+use scylla::frame::value::{MaybeUnset, Unset};
+use scylla::history::HistoryCollector;
 use scylla::macros::FromUserType;
 use scylla::macros::IntoUserType;
+use scylla::query::Query;
+use scylla::serialize::row::SerializeRow;
+use scylla::statement::Consistency;
 use scylla::transport::errors::DbError;
 use scylla::transport::errors::QueryError;
+use scylla::transport::load_balancing::DefaultPolicy;
+use scylla::ExecutionProfile;
 use scylla::SerializeCql;
 use scylla::SessionBuilder;
-use scylla::{FromRow, Session, ValueList};
+use scylla::{FromRow, QueryResult, Session, ValueList};
+use tracing::warn;
 use uuid::Uuid;
 
 pub struct Cassandra {
     session: Session,
+    consistency: CassandraConsistencyConfig,
+    retrier: Retrier,
 }
 
-#[derive(Debug, FromUserType, IntoUserType, SerializeCql)]
+#[derive(Clone, Debug, FromUserType, IntoUserType, SerializeCql)]
 struct CqlEpochRange {
     lower_bound_inclusive: i64,
     upper_bound_inclusive: i64,
@@ -36,6 +48,35 @@ struct CqlRangeLease {
 struct CqlVal {
     value: Option<Vec<u8>>,
     is_tombstone: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct CqlKeyVersion {
+    key: Vec<u8>,
+    epoch: i64,
+    transaction_id: Option<Uuid>,
+    value: Option<Vec<u8>>,
+    is_tombstone: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct CqlKeyVal {
+    key: Vec<u8>,
+    epoch: i64,
+    value: Option<Vec<u8>>,
+    is_tombstone: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct CqlVersion {
+    epoch: i64,
+    transaction_id: Option<Uuid>,
+    value: Option<Vec<u8>>,
+    is_tombstone: bool,
+    expires_at: Option<DateTime<Utc>>,
 }
 
 impl CqlRangeLease {
@@ -73,24 +114,111 @@ static RENEW_EPOCH_LEASE_QUERY: &str = r#"
     IF leader_sequence_number = ? 
 "#;
 
+static CREATE_RANGE_LEASE_QUERY: &str = r#"
+  INSERT INTO atomix.range_leases (range_id, key_lower_bound_inclusive, key_upper_bound_exclusive, leader_sequence_number, epoch_lease, safe_snapshot_epochs)
+    VALUES (?, ?, ?, ?, ?, ?)
+    IF NOT EXISTS
+"#;
+
 static UPSERT_QUERY: &str = r#"
-  INSERT INTO atomix.records (range_id, key, value, epoch, is_tombstone) 
-    VALUES (?, ?, ?, ?, ?) 
+  INSERT INTO atomix.records (range_id, key, value, epoch, is_tombstone, transaction_id, expires_at) 
+    VALUES (?, ?, ?, ?, ?, ?, ?) 
     USING TIMESTAMP ?
 "#;
 
 static GET_QUERY: &str = r#"
-  SELECT value, is_tombstone from atomix.records
+  SELECT value, is_tombstone, expires_at from atomix.records
+  WHERE range_id = ? AND key = ?
+  LIMIT 1
+"#;
+
+static GET_AT_EPOCH_QUERY: &str = r#"
+  SELECT value, is_tombstone, expires_at from atomix.records
+  WHERE range_id = ? AND key = ? AND epoch <= ?
+  LIMIT 1
+"#;
+
+static GET_VERSIONS_QUERY: &str = r#"
+  SELECT epoch, transaction_id, value, is_tombstone, expires_at from atomix.records
   WHERE range_id = ? AND key = ?
+  LIMIT ?
+"#;
+
+static SCAN_VERSIONS_QUERY: &str = r#"
+  SELECT key, epoch, transaction_id, value, is_tombstone, expires_at from atomix.records
+  WHERE range_id = ?
+"#;
+
+// Deletes with a single range tombstone, which is much cheaper for Cassandra
+// to compact away than one tombstone per version.
+// Rows come back newest first within each key. The bounds on the key get
+// appended as needed.
+static SCAN_QUERY: &str = r#"
+  SELECT key, epoch, value, is_tombstone, expires_at from atomix.records
+  WHERE range_id = ?
+"#;
+
+static PURGE_VERSIONS_QUERY: &str = r#"
+  DELETE FROM atomix.records
+  WHERE range_id = ? AND key = ? AND epoch <= ?
+"#;
+
+static PERSIST_PREPARE_QUERY: &str = r#"
+  UPDATE atomix.prepared_transactions SET prepare = ?
+    WHERE range_id = ? AND transaction_id = ?
+    IF leader_sequence_number = ?
+"#;
+
+static REMOVE_PREPARE_QUERY: &str = r#"
+  DELETE FROM atomix.prepared_transactions
+  WHERE range_id = ? AND transaction_id = ?
+  IF leader_sequence_number = ?
+"#;
+
+static GET_PREPARES_FENCE_QUERY: &str = r#"
+  SELECT leader_sequence_number FROM atomix.prepared_transactions
+  WHERE range_id = ?
   LIMIT 1
 "#;
 
+static FENCE_PREPARES_QUERY: &str = r#"
+  UPDATE atomix.prepared_transactions SET leader_sequence_number = ?
+    WHERE range_id = ?
+    IF leader_sequence_number = ?
+"#;
+
+static LOAD_PREPARES_QUERY: &str = r#"
+  SELECT transaction_id, prepare FROM atomix.prepared_transactions
+  WHERE range_id = ?
+"#;
+
+// Rows fetched per round trip when scanning a whole range.
+const SCAN_PAGE_SIZE: i32 = 1000;
+
+static CHECK_REACHABLE_QUERY: &str = r#"
+  SELECT release_version FROM system.local
+"#;
+
 fn scylla_query_error_to_persistence_error(qe: QueryError) -> Error {
     match qe {
-        QueryError::TimeoutError | QueryError::DbError(DbError::WriteTimeout { .. }, _) => {
-            Error::Timeout
+        QueryError::TimeoutError
+        | QueryError::RequestTimeout(_)
+        | QueryError::DbError(DbError::ReadTimeout { .. }, _)
+        | QueryError::DbError(DbError::WriteTimeout { .. }, _) => Error::Timeout,
+        // The query was not executed, or the node could not be reached. Trying
+        // again later may succeed.
+        QueryError::IoError(_)
+        | QueryError::TooManyOrphanedStreamIds(_)
+        | QueryError::UnableToAllocStreamId
+        | QueryError::DbError(DbError::Unavailable { .. }, _)
+        | QueryError::DbError(DbError::Overloaded, _)
+        | QueryError::DbError(DbError::IsBootstrapping, _)
+        | QueryError::DbError(DbError::RateLimitReached { .. }, _) => {
+            Error::Unavailable(Arc::new(qe))
         }
         _ => {
+            // Everything else (bad queries, schema mismatches, corrupted
+            // responses...) will not go away by retrying.
             // TODO: It is essential to correctly categorize timeout errors, since these could indicate an operation
             // might still succeed and require extra care in dealing with. Having a catch-all is bad since we might
             // break if a new timeout variant is added.
@@ -99,22 +227,289 @@ fn scylla_query_error_to_persistence_error(qe: QueryError) -> Error {
     }
 }
 
+// The node the last attempt at a query went to, if it got as far as one.
+fn last_attempted_node(history: &HistoryCollector) -> Option<String> {
+    history
+        .take_structured_history()
+        .queries
+        .pop()?
+        .non_speculative_fiber
+        .attempts
+        .pop()
+        .map(|attempt| attempt.node_addr.to_string())
+}
+
+pub fn scylla_consistency(level: ConsistencyLevel) -> Consistency {
+    match level {
+        ConsistencyLevel::Any => Consistency::Any,
+        ConsistencyLevel::One => Consistency::One,
+        ConsistencyLevel::Two => Consistency::Two,
+        ConsistencyLevel::Three => Consistency::Three,
+        ConsistencyLevel::Quorum => Consistency::Quorum,
+        ConsistencyLevel::All => Consistency::All,
+        ConsistencyLevel::LocalQuorum => Consistency::LocalQuorum,
+        ConsistencyLevel::EachQuorum => Consistency::EachQuorum,
+        ConsistencyLevel::LocalOne => Consistency::LocalOne,
+    }
+}
+
+/// Connects to the Cassandra cluster. If `local_datacenter` is set, queries
+/// are routed to nodes in that datacenter, and only go to other datacenters
+/// when none of its nodes can take them.
+pub async fn build_session(known_node: String, local_datacenter: Option<String>) -> Session {
+    let mut builder = SessionBuilder::new().known_node(known_node);
+    if let Some(datacenter) = local_datacenter {
+        let policy = DefaultPolicy::builder()
+            .prefer_datacenter(datacenter)
+            .permit_dc_failover(true)
+            .token_aware(true)
+            .build();
+        let profile = ExecutionProfile::builder()
+            .load_balancing_policy(policy)
+            .build();
+        builder = builder.default_execution_profile_handle(profile.into_handle());
+    }
+    builder.build().await.unwrap()
+}
+
 impl Cassandra {
     pub async fn new(known_node: String) -> Cassandra {
-        let session = SessionBuilder::new()
-            .known_node(known_node)
-            .build()
+        Self::new_with_options(
+            known_node,
+            None,
+            CassandraConsistencyConfig::default(),
+            RetryPolicy::default(),
+        )
+        .await
+    }
+
+    pub async fn from_config(
+        config: &CassandraConfig,
+        local_datacenter: Option<String>,
+    ) -> Cassandra {
+        // Non-local levels make every operation wait on replicas in remote
+        // datacenters, which is rarely intended for the hot path.
+        if let Some(datacenter) = &local_datacenter {
+            for (operation, level) in [
+                ("record_reads", config.consistency.record_reads),
+                ("record_writes", config.consistency.record_writes),
+            ] {
+                if !level.is_dc_local() {
+                    warn!(
+                        datacenter,
+                        "{} use consistency level {:?}, which involves remote datacenters",
+                        operation,
+                        level
+                    );
+                }
+            }
+        }
+        Self::new_with_options(
+            config.cql_addr.to_string(),
+            local_datacenter,
+            config.consistency.clone(),
+            RetryPolicy::default(),
+        )
+        .await
+    }
+
+    pub async fn new_with_options(
+        known_node: String,
+        local_datacenter: Option<String>,
+        consistency: CassandraConsistencyConfig,
+        policy: RetryPolicy,
+    ) -> Cassandra {
+        let session = build_session(known_node, local_datacenter).await;
+        let retrier = Retrier::new(policy);
+        Cassandra {
+            session,
+            consistency,
+            retrier,
+        }
+    }
+
+    // Sends the query once, telling the retrier which node served it.
+    async fn attempt(
+        &self,
+        query: &Query,
+        values: impl SerializeRow,
+        paging_state: Option<Bytes>,
+    ) -> (Option<String>, Result<QueryResult, Error>) {
+        let mut query = query.clone();
+        let history = Arc::new(HistoryCollector::new());
+        query.set_history_listener(history.clone());
+        let res = self
+            .session
+            .query_paged(query, values, paging_state)
             .await
-            .unwrap();
-        Cassandra { session }
+            .map_err(scylla_query_error_to_persistence_error);
+        (last_attempted_node(&history), res)
+    }
+
+    // The live values of the keys in `key_range` as of `epoch`. Rows come
+    // back newest first within each key, so the first one at or below the
+    // epoch is the one that counts.
+    async fn scan_as_of(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let mut statement = SCAN_QUERY.trim_end().to_string();
+        let mut values = vec![CqlValue::Uuid(range_id.range_id)];
+        if let Some(lower) = &key_range.lower_bound_inclusive {
+            statement.push_str(" AND key >= ?");
+            values.push(CqlValue::Blob(lower.to_vec()));
+        }
+        if let Some(upper) = &key_range.upper_bound_exclusive {
+            statement.push_str(" AND key < ?");
+            values.push(CqlValue::Blob(upper.to_vec()));
+        }
+        let limit = limit.unwrap_or(usize::MAX);
+        let now = Utc::now();
+        let mut query = Query::new(statement);
+        query.set_consistency(scylla_consistency(self.consistency.record_reads));
+        query.set_page_size(SCAN_PAGE_SIZE);
+        let mut records: Vec<(Bytes, Bytes)> = Vec::new();
+        let mut last_key: Option<Vec<u8>> = None;
+        let mut paging_state = None;
+        loop {
+            let result = self
+                .retrier
+                .run(|| self.attempt(&query, values.clone(), paging_state.clone()))
+                .await?;
+            for row in result.rows.unwrap_or_default() {
+                let row = row.into_typed::<CqlKeyVal>().unwrap();
+                if row.epoch as u64 > epoch {
+                    continue;
+                }
+                // Only the newest version of each key counts.
+                if last_key.as_ref() == Some(&row.key) {
+                    continue;
+                }
+                last_key = Some(row.key.clone());
+                if row.is_tombstone || is_expired(row.expires_at, now) {
+                    continue;
+                }
+                if let Some(value) = row.value {
+                    records.push((Bytes::from(row.key), Bytes::from(value)));
+                    if records.len() >= limit {
+                        return Ok(records);
+                    }
+                }
+            }
+            match result.paging_state {
+                Some(next) => paging_state = Some(next),
+                None => return Ok(records),
+            }
+        }
+    }
+
+    // For idempotent queries, which are safe to retry.
+    async fn query(
+        &self,
+        query: &'static str,
+        consistency: ConsistencyLevel,
+        values: impl SerializeRow + Clone,
+    ) -> Result<QueryResult, Error> {
+        let mut query = Query::new(query);
+        query.set_consistency(scylla_consistency(consistency));
+        self.retrier
+            .run(|| self.attempt(&query, values.clone(), None))
+            .await
+    }
+
+    // For conditional (lightweight transaction) queries, which are not
+    // retried: one that timed out may still have been applied, and applying
+    // it again would fail its condition. Callers read back what happened
+    // instead, and a timeout is returned to them as such.
+    async fn conditional_query(
+        &self,
+        query: &'static str,
+        consistency: ConsistencyLevel,
+        values: impl SerializeRow,
+    ) -> Result<QueryResult, Error> {
+        let mut query = Query::new(query);
+        query.set_consistency(scylla_consistency(consistency));
+        self.retrier
+            .run_once(self.attempt(&query, values, None))
+            .await
+    }
+
+    // The leader sequence number the prepare records of the range are written
+    // under, None if they never were.
+    async fn get_prepares_fence(&self, range_id: FullRangeId) -> Result<Option<i64>, Error> {
+        let row = self
+            .query(
+                GET_PREPARES_FENCE_QUERY,
+                self.consistency.range_metadata,
+                (range_id.range_id,),
+            )
+            .await?
+            .rows
+            .unwrap_or_default()
+            .pop();
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let (fence,) = row
+                    .into_typed::<(Option<i64>,)>()
+                    .map_err(|e| Error::InternalError(Arc::new(e)))?;
+                Ok(fence)
+            }
+        }
+    }
+
+    // Raises the leader sequence number the prepare records of the range are
+    // written under to the new owner's, so previous owners can't write them
+    // anymore.
+    async fn fence_prepares(
+        &self,
+        range_id: FullRangeId,
+        leader_sequence_number: i64,
+    ) -> Result<(), Error> {
+        loop {
+            let fence = self.get_prepares_fence(range_id).await?;
+            match fence {
+                Some(fence) if fence == leader_sequence_number => return Ok(()),
+                Some(fence) if fence > leader_sequence_number => {
+                    return Err(Error::RangeOwnershipLost)
+                }
+                _ => (),
+            }
+            let _ = self
+                .conditional_query(
+                    FENCE_PREPARES_QUERY,
+                    self.consistency.range_metadata,
+                    (leader_sequence_number, range_id.range_id, fence),
+                )
+                .await?;
+        }
+    }
+
+    // Like the lease queries, conditional writes of prepare records are
+    // checked with a read rather than with what the write returned.
+    async fn check_prepares_fence(
+        &self,
+        range_id: FullRangeId,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        if self.get_prepares_fence(range_id).await? != Some(leader_sequence_number as i64) {
+            Err(Error::RangeOwnershipLost)
+        } else {
+            Ok(())
+        }
     }
 
     async fn get_range_lease(&self, range_id: FullRangeId) -> Result<CqlRangeLease, Error> {
         let rows = self
-            .session
-            .query(GET_RANGE_LEASE_QUERY, (range_id.range_id,))
-            .await
-            .map_err(scylla_query_error_to_persistence_error)?
+            .query(
+                GET_RANGE_LEASE_QUERY,
+                self.consistency.range_metadata,
+                (range_id.range_id,),
+            )
+            .await?
             .rows;
 
         match rows {
@@ -141,17 +536,16 @@ impl Storage for Cassandra {
         let prev_leader_sequence_number = cql_lease.leader_sequence_number;
         let new_leader_sequence_number = prev_leader_sequence_number + 1;
         let _ = self
-            .session
-            .query(
+            .conditional_query(
                 ACQUIRE_RANGE_LEASE_QUERY,
+                self.consistency.range_metadata,
                 (
                     new_leader_sequence_number,
                     cql_lease.range_id,
                     prev_leader_sequence_number,
                 ),
             )
-            .await
-            .map_err(scylla_query_error_to_persistence_error)?;
+            .await?;
 
         // We must read the lease again after we've taken ownership to ensure we get its most up-to-date info.
         // Otherwise, the previous owner could have updated the lease between looking it up and owning it.
@@ -159,6 +553,8 @@ impl Storage for Cassandra {
         if cql_lease.leader_sequence_number != new_leader_sequence_number {
             Err(Error::RangeOwnershipLost)
         } else {
+            self.fence_prepares(range_id, new_leader_sequence_number)
+                .await?;
             Ok(RangeInfo {
                 id: range_id.range_id,
                 leader_sequence_number: new_leader_sequence_number as u64,
@@ -182,17 +578,16 @@ impl Storage for Cassandra {
             upper_bound_inclusive: lup as i64,
         };
         let _ = self
-            .session
-            .query(
+            .conditional_query(
                 RENEW_EPOCH_LEASE_QUERY,
+                self.consistency.range_metadata,
                 (
                     cql_epoch_range,
                     range_id.range_id,
                     leader_sequence_number as i64,
                 ),
             )
-            .await
-            .map_err(scylla_query_error_to_persistence_error)?;
+            .await?;
         // Scylla and cassandra have different ways of communicating whether the conditional statement actually
         // took effect or not. To support both, we just do a serial read and see what actually happened.
         // We should revisit this approach at some point though.
@@ -204,28 +599,74 @@ impl Storage for Cassandra {
         }
     }
 
+    async fn create_range(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        epoch_lease: EpochLease,
+    ) -> Result<(), Error> {
+        let _ = self
+            .conditional_query(
+                CREATE_RANGE_LEASE_QUERY,
+                self.consistency.range_metadata,
+                (
+                    range_id.range_id,
+                    key_range.lower_bound_inclusive.map(|b| b.to_vec()),
+                    key_range.upper_bound_exclusive.map(|b| b.to_vec()),
+                    0_i64,
+                    CqlEpochRange {
+                        lower_bound_inclusive: epoch_lease.0 as i64,
+                        upper_bound_inclusive: epoch_lease.1 as i64,
+                    },
+                    CqlEpochRange {
+                        lower_bound_inclusive: 0,
+                        upper_bound_inclusive: 0,
+                    },
+                ),
+            )
+            .await?;
+        // Prepare records can be written under the range's first leader
+        // sequence number until it is loaded, e.g. by migrations.
+        let _ = self
+            .conditional_query(
+                FENCE_PREPARES_QUERY,
+                self.consistency.range_metadata,
+                (0_i64, range_id.range_id, None::<i64>),
+            )
+            .await?;
+        Ok(())
+    }
+
     async fn upsert(
         &self,
         range_id: FullRangeId,
         key: Bytes,
         val: Bytes,
         version: KeyVersion,
+        expires_at: Option<DateTime<Utc>>,
     ) -> Result<(), Error> {
+        // Left unset rather than null when there is no expiry, so that it
+        // doesn't write a tombstone for the cell.
+        let expires_at = match expires_at {
+            Some(expires_at) => MaybeUnset::Set(expires_at),
+            None => MaybeUnset::Unset,
+        };
         let _ = self
-            .session
             .query(
                 UPSERT_QUERY,
+                self.consistency.record_writes,
                 (
                     range_id.range_id,
                     key.to_vec(),
                     val.to_vec(),
                     version.epoch as i64,
                     false,
+                    version.transaction_id,
+                    expires_at,
                     version.version_counter as i64,
                 ),
             )
-            .await
-            .map_err(scylla_query_error_to_persistence_error)?;
+            .await?;
         Ok(())
     }
 
@@ -236,29 +677,32 @@ impl Storage for Cassandra {
         version: KeyVersion,
     ) -> Result<(), Error> {
         let _ = self
-            .session
             .query(
                 UPSERT_QUERY,
+                self.consistency.record_writes,
                 (
                     range_id.range_id,
                     key.to_vec(),
                     Unset, /* val */
                     version.epoch as i64,
                     true, /* is_tombstone */
+                    version.transaction_id,
+                    Unset, /* expires_at */
                     version.version_counter as i64,
                 ),
             )
-            .await
-            .map_err(scylla_query_error_to_persistence_error)?;
+            .await?;
         Ok(())
     }
 
     async fn get(&self, range_id: FullRangeId, key: Bytes) -> Result<Option<Bytes>, Error> {
         let rows = self
-            .session
-            .query(GET_QUERY, (range_id.range_id, key.to_vec()))
-            .await
-            .map_err(scylla_query_error_to_persistence_error)?
+            .query(
+                GET_QUERY,
+                self.consistency.record_reads,
+                (range_id.range_id, key.to_vec()),
+            )
+            .await?
             .rows;
 
         match rows {
@@ -271,7 +715,7 @@ impl Storage for Cassandra {
                 } else {
                     let row = rows.pop().unwrap();
                     let row = row.into_typed::<CqlVal>().unwrap();
-                    if row.is_tombstone {
+                    if row.is_tombstone || is_expired(row.expires_at, Utc::now()) {
                         return Ok(None);
                     }
                     Ok(row.value.map(|v| Bytes::copy_from_slice(&v)))
@@ -279,6 +723,212 @@ impl Storage for Cassandra {
             }
         }
     }
+
+    async fn get_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        epoch: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        let rows = self
+            .query(
+                GET_AT_EPOCH_QUERY,
+                self.consistency.record_reads,
+                (range_id.range_id, key.to_vec(), epoch as i64),
+            )
+            .await?
+            .rows
+            .unwrap_or_default();
+        match rows.into_iter().next() {
+            None => Ok(None),
+            Some(row) => {
+                let row = row.into_typed::<CqlVal>().unwrap();
+                if row.is_tombstone || is_expired(row.expires_at, Utc::now()) {
+                    return Ok(None);
+                }
+                Ok(row.value.map(|v| Bytes::copy_from_slice(&v)))
+            }
+        }
+    }
+
+    async fn get_versions(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        limit: usize,
+    ) -> Result<Vec<RecordVersion>, Error> {
+        let rows = self
+            .query(
+                GET_VERSIONS_QUERY,
+                self.consistency.record_reads,
+                (
+                    range_id.range_id,
+                    key.to_vec(),
+                    limit.min(i32::MAX as usize) as i32,
+                ),
+            )
+            .await?
+            .rows
+            .unwrap_or_default();
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let row = row.into_typed::<CqlVersion>().unwrap();
+                RecordVersion {
+                    epoch: row.epoch as u64,
+                    transaction_id: row.transaction_id,
+                    value: match row.is_tombstone {
+                        true => None,
+                        false => row.value.map(|v| Bytes::copy_from_slice(&v)),
+                    },
+                    expires_at: row.expires_at,
+                }
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        self.scan_as_of(range_id, key_range, limit, u64::MAX).await
+    }
+
+    async fn scan_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        self.scan_as_of(range_id, key_range, limit, epoch).await
+    }
+
+    async fn scan_versions(
+        &self,
+        range_id: FullRangeId,
+    ) -> Result<Vec<(Bytes, RecordVersion)>, Error> {
+        let mut query = Query::new(SCAN_VERSIONS_QUERY);
+        query.set_consistency(scylla_consistency(self.consistency.record_reads));
+        query.set_page_size(SCAN_PAGE_SIZE);
+        let mut versions = Vec::new();
+        let mut paging_state = None;
+        loop {
+            let result = self
+                .retrier
+                .run(|| self.attempt(&query, (range_id.range_id,), paging_state.clone()))
+                .await?;
+            for row in result.rows.unwrap_or_default() {
+                let row = row.into_typed::<CqlKeyVersion>().unwrap();
+                versions.push((
+                    Bytes::from(row.key),
+                    RecordVersion {
+                        epoch: row.epoch as u64,
+                        transaction_id: row.transaction_id,
+                        value: match row.is_tombstone {
+                            true => None,
+                            false => row.value.map(Bytes::from),
+                        },
+                        expires_at: row.expires_at,
+                    },
+                ));
+            }
+            match result.paging_state {
+                Some(next) => paging_state = Some(next),
+                None => return Ok(versions),
+            }
+        }
+    }
+
+    async fn purge_versions(
+        &self,
+        range_id: FullRangeId,
+        purges: Vec<(Bytes, u64)>,
+    ) -> Result<(), Error> {
+        for (key, up_to) in purges {
+            let _ = self
+                .query(
+                    PURGE_VERSIONS_QUERY,
+                    self.consistency.record_writes,
+                    (range_id.range_id, key.to_vec(), up_to as i64),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn persist_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        prepare: Bytes,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let _ = self
+            .conditional_query(
+                PERSIST_PREPARE_QUERY,
+                self.consistency.record_writes,
+                (
+                    prepare.to_vec(),
+                    range_id.range_id,
+                    transaction_id,
+                    leader_sequence_number as i64,
+                ),
+            )
+            .await?;
+        self.check_prepares_fence(range_id, leader_sequence_number)
+            .await
+    }
+
+    async fn remove_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let _ = self
+            .conditional_query(
+                REMOVE_PREPARE_QUERY,
+                self.consistency.record_writes,
+                (
+                    range_id.range_id,
+                    transaction_id,
+                    leader_sequence_number as i64,
+                ),
+            )
+            .await?;
+        self.check_prepares_fence(range_id, leader_sequence_number)
+            .await
+    }
+
+    async fn load_prepares(&self, range_id: FullRangeId) -> Result<Vec<(Uuid, Bytes)>, Error> {
+        let rows = self
+            .query(
+                LOAD_PREPARES_QUERY,
+                self.consistency.record_reads,
+                (range_id.range_id,),
+            )
+            .await?
+            .rows
+            .unwrap_or_default();
+        rows.into_iter()
+            .map(|row| {
+                let (transaction_id, prepare) = row
+                    .into_typed::<(Uuid, Vec<u8>)>()
+                    .map_err(|e| Error::InternalError(Arc::new(e)))?;
+                Ok((transaction_id, Bytes::from(prepare)))
+            })
+            .collect()
+    }
+
+    async fn check_reachable(&self) -> Result<(), Error> {
+        let _ = self
+            .query(CHECK_REACHABLE_QUERY, ConsistencyLevel::LocalOne, ())
+            .await?;
+        Ok(())
+    }
 }
 
 pub mod for_testing {
@@ -396,7 +1046,9 @@ pub mod tests {
                 KeyVersion {
                     epoch: 2,
                     version_counter: 0,
+                    transaction_id: Uuid::new_v4(),
                 },
+                None,
             )
             .await
             .unwrap();
@@ -418,7 +1070,9 @@ pub mod tests {
                 KeyVersion {
                     epoch: 1,
                     version_counter: 0,
+                    transaction_id: Uuid::new_v4(),
                 },
+                None,
             )
             .await
             .unwrap();
@@ -440,7 +1094,9 @@ pub mod tests {
                 KeyVersion {
                     epoch: 3,
                     version_counter: 0,
+                    transaction_id: Uuid::new_v4(),
                 },
+                None,
             )
             .await
             .unwrap();
@@ -460,6 +1116,7 @@ pub mod tests {
                 KeyVersion {
                     epoch: 2,
                     version_counter: 0,
+                    transaction_id: Uuid::new_v4(),
                 },
             )
             .await
@@ -480,6 +1137,7 @@ pub mod tests {
                 KeyVersion {
                     epoch: 4,
                     version_counter: 0,
+                    transaction_id: Uuid::new_v4(),
                 },
             )
             .await
@@ -491,4 +1149,63 @@ pub mod tests {
             .unwrap()
             .is_none());
     }
+
+    #[tokio::test]
+    async fn get_versions_newest_first() {
+        let context = init().await;
+        let cassandra = context.cassandra.clone();
+        let full_range_id = FullRangeId {
+            keyspace_id: context.keyspace_id,
+            range_id: context.range_id,
+        };
+        let key = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let first = KeyVersion {
+            epoch: 1,
+            version_counter: 0,
+            transaction_id: Uuid::new_v4(),
+        };
+        let second = KeyVersion {
+            epoch: 2,
+            version_counter: 0,
+            transaction_id: Uuid::new_v4(),
+        };
+        cassandra
+            .upsert(
+                full_range_id,
+                key.clone(),
+                Bytes::from_static(b"A"),
+                first,
+                None,
+            )
+            .await
+            .unwrap();
+        cassandra
+            .delete(full_range_id, key.clone(), second)
+            .await
+            .unwrap();
+
+        let versions = cassandra
+            .get_versions(full_range_id, key.clone(), 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            versions,
+            vec![
+                RecordVersion {
+                    epoch: 2,
+                    transaction_id: Some(second.transaction_id),
+                    value: None,
+                    expires_at: None,
+                },
+                RecordVersion {
+                    epoch: 1,
+                    transaction_id: Some(first.transaction_id),
+                    value: Some(Bytes::from_static(b"A")),
+                    expires_at: None,
+                },
+            ]
+        );
+        let versions = cassandra.get_versions(full_range_id, key, 1).await.unwrap();
+        assert_eq!(versions.len(), 1);
+    }
 }
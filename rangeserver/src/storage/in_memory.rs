@@ -0,0 +1,1029 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use common::full_range_id::FullRangeId;
+use common::key_range::KeyRange;
+use common::keyspace_id::KeyspaceId;
+use proto::rangeserver::RangeSnapshot;
+use uuid::Uuid;
+
+use super::{
+    is_expired, EpochLease, Error, RangeInfo, RecordVersion, Storage, RANGE_SNAPSHOT_FORMAT_VERSION,
+};
+use crate::key_version::KeyVersion;
+
+struct RangeLease {
+    leader_sequence_number: u64,
+    epoch_lease: EpochLease,
+    key_range: KeyRange,
+}
+
+struct Record {
+    // Writes with a lower version counter than the latest are ignored, just
+    // like Cassandra does with write timestamps.
+    version_counter: u64,
+    transaction_id: Uuid,
+    // None for a tombstone.
+    value: Option<Bytes>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Record {
+    fn live_value(&self) -> Option<Bytes> {
+        if is_expired(self.expires_at, Utc::now()) {
+            return None;
+        }
+        self.value.clone()
+    }
+}
+
+/// Storage that keeps everything in memory and loses it on restart, for
+/// development setups and tests that should not need Cassandra.
+///
+/// Nothing creates the range leases ahead of time in such setups, so a range
+/// that was never loaded before gets created covering the whole key space.
+#[derive(Default)]
+pub struct InMemoryStorage {
+    leases: RwLock<HashMap<Uuid, RangeLease>>,
+    // The versions of each record, by epoch. Reads see the highest epoch.
+    records: RwLock<HashMap<(Uuid, Bytes), BTreeMap<u64, Record>>>,
+    prepares: RwLock<HashMap<Uuid, BTreeMap<Uuid, Bytes>>>,
+}
+
+impl InMemoryStorage {
+    pub fn new() -> InMemoryStorage {
+        InMemoryStorage::default()
+    }
+
+    fn check_owner(
+        leases: &HashMap<Uuid, RangeLease>,
+        range_id: FullRangeId,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        match leases.get(&range_id.range_id) {
+            None => Err(Error::RangeDoesNotExist),
+            Some(lease) if lease.leader_sequence_number != leader_sequence_number => {
+                Err(Error::RangeOwnershipLost)
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Replaces the range in the snapshot with its exported lease and
+    /// records, and returns its id. The transactions prepared on the range
+    /// are not storage state, so they are left for the caller to replay from
+    /// the snapshot.
+    pub fn load_snapshot(&self, snapshot: &RangeSnapshot) -> Result<FullRangeId, String> {
+        if snapshot.format_version != RANGE_SNAPSHOT_FORMAT_VERSION {
+            return Err(format!(
+                "unsupported snapshot format version {}, expected {}",
+                snapshot.format_version, RANGE_SNAPSHOT_FORMAT_VERSION
+            ));
+        }
+        let range = snapshot.range.as_ref().ok_or("snapshot has no range")?;
+        let range_id = FullRangeId {
+            keyspace_id: KeyspaceId::new(
+                Uuid::parse_str(&range.keyspace_id)
+                    .map_err(|e| format!("invalid keyspace id: {}", e))?,
+            ),
+            range_id: Uuid::parse_str(&range.range_id)
+                .map_err(|e| format!("invalid range id: {}", e))?,
+        };
+        let mut records = HashMap::new();
+        for record in &snapshot.records {
+            let transaction_id = match &record.transaction_id {
+                Some(id) => {
+                    Uuid::parse_str(id).map_err(|e| format!("invalid transaction id: {}", e))?
+                }
+                None => Uuid::nil(),
+            };
+            let versions: &mut BTreeMap<u64, Record> = records
+                .entry((range_id.range_id, Bytes::from(record.key.clone())))
+                .or_default();
+            let expires_at = match record.expires_at_us {
+                Some(us) => Some(
+                    DateTime::from_timestamp_micros(us as i64)
+                        .ok_or_else(|| format!("invalid expiry {}", us))?,
+                ),
+                None => None,
+            };
+            versions.insert(
+                record.epoch,
+                Record {
+                    version_counter: 0,
+                    transaction_id,
+                    value: record.value.clone().map(Bytes::from),
+                    expires_at,
+                },
+            );
+        }
+
+        self.leases.write().unwrap().insert(
+            range_id.range_id,
+            RangeLease {
+                leader_sequence_number: snapshot.leader_sequence_number,
+                epoch_lease: (
+                    snapshot.epoch_lease_lower_bound,
+                    snapshot.epoch_lease_upper_bound,
+                ),
+                key_range: KeyRange {
+                    lower_bound_inclusive: snapshot
+                        .key_lower_bound_inclusive
+                        .clone()
+                        .map(Bytes::from),
+                    upper_bound_exclusive: snapshot
+                        .key_upper_bound_exclusive
+                        .clone()
+                        .map(Bytes::from),
+                },
+            },
+        );
+        let mut all_records = self.records.write().unwrap();
+        all_records.retain(|(id, _), _| *id != range_id.range_id);
+        all_records.extend(records);
+        Ok(range_id)
+    }
+
+    fn write(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        value: Option<Bytes>,
+        version: KeyVersion,
+        expires_at: Option<DateTime<Utc>>,
+    ) {
+        let mut records = self.records.write().unwrap();
+        let versions = records.entry((range_id.range_id, key)).or_default();
+        let record = versions.entry(version.epoch).or_insert(Record {
+            version_counter: 0,
+            transaction_id: version.transaction_id,
+            value: None,
+            expires_at: None,
+        });
+        if version.version_counter >= record.version_counter {
+            record.version_counter = version.version_counter;
+            record.transaction_id = version.transaction_id;
+            record.value = value;
+            record.expires_at = expires_at;
+        }
+    }
+
+    // The live values of the keys in `key_range` as of `epoch`.
+    fn scan_as_of(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Vec<(Bytes, Bytes)> {
+        let records = self.records.read().unwrap();
+        let mut live: Vec<(Bytes, Bytes)> = records
+            .iter()
+            .filter(|((id, key), _)| *id == range_id.range_id && key_range.includes(key.clone()))
+            .filter_map(|((_, key), versions)| {
+                let (_, record) = versions.range(..=epoch).next_back()?;
+                Some((key.clone(), record.live_value()?))
+            })
+            .collect();
+        live.sort();
+        live.truncate(limit.unwrap_or(usize::MAX));
+        live
+    }
+}
+
+impl Storage for InMemoryStorage {
+    async fn take_ownership_and_load_range(
+        &self,
+        range_id: FullRangeId,
+    ) -> Result<RangeInfo, Error> {
+        let mut leases = self.leases.write().unwrap();
+        let lease = leases.entry(range_id.range_id).or_insert(RangeLease {
+            leader_sequence_number: 0,
+            epoch_lease: (0, 0),
+            key_range: KeyRange {
+                lower_bound_inclusive: None,
+                upper_bound_exclusive: None,
+            },
+        });
+        lease.leader_sequence_number += 1;
+        Ok(RangeInfo {
+            id: range_id.range_id,
+            key_range: lease.key_range.clone(),
+            leader_sequence_number: lease.leader_sequence_number,
+            epoch_lease: lease.epoch_lease,
+        })
+    }
+
+    async fn renew_epoch_lease(
+        &self,
+        range_id: FullRangeId,
+        new_lease: EpochLease,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let mut leases = self.leases.write().unwrap();
+        match leases.get_mut(&range_id.range_id) {
+            None => Err(Error::RangeDoesNotExist),
+            Some(lease) if lease.leader_sequence_number != leader_sequence_number => {
+                Err(Error::RangeOwnershipLost)
+            }
+            Some(lease) => {
+                lease.epoch_lease = new_lease;
+                Ok(())
+            }
+        }
+    }
+
+    async fn create_range(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        epoch_lease: EpochLease,
+    ) -> Result<(), Error> {
+        self.leases
+            .write()
+            .unwrap()
+            .entry(range_id.range_id)
+            .or_insert(RangeLease {
+                leader_sequence_number: 0,
+                epoch_lease,
+                key_range,
+            });
+        Ok(())
+    }
+
+    async fn upsert(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        val: Bytes,
+        version: KeyVersion,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        self.write(range_id, key, Some(val), version, expires_at);
+        Ok(())
+    }
+
+    async fn delete(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        version: KeyVersion,
+    ) -> Result<(), Error> {
+        self.write(range_id, key, None, version, None);
+        Ok(())
+    }
+
+    async fn get(&self, range_id: FullRangeId, key: Bytes) -> Result<Option<Bytes>, Error> {
+        let records = self.records.read().unwrap();
+        Ok(records
+            .get(&(range_id.range_id, key))
+            .and_then(|versions| versions.values().next_back())
+            .and_then(Record::live_value))
+    }
+
+    async fn get_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        epoch: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        let records = self.records.read().unwrap();
+        Ok(records
+            .get(&(range_id.range_id, key))
+            .and_then(|versions| versions.range(..=epoch).next_back())
+            .and_then(|(_, record)| record.live_value()))
+    }
+
+    async fn scan(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        Ok(self.scan_as_of(range_id, key_range, limit, u64::MAX))
+    }
+
+    async fn scan_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        Ok(self.scan_as_of(range_id, key_range, limit, epoch))
+    }
+
+    async fn get_versions(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        limit: usize,
+    ) -> Result<Vec<RecordVersion>, Error> {
+        let records = self.records.read().unwrap();
+        Ok(records
+            .get(&(range_id.range_id, key))
+            .map(|versions| {
+                versions
+                    .iter()
+                    .rev()
+                    .take(limit)
+                    .map(|(epoch, record)| RecordVersion {
+                        epoch: *epoch,
+                        transaction_id: Some(record.transaction_id),
+                        value: record.value.clone(),
+                        expires_at: record.expires_at,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn scan_versions(
+        &self,
+        range_id: FullRangeId,
+    ) -> Result<Vec<(Bytes, RecordVersion)>, Error> {
+        let records = self.records.read().unwrap();
+        let mut keys: Vec<&Bytes> = records
+            .keys()
+            .filter(|(id, _)| *id == range_id.range_id)
+            .map(|(_, key)| key)
+            .collect();
+        keys.sort();
+        let mut versions = Vec::new();
+        for key in keys {
+            for (epoch, record) in records[&(range_id.range_id, key.clone())].iter().rev() {
+                versions.push((
+                    key.clone(),
+                    RecordVersion {
+                        epoch: *epoch,
+                        transaction_id: Some(record.transaction_id),
+                        value: record.value.clone(),
+                        expires_at: record.expires_at,
+                    },
+                ));
+            }
+        }
+        Ok(versions)
+    }
+
+    async fn purge_versions(
+        &self,
+        range_id: FullRangeId,
+        purges: Vec<(Bytes, u64)>,
+    ) -> Result<(), Error> {
+        let mut records = self.records.write().unwrap();
+        for (key, up_to) in purges {
+            let record_id = (range_id.range_id, key);
+            if let Some(versions) = records.get_mut(&record_id) {
+                versions.retain(|epoch, _| *epoch > up_to);
+                if versions.is_empty() {
+                    records.remove(&record_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn persist_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        prepare: Bytes,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let leases = self.leases.read().unwrap();
+        Self::check_owner(&leases, range_id, leader_sequence_number)?;
+        self.prepares
+            .write()
+            .unwrap()
+            .entry(range_id.range_id)
+            .or_default()
+            .insert(transaction_id, prepare);
+        Ok(())
+    }
+
+    async fn remove_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let leases = self.leases.read().unwrap();
+        Self::check_owner(&leases, range_id, leader_sequence_number)?;
+        if let Some(prepares) = self.prepares.write().unwrap().get_mut(&range_id.range_id) {
+            prepares.remove(&transaction_id);
+        }
+        Ok(())
+    }
+
+    async fn load_prepares(&self, range_id: FullRangeId) -> Result<Vec<(Uuid, Bytes)>, Error> {
+        Ok(self
+            .prepares
+            .read()
+            .unwrap()
+            .get(&range_id.range_id)
+            .map(|prepares| prepares.iter().map(|(id, p)| (*id, p.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    async fn check_reachable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub mod for_testing {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Like the Cassandra test context, but nothing outlives the storage, so
+    /// there is nothing to clean up.
+    pub struct TestContext {
+        pub keyspace_id: KeyspaceId,
+        pub range_id: Uuid,
+        pub storage: Arc<InMemoryStorage>,
+    }
+
+    pub async fn init() -> TestContext {
+        let storage = Arc::new(InMemoryStorage::new());
+        let range_id = Uuid::new_v4();
+        storage.leases.write().unwrap().insert(
+            range_id,
+            RangeLease {
+                leader_sequence_number: 0,
+                epoch_lease: (0, 0),
+                key_range: KeyRange {
+                    lower_bound_inclusive: None,
+                    upper_bound_exclusive: None,
+                },
+            },
+        );
+        TestContext {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id,
+            storage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::rangeserver::{RangeId, SnapshotRecord};
+
+    fn range_id() -> FullRangeId {
+        FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        }
+    }
+
+    fn version(version_counter: u64) -> KeyVersion {
+        KeyVersion {
+            epoch: 1,
+            version_counter,
+            transaction_id: Uuid::new_v4(),
+        }
+    }
+
+    #[tokio::test]
+    async fn new_owner_fences_the_previous_one() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let first = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        let second = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert!(second.leader_sequence_number > first.leader_sequence_number);
+        let res = storage
+            .renew_epoch_lease(range_id, (1, 10), first.leader_sequence_number)
+            .await;
+        assert!(matches!(res, Err(Error::RangeOwnershipLost)));
+        storage
+            .renew_epoch_lease(range_id, (1, 10), second.leader_sequence_number)
+            .await
+            .unwrap();
+        let third = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert_eq!(third.epoch_lease, (1, 10));
+    }
+
+    #[tokio::test]
+    async fn prepares_are_kept_until_removed() {
+        let storage = InMemoryStorage::new();
+        let (range_id, other_range_id) = (range_id(), range_id());
+        let (tx1, tx2) = (Uuid::new_v4(), Uuid::new_v4());
+        let prepare = Bytes::from_static(b"prepare");
+        let owner = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        let other_owner = storage
+            .take_ownership_and_load_range(other_range_id)
+            .await
+            .unwrap();
+        storage
+            .persist_prepare(range_id, tx1, prepare.clone(), owner.leader_sequence_number)
+            .await
+            .unwrap();
+        storage
+            .persist_prepare(
+                other_range_id,
+                tx2,
+                prepare.clone(),
+                other_owner.leader_sequence_number,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.load_prepares(range_id).await.unwrap(),
+            vec![(tx1, prepare.clone())]
+        );
+        storage
+            .remove_prepare(range_id, tx1, owner.leader_sequence_number)
+            .await
+            .unwrap();
+        assert!(storage.load_prepares(range_id).await.unwrap().is_empty());
+        assert_eq!(
+            storage.load_prepares(other_range_id).await.unwrap(),
+            vec![(tx2, prepare)]
+        );
+    }
+
+    #[tokio::test]
+    async fn previous_owner_cannot_touch_prepares() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let transaction_id = Uuid::new_v4();
+        let prepare = Bytes::from_static(b"prepare");
+        let first = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        storage
+            .persist_prepare(
+                range_id,
+                transaction_id,
+                prepare.clone(),
+                first.leader_sequence_number,
+            )
+            .await
+            .unwrap();
+        storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        let res = storage
+            .persist_prepare(
+                range_id,
+                Uuid::new_v4(),
+                prepare.clone(),
+                first.leader_sequence_number,
+            )
+            .await;
+        assert!(matches!(res, Err(Error::RangeOwnershipLost)));
+        let res = storage
+            .remove_prepare(range_id, transaction_id, first.leader_sequence_number)
+            .await;
+        assert!(matches!(res, Err(Error::RangeOwnershipLost)));
+        assert_eq!(
+            storage.load_prepares(range_id).await.unwrap(),
+            vec![(transaction_id, prepare)]
+        );
+    }
+
+    #[tokio::test]
+    async fn created_range_keeps_its_lease_and_key_range() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let key_range = KeyRange {
+            lower_bound_inclusive: Some(Bytes::from_static(b"m")),
+            upper_bound_exclusive: None,
+        };
+        storage
+            .create_range(range_id, key_range.clone(), (5, 10))
+            .await
+            .unwrap();
+        // Creating it again does nothing.
+        storage
+            .create_range(
+                range_id,
+                KeyRange {
+                    lower_bound_inclusive: None,
+                    upper_bound_exclusive: None,
+                },
+                (0, 0),
+            )
+            .await
+            .unwrap();
+        let loaded = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert_eq!(loaded.epoch_lease, (5, 10));
+        assert_eq!(loaded.key_range, key_range);
+    }
+
+    #[tokio::test]
+    async fn latest_version_wins() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let key = Bytes::from_static(b"key");
+        storage
+            .upsert(
+                range_id,
+                key.clone(),
+                Bytes::from_static(b"new"),
+                version(2),
+                None,
+            )
+            .await
+            .unwrap();
+        storage
+            .upsert(
+                range_id,
+                key.clone(),
+                Bytes::from_static(b"old"),
+                version(1),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get(range_id, key.clone()).await.unwrap(),
+            Some(Bytes::from_static(b"new"))
+        );
+        storage
+            .delete(range_id, key.clone(), version(3))
+            .await
+            .unwrap();
+        assert_eq!(storage.get(range_id, key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn versions_are_kept_per_epoch() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let key = Bytes::from_static(b"key");
+        let first = version(1);
+        let second = KeyVersion {
+            epoch: 2,
+            ..version(1)
+        };
+        storage
+            .upsert(range_id, key.clone(), Bytes::from_static(b"a"), first, None)
+            .await
+            .unwrap();
+        storage.delete(range_id, key.clone(), second).await.unwrap();
+        assert_eq!(storage.get(range_id, key.clone()).await.unwrap(), None);
+        let versions = storage
+            .get_versions(range_id, key.clone(), 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            versions,
+            vec![
+                RecordVersion {
+                    epoch: 2,
+                    transaction_id: Some(second.transaction_id),
+                    value: None,
+                    expires_at: None,
+                },
+                RecordVersion {
+                    epoch: 1,
+                    transaction_id: Some(first.transaction_id),
+                    value: Some(Bytes::from_static(b"a")),
+                    expires_at: None,
+                },
+            ]
+        );
+        assert_eq!(
+            storage.get_versions(range_id, key, 1).await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn get_at_epoch_reads_the_latest_version_up_to_it() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let key = Bytes::from_static(b"key");
+        for (epoch, value) in [(2, "a"), (4, "b")] {
+            let version = KeyVersion {
+                epoch,
+                ..version(1)
+            };
+            storage
+                .upsert(range_id, key.clone(), Bytes::from(value), version, None)
+                .await
+                .unwrap();
+        }
+        let deleted = KeyVersion {
+            epoch: 6,
+            ..version(1)
+        };
+        storage
+            .delete(range_id, key.clone(), deleted)
+            .await
+            .unwrap();
+        let at = |epoch| storage.get_at_epoch(range_id, key.clone(), epoch);
+        assert_eq!(at(1).await.unwrap(), None);
+        assert_eq!(at(2).await.unwrap(), Some(Bytes::from_static(b"a")));
+        assert_eq!(at(3).await.unwrap(), Some(Bytes::from_static(b"a")));
+        assert_eq!(at(5).await.unwrap(), Some(Bytes::from_static(b"b")));
+        assert_eq!(at(6).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn scan_at_epoch_reads_the_latest_versions_up_to_it() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        for (key, epoch, value) in [("a", 2, "a1"), ("a", 4, "a2"), ("b", 3, "b"), ("c", 6, "c")] {
+            let version = KeyVersion {
+                epoch,
+                ..version(1)
+            };
+            storage
+                .upsert(
+                    range_id,
+                    Bytes::from(key),
+                    Bytes::from(value),
+                    version,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let deleted = KeyVersion {
+            epoch: 5,
+            ..version(1)
+        };
+        storage
+            .delete(range_id, Bytes::from_static(b"b"), deleted)
+            .await
+            .unwrap();
+        let at = |epoch, limit| storage.scan_at_epoch(range_id, KeyRange::all(), limit, epoch);
+        let record =
+            |key: &'static str, value: &'static str| (Bytes::from(key), Bytes::from(value));
+        assert_eq!(at(1, None).await.unwrap(), vec![]);
+        assert_eq!(at(2, None).await.unwrap(), vec![record("a", "a1")]);
+        assert_eq!(
+            at(4, None).await.unwrap(),
+            vec![record("a", "a2"), record("b", "b")]
+        );
+        assert_eq!(at(4, Some(1)).await.unwrap(), vec![record("a", "a2")]);
+        assert_eq!(
+            at(6, None).await.unwrap(),
+            vec![record("a", "a2"), record("c", "c")]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_returns_live_keys_in_order() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        for (i, key) in [b"d", b"a", b"c", b"b"].iter().enumerate() {
+            storage
+                .upsert(
+                    range_id,
+                    Bytes::from_static(*key),
+                    Bytes::from_static(*key),
+                    version(i as u64),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .delete(range_id, Bytes::from_static(b"c"), version(10))
+            .await
+            .unwrap();
+        let key_range = KeyRange {
+            lower_bound_inclusive: Some(Bytes::from_static(b"b")),
+            upper_bound_exclusive: None,
+        };
+        let keys = |records: Vec<(Bytes, Bytes)>| {
+            records.into_iter().map(|(key, _)| key).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(
+                storage
+                    .scan(range_id, key_range.clone(), None)
+                    .await
+                    .unwrap()
+            ),
+            vec![Bytes::from_static(b"b"), Bytes::from_static(b"d")]
+        );
+        assert_eq!(
+            keys(
+                storage
+                    .scan(range_id, KeyRange::all(), Some(2))
+                    .await
+                    .unwrap()
+            ),
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_values_are_not_read() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let expired = Bytes::from_static(b"expired");
+        let live = Bytes::from_static(b"live");
+        let now = Utc::now();
+        storage
+            .upsert(
+                range_id,
+                expired.clone(),
+                Bytes::from_static(b"1"),
+                version(1),
+                Some(now - chrono::Duration::seconds(1)),
+            )
+            .await
+            .unwrap();
+        storage
+            .upsert(
+                range_id,
+                live.clone(),
+                Bytes::from_static(b"2"),
+                version(1),
+                Some(now + chrono::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.get(range_id, expired.clone()).await.unwrap(), None);
+        assert_eq!(
+            storage.get(range_id, live.clone()).await.unwrap(),
+            Some(Bytes::from_static(b"2"))
+        );
+        assert_eq!(
+            storage.scan(range_id, KeyRange::all(), None).await.unwrap(),
+            vec![(live, Bytes::from_static(b"2"))]
+        );
+        // The expired version is kept until purged.
+        let versions = storage.get_versions(range_id, expired, 10).await.unwrap();
+        assert_eq!(versions.len(), 1);
+        assert!(is_expired(versions[0].expires_at, now));
+    }
+
+    #[tokio::test]
+    async fn purge_removes_versions_up_to_epoch() {
+        let storage = InMemoryStorage::new();
+        let range_id = range_id();
+        let key = Bytes::from_static(b"key");
+        for epoch in 1..=3 {
+            let version = KeyVersion {
+                epoch,
+                ..version(1)
+            };
+            storage
+                .upsert(
+                    range_id,
+                    key.clone(),
+                    Bytes::from(vec![epoch as u8]),
+                    version,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .purge_versions(range_id, vec![(key.clone(), 2)])
+            .await
+            .unwrap();
+        let versions = storage
+            .get_versions(range_id, key.clone(), 10)
+            .await
+            .unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].epoch, 3);
+
+        storage
+            .purge_versions(range_id, vec![(key.clone(), 3)])
+            .await
+            .unwrap();
+        assert!(storage.scan_versions(range_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn snapshot_round_trip() {
+        let source = InMemoryStorage::new();
+        let range_id = range_id();
+        let info = source
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        let first = version(1);
+        let second = KeyVersion {
+            epoch: 2,
+            ..version(1)
+        };
+        source
+            .upsert(
+                range_id,
+                Bytes::from_static(b"b"),
+                Bytes::from_static(b"1"),
+                first,
+                None,
+            )
+            .await
+            .unwrap();
+        source
+            .upsert(
+                range_id,
+                Bytes::from_static(b"a"),
+                Bytes::from_static(b"2"),
+                first,
+                None,
+            )
+            .await
+            .unwrap();
+        source
+            .delete(range_id, Bytes::from_static(b"a"), second)
+            .await
+            .unwrap();
+        let versions = source.scan_versions(range_id).await.unwrap();
+        let keys: Vec<(&[u8], u64)> = versions
+            .iter()
+            .map(|(key, version)| (key.as_ref(), version.epoch))
+            .collect();
+        assert_eq!(keys, vec![(&b"a"[..], 2), (&b"a"[..], 1), (&b"b"[..], 1)]);
+
+        let snapshot = RangeSnapshot {
+            format_version: RANGE_SNAPSHOT_FORMAT_VERSION,
+            range: Some(RangeId {
+                keyspace_id: range_id.keyspace_id.id.to_string(),
+                range_id: range_id.range_id.to_string(),
+            }),
+            key_lower_bound_inclusive: None,
+            key_upper_bound_exclusive: None,
+            leader_sequence_number: info.leader_sequence_number,
+            epoch_lease_lower_bound: 3,
+            epoch_lease_upper_bound: 5,
+            highest_known_epoch: 2,
+            records: versions
+                .into_iter()
+                .map(|(key, version)| SnapshotRecord {
+                    key: key.to_vec(),
+                    epoch: version.epoch,
+                    transaction_id: version.transaction_id.map(|id| id.to_string()),
+                    value: version.value.map(|value| value.to_vec()),
+                    expires_at_us: version
+                        .expires_at
+                        .map(|expires_at| expires_at.timestamp_micros() as u64),
+                })
+                .collect(),
+            prepared_transactions: vec![],
+            taken_at_us: 0,
+        };
+        let target = InMemoryStorage::new();
+        assert_eq!(target.load_snapshot(&snapshot).unwrap(), range_id);
+        assert_eq!(
+            target
+                .get(range_id, Bytes::from_static(b"a"))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            target
+                .get(range_id, Bytes::from_static(b"b"))
+                .await
+                .unwrap(),
+            Some(Bytes::from_static(b"1"))
+        );
+        assert_eq!(
+            target
+                .get_versions(range_id, Bytes::from_static(b"a"), 10)
+                .await
+                .unwrap(),
+            source
+                .get_versions(range_id, Bytes::from_static(b"a"), 10)
+                .await
+                .unwrap()
+        );
+        let loaded = target
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            loaded.leader_sequence_number,
+            info.leader_sequence_number + 1
+        );
+        assert_eq!(loaded.epoch_lease, (3, 5));
+
+        let future = RangeSnapshot {
+            format_version: RANGE_SNAPSHOT_FORMAT_VERSION + 1,
+            ..snapshot
+        };
+        assert!(target.load_snapshot(&future).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Copies ranges from one storage backend to another while range servers keep
+//! serving them from the first one, to switch backends without downtime.
+//!
+//! A range is copied in passes. The first pass copies every version the
+//! source holds. Commits are applied to a range one at a time, in epoch
+//! order, so every version written since a pass is of an epoch at least as
+//! high as the highest one the pass copied, and the next pass only copies
+//! those. Passes get shorter as they catch up, until only the writes of the
+//! last few epochs are left. The range is then frozen at its range server,
+//! and once its prepared transactions finish, `RangeMigration::cut_over`
+//! copies what is left and creates the range in the destination. From then
+//! on, whoever loads the range from the destination sees all of its data.
+//!
+//! Versions purged from the source between passes are still in the
+//! destination, but newer versions hide them there as well, and the next
+//! compaction of the range removes them.
+
+use common::full_range_id::FullRangeId;
+use common::key_range::KeyRange;
+use uuid::Uuid;
+
+use crate::key_version::KeyVersion;
+use crate::storage::{Error, Storage};
+
+/// What copying a range so far did.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MigrationProgress {
+    pub passes: u32,
+    pub versions_copied: usize,
+    /// The versions of epochs below this one are all in the destination.
+    pub next_epoch: u64,
+}
+
+/// Copies one range from `source` to `destination`.
+pub struct RangeMigration<'a, S: Storage, D: Storage> {
+    source: &'a S,
+    destination: &'a D,
+    range_id: FullRangeId,
+    progress: MigrationProgress,
+}
+
+impl<'a, S: Storage, D: Storage> RangeMigration<'a, S, D> {
+    pub fn new(source: &'a S, destination: &'a D, range_id: FullRangeId) -> Self {
+        RangeMigration {
+            source,
+            destination,
+            range_id,
+            progress: MigrationProgress::default(),
+        }
+    }
+
+    pub fn progress(&self) -> MigrationProgress {
+        self.progress
+    }
+
+    /// Copies the versions written since the previous pass, or all of them on
+    /// the first one, and returns how many it copied.
+    pub async fn copy_pass(&mut self) -> Result<usize, Error> {
+        let versions = self.source.scan_versions(self.range_id).await?;
+        let mut copied = 0;
+        let mut highest_epoch = None;
+        for (key, version) in versions {
+            if version.epoch < self.progress.next_epoch {
+                continue;
+            }
+            highest_epoch = highest_epoch.max(Some(version.epoch));
+            let key_version = KeyVersion {
+                epoch: version.epoch,
+                version_counter: 0,
+                transaction_id: version.transaction_id.unwrap_or(Uuid::nil()),
+            };
+            match version.value {
+                Some(value) => {
+                    self.destination
+                        .upsert(self.range_id, key, value, key_version, version.expires_at)
+                        .await?
+                }
+                None => {
+                    self.destination
+                        .delete(self.range_id, key, key_version)
+                        .await?
+                }
+            }
+            copied += 1;
+        }
+        // The highest epoch copied may still get more writes, so the next
+        // pass copies it again.
+        if let Some(epoch) = highest_epoch {
+            self.progress.next_epoch = epoch;
+        }
+        self.progress.passes += 1;
+        self.progress.versions_copied += copied;
+        Ok(copied)
+    }
+
+    /// Runs passes until one copies at most `small_enough` versions, or
+    /// `max_passes` passes ran, and returns what the last one copied. Runs at
+    /// least one pass.
+    pub async fn catch_up(&mut self, small_enough: usize, max_passes: u32) -> Result<usize, Error> {
+        let mut copied = self.copy_pass().await?;
+        for _ in 1..max_passes {
+            if copied <= small_enough {
+                break;
+            }
+            copied = self.copy_pass().await?;
+        }
+        Ok(copied)
+    }
+
+    /// Copies what is left of the range, creates it in the destination
+    /// covering `key_range`, and then copies its prepared transactions.
+    /// The range must not take commits anymore, e.g. be frozen with its
+    /// prepared transactions finished, or else they may be lost.
+    pub async fn cut_over(&mut self, key_range: KeyRange) -> Result<MigrationProgress, Error> {
+        self.copy_pass().await?;
+        let epoch = self.progress.next_epoch;
+        self.destination
+            .create_range(self.range_id, key_range, (epoch, epoch))
+            .await?;
+        // Under the leader sequence number of the range just created, which
+        // nothing loaded yet.
+        for (transaction_id, prepare) in self.source.load_prepares(self.range_id).await? {
+            self.destination
+                .persist_prepare(self.range_id, transaction_id, prepare, 0)
+                .await?;
+        }
+        Ok(self.progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::in_memory::InMemoryStorage;
+    use bytes::Bytes;
+    use common::keyspace_id::KeyspaceId;
+
+    fn range_id() -> FullRangeId {
+        FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        }
+    }
+
+    fn version(epoch: u64) -> KeyVersion {
+        KeyVersion {
+            epoch,
+            version_counter: 0,
+            transaction_id: Uuid::new_v4(),
+        }
+    }
+
+    fn key(k: &'static str) -> Bytes {
+        Bytes::from_static(k.as_bytes())
+    }
+
+    #[tokio::test]
+    async fn passes_only_copy_what_was_written_since() {
+        let (source, destination) = (InMemoryStorage::new(), InMemoryStorage::new());
+        let range_id = range_id();
+        source
+            .upsert(range_id, key("a"), key("1"), version(1), None)
+            .await
+            .unwrap();
+        source
+            .upsert(range_id, key("b"), key("1"), version(2), None)
+            .await
+            .unwrap();
+        let mut migration = RangeMigration::new(&source, &destination, range_id);
+        assert_eq!(migration.copy_pass().await.unwrap(), 2);
+
+        source
+            .upsert(range_id, key("a"), key("2"), version(3), None)
+            .await
+            .unwrap();
+        source.delete(range_id, key("b"), version(3)).await.unwrap();
+        // Each pass copies the versions of the highest epoch again, in case
+        // that epoch got more writes since.
+        assert_eq!(migration.catch_up(2, 10).await.unwrap(), 2);
+        assert_eq!(
+            migration.progress(),
+            MigrationProgress {
+                passes: 3,
+                versions_copied: 7,
+                next_epoch: 3
+            }
+        );
+        assert_eq!(
+            destination.scan_versions(range_id).await.unwrap(),
+            source.scan_versions(range_id).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn cut_over_creates_the_range_with_its_prepares() {
+        let (source, destination) = (InMemoryStorage::new(), InMemoryStorage::new());
+        let range_id = range_id();
+        let key_range = KeyRange {
+            lower_bound_inclusive: Some(key("a")),
+            upper_bound_exclusive: Some(key("m")),
+        };
+        source
+            .upsert(range_id, key("b"), key("1"), version(7), None)
+            .await
+            .unwrap();
+        let transaction_id = Uuid::new_v4();
+        let owner = source
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        source
+            .persist_prepare(
+                range_id,
+                transaction_id,
+                key("prepare"),
+                owner.leader_sequence_number,
+            )
+            .await
+            .unwrap();
+
+        let mut migration = RangeMigration::new(&source, &destination, range_id);
+        migration.copy_pass().await.unwrap();
+        migration.cut_over(key_range.clone()).await.unwrap();
+        assert_eq!(
+            destination.get(range_id, key("b")).await.unwrap(),
+            Some(key("1"))
+        );
+        assert_eq!(
+            destination.load_prepares(range_id).await.unwrap(),
+            vec![(transaction_id, key("prepare"))]
+        );
+        let range_info = destination
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert_eq!(range_info.key_range, key_range);
+        assert_eq!(range_info.epoch_lease, (7, 7));
+    }
+}
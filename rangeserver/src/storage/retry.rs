@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use super::Error;
+
+/// How storage operations are retried, and when to stop sending them to a
+/// storage node that keeps failing.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Number of consecutive retryable failures after which the circuit
+    /// breaker opens.
+    pub breaker_failure_threshold: u32,
+    /// How long the circuit breaker stays open before letting operations
+    /// through again.
+    pub breaker_open_duration: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(500),
+            breaker_failure_threshold: 10,
+            breaker_open_duration: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("circuit breakers for storage nodes {0} are open")]
+struct CircuitOpen(String);
+
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Trips for a while after a storage node failed too many times in a row.
+struct CircuitBreaker {
+    node: String,
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        // Once the open duration has passed, operations go through again and
+        // the first failure reopens the breaker.
+        matches!(state.open_until, Some(open_until) if Instant::now() < open_until)
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.failure_threshold {
+            if state.open_until.is_none() {
+                warn!(node = self.node, "Opening storage circuit breaker");
+            }
+            state.open_until = Some(Instant::now() + self.open_duration);
+        }
+    }
+}
+
+/// Runs storage operations against a cluster of storage nodes, retrying the
+/// ones that failed for transient reasons.
+///
+/// Each node has its own circuit breaker, keyed by the node that served or
+/// failed an attempt. Operations fail fast only once the breakers of all the
+/// nodes seen so far are open, so that callers don't pile up on timeouts,
+/// while a single failing node doesn't stop operations the others can serve.
+pub struct Retrier {
+    policy: RetryPolicy,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl Retrier {
+    pub fn new(policy: RetryPolicy) -> Retrier {
+        Retrier {
+            policy,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn breaker(&self, node: String) -> Arc<CircuitBreaker> {
+        let mut breakers = self.breakers.lock().unwrap();
+        breakers
+            .entry(node)
+            .or_insert_with_key(|node| {
+                Arc::new(CircuitBreaker {
+                    node: node.clone(),
+                    failure_threshold: self.policy.breaker_failure_threshold,
+                    open_duration: self.policy.breaker_open_duration,
+                    state: Mutex::new(BreakerState {
+                        consecutive_failures: 0,
+                        open_until: None,
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let breakers = self.breakers.lock().unwrap();
+        if breakers.is_empty() || breakers.values().any(|breaker| !breaker.is_open()) {
+            return Ok(());
+        }
+        let mut nodes: Vec<&str> = breakers.keys().map(String::as_str).collect();
+        nodes.sort();
+        Err(Error::Unavailable(Arc::new(CircuitOpen(nodes.join(", ")))))
+    }
+
+    /// Awaits a single attempt at an operation, which returns the node that
+    /// served or failed it, if it got as far as one, along with its result.
+    async fn attempt<T, Fut>(&self, op: Fut) -> Result<T, Error>
+    where
+        Fut: Future<Output = (Option<String>, Result<T, Error>)>,
+    {
+        let (node, res) = op.await;
+        if let Some(node) = node {
+            match &res {
+                Ok(_) => self.breaker(node).record_success(),
+                Err(e) if e.is_retryable() => self.breaker(node).record_failure(),
+                Err(_) => (),
+            }
+        }
+        res
+    }
+
+    /// Runs `op` until it succeeds, fails with a non-retryable error, or runs
+    /// out of attempts. `op` may get called several times, so it must be
+    /// idempotent.
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = (Option<String>, Result<T, Error>)>,
+    {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            self.check()?;
+            match self.attempt(op()).await {
+                Err(e) if e.is_retryable() && attempt < self.policy.max_attempts => (),
+                res => return res,
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, self.policy.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    /// Runs `op` once. For operations that are not idempotent, like
+    /// lightweight transactions, which may have been applied even though
+    /// they timed out and could fail their condition if applied again.
+    pub async fn run_once<T, Fut>(&self, op: Fut) -> Result<T, Error>
+    where
+        Fut: Future<Output = (Option<String>, Result<T, Error>)>,
+    {
+        self.check()?;
+        self.attempt(op).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn test_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            breaker_failure_threshold: 5,
+            breaker_open_duration: Duration::from_millis(50),
+        }
+    }
+
+    fn fatal_error() -> Error {
+        Error::InternalError(Arc::new(std::fmt::Error))
+    }
+
+    fn served_by<T>(node: &str, res: Result<T, Error>) -> (Option<String>, Result<T, Error>) {
+        (Some(node.to_string()), res)
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let retrier = Retrier::new(test_policy());
+        let calls = AtomicU32::new(0);
+        let res = retrier
+            .run(|| async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    served_by("node", Err(Error::Timeout))
+                } else {
+                    served_by("node", Ok(42))
+                }
+            })
+            .await;
+        assert_eq!(res.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let retrier = Retrier::new(test_policy());
+        let calls = AtomicU32::new(0);
+        let res: Result<(), Error> = retrier
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                served_by("node", Err(Error::Timeout))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::Timeout)));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_fatal_errors() {
+        let retrier = Retrier::new(test_policy());
+        let calls = AtomicU32::new(0);
+        let res: Result<(), Error> = retrier
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                served_by("node", Err(fatal_error()))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::InternalError(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn run_once_does_not_retry() {
+        let retrier = Retrier::new(test_policy());
+        let calls = AtomicU32::new(0);
+        let res: Result<(), Error> = retrier
+            .run_once(async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                served_by("node", Err(Error::Timeout))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::Timeout)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_and_recovers() {
+        let retrier = Retrier::new(test_policy());
+        for _ in 0..2 {
+            let _: Result<(), Error> = retrier
+                .run(|| async { served_by("node", Err(Error::Timeout)) })
+                .await;
+        }
+        // The breaker is open, so the operation is not even attempted.
+        let calls = AtomicU32::new(0);
+        let res = retrier
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                served_by("node", Ok(()))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::Unavailable(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(retrier
+            .run_once(async { served_by("node", Ok(())) })
+            .await
+            .is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(retrier
+            .run(|| async { served_by("node", Ok(())) })
+            .await
+            .is_ok());
+        assert!(retrier
+            .run(|| async { served_by("node", Ok(())) })
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn breakers_are_per_node() {
+        let retrier = Retrier::new(test_policy());
+        assert!(retrier
+            .run(|| async { served_by("b", Ok(())) })
+            .await
+            .is_ok());
+        for _ in 0..2 {
+            let _: Result<(), Error> = retrier
+                .run(|| async { served_by("a", Err(Error::Timeout)) })
+                .await;
+        }
+        // Node a's breaker is open, but b can still serve operations.
+        assert!(retrier
+            .run(|| async { served_by("b", Ok(())) })
+            .await
+            .is_ok());
+
+        for _ in 0..2 {
+            let _: Result<(), Error> = retrier
+                .run(|| async { served_by("b", Err(Error::Timeout)) })
+                .await;
+        }
+        // Now every node's breaker is open.
+        let calls = AtomicU32::new(0);
+        let res = retrier
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                served_by("a", Ok(()))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::Unavailable(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,905 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use ::rocksdb::{ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use common::full_range_id::FullRangeId;
+use common::key_range::KeyRange;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{is_expired, EpochLease, Error, RangeInfo, RecordVersion, Storage};
+use crate::key_version::KeyVersion;
+
+/// Every version of every record, keyed by range, then key, then epoch with
+/// the newest first.
+pub const RECORDS_CF: &str = "records";
+/// The lease of each range, keyed by range.
+pub const RANGE_LEASES_CF: &str = "range_leases";
+/// The write-ahead logs, see `wal::rocksdb`.
+pub const WAL_CF: &str = "wal";
+/// The prepare records of the transactions prepared on each range, keyed by
+/// range and then transaction.
+pub const PREPARED_CF: &str = "prepared_transactions";
+
+#[derive(Serialize, Deserialize)]
+struct RangeLease {
+    leader_sequence_number: u64,
+    epoch_lease: EpochLease,
+    key_lower_bound_inclusive: Option<Vec<u8>>,
+    key_upper_bound_exclusive: Option<Vec<u8>>,
+}
+
+fn internal_error(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::InternalError(Arc::new(e))
+}
+
+// Keys are escaped so that keys sort the same way once encoded, even when one
+// is a prefix of another: zero bytes become 0x00 0xff and the key ends with
+// 0x00 0x00.
+fn encode_key(range_id: Uuid, key: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(16 + key.len() + 10);
+    encoded.extend_from_slice(range_id.as_bytes());
+    for byte in key {
+        encoded.push(*byte);
+        if *byte == 0 {
+            encoded.push(0xff);
+        }
+    }
+    encoded.extend_from_slice(&[0, 0]);
+    encoded
+}
+
+fn encode_record_key(range_id: Uuid, key: &[u8], epoch: u64) -> Vec<u8> {
+    let mut encoded = encode_key(range_id, key);
+    encoded.extend_from_slice(&(u64::MAX - epoch).to_be_bytes());
+    encoded
+}
+
+/// Sorts after every version of the key, and before any other key.
+fn encode_key_end(range_id: Uuid, key: &[u8]) -> Vec<u8> {
+    let mut encoded = encode_key(range_id, key);
+    *encoded.last_mut().unwrap() = 1;
+    encoded
+}
+
+fn decode_record_key(encoded: &[u8]) -> (Bytes, u64) {
+    let mut key = Vec::new();
+    let mut i = 16;
+    loop {
+        let byte = encoded[i];
+        if byte == 0 {
+            if encoded[i + 1] == 0 {
+                break;
+            }
+            i += 1;
+        }
+        key.push(byte);
+        i += 1;
+    }
+    let epoch = u64::MAX - u64::from_be_bytes(encoded[i + 2..i + 10].try_into().unwrap());
+    (Bytes::from(key), epoch)
+}
+
+struct Record {
+    version_counter: u64,
+    transaction_id: Uuid,
+    // None for a tombstone.
+    value: Option<Bytes>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// The byte after the transaction id tells what follows it.
+const TOMBSTONE: u8 = 0;
+const VALUE: u8 = 1;
+// The expiry, in microseconds since the Unix epoch, and then the value.
+const EXPIRING_VALUE: u8 = 2;
+
+impl Record {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(33 + self.value.as_ref().map_or(0, |v| v.len()));
+        encoded.extend_from_slice(&self.version_counter.to_be_bytes());
+        encoded.extend_from_slice(self.transaction_id.as_bytes());
+        match (&self.value, self.expires_at) {
+            (None, _) => encoded.push(TOMBSTONE),
+            (Some(value), None) => {
+                encoded.push(VALUE);
+                encoded.extend_from_slice(value);
+            }
+            (Some(value), Some(expires_at)) => {
+                encoded.push(EXPIRING_VALUE);
+                encoded.extend_from_slice(&expires_at.timestamp_micros().to_be_bytes());
+                encoded.extend_from_slice(value);
+            }
+        }
+        encoded
+    }
+
+    fn decode(encoded: &[u8]) -> Record {
+        let (value, expires_at) = match encoded[24] {
+            TOMBSTONE => (None, None),
+            VALUE => (Some(Bytes::copy_from_slice(&encoded[25..])), None),
+            _ => {
+                let micros = i64::from_be_bytes(encoded[25..33].try_into().unwrap());
+                (
+                    Some(Bytes::copy_from_slice(&encoded[33..])),
+                    DateTime::from_timestamp_micros(micros),
+                )
+            }
+        };
+        Record {
+            version_counter: u64::from_be_bytes(encoded[..8].try_into().unwrap()),
+            transaction_id: Uuid::from_slice(&encoded[8..24]).unwrap(),
+            value,
+            expires_at,
+        }
+    }
+
+    fn live_value(self, now: DateTime<Utc>) -> Option<Bytes> {
+        if is_expired(self.expires_at, now) {
+            return None;
+        }
+        self.value
+    }
+}
+
+/// Storage on a local RocksDB database, for single-node and embedded
+/// deployments that should not need a Cassandra cluster.
+///
+/// As with `InMemoryStorage`, nothing creates the range leases ahead of time
+/// in such deployments, so a range that was never loaded before gets created
+/// covering the whole key space.
+pub struct RocksDbStorage {
+    db: Arc<DB>,
+    // Writes compare against the stored version before replacing it, and
+    // taking ownership of a range bumps its sequence number, so both are
+    // serialized.
+    write_lock: Mutex<()>,
+}
+
+impl RocksDbStorage {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<RocksDbStorage, Error> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(
+            &options,
+            path,
+            [RECORDS_CF, RANGE_LEASES_CF, WAL_CF, PREPARED_CF],
+        )
+        .map_err(internal_error)?;
+        Ok(RocksDbStorage {
+            db: Arc::new(db),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// The underlying database, to keep write-ahead logs in as well.
+    pub fn db(&self) -> Arc<DB> {
+        self.db.clone()
+    }
+
+    fn cf(&self, name: &str) -> &ColumnFamily {
+        self.db.cf_handle(name).unwrap()
+    }
+
+    fn get_lease(&self, range_id: Uuid) -> Result<Option<RangeLease>, Error> {
+        let lease = self
+            .db
+            .get_cf(self.cf(RANGE_LEASES_CF), range_id.as_bytes())
+            .map_err(internal_error)?;
+        lease
+            .map(|lease| serde_json::from_slice(&lease).map_err(internal_error))
+            .transpose()
+    }
+
+    fn put_lease(&self, range_id: Uuid, lease: &RangeLease) -> Result<(), Error> {
+        let lease = serde_json::to_vec(lease).map_err(internal_error)?;
+        self.db
+            .put_cf(self.cf(RANGE_LEASES_CF), range_id.as_bytes(), lease)
+            .map_err(internal_error)
+    }
+
+    // Must be called holding the write lock.
+    fn check_owner(&self, range_id: FullRangeId, leader_sequence_number: u64) -> Result<(), Error> {
+        match self.get_lease(range_id.range_id)? {
+            None => Err(Error::RangeDoesNotExist),
+            Some(lease) if lease.leader_sequence_number != leader_sequence_number => {
+                Err(Error::RangeOwnershipLost)
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    // The live values of the keys in `key_range` as of `epoch`.
+    fn scan_as_of(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let from = encode_key(
+            range_id.range_id,
+            key_range
+                .lower_bound_inclusive
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        let now = Utc::now();
+        let mut live = Vec::new();
+        let mut last_key: Option<Bytes> = None;
+        self.visit_versions(&from, |encoded_key, record| {
+            if live.len() >= limit || !encoded_key.starts_with(range_id.range_id.as_bytes()) {
+                return false;
+            }
+            let (key, key_epoch) = decode_record_key(encoded_key);
+            if key_range
+                .upper_bound_exclusive
+                .as_ref()
+                .is_some_and(|upper| key >= upper)
+            {
+                return false;
+            }
+            // Only the newest version of each key as of the epoch counts.
+            if key_epoch <= epoch && last_key.as_ref() != Some(&key) {
+                last_key = Some(key.clone());
+                if let Some(value) = record.live_value(now) {
+                    live.push((key, value));
+                }
+            }
+            true
+        })?;
+        Ok(live)
+    }
+
+    /// Visits the stored versions from `from` on, in order, for as long as
+    /// `visit` returns true.
+    fn visit_versions(
+        &self,
+        from: &[u8],
+        mut visit: impl FnMut(&[u8], Record) -> bool,
+    ) -> Result<(), Error> {
+        let iter = self.db.iterator_cf(
+            self.cf(RECORDS_CF),
+            IteratorMode::From(from, Direction::Forward),
+        );
+        for item in iter {
+            let (encoded_key, encoded_record) = item.map_err(internal_error)?;
+            if !visit(&encoded_key, Record::decode(&encoded_record)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        value: Option<Bytes>,
+        version: KeyVersion,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let record_key = encode_record_key(range_id.range_id, &key, version.epoch);
+        let _guard = self.write_lock.lock().unwrap();
+        // Writes with a lower version counter than the stored one are
+        // ignored, just like Cassandra does with write timestamps.
+        let existing = self
+            .db
+            .get_cf(self.cf(RECORDS_CF), &record_key)
+            .map_err(internal_error)?;
+        if existing.is_some_and(|r| Record::decode(&r).version_counter > version.version_counter) {
+            return Ok(());
+        }
+        let record = Record {
+            version_counter: version.version_counter,
+            transaction_id: version.transaction_id,
+            value,
+            expires_at,
+        };
+        self.db
+            .put_cf(self.cf(RECORDS_CF), record_key, record.encode())
+            .map_err(internal_error)
+    }
+}
+
+impl Storage for RocksDbStorage {
+    async fn take_ownership_and_load_range(
+        &self,
+        range_id: FullRangeId,
+    ) -> Result<RangeInfo, Error> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut lease = self.get_lease(range_id.range_id)?.unwrap_or(RangeLease {
+            leader_sequence_number: 0,
+            epoch_lease: (0, 0),
+            key_lower_bound_inclusive: None,
+            key_upper_bound_exclusive: None,
+        });
+        lease.leader_sequence_number += 1;
+        self.put_lease(range_id.range_id, &lease)?;
+        Ok(RangeInfo {
+            id: range_id.range_id,
+            key_range: KeyRange {
+                lower_bound_inclusive: lease.key_lower_bound_inclusive.map(Bytes::from),
+                upper_bound_exclusive: lease.key_upper_bound_exclusive.map(Bytes::from),
+            },
+            leader_sequence_number: lease.leader_sequence_number,
+            epoch_lease: lease.epoch_lease,
+        })
+    }
+
+    async fn renew_epoch_lease(
+        &self,
+        range_id: FullRangeId,
+        new_lease: EpochLease,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let _guard = self.write_lock.lock().unwrap();
+        match self.get_lease(range_id.range_id)? {
+            None => Err(Error::RangeDoesNotExist),
+            Some(lease) if lease.leader_sequence_number != leader_sequence_number => {
+                Err(Error::RangeOwnershipLost)
+            }
+            Some(mut lease) => {
+                lease.epoch_lease = new_lease;
+                self.put_lease(range_id.range_id, &lease)
+            }
+        }
+    }
+
+    async fn create_range(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        epoch_lease: EpochLease,
+    ) -> Result<(), Error> {
+        let _guard = self.write_lock.lock().unwrap();
+        if self.get_lease(range_id.range_id)?.is_some() {
+            return Ok(());
+        }
+        self.put_lease(
+            range_id.range_id,
+            &RangeLease {
+                leader_sequence_number: 0,
+                epoch_lease,
+                key_lower_bound_inclusive: key_range.lower_bound_inclusive.map(|b| b.to_vec()),
+                key_upper_bound_exclusive: key_range.upper_bound_exclusive.map(|b| b.to_vec()),
+            },
+        )
+    }
+
+    async fn upsert(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        val: Bytes,
+        version: KeyVersion,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        self.write(range_id, key, Some(val), version, expires_at)
+    }
+
+    async fn delete(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        version: KeyVersion,
+    ) -> Result<(), Error> {
+        self.write(range_id, key, None, version, None)
+    }
+
+    async fn get(&self, range_id: FullRangeId, key: Bytes) -> Result<Option<Bytes>, Error> {
+        let prefix = encode_key(range_id.range_id, &key);
+        let now = Utc::now();
+        let mut value = None;
+        self.visit_versions(&prefix, |encoded_key, record| {
+            if encoded_key.starts_with(&prefix) {
+                value = record.live_value(now);
+            }
+            false
+        })?;
+        Ok(value)
+    }
+
+    async fn get_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        epoch: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        // Versions are ordered newest first, so the first one from the
+        // version at `epoch` on is the newest at or below it.
+        let prefix = encode_key(range_id.range_id, &key);
+        let from = encode_record_key(range_id.range_id, &key, epoch);
+        let now = Utc::now();
+        let mut value = None;
+        self.visit_versions(&from, |encoded_key, record| {
+            if encoded_key.starts_with(&prefix) {
+                value = record.live_value(now);
+            }
+            false
+        })?;
+        Ok(value)
+    }
+
+    async fn scan(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        self.scan_as_of(range_id, key_range, limit, u64::MAX)
+    }
+
+    async fn scan_at_epoch(
+        &self,
+        range_id: FullRangeId,
+        key_range: KeyRange,
+        limit: Option<usize>,
+        epoch: u64,
+    ) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        self.scan_as_of(range_id, key_range, limit, epoch)
+    }
+
+    async fn get_versions(
+        &self,
+        range_id: FullRangeId,
+        key: Bytes,
+        limit: usize,
+    ) -> Result<Vec<RecordVersion>, Error> {
+        let prefix = encode_key(range_id.range_id, &key);
+        let mut versions = Vec::new();
+        self.visit_versions(&prefix, |encoded_key, record| {
+            if versions.len() >= limit || !encoded_key.starts_with(&prefix) {
+                return false;
+            }
+            let (_, epoch) = decode_record_key(encoded_key);
+            versions.push(RecordVersion {
+                epoch,
+                transaction_id: Some(record.transaction_id),
+                value: record.value,
+                expires_at: record.expires_at,
+            });
+            true
+        })?;
+        Ok(versions)
+    }
+
+    async fn scan_versions(
+        &self,
+        range_id: FullRangeId,
+    ) -> Result<Vec<(Bytes, RecordVersion)>, Error> {
+        let mut versions = Vec::new();
+        self.visit_versions(range_id.range_id.as_bytes(), |encoded_key, record| {
+            if !encoded_key.starts_with(range_id.range_id.as_bytes()) {
+                return false;
+            }
+            let (key, epoch) = decode_record_key(encoded_key);
+            versions.push((
+                key,
+                RecordVersion {
+                    epoch,
+                    transaction_id: Some(record.transaction_id),
+                    value: record.value,
+                    expires_at: record.expires_at,
+                },
+            ));
+            true
+        })?;
+        Ok(versions)
+    }
+
+    async fn purge_versions(
+        &self,
+        range_id: FullRangeId,
+        purges: Vec<(Bytes, u64)>,
+    ) -> Result<(), Error> {
+        let mut batch = WriteBatch::default();
+        for (key, up_to) in purges {
+            batch.delete_range_cf(
+                self.cf(RECORDS_CF),
+                encode_record_key(range_id.range_id, &key, up_to),
+                encode_key_end(range_id.range_id, &key),
+            );
+        }
+        let _guard = self.write_lock.lock().unwrap();
+        self.db.write(batch).map_err(internal_error)
+    }
+
+    async fn persist_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        prepare: Bytes,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.check_owner(range_id, leader_sequence_number)?;
+        let mut key = range_id.range_id.as_bytes().to_vec();
+        key.extend_from_slice(transaction_id.as_bytes());
+        self.db
+            .put_cf(self.cf(PREPARED_CF), key, prepare)
+            .map_err(internal_error)
+    }
+
+    async fn remove_prepare(
+        &self,
+        range_id: FullRangeId,
+        transaction_id: Uuid,
+        leader_sequence_number: u64,
+    ) -> Result<(), Error> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.check_owner(range_id, leader_sequence_number)?;
+        let mut key = range_id.range_id.as_bytes().to_vec();
+        key.extend_from_slice(transaction_id.as_bytes());
+        self.db
+            .delete_cf(self.cf(PREPARED_CF), key)
+            .map_err(internal_error)
+    }
+
+    async fn load_prepares(&self, range_id: FullRangeId) -> Result<Vec<(Uuid, Bytes)>, Error> {
+        let prefix = range_id.range_id.as_bytes();
+        let iter = self.db.iterator_cf(
+            self.cf(PREPARED_CF),
+            IteratorMode::From(prefix, Direction::Forward),
+        );
+        let mut prepares = Vec::new();
+        for item in iter {
+            let (key, prepare) = item.map_err(internal_error)?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let transaction_id = Uuid::from_slice(&key[prefix.len()..]).map_err(internal_error)?;
+            prepares.push((transaction_id, Bytes::from(prepare.into_vec())));
+        }
+        Ok(prepares)
+    }
+
+    async fn check_reachable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub mod for_testing {
+    use super::*;
+    use common::keyspace_id::KeyspaceId;
+    use std::path::PathBuf;
+
+    pub struct TestContext {
+        pub keyspace_id: KeyspaceId,
+        pub range_id: Uuid,
+        pub storage: Arc<RocksDbStorage>,
+        path: PathBuf,
+    }
+
+    impl Drop for TestContext {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// Opens a database in a fresh temporary directory, removed along with
+    /// the context.
+    pub async fn init() -> TestContext {
+        let path = std::env::temp_dir().join(format!("atomix-rocksdb-{}", Uuid::new_v4()));
+        let storage = Arc::new(RocksDbStorage::open(&path).unwrap());
+        TestContext {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+            storage,
+            path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::for_testing::*;
+    use super::*;
+
+    fn full_range_id(context: &TestContext) -> FullRangeId {
+        FullRangeId {
+            keyspace_id: context.keyspace_id,
+            range_id: context.range_id,
+        }
+    }
+
+    fn version(epoch: u64, version_counter: u64) -> KeyVersion {
+        KeyVersion {
+            epoch,
+            version_counter,
+            transaction_id: Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn encoded_keys_keep_their_order() {
+        let range_id = Uuid::new_v4();
+        let keys: [&[u8]; 5] = [b"", b"a", b"a\0", b"a\0\0", b"ab"];
+        for pair in keys.windows(2) {
+            assert!(
+                encode_record_key(range_id, pair[0], 0) < encode_record_key(range_id, pair[1], 7)
+            );
+            assert!(encode_key_end(range_id, pair[0]) < encode_key(range_id, pair[1]));
+        }
+        let encoded = encode_record_key(range_id, b"a\0b", 7);
+        assert_eq!(
+            decode_record_key(&encoded),
+            (Bytes::from_static(b"a\0b"), 7)
+        );
+    }
+
+    #[tokio::test]
+    async fn new_owner_fences_the_previous_one() {
+        let context = init().await;
+        let storage = context.storage.clone();
+        let range_id = full_range_id(&context);
+        let first = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        let second = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert!(second.leader_sequence_number > first.leader_sequence_number);
+        let res = storage
+            .renew_epoch_lease(range_id, (1, 10), first.leader_sequence_number)
+            .await;
+        assert!(matches!(res, Err(Error::RangeOwnershipLost)));
+        storage
+            .renew_epoch_lease(range_id, (1, 10), second.leader_sequence_number)
+            .await
+            .unwrap();
+        let third = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert_eq!(third.epoch_lease, (1, 10));
+    }
+
+    #[tokio::test]
+    async fn basic_crud() {
+        let context = init().await;
+        let storage = context.storage.clone();
+        let range_id = full_range_id(&context);
+        let key = Bytes::from_static(b"key");
+        assert_eq!(storage.get(range_id, key.clone()).await.unwrap(), None);
+
+        storage
+            .upsert(
+                range_id,
+                key.clone(),
+                Bytes::from_static(b"C"),
+                version(2, 1),
+                None,
+            )
+            .await
+            .unwrap();
+        // A lower version counter at the same epoch is ignored.
+        storage
+            .upsert(
+                range_id,
+                key.clone(),
+                Bytes::from_static(b"B"),
+                version(2, 0),
+                None,
+            )
+            .await
+            .unwrap();
+        // And so is a lower epoch, which is kept as an older version.
+        storage
+            .upsert(
+                range_id,
+                key.clone(),
+                Bytes::from_static(b"A"),
+                version(1, 5),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get(range_id, key.clone()).await.unwrap(),
+            Some(Bytes::from_static(b"C"))
+        );
+        storage
+            .upsert(
+                range_id,
+                key.clone(),
+                Bytes::from_static(b"D"),
+                version(2, 2),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get(range_id, key.clone()).await.unwrap(),
+            Some(Bytes::from_static(b"D"))
+        );
+
+        storage
+            .delete(range_id, key.clone(), version(3, 0))
+            .await
+            .unwrap();
+        assert_eq!(storage.get(range_id, key.clone()).await.unwrap(), None);
+        let versions = storage
+            .get_versions(range_id, key.clone(), 10)
+            .await
+            .unwrap();
+        let epochs: Vec<u64> = versions.iter().map(|v| v.epoch).collect();
+        assert_eq!(epochs, vec![3, 2, 1]);
+        assert_eq!(versions[0].value, None);
+        assert_eq!(
+            storage.get_versions(range_id, key, 1).await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_returns_live_keys_in_order() {
+        let context = init().await;
+        let storage = context.storage.clone();
+        let range_id = full_range_id(&context);
+        for (i, key) in [&b"d"[..], b"a", b"c", b"b", b"b\0"].iter().enumerate() {
+            storage
+                .upsert(
+                    range_id,
+                    Bytes::from_static(key),
+                    Bytes::from_static(key),
+                    version(1, i as u64),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .delete(range_id, Bytes::from_static(b"c"), version(2, 0))
+            .await
+            .unwrap();
+        let keys = |records: Vec<(Bytes, Bytes)>| {
+            records.into_iter().map(|(key, _)| key).collect::<Vec<_>>()
+        };
+        let key_range = KeyRange {
+            lower_bound_inclusive: Some(Bytes::from_static(b"b")),
+            upper_bound_exclusive: Some(Bytes::from_static(b"d")),
+        };
+        assert_eq!(
+            keys(storage.scan(range_id, key_range, None).await.unwrap()),
+            vec![Bytes::from_static(b"b"), Bytes::from_static(b"b\0")]
+        );
+        assert_eq!(
+            keys(
+                storage
+                    .scan(range_id, KeyRange::all(), Some(2))
+                    .await
+                    .unwrap()
+            ),
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_at_epoch_reads_the_latest_versions_up_to_it() {
+        let context = init().await;
+        let storage = context.storage.clone();
+        let range_id = full_range_id(&context);
+        for (key, epoch, value) in [("a", 2, "a1"), ("a", 4, "a2"), ("b", 3, "b"), ("c", 6, "c")] {
+            storage
+                .upsert(
+                    range_id,
+                    Bytes::from(key),
+                    Bytes::from(value),
+                    version(epoch, 0),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .delete(range_id, Bytes::from_static(b"b"), version(5, 0))
+            .await
+            .unwrap();
+        let at = |epoch| storage.scan_at_epoch(range_id, KeyRange::all(), None, epoch);
+        let record =
+            |key: &'static str, value: &'static str| (Bytes::from(key), Bytes::from(value));
+        assert_eq!(at(1).await.unwrap(), vec![]);
+        assert_eq!(at(2).await.unwrap(), vec![record("a", "a1")]);
+        assert_eq!(
+            at(4).await.unwrap(),
+            vec![record("a", "a2"), record("b", "b")]
+        );
+        assert_eq!(
+            at(6).await.unwrap(),
+            vec![record("a", "a2"), record("c", "c")]
+        );
+    }
+
+    #[tokio::test]
+    async fn purge_removes_versions_up_to_epoch() {
+        let context = init().await;
+        let storage = context.storage.clone();
+        let range_id = full_range_id(&context);
+        let key = Bytes::from_static(b"key");
+        for epoch in 1..=3 {
+            storage
+                .upsert(
+                    range_id,
+                    key.clone(),
+                    Bytes::from(vec![epoch as u8]),
+                    version(epoch, 0),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .upsert(
+                range_id,
+                Bytes::from_static(b"key\0"),
+                Bytes::new(),
+                version(1, 0),
+                None,
+            )
+            .await
+            .unwrap();
+        storage
+            .purge_versions(range_id, vec![(key.clone(), 2)])
+            .await
+            .unwrap();
+        let versions = storage.scan_versions(range_id).await.unwrap();
+        let epochs: Vec<(&[u8], u64)> = versions
+            .iter()
+            .map(|(key, version)| (key.as_ref(), version.epoch))
+            .collect();
+        assert_eq!(epochs, vec![(&b"key"[..], 3), (&b"key\0"[..], 1)]);
+    }
+
+    #[tokio::test]
+    async fn data_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("atomix-rocksdb-{}", Uuid::new_v4()));
+        let range_id = FullRangeId {
+            keyspace_id: common::keyspace_id::KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        };
+        let storage = RocksDbStorage::open(&path).unwrap();
+        let info = storage
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        storage
+            .upsert(
+                range_id,
+                Bytes::from_static(b"key"),
+                Bytes::from_static(b"value"),
+                version(1, 0),
+                None,
+            )
+            .await
+            .unwrap();
+        // The database can only be open once at a time.
+        drop(storage);
+
+        let reopened = RocksDbStorage::open(&path).unwrap();
+        assert_eq!(
+            reopened
+                .get(range_id, Bytes::from_static(b"key"))
+                .await
+                .unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+        let loaded = reopened
+            .take_ownership_and_load_range(range_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            loaded.leader_sequence_number,
+            info.leader_sequence_number + 1
+        );
+        drop(reopened);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
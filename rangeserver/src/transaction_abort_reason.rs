@@ -2,7 +2,16 @@ use strum::Display;
 
 #[derive(Clone, Debug, Display)]
 pub enum TransactionAbortReason {
-    WaitDie,
+    /// The transaction asked for a lock held or waited for by an older
+    /// transaction, which may in turn be waiting on one of its locks. Only
+    /// older transactions wait on younger ones, so waits never form a cycle.
+    Deadlock,
     TransactionLockLost,
+    /// Something committed on the range after the transaction read it
+    /// optimistically.
+    ReadConflict,
+    /// Something committed a key the snapshot transaction writes after its
+    /// snapshot.
+    WriteConflict,
     Other,
 }
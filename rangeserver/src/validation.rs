@@ -0,0 +1,327 @@
+use flatbuf::rangeserver_flatbuffers::range_server::PrepareRequest;
+use proto::universe::{ValidationPolicy as ProtoValidationPolicy, ValueFormat};
+
+// Bounds the recursion when checking nested values, so a malicious value
+// can't overflow the stack.
+const MAX_NESTING: usize = 128;
+
+/// The checks a keyspace runs on every write to it, see `ValidationPolicy` in
+/// universe.proto.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValidationPolicy {
+    max_key_size: Option<usize>,
+    max_value_size: Option<usize>,
+    value_format: ValueFormat,
+}
+
+impl ValidationPolicy {
+    pub fn from_proto(policy: &ProtoValidationPolicy) -> ValidationPolicy {
+        let limit = |size: u32| (size > 0).then_some(size as usize);
+        ValidationPolicy {
+            max_key_size: limit(policy.max_key_size),
+            max_value_size: limit(policy.max_value_size),
+            // Formats this build does not know about are not checked.
+            value_format: ValueFormat::try_from(policy.value_format).unwrap_or(ValueFormat::Any),
+        }
+    }
+
+    /// Returns why the write is rejected, if it is. `value` is None for a
+    /// delete.
+    pub fn check_write(&self, key: &[u8], value: Option<&[u8]>) -> Result<(), String> {
+        if let Some(max) = self.max_key_size {
+            if key.len() > max {
+                return Err(format!(
+                    "key is {} bytes, at most {} allowed",
+                    key.len(),
+                    max
+                ));
+            }
+        }
+        let Some(value) = value else {
+            return Ok(());
+        };
+        if let Some(max) = self.max_value_size {
+            if value.len() > max {
+                return Err(format!(
+                    "value is {} bytes, at most {} allowed",
+                    value.len(),
+                    max
+                ));
+            }
+        }
+        match self.value_format {
+            ValueFormat::Any => Ok(()),
+            ValueFormat::Msgpack => check_msgpack(value),
+            ValueFormat::Protobuf => check_protobuf(value),
+        }
+        .map_err(|e| {
+            format!(
+                "value is not valid {}: {}",
+                self.value_format.as_str_name(),
+                e
+            )
+        })
+    }
+
+    /// Checks every put, delete and increment of the prepare.
+    pub fn check_prepare(&self, prepare: &PrepareRequest) -> Result<(), String> {
+        for put in prepare.puts().iter().flatten() {
+            let key = put.key().and_then(|k| k.k()).map_or(&[][..], |k| k.bytes());
+            let value = put.value().map_or(&[][..], |v| v.bytes());
+            self.check_write(key, Some(value))?;
+        }
+        for del in prepare.deletes().iter().flatten() {
+            let key = del.k().map_or(&[][..], |k| k.bytes());
+            self.check_write(key, None)?;
+        }
+        // The value a counter ends up with is only known once the range
+        // applies the increment, so it is checked as a zero counter: what
+        // matters is that counters are 8 bytes of no structured format.
+        for increment in prepare.increments().iter().flatten() {
+            let key = increment
+                .key()
+                .and_then(|k| k.k())
+                .map_or(&[][..], |k| k.bytes());
+            self.check_write(key, Some(&0i64.to_be_bytes()))?;
+        }
+        Ok(())
+    }
+}
+
+fn take(buf: &[u8], pos: usize, n: usize) -> Result<usize, String> {
+    match pos.checked_add(n) {
+        Some(end) if end <= buf.len() => Ok(end),
+        _ => Err("truncated".to_string()),
+    }
+}
+
+fn read_be(buf: &[u8], pos: usize, n: usize) -> Result<(u64, usize), String> {
+    let end = take(buf, pos, n)?;
+    let value = buf[pos..end]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    Ok((value, end))
+}
+
+/// Checks that the value is exactly one MessagePack object.
+fn check_msgpack(value: &[u8]) -> Result<(), String> {
+    let end = msgpack_object(value, 0, 0)?;
+    if end != value.len() {
+        return Err(format!("{} trailing bytes", value.len() - end));
+    }
+    Ok(())
+}
+
+// Returns where the object starting at `pos` ends.
+fn msgpack_object(buf: &[u8], pos: usize, depth: usize) -> Result<usize, String> {
+    if depth > MAX_NESTING {
+        return Err("nested too deeply".to_string());
+    }
+    let Some(&marker) = buf.get(pos) else {
+        return Err("truncated".to_string());
+    };
+    let pos = pos + 1;
+    // Checks the `count * per` objects of an array or map.
+    let elements = |buf: &[u8], pos: usize, count: u64, per: u64| {
+        let mut pos = pos;
+        for _ in 0..count * per {
+            pos = msgpack_object(buf, pos, depth + 1)?;
+        }
+        Ok(pos)
+    };
+    match marker {
+        0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => Ok(pos),
+        0x80..=0x8f => elements(buf, pos, (marker & 0x0f) as u64, 2),
+        0x90..=0x9f => elements(buf, pos, (marker & 0x0f) as u64, 1),
+        0xa0..=0xbf => take(buf, pos, (marker & 0x1f) as usize),
+        0xc1 => Err("reserved marker 0xc1".to_string()),
+        // bin and str
+        0xc4 | 0xd9 => read_be(buf, pos, 1).and_then(|(n, pos)| take(buf, pos, n as usize)),
+        0xc5 | 0xda => read_be(buf, pos, 2).and_then(|(n, pos)| take(buf, pos, n as usize)),
+        0xc6 | 0xdb => read_be(buf, pos, 4).and_then(|(n, pos)| take(buf, pos, n as usize)),
+        // ext, with a type byte before the data
+        0xc7 => read_be(buf, pos, 1).and_then(|(n, pos)| take(buf, pos, n as usize + 1)),
+        0xc8 => read_be(buf, pos, 2).and_then(|(n, pos)| take(buf, pos, n as usize + 1)),
+        0xc9 => read_be(buf, pos, 4).and_then(|(n, pos)| take(buf, pos, n as usize + 1)),
+        0xca | 0xce | 0xd2 => take(buf, pos, 4),
+        0xcb | 0xcf | 0xd3 => take(buf, pos, 8),
+        0xcc | 0xd0 => take(buf, pos, 1),
+        0xcd | 0xd1 => take(buf, pos, 2),
+        // fixext
+        0xd4 => take(buf, pos, 2),
+        0xd5 => take(buf, pos, 3),
+        0xd6 => take(buf, pos, 5),
+        0xd7 => take(buf, pos, 9),
+        0xd8 => take(buf, pos, 17),
+        0xdc => read_be(buf, pos, 2).and_then(|(n, pos)| elements(buf, pos, n, 1)),
+        0xdd => read_be(buf, pos, 4).and_then(|(n, pos)| elements(buf, pos, n, 1)),
+        0xde => read_be(buf, pos, 2).and_then(|(n, pos)| elements(buf, pos, n, 2)),
+        0xdf => read_be(buf, pos, 4).and_then(|(n, pos)| elements(buf, pos, n, 2)),
+    }
+}
+
+/// Checks that the value is a sequence of well-formed protobuf fields.
+fn check_protobuf(value: &[u8]) -> Result<(), String> {
+    protobuf_fields(value, 0, None, 0).map(|_| ())
+}
+
+fn varint(buf: &[u8], pos: usize) -> Result<(u64, usize), String> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let Some(&b) = buf.get(pos + i) else {
+            return Err("truncated varint".to_string());
+        };
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok((value, pos + i + 1));
+        }
+    }
+    Err("varint is longer than 10 bytes".to_string())
+}
+
+// Reads fields until the end of the buffer, or until the end of `group` if
+// reading a group. Returns where reading stopped.
+fn protobuf_fields(
+    buf: &[u8],
+    mut pos: usize,
+    group: Option<u64>,
+    depth: usize,
+) -> Result<usize, String> {
+    if depth > MAX_NESTING {
+        return Err("nested too deeply".to_string());
+    }
+    while pos < buf.len() {
+        let (tag, next) = varint(buf, pos)?;
+        pos = next;
+        let field = tag >> 3;
+        if field == 0 || field > (1 << 29) - 1 {
+            return Err(format!("invalid field number {}", field));
+        }
+        pos = match tag & 0x7 {
+            0 => varint(buf, pos)?.1,
+            1 => take(buf, pos, 8)?,
+            2 => {
+                let (len, pos) = varint(buf, pos)?;
+                take(buf, pos, usize::try_from(len).map_err(|_| "truncated")?)?
+            }
+            3 => protobuf_fields(buf, pos, Some(field), depth + 1)?,
+            4 if group == Some(field) => return Ok(pos),
+            4 => return Err(format!("unexpected end of group {}", field)),
+            5 => take(buf, pos, 4)?,
+            wire_type => return Err(format!("invalid wire type {}", wire_type)),
+        };
+    }
+    match group {
+        Some(field) => Err(format!("group {} is not closed", field)),
+        None => Ok(pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(
+        max_key_size: u32,
+        max_value_size: u32,
+        value_format: ValueFormat,
+    ) -> ValidationPolicy {
+        ValidationPolicy::from_proto(&ProtoValidationPolicy {
+            max_key_size,
+            max_value_size,
+            value_format: value_format as i32,
+        })
+    }
+
+    #[test]
+    fn sizes() {
+        let limited = policy(3, 4, ValueFormat::Any);
+        assert!(limited.check_write(b"key", Some(b"four")).is_ok());
+        assert!(limited.check_write(b"keys", Some(b"four")).is_err());
+        assert!(limited.check_write(b"key", Some(b"five!")).is_err());
+        assert!(limited.check_write(b"key", None).is_ok());
+        let unlimited = policy(0, 0, ValueFormat::Any);
+        assert!(unlimited.check_write(&[0; 1000], Some(&[0; 1000])).is_ok());
+    }
+
+    #[test]
+    fn msgpack() {
+        let policy = policy(0, 0, ValueFormat::Msgpack);
+        let valid: &[&[u8]] = &[
+            &[0x01],
+            &[0xc0],
+            // {"a": [1, -1]}
+            &[0x81, 0xa1, b'a', 0x92, 0x01, 0xff],
+            // bin 8 of 2 bytes
+            &[0xc4, 0x02, 0xaa, 0xbb],
+            // uint 32
+            &[0xce, 0, 0, 0, 1],
+            // fixext 1
+            &[0xd4, 0x01, 0x02],
+            // array 16 with 1 element
+            &[0xdc, 0x00, 0x01, 0xc3],
+        ];
+        for value in valid {
+            assert!(policy.check_write(b"k", Some(value)).is_ok(), "{:?}", value);
+        }
+        let invalid: &[&[u8]] = &[
+            &[],
+            &[0xc1],
+            // two objects
+            &[0x01, 0x02],
+            // str 8 claiming more bytes than there are
+            &[0xd9, 0x05, b'a'],
+            // fixarray missing an element
+            &[0x92, 0x01],
+            // map missing a value
+            &[0x81, 0x01],
+        ];
+        for value in invalid {
+            assert!(
+                policy.check_write(b"k", Some(value)).is_err(),
+                "{:?}",
+                value
+            );
+        }
+        let deeply_nested = vec![0x91; MAX_NESTING + 2];
+        assert!(policy.check_write(b"k", Some(&deeply_nested)).is_err());
+    }
+
+    #[test]
+    fn protobuf() {
+        let policy = policy(0, 0, ValueFormat::Protobuf);
+        let valid: &[&[u8]] = &[
+            &[],
+            // field 1 varint 150
+            &[0x08, 0x96, 0x01],
+            // field 2 string "hi", field 3 fixed32
+            &[0x12, 0x02, b'h', b'i', 0x1d, 0, 0, 0, 0],
+            // field 4 group containing field 1 varint
+            &[0x23, 0x08, 0x01, 0x24],
+        ];
+        for value in valid {
+            assert!(policy.check_write(b"k", Some(value)).is_ok(), "{:?}", value);
+        }
+        let invalid: &[&[u8]] = &[
+            // field 0
+            &[0x00, 0x01],
+            // length past the end
+            &[0x12, 0x05, b'h'],
+            // truncated varint
+            &[0x08, 0x96],
+            // wire type 7
+            &[0x0f],
+            // unclosed group
+            &[0x23, 0x08, 0x01],
+            // end of a group that was not started
+            &[0x24],
+        ];
+        for value in invalid {
+            assert!(
+                policy.check_write(b"k", Some(value)).is_err(),
+                "{:?}",
+                value
+            );
+        }
+    }
+}
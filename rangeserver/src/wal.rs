@@ -1,4 +1,6 @@
 pub mod cassandra;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb;
 
 use std::sync::Arc;
 
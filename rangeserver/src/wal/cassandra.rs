@@ -3,6 +3,7 @@ use std::ops::DerefMut;
 use std::sync::Arc;
 
 use crate::for_testing::in_memory_wal::InMemIterator;
+use crate::storage::cassandra::build_session;
 
 use super::*;
 use async_trait::async_trait;
@@ -13,7 +14,6 @@ use scylla::statement::SerialConsistency;
 use scylla::transport::errors::DbError;
 use scylla::transport::errors::QueryError;
 use scylla::Session;
-use scylla::SessionBuilder;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
@@ -80,11 +80,7 @@ static METADATA_OFFSET: i64 = i64::MAX;
 
 impl CassandraWal {
     pub async fn new(known_node: String, wal_id: Uuid) -> CassandraWal {
-        let session = SessionBuilder::new()
-            .known_node(known_node)
-            .build()
-            .await
-            .unwrap();
+        let session = build_session(known_node, None).await;
         CassandraWal {
             session,
             wal_id,
@@ -0,0 +1,297 @@
+use std::sync::{Arc, Mutex};
+
+use ::rocksdb::{ColumnFamily, WriteBatch, DB};
+use async_trait::async_trait;
+use flatbuffers::FlatBufferBuilder;
+use uuid::Uuid;
+
+use super::*;
+use crate::storage::rocksdb::WAL_CF;
+
+// Like in Cassandra, the offsets of the log live next to its entries, in a
+// row placed after any entry.
+const METADATA_OFFSET: u64 = u64::MAX;
+
+fn internal_error(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::Internal(Arc::new(e))
+}
+
+fn entry_key(wal_id: Uuid, offset: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(24);
+    key.extend_from_slice(wal_id.as_bytes());
+    key.extend_from_slice(&offset.to_be_bytes());
+    key
+}
+
+struct LogState {
+    first_offset: Option<u64>,
+    next_offset: u64,
+    flatbuf_builder: FlatBufferBuilder<'static>,
+}
+
+impl LogState {
+    fn encode_metadata(&self) -> Vec<u8> {
+        let mut metadata = Vec::with_capacity(17);
+        match self.first_offset {
+            None => metadata.push(0),
+            Some(first_offset) => {
+                metadata.push(1);
+                metadata.extend_from_slice(&first_offset.to_be_bytes());
+            }
+        }
+        metadata.extend_from_slice(&self.next_offset.to_be_bytes());
+        metadata
+    }
+
+    fn decode_metadata(metadata: &[u8]) -> LogState {
+        let (first_offset, next_offset) = match metadata[0] {
+            0 => (None, &metadata[1..]),
+            _ => (
+                Some(u64::from_be_bytes(metadata[1..9].try_into().unwrap())),
+                &metadata[9..],
+            ),
+        };
+        LogState {
+            first_offset,
+            next_offset: u64::from_be_bytes(next_offset.try_into().unwrap()),
+            flatbuf_builder: FlatBufferBuilder::new(),
+        }
+    }
+}
+
+/// A write-ahead log kept in the `wal` column family of a RocksDB database,
+/// usually the one a `RocksDbStorage` opened. Several logs can share the
+/// database, each under its own id.
+///
+/// Only one process can open the database, so unlike `CassandraWal` there are
+/// no concurrent appenders to guard against, and a log that was never written
+/// to starts out empty on sync.
+pub struct RocksDbWal {
+    db: Arc<DB>,
+    wal_id: Uuid,
+    // None until synced.
+    state: Mutex<Option<LogState>>,
+}
+
+impl RocksDbWal {
+    pub fn new(db: Arc<DB>, wal_id: Uuid) -> RocksDbWal {
+        RocksDbWal {
+            db,
+            wal_id,
+            state: Mutex::new(None),
+        }
+    }
+
+    fn cf(&self) -> &ColumnFamily {
+        self.db.cf_handle(WAL_CF).unwrap()
+    }
+
+    fn append_entry(&self, entry_type: Entry, entry: &[u8]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let log_state = state.as_mut().ok_or(Error::NotSynced)?;
+        let bytes = log_state.flatbuf_builder.create_vector(entry);
+        let fb_root = LogEntry::create(
+            &mut log_state.flatbuf_builder,
+            &LogEntryArgs {
+                entry: entry_type,
+                bytes: Some(bytes),
+            },
+        );
+        log_state.flatbuf_builder.finish(fb_root, None);
+        let content = Vec::from(log_state.flatbuf_builder.finished_data());
+        log_state.flatbuf_builder.reset();
+
+        let offset = log_state.next_offset;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(self.cf(), entry_key(self.wal_id, offset), content);
+        log_state.next_offset += 1;
+        log_state.first_offset.get_or_insert(offset);
+        batch.put_cf(
+            self.cf(),
+            entry_key(self.wal_id, METADATA_OFFSET),
+            log_state.encode_metadata(),
+        );
+        if let Err(e) = self.db.write(batch) {
+            // The offsets were already advanced.
+            *state = None;
+            return Err(internal_error(e));
+        }
+        Ok(())
+    }
+}
+
+pub struct RocksDbIterator<'a> {
+    wal: &'a RocksDbWal,
+    // None until the first entry is read, since the log may not be synced
+    // yet when the iterator is created.
+    offset: Option<u64>,
+    current_entry: Option<Vec<u8>>,
+}
+
+impl<'a> Iterator<'a> for RocksDbIterator<'a> {
+    async fn next(&mut self) -> Option<LogEntry<'_>> {
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => self.wal.first_offset().await.ok()??,
+        };
+        let entry = self
+            .wal
+            .db
+            .get_cf(self.wal.cf(), entry_key(self.wal.wal_id, offset))
+            .ok()??;
+        self.offset = Some(offset + 1);
+        self.current_entry = Some(entry);
+        self.current_entry
+            .as_ref()
+            .map(|e| root_as_log_entry(e).unwrap())
+    }
+
+    async fn next_offset(&self) -> Result<u64, Error> {
+        match self.offset {
+            Some(offset) => Ok(offset),
+            None => Ok(self.wal.first_offset().await?.unwrap_or(0)),
+        }
+    }
+}
+
+#[async_trait]
+impl Wal for RocksDbWal {
+    async fn sync(&self) -> Result<(), Error> {
+        let metadata = self
+            .db
+            .get_cf(self.cf(), entry_key(self.wal_id, METADATA_OFFSET))
+            .map_err(internal_error)?;
+        let log_state = match metadata {
+            Some(metadata) => LogState::decode_metadata(&metadata),
+            None => LogState {
+                first_offset: None,
+                next_offset: 0,
+                flatbuf_builder: FlatBufferBuilder::new(),
+            },
+        };
+        *self.state.lock().unwrap() = Some(log_state);
+        Ok(())
+    }
+
+    async fn first_offset(&self) -> Result<Option<u64>, Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.as_ref().ok_or(Error::NotSynced)?.first_offset)
+    }
+
+    async fn next_offset(&self) -> Result<u64, Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.as_ref().ok_or(Error::NotSynced)?.next_offset)
+    }
+
+    async fn trim_before_offset(&self, offset: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let log_state = state.as_mut().ok_or(Error::NotSynced)?;
+        let first_offset = match log_state.first_offset {
+            Some(first_offset) if first_offset < offset => first_offset,
+            _ => return Ok(()),
+        };
+        let offset = offset.min(log_state.next_offset);
+        let mut batch = WriteBatch::default();
+        batch.delete_range_cf(
+            self.cf(),
+            entry_key(self.wal_id, first_offset),
+            entry_key(self.wal_id, offset),
+        );
+        log_state.first_offset = Some(offset);
+        batch.put_cf(
+            self.cf(),
+            entry_key(self.wal_id, METADATA_OFFSET),
+            log_state.encode_metadata(),
+        );
+        if let Err(e) = self.db.write(batch) {
+            *state = None;
+            return Err(internal_error(e));
+        }
+        Ok(())
+    }
+
+    async fn append_prepare(&self, entry: PrepareRequest<'_>) -> Result<(), Error> {
+        self.append_entry(Entry::Prepare, entry._tab.buf())
+    }
+
+    async fn append_abort(&self, entry: AbortRequest<'_>) -> Result<(), Error> {
+        self.append_entry(Entry::Abort, entry._tab.buf())
+    }
+
+    async fn append_commit(&self, entry: CommitRequest<'_>) -> Result<(), Error> {
+        self.append_entry(Entry::Commit, entry._tab.buf())
+    }
+
+    fn iterator(&self) -> RocksDbIterator<'_> {
+        RocksDbIterator {
+            wal: self,
+            offset: None,
+            current_entry: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::rocksdb::for_testing::init;
+    use common::util;
+
+    fn abort_record(fbb: &mut FlatBufferBuilder) -> Vec<u8> {
+        let transaction_id = Some(Uuidu128::create(
+            fbb,
+            &util::flatbuf::serialize_uuid(Uuid::new_v4()),
+        ));
+        let request_id = Some(Uuidu128::create(
+            fbb,
+            &util::flatbuf::serialize_uuid(Uuid::new_v4()),
+        ));
+        let fbb_root = AbortRequest::create(
+            fbb,
+            &AbortRequestArgs {
+                request_id,
+                transaction_id,
+                range_id: None,
+            },
+        );
+        fbb.finish(fbb_root, None);
+        fbb.finished_data().to_vec()
+    }
+
+    #[tokio::test]
+    async fn append_trim_and_iterate() {
+        let context = init().await;
+        let wal = RocksDbWal::new(context.storage.db(), Uuid::new_v4());
+        let mut fbb = FlatBufferBuilder::new();
+        let abort_record_bytes = abort_record(&mut fbb);
+        let abort_record = flatbuffers::root::<AbortRequest>(&abort_record_bytes).unwrap();
+        assert!(matches!(
+            wal.append_abort(abort_record).await,
+            Err(Error::NotSynced)
+        ));
+
+        wal.sync().await.unwrap();
+        assert_eq!(wal.first_offset().await.unwrap(), None);
+        assert_eq!(wal.next_offset().await.unwrap(), 0);
+        for _ in 0..3 {
+            wal.append_abort(abort_record).await.unwrap();
+        }
+        wal.trim_before_offset(1).await.unwrap();
+        assert_eq!(wal.first_offset().await.unwrap(), Some(1));
+        assert_eq!(wal.next_offset().await.unwrap(), 3);
+
+        // Another process picking up the log sees the same offsets.
+        let reopened = RocksDbWal::new(context.storage.db(), wal.wal_id);
+        reopened.sync().await.unwrap();
+        assert_eq!(reopened.first_offset().await.unwrap(), Some(1));
+        assert_eq!(reopened.next_offset().await.unwrap(), 3);
+        let mut iterator = reopened.iterator();
+        let mut entries = 0;
+        while let Some(entry) = iterator.next().await {
+            assert_eq!(entry.entry(), Entry::Abort);
+            entries += 1;
+        }
+        assert_eq!(entries, 2);
+        assert_eq!(iterator.next_offset().await.unwrap(), 3);
+    }
+}
@@ -1,11 +1,139 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+use crate::for_testing::in_memory_storage::InMemoryStorage;
 use crate::storage::{cassandra::Cassandra, Storage};
+use common::clock::Clock;
 use common::config::Config;
 use common::region::Region;
 
+/// How long the decisions of transactions must be kept for participants to
+/// still find them. A participant asks for the decision of a transaction
+/// while it is prepared on one of its ranges, and a missing decision reads as
+/// aborted, so pruning one a participant still needs would abort a committed
+/// transaction on that participant only.
+///
+/// Every transaction commits after all its participants prepared, so
+/// decisions made before the oldest transaction still prepared on any range
+/// are no longer needed. The ranges that are loaded report theirs, see
+/// `GetOldestPrepared` in rangeserver.proto, as of when it first prepared:
+/// the prepare record keeps that time when another range server recovers
+/// the transaction. The ranges that are not loaded cannot report, so
+/// decisions are also kept for as long as a transaction may run, plus as long
+/// as a range may stay unloaded before it is recovered.
+///
+/// On top of that, decisions are kept for at least `retention`, e.g. to look
+/// into how recent transactions ended.
+#[derive(Clone, Debug)]
+pub struct RetentionPolicy {
+    /// The longest overall timeout transactions are started with.
+    pub max_transaction_timeout: Duration,
+    /// How long a range may stay unloaded, e.g. after its range server
+    /// failed, before another one loads it and recovers its prepared
+    /// transactions.
+    pub max_recovery_delay: Duration,
+    /// How far apart the clocks of range servers and Cassandra nodes may be.
+    pub max_clock_skew: Duration,
+    /// How long decisions are kept however early participants are done with
+    /// them.
+    pub retention: Duration,
+}
+
+/// How `Client::run_gc` prunes decisions.
+#[derive(Clone, Debug)]
+pub struct GcConfig {
+    /// Decisions are pruned this often.
+    pub interval: Duration,
+    pub retention: RetentionPolicy,
+}
+
+impl RetentionPolicy {
+    /// Returns the time before which decisions may be pruned, given when the
+    /// oldest transaction still prepared on any loaded range prepared, None
+    /// if there is none.
+    pub fn horizon(
+        &self,
+        now: DateTime<Utc>,
+        oldest_prepared: Option<DateTime<Utc>>,
+    ) -> DateTime<Utc> {
+        // Durations too long to subtract keep every decision.
+        let before = |t: DateTime<Utc>, d: Duration| {
+            chrono::Duration::from_std(d)
+                .ok()
+                .and_then(|d| t.checked_sub_signed(d))
+                .unwrap_or(DateTime::<Utc>::MIN_UTC)
+        };
+        let window = self
+            .max_transaction_timeout
+            .saturating_add(self.max_recovery_delay)
+            .saturating_add(self.max_clock_skew)
+            .max(self.retention);
+        let horizon = before(now, window);
+        match oldest_prepared {
+            Some(oldest) => horizon.min(before(oldest, self.max_clock_skew)),
+            None => horizon,
+        }
+    }
+}
+
+enum Backend {
+    Cassandra(Cassandra),
+    InMemory(Arc<InMemoryStorage>),
+}
+
+impl Storage for Backend {
+    async fn start_transaction(&self, transaction_id: Uuid) -> Result<(), Error> {
+        match self {
+            Backend::Cassandra(s) => s.start_transaction(transaction_id).await,
+            Backend::InMemory(s) => s.start_transaction(transaction_id).await,
+        }
+    }
+
+    async fn abort_transaction(&self, transaction_id: Uuid) -> Result<OpResult, Error> {
+        match self {
+            Backend::Cassandra(s) => s.abort_transaction(transaction_id).await,
+            Backend::InMemory(s) => s.abort_transaction(transaction_id).await,
+        }
+    }
+
+    async fn commit_transaction(
+        &self,
+        transaction_id: Uuid,
+        epoch: u64,
+    ) -> Result<OpResult, Error> {
+        match self {
+            Backend::Cassandra(s) => s.commit_transaction(transaction_id, epoch).await,
+            Backend::InMemory(s) => s.commit_transaction(transaction_id, epoch).await,
+        }
+    }
+
+    async fn get_transaction_outcome(
+        &self,
+        transaction_id: Uuid,
+    ) -> Result<Option<OpResult>, Error> {
+        match self {
+            Backend::Cassandra(s) => s.get_transaction_outcome(transaction_id).await,
+            Backend::InMemory(s) => s.get_transaction_outcome(transaction_id).await,
+        }
+    }
+
+    async fn prune_decisions(&self, decided_before: DateTime<Utc>) -> Result<u64, Error> {
+        match self {
+            Backend::Cassandra(s) => s.prune_decisions(decided_before).await,
+            Backend::InMemory(s) => s.prune_decisions(decided_before).await,
+        }
+    }
+}
+
 pub struct Client {
-    storage: Cassandra,
+    storage: Backend,
 }
 
 pub type Error = crate::storage::Error;
@@ -13,8 +141,21 @@ pub type OpResult = crate::storage::OpResult;
 
 impl Client {
     pub async fn new(config: Config, _region: Region) -> Client {
+        Self::connect(config.cassandra.cql_addr.to_string()).await
+    }
+
+    /// Connects to the Cassandra cluster the decisions are stored in through
+    /// `known_node`, e.g. for tools without the full config.
+    pub async fn connect(known_node: String) -> Client {
+        Client {
+            storage: Backend::Cassandra(Cassandra::new(known_node).await),
+        }
+    }
+
+    /// A client keeping the decisions in `storage`, for tests.
+    pub fn in_memory(storage: Arc<InMemoryStorage>) -> Client {
         Client {
-            storage: Cassandra::new(config.cassandra.cql_addr.to_string()).await,
+            storage: Backend::InMemory(storage),
         }
     }
 
@@ -41,4 +182,114 @@ impl Client {
     pub async fn try_commit_transaction(&self, id: Uuid, epoch: u64) -> Result<OpResult, Error> {
         self.storage.commit_transaction(id, epoch).await
     }
+
+    /// Looks up whether a transaction committed or aborted, without deciding
+    /// it. Returns None if the transaction is still undecided.
+    pub async fn get_transaction_outcome(&self, id: Uuid) -> Result<Option<OpResult>, Error> {
+        self.storage.get_transaction_outcome(id).await
+    }
+
+    /// Deletes the decisions made before `decided_before`, which should come
+    /// from `RetentionPolicy::horizon`, and returns how many it deleted.
+    /// Decisions recorded before their time was are never pruned. This scans
+    /// the whole table, so it is meant to run now and then, off peak.
+    pub async fn prune_decisions(&self, decided_before: DateTime<Utc>) -> Result<u64, Error> {
+        self.storage.prune_decisions(decided_before).await
+    }
+
+    /// Prunes the decisions past the horizon of `config.retention` every
+    /// `config.interval`, and never returns. Each round first calls
+    /// `oldest_prepared` for when the oldest transaction still prepared on
+    /// any range prepared, None if there is none, which is how far every
+    /// participant has acknowledged the decisions: a range drops a prepared
+    /// transaction once it applied its decision. A round it fails for is
+    /// skipped, as some participant may still need any decision.
+    pub async fn run_gc<F, Fut, E>(&self, clock: &dyn Clock, config: GcConfig, oldest_prepared: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Option<DateTime<Utc>>, E>>,
+        E: Display,
+    {
+        loop {
+            match oldest_prepared().await {
+                Ok(oldest) => {
+                    let horizon = config.retention.horizon(clock.now(), oldest);
+                    match self.prune_decisions(horizon).await {
+                        Ok(pruned) => {
+                            info!("pruned {} decisions made before {}", pruned, horizon)
+                        }
+                        Err(e) => warn!("failed to prune decisions: {:?}", e),
+                    }
+                }
+                Err(e) => warn!(
+                    "not pruning decisions, participants did not report their prepared transactions: {}",
+                    e
+                ),
+            }
+            clock.sleep(config.interval).await;
+        }
+    }
+
+    /// Attempt to commit several transactions, each with its own epoch. Each
+    /// one is decided independently, exactly as by try_commit_transaction,
+    /// and the results are in the same order as `decisions`. This is still
+    /// one conditional write per transaction, but they are all in flight at
+    /// once, so a batch costs about one round trip.
+    pub async fn try_commit_transactions(
+        &self,
+        decisions: &[(Uuid, u64)],
+    ) -> Vec<Result<OpResult, Error>> {
+        // Conditional updates can only be batched within a partition, and
+        // every transaction has its own, so these stay separate LWTs.
+        join_all(
+            decisions
+                .iter()
+                .map(|(id, epoch)| self.storage.commit_transaction(*id, *epoch)),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy {
+            max_transaction_timeout: Duration::from_secs(60),
+            max_recovery_delay: Duration::from_secs(240),
+            max_clock_skew: Duration::from_secs(10),
+            retention: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn horizon_stays_before_the_oldest_prepared() {
+        let now = DateTime::from_timestamp(1_000_000, 0).unwrap();
+        let window = DateTime::from_timestamp(1_000_000 - 310, 0).unwrap();
+        assert_eq!(policy().horizon(now, None), window);
+        let recent = DateTime::from_timestamp(1_000_000 - 5, 0).unwrap();
+        assert_eq!(policy().horizon(now, Some(recent)), window);
+        let old = DateTime::from_timestamp(1_000_000 - 3600, 0).unwrap();
+        assert_eq!(
+            policy().horizon(now, Some(old)),
+            DateTime::from_timestamp(1_000_000 - 3610, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn decisions_are_kept_for_the_retention() {
+        let policy = RetentionPolicy {
+            retention: Duration::from_secs(3600),
+            ..policy()
+        };
+        let now = DateTime::from_timestamp(1_000_000, 0).unwrap();
+        let retained = DateTime::from_timestamp(1_000_000 - 3600, 0).unwrap();
+        assert_eq!(policy.horizon(now, None), retained);
+        let old = DateTime::from_timestamp(1_000_000 - 7200, 0).unwrap();
+        assert_eq!(
+            policy.horizon(now, Some(old)),
+            DateTime::from_timestamp(1_000_000 - 7210, 0).unwrap()
+        );
+    }
 }
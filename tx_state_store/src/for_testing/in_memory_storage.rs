@@ -0,0 +1,135 @@
+use crate::storage::{CommitInfo, Error, OpResult, Storage};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+enum Status {
+    Started,
+    Committed { epoch: u64 },
+    Aborted,
+}
+
+struct Record {
+    status: Status,
+    decided_at: Option<DateTime<Utc>>,
+}
+
+struct State {
+    transactions: HashMap<Uuid, Record>,
+    available: bool,
+}
+
+/// Keeps the transaction records in memory, deciding them the way the
+/// Cassandra storage does, including presuming a missing record aborted.
+pub struct InMemoryStorage {
+    state: Mutex<State>,
+}
+
+impl Default for InMemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryStorage {
+    pub fn new() -> InMemoryStorage {
+        InMemoryStorage {
+            state: Mutex::new(State {
+                transactions: HashMap::new(),
+                available: true,
+            }),
+        }
+    }
+
+    /// While unavailable, every operation fails with Timeout, without
+    /// changing any record, as if the cluster could not be reached.
+    pub fn set_available(&self, available: bool) {
+        self.state.lock().unwrap().available = available;
+    }
+
+    fn decide(&self, transaction_id: Uuid, decision: Status) -> Result<OpResult, Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state.available {
+            return Err(Error::Timeout);
+        }
+        let Some(record) = state.transactions.get_mut(&transaction_id) else {
+            return Ok(OpResult::TransactionIsAborted);
+        };
+        if let Status::Started = record.status {
+            record.status = decision;
+            record.decided_at = Some(Utc::now());
+        }
+        Ok(match record.status {
+            Status::Committed { epoch } => OpResult::TransactionIsCommitted(CommitInfo { epoch }),
+            _ => OpResult::TransactionIsAborted,
+        })
+    }
+}
+
+impl Storage for InMemoryStorage {
+    async fn start_transaction(&self, transaction_id: Uuid) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state.available {
+            return Err(Error::Timeout);
+        }
+        state.transactions.entry(transaction_id).or_insert(Record {
+            status: Status::Started,
+            decided_at: None,
+        });
+        Ok(())
+    }
+
+    async fn abort_transaction(&self, transaction_id: Uuid) -> Result<OpResult, Error> {
+        self.decide(transaction_id, Status::Aborted)
+    }
+
+    async fn commit_transaction(
+        &self,
+        transaction_id: Uuid,
+        epoch: u64,
+    ) -> Result<OpResult, Error> {
+        self.decide(transaction_id, Status::Committed { epoch })
+    }
+
+    async fn get_transaction_outcome(
+        &self,
+        transaction_id: Uuid,
+    ) -> Result<Option<OpResult>, Error> {
+        let state = self.state.lock().unwrap();
+        if !state.available {
+            return Err(Error::Timeout);
+        }
+        Ok(match state.transactions.get(&transaction_id) {
+            None
+            | Some(Record {
+                status: Status::Aborted,
+                ..
+            }) => Some(OpResult::TransactionIsAborted),
+            Some(Record {
+                status: Status::Committed { epoch },
+                ..
+            }) => Some(OpResult::TransactionIsCommitted(CommitInfo {
+                epoch: *epoch,
+            })),
+            Some(Record {
+                status: Status::Started,
+                ..
+            }) => None,
+        })
+    }
+
+    async fn prune_decisions(&self, decided_before: DateTime<Utc>) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state.available {
+            return Err(Error::Timeout);
+        }
+        let before = state.transactions.len();
+        state.transactions.retain(|_, record| {
+            record
+                .decided_at
+                .is_none_or(|decided_at| decided_at >= decided_before)
+        });
+        Ok((before - state.transactions.len()) as u64)
+    }
+}
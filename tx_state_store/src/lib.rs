@@ -1,2 +1,3 @@
 pub mod client;
+pub mod for_testing;
 mod storage;
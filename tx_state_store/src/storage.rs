@@ -1,5 +1,6 @@
 pub mod cassandra;
 
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
@@ -37,4 +38,19 @@ pub trait Storage: Send + Sync + 'static {
         transaction_id: Uuid,
         epoch: u64,
     ) -> impl std::future::Future<Output = Result<OpResult, Error>> + Send;
+
+    /// Returns the outcome of the transaction without deciding it, None if it
+    /// is still undecided.
+    fn get_transaction_outcome(
+        &self,
+        transaction_id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Option<OpResult>, Error>> + Send;
+
+    /// Deletes the records of the transactions decided before
+    /// `decided_before`, and returns how many it deleted. Records of
+    /// undecided transactions are kept.
+    fn prune_decisions(
+        &self,
+        decided_before: DateTime<Utc>,
+    ) -> impl std::future::Future<Output = Result<u64, Error>> + Send;
 }
@@ -1,11 +1,13 @@
 use super::*;
+use scylla::frame::value::CqlTimestamp;
 use scylla::query::Query;
-use scylla::statement::SerialConsistency;
+use scylla::statement::{Consistency, SerialConsistency};
 use scylla::transport::errors::DbError;
 use scylla::transport::errors::QueryError;
 use scylla::transport::PagingState;
 use scylla::Session;
 use scylla::SessionBuilder;
+use std::ops::ControlFlow;
 
 pub struct Cassandra {
     session: Session,
@@ -18,13 +20,13 @@ static START_TRANSACTION_QUERY: &str = r#"
 "#;
 
 static COMMIT_TRANSACTION_QUERY: &str = r#"
-  UPDATE atomix.transactions SET status = 'committed', epoch = ?
+  UPDATE atomix.transactions SET status = 'committed', epoch = ?, decided_at = toTimestamp(now())
     WHERE transaction_id = ? 
     IF status IN ('started', 'committed')
 "#;
 
 static ABORT_TRANSACTION_QUERY: &str = r#"
-  UPDATE atomix.transactions SET status = 'aborted'
+  UPDATE atomix.transactions SET status = 'aborted', decided_at = toTimestamp(now())
     WHERE transaction_id = ? 
     IF status IN ('started', 'aborted')
 "#;
@@ -36,6 +38,23 @@ static GET_COMMIT_EPOCH_QUERY: &str = r#"
     ALLOW FILTERING
 "#;
 
+static GET_TRANSACTION_QUERY: &str = r#"
+  SELECT status, epoch from atomix.transactions
+    WHERE transaction_id = ?
+"#;
+
+static LIST_DECIDED_BEFORE_QUERY: &str = r#"
+  SELECT transaction_id from atomix.transactions
+    WHERE decided_at < ?
+    ALLOW FILTERING
+"#;
+
+static DELETE_DECISION_QUERY: &str = r#"
+  DELETE FROM atomix.transactions
+    WHERE transaction_id = ?
+    IF status IN ('committed', 'aborted')
+"#;
+
 fn scylla_query_error_to_storage_error(qe: QueryError) -> Error {
     match qe {
         QueryError::TimeoutError | QueryError::DbError(DbError::WriteTimeout { .. }, _) => {
@@ -166,6 +185,83 @@ impl Storage for Cassandra {
         };
         res
     }
+
+    async fn get_transaction_outcome(
+        &self,
+        transaction_id: Uuid,
+    ) -> Result<Option<OpResult>, Error> {
+        // Serial reads see the outcome of any LWT that decided the
+        // transaction, even one that did not complete.
+        let mut query = Query::new(GET_TRANSACTION_QUERY);
+        query.set_consistency(Consistency::Serial);
+        let rows = self
+            .session
+            .query_single_page(query, (transaction_id,), PagingState::start())
+            .await
+            .map_err(scylla_query_error_to_storage_error)?
+            .0
+            .rows
+            .unwrap_or_default();
+        let Some(row) = rows.into_iter().next() else {
+            // A missing record means presumed abort.
+            return Ok(Some(OpResult::TransactionIsAborted));
+        };
+        let status = row.columns[0].as_ref().and_then(|c| c.as_text().cloned());
+        match status.as_deref() {
+            Some("committed") => {
+                let epoch = row.columns[1].as_ref().unwrap().as_bigint().unwrap();
+                Ok(Some(OpResult::TransactionIsCommitted(CommitInfo {
+                    epoch: epoch as u64,
+                })))
+            }
+            Some("aborted") => Ok(Some(OpResult::TransactionIsAborted)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn prune_decisions(&self, decided_before: DateTime<Utc>) -> Result<u64, Error> {
+        let list = Query::new(LIST_DECIDED_BEFORE_QUERY);
+        let before = CqlTimestamp(decided_before.timestamp_millis());
+        let mut transaction_ids = Vec::new();
+        let mut paging_state = PagingState::start();
+        loop {
+            let (result, paging) = self
+                .session
+                .query_single_page(list.clone(), (before,), paging_state)
+                .await
+                .map_err(scylla_query_error_to_storage_error)?;
+            for row in result.rows.unwrap_or_default() {
+                if let Some(id) = row.columns[0].as_ref().and_then(|c| c.as_uuid()) {
+                    transaction_ids.push(id);
+                }
+            }
+            match paging.into_paging_control_flow() {
+                ControlFlow::Break(()) => break,
+                ControlFlow::Continue(next) => paging_state = next,
+            }
+        }
+        // Decided records are never written again, but every other write to
+        // them is a LWT, so deletes are too.
+        let mut pruned = 0;
+        for transaction_id in transaction_ids {
+            let query = get_serial_query(DELETE_DECISION_QUERY);
+            let rows = self
+                .session
+                .query_single_page(query, (transaction_id,), PagingState::start())
+                .await
+                .map_err(scylla_query_error_to_storage_error)?
+                .0
+                .rows
+                .unwrap_or_default();
+            let applied = rows
+                .first()
+                .and_then(|row| row.columns[0].as_ref())
+                .and_then(|c| c.as_boolean())
+                .unwrap_or(false);
+            pruned += applied as u64;
+        }
+        Ok(pruned)
+    }
 }
 
 #[cfg(test)]
@@ -238,4 +334,21 @@ pub mod tests {
             OpResult::TransactionIsAborted => (),
         }
     }
+
+    #[tokio::test]
+    async fn get_outcome_does_not_decide() {
+        let cassandra = Cassandra::create_test().await;
+        let tx_id = Uuid::new_v4();
+        cassandra.start_transaction(tx_id).await.unwrap();
+        assert!(cassandra
+            .get_transaction_outcome(tx_id)
+            .await
+            .unwrap()
+            .is_none());
+        cassandra.commit_transaction(tx_id, 7).await.unwrap();
+        match cassandra.get_transaction_outcome(tx_id).await.unwrap() {
+            Some(OpResult::TransactionIsCommitted(c)) => assert!(c.epoch == 7),
+            _ => panic!("expected transaction to be committed"),
+        }
+    }
 }
@@ -1,2 +1,4 @@
+pub mod namespace_defaults;
+pub mod namespace_policy;
 pub mod server;
 pub mod storage;
@@ -0,0 +1,70 @@
+//! Resolves the settings keyspaces inherit from the defaults of their
+//! namespace.
+
+use proto::universe::{KeyspaceInfo, NamespaceDefaults};
+
+/// Whether a keyspace being created gets optimistic reads, given what its
+/// create request asked for.
+pub fn optimistic_reads(requested: Option<bool>, defaults: Option<&NamespaceDefaults>) -> bool {
+    requested
+        .or_else(|| defaults.and_then(|d| d.optimistic_reads))
+        .unwrap_or(false)
+}
+
+/// Fills in what `info` does not set itself from the defaults of its
+/// namespace. Keyspaces are stored with only their own settings, so that
+/// changing a default applies to every keyspace that did not override it.
+pub fn apply(info: &mut KeyspaceInfo, defaults: Option<&NamespaceDefaults>) {
+    if info.validation_policy.is_some() {
+        return;
+    }
+    if let Some(policy) = defaults.and_then(|d| d.validation_policy.clone()) {
+        info.validation_policy = Some(policy);
+        info.validation_policy_inherited = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::universe::{ValidationPolicy, ValueFormat};
+
+    fn policy(max_value_size: u32) -> ValidationPolicy {
+        ValidationPolicy {
+            max_key_size: 0,
+            max_value_size,
+            value_format: ValueFormat::Any as i32,
+        }
+    }
+
+    #[test]
+    fn request_overrides_default_optimistic_reads() {
+        let defaults = NamespaceDefaults {
+            optimistic_reads: Some(true),
+            validation_policy: None,
+        };
+        assert!(optimistic_reads(None, Some(&defaults)));
+        assert!(!optimistic_reads(Some(false), Some(&defaults)));
+        assert!(!optimistic_reads(None, None));
+    }
+
+    #[test]
+    fn keyspace_policy_overrides_default() {
+        let defaults = NamespaceDefaults {
+            optimistic_reads: None,
+            validation_policy: Some(policy(1024)),
+        };
+        let mut info = KeyspaceInfo::default();
+        apply(&mut info, Some(&defaults));
+        assert_eq!(info.validation_policy, Some(policy(1024)));
+        assert!(info.validation_policy_inherited);
+
+        let mut info = KeyspaceInfo {
+            validation_policy: Some(policy(0)),
+            ..Default::default()
+        };
+        apply(&mut info, Some(&defaults));
+        assert_eq!(info.validation_policy, Some(policy(0)));
+        assert!(!info.validation_policy_inherited);
+    }
+}
@@ -0,0 +1,78 @@
+//! Decides whether a transaction may span keyspaces from several namespaces.
+
+use std::collections::HashMap;
+
+use proto::universe::{NamespacePair, NamespacePolicy};
+
+/// Lets a namespace share transactions with any other.
+pub const ANY_NAMESPACE: &str = "*";
+
+fn allows(policy: Option<&NamespacePolicy>, peer: &str) -> bool {
+    policy.is_some_and(|policy| {
+        policy
+            .cross_namespace_peers
+            .iter()
+            .any(|p| p == ANY_NAMESPACE || p == peer)
+    })
+}
+
+/// Returns every namespace paired with a peer its policy does not allow, so
+/// an empty result means a transaction may touch all of `namespaces`.
+pub fn denied_pairs(
+    namespaces: &[String],
+    policies: &HashMap<String, NamespacePolicy>,
+) -> Vec<NamespacePair> {
+    let mut denied = Vec::new();
+    for namespace in namespaces {
+        for peer in namespaces {
+            if peer != namespace && !allows(policies.get(namespace), peer) {
+                denied.push(NamespacePair {
+                    namespace: namespace.clone(),
+                    peer: peer.clone(),
+                });
+            }
+        }
+    }
+    denied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(peers: &[&str]) -> NamespacePolicy {
+        NamespacePolicy {
+            cross_namespace_peers: peers.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    fn pair(namespace: &str, peer: &str) -> NamespacePair {
+        NamespacePair {
+            namespace: namespace.to_string(),
+            peer: peer.to_string(),
+        }
+    }
+
+    #[test]
+    fn single_namespace_needs_no_policy() {
+        let namespaces = vec!["a".to_string(), "a".to_string()];
+        assert!(denied_pairs(&namespaces, &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn peers_must_allow_each_other() {
+        let namespaces = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let policies = HashMap::from([
+            ("a".to_string(), policy(&["b", "c"])),
+            ("b".to_string(), policy(&[ANY_NAMESPACE])),
+            ("c".to_string(), policy(&["a"])),
+        ]);
+        assert_eq!(denied_pairs(&namespaces, &policies), vec![pair("c", "b")]);
+
+        let policies = HashMap::from([("a".to_string(), policy(&["b"]))]);
+        assert_eq!(
+            denied_pairs(&namespaces[..2], &policies),
+            vec![pair("b", "a")]
+        );
+    }
+}
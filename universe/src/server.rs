@@ -1,15 +1,27 @@
 use std::sync::Arc;
 
+use common::colocation::Colocation;
 use proto::universe::universe_server::Universe;
 use proto::universe::{
-    CreateKeyspaceRequest, CreateKeyspaceResponse, GetKeyspaceInfoRequest, GetKeyspaceInfoResponse,
-    ListKeyspacesRequest, ListKeyspacesResponse,
+    CheckCrossNamespaceAccessRequest, CheckCrossNamespaceAccessResponse, CreateKeyspaceRequest,
+    CreateKeyspaceResponse, DeleteKeyspaceAliasRequest, DeleteKeyspaceAliasResponse,
+    GetKeyspaceInfoRequest, GetKeyspaceInfoResponse, GetNamespaceDefaultsRequest,
+    GetNamespaceDefaultsResponse, GetNamespacePolicyRequest, GetNamespacePolicyResponse,
+    ListKeyspaceAliasesRequest, ListKeyspaceAliasesResponse, ListKeyspacesRequest,
+    ListKeyspacesResponse, NamespaceDefaults, RenameKeyspaceRequest, RenameKeyspaceResponse,
+    SetKeyspaceColocationRequest, SetKeyspaceColocationResponse, SetKeyspacePlacementRequest,
+    SetKeyspacePlacementResponse, SetKeyspaceReadOnlyRequest, SetKeyspaceReadOnlyResponse,
+    SetKeyspaceValidationPolicyRequest, SetKeyspaceValidationPolicyResponse,
+    SetNamespaceDefaultsRequest, SetNamespaceDefaultsResponse, SetNamespacePolicyRequest,
+    SetNamespacePolicyResponse, SplitKeyRangeRequest, SplitKeyRangeResponse,
 };
+use std::collections::HashMap;
 use tonic::{Request, Response, Status};
 use tracing::{debug, info, instrument};
 use uuid::Uuid;
 
-use crate::storage::{KeyspaceInfoSearchField, Storage};
+use crate::storage::{Error as StorageError, KeyspaceInfoSearchField, Storage};
+use crate::{namespace_defaults, namespace_policy};
 
 /// Implementation of the Universe manager.
 pub struct UniverseServer<S: Storage> {
@@ -21,6 +33,16 @@ impl<S: Storage> UniverseServer<S> {
     pub fn new(storage: Arc<S>) -> Self {
         Self { storage }
     }
+
+    async fn namespace_defaults(
+        &self,
+        namespace: &str,
+    ) -> Result<Option<NamespaceDefaults>, Status> {
+        self.storage
+            .get_namespace_defaults(namespace)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to get namespace defaults: {}", e)))
+    }
 }
 
 #[tonic::async_trait]
@@ -36,6 +58,20 @@ impl<S: Storage> Universe for UniverseServer<S> {
         // TODO: Validate the base key ranges. Must be non-overlapping and
         // cover the entire key space.
 
+        // A keyspace can't be created under a name that's still taken by an
+        // alias, lookups by that name would keep finding the old keyspace.
+        let alias = self
+            .storage
+            .get_keyspace_alias(&req_inner.namespace, &req_inner.name)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to get keyspace alias: {}", e)))?;
+        if alias.is_some() {
+            return Err(Status::already_exists(format!(
+                "{} is an alias of another keyspace",
+                req_inner.name
+            )));
+        }
+
         let base_key_ranges: Vec<proto::universe::KeyRange> = req_inner
             .base_key_ranges
             .into_iter()
@@ -58,6 +94,10 @@ impl<S: Storage> Universe for UniverseServer<S> {
             base_key_ranges
         };
 
+        let defaults = self.namespace_defaults(&req_inner.namespace).await?;
+        let optimistic_reads =
+            namespace_defaults::optimistic_reads(req_inner.optimistic_reads, defaults.as_ref());
+
         let keyspace_id = self
             .storage
             .create_keyspace(
@@ -66,6 +106,7 @@ impl<S: Storage> Universe for UniverseServer<S> {
                 &req_inner.namespace,
                 req_inner.primary_zone.unwrap(),
                 base_key_ranges,
+                optimistic_reads,
             )
             .await
             .map_err(|e| Status::internal(format!("Failed to create keyspace: {}", e)))?;
@@ -81,11 +122,19 @@ impl<S: Storage> Universe for UniverseServer<S> {
     ) -> Result<Response<ListKeyspacesResponse>, Status> {
         debug!("Got a list_keyspaces request: {:?}", request);
 
-        let keyspaces = self
+        let mut keyspaces = self
             .storage
             .list_keyspaces(request.into_inner().region)
             .await
             .map_err(|e| Status::internal(format!("Failed to list keyspaces: {}", e)))?;
+        let mut defaults = HashMap::new();
+        for keyspace in &mut keyspaces {
+            if !defaults.contains_key(&keyspace.namespace) {
+                let namespace_defaults = self.namespace_defaults(&keyspace.namespace).await?;
+                defaults.insert(keyspace.namespace.clone(), namespace_defaults);
+            }
+            namespace_defaults::apply(keyspace, defaults[&keyspace.namespace].as_ref());
+        }
 
         let response = ListKeyspacesResponse { keyspaces };
         Ok(Response::new(response))
@@ -101,17 +150,331 @@ impl<S: Storage> Universe for UniverseServer<S> {
         let req_inner = request.into_inner();
         let keyspace_info_search_field =
             KeyspaceInfoSearchField::from(req_inner.keyspace_info_search_field.unwrap());
-        let keyspace_info = self
+        let mut result = self
             .storage
-            .get_keyspace_info(keyspace_info_search_field)
-            .await
-            .map_err(|e| Status::internal(format!("Failed to get keyspace info: {}", e)))?;
+            .get_keyspace_info(keyspace_info_search_field.clone())
+            .await;
+        // Keyspaces can also be looked up by the names they had before being
+        // renamed.
+        if let (
+            Err(StorageError::KeyspaceDoesNotExist),
+            KeyspaceInfoSearchField::Keyspace { namespace, name },
+        ) = (&result, &keyspace_info_search_field)
+        {
+            let alias = self
+                .storage
+                .get_keyspace_alias(namespace, name)
+                .await
+                .map_err(|e| Status::internal(format!("Failed to get keyspace alias: {}", e)))?;
+            if let Some(name) = alias {
+                result = self
+                    .storage
+                    .get_keyspace_info(KeyspaceInfoSearchField::Keyspace {
+                        namespace: namespace.clone(),
+                        name,
+                    })
+                    .await;
+            }
+        }
+        let mut keyspace_info = result.map_err(|e| match e {
+            StorageError::KeyspaceDoesNotExist => Status::not_found(e.to_string()),
+            _ => Status::internal(format!("Failed to get keyspace info: {}", e)),
+        })?;
+        let defaults = self.namespace_defaults(&keyspace_info.namespace).await?;
+        namespace_defaults::apply(&mut keyspace_info, defaults.as_ref());
 
         let response = GetKeyspaceInfoResponse {
             keyspace_info: Some(keyspace_info),
         };
         Ok(Response::new(response))
     }
+
+    #[instrument(skip(self))]
+    async fn set_keyspace_read_only(
+        &self,
+        request: Request<SetKeyspaceReadOnlyRequest>,
+    ) -> Result<Response<SetKeyspaceReadOnlyResponse>, Status> {
+        info!("Got a set_keyspace_read_only request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        let keyspace = req_inner
+            .keyspace
+            .ok_or_else(|| Status::invalid_argument("Missing keyspace"))?;
+        self.storage
+            .set_keyspace_read_only(&keyspace.namespace, &keyspace.name, req_inner.read_only)
+            .await
+            .map_err(|e| match e {
+                StorageError::KeyspaceDoesNotExist => Status::not_found(e.to_string()),
+                _ => Status::internal(format!("Failed to set keyspace read-only flag: {}", e)),
+            })?;
+        Ok(Response::new(SetKeyspaceReadOnlyResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn set_keyspace_validation_policy(
+        &self,
+        request: Request<SetKeyspaceValidationPolicyRequest>,
+    ) -> Result<Response<SetKeyspaceValidationPolicyResponse>, Status> {
+        info!(
+            "Got a set_keyspace_validation_policy request: {:?}",
+            request
+        );
+
+        let req_inner = request.into_inner();
+        let keyspace = req_inner
+            .keyspace
+            .ok_or_else(|| Status::invalid_argument("Missing keyspace"))?;
+        self.storage
+            .set_keyspace_validation_policy(
+                &keyspace.namespace,
+                &keyspace.name,
+                req_inner.validation_policy,
+            )
+            .await
+            .map_err(|e| match e {
+                StorageError::KeyspaceDoesNotExist => Status::not_found(e.to_string()),
+                _ => Status::internal(format!("Failed to set keyspace validation policy: {}", e)),
+            })?;
+        Ok(Response::new(SetKeyspaceValidationPolicyResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn set_keyspace_placement(
+        &self,
+        request: Request<SetKeyspacePlacementRequest>,
+    ) -> Result<Response<SetKeyspacePlacementResponse>, Status> {
+        info!("Got a set_keyspace_placement request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        let keyspace = req_inner
+            .keyspace
+            .ok_or_else(|| Status::invalid_argument("Missing keyspace"))?;
+        if let Some(placement) = &req_inner.placement {
+            if placement.required_labels.keys().any(|key| key.is_empty()) {
+                return Err(Status::invalid_argument("Label keys cannot be empty"));
+            }
+        }
+        self.storage
+            .set_keyspace_placement(&keyspace.namespace, &keyspace.name, req_inner.placement)
+            .await
+            .map_err(|e| match e {
+                StorageError::KeyspaceDoesNotExist => Status::not_found(e.to_string()),
+                _ => Status::internal(format!("Failed to set keyspace placement: {}", e)),
+            })?;
+        Ok(Response::new(SetKeyspacePlacementResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn set_keyspace_colocation(
+        &self,
+        request: Request<SetKeyspaceColocationRequest>,
+    ) -> Result<Response<SetKeyspaceColocationResponse>, Status> {
+        info!("Got a set_keyspace_colocation request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        let keyspace = req_inner
+            .keyspace
+            .ok_or_else(|| Status::invalid_argument("Missing keyspace"))?;
+        if let Some(colocation) = &req_inner.colocation {
+            if Colocation::from_proto(colocation).is_none() {
+                return Err(Status::invalid_argument(
+                    "Colocation needs a non-zero prefix length or a single byte delimiter",
+                ));
+            }
+        }
+        self.storage
+            .set_keyspace_colocation(&keyspace.namespace, &keyspace.name, req_inner.colocation)
+            .await
+            .map_err(|e| match e {
+                StorageError::KeyspaceDoesNotExist => Status::not_found(e.to_string()),
+                _ => Status::internal(format!("Failed to set keyspace colocation: {}", e)),
+            })?;
+        Ok(Response::new(SetKeyspaceColocationResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn split_key_range(
+        &self,
+        request: Request<SplitKeyRangeRequest>,
+    ) -> Result<Response<SplitKeyRangeResponse>, Status> {
+        info!("Got a split_key_range request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        if req_inner.new_ranges.is_empty() {
+            return Err(Status::invalid_argument("Missing new ranges"));
+        }
+        for id in std::iter::once(&req_inner.keyspace_id)
+            .chain(std::iter::once(&req_inner.range_id))
+            .chain(req_inner.new_ranges.iter().map(|r| &r.base_range_uuid))
+        {
+            Uuid::parse_str(id)
+                .map_err(|e| Status::invalid_argument(format!("Invalid id {}: {}", id, e)))?;
+        }
+        self.storage
+            .split_key_range(
+                &req_inner.keyspace_id,
+                &req_inner.range_id,
+                req_inner.new_ranges,
+            )
+            .await
+            .map_err(|e| match e {
+                StorageError::KeyspaceDoesNotExist | StorageError::RangeDoesNotExist => {
+                    Status::not_found(e.to_string())
+                }
+                _ => Status::internal(format!("Failed to split key range: {}", e)),
+            })?;
+        Ok(Response::new(SplitKeyRangeResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn set_namespace_policy(
+        &self,
+        request: Request<SetNamespacePolicyRequest>,
+    ) -> Result<Response<SetNamespacePolicyResponse>, Status> {
+        info!("Got a set_namespace_policy request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        self.storage
+            .set_namespace_policy(&req_inner.namespace, req_inner.policy)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to set namespace policy: {}", e)))?;
+        Ok(Response::new(SetNamespacePolicyResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_namespace_policy(
+        &self,
+        request: Request<GetNamespacePolicyRequest>,
+    ) -> Result<Response<GetNamespacePolicyResponse>, Status> {
+        debug!("Got a get_namespace_policy request: {:?}", request);
+
+        let policy = self
+            .storage
+            .get_namespace_policy(&request.into_inner().namespace)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to get namespace policy: {}", e)))?;
+        Ok(Response::new(GetNamespacePolicyResponse { policy }))
+    }
+
+    #[instrument(skip(self))]
+    async fn set_namespace_defaults(
+        &self,
+        request: Request<SetNamespaceDefaultsRequest>,
+    ) -> Result<Response<SetNamespaceDefaultsResponse>, Status> {
+        info!("Got a set_namespace_defaults request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        self.storage
+            .set_namespace_defaults(&req_inner.namespace, req_inner.defaults)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to set namespace defaults: {}", e)))?;
+        Ok(Response::new(SetNamespaceDefaultsResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_namespace_defaults(
+        &self,
+        request: Request<GetNamespaceDefaultsRequest>,
+    ) -> Result<Response<GetNamespaceDefaultsResponse>, Status> {
+        debug!("Got a get_namespace_defaults request: {:?}", request);
+
+        let defaults = self
+            .namespace_defaults(&request.into_inner().namespace)
+            .await?;
+        Ok(Response::new(GetNamespaceDefaultsResponse { defaults }))
+    }
+
+    #[instrument(skip(self))]
+    async fn check_cross_namespace_access(
+        &self,
+        request: Request<CheckCrossNamespaceAccessRequest>,
+    ) -> Result<Response<CheckCrossNamespaceAccessResponse>, Status> {
+        debug!("Got a check_cross_namespace_access request: {:?}", request);
+
+        let mut namespaces = request.into_inner().namespaces;
+        namespaces.sort();
+        namespaces.dedup();
+        let mut policies = HashMap::new();
+        if namespaces.len() > 1 {
+            for namespace in &namespaces {
+                let policy = self
+                    .storage
+                    .get_namespace_policy(namespace)
+                    .await
+                    .map_err(|e| {
+                        Status::internal(format!("Failed to get namespace policy: {}", e))
+                    })?;
+                if let Some(policy) = policy {
+                    policies.insert(namespace.clone(), policy);
+                }
+            }
+        }
+        let denied = namespace_policy::denied_pairs(&namespaces, &policies);
+        Ok(Response::new(CheckCrossNamespaceAccessResponse {
+            allowed: denied.is_empty(),
+            denied,
+        }))
+    }
+
+    #[instrument(skip(self))]
+    async fn rename_keyspace(
+        &self,
+        request: Request<RenameKeyspaceRequest>,
+    ) -> Result<Response<RenameKeyspaceResponse>, Status> {
+        info!("Got a rename_keyspace request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        let keyspace = req_inner
+            .keyspace
+            .ok_or_else(|| Status::invalid_argument("Missing keyspace"))?;
+        if req_inner.new_name.is_empty() || req_inner.new_name == keyspace.name {
+            return Err(Status::invalid_argument("Invalid new keyspace name"));
+        }
+        self.storage
+            .rename_keyspace(
+                &keyspace.namespace,
+                &keyspace.name,
+                &req_inner.new_name,
+                req_inner.keep_alias,
+            )
+            .await
+            .map_err(|e| match e {
+                StorageError::KeyspaceDoesNotExist => Status::not_found(e.to_string()),
+                StorageError::KeyspaceAlreadyExists => Status::already_exists(e.to_string()),
+                _ => Status::internal(format!("Failed to rename keyspace: {}", e)),
+            })?;
+        Ok(Response::new(RenameKeyspaceResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn list_keyspace_aliases(
+        &self,
+        request: Request<ListKeyspaceAliasesRequest>,
+    ) -> Result<Response<ListKeyspaceAliasesResponse>, Status> {
+        debug!("Got a list_keyspace_aliases request: {:?}", request);
+
+        let aliases = self
+            .storage
+            .list_keyspace_aliases(&request.into_inner().namespace)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to list keyspace aliases: {}", e)))?;
+        Ok(Response::new(ListKeyspaceAliasesResponse { aliases }))
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_keyspace_alias(
+        &self,
+        request: Request<DeleteKeyspaceAliasRequest>,
+    ) -> Result<Response<DeleteKeyspaceAliasResponse>, Status> {
+        info!("Got a delete_keyspace_alias request: {:?}", request);
+
+        let req_inner = request.into_inner();
+        self.storage
+            .delete_keyspace_alias(&req_inner.namespace, &req_inner.alias)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to delete keyspace alias: {}", e)))?;
+        Ok(Response::new(DeleteKeyspaceAliasResponse {}))
+    }
 }
 
 /// Runs the Universe Manager, listening on the provided address.
@@ -1,6 +1,7 @@
 use proto::universe::{
-    get_keyspace_info_request::KeyspaceInfoSearchField as ProtoKeyspaceInfoSearchField, KeyRange,
-    Keyspace, KeyspaceInfo, Zone,
+    get_keyspace_info_request::KeyspaceInfoSearchField as ProtoKeyspaceInfoSearchField,
+    ColocationPolicy, KeyRange, Keyspace, KeyspaceAlias, KeyspaceInfo, NamespaceDefaults,
+    NamespacePolicy, PlacementConstraints, ValidationPolicy, Zone,
 };
 use std::sync::Arc;
 use thiserror::Error;
@@ -17,9 +18,11 @@ pub enum Error {
     InternalError(Option<Arc<dyn std::error::Error + Send + Sync>>),
     #[error("Keyspace does not exist")]
     KeyspaceDoesNotExist,
+    #[error("Range does not exist")]
+    RangeDoesNotExist,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum KeyspaceInfoSearchField {
     Keyspace { namespace: String, name: String },
     KeyspaceId(String),
@@ -60,6 +63,7 @@ pub trait Storage: Send + Sync + 'static {
         namespace: &str,
         primary_zone: Zone,
         base_key_ranges: Vec<KeyRange>,
+        optimistic_reads: bool,
     ) -> impl std::future::Future<Output = Result<String, Error>> + Send;
 
     fn list_keyspaces(
@@ -71,4 +75,96 @@ pub trait Storage: Send + Sync + 'static {
         &self,
         keyspace_info_search_field: KeyspaceInfoSearchField,
     ) -> impl std::future::Future<Output = Result<KeyspaceInfo, Error>> + Send;
+
+    fn set_keyspace_read_only(
+        &self,
+        namespace: &str,
+        name: &str,
+        read_only: bool,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    fn set_keyspace_validation_policy(
+        &self,
+        namespace: &str,
+        name: &str,
+        validation_policy: Option<ValidationPolicy>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    /// Removes the keyspace's constraints if `placement` is None.
+    fn set_keyspace_placement(
+        &self,
+        namespace: &str,
+        name: &str,
+        placement: Option<PlacementConstraints>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    /// Removes the keyspace's policy if `colocation` is None.
+    fn set_keyspace_colocation(
+        &self,
+        namespace: &str,
+        name: &str,
+        colocation: Option<ColocationPolicy>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    /// Replaces the range of the keyspace whose base_range_uuid is `range_id`
+    /// with `new_ranges`. Does nothing if that was already done.
+    fn split_key_range(
+        &self,
+        keyspace_id: &str,
+        range_id: &str,
+        new_ranges: Vec<KeyRange>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    /// Removes the namespace's policy if `policy` is None.
+    fn set_namespace_policy(
+        &self,
+        namespace: &str,
+        policy: Option<NamespacePolicy>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    fn get_namespace_policy(
+        &self,
+        namespace: &str,
+    ) -> impl std::future::Future<Output = Result<Option<NamespacePolicy>, Error>> + Send;
+
+    /// Removes the namespace's defaults if `defaults` is None.
+    fn set_namespace_defaults(
+        &self,
+        namespace: &str,
+        defaults: Option<NamespaceDefaults>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    fn get_namespace_defaults(
+        &self,
+        namespace: &str,
+    ) -> impl std::future::Future<Output = Result<Option<NamespaceDefaults>, Error>> + Send;
+
+    /// Renames the keyspace within its namespace, keeping an alias for the
+    /// old name if `keep_alias` is set. Aliases of the keyspace follow it to
+    /// the new name.
+    fn rename_keyspace(
+        &self,
+        namespace: &str,
+        name: &str,
+        new_name: &str,
+        keep_alias: bool,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    /// Returns the name of the keyspace the alias stands for, if any.
+    fn get_keyspace_alias(
+        &self,
+        namespace: &str,
+        alias: &str,
+    ) -> impl std::future::Future<Output = Result<Option<String>, Error>> + Send;
+
+    fn list_keyspace_aliases(
+        &self,
+        namespace: &str,
+    ) -> impl std::future::Future<Output = Result<Vec<KeyspaceAlias>, Error>> + Send;
+
+    fn delete_keyspace_alias(
+        &self,
+        namespace: &str,
+        alias: &str,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
 }
@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use super::*;
-use proto::universe::KeyspaceInfo;
+use proto::universe::{
+    colocation_policy::GroupPrefix, AntiAffinity, ColocationPolicy, KeyspaceAlias, KeyspaceInfo,
+    NamespaceDefaults, NamespacePolicy, PlacementConstraints, ValidationPolicy, ValueFormat,
+};
 use scylla::macros::{FromUserType, SerializeValue};
 use scylla::query::Query;
 use scylla::statement::SerialConsistency;
@@ -18,18 +22,21 @@ pub struct Cassandra {
 
 static CREATE_KEYSPACE_QUERY: &str = r#"
     INSERT INTO atomix.keyspaces
-    (keyspace_id, name, namespace, primary_zone, base_key_ranges)
-    VALUES (?, ?, ?, ?, ?)
+    (keyspace_id, name, namespace, primary_zone, base_key_ranges, read_only,
+     optimistic_reads, validation_policy, placement, colocation)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
     IF NOT EXISTS
 "#;
 
 static LIST_KEYSPACES_QUERY: &str = r#"
-    SELECT keyspace_id, name, namespace, primary_zone, base_key_ranges
+    SELECT keyspace_id, name, namespace, primary_zone, base_key_ranges, read_only,
+        optimistic_reads, validation_policy, placement, colocation
     FROM atomix.keyspaces
 "#;
 
 static GET_KEYSPACE_INFO_BY_KEYSPACE_QUERY: &str = r#"
-    SELECT keyspace_id, name, namespace, primary_zone, base_key_ranges
+    SELECT keyspace_id, name, namespace, primary_zone, base_key_ranges, read_only,
+        optimistic_reads, validation_policy, placement, colocation
     FROM atomix.keyspaces
     WHERE namespace = ? AND name = ?
 "#;
@@ -37,11 +44,96 @@ static GET_KEYSPACE_INFO_BY_KEYSPACE_QUERY: &str = r#"
 //  TODO(kelly): Add ALLOW FILTERING is bad - discuss whether we will ever need to query by KeyspaceId in practice
 //  and create an index on the field if so.
 static GET_KEYSPACE_INFO_BY_KEYSPACE_ID_QUERY: &str = r#"
-    SELECT keyspace_id, name, namespace, primary_zone, base_key_ranges
+    SELECT keyspace_id, name, namespace, primary_zone, base_key_ranges, read_only,
+        optimistic_reads, validation_policy, placement, colocation
     FROM atomix.keyspaces
     WHERE keyspace_id = ? ALLOW FILTERING
 "#;
 
+static SET_KEYSPACE_READ_ONLY_QUERY: &str = r#"
+    UPDATE atomix.keyspaces SET read_only = ?
+    WHERE namespace = ? AND name = ?
+    IF EXISTS
+"#;
+
+static SET_KEYSPACE_VALIDATION_POLICY_QUERY: &str = r#"
+    UPDATE atomix.keyspaces SET validation_policy = ?
+    WHERE namespace = ? AND name = ?
+    IF EXISTS
+"#;
+
+static SET_KEYSPACE_PLACEMENT_QUERY: &str = r#"
+    UPDATE atomix.keyspaces SET placement = ?
+    WHERE namespace = ? AND name = ?
+    IF EXISTS
+"#;
+
+static SET_KEYSPACE_COLOCATION_QUERY: &str = r#"
+    UPDATE atomix.keyspaces SET colocation = ?
+    WHERE namespace = ? AND name = ?
+    IF EXISTS
+"#;
+
+static SET_NAMESPACE_POLICY_QUERY: &str = r#"
+    INSERT INTO atomix.namespace_policies (namespace, cross_namespace_peers)
+    VALUES (?, ?)
+"#;
+
+static DELETE_NAMESPACE_POLICY_QUERY: &str = r#"
+    DELETE FROM atomix.namespace_policies WHERE namespace = ?
+"#;
+
+static GET_NAMESPACE_POLICY_QUERY: &str = r#"
+    SELECT cross_namespace_peers FROM atomix.namespace_policies
+    WHERE namespace = ?
+"#;
+
+static SET_NAMESPACE_DEFAULTS_QUERY: &str = r#"
+    INSERT INTO atomix.namespace_defaults (namespace, optimistic_reads, validation_policy)
+    VALUES (?, ?, ?)
+"#;
+
+static DELETE_NAMESPACE_DEFAULTS_QUERY: &str = r#"
+    DELETE FROM atomix.namespace_defaults WHERE namespace = ?
+"#;
+
+static GET_NAMESPACE_DEFAULTS_QUERY: &str = r#"
+    SELECT optimistic_reads, validation_policy FROM atomix.namespace_defaults
+    WHERE namespace = ?
+"#;
+
+static DELETE_KEYSPACE_QUERY: &str = r#"
+    DELETE FROM atomix.keyspaces
+    WHERE namespace = ? AND name = ?
+    IF keyspace_id = ?
+"#;
+
+static SET_KEYSPACE_ALIAS_QUERY: &str = r#"
+    INSERT INTO atomix.keyspace_aliases (namespace, alias, name)
+    VALUES (?, ?, ?)
+"#;
+
+static GET_KEYSPACE_ALIAS_QUERY: &str = r#"
+    SELECT name FROM atomix.keyspace_aliases
+    WHERE namespace = ? AND alias = ?
+"#;
+
+static LIST_KEYSPACE_ALIASES_QUERY: &str = r#"
+    SELECT alias, name FROM atomix.keyspace_aliases
+    WHERE namespace = ?
+"#;
+
+static DELETE_KEYSPACE_ALIAS_QUERY: &str = r#"
+    DELETE FROM atomix.keyspace_aliases
+    WHERE namespace = ? AND alias = ?
+"#;
+
+static SET_KEYSPACE_KEY_RANGES_QUERY: &str = r#"
+    UPDATE atomix.keyspaces SET base_key_ranges = ?
+    WHERE namespace = ? AND name = ?
+    IF EXISTS
+"#;
+
 // TODO: Similar to tx_state_store. We should move this to a common location.
 fn get_serial_query(query_text: impl Into<String>) -> Query {
     let mut query = Query::new(query_text);
@@ -85,6 +177,105 @@ struct SerializedKeyRange {
     upper_bound_exclusive: Option<Vec<u8>>,
 }
 
+impl SerializedKeyRange {
+    fn from_proto(range: KeyRange) -> Self {
+        SerializedKeyRange {
+            base_range_uuid: Uuid::from_str(&range.base_range_uuid).unwrap(),
+            lower_bound_inclusive: Some(range.lower_bound_inclusive),
+            upper_bound_exclusive: Some(range.upper_bound_exclusive),
+        }
+    }
+}
+
+#[derive(Debug, FromUserType, SerializeValue)]
+struct SerializedValidationPolicy {
+    max_key_size: i32,
+    max_value_size: i32,
+    value_format: String,
+}
+
+impl SerializedValidationPolicy {
+    fn from_proto(policy: ValidationPolicy) -> Self {
+        SerializedValidationPolicy {
+            max_key_size: policy.max_key_size as i32,
+            max_value_size: policy.max_value_size as i32,
+            value_format: policy.value_format().as_str_name().to_string(),
+        }
+    }
+
+    fn into_proto(self) -> ValidationPolicy {
+        // Formats this build does not know about are checked as opaque bytes.
+        let value_format =
+            ValueFormat::from_str_name(&self.value_format).unwrap_or(ValueFormat::Any);
+        ValidationPolicy {
+            max_key_size: self.max_key_size as u32,
+            max_value_size: self.max_value_size as u32,
+            value_format: value_format as i32,
+        }
+    }
+}
+
+#[derive(Debug, FromUserType, SerializeValue)]
+struct SerializedPlacementConstraints {
+    // Scylla reads back an empty map as null.
+    required_labels: Option<HashMap<String, String>>,
+    anti_affinity: String,
+}
+
+impl SerializedPlacementConstraints {
+    fn from_proto(placement: PlacementConstraints) -> Self {
+        SerializedPlacementConstraints {
+            anti_affinity: placement.anti_affinity().as_str_name().to_string(),
+            required_labels: Some(placement.required_labels),
+        }
+    }
+
+    fn into_proto(self) -> PlacementConstraints {
+        // Rules this build does not know about are not enforced.
+        let anti_affinity =
+            AntiAffinity::from_str_name(&self.anti_affinity).unwrap_or(AntiAffinity::None);
+        PlacementConstraints {
+            required_labels: self.required_labels.unwrap_or_default(),
+            anti_affinity: anti_affinity as i32,
+        }
+    }
+}
+
+// Exactly one of the fields is set.
+#[derive(Debug, FromUserType, SerializeValue)]
+struct SerializedColocationPolicy {
+    prefix_length: Option<i32>,
+    delimiter: Option<Vec<u8>>,
+}
+
+impl SerializedColocationPolicy {
+    fn from_proto(policy: ColocationPolicy) -> Self {
+        match policy.group_prefix {
+            Some(GroupPrefix::PrefixLength(len)) => SerializedColocationPolicy {
+                prefix_length: Some(len as i32),
+                delimiter: None,
+            },
+            Some(GroupPrefix::Delimiter(delimiter)) => SerializedColocationPolicy {
+                prefix_length: None,
+                delimiter: Some(delimiter),
+            },
+            None => SerializedColocationPolicy {
+                prefix_length: None,
+                delimiter: None,
+            },
+        }
+    }
+
+    fn into_proto(self) -> ColocationPolicy {
+        let group_prefix = match (self.prefix_length, self.delimiter) {
+            (Some(len), _) => Some(GroupPrefix::PrefixLength(len as u32)),
+            (None, Some(delimiter)) => Some(GroupPrefix::Delimiter(delimiter)),
+            (None, None) => None,
+        };
+        ColocationPolicy { group_prefix }
+    }
+}
+
 #[derive(Debug, FromRow, SerializeRow)]
 struct SerializedKeyspaceInfo {
     keyspace_id: Uuid,
@@ -94,15 +285,28 @@ struct SerializedKeyspaceInfo {
     // We need Option here because Scylla doesn't support empty lists.
     // Without Option, on reading, deserialization will fail if the list is empty.
     base_key_ranges: Vec<SerializedKeyRange>,
+    // Null for keyspaces created before the column was added.
+    read_only: Option<bool>,
+    // Same.
+    optimistic_reads: Option<bool>,
+    validation_policy: Option<SerializedValidationPolicy>,
+    placement: Option<SerializedPlacementConstraints>,
+    colocation: Option<SerializedColocationPolicy>,
 }
 
 impl SerializedKeyspaceInfo {
+    #[allow(clippy::too_many_arguments)]
     fn construct_from_parts(
         keyspace_id: Uuid,
         name: String,
         namespace: String,
         primary_zone: Zone,
         base_key_range_requests: Vec<KeyRange>,
+        read_only: bool,
+        optimistic_reads: bool,
+        validation_policy: Option<ValidationPolicy>,
+        placement: Option<PlacementConstraints>,
+        colocation: Option<ColocationPolicy>,
     ) -> Self {
         SerializedKeyspaceInfo {
             keyspace_id,
@@ -126,12 +330,13 @@ impl SerializedKeyspaceInfo {
             },
             base_key_ranges: base_key_range_requests
                 .into_iter()
-                .map(|range| SerializedKeyRange {
-                    base_range_uuid: Uuid::from_str(&range.base_range_uuid).unwrap(),
-                    lower_bound_inclusive: Some(range.lower_bound_inclusive),
-                    upper_bound_exclusive: Some(range.upper_bound_exclusive),
-                })
+                .map(SerializedKeyRange::from_proto)
                 .collect(),
+            read_only: Some(read_only),
+            optimistic_reads: Some(optimistic_reads),
+            validation_policy: validation_policy.map(SerializedValidationPolicy::from_proto),
+            placement: placement.map(SerializedPlacementConstraints::from_proto),
+            colocation: colocation.map(SerializedColocationPolicy::from_proto),
         }
     }
 
@@ -173,6 +378,16 @@ impl SerializedKeyspaceInfo {
             namespace: self.namespace,
             primary_zone: Some(primary_zone),
             base_key_ranges,
+            read_only: self.read_only.unwrap_or(false),
+            optimistic_reads: self.optimistic_reads.unwrap_or(false),
+            validation_policy: self
+                .validation_policy
+                .map(SerializedValidationPolicy::into_proto),
+            validation_policy_inherited: false,
+            placement: self
+                .placement
+                .map(SerializedPlacementConstraints::into_proto),
+            colocation: self.colocation.map(SerializedColocationPolicy::into_proto),
         }
     }
 }
@@ -186,6 +401,20 @@ impl Cassandra {
             .unwrap();
         Self { session }
     }
+
+    async fn set_keyspace_alias(
+        &self,
+        namespace: &str,
+        alias: &str,
+        name: &str,
+    ) -> Result<(), Error> {
+        let query = get_serial_query(SET_KEYSPACE_ALIAS_QUERY);
+        self.session
+            .query_unpaged(query, (namespace, alias, name))
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        Ok(())
+    }
 }
 
 impl Storage for Cassandra {
@@ -196,6 +425,7 @@ impl Storage for Cassandra {
         namespace: &str,
         primary_zone: Zone,
         base_key_ranges: Vec<KeyRange>,
+        optimistic_reads: bool,
     ) -> Result<String, Error> {
         // TODO: Validate base_key_ranges
 
@@ -206,6 +436,11 @@ impl Storage for Cassandra {
             namespace.to_string(),
             primary_zone,
             base_key_ranges,
+            false,
+            optimistic_reads,
+            None,
+            None,
+            None,
         );
 
         let keyspace_id = keyspace_id.to_string();
@@ -315,6 +550,382 @@ impl Storage for Cassandra {
             Err(Error::KeyspaceDoesNotExist)
         }
     }
+
+    async fn set_keyspace_read_only(
+        &self,
+        namespace: &str,
+        name: &str,
+        read_only: bool,
+    ) -> Result<(), Error> {
+        let query = get_serial_query(SET_KEYSPACE_READ_ONLY_QUERY);
+        let query_result = self
+            .session
+            .query_single_page(query, (read_only, namespace, name), PagingState::start())
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        // If the first row of the result is false, the update was not applied
+        // because the keyspace does not exist.
+        if let Some(Some(update_applied)) = query_result.0.first_row().unwrap().columns.first() {
+            if !update_applied.as_boolean().unwrap() {
+                return Err(Error::KeyspaceDoesNotExist);
+            }
+        } else {
+            return Err(Error::InternalError(None));
+        }
+
+        Ok(())
+    }
+
+    async fn set_keyspace_validation_policy(
+        &self,
+        namespace: &str,
+        name: &str,
+        validation_policy: Option<ValidationPolicy>,
+    ) -> Result<(), Error> {
+        let query = get_serial_query(SET_KEYSPACE_VALIDATION_POLICY_QUERY);
+        let query_result = self
+            .session
+            .query_single_page(
+                query,
+                (
+                    validation_policy.map(SerializedValidationPolicy::from_proto),
+                    namespace,
+                    name,
+                ),
+                PagingState::start(),
+            )
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        // Same as for set_keyspace_read_only.
+        if let Some(Some(update_applied)) = query_result.0.first_row().unwrap().columns.first() {
+            if !update_applied.as_boolean().unwrap() {
+                return Err(Error::KeyspaceDoesNotExist);
+            }
+        } else {
+            return Err(Error::InternalError(None));
+        }
+
+        Ok(())
+    }
+
+    async fn set_keyspace_placement(
+        &self,
+        namespace: &str,
+        name: &str,
+        placement: Option<PlacementConstraints>,
+    ) -> Result<(), Error> {
+        let query = get_serial_query(SET_KEYSPACE_PLACEMENT_QUERY);
+        let query_result = self
+            .session
+            .query_single_page(
+                query,
+                (
+                    placement.map(SerializedPlacementConstraints::from_proto),
+                    namespace,
+                    name,
+                ),
+                PagingState::start(),
+            )
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        // Same as for set_keyspace_read_only.
+        if let Some(Some(update_applied)) = query_result.0.first_row().unwrap().columns.first() {
+            if !update_applied.as_boolean().unwrap() {
+                return Err(Error::KeyspaceDoesNotExist);
+            }
+        } else {
+            return Err(Error::InternalError(None));
+        }
+
+        Ok(())
+    }
+
+    async fn set_keyspace_colocation(
+        &self,
+        namespace: &str,
+        name: &str,
+        colocation: Option<ColocationPolicy>,
+    ) -> Result<(), Error> {
+        let query = get_serial_query(SET_KEYSPACE_COLOCATION_QUERY);
+        let query_result = self
+            .session
+            .query_single_page(
+                query,
+                (
+                    colocation.map(SerializedColocationPolicy::from_proto),
+                    namespace,
+                    name,
+                ),
+                PagingState::start(),
+            )
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        // Same as for set_keyspace_read_only.
+        if let Some(Some(update_applied)) = query_result.0.first_row().unwrap().columns.first() {
+            if !update_applied.as_boolean().unwrap() {
+                return Err(Error::KeyspaceDoesNotExist);
+            }
+        } else {
+            return Err(Error::InternalError(None));
+        }
+
+        Ok(())
+    }
+
+    async fn split_key_range(
+        &self,
+        keyspace_id: &str,
+        range_id: &str,
+        new_ranges: Vec<KeyRange>,
+    ) -> Result<(), Error> {
+        let info = self
+            .get_keyspace_info(KeyspaceInfoSearchField::KeyspaceId(keyspace_id.to_string()))
+            .await?;
+        let mut key_ranges = info.base_key_ranges;
+        let Some(position) = key_ranges
+            .iter()
+            .position(|range| range.base_range_uuid == range_id)
+        else {
+            let already_split = new_ranges.iter().all(|new| {
+                key_ranges
+                    .iter()
+                    .any(|r| r.base_range_uuid == new.base_range_uuid)
+            });
+            return if already_split {
+                Ok(())
+            } else {
+                Err(Error::RangeDoesNotExist)
+            };
+        };
+        key_ranges.splice(position..position + 1, new_ranges);
+        let serialized: Vec<_> = key_ranges
+            .into_iter()
+            .map(SerializedKeyRange::from_proto)
+            .collect();
+        let query = get_serial_query(SET_KEYSPACE_KEY_RANGES_QUERY);
+        let query_result = self
+            .session
+            .query_single_page(
+                query,
+                (serialized, info.namespace, info.name),
+                PagingState::start(),
+            )
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        // Same as for set_keyspace_read_only.
+        if let Some(Some(update_applied)) = query_result.0.first_row().unwrap().columns.first() {
+            if !update_applied.as_boolean().unwrap() {
+                return Err(Error::KeyspaceDoesNotExist);
+            }
+        } else {
+            return Err(Error::InternalError(None));
+        }
+
+        Ok(())
+    }
+
+    async fn set_namespace_policy(
+        &self,
+        namespace: &str,
+        policy: Option<NamespacePolicy>,
+    ) -> Result<(), Error> {
+        match policy {
+            Some(policy) => {
+                let query = get_serial_query(SET_NAMESPACE_POLICY_QUERY);
+                self.session
+                    .query_unpaged(query, (namespace, policy.cross_namespace_peers))
+                    .await
+            }
+            None => {
+                let query = get_serial_query(DELETE_NAMESPACE_POLICY_QUERY);
+                self.session.query_unpaged(query, (namespace,)).await
+            }
+        }
+        .map_err(scylla_query_error_to_storage_error)?;
+        Ok(())
+    }
+
+    async fn get_namespace_policy(
+        &self,
+        namespace: &str,
+    ) -> Result<Option<NamespacePolicy>, Error> {
+        let query = get_serial_query(GET_NAMESPACE_POLICY_QUERY);
+        let row = self
+            .session
+            .query_unpaged(query, (namespace,))
+            .await
+            .map_err(scylla_query_error_to_storage_error)?
+            .maybe_first_row_typed::<(Option<Vec<String>>,)>()
+            .map_err(|e| Error::InternalError(Some(Arc::new(e))))?;
+        // Scylla reads back an empty set as null.
+        Ok(row.map(|(peers,)| NamespacePolicy {
+            cross_namespace_peers: peers.unwrap_or_default(),
+        }))
+    }
+
+    async fn set_namespace_defaults(
+        &self,
+        namespace: &str,
+        defaults: Option<NamespaceDefaults>,
+    ) -> Result<(), Error> {
+        match defaults {
+            Some(defaults) => {
+                let query = get_serial_query(SET_NAMESPACE_DEFAULTS_QUERY);
+                self.session
+                    .query_unpaged(
+                        query,
+                        (
+                            namespace,
+                            defaults.optimistic_reads,
+                            defaults
+                                .validation_policy
+                                .map(SerializedValidationPolicy::from_proto),
+                        ),
+                    )
+                    .await
+            }
+            None => {
+                let query = get_serial_query(DELETE_NAMESPACE_DEFAULTS_QUERY);
+                self.session.query_unpaged(query, (namespace,)).await
+            }
+        }
+        .map_err(scylla_query_error_to_storage_error)?;
+        Ok(())
+    }
+
+    async fn get_namespace_defaults(
+        &self,
+        namespace: &str,
+    ) -> Result<Option<NamespaceDefaults>, Error> {
+        let query = get_serial_query(GET_NAMESPACE_DEFAULTS_QUERY);
+        let row = self
+            .session
+            .query_unpaged(query, (namespace,))
+            .await
+            .map_err(scylla_query_error_to_storage_error)?
+            .maybe_first_row_typed::<(Option<bool>, Option<SerializedValidationPolicy>)>()
+            .map_err(|e| Error::InternalError(Some(Arc::new(e))))?;
+        Ok(
+            row.map(|(optimistic_reads, validation_policy)| NamespaceDefaults {
+                optimistic_reads,
+                validation_policy: validation_policy.map(SerializedValidationPolicy::into_proto),
+            }),
+        )
+    }
+
+    async fn rename_keyspace(
+        &self,
+        namespace: &str,
+        name: &str,
+        new_name: &str,
+        keep_alias: bool,
+    ) -> Result<(), Error> {
+        let info = self
+            .get_keyspace_info(KeyspaceInfoSearchField::Keyspace {
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+            })
+            .await?;
+        match self.get_keyspace_alias(namespace, new_name).await? {
+            // Renaming back to a name the keyspace had before.
+            Some(target) if target == name => {
+                self.delete_keyspace_alias(namespace, new_name).await?
+            }
+            Some(_) => return Err(Error::KeyspaceAlreadyExists),
+            None => (),
+        }
+
+        // The keyspace is written under its new name first and removed from
+        // its old one last, with the aliases updated in between, so that it
+        // can be looked up by the old name throughout.
+        let keyspace_id = Uuid::from_str(&info.keyspace_id).unwrap();
+        let serialized_info = SerializedKeyspaceInfo::construct_from_parts(
+            keyspace_id,
+            new_name.to_string(),
+            namespace.to_string(),
+            info.primary_zone.unwrap_or_default(),
+            info.base_key_ranges,
+            info.read_only,
+            info.optimistic_reads,
+            info.validation_policy,
+            info.placement,
+            info.colocation,
+        );
+        let query = get_serial_query(CREATE_KEYSPACE_QUERY);
+        let query_result = self
+            .session
+            .query_single_page(query, serialized_info, PagingState::start())
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        // Same as for create_keyspace.
+        if let Some(Some(insert_succeeded)) = query_result.0.first_row().unwrap().columns.first() {
+            if !insert_succeeded.as_boolean().unwrap() {
+                return Err(Error::KeyspaceAlreadyExists);
+            }
+        } else {
+            return Err(Error::InternalError(None));
+        }
+
+        for alias in self.list_keyspace_aliases(namespace).await? {
+            if alias.name == name {
+                self.set_keyspace_alias(namespace, &alias.alias, new_name)
+                    .await?;
+            }
+        }
+        if keep_alias {
+            self.set_keyspace_alias(namespace, name, new_name).await?;
+        }
+
+        let query = get_serial_query(DELETE_KEYSPACE_QUERY);
+        self.session
+            .query_single_page(query, (namespace, name, keyspace_id), PagingState::start())
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        Ok(())
+    }
+
+    async fn get_keyspace_alias(
+        &self,
+        namespace: &str,
+        alias: &str,
+    ) -> Result<Option<String>, Error> {
+        let query = get_serial_query(GET_KEYSPACE_ALIAS_QUERY);
+        let row = self
+            .session
+            .query_unpaged(query, (namespace, alias))
+            .await
+            .map_err(scylla_query_error_to_storage_error)?
+            .maybe_first_row_typed::<(String,)>()
+            .map_err(|e| Error::InternalError(Some(Arc::new(e))))?;
+        Ok(row.map(|(name,)| name))
+    }
+
+    async fn list_keyspace_aliases(&self, namespace: &str) -> Result<Vec<KeyspaceAlias>, Error> {
+        let query = get_serial_query(LIST_KEYSPACE_ALIASES_QUERY);
+        let rows = self
+            .session
+            .query_unpaged(query, (namespace,))
+            .await
+            .map_err(scylla_query_error_to_storage_error)?
+            .rows
+            .unwrap_or_default();
+        rows.into_iter()
+            .map(|row| {
+                row.into_typed::<(String, String)>()
+                    .map(|(alias, name)| KeyspaceAlias { alias, name })
+                    .map_err(|e| Error::InternalError(Some(Arc::new(e))))
+            })
+            .collect()
+    }
+
+    async fn delete_keyspace_alias(&self, namespace: &str, alias: &str) -> Result<(), Error> {
+        let query = get_serial_query(DELETE_KEYSPACE_ALIAS_QUERY);
+        self.session
+            .query_unpaged(query, (namespace, alias))
+            .await
+            .map_err(scylla_query_error_to_storage_error)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -340,6 +951,21 @@ mod tests {
                 }),
                 name: "example_zone".to_string(),
             }),
+            read_only: false,
+            optimistic_reads: false,
+            validation_policy: Some(ValidationPolicy {
+                max_key_size: 64,
+                max_value_size: 1024,
+                value_format: ValueFormat::Msgpack as i32,
+            }),
+            validation_policy_inherited: false,
+            placement: Some(PlacementConstraints {
+                required_labels: HashMap::from([("ssd".to_string(), "true".to_string())]),
+                anti_affinity: AntiAffinity::Zone as i32,
+            }),
+            colocation: Some(ColocationPolicy {
+                group_prefix: Some(GroupPrefix::Delimiter(b"/".to_vec())),
+            }),
             base_key_ranges: vec![
                 KeyRange {
                     base_range_uuid: Uuid::new_v4().to_string(),
@@ -376,6 +1002,11 @@ mod tests {
                     upper_bound_exclusive: range.upper_bound_exclusive.clone(),
                 })
                 .collect(),
+            original.read_only,
+            original.optimistic_reads,
+            original.validation_policy.clone(),
+            original.placement.clone(),
+            original.colocation.clone(),
         );
         let roundtrip = serialized.into_keyspace_info();
         assert!(original == roundtrip);
@@ -419,10 +1050,36 @@ mod tests {
                     &original.namespace,
                     original.primary_zone.clone().unwrap(),
                     base_key_range_requests,
+                    original.optimistic_reads,
                 )
                 .await
                 .unwrap();
             keyspace_ids.push(keyspace_id.clone());
+            // Keyspaces are created without a validation policy.
+            storage
+                .set_keyspace_validation_policy(
+                    &original.namespace,
+                    &original.name,
+                    original.validation_policy.clone(),
+                )
+                .await
+                .unwrap();
+            storage
+                .set_keyspace_placement(
+                    &original.namespace,
+                    &original.name,
+                    original.placement.clone(),
+                )
+                .await
+                .unwrap();
+            storage
+                .set_keyspace_colocation(
+                    &original.namespace,
+                    &original.name,
+                    original.colocation.clone(),
+                )
+                .await
+                .unwrap();
             // Print keyspace id
             println!("Keyspace ID: {}", keyspace_id);
             // List keyspaces from Cassandra
@@ -464,8 +1121,145 @@ mod tests {
                 &keyspace.namespace,
                 keyspace.primary_zone.unwrap(),
                 keyspace.base_key_ranges,
+                keyspace.optimistic_reads,
             )
             .await;
         assert!(matches!(result, Err(Error::KeyspaceAlreadyExists)));
     }
+
+    #[tokio::test]
+    async fn test_cassandra_rename_keyspace() {
+        let uuid_str = Uuid::new_v4().to_string();
+        let namespace = "example_namespace_".to_string() + &uuid_str;
+        let original =
+            create_example_keyspace_info("before".to_string(), namespace.clone(), "r".to_string());
+        let storage = Cassandra::new("127.0.0.1:9042".to_string()).await;
+        let keyspace_id = storage
+            .create_keyspace(
+                &original.keyspace_id,
+                &original.name,
+                &namespace,
+                original.primary_zone.clone().unwrap(),
+                original.base_key_ranges.clone(),
+                original.optimistic_reads,
+            )
+            .await
+            .unwrap();
+
+        storage
+            .rename_keyspace(&namespace, "before", "after", true)
+            .await
+            .unwrap();
+        let renamed = storage
+            .get_keyspace_info(KeyspaceInfoSearchField::Keyspace {
+                namespace: namespace.clone(),
+                name: "after".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(renamed.keyspace_id, keyspace_id);
+        assert_eq!(renamed.base_key_ranges, original.base_key_ranges);
+        let result = storage
+            .get_keyspace_info(KeyspaceInfoSearchField::Keyspace {
+                namespace: namespace.clone(),
+                name: "before".to_string(),
+            })
+            .await;
+        assert!(matches!(result, Err(Error::KeyspaceDoesNotExist)));
+        assert_eq!(
+            storage
+                .get_keyspace_alias(&namespace, "before")
+                .await
+                .unwrap(),
+            Some("after".to_string())
+        );
+
+        // The alias follows the keyspace to its next name.
+        storage
+            .rename_keyspace(&namespace, "after", "latest", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.list_keyspace_aliases(&namespace).await.unwrap(),
+            vec![KeyspaceAlias {
+                alias: "before".to_string(),
+                name: "latest".to_string(),
+            }]
+        );
+
+        storage
+            .delete_keyspace_alias(&namespace, "before")
+            .await
+            .unwrap();
+        assert!(storage
+            .list_keyspace_aliases(&namespace)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cassandra_split_key_range() {
+        let uuid_str = Uuid::new_v4().to_string();
+        let namespace = "example_namespace_".to_string() + &uuid_str;
+        let original =
+            create_example_keyspace_info("split".to_string(), namespace.clone(), "r".to_string());
+        let storage = Cassandra::new("127.0.0.1:9042".to_string()).await;
+        let keyspace_id = storage
+            .create_keyspace(
+                &original.keyspace_id,
+                &original.name,
+                &namespace,
+                original.primary_zone.clone().unwrap(),
+                original.base_key_ranges.clone(),
+                original.optimistic_reads,
+            )
+            .await
+            .unwrap();
+
+        let split = &original.base_key_ranges[0];
+        let new_ranges = vec![
+            KeyRange {
+                base_range_uuid: Uuid::new_v4().to_string(),
+                lower_bound_inclusive: split.lower_bound_inclusive.clone(),
+                upper_bound_exclusive: vec![64],
+            },
+            KeyRange {
+                base_range_uuid: Uuid::new_v4().to_string(),
+                lower_bound_inclusive: vec![64],
+                upper_bound_exclusive: split.upper_bound_exclusive.clone(),
+            },
+        ];
+        for _ in 0..2 {
+            // Replaying the split changes nothing.
+            storage
+                .split_key_range(&keyspace_id, &split.base_range_uuid, new_ranges.clone())
+                .await
+                .unwrap();
+            let info = storage
+                .get_keyspace_info(KeyspaceInfoSearchField::KeyspaceId(keyspace_id.clone()))
+                .await
+                .unwrap();
+            assert_eq!(
+                info.base_key_ranges,
+                vec![
+                    new_ranges[0].clone(),
+                    new_ranges[1].clone(),
+                    original.base_key_ranges[1].clone()
+                ]
+            );
+        }
+        let result = storage
+            .split_key_range(
+                &keyspace_id,
+                &Uuid::new_v4().to_string(),
+                vec![KeyRange {
+                    base_range_uuid: Uuid::new_v4().to_string(),
+                    lower_bound_inclusive: vec![],
+                    upper_bound_exclusive: vec![],
+                }],
+            )
+            .await;
+        assert!(matches!(result, Err(Error::RangeDoesNotExist)));
+    }
 }
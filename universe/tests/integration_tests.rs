@@ -49,6 +49,7 @@ async fn test_create_and_list_keyspace_handlers() {
         namespace: namespace.to_string(),
         primary_zone,
         base_key_ranges,
+        optimistic_reads: None,
     };
     let keyspace_id = client
         .create_keyspace(keyspace_req)
@@ -1,17 +1,19 @@
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::HashMap;
 use std::ops::Add;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{collections::HashSet, ops::Deref, sync::Mutex};
 
 use bytes::Bytes;
+use common::full_range_id::FullRangeId;
 use common::host_info::HostInfo;
 use common::key_range::KeyRange;
+use common::keyspace_id::KeyspaceId;
 use common::region::Region;
 use proto::universe::universe_client::UniverseClient;
-use proto::universe::ListKeyspacesRequest;
+use proto::universe::{ListKeyspacesRequest, PlacementConstraints, SplitKeyRangeRequest};
 use proto::warden::{FullAssignment, WardenUpdate};
-use std::cmp::{Ordering, Reverse};
+use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 use tokio_util::sync::CancellationToken;
@@ -20,10 +22,19 @@ use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::persistence::{Persistence, RangeAssignment, RangeInfo};
+use crate::placement::{self, Placer};
 
 // TODO(purujit): Convert these to configuration.
 const MIN_NUM_RANGE_SERVERS: usize = 1;
 const MAX_VERSIONS_TO_KEEP: usize = 5;
+// Range servers heartbeat every second. One that has not for this long is
+// considered dead, even if its registration stream still looks open, e.g.
+// because its host hung or got partitioned away without closing connections.
+const HEARTBEAT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+// How long a range moved off a live range server is held back from its next
+// one, waiting for the first to report that it let go of the range. Range
+// servers drain ranges for 5 seconds by default.
+const HANDOFF_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
 
 /// We need to implement Eq, Ord and Hash for Range Server identities in
 /// HostInfo.  Since that type is in another crate and may have a different
@@ -67,22 +78,67 @@ impl Hash for HostInfoWrapper {
 pub trait AssignmentComputation {
     fn register_range_server(&self, host_info: HostInfo) -> Result<Receiver<i64>, Status>;
     fn notify_range_server_unavailable(&self, host_info: HostInfo);
+    /// Records a heartbeat of the session of `host_info`. Returns whether the
+    /// session is registered, which it no longer is once the range server
+    /// was declared dead or registered a newer session.
+    fn record_heartbeat(&self, host_info: &HostInfo) -> bool;
     fn get_assignment_update(
         &self,
         host_info: &HostInfo,
         version: i64,
         full_update: bool,
     ) -> Option<WardenUpdate>;
+    /// Records that the range server of `host_info` split `range` into
+    /// `new_ranges`, which stay assigned to it once the split is applied.
+    // Returns the status the warden answers the report with, like
+    // `register_range_server`. Splits are rare, so the size of the error on
+    // this path doesn't matter.
+    #[allow(clippy::result_large_err)]
+    fn report_range_split(
+        &self,
+        host_info: &HostInfo,
+        range: &FullRangeId,
+        new_ranges: Vec<RangeInfo>,
+    ) -> Result<(), Status>;
+    /// Records that a range server drained a range moved off it and stopped
+    /// serving it, so that the range can go to its next range server.
+    fn report_range_handed_off(&self, host_info: &HostInfo, range: &FullRangeId);
+}
+
+/// A range taken off a live range server, which gets to finish the
+/// transactions running on it before the range goes elsewhere.
+#[derive(Clone, Debug)]
+struct PendingHandoff {
+    range: RangeInfo,
+    from: String,
+    deadline: tokio::time::Instant,
+    handed_off: bool,
+}
+
+/// A range split reported by its range server, waiting to be recorded in the
+/// universe and applied to the assignments.
+#[derive(Clone, Debug)]
+struct PendingSplit {
+    parent: RangeInfo,
+    children: Vec<RangeInfo>,
 }
 
 pub struct AssignmentComputationImpl {
     base_ranges: Mutex<Vec<RangeInfo>>,
+    placements: Mutex<HashMap<KeyspaceId, PlacementConstraints>>,
+    // Set when the placement of a keyspace changes, so that its ranges get
+    // checked against it again.
+    placements_changed: Mutex<bool>,
     universe_client: UniverseClient<tonic::transport::Channel>,
     region: Region,
     range_assignments: Mutex<HashMap<i64, Vec<RangeAssignment>>>,
     current_version: Mutex<i64>,
     ready_range_servers: Mutex<HashSet<HostInfoWrapper>>,
+    // When each ready range server last registered or heartbeated, by name.
+    last_heartbeats: Mutex<HashMap<String, tokio::time::Instant>>,
     unassigned_base_ranges: Mutex<Vec<RangeInfo>>,
+    pending_splits: Mutex<Vec<PendingSplit>>,
+    pending_handoffs: Mutex<Vec<PendingHandoff>>,
     assignment_update_sender: Sender<i64>,
     persistence: Arc<dyn Persistence + Send + Sync + 'static>,
 }
@@ -95,6 +151,8 @@ impl AssignmentComputationImpl {
     ) -> Arc<Self> {
         let s = Arc::new(Self {
             base_ranges: Mutex::new(vec![]),
+            placements: Mutex::new(HashMap::new()),
+            placements_changed: Mutex::new(false),
             universe_client,
             region,
             range_assignments: Mutex::new(HashMap::new()),
@@ -106,7 +164,10 @@ impl AssignmentComputationImpl {
                     .as_millis() as i64,
             ),
             ready_range_servers: Mutex::new(HashSet::new()),
+            last_heartbeats: Mutex::new(HashMap::new()),
             unassigned_base_ranges: Mutex::new(vec![]),
+            pending_splits: Mutex::new(vec![]),
+            pending_handoffs: Mutex::new(vec![]),
             // Using capacity 1 here because receivers will resync if they lag.
             assignment_update_sender: channel(1).0,
             persistence,
@@ -142,13 +203,18 @@ impl AssignmentComputationImpl {
         let key_spaces = response.into_inner().keyspaces;
 
         let mut base_ranges = Vec::new();
+        let mut placements = HashMap::new();
         for keyspace in key_spaces {
+            let keyspace_id = KeyspaceId {
+                id: Uuid::parse_str(&keyspace.keyspace_id)
+                    .map_err(|e| tonic::Status::internal(e.to_string()))?,
+            };
+            if let Some(placement) = keyspace.placement {
+                placements.insert(keyspace_id, placement);
+            }
             for range in keyspace.base_key_ranges {
                 base_ranges.push(RangeInfo {
-                    keyspace_id: common::keyspace_id::KeyspaceId {
-                        id: Uuid::parse_str(&keyspace.keyspace_id)
-                            .map_err(|e| tonic::Status::internal(e.to_string()))?,
-                    },
+                    keyspace_id,
                     id: Uuid::parse_str(&range.base_range_uuid)
                         .map_err(|e| tonic::Status::internal(e.to_string()))?,
                     key_range: KeyRange {
@@ -166,6 +232,13 @@ impl AssignmentComputationImpl {
                 });
             }
         }
+        {
+            let mut current_placements = self.placements.lock().unwrap();
+            if *current_placements != placements {
+                *current_placements = placements;
+                *self.placements_changed.lock().unwrap() = true;
+            }
+        }
         let mut new_base_ranges = vec![];
         {
             let mut l = self.base_ranges.lock().unwrap();
@@ -203,6 +276,106 @@ impl AssignmentComputationImpl {
         Ok(())
     }
 
+    /// Records the pending splits in the universe and replaces the split
+    /// ranges with their children in the base ranges. Returns the splits that
+    /// were applied, the others are retried on the next run.
+    async fn apply_pending_splits(&self) -> Vec<PendingSplit> {
+        let pending = std::mem::take(&mut *self.pending_splits.lock().unwrap());
+        let mut applied = vec![];
+        let mut failed = vec![];
+        for split in pending {
+            let request = tonic::Request::new(SplitKeyRangeRequest {
+                keyspace_id: split.parent.keyspace_id.id.to_string(),
+                range_id: split.parent.id.to_string(),
+                new_ranges: split
+                    .children
+                    .iter()
+                    .map(|range| proto::universe::KeyRange {
+                        base_range_uuid: range.id.to_string(),
+                        lower_bound_inclusive: range
+                            .key_range
+                            .lower_bound_inclusive
+                            .as_ref()
+                            .map(|b| b.to_vec())
+                            .unwrap_or_default(),
+                        upper_bound_exclusive: range
+                            .key_range
+                            .upper_bound_exclusive
+                            .as_ref()
+                            .map(|b| b.to_vec())
+                            .unwrap_or_default(),
+                    })
+                    .collect(),
+            });
+            let mut client = self.universe_client.clone();
+            if let Err(e) = client.split_key_range(request).await {
+                error!("Failed to record split of range {:?}: {}", split.parent, e);
+                failed.push(split);
+                continue;
+            }
+            for ranges in [&self.base_ranges, &self.unassigned_base_ranges] {
+                let mut ranges = ranges.lock().unwrap();
+                if let Some(position) = ranges.iter().position(|r| r.id == split.parent.id) {
+                    ranges.splice(position..position + 1, split.children.iter().cloned());
+                }
+            }
+            info!(
+                "Split range {:?} into {:?}.",
+                split.parent.id,
+                split.children.iter().map(|r| r.id).collect::<Vec<_>>()
+            );
+            applied.push(split);
+        }
+        self.pending_splits.lock().unwrap().extend(failed);
+        applied
+    }
+
+    /// Drops the range servers that stopped heartbeating from the ready ones,
+    /// so that the next assignment computation moves their ranges elsewhere.
+    fn remove_dead_range_servers(&self, now: tokio::time::Instant) {
+        let mut ready_servers = self.ready_range_servers.lock().unwrap();
+        let mut last_heartbeats = self.last_heartbeats.lock().unwrap();
+        ready_servers.retain(|server| {
+            let alive = last_heartbeats
+                .get(&server.identity.name)
+                .is_some_and(|last| now.saturating_duration_since(*last) < HEARTBEAT_TIMEOUT);
+            if !alive {
+                warn!(
+                    "Range server {} has not heartbeated for {:?}, considering it dead.",
+                    server.identity.name, HEARTBEAT_TIMEOUT
+                );
+                last_heartbeats.remove(&server.identity.name);
+            }
+            alive
+        });
+    }
+
+    /// Returns the ranges whose previous range server let go of them, gave
+    /// up on draining them in time, or died meanwhile, so that they can be
+    /// assigned again.
+    fn take_handed_off_ranges(&self, now: tokio::time::Instant) -> Vec<RangeInfo> {
+        let ready_servers = self.ready_range_servers.lock().unwrap();
+        let mut ranges = vec![];
+        self.pending_handoffs.lock().unwrap().retain(|handoff| {
+            let from_alive = ready_servers
+                .iter()
+                .any(|s| s.identity.name == handoff.from);
+            let timed_out = now >= handoff.deadline;
+            if !handoff.handed_off && from_alive && !timed_out {
+                return true;
+            }
+            if timed_out && !handoff.handed_off {
+                warn!(
+                    "Range server {} did not hand off range {:?} within {:?}, moving it anyway.",
+                    handoff.from, handoff.range.id, HANDOFF_TIMEOUT
+                );
+            }
+            ranges.push(handoff.range.clone());
+            false
+        });
+        ranges
+    }
+
     async fn assignment_computation_loop(self: Arc<Self>) -> () {
         let mut ready_servers = HashSet::new();
         loop {
@@ -211,6 +384,7 @@ impl AssignmentComputationImpl {
             let _ = self.read_base_ranges().await.map_err(|e| {
                 error!("Failed to read base ranges: {}", e);
             });
+            self.remove_dead_range_servers(tokio::time::Instant::now());
             ready_servers = clone.run_assignment_computation(ready_servers).await;
         }
     }
@@ -220,13 +394,18 @@ impl AssignmentComputationImpl {
     /// This function is called periodically to update the assignment of key ranges to range servers. It performs the following steps:
     ///
     /// 1. Checks if the number of ready range servers is at least the minimum required. If not, it waits for 1 second.
-    /// 2. Computes the set of added and removed servers since the last run.
-    /// 3. If there are no changes in the set of ready servers or unassigned base ranges, it waits for 1 second.
-    /// 4. Constructs a map of assignee (range server) to the list of ranges assigned to that server.
-    /// 5. Builds a min-heap of servers, where the priority is the number of ranges assigned to the server.
-    /// 6. Reassigns any ranges from removed servers to the current servers, starting from the least loaded.
-    /// 7. Assigns any newly added base ranges to the current servers, starting from the least loaded.
-    /// 8. Updates the `range_assignments` vector with the new assignments.
+    /// 2. Applies the range splits reported since the last run, see `apply_pending_splits`.
+    /// 3. Computes the set of added and removed servers since the last run.
+    /// 4. If there are no changes in the set of ready servers, unassigned base ranges, keyspace placements or splits,
+    ///    it waits for 1 second.
+    /// 5. Constructs a map of assignee (range server) to the list of ranges assigned to that server, where the children
+    ///    of a split range take its place on its assignee.
+    /// 6. Builds a `Placer` over the servers, where the load is the number of ranges assigned to the server.
+    /// 7. Reassigns any ranges from removed servers, and any ranges on servers that lost the labels their keyspace requires.
+    /// 8. Assigns these and any newly added base ranges to the servers allowed by their keyspace placement, spreading them
+    ///    across hosts or zones if the keyspace asks for it and otherwise starting from the least loaded.
+    ///    Ranges that no server is allowed to hold stay unassigned.
+    /// 9. Updates the `range_assignments` vector with the new assignments.
     ///
     /// The function returns the current set of ready range servers.
     async fn run_assignment_computation(
@@ -244,13 +423,20 @@ impl AssignmentComputationImpl {
             return new_ready_servers;
         }
 
+        let splits = self.apply_pending_splits().await;
+        let now = tokio::time::Instant::now();
+        let handed_off = self.take_handed_off_ranges(now);
         let added_servers: Vec<_> = new_ready_servers.difference(&prev_ready_servers).collect();
         let removed_servers: Vec<_> = prev_ready_servers.difference(&new_ready_servers).collect();
+        let placements_changed = std::mem::take(&mut *self.placements_changed.lock().unwrap());
         if added_servers.len() == 0
             && removed_servers.len() == 0
             && self.unassigned_base_ranges.lock().unwrap().len() == 0
+            && !placements_changed
+            && splits.is_empty()
+            && handed_off.is_empty()
         {
-            debug!("No changes in the set of ready range servers or unassigned base ranges. Will wait.");
+            debug!("No changes in the set of ready range servers, unassigned base ranges, placements or splits. Will wait.");
             tokio::time::sleep(std::time::Duration::from_secs(1)).await;
             return new_ready_servers;
         }
@@ -275,16 +461,43 @@ impl AssignmentComputationImpl {
                     .push(assignment.range.clone());
             }
         }
-        let mut server_heap = BinaryHeap::new();
+        let mut split_assignments = vec![];
+        for split in &splits {
+            for (assignee, ranges) in assignee_to_range_info.iter_mut() {
+                if let Some(position) = ranges.iter().position(|r| r.id == split.parent.id) {
+                    ranges.splice(position..position + 1, split.children.iter().cloned());
+                    split_assignments.extend(split.children.iter().map(|range| RangeAssignment {
+                        assignee: assignee.clone(),
+                        range: range.clone(),
+                    }));
+                }
+            }
+        }
+        let placements = self.placements.lock().unwrap().clone();
+        let hosts: HashMap<_, _> = prev_ready_servers
+            .iter()
+            .chain(new_ready_servers.iter())
+            .map(|server| (server.identity.name.clone(), server))
+            .collect();
+        let mut placer = Placer::default();
+        let mut add_server = |name: &String, load| match hosts.get(name) {
+            Some(host) => placer.add_server(
+                name.clone(),
+                host.identity.zone.name.clone(),
+                host.labels.clone(),
+                load,
+            ),
+            None => placer.add_server(name.clone(), String::new(), HashMap::new(), load),
+        };
         for (server, ranges) in assignee_to_range_info.iter() {
-            server_heap.push(Reverse((ranges.len(), server.clone())));
+            add_server(server, ranges.len());
         }
-        for added_server in added_servers {
-            server_heap.push(Reverse((0, added_server.identity.name.clone())));
+        for added_server in &added_servers {
+            add_server(&added_server.identity.name, 0);
         }
-        if server_heap.is_empty() {
+        if assignee_to_range_info.is_empty() && added_servers.is_empty() {
             for server in &new_ready_servers {
-                server_heap.push(Reverse((0, server.identity.name.clone())));
+                add_server(&server.identity.name, 0);
             }
         }
         // TODO(purujit): Put the removed servers in quarantine for a few seconds, they might come back.
@@ -296,43 +509,109 @@ impl AssignmentComputationImpl {
         // TODO(purujit): Use a more robust versioning scheme.
         let new_version = self.current_version.lock().unwrap().add(1);
         {
-            let mut updated_assignments = vec![];
+            let mut updated_assignments = split_assignments;
             ranges_to_assign = self.unassigned_base_ranges.lock().unwrap().clone();
+            ranges_to_assign.extend(handed_off.iter().cloned());
+            let mut handoffs = vec![];
 
             for removed_server in removed_servers.clone() {
                 if let Some(ranges) = assignee_to_range_info.remove(&removed_server.identity.name) {
                     ranges_to_assign.extend(ranges);
                 }
             }
+            for (assignee, ranges) in assignee_to_range_info.iter_mut() {
+                let Some(host) = hosts.get(assignee) else {
+                    continue;
+                };
+                ranges.retain(|range| {
+                    let eligible =
+                        placement::is_eligible(&host.labels, placements.get(&range.keyspace_id));
+                    if !eligible {
+                        // The server is alive, so let it drain the range
+                        // before its next server takes over.
+                        handoffs.push(PendingHandoff {
+                            range: range.clone(),
+                            from: assignee.clone(),
+                            deadline: now + HANDOFF_TIMEOUT,
+                            handed_off: false,
+                        });
+                    }
+                    eligible
+                });
+            }
+            for (assignee, ranges) in assignee_to_range_info.iter() {
+                for range in ranges {
+                    placer.count_placed(assignee, range);
+                }
+            }
             debug!("Ranges to assign: {:?}.", ranges_to_assign);
 
             let mut assigned_ranges = HashSet::new();
+            let mut unplaced_ranges = vec![];
             for range in ranges_to_assign.into_iter() {
-                let next_server = server_heap.pop().unwrap();
+                let Some(next_server) = placer.place(&range, placements.get(&range.keyspace_id))
+                else {
+                    warn!(
+                        "No range server satisfies the placement of range {:?}, leaving it unassigned.",
+                        range
+                    );
+                    unplaced_ranges.push(range);
+                    continue;
+                };
                 debug!("Assigning range {:?} to server {:?}.", range, next_server);
                 assigned_ranges.insert(range.id);
                 assignee_to_range_info
-                    .entry(next_server.0 .1.clone())
+                    .entry(next_server.clone())
                     .or_insert_with(Vec::new)
                     .push(range.clone());
-                server_heap.push(Reverse((next_server.0 .0 + 1, next_server.0 .1.clone())));
                 updated_assignments.push(RangeAssignment {
-                    assignee: next_server.0 .1.clone(),
+                    assignee: next_server,
                     range,
                 });
             }
+            if updated_assignments.is_empty()
+                && handoffs.is_empty()
+                && handed_off.is_empty()
+                && added_servers.is_empty()
+                && removed_servers.is_empty()
+                && !placements_changed
+            {
+                // Nothing could be placed, so avoid publishing an identical version in a loop.
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                return new_ready_servers;
+            }
             if let Err(e) = self
                 .persistence
                 .update_range_assignments(new_version, updated_assignments)
                 .await
             {
                 print!("Failed to update range assignments: {:?}.", e);
+                self.pending_splits.lock().unwrap().extend(splits);
+                // Their previous servers let go of them already.
+                self.unassigned_base_ranges
+                    .lock()
+                    .unwrap()
+                    .extend(handed_off);
                 return new_ready_servers;
             }
+            self.pending_handoffs.lock().unwrap().extend(handoffs);
+            if !splits.is_empty() {
+                let parents: Vec<_> = splits.iter().map(|split| split.parent.clone()).collect();
+                if let Err(e) = self.persistence.remove_range_assignments(&parents).await {
+                    warn!("Failed to remove assignments of split ranges: {:?}.", e);
+                }
+            }
             let mut previously_unassigned = self.unassigned_base_ranges.lock().unwrap();
             // Remove only the ranges that were assigned.
             // This is a safe-guard to make sure if we fail to assign any range, it still stays in the unassigned list.
             previously_unassigned.retain(|r| !assigned_ranges.contains(&r.id));
+            // Ranges taken off a server that could not be placed anywhere wait with the unassigned ones.
+            let still_unassigned: HashSet<_> = previously_unassigned.iter().map(|r| r.id).collect();
+            previously_unassigned.extend(
+                unplaced_ranges
+                    .into_iter()
+                    .filter(|r| !still_unassigned.contains(&r.id)),
+            );
         }
         {
             let mut range_assignments = self.range_assignments.lock().unwrap();
@@ -391,9 +670,29 @@ impl AssignmentComputation for AssignmentComputationImpl {
             }
         }
         ready_servers.replace(HostInfoWrapper(host_info.clone()));
+        self.last_heartbeats
+            .lock()
+            .unwrap()
+            .insert(host_info.identity.name.clone(), tokio::time::Instant::now());
         Ok(self.assignment_update_sender.subscribe())
     }
 
+    fn record_heartbeat(&self, host_info: &HostInfo) -> bool {
+        let ready_servers = self.ready_range_servers.lock().unwrap();
+        let registered = ready_servers
+            .get(&HostInfoWrapper(host_info.clone()))
+            .is_some_and(|existing| {
+                existing.warden_connection_epoch == host_info.warden_connection_epoch
+            });
+        if registered {
+            self.last_heartbeats
+                .lock()
+                .unwrap()
+                .insert(host_info.identity.name.clone(), tokio::time::Instant::now());
+        }
+        registered
+    }
+
     fn get_assignment_update(
         &self,
         host_info: &HostInfo,
@@ -435,6 +734,84 @@ impl AssignmentComputation for AssignmentComputationImpl {
         }
     }
 
+    fn report_range_split(
+        &self,
+        host_info: &HostInfo,
+        range: &FullRangeId,
+        new_ranges: Vec<RangeInfo>,
+    ) -> Result<(), Status> {
+        let range_assignments = self.range_assignments.lock().unwrap();
+        let current_version = self.current_version.lock().unwrap();
+        let assignments = range_assignments
+            .get(&current_version)
+            .map(|a| a.as_slice())
+            .unwrap_or_default();
+        let is_assigned = |id: &Uuid| {
+            assignments.iter().any(|a| {
+                a.range.id == *id
+                    && a.range.keyspace_id == range.keyspace_id
+                    && a.assignee == host_info.identity.name
+            })
+        };
+        // The range server retries the report, which may have been applied
+        // already.
+        if !new_ranges.is_empty() && new_ranges.iter().all(|r| is_assigned(&r.id)) {
+            return Ok(());
+        }
+        let Some(parent) = assignments
+            .iter()
+            .find(|a| a.range.id == range.range_id && a.assignee == host_info.identity.name)
+            .map(|a| a.range.clone())
+        else {
+            return Err(Status::failed_precondition(format!(
+                "Range {} is not assigned to {}",
+                range.range_id, host_info.identity.name
+            )));
+        };
+        let covers_parent = new_ranges
+            .first()
+            .map(|r| &r.key_range.lower_bound_inclusive)
+            == Some(&parent.key_range.lower_bound_inclusive)
+            && new_ranges
+                .last()
+                .map(|r| &r.key_range.upper_bound_exclusive)
+                == Some(&parent.key_range.upper_bound_exclusive)
+            && new_ranges.windows(2).all(|pair| {
+                pair[0].key_range.upper_bound_exclusive.is_some()
+                    && pair[0].key_range.upper_bound_exclusive
+                        == pair[1].key_range.lower_bound_inclusive
+            });
+        if !covers_parent {
+            return Err(Status::invalid_argument(
+                "The new ranges must cover the key range of the split range, in order",
+            ));
+        }
+        info!(
+            "Range server {} split range {:?}.",
+            host_info.identity.name, parent.id
+        );
+        let mut pending_splits = self.pending_splits.lock().unwrap();
+        pending_splits.retain(|split| split.parent.id != parent.id);
+        pending_splits.push(PendingSplit {
+            parent,
+            children: new_ranges,
+        });
+        Ok(())
+    }
+
+    fn report_range_handed_off(&self, host_info: &HostInfo, range: &FullRangeId) {
+        let mut pending_handoffs = self.pending_handoffs.lock().unwrap();
+        if let Some(handoff) = pending_handoffs.iter_mut().find(|handoff| {
+            handoff.range.id == range.range_id && handoff.from == host_info.identity.name
+        }) {
+            info!(
+                "Range server {} handed off range {:?}.",
+                host_info.identity.name, range.range_id
+            );
+            handoff.handed_off = true;
+        }
+    }
+
     fn notify_range_server_unavailable(&self, host_info: HostInfo) {
         // TODO(purujit): Implement Quarantine.
         debug!("Notifying range server {:?} is unavailable.", host_info);
@@ -444,6 +821,10 @@ impl AssignmentComputation for AssignmentComputationImpl {
             // Disconnect could come after a new connection is established.
             // We should not drop the new connection in that case.
             if existing.0.warden_connection_epoch <= host_info.warden_connection_epoch {
+                self.last_heartbeats
+                    .lock()
+                    .unwrap()
+                    .remove(&host_info.identity.name);
                 ready_servers.remove(&HostInfoWrapper(host_info));
             }
         }
@@ -461,8 +842,17 @@ mod tests {
     use once_cell::sync::Lazy;
     use proto::universe::{
         universe_server::{Universe, UniverseServer},
-        CreateKeyspaceRequest, CreateKeyspaceResponse, GetKeyspaceInfoRequest,
-        GetKeyspaceInfoResponse, KeyspaceInfo, ListKeyspacesResponse,
+        CheckCrossNamespaceAccessRequest, CheckCrossNamespaceAccessResponse, CreateKeyspaceRequest,
+        CreateKeyspaceResponse, DeleteKeyspaceAliasRequest, DeleteKeyspaceAliasResponse,
+        GetKeyspaceInfoRequest, GetKeyspaceInfoResponse, GetNamespaceDefaultsRequest,
+        GetNamespaceDefaultsResponse, GetNamespacePolicyRequest, GetNamespacePolicyResponse,
+        KeyspaceInfo, ListKeyspaceAliasesRequest, ListKeyspaceAliasesResponse,
+        ListKeyspacesResponse, RenameKeyspaceRequest, RenameKeyspaceResponse,
+        SetKeyspaceColocationRequest, SetKeyspaceColocationResponse, SetKeyspacePlacementRequest,
+        SetKeyspacePlacementResponse, SetKeyspaceReadOnlyRequest, SetKeyspaceReadOnlyResponse,
+        SetKeyspaceValidationPolicyRequest, SetKeyspaceValidationPolicyResponse,
+        SetNamespaceDefaultsRequest, SetNamespaceDefaultsResponse, SetNamespacePolicyRequest,
+        SetNamespacePolicyResponse, SplitKeyRangeRequest, SplitKeyRangeResponse,
     };
     use scylla::{Session, SessionBuilder};
     use tokio::sync::oneshot;
@@ -558,6 +948,12 @@ mod tests {
                             base_range_uuid: range.id.to_string(),
                         })
                         .collect(),
+                    read_only: false,
+                    optimistic_reads: false,
+                    validation_policy: None,
+                    validation_policy_inherited: false,
+                    placement: None,
+                    colocation: None,
                 }],
             }))
         }
@@ -568,6 +964,120 @@ mod tests {
         ) -> Result<Response<GetKeyspaceInfoResponse>, Status> {
             unreachable!()
         }
+
+        async fn set_keyspace_read_only(
+            &self,
+            _request: Request<SetKeyspaceReadOnlyRequest>,
+        ) -> Result<Response<SetKeyspaceReadOnlyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_keyspace_validation_policy(
+            &self,
+            _request: Request<SetKeyspaceValidationPolicyRequest>,
+        ) -> Result<Response<SetKeyspaceValidationPolicyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_keyspace_placement(
+            &self,
+            _request: Request<SetKeyspacePlacementRequest>,
+        ) -> Result<Response<SetKeyspacePlacementResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_keyspace_colocation(
+            &self,
+            _request: Request<SetKeyspaceColocationRequest>,
+        ) -> Result<Response<SetKeyspaceColocationResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn split_key_range(
+            &self,
+            request: Request<SplitKeyRangeRequest>,
+        ) -> Result<Response<SplitKeyRangeResponse>, Status> {
+            let request = request.into_inner();
+            let range_id = Uuid::parse_str(&request.range_id).unwrap();
+            let mut base_ranges = self.base_ranges.lock().unwrap();
+            if let Some(position) = base_ranges.iter().position(|r| r.id == range_id) {
+                let keyspace_id = base_ranges[position].keyspace_id;
+                let new_ranges: Vec<_> = request
+                    .new_ranges
+                    .into_iter()
+                    .map(|range| RangeInfo {
+                        keyspace_id,
+                        id: Uuid::parse_str(&range.base_range_uuid).unwrap(),
+                        key_range: KeyRange {
+                            lower_bound_inclusive: Some(range.lower_bound_inclusive)
+                                .filter(|b| !b.is_empty())
+                                .map(Bytes::from),
+                            upper_bound_exclusive: Some(range.upper_bound_exclusive)
+                                .filter(|b| !b.is_empty())
+                                .map(Bytes::from),
+                        },
+                    })
+                    .collect();
+                base_ranges.splice(position..position + 1, new_ranges);
+            }
+            Ok(Response::new(SplitKeyRangeResponse {}))
+        }
+
+        async fn set_namespace_policy(
+            &self,
+            _request: Request<SetNamespacePolicyRequest>,
+        ) -> Result<Response<SetNamespacePolicyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn get_namespace_policy(
+            &self,
+            _request: Request<GetNamespacePolicyRequest>,
+        ) -> Result<Response<GetNamespacePolicyResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn check_cross_namespace_access(
+            &self,
+            _request: Request<CheckCrossNamespaceAccessRequest>,
+        ) -> Result<Response<CheckCrossNamespaceAccessResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn rename_keyspace(
+            &self,
+            _request: Request<RenameKeyspaceRequest>,
+        ) -> Result<Response<RenameKeyspaceResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn list_keyspace_aliases(
+            &self,
+            _request: Request<ListKeyspaceAliasesRequest>,
+        ) -> Result<Response<ListKeyspaceAliasesResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn delete_keyspace_alias(
+            &self,
+            _request: Request<DeleteKeyspaceAliasRequest>,
+        ) -> Result<Response<DeleteKeyspaceAliasResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn set_namespace_defaults(
+            &self,
+            _request: Request<SetNamespaceDefaultsRequest>,
+        ) -> Result<Response<SetNamespaceDefaultsResponse>, Status> {
+            unreachable!()
+        }
+
+        async fn get_namespace_defaults(
+            &self,
+            _request: Request<GetNamespaceDefaultsRequest>,
+        ) -> Result<Response<GetNamespaceDefaultsResponse>, Status> {
+            unreachable!()
+        }
     }
 
     static RUNTIME: Lazy<tokio::runtime::Runtime> =
@@ -704,6 +1214,7 @@ mod tests {
             },
             address: "1.2.3.4:8080".parse().unwrap(),
             warden_connection_epoch: 1,
+            labels: Default::default(),
         };
 
         computation
@@ -792,6 +1303,8 @@ mod tests {
                 address: "1.2.3.4:8080".parse().unwrap(),
 
                 warden_connection_epoch: 1,
+
+                labels: Default::default(),
             }),
             HostInfoWrapper(HostInfo {
                 identity: HostIdentity {
@@ -801,6 +1314,8 @@ mod tests {
                 address: "5.6.7.8:8081".parse().unwrap(),
 
                 warden_connection_epoch: 1,
+
+                labels: Default::default(),
             }),
         ];
         computation
@@ -839,6 +1354,97 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_report_range_split() {
+        let context = setup().await;
+        let computation = context.assignment_computation.clone();
+        let range = make_range(0, 127);
+        context.base_ranges.lock().unwrap().push(range.clone());
+        computation
+            .unassigned_base_ranges
+            .lock()
+            .unwrap()
+            .push(range.clone());
+        let host_info = HostInfo {
+            identity: HostIdentity {
+                name: "server1".to_string(),
+                zone: make_zone(),
+            },
+            address: "1.2.3.4:8080".parse().unwrap(),
+            warden_connection_epoch: 1,
+            labels: Default::default(),
+        };
+        let other_host_info = HostInfo {
+            identity: HostIdentity {
+                name: "server2".to_string(),
+                zone: make_zone(),
+            },
+            address: "5.6.7.8:8081".parse().unwrap(),
+            warden_connection_epoch: 1,
+            labels: Default::default(),
+        };
+        computation
+            .ready_range_servers
+            .lock()
+            .unwrap()
+            .insert(HostInfoWrapper(host_info.clone()));
+        let ready_servers = computation
+            .clone()
+            .run_assignment_computation(HashSet::new())
+            .await;
+
+        let full_range_id = FullRangeId {
+            keyspace_id: range.keyspace_id,
+            range_id: range.id,
+        };
+        let mut children = vec![make_range(0, 64), make_range(64, 127)];
+        for child in children.iter_mut() {
+            child.keyspace_id = range.keyspace_id;
+        }
+        let err = computation
+            .report_range_split(&other_host_info, &full_range_id, children.clone())
+            .unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        let err = computation
+            .report_range_split(&host_info, &full_range_id, children[..1].to_vec())
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        computation
+            .report_range_split(&host_info, &full_range_id, children.clone())
+            .unwrap();
+        computation
+            .clone()
+            .run_assignment_computation(ready_servers)
+            .await;
+
+        let mut assigned: Vec<_> = {
+            let range_assignments = computation.range_assignments.lock().unwrap();
+            let current_version = computation.current_version.lock().unwrap();
+            range_assignments[&current_version]
+                .iter()
+                .map(|a| (a.assignee.clone(), a.range.clone()))
+                .collect()
+        };
+        assigned.sort_by(|a, b| {
+            a.1.key_range
+                .lower_bound_inclusive
+                .cmp(&b.1.key_range.lower_bound_inclusive)
+        });
+        assert_eq!(
+            assigned,
+            children
+                .iter()
+                .map(|c| ("server1".to_string(), c.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(*computation.base_ranges.lock().unwrap(), children);
+        assert_eq!(*context.base_ranges.lock().unwrap(), children);
+        // Reporting the split again is fine.
+        computation
+            .report_range_split(&host_info, &full_range_id, children)
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_run_assignment_computation_reassign_unavailable_server() {
         let context = setup().await;
@@ -856,6 +1462,7 @@ mod tests {
                 },
                 address: "1.2.3.4:8080".parse().unwrap(),
                 warden_connection_epoch: 1,
+                labels: Default::default(),
             }),
             HostInfoWrapper(HostInfo {
                 identity: HostIdentity {
@@ -865,6 +1472,8 @@ mod tests {
                 address: "5.6.7.8:8081".parse().unwrap(),
 
                 warden_connection_epoch: 1,
+
+                labels: Default::default(),
             }),
         ];
 
@@ -903,6 +1512,8 @@ mod tests {
                 address: "0.0.0.0:0".parse().unwrap(),
 
                 warden_connection_epoch: 1,
+
+                labels: Default::default(),
             }),
             servers[0].clone(),
         ]);
@@ -948,6 +1559,8 @@ mod tests {
             address: "127.0.0.1:8080".parse().unwrap(),
 
             warden_connection_epoch: 1,
+
+            labels: Default::default(),
         };
 
         let _ = computation.register_range_server(server.clone());
@@ -978,6 +1591,8 @@ mod tests {
             address: "127.0.0.1:8080".parse().unwrap(),
 
             warden_connection_epoch: 1,
+
+            labels: Default::default(),
         };
 
         let _ = computation.register_range_server(server.clone());
@@ -1000,6 +1615,7 @@ mod tests {
             },
             address: "127.0.0.1:8080".parse().unwrap(),
             warden_connection_epoch: 2,
+            labels: Default::default(),
         };
 
         let _ = computation.register_range_server(server.clone());
@@ -1016,10 +1632,92 @@ mod tests {
             },
             address: "127.0.0.1:8080".parse().unwrap(),
             warden_connection_epoch: 1,
+            labels: Default::default(),
         };
         computation.notify_range_server_unavailable(older_epoch_server);
 
         let ready_servers = computation.ready_range_servers.lock().unwrap();
         assert!(ready_servers.contains(&HostInfoWrapper(server)));
     }
+
+    #[tokio::test]
+    async fn test_range_servers_that_stop_heartbeating_are_removed() {
+        let context = setup().await;
+        let computation = context.assignment_computation.clone();
+        let server = |name: &str, epoch| HostInfo {
+            identity: HostIdentity {
+                name: name.to_string(),
+                zone: make_zone(),
+            },
+            address: "127.0.0.1:8080".parse().unwrap(),
+            warden_connection_epoch: epoch,
+            labels: Default::default(),
+        };
+        let _ = computation.register_range_server(server("server1", 1));
+        let _ = computation.register_range_server(server("server2", 1));
+        // Heartbeats of sessions other than the registered one don't count.
+        assert!(!computation.record_heartbeat(&server("server1", 2)));
+        assert!(!computation.record_heartbeat(&server("unknown", 1)));
+
+        let later = tokio::time::Instant::now() + HEARTBEAT_TIMEOUT / 2;
+        computation.remove_dead_range_servers(later);
+        assert_eq!(computation.ready_range_servers.lock().unwrap().len(), 2);
+
+        assert!(computation.record_heartbeat(&server("server2", 1)));
+        computation.remove_dead_range_servers(later + HEARTBEAT_TIMEOUT / 2);
+        {
+            let ready_servers = computation.ready_range_servers.lock().unwrap();
+            assert!(!ready_servers.contains(&HostInfoWrapper(server("server1", 1))));
+            assert!(ready_servers.contains(&HostInfoWrapper(server("server2", 1))));
+        }
+        // A server declared dead learns so from its next heartbeat, and has
+        // to register again.
+        assert!(!computation.record_heartbeat(&server("server1", 1)));
+    }
+
+    #[tokio::test]
+    async fn test_moved_ranges_wait_for_handoff() {
+        let context = setup().await;
+        let computation = context.assignment_computation.clone();
+        let server = |name: &str| HostInfo {
+            identity: HostIdentity {
+                name: name.to_string(),
+                zone: make_zone(),
+            },
+            address: "127.0.0.1:8080".parse().unwrap(),
+            warden_connection_epoch: 1,
+            labels: Default::default(),
+        };
+        let _ = computation.register_range_server(server("server1"));
+        let now = tokio::time::Instant::now();
+        let handoff = |range: &RangeInfo, from: &str| PendingHandoff {
+            range: range.clone(),
+            from: from.to_string(),
+            deadline: now + HANDOFF_TIMEOUT,
+            handed_off: false,
+        };
+        let (handed_off, pending, from_dead) =
+            (make_range(0, 10), make_range(10, 20), make_range(20, 30));
+        computation.pending_handoffs.lock().unwrap().extend([
+            handoff(&handed_off, "server1"),
+            handoff(&pending, "server1"),
+            handoff(&from_dead, "server2"),
+        ]);
+        computation.report_range_handed_off(
+            &server("server1"),
+            &FullRangeId {
+                keyspace_id: handed_off.keyspace_id,
+                range_id: handed_off.id,
+            },
+        );
+
+        let ranges = computation.take_handed_off_ranges(now);
+        assert_eq!(ranges, vec![handed_off, from_dead]);
+        // A range that is not handed off in time moves anyway.
+        assert!(computation.take_handed_off_ranges(now).is_empty());
+        assert_eq!(
+            computation.take_handed_off_ranges(now + HANDOFF_TIMEOUT),
+            vec![pending]
+        );
+    }
 }
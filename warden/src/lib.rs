@@ -0,0 +1,4 @@
+mod assignment_computation;
+mod persistence;
+mod placement;
+pub mod server;
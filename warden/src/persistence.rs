@@ -43,4 +43,8 @@ pub trait Persistence: Send + Sync + 'static {
     ) -> Result<(), Error>;
 
     async fn insert_new_ranges(&self, ranges: &Vec<RangeInfo>) -> Result<(), Error>;
+
+    /// Forgets the assignments of ranges that no longer exist, such as the
+    /// parent of a split.
+    async fn remove_range_assignments(&self, ranges: &[RangeInfo]) -> Result<(), Error>;
 }
@@ -56,6 +56,10 @@ static INSERT_OR_UPDATE_RANGE_ASSIGNMENT_QUERY: &str = r#"
   VALUES (?, ?, ?, ?, ?)
   "#;
 
+static DELETE_RANGE_ASSIGNMENT_QUERY: &str = r#"
+  DELETE FROM atomix.range_map WHERE keyspace_id = ? AND range_id = ?
+  "#;
+
 #[async_trait::async_trait]
 impl Persistence for Cassandra {
     async fn get_keyspace_range_map(
@@ -139,6 +143,21 @@ impl Persistence for Cassandra {
         }
         Ok(())
     }
+
+    async fn remove_range_assignments(&self, ranges: &[RangeInfo]) -> Result<(), Error> {
+        let prepared = self
+            .session
+            .prepare(DELETE_RANGE_ASSIGNMENT_QUERY)
+            .await
+            .map_err(|op| Error::InternalError(Arc::new(op)))?;
+        for range in ranges {
+            self.session
+                .execute(&prepared, (range.keyspace_id.id, range.id))
+                .await
+                .map_err(|op| Error::InternalError(Arc::new(op)))?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
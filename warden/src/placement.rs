@@ -0,0 +1,205 @@
+//! Picks the range server each range goes on, within the placement
+//! constraints of its keyspace.
+
+use std::collections::HashMap;
+
+use common::keyspace_id::KeyspaceId;
+use proto::universe::{AntiAffinity, PlacementConstraints};
+
+use crate::persistence::RangeInfo;
+
+/// Whether a range server with `labels` may hold the ranges of a keyspace
+/// with `placement`.
+pub fn is_eligible(
+    labels: &HashMap<String, String>,
+    placement: Option<&PlacementConstraints>,
+) -> bool {
+    placement.is_none_or(|p| {
+        p.required_labels
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+    })
+}
+
+struct Server {
+    zone: String,
+    labels: HashMap<String, String>,
+    load: usize,
+}
+
+/// Places ranges one at a time on the least loaded eligible server, after
+/// spreading them as the anti-affinity of their keyspace asks.
+#[derive(Default)]
+pub struct Placer {
+    servers: HashMap<String, Server>,
+    // Ranges of each keyspace per server, and per zone.
+    on_server: HashMap<(KeyspaceId, String), usize>,
+    in_zone: HashMap<(KeyspaceId, String), usize>,
+}
+
+impl Placer {
+    /// Makes `name` a candidate for the ranges to place, counting `load`
+    /// ranges as already on it.
+    pub fn add_server(
+        &mut self,
+        name: String,
+        zone: String,
+        labels: HashMap<String, String>,
+        load: usize,
+    ) {
+        self.servers.insert(name, Server { zone, labels, load });
+    }
+
+    /// Counts `range` as placed on `server` for the anti-affinity of its
+    /// keyspace, without adding to the load of the server.
+    pub fn count_placed(&mut self, server: &str, range: &RangeInfo) {
+        let zone = self.servers.get(server).map(|s| s.zone.clone());
+        *self
+            .on_server
+            .entry((range.keyspace_id, server.to_string()))
+            .or_default() += 1;
+        if let Some(zone) = zone {
+            *self.in_zone.entry((range.keyspace_id, zone)).or_default() += 1;
+        }
+    }
+
+    /// Returns the server to place `range` on, or None if no server meets
+    /// the constraints of its keyspace.
+    pub fn place(
+        &mut self,
+        range: &RangeInfo,
+        placement: Option<&PlacementConstraints>,
+    ) -> Option<String> {
+        let anti_affinity = placement
+            .map(|p| p.anti_affinity())
+            .unwrap_or(AntiAffinity::None);
+        let (name, _) = self
+            .servers
+            .iter()
+            .filter(|(_, server)| is_eligible(&server.labels, placement))
+            .min_by_key(|(name, server)| {
+                let spread = match anti_affinity {
+                    AntiAffinity::None => 0,
+                    AntiAffinity::Host => self.count(&self.on_server, range, name),
+                    AntiAffinity::Zone => self.count(&self.in_zone, range, &server.zone),
+                };
+                (spread, server.load, name.as_str())
+            })?;
+        let name = name.clone();
+        self.servers.get_mut(&name).unwrap().load += 1;
+        self.count_placed(&name, range);
+        Some(name)
+    }
+
+    fn count(
+        &self,
+        counts: &HashMap<(KeyspaceId, String), usize>,
+        range: &RangeInfo,
+        key: &str,
+    ) -> usize {
+        counts
+            .get(&(range.keyspace_id, key.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::key_range::KeyRange;
+    use uuid::Uuid;
+
+    fn range(keyspace_id: KeyspaceId) -> RangeInfo {
+        RangeInfo {
+            keyspace_id,
+            id: Uuid::new_v4(),
+            key_range: KeyRange {
+                lower_bound_inclusive: None,
+                upper_bound_exclusive: None,
+            },
+        }
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn required_labels_restrict_servers() {
+        let placement = PlacementConstraints {
+            required_labels: labels(&[("ssd", "true")]),
+            anti_affinity: AntiAffinity::None as i32,
+        };
+        let mut placer = Placer::default();
+        placer.add_server("hdd".into(), "a".into(), labels(&[("ssd", "false")]), 0);
+        placer.add_server("ssd".into(), "a".into(), labels(&[("ssd", "true")]), 10);
+        let keyspace_id = KeyspaceId::new(Uuid::new_v4());
+        for _ in 0..3 {
+            assert_eq!(
+                placer.place(&range(keyspace_id), Some(&placement)),
+                Some("ssd".to_string())
+            );
+        }
+        // Without constraints the least loaded server wins.
+        assert_eq!(
+            placer.place(&range(keyspace_id), None),
+            Some("hdd".to_string())
+        );
+
+        let mut placer = Placer::default();
+        placer.add_server("hdd".into(), "a".into(), HashMap::new(), 0);
+        assert_eq!(placer.place(&range(keyspace_id), Some(&placement)), None);
+    }
+
+    #[test]
+    fn zone_anti_affinity_spreads_ranges() {
+        let placement = PlacementConstraints {
+            required_labels: HashMap::new(),
+            anti_affinity: AntiAffinity::Zone as i32,
+        };
+        let mut placer = Placer::default();
+        placer.add_server("a1".into(), "a".into(), HashMap::new(), 0);
+        placer.add_server("a2".into(), "a".into(), HashMap::new(), 0);
+        placer.add_server("b1".into(), "b".into(), HashMap::new(), 5);
+        let keyspace_id = KeyspaceId::new(Uuid::new_v4());
+        let existing = range(keyspace_id);
+        placer.count_placed("a1", &existing);
+
+        // Zone b holds none of the keyspace's ranges yet, despite its load.
+        assert_eq!(
+            placer.place(&range(keyspace_id), Some(&placement)),
+            Some("b1".to_string())
+        );
+        // Both zones hold one, so the least loaded server goes next.
+        assert_eq!(
+            placer.place(&range(keyspace_id), Some(&placement)),
+            Some("a1".to_string())
+        );
+        // Other keyspaces are not affected.
+        assert_eq!(
+            placer.place(&range(KeyspaceId::new(Uuid::new_v4())), Some(&placement)),
+            Some("a2".to_string())
+        );
+    }
+
+    #[test]
+    fn host_anti_affinity_spreads_ranges() {
+        let placement = PlacementConstraints {
+            required_labels: HashMap::new(),
+            anti_affinity: AntiAffinity::Host as i32,
+        };
+        let mut placer = Placer::default();
+        placer.add_server("s1".into(), "a".into(), HashMap::new(), 0);
+        placer.add_server("s2".into(), "a".into(), HashMap::new(), 3);
+        let keyspace_id = KeyspaceId::new(Uuid::new_v4());
+        let mut placed: Vec<_> = (0..2)
+            .map(|_| placer.place(&range(keyspace_id), Some(&placement)).unwrap())
+            .collect();
+        placed.sort();
+        assert_eq!(placed, vec!["s1".to_string(), "s2".to_string()]);
+    }
+}
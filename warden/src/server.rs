@@ -5,14 +5,23 @@ use std::{
     task::{Context, Poll},
 };
 
+use bytes::Bytes;
 use common::{
+    full_range_id::FullRangeId,
     host_info::{HostIdentity, HostInfo},
+    key_range::KeyRange,
+    keyspace_id::KeyspaceId,
     region::{Region, Zone},
 };
 use pin_project::{pin_project, pinned_drop};
 use proto::{
     universe::universe_client::UniverseClient,
-    warden::{warden_server::Warden, RegisterRangeServerRequest, WardenUpdate},
+    warden::{
+        warden_server::Warden, HeartbeatRequest, HeartbeatResponse, RegisterRangeServerRequest,
+        ReportRangeFaultRequest, ReportRangeFaultResponse, ReportRangeHandedOffRequest,
+        ReportRangeHandedOffResponse, ReportRangeSplitRequest, ReportRangeSplitResponse,
+        WardenUpdate,
+    },
 };
 use tokio::sync::broadcast;
 use tokio_stream::{
@@ -21,11 +30,12 @@ use tokio_stream::{
 };
 use tokio_util::sync::CancellationToken;
 use tonic::{Request, Response, Status};
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
 
 use crate::{
     assignment_computation::{AssignmentComputation, AssignmentComputationImpl},
-    persistence::cassandra::Cassandra,
+    persistence::{cassandra::Cassandra, RangeInfo},
 };
 
 /// Implementation of the Warden service.
@@ -53,23 +63,7 @@ impl Warden for WardenServer {
             }
             Some(range_server) => {
                 info!("Registering range server: {}", range_server.identity);
-                let host_info = HostInfo {
-                    identity: HostIdentity {
-                        name: range_server.identity,
-                        zone: Zone {
-                            name: range_server.zone,
-                            // TODO(purujit): Get the region from the range server.
-                            region: Region {
-                                cloud: None,
-                                name: "".to_string(),
-                            },
-                        },
-                    },
-                    // todo(purujit): Get the address from the range server.
-                    address: SocketAddr::from(([0, 0, 0, 0], 0)),
-
-                    warden_connection_epoch: range_server.epoch,
-                };
+                let host_info = host_info_from_proto(range_server);
                 match self
                     .assignment_computation
                     .register_range_server(host_info.clone())
@@ -84,6 +78,159 @@ impl Warden for WardenServer {
             }
         }
     }
+
+    #[instrument(skip(self))]
+    async fn report_range_fault(
+        &self,
+        request: Request<ReportRangeFaultRequest>,
+    ) -> Result<Response<ReportRangeFaultResponse>, Status> {
+        let report = request.into_inner();
+        let (range_server, range) = match (report.range_server, report.range) {
+            (Some(range_server), Some(range)) => (range_server, range),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "range_server and range must be set in the request",
+                ))
+            }
+        };
+        // TODO: reassign ranges that their range server gave up on.
+        if report.recovered {
+            info!(
+                range_server = range_server.identity,
+                keyspace_id = range.keyspace_id,
+                range_id = range.range_id,
+                "Range recovered from fault: {}",
+                report.reason
+            );
+        } else {
+            warn!(
+                range_server = range_server.identity,
+                keyspace_id = range.keyspace_id,
+                range_id = range.range_id,
+                "Range faulted: {}",
+                report.reason
+            );
+        }
+        Ok(Response::new(ReportRangeFaultResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn report_range_split(
+        &self,
+        request: Request<ReportRangeSplitRequest>,
+    ) -> Result<Response<ReportRangeSplitResponse>, Status> {
+        let report = request.into_inner();
+        let (range_server, range) = match (report.range_server, report.range) {
+            (Some(range_server), Some(range)) => (range_server, range),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "range_server and range must be set in the request",
+                ))
+            }
+        };
+        let range = parse_range_id(&range).map_err(Status::invalid_argument)?;
+        let new_ranges = report
+            .new_ranges
+            .into_iter()
+            .map(|new_range| range_info_from_proto(new_range, &range))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(Status::invalid_argument)?;
+        self.assignment_computation.report_range_split(
+            &host_info_from_proto(range_server),
+            &range,
+            new_ranges,
+        )?;
+        Ok(Response::new(ReportRangeSplitResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn report_range_handed_off(
+        &self,
+        request: Request<ReportRangeHandedOffRequest>,
+    ) -> Result<Response<ReportRangeHandedOffResponse>, Status> {
+        let report = request.into_inner();
+        let (range_server, range) = match (report.range_server, report.range) {
+            (Some(range_server), Some(range)) => (range_server, range),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "range_server and range must be set in the request",
+                ))
+            }
+        };
+        let range = parse_range_id(&range).map_err(Status::invalid_argument)?;
+        self.assignment_computation
+            .report_range_handed_off(&host_info_from_proto(range_server), &range);
+        Ok(Response::new(ReportRangeHandedOffResponse {}))
+    }
+
+    #[instrument(skip(self))]
+    async fn heartbeat(
+        &self,
+        request: Request<HeartbeatRequest>,
+    ) -> Result<Response<HeartbeatResponse>, Status> {
+        let range_server = request
+            .into_inner()
+            .range_server
+            .ok_or_else(|| Status::invalid_argument("range_server must be set in the request"))?;
+        let registered = self
+            .assignment_computation
+            .record_heartbeat(&host_info_from_proto(range_server));
+        Ok(Response::new(HeartbeatResponse { registered }))
+    }
+}
+
+fn host_info_from_proto(range_server: proto::warden::HostInfo) -> HostInfo {
+    HostInfo {
+        identity: HostIdentity {
+            name: range_server.identity,
+            zone: Zone {
+                name: range_server.zone,
+                // TODO(purujit): Get the region from the range server.
+                region: Region {
+                    cloud: None,
+                    name: "".to_string(),
+                },
+            },
+        },
+        // todo(purujit): Get the address from the range server.
+        address: SocketAddr::from(([0, 0, 0, 0], 0)),
+
+        warden_connection_epoch: range_server.epoch,
+
+        labels: range_server.labels,
+    }
+}
+
+fn parse_range_id(range: &proto::warden::RangeId) -> Result<FullRangeId, String> {
+    let parse = |id: &str| Uuid::parse_str(id).map_err(|e| format!("invalid id {id}: {e}"));
+    Ok(FullRangeId {
+        keyspace_id: KeyspaceId::new(parse(&range.keyspace_id)?),
+        range_id: parse(&range.range_id)?,
+    })
+}
+
+fn range_info_from_proto(
+    new_range: proto::warden::NewRange,
+    parent: &FullRangeId,
+) -> Result<RangeInfo, String> {
+    let id = parse_range_id(
+        new_range
+            .range
+            .as_ref()
+            .ok_or("new ranges must have an id")?,
+    )?;
+    if id.keyspace_id != parent.keyspace_id {
+        return Err("new ranges must be in the keyspace of the split range".to_string());
+    }
+    let bound = |b: Vec<u8>| Some(b).filter(|b| !b.is_empty()).map(Bytes::from);
+    Ok(RangeInfo {
+        keyspace_id: id.keyspace_id,
+        id: id.range_id,
+        key_range: KeyRange {
+            lower_bound_inclusive: bound(new_range.lower_bound_inclusive),
+            upper_bound_exclusive: bound(new_range.upper_bound_exclusive),
+        },
+    })
 }
 
 impl WardenServer {
@@ -300,6 +447,21 @@ mod tests {
         fn notify_range_server_unavailable(&self, host_info: HostInfo) {
             self.dropped_clients.lock().unwrap().push(host_info)
         }
+
+        fn record_heartbeat(&self, _: &HostInfo) -> bool {
+            true
+        }
+
+        fn report_range_split(
+            &self,
+            _: &HostInfo,
+            _: &FullRangeId,
+            _: Vec<RangeInfo>,
+        ) -> Result<(), Status> {
+            Ok(())
+        }
+
+        fn report_range_handed_off(&self, _: &HostInfo, _: &FullRangeId) {}
     }
     #[tokio::test]
     async fn test_warden_server_startup_and_client_updates() {
@@ -334,6 +496,7 @@ mod tests {
                 identity: "test_server".to_string(),
                 zone: "test_zone".to_string(),
                 epoch: 1,
+                labels: Default::default(),
             }),
         });
         let response = client.register_range_server(request).await.unwrap();
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use common::full_range_id::FullRangeId;
+use common::keyspace_id::KeyspaceId;
+use proto::warden::{warden_update::Update, RangeId, WardenUpdate};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AssignmentChange {
+    LoadRange(FullRangeId),
+    UnloadRange(FullRangeId),
+}
+
+/// Gets told about every change to the set of ranges assigned to the host.
+pub trait AssignmentListener: Send + Sync + 'static {
+    fn on_change(&self, change: AssignmentChange);
+}
+
+impl AssignmentListener for mpsc::UnboundedSender<AssignmentChange> {
+    fn on_change(&self, change: AssignmentChange) {
+        // Nobody is listening anymore, which is fine.
+        let _ = self.send(change);
+    }
+}
+
+impl<F> AssignmentListener for F
+where
+    F: Fn(AssignmentChange) + Send + Sync + 'static,
+{
+    fn on_change(&self, change: AssignmentChange) {
+        self(change)
+    }
+}
+
+fn full_range_id_from_proto(proto_range_id: &RangeId) -> Result<FullRangeId, Error> {
+    let parse = |id: &str| {
+        Uuid::from_str(id).map_err(|e| Error::MalformedUpdate(format!("bad id {}: {}", id, e)))
+    };
+    Ok(FullRangeId {
+        keyspace_id: KeyspaceId::new(parse(&proto_range_id.keyspace_id)?),
+        range_id: parse(&proto_range_id.range_id)?,
+    })
+}
+
+/// The ranges currently assigned to the host, as told by the warden.
+#[derive(Default)]
+pub struct Assignment {
+    ranges: HashSet<FullRangeId>,
+}
+
+impl Assignment {
+    pub fn contains(&self, range_id: &FullRangeId) -> bool {
+        self.ranges.contains(range_id)
+    }
+
+    pub fn ranges(&self) -> &HashSet<FullRangeId> {
+        &self.ranges
+    }
+
+    /// Applies an update from the warden, and returns the resulting changes.
+    /// A malformed update is rejected as a whole.
+    pub fn apply(&mut self, update: &WardenUpdate) -> Result<Vec<AssignmentChange>, Error> {
+        let update = update
+            .update
+            .as_ref()
+            .ok_or_else(|| Error::MalformedUpdate("empty update".to_string()))?;
+        let mut changes = Vec::new();
+        match update {
+            Update::FullAssignment(full_assignment) => {
+                let new_assignment = full_assignment
+                    .range
+                    .iter()
+                    .map(full_range_id_from_proto)
+                    .collect::<Result<HashSet<_>, _>>()?;
+                // Unload any ranges that are no longer assigned to us.
+                for range_id in self.ranges.difference(&new_assignment) {
+                    changes.push(AssignmentChange::UnloadRange(*range_id));
+                }
+                // Load any ranges that got newly assigned to us.
+                for range_id in new_assignment.difference(&self.ranges) {
+                    changes.push(AssignmentChange::LoadRange(*range_id));
+                }
+                self.ranges = new_assignment;
+            }
+            Update::IncrementalAssignment(incremental) => {
+                let load = incremental
+                    .load
+                    .iter()
+                    .map(full_range_id_from_proto)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let unload = incremental
+                    .unload
+                    .iter()
+                    .map(full_range_id_from_proto)
+                    .collect::<Result<Vec<_>, _>>()?;
+                for range_id in load {
+                    if self.ranges.insert(range_id) {
+                        changes.push(AssignmentChange::LoadRange(range_id));
+                    }
+                }
+                for range_id in unload {
+                    if self.ranges.remove(&range_id) {
+                        changes.push(AssignmentChange::UnloadRange(range_id));
+                    }
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::warden::{FullAssignment, IncrementalAssignment};
+
+    fn range_id() -> FullRangeId {
+        FullRangeId {
+            keyspace_id: KeyspaceId::new(Uuid::new_v4()),
+            range_id: Uuid::new_v4(),
+        }
+    }
+
+    fn to_proto(range_id: &FullRangeId) -> RangeId {
+        RangeId {
+            keyspace_id: range_id.keyspace_id.id.to_string(),
+            range_id: range_id.range_id.to_string(),
+        }
+    }
+
+    fn full(ranges: &[FullRangeId]) -> WardenUpdate {
+        WardenUpdate {
+            update: Some(Update::FullAssignment(FullAssignment {
+                version: 1,
+                range: ranges.iter().map(to_proto).collect(),
+            })),
+        }
+    }
+
+    #[test]
+    fn full_assignment_is_diffed_against_the_current_one() {
+        let (a, b, c) = (range_id(), range_id(), range_id());
+        let mut assignment = Assignment::default();
+        let changes: HashSet<_> = assignment
+            .apply(&full(&[a, b]))
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            changes,
+            HashSet::from([
+                AssignmentChange::LoadRange(a),
+                AssignmentChange::LoadRange(b)
+            ])
+        );
+
+        let changes = assignment.apply(&full(&[b, c])).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.contains(&AssignmentChange::UnloadRange(a)));
+        assert!(changes.contains(&AssignmentChange::LoadRange(c)));
+        assert!(assignment.contains(&b) && assignment.contains(&c) && !assignment.contains(&a));
+    }
+
+    #[test]
+    fn incremental_assignment_skips_no_op_changes() {
+        let (a, b) = (range_id(), range_id());
+        let mut assignment = Assignment::default();
+        assignment.apply(&full(&[a])).unwrap();
+        let update = WardenUpdate {
+            update: Some(Update::IncrementalAssignment(IncrementalAssignment {
+                version: 2,
+                previous_version: 1,
+                load: vec![to_proto(&a), to_proto(&b)],
+                unload: vec![to_proto(&range_id())],
+            })),
+        };
+        let changes = assignment.apply(&update).unwrap();
+        assert_eq!(changes, vec![AssignmentChange::LoadRange(b)]);
+    }
+
+    #[test]
+    fn malformed_update_is_rejected_as_a_whole() {
+        let a = range_id();
+        let mut assignment = Assignment::default();
+        let mut update = full(&[a, range_id()]);
+        if let Some(Update::FullAssignment(full)) = &mut update.update {
+            full.range[1].range_id = "not-a-uuid".to_string();
+        }
+        assert!(matches!(
+            assignment.apply(&update),
+            Err(Error::MalformedUpdate(_))
+        ));
+        assert!(assignment.ranges().is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use common::config::HostPort;
+use common::full_range_id::FullRangeId;
+use common::host_info::HostInfo;
+use common::key_range::KeyRange;
+use epoch_reader::source::EpochSource;
+use proto::warden::warden_client::WardenClient as ProtoWardenClient;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tonic::Request;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::assignment::{Assignment, AssignmentListener};
+use crate::error::Error;
+
+// How often a registered host tells the warden it is alive. The warden
+// considers hosts that stop heartbeating for a while dead.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait before reconnecting to the warden after losing the
+/// connection, doubling on every failed attempt.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+enum State {
+    NotStarted,
+    Started(CancellationToken),
+    Stopped,
+}
+
+struct Inner {
+    warden_address: HostPort,
+    host_info: HostInfo,
+    epoch_source: Arc<dyn EpochSource>,
+    policy: ReconnectPolicy,
+    assignment: RwLock<Assignment>,
+    // Epoch the current session with the warden was registered at, 0 before
+    // the first one.
+    session_epoch: AtomicU64,
+}
+
+/// Keeps a host registered with the warden of its region, and tracks the
+/// ranges the warden assigns to it.
+///
+/// Every time the connection is (re)established a new session gets registered
+/// with the warden, which starts by sending the full assignment, so changes
+/// missed while disconnected get caught up on.
+pub struct WardenClient {
+    inner: Arc<Inner>,
+    state: Mutex<State>,
+}
+
+impl WardenClient {
+    pub fn new(
+        warden_address: HostPort,
+        host_info: HostInfo,
+        epoch_source: Arc<dyn EpochSource>,
+        policy: ReconnectPolicy,
+    ) -> WardenClient {
+        WardenClient {
+            inner: Arc::new(Inner {
+                warden_address,
+                host_info,
+                epoch_source,
+                policy,
+                assignment: RwLock::new(Assignment::default()),
+                session_epoch: AtomicU64::new(0),
+            }),
+            state: Mutex::new(State::NotStarted),
+        }
+    }
+
+    /// Connects to the warden in the background and keeps reconnecting until
+    /// stopped, telling `listener` about every assignment change. Returns a
+    /// receiver that resolves once the client stopped.
+    pub fn start<L: AssignmentListener>(
+        &self,
+        listener: L,
+    ) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::NotStarted) {
+            return Err(Error::AlreadyStarted);
+        }
+        let stop = CancellationToken::new();
+        *state = State::Started(stop.clone());
+        let (done_tx, done_rx) = oneshot::channel();
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            let _ = done_tx.send(inner.run(listener, stop).await);
+        });
+        Ok(done_rx)
+    }
+
+    /// Stops the client. Once stopped, no range is considered assigned.
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let State::Started(stop) = std::mem::replace(&mut *state, State::Stopped) {
+            stop.cancel();
+        }
+        *self.inner.assignment.write().unwrap() = Assignment::default();
+    }
+
+    pub fn is_assigned(&self, range_id: &FullRangeId) -> bool {
+        self.inner.assignment.read().unwrap().contains(range_id)
+    }
+
+    pub fn assigned_ranges(&self) -> HashSet<FullRangeId> {
+        self.inner.assignment.read().unwrap().ranges().clone()
+    }
+
+    /// Epoch the current session was registered at, None if no session was
+    /// established yet.
+    pub fn session_epoch(&self) -> Option<u64> {
+        match self.inner.session_epoch.load(Ordering::SeqCst) {
+            0 => None,
+            epoch => Some(epoch),
+        }
+    }
+
+    /// Tells the warden that a range hit persistent storage errors, or that it
+    /// recovered from them.
+    pub async fn report_range_fault(
+        &self,
+        range_id: &FullRangeId,
+        reason: String,
+        recovered: bool,
+    ) -> Result<(), Error> {
+        let epoch = self.inner.epoch_source.read_epoch().await?;
+        let mut client = self.inner.connect().await?;
+        let request = proto::warden::ReportRangeFaultRequest {
+            range_server: Some(self.inner.proto_host_info(epoch)),
+            range: Some(proto::warden::RangeId {
+                keyspace_id: range_id.keyspace_id.id.to_string(),
+                range_id: range_id.range_id.to_string(),
+            }),
+            reason,
+            recovered,
+        };
+        client.report_range_fault(Request::new(request)).await?;
+        Ok(())
+    }
+
+    /// Tells the warden that this host stopped serving a range that was
+    /// moved off it, so that its next host can load it.
+    pub async fn report_range_handed_off(&self, range_id: &FullRangeId) -> Result<(), Error> {
+        let epoch = self.inner.epoch_source.read_epoch().await?;
+        let mut client = self.inner.connect().await?;
+        let request = proto::warden::ReportRangeHandedOffRequest {
+            range_server: Some(self.inner.proto_host_info(epoch)),
+            range: Some(proto::warden::RangeId {
+                keyspace_id: range_id.keyspace_id.id.to_string(),
+                range_id: range_id.range_id.to_string(),
+            }),
+        };
+        client
+            .report_range_handed_off(Request::new(request))
+            .await?;
+        Ok(())
+    }
+
+    /// Tells the warden that a range was split into `new_ranges`, which take
+    /// over its keys and stay on this host.
+    pub async fn report_range_split(
+        &self,
+        range_id: &FullRangeId,
+        new_ranges: &[(Uuid, KeyRange)],
+    ) -> Result<(), Error> {
+        let epoch = self.inner.epoch_source.read_epoch().await?;
+        let mut client = self.inner.connect().await?;
+        let request = proto::warden::ReportRangeSplitRequest {
+            range_server: Some(self.inner.proto_host_info(epoch)),
+            range: Some(proto::warden::RangeId {
+                keyspace_id: range_id.keyspace_id.id.to_string(),
+                range_id: range_id.range_id.to_string(),
+            }),
+            new_ranges: new_ranges
+                .iter()
+                .map(|(id, key_range)| proto::warden::NewRange {
+                    range: Some(proto::warden::RangeId {
+                        keyspace_id: range_id.keyspace_id.id.to_string(),
+                        range_id: id.to_string(),
+                    }),
+                    lower_bound_inclusive: key_range
+                        .lower_bound_inclusive
+                        .as_ref()
+                        .map(|bound| bound.to_vec())
+                        .unwrap_or_default(),
+                    upper_bound_exclusive: key_range
+                        .upper_bound_exclusive
+                        .as_ref()
+                        .map(|bound| bound.to_vec())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        };
+        client.report_range_split(Request::new(request)).await?;
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn proto_host_info(&self, epoch: u64) -> proto::warden::HostInfo {
+        proto::warden::HostInfo {
+            identity: self.host_info.identity.name.clone(),
+            zone: self.host_info.identity.zone.name.clone(),
+            epoch,
+            labels: self.host_info.labels.clone(),
+        }
+    }
+
+    async fn connect(&self) -> Result<ProtoWardenClient<tonic::transport::Channel>, Error> {
+        let addr = format!("http://{}", self.warden_address);
+        Ok(ProtoWardenClient::connect(addr).await?)
+    }
+
+    // The epoch source may return an epoch one less than the latest, so make
+    // sure a new session never registers at an older epoch than the previous
+    // one did.
+    async fn next_session_epoch(&self) -> Result<u64, Error> {
+        let epoch = self.epoch_source.read_epoch().await?;
+        Ok(std::cmp::max(
+            epoch,
+            self.session_epoch.load(Ordering::SeqCst),
+        ))
+    }
+
+    async fn run<L: AssignmentListener>(
+        &self,
+        listener: L,
+        stop: CancellationToken,
+    ) -> Result<(), Error> {
+        let mut backoff = self.policy.initial_backoff;
+        loop {
+            match self.run_session(&listener, &stop, &mut backoff).await {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!(?backoff, "Lost connection to warden, reconnecting: {}", e),
+            }
+            tokio::select! {
+                () = stop.cancelled() => return Ok(()),
+                () = tokio::time::sleep(backoff) => {}
+            }
+            backoff = std::cmp::min(backoff * 2, self.policy.max_backoff);
+        }
+    }
+
+    async fn run_session<L: AssignmentListener>(
+        &self,
+        listener: &L,
+        stop: &CancellationToken,
+        backoff: &mut Duration,
+    ) -> Result<(), Error> {
+        let epoch = self.next_session_epoch().await?;
+        let mut client = self.connect().await?;
+        let registration_request = proto::warden::RegisterRangeServerRequest {
+            range_server: Some(self.proto_host_info(epoch)),
+        };
+        let mut stream = client
+            .register_range_server(Request::new(registration_request))
+            .await?
+            .into_inner();
+        self.session_epoch.store(epoch, Ordering::SeqCst);
+        *backoff = self.policy.initial_backoff;
+        info!(epoch, "Registered with warden");
+
+        let mut heartbeats = tokio::time::interval(HEARTBEAT_INTERVAL);
+        heartbeats.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = stop.cancelled() => return Ok(()),
+                _ = heartbeats.tick() => {
+                    let request = proto::warden::HeartbeatRequest {
+                        range_server: Some(self.proto_host_info(epoch)),
+                    };
+                    // Missed heartbeats are fine as long as the warden hears
+                    // from the host again before it gives up on it.
+                    match tokio::time::timeout(HEARTBEAT_INTERVAL, client.heartbeat(Request::new(request))).await {
+                        Ok(Ok(response)) if !response.get_ref().registered => {
+                            return Err(Error::SessionExpired)
+                        }
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => warn!("Failed to heartbeat to warden: {}", e),
+                        Err(_) => warn!("Heartbeat to warden timed out"),
+                    }
+                }
+                maybe_update = stream.message() => {
+                    let update = maybe_update?.ok_or(Error::ConnectionClosed)?;
+                    let changes = self.assignment.write().unwrap().apply(&update)?;
+                    for change in changes {
+                        listener.on_change(change);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read the epoch: {0}")]
+    Epoch(#[from] epoch_publisher::error::Error),
+    #[error("Failed to connect to the warden: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("Warden request failed: {0}")]
+    Rpc(Box<tonic::Status>),
+    #[error("Connection closed by the warden")]
+    ConnectionClosed,
+    #[error("The warden no longer knows the session")]
+    SessionExpired,
+    #[error("Malformed update from the warden: {0}")]
+    MalformedUpdate(String),
+    #[error("The warden client can only be started once")]
+    AlreadyStarted,
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Rpc(Box::new(status))
+    }
+}
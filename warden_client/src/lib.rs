@@ -0,0 +1,3 @@
+pub mod assignment;
+pub mod client;
+pub mod error;